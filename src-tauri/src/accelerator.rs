@@ -0,0 +1,207 @@
+// Accelerators as the menu takes them: modifiers and then one key, joined
+// by "+", case-insensitive, e.g. "CmdOrCtrl+Shift+N". `CmdOrCtrl` is Cmd
+// on macOS and Ctrl elsewhere, so the same string can mean a different
+// combination, and collide with a different system shortcut, per platform.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accelerator {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// Cmd on macOS, the Windows or Super key elsewhere.
+    pub meta: bool,
+    /// The key's canonical name, e.g. "N", "F5" or "SPACE".
+    pub key: String,
+}
+
+const NAMED_KEYS: &[&str] = &[
+    "SPACE",
+    "TAB",
+    "ENTER",
+    "ESCAPE",
+    "BACKSPACE",
+    "DELETE",
+    "INSERT",
+    "HOME",
+    "END",
+    "PAGEUP",
+    "PAGEDOWN",
+    "UP",
+    "DOWN",
+    "LEFT",
+    "RIGHT",
+    "PLUS",
+    "PRINTSCREEN",
+];
+
+// Spellings accepted for the names above
+const KEY_ALIASES: &[(&str, &str)] = &[
+    ("RETURN", "ENTER"),
+    ("ESC", "ESCAPE"),
+    ("DEL", "DELETE"),
+    ("ARROWUP", "UP"),
+    ("ARROWDOWN", "DOWN"),
+    ("ARROWLEFT", "LEFT"),
+    ("ARROWRIGHT", "RIGHT"),
+    ("PRINT", "PRINTSCREEN"),
+];
+
+const PUNCTUATION: &str = "-=[]\\;',./`";
+
+impl Accelerator {
+    /// Parses `text` for the platform this runs on.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let (key, modifiers) = match parts.split_last() {
+            Some((key, modifiers)) if !key.is_empty() => (*key, modifiers),
+            _ => {
+                return Err(
+                    "An accelerator needs a key after its modifiers; + is spelled Plus".to_string(),
+                )
+            }
+        };
+
+        let mut accelerator = Accelerator {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+            key: parse_key(key)?,
+        };
+        for modifier in modifiers {
+            let flag = match modifier.to_ascii_uppercase().as_str() {
+                "CMDORCTRL" | "CMDORCONTROL" | "COMMANDORCONTROL" | "COMMANDORCTRL" => {
+                    if cfg!(target_os = "macos") {
+                        &mut accelerator.meta
+                    } else {
+                        &mut accelerator.ctrl
+                    }
+                }
+                "CTRL" | "CONTROL" => &mut accelerator.ctrl,
+                "ALT" | "OPTION" => &mut accelerator.alt,
+                "SHIFT" => &mut accelerator.shift,
+                "CMD" | "COMMAND" | "SUPER" | "META" => &mut accelerator.meta,
+                "" => return Err("An accelerator can't have an empty modifier".to_string()),
+                _ => return Err(format!("Unknown modifier: {}", modifier)),
+            };
+            if *flag {
+                return Err(format!("Modifier {} is given twice", modifier));
+            }
+            *flag = true;
+        }
+        Ok(accelerator)
+    }
+
+    fn has_modifier(&self) -> bool {
+        self.ctrl || self.alt || self.meta
+    }
+}
+
+fn parse_key(key: &str) -> Result<String, String> {
+    let upper = key.to_ascii_uppercase();
+    let mut chars = upper.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() || PUNCTUATION.contains(c) {
+            return Ok(upper);
+        }
+    }
+    if let Some(number) = upper.strip_prefix('F') {
+        if number.parse::<u8>().is_ok_and(|n| (1..=24).contains(&n)) {
+            return Ok(upper);
+        }
+    }
+    if let Some(&(_, name)) = KEY_ALIASES.iter().find(|(alias, _)| *alias == upper) {
+        return Ok(name.to_string());
+    }
+    if NAMED_KEYS.contains(&upper.as_str()) {
+        return Ok(upper);
+    }
+    Err(format!("Unknown key: {}", key))
+}
+
+// Taken by the system or the usual desktop shells before the app sees them
+#[cfg(target_os = "macos")]
+const RESERVED: &[(&str, &str)] = &[
+    ("Cmd+Q", "quits the app"),
+    ("Cmd+H", "hides the app"),
+    ("Cmd+Alt+H", "hides other apps"),
+    ("Cmd+M", "minimizes the window"),
+    ("Cmd+Tab", "switches apps"),
+    ("Cmd+Shift+Tab", "switches apps"),
+    ("Cmd+`", "switches windows"),
+    ("Cmd+Space", "opens Spotlight"),
+    ("Cmd+Alt+Space", "opens a Finder search"),
+    ("Ctrl+Space", "switches input sources"),
+    ("Cmd+Shift+3", "takes a screenshot"),
+    ("Cmd+Shift+4", "takes a screenshot"),
+    ("Cmd+Shift+5", "opens the screenshot tools"),
+    ("Cmd+Alt+Escape", "opens Force Quit"),
+    ("Cmd+Ctrl+Q", "locks the screen"),
+    ("Cmd+Ctrl+Space", "opens the character viewer"),
+    ("Ctrl+Up", "opens Mission Control"),
+    ("Ctrl+Down", "shows the app's windows"),
+    ("Ctrl+Left", "switches spaces"),
+    ("Ctrl+Right", "switches spaces"),
+];
+
+#[cfg(target_os = "windows")]
+const RESERVED: &[(&str, &str)] = &[
+    ("Alt+F4", "closes the window"),
+    ("Alt+Tab", "switches windows"),
+    ("Alt+Shift+Tab", "switches windows"),
+    ("Alt+Escape", "cycles windows"),
+    ("Alt+Space", "opens the window menu"),
+    ("Ctrl+Escape", "opens the Start menu"),
+    ("Ctrl+Alt+Delete", "opens the security screen"),
+    ("Ctrl+Shift+Escape", "opens Task Manager"),
+    ("Super+L", "locks the screen"),
+    ("Super+D", "shows the desktop"),
+    ("Super+E", "opens File Explorer"),
+    ("Super+R", "opens Run"),
+    ("Super+Tab", "opens Task View"),
+    ("Super+Shift+S", "takes a screenshot"),
+    ("PrintScreen", "takes a screenshot"),
+];
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const RESERVED: &[(&str, &str)] = &[
+    ("Alt+F4", "closes the window"),
+    ("Alt+Tab", "switches windows"),
+    ("Alt+Shift+Tab", "switches windows"),
+    ("Alt+F2", "opens the run dialog"),
+    ("Ctrl+Alt+Delete", "logs out"),
+    ("Ctrl+Alt+T", "opens a terminal"),
+    ("Ctrl+Alt+L", "locks the screen"),
+    ("Super+L", "locks the screen"),
+    ("Ctrl+Alt+Up", "switches workspaces"),
+    ("Ctrl+Alt+Down", "switches workspaces"),
+    ("Ctrl+Alt+Left", "switches workspaces"),
+    ("Ctrl+Alt+Right", "switches workspaces"),
+    ("Super+Tab", "switches apps"),
+    ("Super+Space", "switches input sources"),
+    ("PrintScreen", "takes a screenshot"),
+];
+
+/// Why `accelerator` may never reach the app on this platform, if it
+/// might not.
+pub fn warning(accelerator: &Accelerator) -> Option<String> {
+    let reserved = RESERVED.iter().find(|(shortcut, _)| {
+        Accelerator::parse(shortcut).is_ok_and(|shortcut| shortcut == *accelerator)
+    });
+    if let Some((shortcut, effect)) = reserved {
+        return Some(format!("{} {} on this system", shortcut, effect));
+    }
+    // Shift alone doesn't stop a key from being typed
+    let typed = accelerator.key.len() == 1 || accelerator.key == "SPACE";
+    if typed && !accelerator.has_modifier() {
+        return Some(format!(
+            "Without Ctrl, Alt or {} the key is typed as text instead",
+            if cfg!(target_os = "macos") {
+                "Cmd"
+            } else {
+                "Super"
+            }
+        ));
+    }
+    None
+}
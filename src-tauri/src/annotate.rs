@@ -0,0 +1,368 @@
+use std::fs::{self, File};
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::convert;
+use crate::ocr;
+use crate::pdf::{self, Font, Image, PdfDocument, A4};
+
+// Annotations sit on top of an attachment without touching its file. A
+// layer is JSON like
+//
+//   {"annotations": [
+//     {"kind": "highlight", "page": 1, "x": 0.1, "y": 0.2, "width": 0.5, "height": 0.05},
+//     {"kind": "note", "x": 0.8, "y": 0.1, "text": "Check this date"}
+//   ]}
+//
+// with coordinates as fractions of the page from its top left corner, so
+// they hold at any zoom or resolution.
+
+const MAX_ANNOTATIONS: usize = 1000;
+const MAX_NOTE_CHARS: usize = 2000;
+// Resolution PDF pages are flattened at; enough to read, small enough to share
+const PDF_DPI: u32 = 150;
+const HIGHLIGHT_ALPHA: f32 = 0.35;
+const NOTE_SIZE: f32 = 9.0;
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationKind {
+    Highlight,
+    Rectangle,
+    Note,
+}
+
+impl AnnotationKind {
+    fn default_color(self) -> [u8; 3] {
+        match self {
+            AnnotationKind::Highlight => [0xff, 0xeb, 0x3b],
+            AnnotationKind::Rectangle => [0xe5, 0x39, 0x35],
+            AnnotationKind::Note => [0xfb, 0x8c, 0x00],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotation {
+    pub kind: AnnotationKind,
+    /// Counted from 1; images have a single page.
+    #[serde(default = "first_page")]
+    pub page: u32,
+    pub x: f64,
+    pub y: f64,
+    /// Notes are a point and leave these at 0.
+    #[serde(default)]
+    pub width: f64,
+    #[serde(default)]
+    pub height: f64,
+    /// `#rrggbb`; each kind has its own default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// The text of a note; other kinds have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+fn first_page() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnnotationLayer {
+    pub annotations: Vec<Annotation>,
+}
+
+/// Parses and checks a layer, naming the first annotation that is wrong.
+pub fn parse_layer(json: &str) -> Result<AnnotationLayer, String> {
+    let layer: AnnotationLayer =
+        serde_json::from_str(json).map_err(|e| format!("Invalid annotation layer: {}", e))?;
+    if layer.annotations.len() > MAX_ANNOTATIONS {
+        return Err(format!(
+            "A layer holds at most {} annotations",
+            MAX_ANNOTATIONS
+        ));
+    }
+    for (index, annotation) in layer.annotations.iter().enumerate() {
+        check(annotation).map_err(|e| format!("Annotation {}: {}", index + 1, e))?;
+    }
+    Ok(layer)
+}
+
+fn check(annotation: &Annotation) -> Result<(), String> {
+    if annotation.page == 0 {
+        return Err("pages are counted from 1".to_string());
+    }
+    let fraction = |value: f64| value.is_finite() && (0.0..=1.0).contains(&value);
+    if ![
+        annotation.x,
+        annotation.y,
+        annotation.width,
+        annotation.height,
+    ]
+    .into_iter()
+    .all(fraction)
+    {
+        return Err("coordinates must be fractions of the page between 0 and 1".to_string());
+    }
+    // A little slack for rounding in the viewer
+    if annotation.x + annotation.width > 1.0 + 1e-6 || annotation.y + annotation.height > 1.0 + 1e-6
+    {
+        return Err("extends past the edge of the page".to_string());
+    }
+    if let Some(color) = &annotation.color {
+        parse_color(color).ok_or_else(|| format!("color must be #rrggbb, not {}", color))?;
+    }
+
+    match annotation.kind {
+        AnnotationKind::Note => {
+            let text = annotation.text.as_deref().unwrap_or_default();
+            if text.trim().is_empty() {
+                return Err("a note needs text".to_string());
+            }
+            if text.chars().count() > MAX_NOTE_CHARS {
+                return Err(format!(
+                    "notes are limited to {} characters",
+                    MAX_NOTE_CHARS
+                ));
+            }
+            if annotation.width != 0.0 || annotation.height != 0.0 {
+                return Err("a note is a point and has no size".to_string());
+            }
+        }
+        AnnotationKind::Highlight | AnnotationKind::Rectangle => {
+            if annotation.text.is_some() {
+                return Err("only notes have text".to_string());
+            }
+            if annotation.width <= 0.0 || annotation.height <= 0.0 {
+                return Err("needs a width and height".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn color_of(annotation: &Annotation) -> [u8; 3] {
+    annotation
+        .color
+        .as_deref()
+        .and_then(parse_color)
+        .unwrap_or_else(|| annotation.kind.default_color())
+}
+
+/// Writes a copy of `source` with the layer drawn onto it. The format
+/// follows `dest`'s extension: `pdf`, or `png` and `jpg` for a single page
+/// file. Highlights and rectangles are drawn into the pages; note text only
+/// fits in a PDF, elsewhere a note is just its marker.
+pub fn export(
+    source: &Path,
+    mime: &str,
+    layer: &AnnotationLayer,
+    dest: &Path,
+) -> Result<(), String> {
+    let extension = dest
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    if !matches!(extension.as_str(), "pdf" | "png" | "jpg" | "jpeg") {
+        return Err("Export to a .pdf, .png or .jpg file".to_string());
+    }
+
+    let (pages, dpi) = if mime == "application/pdf" {
+        (pdf_pages(source)?, Some(PDF_DPI))
+    } else if mime.starts_with("image/") {
+        (convert::image_pages(source, mime)?, None)
+    } else {
+        return Err(format!("Cannot annotate {} files", mime));
+    };
+    if pages.is_empty() {
+        return Err("The file has no pages".to_string());
+    }
+    if let Some(annotation) = layer
+        .annotations
+        .iter()
+        .find(|annotation| annotation.page as usize > pages.len())
+    {
+        return Err(format!(
+            "An annotation is on page {} but the file has {}",
+            annotation.page,
+            pages.len()
+        ));
+    }
+
+    let mut flattened: Vec<RgbaImage> = pages.iter().map(DynamicImage::to_rgba8).collect();
+    for annotation in &layer.annotations {
+        draw(&mut flattened[annotation.page as usize - 1], annotation);
+    }
+
+    match extension.as_str() {
+        "pdf" => {
+            let document = annotated_pdf(&flattened, layer, dpi);
+            fs::write(dest, document.to_bytes()).map_err(|e| e.to_string())
+        }
+        _ if flattened.len() > 1 => {
+            Err("The file has several pages; export it as a PDF".to_string())
+        }
+        "png" => DynamicImage::ImageRgba8(flattened.remove(0))
+            .save_with_format(dest, ImageFormat::Png)
+            .map_err(|e| e.to_string()),
+        _ => {
+            // JPEG has no alpha channel
+            let file = File::create(dest).map_err(|e| e.to_string())?;
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(flattened.remove(0)).to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(file, JPEG_QUALITY))
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn pdf_pages(source: &Path) -> Result<Vec<DynamicImage>, String> {
+    let dir = ocr::scratch_dir("annotate")?;
+    let pages = ocr::render_pdf(source, &dir, PDF_DPI).and_then(|files| {
+        files
+            .iter()
+            .map(|file| image::open(file).map_err(|e| e.to_string()))
+            .collect()
+    });
+    let _ = fs::remove_dir_all(&dir);
+    pages
+}
+
+fn draw(image: &mut RgbaImage, annotation: &Annotation) {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let [r, g, b] = color_of(annotation);
+    let color = Rgba([r, g, b, 255]);
+    let left = (annotation.x * width) as u32;
+    let top = (annotation.y * height) as u32;
+    let right = ((annotation.x + annotation.width) * width).ceil() as u32;
+    let bottom = ((annotation.y + annotation.height) * height).ceil() as u32;
+
+    match annotation.kind {
+        AnnotationKind::Highlight => {
+            fill(image, (left, top, right, bottom), |pixel| {
+                blend(pixel, color, HIGHLIGHT_ALPHA)
+            });
+        }
+        AnnotationKind::Rectangle => {
+            let stroke = (width.min(height) / 300.0).max(2.0) as u32;
+            for edge in [
+                (left, top, right, top + stroke),
+                (left, bottom.saturating_sub(stroke), right, bottom),
+                (left, top, left + stroke, bottom),
+                (right.saturating_sub(stroke), top, right, bottom),
+            ] {
+                fill(image, edge, |pixel| *pixel = color);
+            }
+        }
+        AnnotationKind::Note => {
+            // A square marker hanging from the point, with a dark border
+            let size = (width.min(height) / 40.0).max(12.0) as u32;
+            let border = (size / 8).max(1);
+            let outline = Rgba([r / 2, g / 2, b / 2, 255]);
+            let (left, top) = (
+                left.min(image.width().saturating_sub(size)),
+                top.min(image.height().saturating_sub(size)),
+            );
+            fill(image, (left, top, left + size, top + size), |pixel| {
+                *pixel = outline
+            });
+            fill(
+                image,
+                (
+                    left + border,
+                    top + border,
+                    left + size - border,
+                    top + size - border,
+                ),
+                |pixel| *pixel = color,
+            );
+        }
+    }
+}
+
+// Applies `paint` to the pixels from (left, top) up to (right, bottom),
+// clipped to the image
+fn fill(
+    image: &mut RgbaImage,
+    (left, top, right, bottom): (u32, u32, u32, u32),
+    paint: impl Fn(&mut Rgba<u8>),
+) {
+    for y in top..bottom.min(image.height()) {
+        for x in left..right.min(image.width()) {
+            paint(image.get_pixel_mut(x, y));
+        }
+    }
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, alpha: f32) {
+    for channel in 0..3 {
+        let under = pixel.0[channel] as f32;
+        pixel.0[channel] = (under + (color.0[channel] as f32 - under) * alpha).round() as u8;
+    }
+    pixel.0[3] = pixel.0[3].max((alpha * 255.0) as u8);
+}
+
+// One page per image. Rendered PDF pages keep their size; images are
+// fitted to A4 like a conversion. Note texts are set beside their markers.
+fn annotated_pdf(pages: &[RgbaImage], layer: &AnnotationLayer, dpi: Option<u32>) -> PdfDocument {
+    let first = &pages[0];
+    let size = match dpi {
+        Some(dpi) => {
+            let scale = 72.0 / dpi as f32;
+            (first.width() as f32 * scale, first.height() as f32 * scale)
+        }
+        None if first.width() > first.height() => (A4.1, A4.0),
+        None => A4,
+    };
+    let (page_width, page_height) = size;
+
+    let mut document = PdfDocument::new(size);
+    for (index, image) in pages.iter().enumerate() {
+        let scale = (page_width / image.width() as f32).min(page_height / image.height() as f32);
+        let (width, height) = (image.width() as f32 * scale, image.height() as f32 * scale);
+        let (left, bottom) = ((page_width - width) / 2.0, (page_height - height) / 2.0);
+
+        let page = document.add_page();
+        let id = document.add_image(Image::from_dynamic(&DynamicImage::ImageRgba8(
+            image.clone(),
+        )));
+        document.draw_image(page, id, left, bottom, width, height);
+
+        let notes = layer.annotations.iter().filter(|annotation| {
+            annotation.kind == AnnotationKind::Note && annotation.page as usize == index + 1
+        });
+        for note in notes {
+            let text = note.text.as_deref().unwrap_or_default();
+            // Beside the marker, or moved left when that runs off the page
+            let max_width = (page_width / 3.0).max(120.0);
+            let marker = (width.min(height) / 40.0).max(12.0 * scale);
+            let x = (left + note.x as f32 * width + marker + 2.0)
+                .min(page_width - max_width - 4.0)
+                .max(4.0);
+            let mut y = bottom + (1.0 - note.y as f32) * height - NOTE_SIZE;
+            for line in pdf::wrap(text, Font::Regular, NOTE_SIZE, max_width) {
+                if y < 4.0 {
+                    break;
+                }
+                document.text(page, x, y, Font::Regular, NOTE_SIZE, &line);
+                y -= NOTE_SIZE * 1.2;
+            }
+        }
+    }
+    document
+}
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::import::{read_json, ArchiveReader};
+use super::zip::{ZipReader, DEFAULT_LEVEL};
+use super::{attachment_files, to_json, write_zip, Manifest, MANIFEST_NAME};
+use crate::db::{Attachment, Category, Document};
+use crate::error::{AppError, CmdResult};
+
+// A zip like `.andoarchive`, but keeping the ids of the source archive so
+// later deltas can refer to the same records
+pub const FORMAT: &str = "ando-delta";
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeltaMetadata {
+    pub format: String,
+    pub version: u32,
+    /// `uid` of the archive the delta was exported from.
+    pub source: String,
+    /// Changes after this marker and up to `marker` are included.
+    pub since: i64,
+    pub marker: i64,
+    pub export_date: String,
+    pub app_version: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Category,
+    Document,
+    Attachment,
+}
+
+impl Entity {
+    /// The name `change_log` and `incremental_ids` use.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Entity::Category => "category",
+            Entity::Document => "document",
+            Entity::Attachment => "attachment",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Entity> {
+        match name {
+            "category" => Some(Entity::Category),
+            "document" => Some(Entity::Document),
+            "attachment" => Some(Entity::Attachment),
+            _ => None,
+        }
+    }
+}
+
+/// A record deleted in the source archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub entity: Entity,
+    pub id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct DeltaCategory {
+    pub id: i64,
+    pub name: String,
+    pub icon: String,
+    pub color: String,
+    pub parent_id: Option<i64>,
+    pub description: Option<String>,
+    pub sort_order: i64,
+}
+
+#[derive(Deserialize)]
+pub struct DeltaDocument {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub text_content: Option<String>,
+    pub category_id: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeltaAttachment {
+    pub id: i64,
+    pub document_id: i64,
+    pub filename: String,
+    pub filetype: String,
+    pub filesize: Option<i64>,
+    pub sort_order: i64,
+    #[serde(rename = "exportPath")]
+    pub export_path: String,
+}
+
+pub struct DeltaContents {
+    pub metadata: DeltaMetadata,
+    /// Parents before their children.
+    pub categories: Vec<DeltaCategory>,
+    pub documents: Vec<DeltaDocument>,
+    pub attachments: Vec<DeltaAttachment>,
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// Writes a delta at `dest` and returns its size and the ids of the
+/// attachments written; those whose file is gone are left out.
+pub fn write_delta(
+    dest: &Path,
+    metadata: &DeltaMetadata,
+    categories: &[Category],
+    documents: &[Document],
+    attachments: &[Attachment],
+    tombstones: &[Tombstone],
+) -> CmdResult<(u64, Vec<i64>)> {
+    let (files, exported) = attachment_files(attachments, |attachment| {
+        format!("attachments/{}_{}", attachment.id, attachment.filename)
+    });
+    let attachment_ids = exported
+        .iter()
+        .map(|exported| exported.attachment.id)
+        .collect();
+
+    let entries = [
+        ("metadata.json", to_json(metadata)?),
+        ("categories.json", to_json(categories)?),
+        ("documents.json", to_json(documents)?),
+        ("attachments.json", to_json(&exported)?),
+        ("tombstones.json", to_json(tombstones)?),
+    ];
+    let size = write_zip(dest, &entries, &files, DEFAULT_LEVEL)?;
+    Ok((size, attachment_ids))
+}
+
+/// Reads the JSON entries of a delta, leaving the attachment files in the
+/// returned reader.
+pub fn read_delta(path: &Path) -> CmdResult<(DeltaContents, ArchiveReader)> {
+    let file = File::open(path)?;
+    let mut zip = ZipReader::new(BufReader::new(file))?;
+
+    let not_delta = || AppError::Validation("Not an incremental export".to_string());
+    let metadata: DeltaMetadata = read_json(&mut zip, "metadata.json").map_err(|_| not_delta())?;
+    if metadata.format != FORMAT {
+        return Err(not_delta());
+    }
+    if metadata.version > VERSION {
+        return Err(AppError::IncompatibleVersion {
+            archive: metadata.version,
+            supported: VERSION,
+        });
+    }
+    let manifest: Manifest = read_json(&mut zip, MANIFEST_NAME)?;
+    manifest.check_schema_version()?;
+
+    let contents = DeltaContents {
+        metadata,
+        categories: read_json(&mut zip, "categories.json")?,
+        documents: read_json(&mut zip, "documents.json")?,
+        attachments: read_json(&mut zip, "attachments.json")?,
+        tombstones: read_json(&mut zip, "tombstones.json")?,
+    };
+    Ok((contents, zip))
+}
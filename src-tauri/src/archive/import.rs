@@ -0,0 +1,203 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::zip::ZipReader;
+use super::{Manifest, FORMAT_VERSION, MANIFEST_NAME};
+use crate::error::{AppError, CmdResult};
+
+pub type ArchiveReader = ZipReader<BufReader<File>>;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportMetadata {
+    pub version: String,
+    pub export_type: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportCategory {
+    pub id: i64,
+    pub name: String,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub parent_id: Option<i64>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportDocument {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub text_content: Option<String>,
+    pub category_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportAttachment {
+    pub document_id: i64,
+    pub filename: String,
+    pub filetype: String,
+    pub filesize: Option<i64>,
+    #[serde(rename = "exportPath")]
+    pub export_path: String,
+}
+
+pub struct ArchiveContents {
+    pub metadata: ImportMetadata,
+    pub categories: Vec<ImportCategory>,
+    pub documents: Vec<ImportDocument>,
+    pub attachments: Vec<ImportAttachment>,
+}
+
+/// Reads the JSON entries of a `.andoarchive`. Attachment files are left in
+/// the returned reader, to be read only when actually imported.
+pub fn read_archive(path: &Path) -> CmdResult<(ArchiveContents, ArchiveReader)> {
+    let file = File::open(path)?;
+    let mut zip = ZipReader::new(BufReader::new(file))?;
+
+    // Archives from the frontend export engine have no manifest
+    if zip.find(MANIFEST_NAME).is_some() {
+        let manifest: Manifest = read_json(&mut zip, MANIFEST_NAME)?;
+        manifest.check_schema_version()?;
+    }
+    let metadata: ImportMetadata = read_json(&mut zip, "metadata.json")?;
+    if metadata.version != FORMAT_VERSION {
+        return Err(AppError::Validation(format!(
+            "Unsupported archive version {}",
+            metadata.version
+        )));
+    }
+
+    let contents = ArchiveContents {
+        metadata,
+        categories: read_json(&mut zip, "categories.json")?,
+        documents: read_json(&mut zip, "documents.json")?,
+        attachments: read_json(&mut zip, "attachments.json")?,
+    };
+    Ok((contents, zip))
+}
+
+pub(super) fn read_json<T: DeserializeOwned>(zip: &mut ArchiveReader, name: &str) -> CmdResult<T> {
+    let entry = zip
+        .find(name)
+        .ok_or_else(|| AppError::Validation(format!("Archive is missing {}", name)))?;
+    let bytes = zip.read(&entry)?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| AppError::Validation(format!("Invalid {}: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufWriter;
+    use std::path::PathBuf;
+
+    use super::super::zip::ZipWriter;
+    use super::super::{verify_archive, write_archive, ExportData, ExportMetadata};
+    use super::*;
+    use crate::migrations;
+
+    const METADATA: &str = r#"{"version":"1.0","exportType":"complete"}"#;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ando-import-{}-{}", std::process::id(), name))
+    }
+
+    fn write_zip(name: &str, manifest: Option<&str>) -> PathBuf {
+        let path = temp_path(name);
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(&path).unwrap()));
+        zip.add_file("metadata.json", METADATA.as_bytes()).unwrap();
+        for entry in ["categories.json", "documents.json", "attachments.json"] {
+            zip.add_file(entry, b"[]").unwrap();
+        }
+        if let Some(manifest) = manifest {
+            zip.add_file(MANIFEST_NAME, manifest.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    fn manifest(schema_version: Option<u32>) -> String {
+        match schema_version {
+            Some(version) => format!(
+                r#"{{"version":"1.0","schema_version":{},"entries":[]}}"#,
+                version
+            ),
+            None => r#"{"version":"1.0","entries":[]}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_archives_from_this_and_older_schemas() {
+        let supported = migrations::schema_version();
+        for (name, schema_version) in [
+            ("current", Some(supported)),
+            ("older", Some(1)),
+            ("unrecorded", None),
+        ] {
+            let path = write_zip(name, Some(&manifest(schema_version)));
+            let result = read_archive(&path);
+            let _ = std::fs::remove_file(&path);
+            assert!(result.is_ok(), "{} schema refused", name);
+        }
+
+        // The frontend export engine writes no manifest
+        let path = write_zip("frontend", None);
+        let result = read_archive(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn refuses_archives_from_a_newer_schema() {
+        let supported = migrations::schema_version();
+        let path = write_zip("newer", Some(&manifest(Some(supported + 1))));
+        let result = read_archive(&path);
+        let _ = std::fs::remove_file(&path);
+        match result {
+            Err(AppError::IncompatibleVersion {
+                archive,
+                supported: reported,
+            }) => {
+                assert_eq!(archive, supported + 1);
+                assert_eq!(reported, supported);
+            }
+            Err(e) => panic!("wrong error: {}", e.code()),
+            Ok(_) => panic!("newer archive accepted"),
+        }
+    }
+
+    #[test]
+    fn written_archives_record_the_schema_version() {
+        let path = temp_path("written");
+        let metadata = ExportMetadata {
+            version: super::super::FORMAT_VERSION.to_string(),
+            export_date: "2026-01-01T00:00:00Z".to_string(),
+            total_categories: 0,
+            total_documents: 0,
+            total_attachments: 0,
+            app_version: "test".to_string(),
+            export_type: "complete".to_string(),
+            archive_name: None,
+            category_id: None,
+            document_id: None,
+        };
+        let data = ExportData {
+            categories: Vec::new(),
+            documents: Vec::new(),
+            attachments: Vec::new(),
+        };
+        write_archive(&path, &metadata, &data).unwrap();
+
+        let (_, mut zip) = read_archive(&path).unwrap();
+        let manifest: Manifest = read_json(&mut zip, MANIFEST_NAME).unwrap();
+        let report = verify_archive(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(manifest.schema_version, Some(migrations::schema_version()));
+        assert_eq!(report.unwrap().mismatch_count, 0);
+    }
+}
@@ -0,0 +1,408 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::vault::{self, Kdf, Key, Sealed, VaultCategory};
+use super::zip::{ZipReader, ZipWriter};
+use crate::error::{AppError, CmdResult};
+
+// Mobile bundles are a read-only copy of the archive for a phone to open
+// offline. They are ZIP files holding:
+//
+//   bundle.json         in the clear: format, version, KDF, cipher, the
+//                       password check and which attachments are included
+//   catalog             categories, and every document's title, tags,
+//                       dates and attachment list
+//   search              the plain text of each document and its
+//                       attachments, for building a local search index
+//   documents/<id>      one document's body as Markdown
+//   thumbnails/<id>     a JPEG at most THUMBNAIL_SIZE pixels on a side, for
+//                       image attachments
+//   attachments/<id>    the attachment file as stored, with `full`
+//
+// Everything but bundle.json is sealed with the key the password derives
+// through `kdf`: a 24-byte nonce, the ciphertext and a 16-byte tag, the
+// entry's name authenticated alongside (see `vault::Key::seal_bytes`).
+// catalog, search and documents/<id> are JSON, raw-deflated before they
+// are sealed; thumbnails and attachments are sealed as they are.
+// Version 1 sealed with `vault::LEGACY_CIPHER` and is no longer read;
+// such bundles have to be exported again.
+
+pub const FORMAT: &str = "ando-mobile";
+pub const VERSION: u32 = 2;
+pub const HEADER_NAME: &str = "bundle.json";
+pub const CATALOG_NAME: &str = "catalog";
+pub const SEARCH_NAME: &str = "search";
+pub const THUMBNAIL_SIZE: u32 = 320;
+pub const THUMBNAIL_QUALITY: u8 = 75;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MobileAttachments {
+    /// Attachment names and sizes only.
+    None,
+    /// Plus a thumbnail of every image.
+    #[default]
+    Thumbnails,
+    /// Plus thumbnails and every file in full.
+    Full,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleHeader {
+    pub format: String,
+    pub version: u32,
+    pub kdf: Kdf,
+    pub cipher: String,
+    /// Opens with the password's key, so a wrong password is caught up front.
+    pub check: Sealed,
+    pub attachments: MobileAttachments,
+    pub exported_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Catalog {
+    pub archive_name: String,
+    pub categories: Vec<VaultCategory>,
+    pub documents: Vec<BundleDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleDocument {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub category_id: Option<i64>,
+    pub tags: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub attachments: Vec<BundleAttachment>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleAttachment {
+    pub id: i64,
+    pub filename: String,
+    pub filetype: String,
+    pub filesize: Option<i64>,
+    pub alt_text: Option<String>,
+    /// Whether `thumbnails/<id>` is in the bundle.
+    pub thumbnail: bool,
+    /// Whether `attachments/<id>` is in the bundle.
+    pub included: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchEntry {
+    pub document_id: i64,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DocumentBody {
+    pub markdown: String,
+}
+
+pub fn document_name(id: i64) -> String {
+    format!("documents/{}", id)
+}
+
+pub fn thumbnail_name(id: i64) -> String {
+    format!("thumbnails/{}", id)
+}
+
+pub fn attachment_name(id: i64) -> String {
+    format!("attachments/{}", id)
+}
+
+pub struct BundleWriter {
+    zip: ZipWriter<BufWriter<File>>,
+    key: Key,
+}
+
+impl BundleWriter {
+    /// Creates the bundle and writes its header. Deriving the key takes a
+    /// while by design.
+    pub fn create(
+        dest: &Path,
+        password: &str,
+        attachments: MobileAttachments,
+        exported_at: String,
+    ) -> CmdResult<Self> {
+        let (kdf, key) = vault::new_password_key(password)?;
+        let header = BundleHeader {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            kdf,
+            cipher: vault::CIPHER.to_string(),
+            check: vault::seal_check(&key)?,
+            attachments,
+            exported_at,
+        };
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(dest)?));
+        zip.add_file(HEADER_NAME, &serde_json::to_vec_pretty(&header)?)?;
+        Ok(Self { zip, key })
+    }
+
+    pub fn add_json<T: Serialize>(&mut self, name: &str, value: &T) -> CmdResult<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        serde_json::to_writer(&mut encoder, value)?;
+        self.add_bytes(name, &encoder.finish()?)
+    }
+
+    pub fn add_bytes(&mut self, name: &str, data: &[u8]) -> CmdResult<()> {
+        let sealed = self.key.seal_bytes(data, name)?;
+        self.zip.add_file(name, &sealed)?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> CmdResult<()> {
+        self.zip.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads bundles back the way a mobile app would, entry by entry.
+pub struct BundleReader {
+    zip: ZipReader<BufReader<File>>,
+    key: Key,
+    pub header: BundleHeader,
+}
+
+impl BundleReader {
+    pub fn open(path: &Path, password: &str) -> CmdResult<Self> {
+        let mut zip = ZipReader::new(BufReader::new(File::open(path)?))
+            .map_err(|e| AppError::Validation(format!("Not a mobile bundle: {}", e)))?;
+        let entry = zip
+            .find(HEADER_NAME)
+            .ok_or_else(|| AppError::Validation("Not a mobile bundle".to_string()))?;
+        let header: BundleHeader = serde_json::from_slice(&zip.read(&entry)?)
+            .map_err(|e| AppError::Validation(format!("Invalid bundle header: {}", e)))?;
+        if header.format != FORMAT {
+            return Err(AppError::Validation("Not a mobile bundle".to_string()));
+        }
+        if header.version > VERSION {
+            return Err(AppError::Validation(format!(
+                "Bundle version {} is newer than this app supports",
+                header.version
+            )));
+        }
+        if header.cipher != vault::CIPHER {
+            return Err(AppError::Validation(format!(
+                "Unsupported cipher: {}",
+                header.cipher
+            )));
+        }
+
+        let key = Key::derive(password, &header.kdf)?;
+        vault::verify_check(&key, &header.check)?;
+        Ok(Self { zip, key, header })
+    }
+
+    pub fn json<T: DeserializeOwned>(&mut self, name: &str) -> CmdResult<T> {
+        let deflated = self.bytes(name)?;
+        let value = serde_json::from_reader(DeflateDecoder::new(deflated.as_slice()))
+            .map_err(|e| AppError::Validation(format!("Corrupt bundle entry {}: {}", name, e)))?;
+        Ok(value)
+    }
+
+    pub fn bytes(&mut self, name: &str) -> CmdResult<Vec<u8>> {
+        let entry = self
+            .zip
+            .find(name)
+            .ok_or_else(|| AppError::Validation(format!("Bundle is missing {}", name)))?;
+        let sealed = self.zip.read(&entry)?;
+        let data = self
+            .key
+            .open_bytes(&sealed, name)
+            .map_err(|e| AppError::Validation(format!("{}: {}", name, e)))?;
+        Ok(data)
+    }
+
+    /// Every entry the catalog promises, decrypted and parsed, so a bundle
+    /// that passes opens in full on the phone.
+    pub fn verify(&mut self) -> CmdResult<BundleContents> {
+        let catalog: Catalog = self.json(CATALOG_NAME)?;
+        let search: Vec<SearchEntry> = self.json(SEARCH_NAME)?;
+
+        let mut contents = BundleContents {
+            categories: catalog.categories.len(),
+            documents: catalog.documents.len(),
+            search_entries: search.len(),
+            ..Default::default()
+        };
+        for document in &catalog.documents {
+            let _: DocumentBody = self.json(&document_name(document.id))?;
+            for attachment in &document.attachments {
+                contents.attachments += 1;
+                if attachment.thumbnail {
+                    self.bytes(&thumbnail_name(attachment.id))?;
+                    contents.thumbnails += 1;
+                }
+                if attachment.included {
+                    let data = self.bytes(&attachment_name(attachment.id))?;
+                    if attachment
+                        .filesize
+                        .is_some_and(|size| size != data.len() as i64)
+                    {
+                        return Err(AppError::Validation(format!(
+                            "Attachment {} has the wrong size",
+                            attachment.id
+                        )));
+                    }
+                    contents.files += 1;
+                }
+            }
+        }
+        Ok(contents)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BundleContents {
+    pub categories: usize,
+    pub documents: usize,
+    pub search_entries: usize,
+    /// Attachments listed, whether or not their files are in the bundle.
+    pub attachments: usize,
+    pub thumbnails: usize,
+    /// Attachment files included in full.
+    pub files: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const PASSWORD: &str = "correct horse";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ando-mobile-{}-{}", std::process::id(), name))
+    }
+
+    fn write_bundle(path: &Path) {
+        let mut writer = BundleWriter::create(
+            path,
+            PASSWORD,
+            MobileAttachments::Full,
+            "2026-01-01T00:00:00Z".to_string(),
+        )
+        .unwrap();
+        let catalog = Catalog {
+            archive_name: "Home".to_string(),
+            categories: vec![VaultCategory {
+                id: 3,
+                name: "Receipts".to_string(),
+                parent_id: None,
+                icon: "folder".to_string(),
+                color: "#6B7280".to_string(),
+            }],
+            documents: vec![BundleDocument {
+                id: 7,
+                title: "Fridge".to_string(),
+                description: None,
+                category_id: Some(3),
+                tags: vec!["kitchen".to_string()],
+                created_at: "2025-05-01 10:00:00".to_string(),
+                updated_at: "2025-05-02 10:00:00".to_string(),
+                attachments: vec![BundleAttachment {
+                    id: 11,
+                    filename: "receipt.jpg".to_string(),
+                    filetype: "image/jpeg".to_string(),
+                    filesize: Some(4),
+                    alt_text: None,
+                    thumbnail: true,
+                    included: true,
+                }],
+            }],
+        };
+        writer.add_json(CATALOG_NAME, &catalog).unwrap();
+        let search = vec![SearchEntry {
+            document_id: 7,
+            text: "fridge warranty".to_string(),
+        }];
+        writer.add_json(SEARCH_NAME, &search).unwrap();
+        let body = DocumentBody {
+            markdown: "# Fridge\n\nBought in *May*.".to_string(),
+        };
+        writer.add_json(&document_name(7), &body).unwrap();
+        writer.add_bytes(&thumbnail_name(11), b"thumb").unwrap();
+        writer
+            .add_bytes(&attachment_name(11), b"\xff\xd8\xff\xd9")
+            .unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn bundles_read_back_in_full() {
+        let path = temp_path("round-trip");
+        write_bundle(&path);
+
+        let opened = BundleReader::open(&path, PASSWORD).and_then(|mut reader| {
+            let contents = reader.verify()?;
+            let catalog: Catalog = reader.json(CATALOG_NAME)?;
+            let body: DocumentBody = reader.json(&document_name(7))?;
+            let file = reader.bytes(&attachment_name(11))?;
+            Ok((reader.header.attachments, contents, catalog, body, file))
+        });
+        let wrong = BundleReader::open(&path, "wrong horse").err();
+        let _ = std::fs::remove_file(&path);
+
+        let (attachments, contents, catalog, body, file) = opened.unwrap();
+        assert_eq!(attachments, MobileAttachments::Full);
+        assert_eq!(
+            (
+                contents.categories,
+                contents.documents,
+                contents.search_entries
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(
+            (contents.attachments, contents.thumbnails, contents.files),
+            (1, 1, 1)
+        );
+        assert_eq!(catalog.archive_name, "Home");
+        assert_eq!(catalog.documents[0].tags, ["kitchen"]);
+        assert_eq!(body.markdown, "# Fridge\n\nBought in *May*.");
+        assert_eq!(file, b"\xff\xd8\xff\xd9");
+        assert_eq!(wrong.map(|e| e.code()), Some("bad_password"));
+    }
+
+    #[test]
+    fn entries_only_open_under_their_own_name() {
+        let path = temp_path("swapped");
+        let mut writer = BundleWriter::create(
+            &path,
+            PASSWORD,
+            MobileAttachments::None,
+            "2026-01-01T00:00:00Z".to_string(),
+        )
+        .unwrap();
+        writer.add_bytes(&document_name(1), b"one").unwrap();
+        // Sealed for document 1 but stored as document 2
+        let sealed = writer.key.seal_bytes(b"one", &document_name(1)).unwrap();
+        writer.zip.add_file(&document_name(2), &sealed).unwrap();
+        writer.finish().unwrap();
+
+        let read = BundleReader::open(&path, PASSWORD).map(|mut reader| {
+            (
+                reader.bytes(&document_name(1)),
+                reader.bytes(&document_name(2)),
+            )
+        });
+        let _ = std::fs::remove_file(&path);
+
+        let (first, second) = read.unwrap();
+        assert_eq!(first.unwrap(), b"one");
+        assert_eq!(second.err().map(|e| e.code()), Some("validation"));
+    }
+}
@@ -0,0 +1,309 @@
+pub mod delta;
+pub mod import;
+pub mod mobile;
+pub mod vault;
+pub mod zip;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::db::{Attachment, Category, Document};
+use crate::error::{AppError, CmdResult};
+use crate::migrations;
+use zip::{ZipReader, ZipWriter, DEFAULT_LEVEL};
+
+// Same layout the frontend export engine writes, so the existing importer
+// can read archives produced here
+pub const FORMAT_VERSION: &str = "1.0";
+
+// Written last, listing a checksum for every other entry. Archives from the
+// frontend export engine don't have one.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    /// Database schema of the app that wrote the archive; missing from
+    /// archives written before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Refuses archives written with a newer schema than this build's,
+    /// rather than importing the part of them it understands.
+    pub fn check_schema_version(&self) -> CmdResult<()> {
+        let supported = migrations::schema_version();
+        match self.schema_version {
+            Some(archive) if archive > supported => {
+                Err(AppError::IncompatibleVersion { archive, supported })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize)]
+pub struct ChecksumMismatch {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct VerifyReport {
+    pub entries_checked: usize,
+    pub mismatch_count: usize,
+    pub first_mismatch: Option<ChecksumMismatch>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMetadata {
+    pub version: String,
+    pub export_date: String,
+    pub total_categories: usize,
+    pub total_documents: usize,
+    pub total_attachments: usize,
+    pub app_version: String,
+    pub export_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ExportedAttachment<'a> {
+    #[serde(flatten)]
+    attachment: &'a Attachment,
+    #[serde(rename = "exportPath")]
+    export_path: String,
+    #[serde(rename = "originalPath")]
+    original_path: &'a str,
+}
+
+pub struct ExportData {
+    pub categories: Vec<Category>,
+    pub documents: Vec<Document>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl ExportData {
+    /// Renumbers every record from 1 so the archive only references ids it
+    /// contains. Parents outside the export become roots.
+    pub fn remap_ids(&mut self) {
+        let category_ids: HashMap<i64, i64> = self
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(index, category)| (category.id, index as i64 + 1))
+            .collect();
+        let document_ids: HashMap<i64, i64> = self
+            .documents
+            .iter()
+            .enumerate()
+            .map(|(index, document)| (document.id, index as i64 + 1))
+            .collect();
+
+        let mut levels: HashMap<i64, i64> = HashMap::new();
+        for category in &mut self.categories {
+            category.id = category_ids[&category.id];
+            category.parent_id = category
+                .parent_id
+                .and_then(|parent| category_ids.get(&parent).copied());
+            // Categories are collected parents first
+            category.level = category
+                .parent_id
+                .and_then(|parent| levels.get(&parent))
+                .map_or(0, |level| level + 1);
+            levels.insert(category.id, category.level);
+        }
+
+        for document in &mut self.documents {
+            document.id = document_ids[&document.id];
+            document.category_id = document
+                .category_id
+                .and_then(|category| category_ids.get(&category).copied());
+        }
+
+        self.attachments
+            .retain(|attachment| document_ids.contains_key(&attachment.document_id));
+        for (index, attachment) in self.attachments.iter_mut().enumerate() {
+            attachment.id = index as i64 + 1;
+            attachment.document_id = document_ids[&attachment.document_id];
+        }
+    }
+}
+
+/// Writes `data` as a `.andoarchive` at `dest` and returns the file size.
+/// Attachments whose file is gone are left out with a warning.
+pub fn write_archive(dest: &Path, metadata: &ExportMetadata, data: &ExportData) -> CmdResult<u64> {
+    write_archive_with(dest, metadata, data, DEFAULT_LEVEL)
+}
+
+/// Like `write_archive`, deflating entries at `level` (0 stores them).
+pub fn write_archive_with(
+    dest: &Path,
+    metadata: &ExportMetadata,
+    data: &ExportData,
+    level: u32,
+) -> CmdResult<u64> {
+    let (files, exported) = attachment_files(&data.attachments, |attachment| {
+        format!(
+            "attachments/doc-{}/{}_{}",
+            attachment.document_id, attachment.id, attachment.filename
+        )
+    });
+
+    let entries = [
+        ("metadata.json", to_json(metadata)?),
+        ("categories.json", to_json(&data.categories)?),
+        ("documents.json", to_json(&data.documents)?),
+        ("attachments.json", to_json(&exported)?),
+    ];
+    write_zip(dest, &entries, &files, level)
+}
+
+// The files of `attachments` that could be read, stored under `export_path`,
+// and the records of those
+fn attachment_files<'a>(
+    attachments: &'a [Attachment],
+    export_path: impl Fn(&Attachment) -> String,
+) -> (Vec<(String, Vec<u8>)>, Vec<ExportedAttachment<'a>>) {
+    let mut files = Vec::new();
+    let mut exported = Vec::new();
+
+    for attachment in attachments {
+        let bytes = match fs::read(&attachment.filepath) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("Attachment file not found: {} ({})", attachment.filepath, e);
+                continue;
+            }
+        };
+        let export_path = export_path(attachment);
+        files.push((export_path.clone(), bytes));
+        exported.push(ExportedAttachment {
+            attachment,
+            export_path,
+            original_path: &attachment.filepath,
+        });
+    }
+    (files, exported)
+}
+
+// Writes the JSON entries and files with a manifest of their checksums
+// and returns the file size
+fn write_zip(
+    dest: &Path,
+    entries: &[(&str, String)],
+    files: &[(String, Vec<u8>)],
+    level: u32,
+) -> CmdResult<u64> {
+    let file = File::create(dest)?;
+    let mut zip = ZipWriter::with_level(BufWriter::new(file), level);
+
+    let mut manifest = Manifest {
+        version: FORMAT_VERSION.to_string(),
+        schema_version: Some(migrations::schema_version()),
+        entries: Vec::new(),
+    };
+
+    let all_entries = entries
+        .iter()
+        .map(|(name, json)| (*name, json.as_bytes()))
+        .chain(
+            files
+                .iter()
+                .map(|(name, bytes)| (name.as_str(), bytes.as_slice())),
+        );
+    for (name, bytes) in all_entries {
+        zip.add_file(name, bytes)?;
+        manifest.entries.push(ManifestEntry {
+            path: name.to_string(),
+            size: bytes.len() as u64,
+            sha256: hex::encode(Sha256::digest(bytes)),
+        });
+    }
+
+    zip.add_file(MANIFEST_NAME, to_json(&manifest)?.as_bytes())?;
+
+    zip.finish()?;
+
+    let size = fs::metadata(dest)?.len();
+    Ok(size)
+}
+
+/// Recomputes the checksum of every entry listed in the archive manifest.
+/// Entries that are missing, unreadable, altered or not listed at all count
+/// as mismatches.
+pub fn verify_archive(path: &Path) -> CmdResult<VerifyReport> {
+    let file = File::open(path)?;
+    let mut zip = ZipReader::new(BufReader::new(file))?;
+
+    let manifest_entry = zip.find(MANIFEST_NAME).ok_or_else(|| {
+        AppError::Validation("Archive has no manifest to verify against".to_string())
+    })?;
+    let manifest: Manifest = serde_json::from_slice(&zip.read(&manifest_entry)?)
+        .map_err(|e| AppError::Validation(format!("Invalid manifest: {}", e)))?;
+    manifest.check_schema_version()?;
+
+    let mut mismatches = Vec::new();
+    for expected in &manifest.entries {
+        let reason = match zip.find(&expected.path) {
+            None => Some("missing from archive".to_string()),
+            Some(entry) => match zip.read(&entry) {
+                Err(e) => Some(format!("unreadable: {}", e)),
+                Ok(bytes) if hex::encode(Sha256::digest(&bytes)) != expected.sha256 => {
+                    Some("checksum mismatch".to_string())
+                }
+                Ok(_) => None,
+            },
+        };
+        if let Some(reason) = reason {
+            mismatches.push(ChecksumMismatch {
+                path: expected.path.clone(),
+                reason,
+            });
+        }
+    }
+
+    for entry in zip.entries() {
+        let listed = entry.name == MANIFEST_NAME
+            || manifest
+                .entries
+                .iter()
+                .any(|expected| expected.path == entry.name);
+        if !listed {
+            mismatches.push(ChecksumMismatch {
+                path: entry.name.clone(),
+                reason: "not listed in manifest".to_string(),
+            });
+        }
+    }
+
+    Ok(VerifyReport {
+        entries_checked: manifest.entries.len(),
+        mismatch_count: mismatches.len(),
+        first_mismatch: mismatches.into_iter().next(),
+    })
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> CmdResult<String> {
+    serde_json::to_string_pretty(value).map_err(|e| AppError::Internal(e.to_string()))
+}
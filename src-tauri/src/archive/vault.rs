@@ -0,0 +1,854 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::AeadInPlace;
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::digest::{self, OutputSizeUser};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::error::{AppError, CmdResult};
+
+// Vaults are JSON: structure and titles in the clear, every document's
+// content sealed with its own random key, and that key sealed with the
+// master key. One document can be decrypted without touching the others.
+// Version 1 derived the master key from the password; since version 2 it
+// is random and stored wrapped, by the password and by a recovery key.
+// Version 3 seals with XChaCha20-Poly1305 instead of LEGACY_CIPHER.
+
+pub const FORMAT: &str = "ando-vault";
+pub const VERSION: u32 = 3;
+
+const KDF_ALGORITHM: &str = "pbkdf2-hmac-sha256";
+// OWASP's recommendation for PBKDF2-HMAC-SHA256
+const KDF_ITERATIONS: u32 = 600_000;
+// Its 24-byte nonces are long enough to pick at random for every seal
+pub const CIPHER: &str = "xchacha20poly1305";
+// Encrypt-then-MAC with keys split off the sealing key: HMAC-SHA256 in
+// counter mode as the keystream, HMAC-SHA256 over nonce and ciphertext
+// as the tag. Vaults before version 3 used it; it is opened but never
+// written, and its entries are told apart by their 16-byte nonce.
+pub const LEGACY_CIPHER: &str = "hmac-sha256-ctr+hmac-sha256";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const LEGACY_NONCE_LEN: usize = 16;
+// Sealed under the master key so a wrong password is caught up front
+const CHECK_PLAINTEXT: &[u8] = b"ando-vault master key";
+// 160 bits, 32 characters written out
+const RECOVERY_LEN: usize = 20;
+// Crockford's base32: no I, L, O or U to misread when typing it back in
+const RECOVERY_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Serialize, Deserialize)]
+pub struct VaultFile {
+    pub format: String,
+    pub version: u32,
+    pub kdf: Kdf,
+    pub cipher: String,
+    pub check: Sealed,
+    /// The master key wrapped with a key derived from the password, from
+    /// version 2 on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub master_key: Option<Sealed>,
+    /// The master key wrapped with the recovery key, if one was generated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<Sealed>,
+    pub archive_name: String,
+    pub exported_at: String,
+    pub categories: Vec<VaultCategory>,
+    pub documents: Vec<VaultDocument>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Kdf {
+    pub algorithm: String,
+    pub iterations: u32,
+    /// Base64.
+    pub salt: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VaultCategory {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    pub icon: String,
+    pub color: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VaultDocument {
+    pub id: i64,
+    pub title: String,
+    pub category_id: Option<i64>,
+    pub tags: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// The document key, sealed with the master key.
+    pub key: Sealed,
+    /// `VaultContent` as JSON, sealed with the document key.
+    pub content: Sealed,
+}
+
+/// What gets encrypted per document.
+#[derive(Serialize, Deserialize)]
+pub struct VaultContent {
+    pub description: Option<String>,
+    pub text_content: Option<String>,
+}
+
+/// Base64 fields of one authenticated ciphertext.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sealed {
+    pub nonce: String,
+    pub ciphertext: String,
+    pub tag: String,
+}
+
+impl Sealed {
+    /// Whether this was sealed with `LEGACY_CIPHER`.
+    pub fn is_legacy(&self) -> bool {
+        BASE64
+            .decode(&self.nonce)
+            .is_ok_and(|nonce| nonce.len() == LEGACY_NONCE_LEN)
+    }
+}
+
+#[derive(PartialEq)]
+pub struct Key([u8; KEY_LEN]);
+
+impl Key {
+    pub fn random() -> CmdResult<Self> {
+        Ok(Self(random_bytes()?))
+    }
+
+    /// The master key for `password`, stretched with the vault's KDF.
+    pub fn derive(password: &str, kdf: &Kdf) -> CmdResult<Self> {
+        if kdf.algorithm != KDF_ALGORITHM {
+            return Err(AppError::Validation(format!(
+                "Unsupported key derivation: {}",
+                kdf.algorithm
+            )));
+        }
+        if kdf.iterations == 0 || kdf.iterations > KDF_ITERATIONS * 10 {
+            return Err(AppError::Validation(
+                "Invalid key derivation iterations".to_string(),
+            ));
+        }
+        let salt = BASE64
+            .decode(&kdf.salt)
+            .map_err(|_| AppError::Validation("Invalid vault salt".to_string()))?;
+        let mut key = [0; KEY_LEN];
+        pbkdf2::<HmacSha256>(password.as_bytes(), &salt, kdf.iterations, &mut key);
+        Ok(Self(key))
+    }
+
+    fn from_slice(bytes: &[u8]) -> CmdResult<Self> {
+        let bytes: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| AppError::Validation("Invalid document key".to_string()))?;
+        Ok(Self(bytes))
+    }
+
+    /// Encrypts `plaintext`; `context` is authenticated but not stored, and
+    /// has to match again when opening.
+    pub fn seal(&self, plaintext: &[u8], context: &str) -> CmdResult<Sealed> {
+        Ok(self.seal_with_nonce(plaintext, context, random_bytes()?))
+    }
+
+    fn seal_with_nonce(&self, plaintext: &[u8], context: &str, nonce: [u8; NONCE_LEN]) -> Sealed {
+        let mut ciphertext = plaintext.to_vec();
+        let tag = self.encrypt(&nonce, &associated_data(context), &mut ciphertext);
+        Sealed {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+            tag: BASE64.encode(tag),
+        }
+    }
+
+    /// Decrypts a `seal` result, failing when the key is wrong or anything
+    /// was altered. Entries from vaults before version 3 open too.
+    pub fn open(&self, sealed: &Sealed, context: &str) -> CmdResult<Vec<u8>> {
+        let decode = |field: &str| {
+            BASE64
+                .decode(field)
+                .map_err(|_| AppError::Validation("Corrupt vault entry".to_string()))
+        };
+        let nonce = decode(&sealed.nonce)?;
+        let mut data = decode(&sealed.ciphertext)?;
+        let tag = decode(&sealed.tag)?;
+
+        match nonce.len() {
+            NONCE_LEN => self.decrypt(&nonce, &associated_data(context), &mut data, &tag)?,
+            LEGACY_NONCE_LEN => self.open_legacy(&nonce, context, &mut data, &tag)?,
+            _ => return Err(AppError::Validation("Corrupt vault entry".to_string())),
+        }
+        Ok(data)
+    }
+
+    /// `seal` without the JSON and base64, for binary containers: nonce,
+    /// ciphertext and tag back to back.
+    pub fn seal_bytes(&self, plaintext: &[u8], context: &str) -> CmdResult<Vec<u8>> {
+        Ok(self.seal_bytes_with_nonce(plaintext, context, random_bytes()?))
+    }
+
+    fn seal_bytes_with_nonce(
+        &self,
+        plaintext: &[u8],
+        context: &str,
+        nonce: [u8; NONCE_LEN],
+    ) -> Vec<u8> {
+        let mut sealed = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(plaintext);
+        let tag = self.encrypt(&nonce, &associated_data(context), &mut sealed[NONCE_LEN..]);
+        sealed.extend_from_slice(&tag);
+        sealed
+    }
+
+    pub fn open_bytes(&self, sealed: &[u8], context: &str) -> CmdResult<Vec<u8>> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(AppError::Validation("Corrupt vault entry".to_string()));
+        }
+        let (nonce, rest) = sealed.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+        let mut data = ciphertext.to_vec();
+        self.decrypt(nonce, &associated_data(context), &mut data, tag)?;
+        Ok(data)
+    }
+
+    pub fn seal_key(&self, key: &Key, context: &str) -> CmdResult<Sealed> {
+        self.seal(&key.0, context)
+    }
+
+    pub fn open_key(&self, sealed: &Sealed, context: &str) -> CmdResult<Key> {
+        Key::from_slice(&self.open(sealed, context)?)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        <XChaCha20Poly1305 as chacha20poly1305::KeyInit>::new(&self.0.into())
+    }
+
+    fn encrypt(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        associated_data: &[u8],
+        data: &mut [u8],
+    ) -> [u8; TAG_LEN] {
+        self.cipher()
+            .encrypt_in_place_detached(XNonce::from_slice(nonce), associated_data, data)
+            .expect("XChaCha20-Poly1305 seals up to 256 GiB")
+            .into()
+    }
+
+    // `nonce` has to be NONCE_LEN bytes; a tag of the wrong length fails
+    // like a wrong one
+    fn decrypt(
+        &self,
+        nonce: &[u8],
+        associated_data: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> CmdResult<()> {
+        if tag.len() != TAG_LEN {
+            return Err(wrong_key());
+        }
+        self.cipher()
+            .decrypt_in_place_detached(XNonce::from_slice(nonce), associated_data, data, tag.into())
+            .map_err(|_| wrong_key())
+    }
+
+    fn open_legacy(
+        &self,
+        nonce: &[u8],
+        context: &str,
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> CmdResult<()> {
+        // verify_slice compares in constant time, and rejects a tag of the
+        // wrong length
+        self.legacy_mac(context, nonce, data)
+            .verify_slice(tag)
+            .map_err(|_| wrong_key())?;
+        apply_keystream(&self.subkey(b"encrypt"), nonce, data);
+        Ok(())
+    }
+
+    fn subkey(&self, purpose: &[u8]) -> [u8; KEY_LEN] {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC takes any key size");
+        mac.update(purpose);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn legacy_mac(&self, context: &str, nonce: &[u8], ciphertext: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.subkey(b"authenticate"))
+            .expect("HMAC takes any key size");
+        // The nonce has a fixed length and the ciphertext comes last, so
+        // only the context needs its length in front
+        mac.update(&(context.len() as u64).to_be_bytes());
+        mac.update(context.as_bytes());
+        mac.update(nonce);
+        mac.update(ciphertext);
+        mac
+    }
+}
+
+// The context with its length in front, so more fields can join it
+// without any two inputs running into each other
+fn associated_data(context: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + context.len());
+    data.extend_from_slice(&(context.len() as u64).to_be_bytes());
+    data.extend_from_slice(context.as_bytes());
+    data
+}
+
+fn wrong_key() -> AppError {
+    AppError::BadPassword("Wrong password or damaged data".to_string())
+}
+
+/// A new KDF setup with a random salt, plus the key it derives from
+/// `password`.
+pub fn new_password_key(password: &str) -> CmdResult<(Kdf, Key)> {
+    let salt: [u8; SALT_LEN] = random_bytes()?;
+    let kdf = Kdf {
+        algorithm: KDF_ALGORITHM.to_string(),
+        iterations: KDF_ITERATIONS,
+        salt: BASE64.encode(salt),
+    };
+    let key = Key::derive(password, &kdf)?;
+    Ok((kdf, key))
+}
+
+/// Wraps `master` with a key derived from `password` and a fresh salt.
+pub fn wrap_master(password: &str, master: &Key) -> CmdResult<(Kdf, Sealed)> {
+    let (kdf, key) = new_password_key(password)?;
+    let sealed = key.seal_key(master, "master")?;
+    Ok((kdf, sealed))
+}
+
+pub fn unwrap_master(password: &str, kdf: &Kdf, sealed: &Sealed) -> CmdResult<Key> {
+    Key::derive(password, kdf)?
+        .open_key(sealed, "master")
+        .map_err(|_| AppError::BadPassword("Wrong master password".to_string()))
+}
+
+/// A new recovery key as the user writes it down, eight groups of four
+/// like `7K2M-Q9XD-...`, and the key it wraps the master key with.
+pub fn new_recovery_key() -> CmdResult<(String, Key)> {
+    let bytes: [u8; RECOVERY_LEN] = random_bytes()?;
+    let mut text = String::new();
+    let mut bits = 0u32;
+    let mut pending = 0;
+    for byte in bytes {
+        bits = (bits << 8) | byte as u32;
+        pending += 8;
+        while pending >= 5 {
+            pending -= 5;
+            if !text.is_empty() && text.len() % 5 == 4 {
+                text.push('-');
+            }
+            text.push(RECOVERY_ALPHABET[((bits >> pending) & 31) as usize] as char);
+        }
+    }
+    Ok((text, recovery_wrapping_key(&bytes)))
+}
+
+/// The key a recovery key written as `text` stands for; dashes, spaces and
+/// case don't matter, and look-alike letters are read as digits.
+pub fn recovery_key(text: &str) -> CmdResult<Key> {
+    let invalid = || AppError::BadPassword("Invalid recovery key".to_string());
+    let mut bytes = Vec::with_capacity(RECOVERY_LEN);
+    let mut bits = 0u32;
+    let mut pending = 0;
+    for c in text.chars().filter(|c| *c != '-' && !c.is_whitespace()) {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        let value = RECOVERY_ALPHABET
+            .iter()
+            .position(|a| *a as char == c)
+            .ok_or_else(invalid)?;
+        bits = (bits << 5) | value as u32;
+        pending += 5;
+        if pending >= 8 {
+            pending -= 8;
+            bytes.push((bits >> pending) as u8);
+        }
+    }
+    // 32 characters are exactly 160 bits; a stray extra one leaves bits over
+    if pending != 0 {
+        return Err(invalid());
+    }
+    let bytes: [u8; RECOVERY_LEN] = bytes.try_into().map_err(|_| invalid())?;
+    Ok(recovery_wrapping_key(&bytes))
+}
+
+// Recovery keys are random already, so one HMAC stretches them to a key
+// without the password KDF's cost
+fn recovery_wrapping_key(bytes: &[u8]) -> Key {
+    let mut mac = HmacSha256::new_from_slice(bytes).expect("HMAC takes any key size");
+    mac.update(b"ando-vault recovery key");
+    Key(mac.finalize().into_bytes().into())
+}
+
+pub fn seal_check(master: &Key) -> CmdResult<Sealed> {
+    master.seal(CHECK_PLAINTEXT, "check")
+}
+
+/// Fails when the vault was written with another password.
+pub fn verify_check(master: &Key, check: &Sealed) -> CmdResult<()> {
+    match master.open(check, "check") {
+        Ok(plaintext) if plaintext == CHECK_PLAINTEXT => Ok(()),
+        _ => Err(AppError::BadPassword("Wrong master password".to_string())),
+    }
+}
+
+fn random_bytes<const N: usize>() -> CmdResult<[u8; N]> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(bytes)
+}
+
+// PBKDF2 as in RFC 8018, over any HMAC so it can be checked against the
+// RFC 6070 vectors, which are for HMAC-SHA1
+fn pbkdf2<M: Mac + digest::KeyInit + Clone>(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    output: &mut [u8],
+) {
+    let prf = <M as Mac>::new_from_slice(password).expect("HMAC takes any key size");
+    let block_len = <M as OutputSizeUser>::output_size();
+    for (index, chunk) in output.chunks_mut(block_len).enumerate() {
+        let mut block = prf.clone();
+        block.update(salt);
+        block.update(&(index as u32 + 1).to_be_bytes());
+        let mut u = block.finalize().into_bytes();
+        let mut t = u.clone();
+        for _ in 1..iterations {
+            let mut next = prf.clone();
+            next.update(&u);
+            u = next.finalize().into_bytes();
+            for (out, byte) in t.iter_mut().zip(u.iter()) {
+                *out ^= byte;
+            }
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+fn apply_keystream(key: &[u8; KEY_LEN], nonce: &[u8], data: &mut [u8]) {
+    let prf = HmacSha256::new_from_slice(key).expect("HMAC takes any key size");
+    for (counter, chunk) in data.chunks_mut(KEY_LEN).enumerate() {
+        let mut block = prf.clone();
+        block.update(nonce);
+        block.update(&(counter as u64).to_be_bytes());
+        let keystream = block.finalize().into_bytes();
+        for (byte, key_byte) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pbkdf2_hex<M: Mac + digest::KeyInit + Clone>(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        len: usize,
+    ) -> String {
+        let mut output = vec![0; len];
+        pbkdf2::<M>(password, salt, iterations, &mut output);
+        hex::encode(output)
+    }
+
+    fn unhex(text: &str) -> Vec<u8> {
+        hex::decode(text).unwrap()
+    }
+
+    // Password, salt, iterations, output length and the expected output
+    type Pbkdf2Case<'a> = (&'a [u8], &'a [u8], u32, usize, &'a str);
+
+    #[test]
+    fn pbkdf2_matches_rfc_6070() {
+        type HmacSha1 = Hmac<sha1::Sha1>;
+        let cases: [Pbkdf2Case; 5] = [
+            (
+                b"password",
+                b"salt",
+                1,
+                20,
+                "0c60c80f961f0e71f3a9b524af6012062fe037a6",
+            ),
+            (
+                b"password",
+                b"salt",
+                2,
+                20,
+                "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957",
+            ),
+            (
+                b"password",
+                b"salt",
+                4096,
+                20,
+                "4b007901b765489abead49d926f721d065a429c1",
+            ),
+            (
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                25,
+                "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038",
+            ),
+            (
+                b"pass\0word",
+                b"sa\0lt",
+                4096,
+                16,
+                "56fa6aa75548099dcc37d7f03425e0c3",
+            ),
+        ];
+        for (password, salt, iterations, len, expected) in cases {
+            assert_eq!(
+                pbkdf2_hex::<HmacSha1>(password, salt, iterations, len),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn pbkdf2_sha256_matches_rfc_7914() {
+        assert_eq!(
+            pbkdf2_hex::<HmacSha256>(b"passwd", b"salt", 1, 64),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+    }
+
+    #[test]
+    fn derive_is_pbkdf2_sha256() {
+        let kdf = Kdf {
+            algorithm: KDF_ALGORITHM.to_string(),
+            iterations: 1,
+            salt: BASE64.encode(b"salt"),
+        };
+        let key = Key::derive("passwd", &kdf).unwrap();
+        assert_eq!(
+            hex::encode(key.0),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+
+        let unsupported = Kdf {
+            algorithm: "scrypt".to_string(),
+            ..kdf.clone()
+        };
+        assert!(Key::derive("passwd", &unsupported).is_err());
+        let no_rounds = Kdf {
+            iterations: 0,
+            ..kdf
+        };
+        assert!(Key::derive("passwd", &no_rounds).is_err());
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        let long_key = [0xaa; 131];
+        let cases: [(&[u8], &[u8], &str); 6] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                &unhex("0102030405060708090a0b0c0d0e0f10111213141516171819"),
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &long_key,
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &long_key,
+                b"This is a test using a larger than block-size key and a larger than \
+                  block-size data. The key needs to be hashed before being used by the \
+                  HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, expected) in cases {
+            let mut mac = HmacSha256::new_from_slice(key).unwrap();
+            mac.update(data);
+            assert_eq!(hex::encode(mac.finalize().into_bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn xchacha20_poly1305_matches_the_draft() {
+        // draft-irtf-cfrg-xchacha-03, appendix A.3.1
+        let key = Key(std::array::from_fn(|i| 0x80 + i as u8));
+        let nonce: [u8; NONCE_LEN] = std::array::from_fn(|i| 0x40 + i as u8);
+        let associated_data = unhex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+            only one tip for the future, sunscreen would be it.";
+        let mut data = plaintext.to_vec();
+        let tag = key.encrypt(&nonce, &associated_data, &mut data);
+        assert_eq!(
+            hex::encode(&data),
+            "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb\
+             731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452\
+             2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9\
+             21f9664c97637da9768812f615c68b13b52e"
+        );
+        assert_eq!(hex::encode(tag), "c0875924c1c7987947deafd8780acf49");
+        key.decrypt(&nonce, &associated_data, &mut data, &tag)
+            .unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    // Pinned so the format can't change unnoticed
+    const KAT_PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog, twice over.";
+    const KAT_CONTEXT: &str = "document 7";
+    const KAT_SEALED: &str = "6465666768696a6b6c6d6e6f707172737475767778797a7b\
+        281b9c908e5be0e1ec10ff4dc918d3e93dd42a518e59a7c0ff750bd14e2c5864\
+        c36363124dcc8ee78e69023074227f380d80f070e9d74688\
+        6e4f01a194aa7b3dfe0770833afb87ce";
+
+    fn kat_key() -> Key {
+        Key(std::array::from_fn(|i| i as u8))
+    }
+
+    fn kat_nonce() -> [u8; NONCE_LEN] {
+        std::array::from_fn(|i| 100 + i as u8)
+    }
+
+    #[test]
+    fn seal_matches_known_answer() {
+        let key = kat_key();
+        let sealed = key.seal_bytes_with_nonce(KAT_PLAINTEXT, KAT_CONTEXT, kat_nonce());
+        assert_eq!(hex::encode(&sealed), KAT_SEALED);
+        assert_eq!(
+            key.open_bytes(&unhex(KAT_SEALED), KAT_CONTEXT).unwrap(),
+            KAT_PLAINTEXT
+        );
+
+        let sealed = key.seal_with_nonce(KAT_PLAINTEXT, KAT_CONTEXT, kat_nonce());
+        assert!(!sealed.is_legacy());
+        let bytes = unhex(KAT_SEALED);
+        let (nonce, rest) = bytes.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        assert_eq!(sealed.nonce, BASE64.encode(nonce));
+        assert_eq!(sealed.ciphertext, BASE64.encode(ciphertext));
+        assert_eq!(sealed.tag, BASE64.encode(tag));
+        assert_eq!(key.open(&sealed, KAT_CONTEXT).unwrap(), KAT_PLAINTEXT);
+    }
+
+    // Sealed by version 2
+    fn legacy_sealed() -> Sealed {
+        Sealed {
+            nonce: "ZGVmZ2hpamtsbW5vcHFycw==".to_string(),
+            ciphertext:
+                "4geKgf3uM+Cw4WQDmuZEJ4MLD4bikSH/Rt+zDgsbSX59e3rhsFzgr3Ebh0gU7wNEAp9z5WAxdI0="
+                    .to_string(),
+            tag: "aG3B4xvA6wTbKlBB7vNwNt+72Y+wTFrVfNuKIQtodSA=".to_string(),
+        }
+    }
+
+    #[test]
+    fn legacy_entries_still_open() {
+        let key = kat_key();
+        let sealed = legacy_sealed();
+        assert!(sealed.is_legacy());
+        assert_eq!(key.open(&sealed, KAT_CONTEXT).unwrap(), KAT_PLAINTEXT);
+        assert!(key.open(&sealed, "document 8").is_err());
+
+        let mut ciphertext = BASE64.decode(&sealed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = Sealed {
+            ciphertext: BASE64.encode(ciphertext),
+            ..sealed.clone()
+        };
+        assert!(key.open(&tampered, KAT_CONTEXT).is_err());
+    }
+
+    #[test]
+    fn nonces_of_other_lengths_are_rejected() {
+        let key = kat_key();
+        let sealed = key.seal(KAT_PLAINTEXT, KAT_CONTEXT).unwrap();
+        let nonce = BASE64.decode(&sealed.nonce).unwrap();
+        for nonce in [
+            Vec::new(),
+            nonce[..NONCE_LEN - 1].to_vec(),
+            [nonce.as_slice(), &[0]].concat(),
+        ] {
+            let tampered = Sealed {
+                nonce: BASE64.encode(nonce),
+                ..sealed.clone()
+            };
+            assert!(matches!(
+                key.open(&tampered, KAT_CONTEXT),
+                Err(AppError::Validation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn seal_round_trips_at_block_boundaries() {
+        let key = Key::random().unwrap();
+        for len in [0, 1, 31, 32, 33, 64, 1000] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+            let sealed = key.seal(&plaintext, "context").unwrap();
+            assert_eq!(key.open(&sealed, "context").unwrap(), plaintext);
+            let sealed = key.seal_bytes(&plaintext, "context").unwrap();
+            assert_eq!(sealed.len(), NONCE_LEN + len + TAG_LEN);
+            assert_eq!(key.open_bytes(&sealed, "context").unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn every_flipped_bit_is_rejected() {
+        let key = kat_key();
+        let sealed = unhex(KAT_SEALED);
+        for index in 0..sealed.len() {
+            for bit in 0..8 {
+                let mut tampered = sealed.clone();
+                tampered[index] ^= 1 << bit;
+                assert!(
+                    key.open_bytes(&tampered, KAT_CONTEXT).is_err(),
+                    "byte {} bit {}",
+                    index,
+                    bit
+                );
+            }
+        }
+
+        let sealed = key.seal(KAT_PLAINTEXT, KAT_CONTEXT).unwrap();
+        let mut ciphertext = BASE64.decode(&sealed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = Sealed {
+            ciphertext: BASE64.encode(ciphertext),
+            ..sealed.clone()
+        };
+        assert!(key.open(&tampered, KAT_CONTEXT).is_err());
+        assert!(key.open(&sealed, "document 8").is_err());
+        assert!(Key::random().unwrap().open(&sealed, KAT_CONTEXT).is_err());
+    }
+
+    #[test]
+    fn shortened_or_lengthened_tags_are_rejected() {
+        let key = kat_key();
+        let sealed = unhex(KAT_SEALED);
+        assert!(key
+            .open_bytes(&sealed[..sealed.len() - 1], KAT_CONTEXT)
+            .is_err());
+        assert!(key
+            .open_bytes(&sealed[..NONCE_LEN + TAG_LEN - 1], KAT_CONTEXT)
+            .is_err());
+
+        let sealed = key.seal(KAT_PLAINTEXT, KAT_CONTEXT).unwrap();
+        let tag = BASE64.decode(&sealed.tag).unwrap();
+        for tag in [
+            tag[..TAG_LEN - 1].to_vec(),
+            [tag.as_slice(), &[0]].concat(),
+            Vec::new(),
+        ] {
+            let tampered = Sealed {
+                tag: BASE64.encode(tag),
+                ..sealed.clone()
+            };
+            assert!(key.open(&tampered, KAT_CONTEXT).is_err());
+        }
+    }
+
+    #[test]
+    fn check_catches_the_wrong_master_key() {
+        let master = Key::random().unwrap();
+        let check = seal_check(&master).unwrap();
+        assert!(verify_check(&master, &check).is_ok());
+        assert!(matches!(
+            verify_check(&Key::random().unwrap(), &check),
+            Err(AppError::BadPassword(_))
+        ));
+    }
+
+    #[test]
+    fn master_key_unwraps_only_with_its_password() {
+        let master = Key::random().unwrap();
+        let kdf = Kdf {
+            algorithm: KDF_ALGORITHM.to_string(),
+            iterations: 1000,
+            salt: BASE64.encode([7; SALT_LEN]),
+        };
+        let sealed = Key::derive("correct horse", &kdf)
+            .unwrap()
+            .seal_key(&master, "master")
+            .unwrap();
+        assert!(unwrap_master("correct horse", &kdf, &sealed).unwrap() == master);
+        assert!(matches!(
+            unwrap_master("correct horsf", &kdf, &sealed),
+            Err(AppError::BadPassword(_))
+        ));
+    }
+
+    #[test]
+    fn recovery_key_round_trips() {
+        let (text, key) = new_recovery_key().unwrap();
+        let groups: Vec<&str> = text.split('-').collect();
+        assert_eq!(groups.len(), 8);
+        assert!(groups.iter().all(|group| group.len() == 4));
+        assert!(text
+            .bytes()
+            .all(|byte| byte == b'-' || RECOVERY_ALPHABET.contains(&byte)));
+
+        assert!(recovery_key(&text).unwrap() == key);
+        assert!(recovery_key(&text.to_lowercase()).unwrap() == key);
+        assert!(recovery_key(&text.replace('-', " ")).unwrap() == key);
+        assert!(recovery_key(&text.replace('-', "")).unwrap() == key);
+    }
+
+    #[test]
+    fn recovery_key_reads_look_alikes_as_digits() {
+        let text = "0000-1111-0000-1111-0000-1111-0000-1111";
+        let key = recovery_key(text).unwrap();
+        assert!(recovery_key("oOoO-iIlL-0000-1111-0000-1111-0000-1111").unwrap() == key);
+    }
+
+    #[test]
+    fn malformed_recovery_keys_are_rejected() {
+        let (text, _) = new_recovery_key().unwrap();
+        for invalid in [
+            String::new(),
+            text[..text.len() - 1].to_string(),
+            format!("{}0", text),
+            text.replacen(|c: char| c.is_ascii_alphanumeric(), "U", 1),
+        ] {
+            assert!(
+                matches!(recovery_key(&invalid), Err(AppError::BadPassword(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
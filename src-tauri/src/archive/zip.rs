@@ -0,0 +1,288 @@
+// Minimal ZIP reader/writer, enough to exchange archives with JSZip (and
+// any unzip tool): stored or deflate entries, UTF-8 names, no ZIP64
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use chrono::{Datelike, Local, Timelike};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x0605_4b50;
+
+const VERSION: u16 = 20;
+const FLAG_UTF8: u16 = 0x0800;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+/// What `ZipWriter::new` deflates at.
+pub const DEFAULT_LEVEL: u32 = 6;
+
+const END_OF_CENTRAL_DIR_SIZE: usize = 22;
+
+struct CentralEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+pub struct ZipWriter<W: Write> {
+    inner: W,
+    written: u64,
+    entries: Vec<CentralEntry>,
+    dos_time: u16,
+    dos_date: u16,
+    level: u32,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_level(inner, DEFAULT_LEVEL)
+    }
+
+    /// Deflates entries at `level`, 1 to 9; 0 stores them as they are.
+    pub fn with_level(inner: W, level: u32) -> Self {
+        let now = Local::now();
+        let dos_time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+        let dos_date =
+            (((now.year().max(1980) - 1980) as u32) << 9 | (now.month() << 5) | now.day()) as u16;
+
+        Self {
+            inner,
+            written: 0,
+            entries: Vec::new(),
+            dos_time,
+            dos_date,
+            level: level.min(9),
+        }
+    }
+
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let (method, compressed) = if self.level == 0 {
+            (METHOD_STORED, data.to_vec())
+        } else {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(self.level));
+            encoder.write_all(data)?;
+            (METHOD_DEFLATE, encoder.finish()?)
+        };
+
+        let entry = CentralEntry {
+            name: name.to_string(),
+            method,
+            crc: crc32fast::hash(data),
+            compressed_size: to_u32(compressed.len() as u64)?,
+            size: to_u32(data.len() as u64)?,
+            offset: to_u32(self.written)?,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut header, VERSION);
+        put_u16(&mut header, FLAG_UTF8);
+        put_u16(&mut header, method);
+        put_u16(&mut header, self.dos_time);
+        put_u16(&mut header, self.dos_date);
+        put_u32(&mut header, entry.crc);
+        put_u32(&mut header, entry.compressed_size);
+        put_u32(&mut header, entry.size);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, 0);
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(&compressed)?;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Writes the central directory and hands back the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.entries.len() > u16::MAX as usize {
+            return Err(io::Error::other("Too many entries for a ZIP archive"));
+        }
+
+        let directory_offset = to_u32(self.written)?;
+        let mut directory = Vec::new();
+
+        for entry in &self.entries {
+            put_u32(&mut directory, CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut directory, VERSION);
+            put_u16(&mut directory, VERSION);
+            put_u16(&mut directory, FLAG_UTF8);
+            put_u16(&mut directory, entry.method);
+            put_u16(&mut directory, self.dos_time);
+            put_u16(&mut directory, self.dos_date);
+            put_u32(&mut directory, entry.crc);
+            put_u32(&mut directory, entry.compressed_size);
+            put_u32(&mut directory, entry.size);
+            put_u16(&mut directory, entry.name.len() as u16);
+            put_u16(&mut directory, 0); // extra field length
+            put_u16(&mut directory, 0); // comment length
+            put_u16(&mut directory, 0); // disk number
+            put_u16(&mut directory, 0); // internal attributes
+            put_u32(&mut directory, 0); // external attributes
+            put_u32(&mut directory, entry.offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let count = self.entries.len() as u16;
+        let directory_size = to_u32(directory.len() as u64)?;
+
+        put_u32(&mut directory, END_OF_CENTRAL_DIR_SIGNATURE);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, count);
+        put_u16(&mut directory, count);
+        put_u32(&mut directory, directory_size);
+        put_u32(&mut directory, directory_offset);
+        put_u16(&mut directory, 0);
+
+        self.write(&directory)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u32,
+    compressed_size: u32,
+    method: u16,
+    offset: u32,
+}
+
+pub struct ZipReader<R: Read + Seek> {
+    inner: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl<R: Read + Seek> ZipReader<R> {
+    /// Parses the central directory; fails on truncated or non-ZIP input.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let len = inner.seek(SeekFrom::End(0))?;
+        // The end record sits in the last 22 bytes plus an optional comment
+        let tail_len = len.min((END_OF_CENTRAL_DIR_SIZE + u16::MAX as usize) as u64);
+        inner.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        inner.read_exact(&mut tail)?;
+
+        let end = (0..tail.len().saturating_sub(END_OF_CENTRAL_DIR_SIZE - 1))
+            .rev()
+            .find(|&i| get_u32(&tail, i) == END_OF_CENTRAL_DIR_SIGNATURE)
+            .ok_or_else(|| invalid("Not a ZIP archive or the file is truncated"))?;
+
+        let count = get_u16(&tail, end + 10) as usize;
+        let directory_size = get_u32(&tail, end + 12) as u64;
+        let directory_offset = get_u32(&tail, end + 16) as u64;
+        if directory_offset + directory_size > len {
+            return Err(invalid("Central directory lies outside the file"));
+        }
+
+        inner.seek(SeekFrom::Start(directory_offset))?;
+        let mut directory = vec![0; directory_size as usize];
+        inner.read_exact(&mut directory)?;
+
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = 0;
+        for _ in 0..count {
+            if pos + 46 > directory.len() || get_u32(&directory, pos) != CENTRAL_HEADER_SIGNATURE {
+                return Err(invalid("Corrupt central directory"));
+            }
+            let name_len = get_u16(&directory, pos + 28) as usize;
+            let extra_len = get_u16(&directory, pos + 30) as usize;
+            let comment_len = get_u16(&directory, pos + 32) as usize;
+            let name_end = pos + 46 + name_len;
+            if name_end > directory.len() {
+                return Err(invalid("Corrupt central directory"));
+            }
+
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(&directory[pos + 46..name_end]).to_string(),
+                method: get_u16(&directory, pos + 10),
+                compressed_size: get_u32(&directory, pos + 20),
+                size: get_u32(&directory, pos + 24),
+                offset: get_u32(&directory, pos + 42),
+            });
+            pos = name_end + extra_len + comment_len;
+        }
+
+        Ok(Self { inner, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, name: &str) -> Option<ZipEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .cloned()
+    }
+
+    /// Reads and decompresses an entry. The CRC is not checked, callers
+    /// verify contents against the manifest instead.
+    pub fn read(&mut self, entry: &ZipEntry) -> io::Result<Vec<u8>> {
+        self.inner.seek(SeekFrom::Start(entry.offset as u64))?;
+        let mut header = [0; 30];
+        self.inner.read_exact(&mut header)?;
+        if get_u32(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid("Corrupt local file header"));
+        }
+        let skip = get_u16(&header, 26) as i64 + get_u16(&header, 28) as i64;
+        self.inner.seek(SeekFrom::Current(skip))?;
+
+        let mut compressed = vec![0; entry.compressed_size as usize];
+        self.inner.read_exact(&mut compressed)?;
+
+        match entry.method {
+            METHOD_STORED => Ok(compressed),
+            METHOD_DEFLATE => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                DeflateDecoder::new(compressed.as_slice()).read_to_end(&mut data)?;
+                Ok(data)
+            }
+            method => Err(invalid(&format!(
+                "Unsupported compression method {}",
+                method
+            ))),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn get_u16(buf: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([buf[pos], buf[pos + 1]])
+}
+
+fn get_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]])
+}
+
+fn to_u32(value: u64) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| io::Error::other("Archive exceeds the 4 GB ZIP limit"))
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
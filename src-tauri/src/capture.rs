@@ -0,0 +1,113 @@
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+// Screenshots go through the capture tool each platform ships with, since
+// there is no capture API in Tauri itself
+
+enum Outcome {
+    Captured,
+    // The tool ran but nothing was saved, e.g. the selection was dismissed
+    Cancelled,
+    Unavailable,
+}
+
+/// Lets the user select a screen region and saves it as PNG at `dest`,
+/// falling back to the full screen where no region tool is available.
+pub fn capture_screen(dest: &Path) -> Result<(), String> {
+    if cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    {
+        return Err("No display available to capture".to_string());
+    }
+
+    let dest_arg = dest.to_string_lossy().to_string();
+    for attempt in [region_tools(&dest_arg), full_screen_tools(&dest_arg)] {
+        for (program, args) in attempt {
+            match run(dest, program, &args)? {
+                Outcome::Captured => return Ok(()),
+                Outcome::Cancelled => return Err("Screen capture cancelled".to_string()),
+                Outcome::Unavailable => continue,
+            }
+        }
+    }
+
+    Err("No screen capture tool found on this system".to_string())
+}
+
+fn run(dest: &Path, program: &str, args: &[String]) -> Result<Outcome, String> {
+    // grim needs the geometry picked with slurp first
+    let args = if program == "grim" && args.first().map(String::as_str) == Some("-g") {
+        let selection = match Command::new("slurp").output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Outcome::Unavailable),
+            Err(e) => return Err(e.to_string()),
+        };
+        if !selection.status.success() {
+            return Ok(Outcome::Cancelled);
+        }
+        let geometry = String::from_utf8_lossy(&selection.stdout)
+            .trim()
+            .to_string();
+        vec!["-g".to_string(), geometry, args[1].clone()]
+    } else {
+        args.to_vec()
+    };
+
+    match Command::new(program).args(&args).status() {
+        Ok(status) if status.success() && dest.exists() => Ok(Outcome::Captured),
+        Ok(_) => Ok(Outcome::Cancelled),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Outcome::Unavailable),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn region_tools(dest: &str) -> Vec<(&'static str, Vec<String>)> {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    if cfg!(target_os = "macos") {
+        vec![("screencapture", args(&["-i", "-x", dest]))]
+    } else if cfg!(target_os = "linux") {
+        vec![
+            ("grim", args(&["-g", dest])),
+            ("gnome-screenshot", args(&["-a", "-f", dest])),
+            ("spectacle", args(&["-r", "-b", "-n", "-o", dest])),
+            ("scrot", args(&["-s", dest])),
+            ("import", args(&[dest])),
+        ]
+    } else {
+        // Windows has no scriptable region picker
+        Vec::new()
+    }
+}
+
+fn full_screen_tools(dest: &str) -> Vec<(&'static str, Vec<String>)> {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    if cfg!(target_os = "macos") {
+        vec![("screencapture", args(&["-x", dest]))]
+    } else if cfg!(target_os = "linux") {
+        vec![
+            ("grim", args(&[dest])),
+            ("gnome-screenshot", args(&["-f", dest])),
+            ("spectacle", args(&["-f", "-b", "-n", "-o", dest])),
+            ("scrot", args(&[dest])),
+            ("import", args(&["-window", "root", dest])),
+        ]
+    } else {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+             $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+             $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+             $g = [System.Drawing.Graphics]::FromImage($bmp); \
+             $g.CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size); \
+             $bmp.Save('{}', [System.Drawing.Imaging.ImageFormat]::Png)",
+            dest.replace('\'', "''")
+        );
+        vec![(
+            "powershell",
+            vec!["-NoProfile".to_string(), "-Command".to_string(), script],
+        )]
+    }
+}
@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Registered on every connection when `collation_locale` is set, for
+// `ORDER BY title COLLATE LOCALE`
+pub const NAME: &str = "LOCALE";
+
+// Letters a locale sorts apart from their base letter: the letter, the
+// letter it follows (or precedes, for a negative step) and its step
+type Tailoring = &'static [(char, char, i8)];
+
+const NORDIC_SV: Tailoring = &[
+    ('å', 'z', 1),
+    ('ä', 'z', 2),
+    ('æ', 'z', 2),
+    ('ö', 'z', 3),
+    ('ø', 'z', 3),
+];
+const NORDIC_DA: Tailoring = &[
+    ('æ', 'z', 1),
+    ('ä', 'z', 1),
+    ('ø', 'z', 2),
+    ('ö', 'z', 2),
+    ('å', 'z', 3),
+];
+const TURKISH: Tailoring = &[
+    ('ç', 'c', 1),
+    ('ğ', 'g', 1),
+    ('ı', 'i', -1),
+    ('ö', 'o', 1),
+    ('ş', 's', 1),
+    ('ü', 'u', 1),
+];
+const SPANISH: Tailoring = &[('ñ', 'n', 1)];
+
+const TAILORINGS: &[(&[&str], &str, Tailoring)] = &[
+    (&["sv", "fi"], "sv", NORDIC_SV),
+    (&["da", "nb", "nn", "no"], "da", NORDIC_DA),
+    (&["tr", "az"], "tr", TURKISH),
+    (&["es"], "es", SPANISH),
+];
+
+// Letters that sort as two, wherever they are not tailored
+const EXPANSIONS: &[(char, &str)] = &[
+    ('ß', "ss"),
+    ('æ', "ae"),
+    ('œ', "oe"),
+    ('þ', "th"),
+    ('ĳ', "ij"),
+];
+
+// Letters without a decomposition that still sort with a base letter
+const VARIANTS: &[(char, char)] = &[('ø', 'o'), ('đ', 'd'), ('ð', 'd'), ('ł', 'l'), ('ı', 'i')];
+
+/// Compares text the way the locale's alphabet orders it, close to what
+/// the Unicode collation algorithm does: letters first, accents only
+/// where the letters tie, case only where the accents do. Punctuation and
+/// spaces sort before digits, digits before letters.
+#[derive(Debug, Clone, Copy)]
+pub struct Collator {
+    rules: &'static str,
+    tailoring: Tailoring,
+    turkic: bool,
+}
+
+// One character's weights: letter, accent and case
+type Element = (u32, u32, u8);
+
+impl Collator {
+    /// The collator for a BCP 47 tag such as `sv-SE`; only the language
+    /// matters. Languages without rules of their own get the root order.
+    pub fn new(locale: &str) -> Self {
+        let language = language(locale);
+        let (rules, tailoring) = TAILORINGS
+            .iter()
+            .find(|(languages, _, _)| languages.contains(&language.as_str()))
+            .map(|(_, rules, tailoring)| (*rules, *tailoring))
+            .unwrap_or(("root", &[]));
+        Self {
+            rules,
+            tailoring,
+            turkic: rules == "tr",
+        }
+    }
+
+    /// Which rules apply: `sv`, `da`, `tr`, `es` or `root`.
+    pub fn rules(&self) -> &'static str {
+        self.rules
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        let a_elements = self.elements(a);
+        let b_elements = self.elements(b);
+        let by = |level: fn(&Element) -> u32| {
+            a_elements
+                .iter()
+                .map(level)
+                .cmp(b_elements.iter().map(level))
+        };
+        by(|element| element.0)
+            .then_with(|| by(|element| element.1))
+            .then_with(|| by(|element| u32::from(element.2)))
+            // Text that only differs in form, e.g. composed or not
+            .then_with(|| a.cmp(b))
+    }
+
+    fn elements(&self, text: &str) -> Vec<Element> {
+        let mut elements = Vec::with_capacity(text.len());
+        for c in text.nfc() {
+            let case = u8::from(c.is_uppercase());
+            let lower = self.lowercase(c);
+            if let Some(&(_, anchor, step)) = self.tailoring.iter().find(|(l, _, _)| *l == lower) {
+                elements.push((
+                    letter_weight(anchor).saturating_add_signed(step.into()),
+                    0,
+                    case,
+                ));
+                continue;
+            }
+            if let Some((_, expansion)) = EXPANSIONS.iter().find(|(l, _)| *l == lower) {
+                for base in expansion.chars() {
+                    elements.push((letter_weight(base), 1, case));
+                }
+                continue;
+            }
+            if let Some(&(_, base)) = VARIANTS.iter().find(|(l, _)| *l == lower) {
+                elements.push((letter_weight(base), 1, case));
+                continue;
+            }
+            let mut decomposed = lower.to_string().nfd().collect::<Vec<char>>().into_iter();
+            let base = decomposed.next().unwrap_or(lower);
+            // A mark on its own sorts with its accent rather than as a letter
+            if is_combining_mark(base) {
+                if let Some(last) = elements.last_mut() {
+                    last.1 = last.1.max(u32::from(base));
+                }
+                continue;
+            }
+            let accent = decomposed.map(u32::from).max().unwrap_or(0);
+            elements.push((weight(base), accent, case));
+        }
+        elements
+    }
+
+    fn lowercase(&self, c: char) -> char {
+        match c {
+            'I' if self.turkic => 'ı',
+            'İ' => 'i',
+            _ => c.to_lowercase().next().unwrap_or(c),
+        }
+    }
+}
+
+// Room between letters for tailored ones on either side
+fn letter_weight(c: char) -> u32 {
+    (0x20_0000 + u32::from(c)) << 3
+}
+
+fn weight(c: char) -> u32 {
+    if c.is_alphanumeric() {
+        letter_weight(c)
+    } else {
+        u32::from(c) << 3
+    }
+}
+
+fn language(locale: &str) -> String {
+    locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Whether `locale` looks like a BCP 47 tag: a language of two or three
+/// letters, then subtags of up to eight letters or digits.
+pub fn is_valid_locale(locale: &str) -> bool {
+    let mut parts = locale.split(['-', '_']);
+    let language = parts.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| {
+            (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveCollation {
+    /// The `collation_locale` setting.
+    pub locale: Option<String>,
+    /// The alphabet titles sort by: `sv`, `da`, `tr` or `es` for a locale
+    /// with letters of its own, `root` for any other, `fallback` without
+    /// a locale, where case and accents are folded away.
+    pub rules: &'static str,
+    /// Whether the connections have the collation; a changed setting only
+    /// reaches them once they reconnect.
+    pub active: bool,
+}
+
+/// Whether the pool's connections sort with `NAME`.
+pub async fn is_active(pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+    let names: Vec<(i64, String)> = sqlx::query_as("PRAGMA collation_list")
+        .fetch_all(pool)
+        .await?;
+    Ok(names.iter().any(|(_, name)| name == NAME))
+}
+
+pub async fn effective(
+    pool: &SqlitePool,
+    locale: Option<String>,
+) -> Result<EffectiveCollation, sqlx::Error> {
+    let active = is_active(pool).await?;
+    let rules = match &locale {
+        Some(locale) if active => Collator::new(locale).rules(),
+        _ => "fallback",
+    };
+    Ok(EffectiveCollation {
+        locale,
+        rules,
+        active,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use sqlx::sqlite::SqliteConnectOptions;
+
+    use super::*;
+
+    fn sorted<'a>(locale: &str, words: &[&'a str]) -> Vec<&'a str> {
+        let collator = Collator::new(locale);
+        let mut words = words.to_vec();
+        words.sort_by(|a, b| collator.compare(a, b));
+        words
+    }
+
+    #[test]
+    fn picks_rules_by_language() {
+        for (locale, rules) in [
+            ("sv-SE", "sv"),
+            ("fi", "sv"),
+            ("nb_NO", "da"),
+            ("DA", "da"),
+            ("az-Latn", "tr"),
+            ("es-419", "es"),
+            ("de-DE", "root"),
+        ] {
+            assert_eq!(Collator::new(locale).rules(), rules, "{}", locale);
+        }
+    }
+
+    #[test]
+    fn nordic_letters_follow_z() {
+        let words = ["öl", "zebra", "åka", "ära", "apa"];
+        assert_eq!(sorted("sv", &words), ["apa", "zebra", "åka", "ära", "öl"]);
+        // The root order keeps them with their base letter
+        assert_eq!(sorted("en", &words), ["åka", "apa", "ära", "öl", "zebra"]);
+
+        let words = ["år", "øl", "æble", "zoo"];
+        assert_eq!(sorted("da", &words), ["zoo", "æble", "øl", "år"]);
+    }
+
+    #[test]
+    fn turkish_dotless_i_is_a_letter_of_its_own() {
+        let words = ["iki", "ılık", "hasta", "jeton", "çay", "dere", "cam"];
+        assert_eq!(
+            sorted("tr", &words),
+            ["cam", "çay", "dere", "hasta", "ılık", "iki", "jeton"]
+        );
+        // Capital I lowercases to ı, and İ to i
+        let collator = Collator::new("tr");
+        assert_eq!(collator.compare("Irmak", "ırmak"), Ordering::Greater);
+        assert_eq!(collator.compare("Irmak", "iz"), Ordering::Less);
+        assert_eq!(collator.compare("İzmir", "ılık"), Ordering::Greater);
+    }
+
+    #[test]
+    fn spanish_n_tilde_follows_n() {
+        assert_eq!(sorted("es", &["oso", "ñu", "nube"]), ["nube", "ñu", "oso"]);
+        // Elsewhere ñ is an accented n
+        assert_eq!(sorted("en", &["oso", "ñu", "nube"]), ["ñu", "nube", "oso"]);
+        assert_eq!(sorted("es", &["ñu", "nz"]), ["nz", "ñu"]);
+        assert_eq!(sorted("en", &["ñu", "nz"]), ["ñu", "nz"]);
+    }
+
+    #[test]
+    fn root_order_weighs_letters_then_accents_then_case() {
+        assert_eq!(sorted("en", &["b", "á", "A", "a"]), ["a", "A", "á", "b"]);
+        assert_eq!(
+            sorted("en", &["Résumé", "resume", "results"]),
+            ["results", "resume", "Résumé"]
+        );
+        // ß expands to ss and sorts just after it
+        assert_eq!(
+            sorted("de", &["strassf", "straße", "strasse"]),
+            ["strasse", "straße", "strassf"]
+        );
+        assert_eq!(
+            sorted("en", &["apple", "1st", "-x", " a"]),
+            [" a", "-x", "1st", "apple"]
+        );
+    }
+
+    #[test]
+    fn composed_and_decomposed_text_sort_together() {
+        let collator = Collator::new("en");
+        assert_eq!(
+            collator.compare("caf\u{e9}", "cafe"),
+            collator.compare("cafe\u{301}", "cafe")
+        );
+        assert_eq!(
+            collator.compare("caf\u{e9}s", "cafe\u{301}t"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn validates_locale_tags() {
+        for locale in ["sv", "sv-SE", "zh_Hant_TW", "es-419", "ast"] {
+            assert!(is_valid_locale(locale), "{}", locale);
+        }
+        for locale in ["", "s", "swedish", "sv-", "sv-toolongtag", "sv SE"] {
+            assert!(!is_valid_locale(locale), "{}", locale);
+        }
+    }
+
+    #[test]
+    fn sqlite_sorts_with_the_registered_collation() {
+        tauri::async_runtime::block_on(async {
+            let collator = Collator::new("sv");
+            let options = SqliteConnectOptions::from_str("sqlite::memory:")
+                .unwrap()
+                .collation(NAME, move |a, b| collator.compare(a, b));
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect_with(options)
+                .await
+                .unwrap();
+            assert!(is_active(&pool).await.unwrap());
+
+            let titles: Vec<(String,)> = sqlx::query_as(
+                "SELECT column1 FROM (VALUES ('Öl'), ('zebra'), ('Åka'), ('apa'))
+                 ORDER BY column1 COLLATE LOCALE",
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            let titles: Vec<String> = titles.into_iter().map(|(title,)| title).collect();
+            assert_eq!(titles, ["apa", "zebra", "Åka", "Öl"]);
+
+            let effective = effective(&pool, Some("sv-FI".to_string())).await.unwrap();
+            assert_eq!((effective.rules, effective.active), ("sv", true));
+        });
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Serialize;
+
+/// Version of the command interface, as semver. Adding a command bumps the
+/// minor version; changing the arguments or result of a stable command, or
+/// removing one, bumps the major version.
+pub const API_VERSION: &str = "1.39.0";
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stability {
+    Stable,
+    /// May still change without a major version bump.
+    Experimental,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub stability: Stability,
+}
+
+#[derive(Serialize)]
+pub struct ApiVersion {
+    pub version: &'static str,
+    /// Every command this backend handles.
+    pub commands: &'static [CommandInfo],
+}
+
+/// The command interface version and the commands available, so clients
+/// can check what this backend supports before calling it.
+#[tauri::command]
+pub fn api_version() -> ApiVersion {
+    ApiVersion {
+        version: API_VERSION,
+        commands: crate::COMMANDS,
+    }
+}
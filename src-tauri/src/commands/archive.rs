@@ -0,0 +1,2502 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{QueryBuilder, Sqlite, SqliteExecutor, SqlitePool};
+use tauri::{AppHandle, Manager, State};
+
+use crate::archive::mobile::{self, MobileAttachments};
+use crate::archive::zip::{self, ZipWriter};
+use crate::archive::{self, vault, ExportData, ExportMetadata, VerifyReport};
+use crate::commands::documents::{MAX_INLINE_IMAGE_BYTES, MAX_PATTERN_SIZE};
+use crate::commands::{archive_meta, audit, related, settings};
+use crate::db::{self, Attachment, Category, Document};
+use crate::deep_link;
+use crate::diff;
+use crate::error::{AppError, CmdResult};
+use crate::html;
+use crate::jobs;
+use crate::markdown::{self, MarkdownOptions};
+use crate::pdf::binder::{self, BinderEntry, BinderOptions};
+use crate::pdf::contact_sheet::{self, Caption, SheetEntry};
+use crate::settings::{Settings, SettingsStore};
+use crate::smart_folders;
+use crate::thumbnails::{self, ThumbnailCache};
+
+#[derive(Serialize)]
+pub struct ExportSummary {
+    pub document_count: usize,
+    pub file_size: u64,
+}
+
+/// Exports one category (optionally with its subcategories) as a
+/// self-contained `.andoarchive`.
+#[tauri::command]
+pub async fn export_category(
+    app: AppHandle,
+    category_id: i64,
+    dest_path: String,
+    include_subcategories: bool,
+) -> CmdResult<ExportSummary> {
+    let pool = db::pool(&app).await?;
+
+    let root: Category = sqlx::query_as("SELECT * FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Category not found".to_string()))?;
+
+    let mut categories = vec![root];
+    if include_subcategories {
+        // Breadth-first, so parents always precede their children
+        let mut index = 0;
+        while index < categories.len() {
+            let children: Vec<Category> = sqlx::query_as(
+                "SELECT * FROM categories WHERE parent_id = ? ORDER BY sort_order ASC, name ASC",
+            )
+            .bind(categories[index].id)
+            .fetch_all(&pool)
+            .await?;
+            for child in children {
+                if !categories.iter().any(|category| category.id == child.id) {
+                    categories.push(child);
+                }
+            }
+            index += 1;
+        }
+    }
+
+    let category_ids: Vec<i64> = categories.iter().map(|category| category.id).collect();
+    let documents = fetch_documents_in(&pool, &category_ids).await?;
+    let document_ids: Vec<i64> = documents.iter().map(|document| document.id).collect();
+    let attachments = fetch_attachments_of(&pool, &document_ids).await?;
+
+    let mut data = ExportData {
+        categories,
+        documents,
+        attachments,
+    };
+    data.remap_ids();
+
+    let metadata = ExportMetadata {
+        version: archive::FORMAT_VERSION.to_string(),
+        export_date: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        total_categories: data.categories.len(),
+        total_documents: data.documents.len(),
+        total_attachments: data.attachments.len(),
+        app_version: app.package_info().version.to_string(),
+        export_type: "category".to_string(),
+        archive_name: Some(archive_meta::load(&pool).await?.name),
+        category_id: Some(1),
+        document_id: None,
+    };
+
+    let dest = PathBuf::from(&dest_path);
+    let document_count = data.documents.len();
+    let file_size = tauri::async_runtime::spawn_blocking(move || {
+        archive::write_archive(&dest, &metadata, &data)
+    })
+    .await??;
+
+    audit::record(&pool, "export", "category", Some(category_id), &dest_path).await?;
+
+    Ok(ExportSummary {
+        document_count,
+        file_size,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// One `.andoarchive` with every matching document.
+    Archive,
+    /// A folder with one `.andoarchive` per document.
+    Bundle,
+}
+
+/// Exports the documents matching a smart folder's saved search, along
+/// with the categories they sit in. For a bundle `dest_path` is the folder
+/// to write into.
+#[tauri::command]
+pub async fn export_smart_folder(
+    app: AppHandle,
+    id: i64,
+    dest_path: String,
+    format: ExportFormat,
+) -> CmdResult<ExportSummary> {
+    let pool = db::pool(&app).await?;
+
+    let (name, document_ids) = smart_folders::matching_documents(&pool, id).await?;
+    if document_ids.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No documents match the smart folder \"{}\"",
+            name
+        )));
+    }
+
+    let dest = PathBuf::from(&dest_path);
+    let file_size = match format {
+        ExportFormat::Archive => {
+            let data = export_data_for(&pool, &document_ids).await?;
+            let metadata = export_metadata(&app, &pool, &data, "smart_folder").await?;
+            tauri::async_runtime::spawn_blocking(move || {
+                archive::write_archive(&dest, &metadata, &data)
+            })
+            .await??
+        }
+        ExportFormat::Bundle => {
+            fs::create_dir_all(&dest)?;
+            let mut total = 0;
+            for document_id in &document_ids {
+                let data = export_data_for(&pool, &[*document_id]).await?;
+                let mut metadata = export_metadata(&app, &pool, &data, "document").await?;
+                metadata.document_id = Some(1);
+                let path = dest.join(bundle_file_name(*document_id, &data.documents[0].title));
+                total += tauri::async_runtime::spawn_blocking(move || {
+                    archive::write_archive(&path, &metadata, &data)
+                })
+                .await??;
+            }
+            total
+        }
+    };
+
+    audit::record(&pool, "export", "smart_folder", Some(id), &dest_path).await?;
+
+    Ok(ExportSummary {
+        document_count: document_ids.len(),
+        file_size,
+    })
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Serialize)]
+pub struct RuleRedactions {
+    pub pattern: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct RedactedExport {
+    pub document_count: usize,
+    pub file_size: u64,
+    /// Replacements made by each rule, in the order given.
+    pub redactions: Vec<RuleRedactions>,
+}
+
+/// Exports the archive as an `.andoarchive` for sharing, with every match
+/// of the `rules` regexes replaced by `[REDACTED]` in titles, descriptions
+/// and bodies. The archive itself is left as it is. Documents in
+/// `exclude_category_ids` (and their subcategories) or tagged with any of
+/// `exclude_tags` are left out. Attachment files can't be redacted, so
+/// they are only included with `include_attachments`, their names
+/// redacted.
+#[tauri::command]
+pub async fn export_redacted(
+    app: AppHandle,
+    dest_path: String,
+    rules: Vec<String>,
+    exclude_category_ids: Option<Vec<i64>>,
+    exclude_tags: Option<Vec<String>>,
+    include_attachments: Option<bool>,
+) -> CmdResult<RedactedExport> {
+    let patterns = rules
+        .iter()
+        .map(|rule| {
+            let regex = RegexBuilder::new(rule)
+                .size_limit(MAX_PATTERN_SIZE)
+                .build()
+                .map_err(|e| AppError::Validation(format!("Invalid pattern {}: {}", rule, e)))?;
+            if regex.is_match("") {
+                return Err(AppError::Validation(format!(
+                    "Pattern {} must not match empty text",
+                    rule
+                )));
+            }
+            Ok(regex)
+        })
+        .collect::<CmdResult<Vec<Regex>>>()?;
+
+    let pool = db::pool(&app).await?;
+    let document_ids: Vec<i64> = sqlx::query_scalar(
+        "WITH RECURSIVE excluded(id) AS (
+           SELECT value FROM json_each(?)
+           UNION
+           SELECT c.id FROM categories c JOIN excluded e ON c.parent_id = e.id
+         )
+         SELECT d.id FROM documents d
+         WHERE (d.category_id IS NULL OR d.category_id NOT IN (SELECT id FROM excluded))
+           AND NOT EXISTS (
+             SELECT 1 FROM document_tags dt
+             JOIN tags t ON t.id = dt.tag_id
+             WHERE dt.document_id = d.id
+               AND t.name COLLATE NOCASE IN (SELECT value FROM json_each(?))
+           )
+         ORDER BY d.id ASC",
+    )
+    .bind(serde_json::to_string(
+        &exclude_category_ids.unwrap_or_default(),
+    )?)
+    .bind(serde_json::to_string(&exclude_tags.unwrap_or_default())?)
+    .fetch_all(&pool)
+    .await?;
+
+    let mut data = export_data_for(&pool, &document_ids).await?;
+    if !include_attachments.unwrap_or(false) {
+        data.attachments.clear();
+    }
+    let mut counts = vec![0; patterns.len()];
+    let mut redact = |text: &str| redact_text(&patterns, &mut counts, text);
+    for document in &mut data.documents {
+        document.title = redact(&document.title);
+        document.description = document.description.as_deref().map(&mut redact);
+        document.text_content = document
+            .text_content
+            .as_deref()
+            .map(|body| redact_html(body, &mut redact));
+        // Would identify the unredacted content
+        document.content_hash = None;
+    }
+    for attachment in &mut data.attachments {
+        attachment.filename = redact(&attachment.filename);
+    }
+
+    let metadata = export_metadata(&app, &pool, &data, "redacted").await?;
+    let dest = PathBuf::from(&dest_path);
+    let document_count = data.documents.len();
+    let file_size = tauri::async_runtime::spawn_blocking(move || {
+        archive::write_archive(&dest, &metadata, &data)
+    })
+    .await??;
+
+    audit::record(&pool, "export", "redacted", None, &dest_path).await?;
+
+    Ok(RedactedExport {
+        document_count,
+        file_size,
+        redactions: rules
+            .into_iter()
+            .zip(counts)
+            .map(|(pattern, count)| RuleRedactions { pattern, count })
+            .collect(),
+    })
+}
+
+// Each rule in turn, so a later one can't match inside an earlier one's
+// replacement unless it matches the placeholder itself
+fn redact_text(patterns: &[Regex], counts: &mut [usize], text: &str) -> String {
+    let mut text = text.to_string();
+    for (regex, count) in patterns.iter().zip(counts.iter_mut()) {
+        let matches = regex.find_iter(&text).count();
+        if matches > 0 {
+            *count += matches;
+            text = regex.replace_all(&text, REDACTED).into_owned();
+        }
+    }
+    text
+}
+
+// Attribute values too, since links keep addresses and the like there
+fn redact_html(html: &str, redact: &mut impl FnMut(&str) -> String) -> String {
+    let text = html::map_text(html, |text| redact(text));
+    html::map_attribute_values(&text, |value| redact(value))
+}
+// Bumped whenever a field of the search index changes meaning
+const SEARCH_INDEX_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct SearchIndex {
+    schema: SearchIndexSchema,
+    archive_name: String,
+    generated_at: String,
+    documents: Vec<SearchIndexEntry>,
+}
+
+// Written into the file so a published site can be built against it
+// without this code at hand
+#[derive(Serialize)]
+struct SearchIndexSchema {
+    version: u32,
+    max_tokens: Option<u32>,
+    fields: [(&'static str, &'static str); 8],
+}
+
+#[derive(Serialize)]
+struct SearchIndexEntry {
+    id: i64,
+    title: String,
+    description: Option<String>,
+    category: Option<String>,
+    tags: Vec<String>,
+    body: String,
+    truncated: bool,
+    updated_at: String,
+}
+
+const SEARCH_INDEX_FIELDS: [(&str, &str); 8] = [
+    ("id", "document id, stable within this archive"),
+    ("title", "document title"),
+    ("description", "short description, or null"),
+    ("category", "name of the document's category, or null"),
+    ("tags", "tag names, sorted"),
+    ("body", "body as plain text, whitespace collapsed"),
+    ("truncated", "whether body was cut to max_tokens words"),
+    ("updated_at", "time of the last edit, as stored"),
+];
+
+/// Writes titles, plain-text bodies and tags of every document as compact
+/// JSON, for client-side search in a published snapshot. `max_tokens` cuts
+/// each body to its first words to keep the file small. Locked documents
+/// are left out.
+#[tauri::command]
+pub async fn export_search_index(
+    app: AppHandle,
+    dest_path: String,
+    max_tokens: Option<u32>,
+) -> CmdResult<ExportSummary> {
+    let pool = db::pool(&app).await?;
+
+    let documents: Vec<Document> =
+        sqlx::query_as("SELECT * FROM documents WHERE NOT locked ORDER BY id ASC")
+            .fetch_all(&pool)
+            .await?;
+    let categories: HashMap<i64, String> = sqlx::query_as("SELECT id, name FROM categories")
+        .fetch_all(&pool)
+        .await?
+        .into_iter()
+        .collect();
+    let tag_rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT dt.document_id, t.name FROM document_tags dt
+         JOIN tags t ON t.id = dt.tag_id
+         ORDER BY t.name ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (document_id, name) in tag_rows {
+        tags.entry(document_id).or_default().push(name);
+    }
+
+    let archive_name = archive_meta::load(&pool).await?.name;
+    let dest = PathBuf::from(&dest_path);
+    let document_count = documents.len();
+    let file_size = tauri::async_runtime::spawn_blocking(move || {
+        let documents = documents
+            .into_iter()
+            .map(|document| {
+                let text = html::strip_tags(document.text_content.as_deref().unwrap_or_default());
+                let words: Vec<&str> = text.split_whitespace().collect();
+                let limit = max_tokens.map_or(words.len(), |max| words.len().min(max as usize));
+                SearchIndexEntry {
+                    id: document.id,
+                    category: document
+                        .category_id
+                        .and_then(|id| categories.get(&id).cloned()),
+                    tags: tags.remove(&document.id).unwrap_or_default(),
+                    body: words[..limit].join(" "),
+                    truncated: limit < words.len(),
+                    title: document.title,
+                    description: document.description,
+                    updated_at: document.updated_at,
+                }
+            })
+            .collect();
+        let index = SearchIndex {
+            schema: SearchIndexSchema {
+                version: SEARCH_INDEX_VERSION,
+                max_tokens,
+                fields: SEARCH_INDEX_FIELDS,
+            },
+            archive_name,
+            generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            documents,
+        };
+
+        let file = fs::File::create(&dest)?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &index)?;
+        writer.flush()?;
+        fs::metadata(&dest)
+            .map(|meta| meta.len())
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    audit::record(&pool, "export", "search_index", None, &dest_path).await?;
+
+    Ok(ExportSummary {
+        document_count,
+        file_size,
+    })
+}
+
+// Short passwords make the KDF pointless against offline guessing
+const MIN_VAULT_PASSWORD_CHARS: usize = 8;
+
+/// Writes every category and document to an encrypted vault. Structure,
+/// titles and tags stay readable; each document's description and body are
+/// encrypted with a key of its own, wrapped by the archive's master key, so
+/// `import_vault` can decrypt documents one by one. Attachments are not
+/// included. The first export sets `master_password`; later ones have to
+/// use the same password.
+#[tauri::command]
+pub async fn export_vault(
+    app: AppHandle,
+    dest_path: String,
+    master_password: String,
+) -> CmdResult<ExportSummary> {
+    check_master_password(&master_password)?;
+
+    let pool = db::pool(&app).await?;
+
+    let categories: Vec<Category> =
+        sqlx::query_as("SELECT * FROM categories ORDER BY level ASC, sort_order ASC, id ASC")
+            .fetch_all(&pool)
+            .await?;
+    let documents: Vec<Document> = sqlx::query_as("SELECT * FROM documents ORDER BY id ASC")
+        .fetch_all(&pool)
+        .await?;
+    let document_count = documents.len();
+    let file_size = write_vault(
+        &pool,
+        categories,
+        documents,
+        PathBuf::from(&dest_path),
+        master_password,
+    )
+    .await?;
+
+    audit::record(&pool, "export", "vault", None, &dest_path).await?;
+
+    Ok(ExportSummary {
+        document_count,
+        file_size,
+    })
+}
+
+// Writes `documents` and `categories` to a vault at `dest`, setting the
+// master password on first use. Returns the file size.
+async fn write_vault(
+    pool: &SqlitePool,
+    categories: Vec<Category>,
+    documents: Vec<Document>,
+    dest: PathBuf,
+    master_password: String,
+) -> CmdResult<u64> {
+    let tag_rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT dt.document_id, t.name FROM document_tags dt
+         JOIN tags t ON t.id = dt.tag_id
+         ORDER BY t.name ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (document_id, name) in tag_rows {
+        tags.entry(document_id).or_default().push(name);
+    }
+
+    let archive_name = archive_meta::load(pool).await?.name;
+    let record = load_master_key(pool).await?;
+    // Key derivation is deliberately slow
+    let (file_size, changed) = tauri::async_runtime::spawn_blocking(move || {
+        let (master, record, changed) = unlock_master_key(record, &master_password)?;
+
+        let documents = documents
+            .into_iter()
+            .map(|document| {
+                let key = vault::Key::random()?;
+                let content = serde_json::to_vec(&vault::VaultContent {
+                    description: document.description,
+                    text_content: document.text_content,
+                })?;
+                Ok(vault::VaultDocument {
+                    key: master.seal_key(&key, &format!("key:{}", document.id))?,
+                    content: key.seal(&content, &format!("content:{}", document.id))?,
+                    tags: tags.remove(&document.id).unwrap_or_default(),
+                    id: document.id,
+                    title: document.title,
+                    category_id: document.category_id,
+                    created_at: document.created_at,
+                    updated_at: document.updated_at,
+                })
+            })
+            .collect::<CmdResult<Vec<_>>>()?;
+        let file = vault::VaultFile {
+            format: vault::FORMAT.to_string(),
+            version: vault::VERSION,
+            kdf: record.kdf.clone(),
+            cipher: vault::CIPHER.to_string(),
+            check: vault::seal_check(&master)?,
+            master_key: Some(record.master_key.clone()),
+            recovery: record.recovery,
+            archive_name,
+            exported_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            categories: categories
+                .into_iter()
+                .map(|category| vault::VaultCategory {
+                    id: category.id,
+                    name: category.name,
+                    parent_id: category.parent_id,
+                    icon: category.icon,
+                    color: category.color,
+                })
+                .collect(),
+            documents,
+        };
+
+        let out = fs::File::create(&dest)?;
+        let mut writer = std::io::BufWriter::new(out);
+        serde_json::to_writer_pretty(&mut writer, &file)?;
+        writer.flush()?;
+        let file_size = fs::metadata(&dest).map(|meta| meta.len())?;
+        Ok::<_, AppError>((
+            file_size,
+            changed.then_some((record.kdf, record.master_key)),
+        ))
+    })
+    .await??;
+
+    if let Some((kdf, master_key)) = changed {
+        save_master_key(pool, &kdf, &master_key).await?;
+    }
+    Ok(file_size)
+}
+
+/// Generates a recovery key that can unlock the archive's master key when
+/// `master_password` is forgotten, replacing any earlier one. Only the
+/// master key wrapped with it is stored, and vaults exported from now on
+/// carry that too; the key itself is returned once, for the user to keep
+/// offline. It is checked to unlock the master key before being returned.
+#[tauri::command]
+pub async fn generate_recovery_key(app: AppHandle, master_password: String) -> CmdResult<String> {
+    check_master_password(&master_password)?;
+    let pool = db::pool(&app).await?;
+    let record = load_master_key(&pool).await?;
+
+    // Key derivation is deliberately slow
+    let (text, record, changed) = tauri::async_runtime::spawn_blocking(move || {
+        let (master, record, changed) = unlock_master_key(record, &master_password)?;
+        let (text, recovery) = vault::new_recovery_key()?;
+        let sealed = recovery.seal_key(&master, "recovery")?;
+
+        // Read back the way unlock_with_recovery_key will
+        let unlocked = vault::recovery_key(&text)?.open_key(&sealed, "recovery")?;
+        if unlocked != master {
+            return Err(AppError::Internal(
+                "Recovery key failed verification".to_string(),
+            ));
+        }
+        Ok((
+            text,
+            MasterKeyRecord {
+                recovery: Some(sealed),
+                ..record
+            },
+            changed,
+        ))
+    })
+    .await??;
+
+    let recovery = serde_json::to_string(&record.recovery)?;
+    let mut tx = pool.begin().await?;
+    if changed {
+        save_master_key(&mut *tx, &record.kdf, &record.master_key).await?;
+    }
+    sqlx::query(
+        "UPDATE vault_keys SET recovery = ?, recovery_created_at = CURRENT_TIMESTAMP WHERE id = 1",
+    )
+    .bind(recovery)
+    .execute(&mut *tx)
+    .await?;
+    audit::record(&mut *tx, "generate", "recovery_key", None, "").await?;
+    tx.commit().await?;
+
+    Ok(text)
+}
+
+/// Unlocks the archive's master key with a recovery key from
+/// `generate_recovery_key` and wraps it with `new_master_password`, which
+/// later exports then use. The recovery key stays valid.
+#[tauri::command]
+pub async fn unlock_with_recovery_key(
+    app: AppHandle,
+    recovery_key: String,
+    new_master_password: String,
+) -> CmdResult<()> {
+    check_master_password(&new_master_password)?;
+    let pool = db::pool(&app).await?;
+    let recovery = load_master_key(&pool)
+        .await?
+        .and_then(|record| record.recovery)
+        .ok_or_else(|| AppError::NotFound("No recovery key has been generated".to_string()))?;
+
+    let (kdf, master_key) = tauri::async_runtime::spawn_blocking(move || {
+        let master = vault::recovery_key(&recovery_key)?
+            .open_key(&recovery, "recovery")
+            .map_err(|_| AppError::BadPassword("Wrong recovery key".to_string()))?;
+        vault::wrap_master(&new_master_password, &master)
+    })
+    .await??;
+
+    let mut tx = pool.begin().await?;
+    save_master_key(&mut *tx, &kdf, &master_key).await?;
+    audit::record(&mut *tx, "unlock", "recovery_key", None, "").await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+fn check_master_password(password: &str) -> CmdResult<()> {
+    if password.chars().count() < MIN_VAULT_PASSWORD_CHARS {
+        return Err(AppError::Validation(format!(
+            "Master password must be at least {} characters",
+            MIN_VAULT_PASSWORD_CHARS
+        )));
+    }
+    Ok(())
+}
+
+// The wrapped master key as stored in `vault_keys`
+struct MasterKeyRecord {
+    kdf: vault::Kdf,
+    master_key: vault::Sealed,
+    recovery: Option<vault::Sealed>,
+}
+
+async fn load_master_key(pool: &SqlitePool) -> CmdResult<Option<MasterKeyRecord>> {
+    let row: Option<(String, String, Option<String>)> =
+        sqlx::query_as("SELECT kdf, master_key, recovery FROM vault_keys WHERE id = 1")
+            .fetch_optional(pool)
+            .await?;
+    let Some((kdf, master_key, recovery)) = row else {
+        return Ok(None);
+    };
+    let parse_error =
+        |e: serde_json::Error| AppError::Validation(format!("Corrupt vault key: {}", e));
+    Ok(Some(MasterKeyRecord {
+        kdf: serde_json::from_str(&kdf).map_err(parse_error)?,
+        master_key: serde_json::from_str(&master_key).map_err(parse_error)?,
+        recovery: recovery
+            .map(|recovery| serde_json::from_str(&recovery))
+            .transpose()
+            .map_err(parse_error)?,
+    }))
+}
+
+async fn save_master_key<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    kdf: &vault::Kdf,
+    master_key: &vault::Sealed,
+) -> CmdResult<()> {
+    sqlx::query(
+        "INSERT INTO vault_keys (id, kdf, master_key) VALUES (1, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET kdf = excluded.kdf, master_key = excluded.master_key",
+    )
+    .bind(serde_json::to_string(kdf)?)
+    .bind(serde_json::to_string(master_key)?)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+// The master key unwrapped with `password`, or a new one wrapped with it
+// when the archive has none yet; says whether the record has to be saved.
+// A master key wrapped with the legacy cipher is wrapped again.
+fn unlock_master_key(
+    record: Option<MasterKeyRecord>,
+    password: &str,
+) -> CmdResult<(vault::Key, MasterKeyRecord, bool)> {
+    if let Some(record) = record {
+        let master = vault::unwrap_master(password, &record.kdf, &record.master_key)?;
+        if !record.master_key.is_legacy() {
+            return Ok((master, record, false));
+        }
+        let (kdf, master_key) = vault::wrap_master(password, &master)?;
+        let record = MasterKeyRecord {
+            kdf,
+            master_key,
+            ..record
+        };
+        return Ok((master, record, true));
+    }
+    let master = vault::Key::random()?;
+    let (kdf, master_key) = vault::wrap_master(password, &master)?;
+    let record = MasterKeyRecord {
+        kdf,
+        master_key,
+        recovery: None,
+    };
+    Ok((master, record, true))
+}
+
+/// Writes every category, document and attachment to `dest` as a complete
+/// archive, like the full export in the export dialog.
+pub(crate) async fn write_complete_archive(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    dest: PathBuf,
+) -> CmdResult<u64> {
+    let categories: Vec<Category> =
+        sqlx::query_as("SELECT * FROM categories ORDER BY level ASC, sort_order ASC, id ASC")
+            .fetch_all(pool)
+            .await?;
+    let documents: Vec<Document> = sqlx::query_as("SELECT * FROM documents ORDER BY id ASC")
+        .fetch_all(pool)
+        .await?;
+    let attachments: Vec<Attachment> = sqlx::query_as(
+        "SELECT * FROM attachments ORDER BY document_id ASC, sort_order ASC, id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut data = ExportData {
+        categories,
+        documents,
+        attachments,
+    };
+    data.remap_ids();
+    let metadata = export_metadata(app, pool, &data, "complete").await?;
+
+    tauri::async_runtime::spawn_blocking(move || archive::write_archive(&dest, &metadata, &data))
+        .await?
+}
+
+// Remapped export data for a set of documents, with their categories and
+// the ancestors of those so the tree can be rebuilt on import
+async fn export_data_for(pool: &SqlitePool, document_ids: &[i64]) -> CmdResult<ExportData> {
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT * FROM documents WHERE id IN (");
+    let mut ids = query.separated(", ");
+    for id in document_ids {
+        ids.push_bind(id);
+    }
+    query.push(") ORDER BY id ASC");
+    let documents: Vec<Document> = query.build_query_as().fetch_all(pool).await?;
+    let categories = categories_of(pool, &documents).await?;
+    let attachments = fetch_attachments_of(pool, document_ids).await?;
+
+    let mut data = ExportData {
+        categories,
+        documents,
+        attachments,
+    };
+    data.remap_ids();
+    Ok(data)
+}
+
+// The categories `documents` sit in and all above them, parents first
+async fn categories_of(pool: &SqlitePool, documents: &[Document]) -> CmdResult<Vec<Category>> {
+    let mut categories: Vec<Category> = Vec::new();
+    let mut pending: Vec<i64> = documents.iter().filter_map(|d| d.category_id).collect();
+    while let Some(category_id) = pending.pop() {
+        if categories.iter().any(|category| category.id == category_id) {
+            continue;
+        }
+        let category: Option<Category> = sqlx::query_as("SELECT * FROM categories WHERE id = ?")
+            .bind(category_id)
+            .fetch_optional(pool)
+            .await?;
+        if let Some(category) = category {
+            pending.extend(category.parent_id);
+            categories.push(category);
+        }
+    }
+    // Parents have to precede their children
+    categories.sort_by_key(|category| (category.level, category.sort_order, category.id));
+    Ok(categories)
+}
+
+async fn export_metadata(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    data: &ExportData,
+    export_type: &str,
+) -> CmdResult<ExportMetadata> {
+    Ok(ExportMetadata {
+        version: archive::FORMAT_VERSION.to_string(),
+        export_date: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        total_categories: data.categories.len(),
+        total_documents: data.documents.len(),
+        total_attachments: data.attachments.len(),
+        app_version: app.package_info().version.to_string(),
+        export_type: export_type.to_string(),
+        archive_name: Some(archive_meta::load(pool).await?.name),
+        category_id: None,
+        document_id: None,
+    })
+}
+
+// The id keeps names unique when titles repeat
+fn bundle_file_name(document_id: i64, title: &str) -> String {
+    format!("{}-{}.andoarchive", document_id, safe_file_stem(title))
+}
+
+// A name that is safe as a file or folder name on every platform
+fn safe_file_stem(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(80)
+        .collect();
+    safe.trim().to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaintextFormat {
+    #[default]
+    Txt,
+    /// Markdown bodies are kept as written, the title becomes a heading.
+    Md,
+}
+
+impl PlaintextFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            PlaintextFormat::Txt => "txt",
+            PlaintextFormat::Md => "md",
+        }
+    }
+}
+
+/// Writes every document as a text file into a zip at `dest_path`, in
+/// folders named after its categories; uncategorized ones go at the top.
+/// Files are named by title and id. No attachments, just text.
+#[tauri::command]
+pub async fn export_plaintext(
+    app: AppHandle,
+    dest_path: String,
+    format: Option<PlaintextFormat>,
+) -> CmdResult<ExportSummary> {
+    let pool = db::pool(&app).await?;
+    let format = format.unwrap_or_default();
+    let export_settings = app.state::<SettingsStore>().get();
+
+    let categories: Vec<Category> =
+        sqlx::query_as("SELECT * FROM categories ORDER BY level ASC, sort_order ASC, id ASC")
+            .fetch_all(&pool)
+            .await?;
+    let documents: Vec<Document> = sqlx::query_as("SELECT * FROM documents ORDER BY id ASC")
+        .fetch_all(&pool)
+        .await?;
+
+    let dest = PathBuf::from(&dest_path);
+    let (document_count, file_size) = tauri::async_runtime::spawn_blocking(move || {
+        // Sibling folders that would share a name get their category id
+        let mut taken: HashSet<String> = HashSet::new();
+        let mut folders: HashMap<i64, String> = HashMap::new();
+        for category in &categories {
+            let parent = category
+                .parent_id
+                .and_then(|parent| folders.get(&parent))
+                .map(|folder| format!("{}/", folder))
+                .unwrap_or_default();
+            let name = stem_or_untitled(&category.name);
+            let mut folder = format!("{}{}", parent, name);
+            if !taken.insert(folder.to_lowercase()) {
+                folder = format!("{}{} ({})", parent, name, category.id);
+                taken.insert(folder.to_lowercase());
+            }
+            folders.insert(category.id, folder);
+        }
+
+        let file = fs::File::create(&dest)?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        for document in &documents {
+            let folder = document
+                .category_id
+                .and_then(|category| folders.get(&category))
+                .map(|folder| format!("{}/", folder))
+                .unwrap_or_default();
+            let name = format!(
+                "{}{}-{}.{}",
+                folder,
+                stem_or_untitled(&document.title),
+                document.id,
+                format.extension()
+            );
+            let text = export_settings.export_text(plaintext(document, format));
+            zip.add_file(&name, text.as_bytes())?;
+        }
+        zip.finish()?;
+
+        let size = fs::metadata(&dest)?.len();
+        Ok::<_, AppError>((documents.len(), size))
+    })
+    .await??;
+
+    audit::record(&pool, "export", "plaintext", None, &dest_path).await?;
+
+    Ok(ExportSummary {
+        document_count,
+        file_size,
+    })
+}
+
+fn stem_or_untitled(name: &str) -> String {
+    let stem = safe_file_stem(name);
+    if stem.is_empty() {
+        "Untitled".to_string()
+    } else {
+        stem
+    }
+}
+
+fn plaintext(document: &Document, format: PlaintextFormat) -> String {
+    render_plaintext(
+        &document.title,
+        document.description.as_deref(),
+        document.text_content.as_deref(),
+        format,
+    )
+}
+
+fn render_plaintext(
+    title: &str,
+    description: Option<&str>,
+    body: Option<&str>,
+    format: PlaintextFormat,
+) -> String {
+    let mut parts = vec![match format {
+        PlaintextFormat::Txt => title.to_string(),
+        PlaintextFormat::Md => format!("# {}", title),
+    }];
+    if let Some(description) = description.filter(|description| !description.trim().is_empty()) {
+        parts.push(description.trim().to_string());
+    }
+
+    parts.extend(body_paragraphs(body.unwrap_or_default()));
+
+    let mut text = parts.join("\n\n");
+    text.push('\n');
+    text
+}
+
+// Editor bodies are HTML; everything else is Markdown already
+fn body_paragraphs(body: &str) -> Vec<String> {
+    if body.trim_start().starts_with('<') {
+        html::to_paragraphs(body)
+    } else if !body.trim().is_empty() {
+        vec![body.trim().to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    /// A standalone page with the export stylesheet, if one is set.
+    Html,
+    Md,
+    Txt,
+}
+
+impl DocumentFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            DocumentFormat::Html => "html",
+            DocumentFormat::Md => "md",
+            DocumentFormat::Txt => "txt",
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "html" | "htm" => Some(DocumentFormat::Html),
+            "md" | "markdown" => Some(DocumentFormat::Md),
+            "txt" => Some(DocumentFormat::Txt),
+            _ => None,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+}
+
+#[derive(Serialize)]
+pub struct LastExport {
+    pub format: DocumentFormat,
+    pub dest_path: String,
+}
+
+#[derive(Serialize)]
+pub struct DocumentExport {
+    pub format: DocumentFormat,
+    pub dest_path: String,
+    pub file_size: u64,
+    /// Whether the format, or the path, came from the last export rather
+    /// than the caller.
+    pub format_remembered: bool,
+    pub path_remembered: bool,
+    /// Every document written, the requested one first, then those
+    /// `include_linked` reached.
+    pub documents: Vec<ExportedDocument>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedDocument {
+    pub document_id: i64,
+    pub title: String,
+    pub dest_path: String,
+    pub file_size: u64,
+    /// Links followed to reach it; 0 for the requested document.
+    pub depth: u32,
+}
+
+const DEFAULT_LINK_DEPTH: u32 = 2;
+const MAX_LINK_DEPTH: u32 = 10;
+
+/// Writes one document to a file as HTML, Markdown or plain text, without
+/// its attachments, and remembers the format and path for next time.
+/// Either can be left out to reuse the last; a format given on its own
+/// goes next to the last file with its own extension, and a path given on
+/// its own picks the format from its extension.
+///
+/// With `include_linked`, the documents it links to are written next to
+/// it as well, and theirs up to `link_depth` links away (2 by default),
+/// each once however the links loop. Links between the files written
+/// point at each other's files; links to documents left out stay
+/// `andoarchive://` links.
+#[tauri::command]
+pub async fn export_document(
+    app: AppHandle,
+    id: i64,
+    format: Option<DocumentFormat>,
+    dest_path: Option<String>,
+    include_linked: Option<bool>,
+    link_depth: Option<u32>,
+) -> CmdResult<DocumentExport> {
+    let pool = db::pool(&app).await?;
+
+    let document: Document = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+    let last = last_export_of(&pool, id).await?;
+
+    let given = format.or_else(|| {
+        dest_path
+            .as_deref()
+            .and_then(|path| DocumentFormat::from_extension(Path::new(path)))
+    });
+    let format_remembered = given.is_none() && last.is_some();
+    let format = given
+        .or(last.as_ref().map(|last| last.format))
+        .unwrap_or(DocumentFormat::Html);
+    let path_remembered = dest_path.is_none();
+    let dest_path = match dest_path {
+        Some(path) => path,
+        None => {
+            let last = last.ok_or_else(|| {
+                AppError::Validation(
+                    "The document hasn't been exported before, so a path is needed".to_string(),
+                )
+            })?;
+            PathBuf::from(last.dest_path)
+                .with_extension(format.extension())
+                .to_string_lossy()
+                .to_string()
+        }
+    };
+
+    let dest = PathBuf::from(&dest_path);
+    let mut included = vec![(document, 0, dest.clone())];
+    if include_linked.unwrap_or(false) {
+        let depth = link_depth.unwrap_or(DEFAULT_LINK_DEPTH).min(MAX_LINK_DEPTH);
+        let folder = dest.parent().map(Path::to_path_buf).unwrap_or_default();
+        for (linked, depth) in linked_graph(&pool, id, depth).await? {
+            let file_name = format!(
+                "{}-{}.{}",
+                linked.id,
+                safe_file_stem(&linked.title),
+                format.extension()
+            );
+            included.push((linked, depth, folder.join(file_name)));
+        }
+    }
+    let files: HashMap<i64, String> = included
+        .iter()
+        .filter_map(|(document, _, path)| {
+            let name = path.file_name()?.to_string_lossy().replace(' ', "%20");
+            Some((document.id, name))
+        })
+        .collect();
+
+    let export_settings = app.state::<SettingsStore>().get();
+    let stylesheet = match format {
+        DocumentFormat::Html => settings::export_stylesheet(&app)?,
+        _ => None,
+    };
+    let mut documents = Vec::new();
+    for (mut document, depth, path) in included {
+        if files.len() > 1 {
+            document.text_content = document
+                .text_content
+                .map(|body| rewrite_document_links(&body, &files));
+        }
+        let contents = export_settings.export_text(match format {
+            DocumentFormat::Html => {
+                html_page(&document, export_settings.markdown, stylesheet.as_deref())
+            }
+            DocumentFormat::Md => plaintext(&document, PlaintextFormat::Md),
+            DocumentFormat::Txt => plaintext(&document, PlaintextFormat::Txt),
+        });
+        let target = path.clone();
+        let file_size = tauri::async_runtime::spawn_blocking(move || {
+            fs::write(&target, contents)?;
+            Ok::<_, AppError>(fs::metadata(&target)?.len())
+        })
+        .await??;
+        documents.push(ExportedDocument {
+            document_id: document.id,
+            title: document.title,
+            dest_path: path.to_string_lossy().to_string(),
+            file_size,
+            depth,
+        });
+    }
+
+    sqlx::query("UPDATE documents SET last_export_format = ?, last_export_path = ? WHERE id = ?")
+        .bind(format.extension())
+        .bind(&dest_path)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    audit::record(&pool, "export", "document", Some(id), &dest_path).await?;
+
+    Ok(DocumentExport {
+        format,
+        file_size: documents[0].file_size,
+        dest_path,
+        format_remembered,
+        path_remembered,
+        documents,
+    })
+}
+
+// Documents `id` links to, breadth-first up to `max_depth` links away,
+// each with the fewest links it takes to reach it
+async fn linked_graph(
+    pool: &SqlitePool,
+    id: i64,
+    max_depth: u32,
+) -> CmdResult<Vec<(Document, u32)>> {
+    related::refresh_related_index(pool).await?;
+
+    let mut seen = HashSet::from([id]);
+    let mut frontier = vec![id];
+    let mut linked = Vec::new();
+    for depth in 1..=max_depth {
+        let mut next = Vec::new();
+        for source in frontier {
+            let targets: Vec<Document> = sqlx::query_as(
+                "SELECT d.* FROM document_links l JOIN documents d ON d.id = l.target_id
+                 WHERE l.source_id = ? ORDER BY d.id ASC",
+            )
+            .bind(source)
+            .fetch_all(pool)
+            .await?;
+            for target in targets {
+                // Cycles end at a document already included
+                if seen.insert(target.id) {
+                    next.push(target.id);
+                    linked.push((target, depth));
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    Ok(linked)
+}
+
+// Points `andoarchive://document/<id>` links at the exported file of
+// that document, where there is one
+fn rewrite_document_links(body: &str, files: &HashMap<i64, String>) -> String {
+    let prefix = format!("{}://document/", deep_link::SCHEME);
+    let mut rewritten = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find(&prefix) {
+        let after = &rest[start + prefix.len()..];
+        let digits = after
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let file = after[..digits]
+            .parse::<i64>()
+            .ok()
+            .and_then(|id| files.get(&id));
+        match file {
+            Some(file) => {
+                rewritten.push_str(&rest[..start]);
+                rewritten.push_str(file);
+                let end = start + prefix.len() + digits;
+                rest = rest[end..].strip_prefix('/').unwrap_or(&rest[end..]);
+            }
+            None => {
+                let end = start + prefix.len();
+                rewritten.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+/// How `export_document` last wrote a document, for offering to export it
+/// the same way again.
+#[tauri::command]
+pub async fn get_last_export(app: AppHandle, id: i64) -> CmdResult<Option<LastExport>> {
+    let pool = db::pool(&app).await?;
+    last_export_of(&pool, id).await
+}
+
+async fn last_export_of(pool: &SqlitePool, id: i64) -> CmdResult<Option<LastExport>> {
+    let row: Option<(Option<String>, Option<String>)> =
+        sqlx::query_as("SELECT last_export_format, last_export_path FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(match row {
+        Some((Some(format), Some(dest_path))) => {
+            DocumentFormat::parse(&format).map(|format| LastExport { format, dest_path })
+        }
+        _ => None,
+    })
+}
+
+fn html_page(document: &Document, options: MarkdownOptions, stylesheet: Option<&str>) -> String {
+    let title = html::escape(&document.title);
+    let style = stylesheet
+        .map(|css| format!("<style>\n{}\n</style>\n", css))
+        .unwrap_or_default();
+    let body = markdown::body_html(
+        document.text_content.as_deref().unwrap_or_default(),
+        options,
+    );
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n{1}</head>\n<body>\n<h1>{0}</h1>\n{2}\n</body>\n</html>\n",
+        title, style, body
+    )
+}
+
+#[derive(sqlx::FromRow)]
+struct VersionRow {
+    id: i64,
+    title: String,
+    description: Option<String>,
+    text_content: Option<String>,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct PatchIndexEntry {
+    version: usize,
+    version_id: i64,
+    created_at: String,
+    title: String,
+    file: String,
+    added: usize,
+    removed: usize,
+}
+
+#[derive(Serialize)]
+pub struct HistoryPatches {
+    pub patches: Vec<String>,
+    pub index: String,
+}
+
+/// Writes the history of a document into `dest_dir` as a patch series:
+/// one unified diff per change of its text, named by version number and
+/// time, the first creating the file from nothing. `index.json` lists the
+/// patches in order. Saves that left the text unchanged get no patch, but
+/// keep their version number. Line breaks are written as configured for
+/// exports.
+#[tauri::command]
+pub async fn export_history_patches(
+    app: AppHandle,
+    document_id: i64,
+    dest_dir: String,
+) -> CmdResult<HistoryPatches> {
+    let pool = db::pool(&app).await?;
+
+    let settings = app.state::<SettingsStore>().get();
+    let result = write_history_patches(&pool, &settings, document_id, &dest_dir).await?;
+
+    let details = format!("{} patches to {}", result.patches.len(), dest_dir);
+    audit::record(&pool, "export", "document", Some(document_id), &details).await?;
+
+    Ok(result)
+}
+
+async fn write_history_patches(
+    pool: &SqlitePool,
+    settings: &Settings,
+    document_id: i64,
+    dest_dir: &str,
+) -> CmdResult<HistoryPatches> {
+    let versions: Vec<VersionRow> = sqlx::query_as(
+        "SELECT id, title, description, text_content, created_at FROM document_versions
+         WHERE document_id = ? ORDER BY id ASC",
+    )
+    .bind(document_id)
+    .fetch_all(pool)
+    .await?;
+    if versions.is_empty() {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let dest = PathBuf::from(dest_dir);
+    let settings = settings.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        fs::create_dir_all(&dest)?;
+        let count = versions.len();
+        let file_name = format!("{}.md", stem_or_untitled(&versions[count - 1].title));
+
+        let mut previous: Option<String> = None;
+        let mut index = Vec::new();
+        let mut patches = Vec::new();
+        for (number, version) in versions.iter().enumerate() {
+            let number = number + 1;
+            let text = render_plaintext(
+                &version.title,
+                version.description.as_deref(),
+                version.text_content.as_deref(),
+                PlaintextFormat::Md,
+            );
+            if previous.as_deref() == Some(text.as_str()) {
+                continue;
+            }
+
+            let time = DateTime::<Utc>::from_timestamp(version.created_at, 0).unwrap_or_default();
+            let (added, removed) = diff::stat(previous.as_deref().unwrap_or_default(), &text);
+            let subject = match previous {
+                None => format!("Create \"{}\"", version.title),
+                Some(_) => format!("Update \"{}\"", version.title),
+            };
+            let header = match previous {
+                None => format!("new file mode 100644\n--- /dev/null\n+++ b/{}\n", file_name),
+                Some(_) => format!("--- a/{0}\n+++ b/{0}\n", file_name),
+            };
+            let mut patch = format!(
+                "From {} {}\nDate: {}\nSubject: [PATCH {}/{}] {}\n\n",
+                version.id,
+                time.format("%a %b %e %H:%M:%S %Y"),
+                time.to_rfc2822(),
+                number,
+                count,
+                subject,
+            );
+            patch.push_str(&format!(
+                "---\n {} | {} {}{}\n\ndiff --git a/{} b/{}\n",
+                file_name,
+                added + removed,
+                "+".repeat(added.min(40)),
+                "-".repeat(removed.min(40)),
+                file_name,
+                file_name,
+            ));
+            patch.push_str(&header);
+            patch.push_str(&diff::unified(
+                previous.as_deref().unwrap_or_default(),
+                &text,
+            ));
+
+            let name = format!("{:04}-{}.patch", number, time.format("%Y%m%d-%H%M%S"));
+            let path = dest.join(&name);
+            fs::write(&path, settings.export_text(patch))?;
+            patches.push(path.to_string_lossy().to_string());
+            index.push(PatchIndexEntry {
+                version: number,
+                version_id: version.id,
+                created_at: time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                title: version.title.clone(),
+                file: name,
+                added,
+                removed,
+            });
+            previous = Some(text);
+        }
+
+        let index_path = dest.join("index.json");
+        fs::write(&index_path, serde_json::to_vec_pretty(&index)?)?;
+        Ok::<_, AppError>(HistoryPatches {
+            patches,
+            index: index_path.to_string_lossy().to_string(),
+        })
+    })
+    .await?
+}
+
+/// Checks every entry of an archive against the checksums in its manifest.
+/// Archives from a newer version of the app fail with
+/// `IncompatibleVersion`, as importing them would.
+#[tauri::command]
+pub async fn verify_archive(path: String) -> CmdResult<VerifyReport> {
+    tauri::async_runtime::spawn_blocking(move || archive::verify_archive(&PathBuf::from(path)))
+        .await?
+}
+
+#[derive(Clone, Serialize)]
+pub struct MobileBundleSummary {
+    #[serde(flatten)]
+    pub contents: mobile::BundleContents,
+    pub file_size: u64,
+}
+
+#[derive(Serialize)]
+pub struct MobileBundleReport {
+    #[serde(flatten)]
+    pub contents: mobile::BundleContents,
+    pub attachments_mode: MobileAttachments,
+    pub exported_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct BundledAttachment {
+    id: i64,
+    document_id: i64,
+    filename: String,
+    filepath: String,
+    filetype: String,
+    filesize: Option<i64>,
+    alt_text: Option<String>,
+    ocr_text: Option<String>,
+}
+
+/// Starts a job writing the archive as an encrypted bundle for reading on
+/// a phone: bodies as Markdown, the plain text of every document for
+/// searching, and per `attachments` no files, thumbnails of the images
+/// (the default) or thumbnails and every file in full. The whole bundle
+/// opens with `password`. The layout is described in `archive::mobile`;
+/// `verify_mobile_bundle` reads one back. Returns the job id.
+#[tauri::command]
+pub async fn export_mobile_bundle(
+    app: AppHandle,
+    dest_path: String,
+    password: String,
+    attachments: Option<MobileAttachments>,
+) -> CmdResult<u64> {
+    if password.chars().count() < MIN_VAULT_PASSWORD_CHARS {
+        return Err(AppError::Validation(format!(
+            "Bundle password must be at least {} characters",
+            MIN_VAULT_PASSWORD_CHARS
+        )));
+    }
+    let mode = attachments.unwrap_or_default();
+
+    let pool = db::pool(&app).await?;
+    let categories: Vec<Category> =
+        sqlx::query_as("SELECT * FROM categories ORDER BY level ASC, sort_order ASC, id ASC")
+            .fetch_all(&pool)
+            .await?;
+    let documents: Vec<Document> = sqlx::query_as("SELECT * FROM documents ORDER BY id ASC")
+        .fetch_all(&pool)
+        .await?;
+    let tag_rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT dt.document_id, t.name FROM document_tags dt
+         JOIN tags t ON t.id = dt.tag_id
+         ORDER BY t.name ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (document_id, name) in tag_rows {
+        tags.entry(document_id).or_default().push(name);
+    }
+    let attachment_rows: Vec<BundledAttachment> = sqlx::query_as(
+        "SELECT id, document_id, filename, filepath, filetype, filesize, alt_text, ocr_text
+         FROM attachments ORDER BY document_id ASC, sort_order ASC, id ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let mut attachments: HashMap<i64, Vec<BundledAttachment>> = HashMap::new();
+    for attachment in attachment_rows {
+        attachments
+            .entry(attachment.document_id)
+            .or_default()
+            .push(attachment);
+    }
+    let archive_name = archive_meta::load(&pool).await?.name;
+
+    let job_id = jobs::spawn(&app, "export_mobile_bundle", move |job| async move {
+        let dest = PathBuf::from(&dest_path);
+        let summary = tauri::async_runtime::spawn_blocking(move || {
+            let exported_at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+            let mut writer = mobile::BundleWriter::create(&dest, &password, mode, exported_at)?;
+            let mut contents = mobile::BundleContents {
+                categories: categories.len(),
+                ..Default::default()
+            };
+
+            let total = documents.len();
+            let mut catalog = Vec::with_capacity(total);
+            let mut search = Vec::with_capacity(total);
+            for (index, document) in documents.into_iter().enumerate() {
+                if job.is_cancelled() {
+                    break;
+                }
+                job.progress(index, total);
+
+                let body = document.text_content.as_deref().unwrap_or_default();
+                writer.add_json(
+                    &mobile::document_name(document.id),
+                    &mobile::DocumentBody {
+                        markdown: body_paragraphs(body).join("\n\n"),
+                    },
+                )?;
+
+                let mut text = vec![document.title.clone()];
+                text.extend(document.description.clone());
+                text.push(html::strip_tags(body));
+                let mut listed = Vec::new();
+                for attachment in attachments.remove(&document.id).unwrap_or_default() {
+                    let path = Path::new(&attachment.filepath);
+                    let thumbnail = mode != MobileAttachments::None
+                        && attachment.filetype.starts_with("image/")
+                        && match thumbnails::jpeg(
+                            path,
+                            mobile::THUMBNAIL_SIZE,
+                            mobile::THUMBNAIL_QUALITY,
+                        ) {
+                            Ok(jpeg) => {
+                                writer.add_bytes(&mobile::thumbnail_name(attachment.id), &jpeg)?;
+                                true
+                            }
+                            Err(e) => {
+                                log::warn!("No thumbnail for {}: {}", attachment.filename, e);
+                                false
+                            }
+                        };
+                    let included = mode == MobileAttachments::Full
+                        && match fs::read(path) {
+                            Ok(data) => {
+                                writer.add_bytes(&mobile::attachment_name(attachment.id), &data)?;
+                                true
+                            }
+                            Err(e) => {
+                                log::warn!("Leaving out {}: {}", attachment.filename, e);
+                                false
+                            }
+                        };
+                    contents.attachments += 1;
+                    contents.thumbnails += thumbnail as usize;
+                    contents.files += included as usize;
+
+                    text.push(attachment.filename.clone());
+                    text.extend(attachment.alt_text.clone());
+                    text.extend(attachment.ocr_text);
+                    listed.push(mobile::BundleAttachment {
+                        id: attachment.id,
+                        filename: attachment.filename,
+                        filetype: attachment.filetype,
+                        // What the bundle holds, should the file have changed
+                        filesize: attachment.filesize,
+                        alt_text: attachment.alt_text,
+                        thumbnail,
+                        included,
+                    });
+                }
+
+                search.push(mobile::SearchEntry {
+                    document_id: document.id,
+                    text: text
+                        .iter()
+                        .map(|part| part.trim())
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                });
+                catalog.push(mobile::BundleDocument {
+                    id: document.id,
+                    tags: tags.remove(&document.id).unwrap_or_default(),
+                    title: document.title,
+                    description: document.description,
+                    category_id: document.category_id,
+                    created_at: document.created_at,
+                    updated_at: document.updated_at,
+                    attachments: listed,
+                });
+            }
+
+            contents.documents = catalog.len();
+            contents.search_entries = search.len();
+            writer.add_json(mobile::SEARCH_NAME, &search)?;
+            writer.add_json(
+                mobile::CATALOG_NAME,
+                &mobile::Catalog {
+                    archive_name,
+                    categories: categories
+                        .into_iter()
+                        .map(|category| vault::VaultCategory {
+                            id: category.id,
+                            name: category.name,
+                            parent_id: category.parent_id,
+                            icon: category.icon,
+                            color: category.color,
+                        })
+                        .collect(),
+                    documents: catalog,
+                },
+            )?;
+            writer.finish()?;
+
+            Ok::<_, AppError>(MobileBundleSummary {
+                contents,
+                file_size: fs::metadata(&dest)?.len(),
+            })
+        })
+        .await??;
+
+        audit::record(&pool, "export", "mobile_bundle", None, &dest_path).await?;
+        Ok(summary)
+    });
+
+    Ok(job_id)
+}
+
+/// Opens a mobile bundle with `password` and decrypts every entry its
+/// catalog lists, the way the mobile app reads it, to make sure a bundle
+/// is complete before it is copied to a phone.
+#[tauri::command]
+pub async fn verify_mobile_bundle(path: String, password: String) -> CmdResult<MobileBundleReport> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut reader = mobile::BundleReader::open(Path::new(&path), &password)?;
+        let contents = reader.verify()?;
+        Ok(MobileBundleReport {
+            contents,
+            attachments_mode: reader.header.attachments,
+            exported_at: reader.header.exported_at.clone(),
+        })
+    })
+    .await?
+}
+
+#[derive(Clone, Serialize)]
+pub struct PdfExportSummary {
+    pub page_count: usize,
+    pub file_size: u64,
+}
+
+/// Starts a job writing the given documents, in order, into a single PDF
+/// with a cover, table of contents, bookmarks and inline image
+/// attachments. Returns the job id.
+#[tauri::command]
+pub async fn export_combined_pdf(
+    app: AppHandle,
+    ids: Vec<i64>,
+    dest_path: String,
+    options: Option<BinderOptions>,
+) -> CmdResult<u64> {
+    if ids.is_empty() {
+        return Err(AppError::Validation("No documents selected".to_string()));
+    }
+
+    let pool = db::pool(&app).await?;
+    let options = options.unwrap_or_default();
+    let markdown_options = app.state::<SettingsStore>().get().markdown;
+    let entries = binder_entries(&pool, &ids, markdown_options).await?;
+
+    let title = archive_meta::load(&pool).await?.name;
+    let export_date = Utc::now().format("%Y-%m-%d").to_string();
+
+    let job_id = jobs::spawn(&app, "export_combined_pdf", move |job| async move {
+        tauri::async_runtime::spawn_blocking(move || {
+            let total = entries.len();
+            let doc = binder::render(&title, &export_date, &entries, &options, |done| {
+                job.progress(done, total);
+                !job.is_cancelled()
+            })?;
+
+            let bytes = doc.to_bytes();
+            fs::write(&dest_path, &bytes)?;
+
+            Ok(PdfExportSummary {
+                page_count: doc.page_count(),
+                file_size: bytes.len() as u64,
+            })
+        })
+        .await?
+    });
+
+    Ok(job_id)
+}
+
+// The documents in `ids` as the binder lays them out, in order
+async fn binder_entries(
+    pool: &SqlitePool,
+    ids: &[i64],
+    markdown_options: MarkdownOptions,
+) -> CmdResult<Vec<BinderEntry>> {
+    let mut entries = Vec::with_capacity(ids.len());
+    for id in ids {
+        let (title, body, updated_at, category): (String, Option<String>, String, Option<String>) =
+            sqlx::query_as(
+                "SELECT d.title, d.text_content, d.updated_at, c.name
+                 FROM documents d
+                 LEFT JOIN categories c ON c.id = d.category_id
+                 WHERE d.id = ?",
+            )
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Document not found: {}", id)))?;
+
+        let images: Vec<(String,)> = sqlx::query_as(
+            "SELECT filepath FROM attachments
+             WHERE document_id = ? AND filetype LIKE 'image/%'
+             ORDER BY sort_order ASC, id ASC",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+
+        let subtitle = match category {
+            Some(category) => format!("{} · {}", category, updated_at),
+            None => updated_at,
+        };
+        entries.push(BinderEntry {
+            title,
+            subtitle,
+            body: markdown::body_html(&body.unwrap_or_default(), markdown_options).into_owned(),
+            images: images
+                .into_iter()
+                .map(|(path,)| PathBuf::from(path))
+                .collect(),
+        });
+    }
+    Ok(entries)
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct AttachmentExportSummary {
+    /// Files in the folder, linked ones included.
+    pub files: usize,
+    /// Bytes written, each distinct file counted once.
+    pub total_bytes: u64,
+    /// Files with the same content as one written before, hard-linked to
+    /// it instead of written again where the drive allows.
+    pub linked: usize,
+    /// Attachments whose stored file is gone, left out.
+    pub missing: usize,
+}
+
+/// Starts a job copying every attachment whose type starts with
+/// `mime_prefix`, e.g. `application/pdf` or `image/`, into `dest_dir`,
+/// named `<document title> - <file name>`. Names that would collide get a
+/// number. Files with the same content are written once: later copies
+/// are hard links, as in the attachment store. The job can be cancelled
+/// and reports what it copied up to then. Returns the job id.
+#[tauri::command]
+pub async fn export_attachments_by_type(
+    app: AppHandle,
+    mime_prefix: String,
+    dest_dir: String,
+) -> CmdResult<u64> {
+    let mime_prefix = mime_prefix.trim().to_lowercase();
+    if mime_prefix.is_empty() {
+        return Err(AppError::Validation(
+            "Content type cannot be empty".to_string(),
+        ));
+    }
+
+    let pool = db::pool(&app).await?;
+    let attachments: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT d.title, a.filename, a.filepath FROM attachments a
+         JOIN documents d ON d.id = a.document_id
+         WHERE substr(lower(a.filetype), 1, length(?1)) = ?1
+         ORDER BY d.title ASC, d.id ASC, a.sort_order ASC, a.id ASC",
+    )
+    .bind(&mime_prefix)
+    .fetch_all(&pool)
+    .await?;
+
+    let job_id = jobs::spawn(&app, "export_attachments_by_type", move |job| async move {
+        let dest = PathBuf::from(&dest_dir);
+        let summary = tauri::async_runtime::spawn_blocking(move || {
+            fs::create_dir_all(&dest)?;
+
+            let total = attachments.len();
+            let mut summary = AttachmentExportSummary::default();
+            let mut names = HashSet::new();
+            // Content hash to the file written with it
+            let mut written: HashMap<String, PathBuf> = HashMap::new();
+            for (index, (title, filename, filepath)) in attachments.into_iter().enumerate() {
+                if job.is_cancelled() {
+                    break;
+                }
+                job.progress(index, total);
+
+                let data = match fs::read(&filepath) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::warn!("Leaving out {}: {}", filename, e);
+                        summary.missing += 1;
+                        continue;
+                    }
+                };
+                let path = dest.join(attachment_file_name(&title, &filename, &mut names));
+                let hash = hex::encode(Sha256::digest(&data));
+                match written.get(&hash) {
+                    Some(first) if fs::hard_link(first, &path).is_ok() => summary.linked += 1,
+                    _ => {
+                        fs::write(&path, &data)?;
+                        summary.total_bytes += data.len() as u64;
+                        written.entry(hash).or_insert(path);
+                    }
+                }
+                summary.files += 1;
+            }
+            Ok::<_, AppError>(summary)
+        })
+        .await??;
+
+        audit::record(&pool, "export", "attachments", None, &dest_dir).await?;
+        Ok(summary)
+    });
+
+    Ok(job_id)
+}
+
+// `<title> - <file name>`, numbered when taken; `taken` is lowercase, for
+// drives that ignore case
+fn attachment_file_name(title: &str, filename: &str, taken: &mut HashSet<String>) -> String {
+    let filename = Path::new(filename);
+    let stem = safe_file_stem(
+        &filename
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default(),
+    );
+    let extension = filename
+        .extension()
+        .map(|extension| format!(".{}", safe_file_stem(&extension.to_string_lossy())))
+        .unwrap_or_default();
+    let base = match safe_file_stem(title) {
+        title if title.is_empty() => stem,
+        title => format!("{} - {}", title, stem),
+    };
+
+    let mut name = format!("{}{}", base, extension);
+    let mut number = 2;
+    while !taken.insert(name.to_lowercase()) {
+        name = format!("{} ({}){}", base, number, extension);
+        number += 1;
+    }
+    name
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveCompression {
+    /// Entries stored as they are, fastest to write.
+    None,
+    Fast,
+    #[default]
+    Default,
+    /// Smallest, slowest to write.
+    Best,
+}
+
+impl ArchiveCompression {
+    fn level(self) -> u32 {
+        match self {
+            ArchiveCompression::None => 0,
+            ArchiveCompression::Fast => 1,
+            ArchiveCompression::Default => zip::DEFAULT_LEVEL,
+            ArchiveCompression::Best => 9,
+        }
+    }
+}
+
+/// Writes the archive as an encrypted vault instead, as `export_vault`
+/// does. The password is never remembered with the target.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveEncryption {
+    #[serde(default, skip_serializing)]
+    pub master_password: String,
+}
+
+impl std::fmt::Debug for ArchiveEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ArchiveEncryption")
+    }
+}
+
+/// Where and how `export` writes a selection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportTarget {
+    /// One `.andoarchive` with the documents, their categories and
+    /// attachments, or with `encryption` a vault without attachments.
+    Archive {
+        path: String,
+        #[serde(default)]
+        compression: ArchiveCompression,
+        #[serde(default)]
+        encryption: Option<ArchiveEncryption>,
+    },
+    /// One file per document in `path`, links between them pointing at
+    /// each other's files. With `embed_assets` each document's
+    /// attachments go in a `<file name>_files` folder beside it, and HTML
+    /// pages show their images inline.
+    Folder {
+        path: String,
+        format: DocumentFormat,
+        #[serde(default)]
+        embed_assets: bool,
+    },
+    /// A single PDF as `export_combined_pdf` writes it.
+    CombinedPdf { path: String },
+}
+
+impl ExportTarget {
+    fn path(&self) -> &str {
+        match self {
+            ExportTarget::Archive { path, .. }
+            | ExportTarget::Folder { path, .. }
+            | ExportTarget::CombinedPdf { path } => path,
+        }
+    }
+
+    fn validate(&self) -> CmdResult<()> {
+        let path = Path::new(self.path().trim());
+        if path.as_os_str().is_empty() {
+            return Err(AppError::Validation("The export needs a path".to_string()));
+        }
+        match self {
+            ExportTarget::Folder { .. } => {
+                if path.exists() && !path.is_dir() {
+                    return Err(AppError::Validation(format!(
+                        "{} is a file, not a folder",
+                        path.display()
+                    )));
+                }
+            }
+            _ => {
+                if path.is_dir() {
+                    return Err(AppError::Validation(format!(
+                        "{} is a folder, not a file",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        if let ExportTarget::Archive {
+            encryption: Some(encryption),
+            ..
+        } = self
+        {
+            check_master_password(&encryption.master_password)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportReport {
+    Archive {
+        document_count: usize,
+        file_size: u64,
+        encrypted: bool,
+    },
+    Folder {
+        document_count: usize,
+        /// Documents and attachment files.
+        files_written: usize,
+        total_bytes: u64,
+    },
+    CombinedPdf {
+        document_count: usize,
+        page_count: usize,
+        file_size: u64,
+    },
+}
+
+/// Exports the selected documents to `target`, the one entry point behind
+/// the export dialog, and remembers the target for next time; without a
+/// target the remembered one is used, though an encrypted archive needs
+/// its password given again. Returns what was written, per kind of
+/// target.
+#[tauri::command]
+pub async fn export(
+    app: AppHandle,
+    selection: Vec<i64>,
+    target: Option<ExportTarget>,
+) -> CmdResult<ExportReport> {
+    let store = app.state::<SettingsStore>();
+    let target = match target {
+        Some(target) => target,
+        None => store.get().last_export_target.ok_or_else(|| {
+            AppError::Validation("Nothing has been exported yet, so a target is needed".to_string())
+        })?,
+    };
+    target.validate()?;
+    let mut ids = Vec::with_capacity(selection.len());
+    for id in selection {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return Err(AppError::Validation("No documents selected".to_string()));
+    }
+
+    let pool = db::pool(&app).await?;
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT * FROM documents WHERE id IN (");
+    let mut separated = query.separated(", ");
+    for id in &ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+    let found: Vec<Document> = query.build_query_as().fetch_all(&pool).await?;
+    if let Some(missing) = ids
+        .iter()
+        .find(|id| !found.iter().any(|document| document.id == **id))
+    {
+        return Err(AppError::NotFound(format!(
+            "Document not found: {}",
+            missing
+        )));
+    }
+    // In the order selected
+    let mut documents = Vec::with_capacity(found.len());
+    for id in &ids {
+        if let Some(document) = found.iter().find(|document| document.id == *id) {
+            documents.push(document.clone());
+        }
+    }
+
+    let document_count = documents.len();
+    let path = target.path().trim().to_string();
+    let report = match &target {
+        ExportTarget::Archive {
+            compression,
+            encryption: None,
+            ..
+        } => {
+            let data = export_data_for(&pool, &ids).await?;
+            let metadata = export_metadata(&app, &pool, &data, "selection").await?;
+            let dest = PathBuf::from(&path);
+            let level = compression.level();
+            let file_size = tauri::async_runtime::spawn_blocking(move || {
+                archive::write_archive_with(&dest, &metadata, &data, level)
+            })
+            .await??;
+            ExportReport::Archive {
+                document_count,
+                file_size,
+                encrypted: false,
+            }
+        }
+        ExportTarget::Archive {
+            encryption: Some(encryption),
+            ..
+        } => {
+            let categories = categories_of(&pool, &documents).await?;
+            let file_size = write_vault(
+                &pool,
+                categories,
+                documents,
+                PathBuf::from(&path),
+                encryption.master_password.clone(),
+            )
+            .await?;
+            ExportReport::Archive {
+                document_count,
+                file_size,
+                encrypted: true,
+            }
+        }
+        ExportTarget::Folder {
+            format,
+            embed_assets,
+            ..
+        } => {
+            let (files_written, total_bytes) =
+                export_folder(&app, &pool, documents, &path, *format, *embed_assets).await?;
+            ExportReport::Folder {
+                document_count,
+                files_written,
+                total_bytes,
+            }
+        }
+        ExportTarget::CombinedPdf { .. } => {
+            let markdown_options = store.get().markdown;
+            let entries = binder_entries(&pool, &ids, markdown_options).await?;
+            let title = archive_meta::load(&pool).await?.name;
+            let export_date = Utc::now().format("%Y-%m-%d").to_string();
+            let dest = path.clone();
+            let (page_count, file_size) = tauri::async_runtime::spawn_blocking(move || {
+                let doc = binder::render(
+                    &title,
+                    &export_date,
+                    &entries,
+                    &BinderOptions::default(),
+                    |_| true,
+                )?;
+                let bytes = doc.to_bytes();
+                fs::write(&dest, &bytes)?;
+                Ok::<_, AppError>((doc.page_count(), bytes.len() as u64))
+            })
+            .await??;
+            ExportReport::CombinedPdf {
+                document_count,
+                page_count,
+                file_size,
+            }
+        }
+    };
+
+    audit::record(&pool, "export", "selection", None, &path).await?;
+    let mut target = target;
+    if let ExportTarget::Archive {
+        encryption: Some(encryption),
+        ..
+    } = &mut target
+    {
+        encryption.master_password.clear();
+    }
+    let mut settings = store.get();
+    if settings.last_export_target.as_ref() != Some(&target) {
+        settings.last_export_target = Some(target);
+        store.replace(settings)?;
+    }
+
+    Ok(report)
+}
+
+// One file per document in `dir`, named like linked exports, with links
+// between them rewritten. Returns the files written and their bytes.
+async fn export_folder(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    documents: Vec<Document>,
+    dir: &str,
+    format: DocumentFormat,
+    embed_assets: bool,
+) -> CmdResult<(usize, u64)> {
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir)?;
+
+    let names: HashMap<i64, String> = documents
+        .iter()
+        .map(|document| {
+            let name = format!(
+                "{}-{}.{}",
+                document.id,
+                safe_file_stem(&document.title),
+                format.extension()
+            );
+            (document.id, name)
+        })
+        .collect();
+    let links: HashMap<i64, String> = names
+        .iter()
+        .map(|(id, name)| (*id, name.replace(' ', "%20")))
+        .collect();
+    let ids: Vec<i64> = documents.iter().map(|document| document.id).collect();
+    let mut attachments: HashMap<i64, Vec<Attachment>> = HashMap::new();
+    if embed_assets {
+        for attachment in fetch_attachments_of(pool, &ids).await? {
+            attachments
+                .entry(attachment.document_id)
+                .or_default()
+                .push(attachment);
+        }
+    }
+
+    let export_settings = app.state::<SettingsStore>().get();
+    let stylesheet = match format {
+        DocumentFormat::Html => settings::export_stylesheet(app)?,
+        _ => None,
+    };
+    let mut pages = Vec::with_capacity(documents.len());
+    for mut document in documents {
+        document.text_content = document
+            .text_content
+            .map(|body| rewrite_document_links(&body, &links));
+        let contents = match format {
+            DocumentFormat::Html => {
+                html_page(&document, export_settings.markdown, stylesheet.as_deref())
+            }
+            DocumentFormat::Md => plaintext(&document, PlaintextFormat::Md),
+            DocumentFormat::Txt => plaintext(&document, PlaintextFormat::Txt),
+        };
+        let name = names[&document.id].clone();
+        pages.push((
+            name,
+            contents,
+            attachments.remove(&document.id).unwrap_or_default(),
+        ));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut files = 0;
+        let mut bytes = 0;
+        for (name, mut contents, attachments) in pages {
+            let path = dir.join(&name);
+            let assets = path.with_extension("").to_string_lossy().to_string() + "_files";
+            let mut inlined = String::new();
+            for (index, attachment) in attachments.iter().enumerate() {
+                let data = match fs::read(&attachment.filepath) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::warn!("Leaving out {}: {}", attachment.filename, e);
+                        continue;
+                    }
+                };
+                if format == DocumentFormat::Html
+                    && attachment.filetype.starts_with("image/")
+                    && data.len() as u64 <= MAX_INLINE_IMAGE_BYTES
+                {
+                    inlined.push_str(&format!(
+                        "\n<p><img src=\"data:{};base64,{}\" alt=\"{}\"></p>",
+                        attachment.filetype,
+                        base64::engine::general_purpose::STANDARD.encode(&data),
+                        html::escape(&attachment.filename),
+                    ));
+                    continue;
+                }
+                fs::create_dir_all(&assets)?;
+                // The index keeps names apart when two files share one
+                let file_name = format!("{}-{}", index + 1, safe_file_name(&attachment.filename));
+                fs::write(Path::new(&assets).join(file_name), &data)?;
+                files += 1;
+                bytes += data.len() as u64;
+            }
+            if !inlined.is_empty() {
+                let end = contents.rfind("</body>").unwrap_or(contents.len());
+                contents.insert_str(end, &(inlined + "\n"));
+            }
+            let contents = export_settings.export_text(contents);
+            fs::write(&path, &contents)?;
+            files += 1;
+            bytes += contents.len() as u64;
+        }
+        Ok::<_, AppError>((files, bytes))
+    })
+    .await?
+}
+
+// `safe_file_stem` for the stem, keeping the extension
+fn safe_file_name(name: &str) -> String {
+    let path = Path::new(name);
+    let stem = safe_file_stem(
+        &path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default(),
+    );
+    match path.extension() {
+        Some(extension) => format!("{}.{}", stem, safe_file_stem(&extension.to_string_lossy())),
+        None => stem,
+    }
+}
+
+const DEFAULT_SHEET_COLUMNS: u32 = 4;
+const DEFAULT_SHEET_THUMB_SIZE: u32 = 120;
+
+#[derive(Serialize)]
+pub struct ContactSheetSummary {
+    pub page_count: usize,
+    pub image_count: usize,
+    pub file_size: u64,
+}
+
+/// Writes a PDF contact sheet: the image attachments of the given
+/// documents, in order, as a captioned thumbnail grid over as many pages as
+/// needed. Thumbnails come from the thumbnail cache, which fills in any
+/// that are missing. `thumb_size` is the largest side of a thumbnail on the
+/// page, in points.
+#[tauri::command]
+pub async fn contact_sheet(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    document_ids: Vec<i64>,
+    dest_path: String,
+    cols: Option<u32>,
+    thumb_size: Option<u32>,
+    caption: Option<Caption>,
+) -> CmdResult<ContactSheetSummary> {
+    if document_ids.is_empty() {
+        return Err(AppError::Validation("No documents selected".to_string()));
+    }
+    let cols = cols.unwrap_or(DEFAULT_SHEET_COLUMNS).clamp(1, 12);
+    let thumb_size = thumb_size
+        .unwrap_or(DEFAULT_SHEET_THUMB_SIZE)
+        .clamp(32, 512);
+    let caption = caption.unwrap_or_default();
+
+    let pool = db::pool(&app).await?;
+    let mut images = Vec::new();
+    for id in &document_ids {
+        let rows: Vec<(i64, String, String, String)> = sqlx::query_as(
+            "SELECT a.id, a.filename, a.filepath, d.title FROM attachments a
+             JOIN documents d ON d.id = a.document_id
+             WHERE a.document_id = ? AND a.filetype LIKE 'image/%'
+             ORDER BY a.sort_order ASC, a.id ASC",
+        )
+        .bind(id)
+        .fetch_all(&pool)
+        .await?;
+        images.extend(rows);
+    }
+    if images.is_empty() {
+        return Err(AppError::Validation(
+            "The selected documents have no image attachments".to_string(),
+        ));
+    }
+
+    // The cached size is enough unless the sheet asks for bigger thumbnails
+    let pixels = store.get().thumbnail_size.max(thumb_size);
+    let cache = thumbnails::cache_root(&app)?;
+    let title = archive_meta::load(&pool).await?.name;
+
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let thumbnail_cache = handle.state::<ThumbnailCache>();
+        let entries: Vec<SheetEntry> = images
+            .into_iter()
+            .filter_map(|(id, filename, filepath, document_title)| {
+                let thumbnail = thumbnails::thumbnail_path(&cache, pixels, id);
+                if thumbnail.exists() {
+                    thumbnail_cache.hit(&thumbnail);
+                } else {
+                    thumbnail_cache.miss();
+                    if let Err(e) =
+                        thumbnails::generate(&PathBuf::from(&filepath), &thumbnail, pixels)
+                    {
+                        log::warn!("Could not generate thumbnail for attachment {}: {}", id, e);
+                        return None;
+                    }
+                }
+                Some(SheetEntry {
+                    caption: match caption {
+                        Caption::Filename => filename,
+                        Caption::Title => document_title,
+                    },
+                    image: thumbnail,
+                })
+            })
+            .collect();
+
+        let (doc, image_count) = contact_sheet::render(&title, &entries, cols, thumb_size as f32)?;
+        let bytes = doc.to_bytes();
+        fs::write(&dest_path, &bytes)?;
+        // Counted only now, so none of the sheet's thumbnails are evicted
+        // before it is written
+        thumbnail_cache.recount();
+
+        Ok(ContactSheetSummary {
+            page_count: doc.page_count(),
+            image_count,
+            file_size: bytes.len() as u64,
+        })
+    })
+    .await?
+}
+
+async fn fetch_documents_in(pool: &SqlitePool, category_ids: &[i64]) -> CmdResult<Vec<Document>> {
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT * FROM documents WHERE category_id IN (");
+    let mut ids = query.separated(", ");
+    for id in category_ids {
+        ids.push_bind(id);
+    }
+    query.push(") ORDER BY id ASC");
+
+    query
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)
+}
+
+async fn fetch_attachments_of(
+    pool: &SqlitePool,
+    document_ids: &[i64],
+) -> CmdResult<Vec<Attachment>> {
+    if document_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT * FROM attachments WHERE document_id IN (");
+    let mut ids = query.separated(", ");
+    for id in document_ids {
+        ids.push_bind(id);
+    }
+    query.push(") ORDER BY document_id ASC, sort_order ASC, id ASC");
+
+    query
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::LineEnding;
+
+    // Every line break in `path` is `\r\n`
+    fn only_crlf(path: &Path) -> bool {
+        let text = fs::read_to_string(path).unwrap();
+        text.contains("\r\n") && !text.replace("\r\n", "").contains(['\r', '\n'])
+    }
+
+    #[test]
+    fn history_patches_use_the_export_line_ending() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query(
+                "INSERT INTO documents (id, title, text_content) VALUES (1, 'Notes', 'one')",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query("UPDATE documents SET text_content = 'one\r\ntwo' WHERE id = 1")
+                .execute(&pool)
+                .await
+                .unwrap();
+
+            let dest = std::env::temp_dir().join(format!("ando-patches-{}", std::process::id()));
+            let settings = Settings {
+                export_line_ending: LineEnding::Crlf,
+                ..Settings::default()
+            };
+            let result = write_history_patches(&pool, &settings, 1, &dest.to_string_lossy())
+                .await
+                .unwrap();
+            assert_eq!(result.patches.len(), 2);
+            for patch in &result.patches {
+                assert!(only_crlf(Path::new(patch)), "{}", patch);
+            }
+            fs::remove_dir_all(&dest).unwrap();
+        });
+    }
+}
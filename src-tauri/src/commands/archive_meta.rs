@@ -0,0 +1,121 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::categories::archived_scope;
+use crate::commands::documents::normalize_name;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ArchiveMeta {
+    pub name: String,
+    pub description: Option<String>,
+    pub icon: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ArchiveStats {
+    pub documents: i64,
+    pub categories: i64,
+    pub tags: i64,
+    pub attachments: i64,
+    pub unread: i64,
+    pub needs_review: i64,
+}
+
+#[tauri::command]
+pub async fn get_archive_meta(app: AppHandle) -> CmdResult<ArchiveMeta> {
+    let pool = db::pool(&app).await?;
+    load(&pool).await
+}
+
+/// Renames and relabels the archive. Fields left `None` keep their value.
+#[tauri::command]
+pub async fn set_archive_meta(
+    app: AppHandle,
+    name: Option<String>,
+    description: Option<String>,
+    icon: Option<String>,
+) -> CmdResult<ArchiveMeta> {
+    let pool = db::pool(&app).await?;
+
+    let mut meta = load(&pool).await?;
+    if let Some(name) = name {
+        let name = normalize_name(&name);
+        if name.is_empty() {
+            return Err(AppError::Validation(
+                "Archive name cannot be empty".to_string(),
+            ));
+        }
+        meta.name = name;
+    }
+    if let Some(description) = description {
+        meta.description = Some(description).filter(|description| !description.trim().is_empty());
+    }
+    if let Some(icon) = icon {
+        meta.icon = icon;
+    }
+
+    sqlx::query("UPDATE archive_meta SET name = ?, description = ?, icon = ? WHERE id = 1")
+        .bind(&meta.name)
+        .bind(&meta.description)
+        .bind(&meta.icon)
+        .execute(&pool)
+        .await?;
+
+    set_window_title(&app, &meta.name);
+    let _ = app.emit("archive_meta_changed", meta.clone());
+
+    Ok(meta)
+}
+
+/// How much the archive holds. Archived categories and what is in them
+/// aren't counted unless `include_archived`.
+#[tauri::command]
+pub async fn archive_stats(
+    app: AppHandle,
+    include_archived: Option<bool>,
+) -> CmdResult<ArchiveStats> {
+    let pool = db::pool(&app).await?;
+
+    let documents = archived_scope("d.category_id", include_archived);
+    let stats = sqlx::query_as(&format!(
+        "SELECT (SELECT COUNT(*) FROM documents d WHERE {0}) AS documents,
+           (SELECT COUNT(*) FROM categories c WHERE {1}) AS categories,
+           (SELECT COUNT(*) FROM tags) AS tags,
+           (SELECT COUNT(*) FROM attachments a JOIN documents d ON d.id = a.document_id
+            WHERE {0}) AS attachments,
+           (SELECT COUNT(*) FROM documents d WHERE d.is_read = 0 AND {0}) AS unread,
+           (SELECT COUNT(*) FROM documents d WHERE d.needs_review = 1 AND {0}) AS needs_review",
+        documents,
+        archived_scope("c.id", include_archived)
+    ))
+    .fetch_one(&pool)
+    .await?;
+    Ok(stats)
+}
+
+pub(crate) async fn load(pool: &SqlitePool) -> CmdResult<ArchiveMeta> {
+    sqlx::query_as("SELECT name, description, icon FROM archive_meta WHERE id = 1")
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Shows the archive name in the window title once the database is open.
+pub fn restore_window_title(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let pool = db::wait_for_pool(&app).await;
+        match load(&pool).await {
+            Ok(meta) => set_window_title(&app, &meta.name),
+            Err(e) => log::warn!("Failed to load archive name: {}", e),
+        }
+    });
+}
+
+fn set_window_title(app: &AppHandle, name: &str) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.set_title(name);
+    }
+}
@@ -0,0 +1,1272 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::annotate::{self, AnnotationLayer};
+use crate::commands::maintenance;
+use crate::convert;
+use crate::db::{self, Attachment};
+use crate::describe::{self, ImageFacts};
+use crate::error::{AppError, CmdResult};
+use crate::jobs;
+use crate::ocr;
+use crate::phash;
+use crate::settings::SettingsStore;
+use crate::thumbnails;
+
+#[derive(Clone, Serialize)]
+pub(crate) struct AttachmentEvent {
+    pub document_id: i64,
+}
+
+#[derive(Serialize)]
+pub struct ConvertedAttachment {
+    pub attachment_id: i64,
+    pub filesize: i64,
+}
+
+// OCR workers at most; tesseract is heavy on memory
+const MAX_OCR_WORKERS: usize = 4;
+
+#[derive(Clone, Serialize)]
+pub struct OcrFailure {
+    pub attachment_id: i64,
+    pub filename: String,
+    pub error: String,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct OcrReport {
+    pub recognized: usize,
+    pub failed: usize,
+    /// Left for a later run because the job was cancelled.
+    pub remaining: usize,
+    pub failures: Vec<OcrFailure>,
+}
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AttachmentSummary {
+    pub id: i64,
+    pub filename: String,
+    pub filesize: Option<i64>,
+    pub filetype: String,
+    /// Whether a thumbnail is cached at the configured size; others are
+    /// made by `get_thumbnail` when first asked for.
+    #[sqlx(skip)]
+    pub has_thumbnail: bool,
+}
+
+#[derive(Serialize)]
+pub struct AttachmentPage {
+    /// Attachments of the document in all, for sizing the grid.
+    pub total: i64,
+    pub attachments: Vec<AttachmentSummary>,
+}
+
+/// Where an attachment was imported from, as the filesystem described
+/// the file then.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Provenance {
+    pub original_path: String,
+    /// `None` where the platform or filesystem doesn't record it.
+    pub created_at: Option<String>,
+    pub modified_at: Option<String>,
+    pub accessed_at: Option<String>,
+    pub size: i64,
+    pub readonly: bool,
+    /// Unix permission bits; `None` on other platforms.
+    pub mode: Option<i64>,
+    pub imported_at: String,
+}
+
+impl Provenance {
+    fn of(source: &Path, metadata: &fs::Metadata) -> Self {
+        let time = |time: std::io::Result<SystemTime>| {
+            time.ok().map(|time| {
+                DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+            })
+        };
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(i64::from(metadata.permissions().mode() & 0o7777))
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        Provenance {
+            original_path: fs::canonicalize(source)
+                .unwrap_or_else(|_| source.to_path_buf())
+                .to_string_lossy()
+                .to_string(),
+            created_at: time(metadata.created()),
+            modified_at: time(metadata.modified()),
+            accessed_at: time(metadata.accessed()),
+            size: metadata.len() as i64,
+            readonly: metadata.permissions().readonly(),
+            mode,
+            imported_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MissingAttachment {
+    pub attachment_id: i64,
+    pub filename: String,
+}
+
+#[derive(Serialize)]
+pub struct DocumentMissingAttachments {
+    pub document_id: i64,
+    pub title: String,
+    pub category_id: Option<i64>,
+    pub missing: Vec<MissingAttachment>,
+}
+
+/// Copies a file into the document's attachment folder and registers it.
+#[tauri::command]
+pub async fn attach_file(
+    app: AppHandle,
+    document_id: i64,
+    source_path: String,
+) -> CmdResult<Attachment> {
+    let pool = db::pool(&app).await?;
+
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM documents WHERE id = ?")
+        .bind(document_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let attachment = store_file(&app, &pool, document_id, Path::new(&source_path)).await?;
+
+    let _ = app.emit("attachment_added", AttachmentEvent { document_id });
+
+    Ok(attachment)
+}
+
+/// Copies `source` into the document's attachment folder and inserts its
+/// record at the end of the document's attachments.
+pub(crate) async fn store_file(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    document_id: i64,
+    source: &Path,
+) -> CmdResult<Attachment> {
+    let filename = source_name(source)?;
+    let metadata = fs::metadata(source)?;
+    let filesize = metadata.len() as i64;
+    let dir = db::attachments_dir(app, document_id)?;
+    let dest = copy_into_store(&dir, source, &filename, &metadata)?;
+
+    let filetype = mime_type(&filename);
+    let phash = if filetype.starts_with("image/") {
+        image_hash(dest.clone()).await
+    } else {
+        None
+    };
+
+    let filepath = dest.to_string_lossy().to_string();
+    let inserted = sqlx::query(
+        "INSERT INTO attachments (document_id, filename, filepath, filetype, filesize, sort_order, phash)
+         VALUES (?, ?, ?, ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM attachments WHERE document_id = ?), ?)",
+    )
+    .bind(document_id)
+    .bind(&filename)
+    .bind(&filepath)
+    .bind(filetype)
+    .bind(filesize)
+    .bind(document_id)
+    .bind(phash)
+    .execute(pool)
+    .await;
+
+    let attachment_id = match inserted {
+        Ok(result) => result.last_insert_rowid(),
+        Err(e) => {
+            // Don't leave an orphaned copy behind
+            let _ = fs::remove_file(&dest);
+            return Err(e.into());
+        }
+    };
+    record_provenance(pool, attachment_id, source, &metadata).await;
+    maintenance::warn_if_over_limit(app);
+
+    sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)
+}
+
+fn source_name(source: &Path) -> CmdResult<String> {
+    source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| AppError::Validation(format!("Invalid file path: {}", source.display())))
+}
+
+/// Copies `source` into `dir`, a document's attachment folder, under a
+/// fresh name, keeping its times where the platform allows, and returns the
+/// copy.
+pub(crate) fn copy_into_store(
+    dir: &Path,
+    source: &Path,
+    filename: &str,
+    metadata: &fs::Metadata,
+) -> CmdResult<PathBuf> {
+    let dest = fresh_path(dir, filename)?;
+    fs::copy(source, &dest)?;
+    // The copy keeps the original's times, best effort
+    if let Ok(modified) = metadata.modified() {
+        let mut times = fs::FileTimes::new().set_modified(modified);
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        if let Err(e) = fs::File::options()
+            .write(true)
+            .open(&dest)
+            .and_then(|file| file.set_times(times))
+        {
+            log::warn!("Failed to keep the times of {}: {}", source.display(), e);
+        }
+    }
+    Ok(dest)
+}
+
+/// Puts a stored file in another document's attachment folder as a hard
+/// link, so both share its bytes and removing either leaves the other.
+/// Copies where links aren't supported, e.g. on FAT drives.
+pub(crate) fn link_into_store(
+    app: &AppHandle,
+    document_id: i64,
+    source: &Path,
+    filename: &str,
+) -> CmdResult<PathBuf> {
+    let dest = fresh_path(&db::attachments_dir(app, document_id)?, filename)?;
+    if let Err(e) = fs::hard_link(source, &dest) {
+        log::info!("Copying {} instead of linking it: {}", source.display(), e);
+        fs::copy(source, &dest)?;
+    }
+    Ok(dest)
+}
+
+// A name in the document's attachment folder no file has yet, creating
+// the folder if needed
+fn fresh_path(dir: &Path, filename: &str) -> CmdResult<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    Ok(dir.join(format!("{}_{}", millis, filename)))
+}
+
+/// Records where an attachment was copied from, unless that is known
+/// already. Failing only logs; the attachment is there either way.
+pub(crate) async fn record_provenance(
+    pool: &SqlitePool,
+    attachment_id: i64,
+    source: &Path,
+    metadata: &fs::Metadata,
+) {
+    let provenance = Provenance::of(source, metadata);
+    if let Err(e) = sqlx::query(
+        "INSERT OR IGNORE INTO attachment_provenance
+           (attachment_id, original_path, created_at, modified_at, accessed_at, size, readonly,
+            mode, imported_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(attachment_id)
+    .bind(&provenance.original_path)
+    .bind(&provenance.created_at)
+    .bind(&provenance.modified_at)
+    .bind(&provenance.accessed_at)
+    .bind(provenance.size)
+    .bind(provenance.readonly)
+    .bind(provenance.mode)
+    .bind(&provenance.imported_at)
+    .execute(pool)
+    .await
+    {
+        log::warn!(
+            "Failed to record where attachment {} came from: {}",
+            attachment_id,
+            e
+        );
+    }
+}
+
+// Hex perceptual hash, or `None` for images that can't be decoded
+pub(crate) async fn image_hash(path: PathBuf) -> Option<String> {
+    tauri::async_runtime::spawn_blocking(move || phash::compute(&path))
+        .await
+        .ok()?
+        .map(phash::to_hex)
+        .ok()
+}
+
+#[derive(sqlx::FromRow)]
+struct HashedAttachment {
+    #[sqlx(flatten)]
+    attachment: Attachment,
+    phash: String,
+}
+
+#[derive(Serialize)]
+pub struct SimilarImage {
+    #[serde(flatten)]
+    pub attachment: Attachment,
+    pub distance: u32,
+}
+
+/// Image attachments whose perceptual hash is within `max_distance` bits of
+/// the given one, closest first. Images attached before hashing existed are
+/// hashed on the way.
+#[tauri::command]
+pub async fn find_similar_images(
+    app: AppHandle,
+    attachment_id: i64,
+    max_distance: u32,
+) -> CmdResult<Vec<SimilarImage>> {
+    let pool = db::pool(&app).await?;
+
+    let unhashed: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, filepath FROM attachments WHERE filetype LIKE 'image/%' AND phash IS NULL",
+    )
+    .fetch_all(&pool)
+    .await?;
+    for (id, filepath) in unhashed {
+        // Undecodable images get an empty hash so they aren't retried
+        let hash = image_hash(PathBuf::from(filepath))
+            .await
+            .unwrap_or_default();
+        sqlx::query("UPDATE attachments SET phash = ? WHERE id = ?")
+            .bind(hash)
+            .bind(id)
+            .execute(&pool)
+            .await?;
+    }
+
+    let (target,): (String,) =
+        sqlx::query_as("SELECT phash FROM attachments WHERE id = ? AND filetype LIKE 'image/%'")
+            .bind(attachment_id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Image attachment not found".to_string()))?;
+    let target = phash::from_hex(&target)
+        .ok_or_else(|| AppError::Validation("Image could not be decoded".to_string()))?;
+
+    let candidates: Vec<HashedAttachment> = sqlx::query_as(
+        "SELECT * FROM attachments WHERE id != ? AND phash IS NOT NULL AND phash != ''",
+    )
+    .bind(attachment_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let mut similar: Vec<SimilarImage> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let distance = phash::distance(target, phash::from_hex(&candidate.phash)?);
+            (distance <= max_distance).then_some(SimilarImage {
+                attachment: candidate.attachment,
+                distance,
+            })
+        })
+        .collect();
+    similar.sort_by_key(|image| (image.distance, image.attachment.id));
+
+    Ok(similar)
+}
+
+/// Removes an attachment record together with its file on disk, and the
+/// files of its earlier versions.
+#[tauri::command]
+pub async fn detach_file(app: AppHandle, attachment_id: i64) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let attachment: Attachment = sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+
+    let mut tx = pool.begin().await?;
+
+    let paths = stored_paths(&mut tx, attachment_id).await?;
+    sqlx::query("DELETE FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Dropping the transaction on error rolls the delete back
+    for path in unreferenced(&mut tx, paths).await? {
+        let path = Path::new(&path);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    tx.commit().await?;
+
+    let _ = app.emit(
+        "attachment_removed",
+        AttachmentEvent {
+            document_id: attachment.document_id,
+        },
+    );
+
+    Ok(())
+}
+
+/// Converts an attachment to `target_format` (`png`, `jpeg` or `pdf`) and
+/// stores the result as a new attachment of the same document. With
+/// `replace` the new file takes the original's place and the original is
+/// removed.
+#[tauri::command]
+pub async fn convert_attachment(
+    app: AppHandle,
+    attachment_id: i64,
+    target_format: String,
+    replace: Option<bool>,
+) -> CmdResult<ConvertedAttachment> {
+    let pool = db::pool(&app).await?;
+
+    let original: Attachment = sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+
+    let target = match target_format.trim().to_lowercase().as_str() {
+        "jpg" => "jpeg".to_string(),
+        target => target.to_string(),
+    };
+    let available = convert::targets_for(&original.filetype);
+    if !available.contains(&target.as_str()) {
+        let options = if available.is_empty() {
+            "none".to_string()
+        } else {
+            available.join(", ")
+        };
+        return Err(AppError::Validation(format!(
+            "Cannot convert {} to {}; conversions available for {}: {}",
+            original.filetype, target, original.filetype, options
+        )));
+    }
+    let (extension, _) = convert::target_type(&target)
+        .ok_or_else(|| AppError::Validation(format!("Unknown format: {}", target)))?;
+
+    // Converted in the cache dir, then stored like any attached file
+    let stem = Path::new(&original.filename)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("attachment");
+    let temp_dir = app
+        .path()
+        .app_cache_dir()?
+        .join("conversions")
+        .join(attachment_id.to_string());
+    fs::create_dir_all(&temp_dir)?;
+    let temp_path = temp_dir.join(format!("{}.{}", stem, extension));
+
+    let source = PathBuf::from(&original.filepath);
+    let mime = original.filetype.clone();
+    let dest = temp_path.clone();
+    let converted = tauri::async_runtime::spawn_blocking(move || {
+        convert::convert(&source, &mime, &target, &dest)
+    })
+    .await?;
+
+    let stored = match converted {
+        Ok(()) => store_file(&app, &pool, original.document_id, &temp_path).await,
+        Err(e) => Err(AppError::Validation(e)),
+    };
+    let _ = fs::remove_dir_all(&temp_dir);
+    let converted = stored?;
+
+    if replace.unwrap_or(false) {
+        let mut tx = pool.begin().await?;
+        let paths = stored_paths(&mut tx, original.id).await?;
+        sqlx::query("DELETE FROM attachments WHERE id = ?")
+            .bind(original.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE attachments SET sort_order = ? WHERE id = ?")
+            .bind(original.sort_order)
+            .bind(converted.id)
+            .execute(&mut *tx)
+            .await?;
+        let paths = unreferenced(&mut tx, paths).await?;
+        tx.commit().await?;
+
+        for path in paths {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("Failed to remove {}: {}", path, e);
+            }
+        }
+        let _ = app.emit(
+            "attachment_removed",
+            AttachmentEvent {
+                document_id: original.document_id,
+            },
+        );
+    }
+
+    let _ = app.emit(
+        "attachment_added",
+        AttachmentEvent {
+            document_id: original.document_id,
+        },
+    );
+
+    Ok(ConvertedAttachment {
+        attachment_id: converted.id,
+        filesize: converted.filesize.unwrap_or_default(),
+    })
+}
+
+/// A file an attachment held before it was replaced.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AttachmentVersion {
+    pub id: i64,
+    pub attachment_id: i64,
+    /// 1 for the attachment's first file, counting up.
+    pub version: i64,
+    pub filename: String,
+    pub filepath: String,
+    pub filetype: String,
+    pub filesize: Option<i64>,
+    /// When the file became the attachment's.
+    pub created_at: String,
+    pub replaced_at: String,
+}
+
+// What is swapped in when an attachment's file changes
+#[derive(sqlx::FromRow)]
+struct StoredFile {
+    filename: String,
+    filepath: String,
+    filetype: String,
+    filesize: Option<i64>,
+    phash: Option<String>,
+    ocr_text: Option<String>,
+    ocr_lang: Option<String>,
+    alt_text: Option<String>,
+    alt_text_source: Option<String>,
+}
+
+/// Replaces an attachment's file with a copy of `new_source_path`, e.g. a
+/// better scan, keeping the attachment's id and place in the document.
+/// The file it held is kept as a version; see `list_attachment_versions`.
+/// Recognized text and alt text belong to the old file and are cleared.
+#[tauri::command]
+pub async fn replace_attachment(
+    app: AppHandle,
+    attachment_id: i64,
+    new_source_path: String,
+) -> CmdResult<Attachment> {
+    let pool = db::pool(&app).await?;
+
+    let current: Attachment = sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+
+    let source = Path::new(&new_source_path);
+    let filename = source_name(source)?;
+    let metadata = fs::metadata(source)?;
+    let dir = db::attachments_dir(&app, current.document_id)?;
+    let dest = copy_into_store(&dir, source, &filename, &metadata)?;
+
+    let filetype = mime_type(&filename);
+    let phash = if filetype.starts_with("image/") {
+        image_hash(dest.clone()).await
+    } else {
+        None
+    };
+    let file = StoredFile {
+        filename,
+        filepath: dest.to_string_lossy().to_string(),
+        filetype: filetype.to_string(),
+        filesize: Some(metadata.len() as i64),
+        phash,
+        ocr_text: None,
+        ocr_lang: None,
+        alt_text: None,
+        alt_text_source: None,
+    };
+    if let Err(e) = swap_in(&pool, attachment_id, &file).await {
+        // Don't leave an orphaned copy behind
+        let _ = fs::remove_file(&dest);
+        return Err(e);
+    }
+    maintenance::warn_if_over_limit(&app);
+
+    replaced(&app, &pool, &current).await
+}
+
+/// The files an attachment held before its current one, newest first.
+#[tauri::command]
+pub async fn list_attachment_versions(
+    app: AppHandle,
+    attachment_id: i64,
+) -> CmdResult<Vec<AttachmentVersion>> {
+    let pool = db::pool(&app).await?;
+
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Attachment not found".to_string()));
+    }
+
+    let versions = sqlx::query_as(
+        "SELECT id, attachment_id, ROW_NUMBER() OVER (ORDER BY id ASC) AS version, filename,
+           filepath, filetype, filesize, created_at, replaced_at
+         FROM attachment_versions WHERE attachment_id = ?
+         ORDER BY id DESC",
+    )
+    .bind(attachment_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(versions)
+}
+
+/// Makes an earlier version the attachment's file again. The file it
+/// held until now is kept as a version in turn, and the restored one
+/// stays in the list, so no version is lost either way.
+#[tauri::command]
+pub async fn restore_attachment_version(app: AppHandle, version_id: i64) -> CmdResult<Attachment> {
+    let pool = db::pool(&app).await?;
+
+    let (attachment_id,): (i64,) =
+        sqlx::query_as("SELECT attachment_id FROM attachment_versions WHERE id = ?")
+            .bind(version_id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Attachment version not found".to_string()))?;
+    let file: StoredFile = sqlx::query_as(
+        "SELECT filename, filepath, filetype, filesize, phash, ocr_text, ocr_lang, alt_text,
+           alt_text_source
+         FROM attachment_versions WHERE id = ?",
+    )
+    .bind(version_id)
+    .fetch_one(&pool)
+    .await?;
+    let current: Attachment = sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_one(&pool)
+        .await?;
+
+    if current.filepath == file.filepath {
+        return Ok(current);
+    }
+    if !Path::new(&file.filepath).exists() {
+        return Err(AppError::NotFound(format!(
+            "The file of this version is missing: {}",
+            file.filepath
+        )));
+    }
+    swap_in(&pool, attachment_id, &file).await?;
+
+    replaced(&app, &pool, &current).await
+}
+
+// Makes `file` the attachment's, keeping the file it held as a version
+// unless one already has it, as after a restore
+async fn swap_in(pool: &SqlitePool, attachment_id: i64, file: &StoredFile) -> CmdResult<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO attachment_versions
+           (attachment_id, filename, filepath, filetype, filesize, phash, ocr_text, ocr_lang,
+            alt_text, alt_text_source, created_at)
+         SELECT id, filename, filepath, filetype, filesize, phash, ocr_text, ocr_lang, alt_text,
+           alt_text_source, COALESCE(replaced_at, created_at, CURRENT_TIMESTAMP)
+         FROM attachments a
+         WHERE id = ? AND NOT EXISTS (
+           SELECT 1 FROM attachment_versions v
+           WHERE v.attachment_id = a.id AND v.filepath = a.filepath
+         )",
+    )
+    .bind(attachment_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "UPDATE attachments SET filename = ?, filepath = ?, filetype = ?, filesize = ?,
+           phash = ?, ocr_text = ?, ocr_lang = ?, alt_text = ?, alt_text_source = ?,
+           replaced_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(&file.filename)
+    .bind(&file.filepath)
+    .bind(&file.filetype)
+    .bind(file.filesize)
+    .bind(&file.phash)
+    .bind(&file.ocr_text)
+    .bind(&file.ocr_lang)
+    .bind(&file.alt_text)
+    .bind(&file.alt_text_source)
+    .bind(attachment_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// Drops the old file's thumbnails and announces the change
+async fn replaced(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    previous: &Attachment,
+) -> CmdResult<Attachment> {
+    if let Ok(root) = thumbnails::cache_root(app) {
+        thumbnails::evict(&root, previous.id);
+    }
+    let _ = app.emit(
+        "attachment_replaced",
+        AttachmentEvent {
+            document_id: previous.document_id,
+        },
+    );
+
+    sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(previous.id)
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)
+}
+
+// The attachment's file and those of its versions
+pub(crate) async fn stored_paths(
+    tx: &mut Transaction<'_, Sqlite>,
+    attachment_id: i64,
+) -> CmdResult<Vec<String>> {
+    let paths: Vec<(String,)> = sqlx::query_as(
+        "SELECT filepath FROM attachments WHERE id = ?
+         UNION
+         SELECT filepath FROM attachment_versions WHERE attachment_id = ?",
+    )
+    .bind(attachment_id)
+    .bind(attachment_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(paths.into_iter().map(|(path,)| path).collect())
+}
+
+// Those of `paths` no attachment or version refers to anymore, so safe to
+// remove. Documents sharing a file through a hard link have a path of
+// their own to it, and removing one link leaves the bytes to the others.
+pub(crate) async fn unreferenced(
+    tx: &mut Transaction<'_, Sqlite>,
+    paths: Vec<String>,
+) -> CmdResult<Vec<String>> {
+    let mut unreferenced = Vec::new();
+    for path in paths {
+        let (referenced,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT 1 FROM attachments WHERE filepath = ?)
+               OR EXISTS (SELECT 1 FROM attachment_versions WHERE filepath = ?)",
+        )
+        .bind(&path)
+        .bind(&path)
+        .fetch_one(&mut **tx)
+        .await?;
+        if !referenced {
+            unreferenced.push(path);
+        }
+    }
+    Ok(unreferenced)
+}
+
+/// Persists a new display order; `ordered_ids` must list every attachment
+/// of the document exactly once.
+#[tauri::command]
+pub async fn reorder_attachments(
+    app: AppHandle,
+    document_id: i64,
+    ordered_ids: Vec<i64>,
+) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let current: Vec<(i64,)> = sqlx::query_as("SELECT id FROM attachments WHERE document_id = ?")
+        .bind(document_id)
+        .fetch_all(&pool)
+        .await?;
+
+    let current: HashSet<i64> = current.into_iter().map(|(id,)| id).collect();
+    let requested: HashSet<i64> = ordered_ids.iter().copied().collect();
+    if requested.len() != ordered_ids.len() || requested != current {
+        return Err(AppError::Validation(
+            "Attachment order must include every attachment of the document once".to_string(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    for (index, id) in ordered_ids.iter().enumerate() {
+        sqlx::query("UPDATE attachments SET sort_order = ? WHERE id = ?")
+            .bind(index as i64 + 1)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    let _ = app.emit("attachments_reordered", AttachmentEvent { document_id });
+
+    Ok(())
+}
+
+/// A page of a document's attachments in display order, with just what
+/// a grid needs to lay them out. Thumbnails are only looked up, never
+/// generated, so a page stays quick however many attachments there are.
+#[tauri::command]
+pub async fn list_attachments_paged(
+    app: AppHandle,
+    document_id: i64,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> CmdResult<AttachmentPage> {
+    let pool = db::pool(&app).await?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM attachments WHERE document_id = ?")
+        .bind(document_id)
+        .fetch_one(&pool)
+        .await?;
+    let mut attachments: Vec<AttachmentSummary> = sqlx::query_as(
+        "SELECT id, filename, filesize, filetype FROM attachments
+         WHERE document_id = ?
+         ORDER BY sort_order ASC, id ASC
+         LIMIT ? OFFSET ?",
+    )
+    .bind(document_id)
+    .bind(limit)
+    .bind(offset.unwrap_or(0))
+    .fetch_all(&pool)
+    .await?;
+
+    let root = thumbnails::cache_root(&app)?;
+    let size = app.state::<SettingsStore>().get().thumbnail_size;
+    for attachment in &mut attachments {
+        attachment.has_thumbnail = thumbnails::thumbnail_path(&root, size, attachment.id).exists();
+    }
+
+    Ok(AttachmentPage { total, attachments })
+}
+
+/// Lists documents whose attachment files are gone from disk, so the user
+/// can re-attach or remove them.
+#[tauri::command]
+pub async fn documents_with_missing_attachments(
+    app: AppHandle,
+) -> CmdResult<Vec<DocumentMissingAttachments>> {
+    let pool = db::pool(&app).await?;
+
+    let rows: Vec<(i64, String, Option<i64>, i64, String, String)> = sqlx::query_as(
+        "SELECT d.id, d.title, d.category_id, a.id, a.filename, a.filepath
+         FROM attachments a
+         JOIN documents d ON d.id = a.document_id
+         ORDER BY d.id ASC, a.sort_order ASC, a.id ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let paths: Vec<String> = rows.iter().map(|row| row.5.clone()).collect();
+    let present = tauri::async_runtime::spawn_blocking(move || files_exist(&paths)).await?;
+
+    let mut documents: BTreeMap<i64, DocumentMissingAttachments> = BTreeMap::new();
+    for ((document_id, title, category_id, attachment_id, filename, _), exists) in
+        rows.into_iter().zip(present)
+    {
+        if exists {
+            continue;
+        }
+        documents
+            .entry(document_id)
+            .or_insert_with(|| DocumentMissingAttachments {
+                document_id,
+                title,
+                category_id,
+                missing: Vec::new(),
+            })
+            .missing
+            .push(MissingAttachment {
+                attachment_id,
+                filename,
+            });
+    }
+
+    Ok(documents.into_values().collect())
+}
+
+/// Starts a job recognizing the text of every image and PDF attachment
+/// that has none yet, or of all of them with `force`, so scans become
+/// searchable. `lang` is a tesseract language code such as `eng` (the
+/// default) or `deu+eng`. A few files are recognized at a time; the job
+/// result counts what worked and what failed. With `background` it pauses
+/// while power is low. Returns the job id.
+#[tauri::command]
+pub async fn ocr_all_pending(
+    app: AppHandle,
+    lang: Option<String>,
+    force: Option<bool>,
+    background: Option<bool>,
+) -> CmdResult<u64> {
+    let lang = lang.unwrap_or_else(|| "eng".to_string());
+    let valid = lang.split('+').all(|code| {
+        !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+    if !valid {
+        return Err(AppError::Validation(format!(
+            "Invalid OCR language: {}",
+            lang
+        )));
+    }
+
+    let pool = db::pool(&app).await?;
+    let pending: Vec<(i64, i64, String, String, String)> = sqlx::query_as(
+        "SELECT id, document_id, filename, filepath, filetype FROM attachments
+         WHERE (filetype LIKE 'image/%' OR filetype = 'application/pdf')
+           AND (ocr_text IS NULL OR ?)
+         ORDER BY id ASC",
+    )
+    .bind(force.unwrap_or(false))
+    .fetch_all(&pool)
+    .await?;
+
+    let events = app.clone();
+    let background = background.unwrap_or(false);
+    let job_id = jobs::spawn_with(&app, "ocr_all_pending", background, move |job| async move {
+        let workers = thread::available_parallelism()
+            .map_or(2, |n| n.get())
+            .min(MAX_OCR_WORKERS);
+        let total = pending.len();
+        let mut report = OcrReport::default();
+        let mut updated = HashSet::new();
+
+        for (index, batch) in pending.chunks(workers).enumerate() {
+            job.wait_if_paused().await;
+            if job.is_cancelled() {
+                report.remaining = total - index * workers;
+                break;
+            }
+            job.progress(index * workers, total);
+
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|(_, _, _, filepath, filetype)| {
+                    let (path, filetype, lang) =
+                        (PathBuf::from(filepath), filetype.clone(), lang.clone());
+                    tauri::async_runtime::spawn_blocking(move || {
+                        ocr::recognize(&path, &filetype, &lang)
+                    })
+                })
+                .collect();
+
+            for ((id, document_id, filename, _, _), handle) in batch.iter().zip(handles) {
+                match handle.await? {
+                    Ok(text) => {
+                        sqlx::query(
+                            "UPDATE attachments SET ocr_text = ?, ocr_lang = ? WHERE id = ?",
+                        )
+                        .bind(&text)
+                        .bind(&lang)
+                        .bind(id)
+                        .execute(&pool)
+                        .await?;
+                        updated.insert(*document_id);
+                        report.recognized += 1;
+                    }
+                    Err(error) => {
+                        log::warn!("OCR of attachment {} failed: {}", id, error);
+                        report.failed += 1;
+                        report.failures.push(OcrFailure {
+                            attachment_id: *id,
+                            filename: filename.clone(),
+                            error,
+                        });
+                    }
+                }
+            }
+        }
+
+        for document_id in updated {
+            let _ = events.emit("attachments_updated", AttachmentEvent { document_id });
+        }
+        job.progress(total - report.remaining, total);
+        Ok(report)
+    });
+
+    Ok(job_id)
+}
+
+#[derive(Serialize)]
+pub struct ImageDescription {
+    pub alt_text: String,
+    /// What wrote it: `metadata`, `command`, or `filename` when describing
+    /// failed.
+    pub source: String,
+}
+
+/// Describes an image attachment in a sentence, from the configured
+/// describer, and stores it as the attachment's alt text, which search and
+/// exported HTML use. Recognize text with `ocr_all_pending` first for it
+/// to be part of the description. When describing fails the filename is
+/// stored instead.
+#[tauri::command]
+pub async fn describe_image(app: AppHandle, attachment_id: i64) -> CmdResult<ImageDescription> {
+    let pool = db::pool(&app).await?;
+
+    let attachment: Option<(i64, String, String, String, Option<String>)> = sqlx::query_as(
+        "SELECT document_id, filename, filepath, filetype, ocr_text FROM attachments
+         WHERE id = ?",
+    )
+    .bind(attachment_id)
+    .fetch_optional(&pool)
+    .await?;
+    let (document_id, filename, filepath, filetype, ocr_text) =
+        attachment.ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+    if !filetype.starts_with("image/") {
+        return Err(AppError::Validation(
+            "Only image attachments can be described".to_string(),
+        ));
+    }
+
+    let describer = describe::describer(&app.state::<SettingsStore>().get());
+    let (alt_text, source) = tauri::async_runtime::spawn_blocking(move || {
+        let facts = ImageFacts {
+            filename: &filename,
+            ocr_text: ocr_text.as_deref(),
+        };
+        match describer.describe(Path::new(&filepath), &facts) {
+            Ok(description) => (description, describer.source()),
+            Err(e) => {
+                log::warn!("Describing attachment {} failed: {}", attachment_id, e);
+                (filename.clone(), "filename")
+            }
+        }
+    })
+    .await?;
+
+    sqlx::query("UPDATE attachments SET alt_text = ?, alt_text_source = ? WHERE id = ?")
+        .bind(&alt_text)
+        .bind(source)
+        .bind(attachment_id)
+        .execute(&pool)
+        .await?;
+
+    let _ = app.emit("attachments_updated", AttachmentEvent { document_id });
+
+    Ok(ImageDescription {
+        alt_text,
+        source: source.to_string(),
+    })
+}
+
+/// The original path, times, size and permissions of the file an
+/// attachment was imported from; `None` for attachments added before this
+/// was recorded or brought in from an archive.
+#[tauri::command]
+pub async fn attachment_provenance(
+    app: AppHandle,
+    attachment_id: i64,
+) -> CmdResult<Option<Provenance>> {
+    let pool = db::pool(&app).await?;
+
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Attachment not found".to_string()));
+    }
+
+    let provenance = sqlx::query_as(
+        "SELECT original_path, created_at, modified_at, accessed_at, size, readonly, mode,
+           imported_at
+         FROM attachment_provenance WHERE attachment_id = ?",
+    )
+    .bind(attachment_id)
+    .fetch_optional(&pool)
+    .await?;
+    Ok(provenance)
+}
+
+/// Replaces the annotation layer of an image or PDF attachment with
+/// `layer_json`, after checking it against the layer schema. The file
+/// itself is never changed. An empty layer removes the annotations.
+#[tauri::command]
+pub async fn save_annotations(
+    app: AppHandle,
+    attachment_id: i64,
+    layer_json: String,
+) -> CmdResult<AnnotationLayer> {
+    let layer = annotate::parse_layer(&layer_json).map_err(AppError::Validation)?;
+    let pool = db::pool(&app).await?;
+    let attachment = annotatable(&pool, attachment_id).await?;
+
+    if layer.annotations.is_empty() {
+        sqlx::query("DELETE FROM attachment_annotations WHERE attachment_id = ?")
+            .bind(attachment_id)
+            .execute(&pool)
+            .await?;
+    } else {
+        // Stored as parsed, so defaults are filled in and stray whitespace goes
+        let stored = serde_json::to_string(&layer)?;
+        sqlx::query(
+            "INSERT INTO attachment_annotations (attachment_id, layer) VALUES (?, ?)
+             ON CONFLICT (attachment_id)
+             DO UPDATE SET layer = excluded.layer, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(attachment_id)
+        .bind(stored)
+        .execute(&pool)
+        .await?;
+    }
+
+    let _ = app.emit(
+        "attachments_updated",
+        AttachmentEvent {
+            document_id: attachment.document_id,
+        },
+    );
+
+    Ok(layer)
+}
+
+/// The annotation layer of an attachment; empty when it has none.
+#[tauri::command]
+pub async fn get_annotations(app: AppHandle, attachment_id: i64) -> CmdResult<AnnotationLayer> {
+    let pool = db::pool(&app).await?;
+    annotatable(&pool, attachment_id).await?;
+    stored_annotations(&pool, attachment_id).await
+}
+
+/// Writes a shareable copy of an attachment with its annotations drawn
+/// in to `dest_path`, a `.pdf`, `.png` or `.jpg` file. Only a PDF keeps
+/// the text of notes; the image formats show their markers.
+#[tauri::command]
+pub async fn export_annotated(
+    app: AppHandle,
+    attachment_id: i64,
+    dest_path: String,
+) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+    let attachment = annotatable(&pool, attachment_id).await?;
+    let layer = stored_annotations(&pool, attachment_id).await?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        annotate::export(
+            Path::new(&attachment.filepath),
+            &attachment.filetype,
+            &layer,
+            Path::new(&dest_path),
+        )
+    })
+    .await?
+    .map_err(AppError::Validation)?;
+    Ok(())
+}
+
+async fn annotatable(pool: &SqlitePool, attachment_id: i64) -> CmdResult<Attachment> {
+    let attachment: Attachment = sqlx::query_as("SELECT * FROM attachments WHERE id = ?")
+        .bind(attachment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Attachment not found".to_string()))?;
+    if !attachment.filetype.starts_with("image/") && attachment.filetype != "application/pdf" {
+        return Err(AppError::Validation(format!(
+            "Only images and PDFs can be annotated, not {}",
+            attachment.filetype
+        )));
+    }
+    Ok(attachment)
+}
+
+async fn stored_annotations(pool: &SqlitePool, attachment_id: i64) -> CmdResult<AnnotationLayer> {
+    let layer: Option<(String,)> =
+        sqlx::query_as("SELECT layer FROM attachment_annotations WHERE attachment_id = ?")
+            .bind(attachment_id)
+            .fetch_optional(pool)
+            .await?;
+    match layer {
+        Some((json,)) => annotate::parse_layer(&json).map_err(AppError::Internal),
+        None => Ok(AnnotationLayer::default()),
+    }
+}
+
+// Stats the paths in parallel chunks; results keep the input order
+pub(crate) fn files_exist(paths: &[String]) -> Vec<bool> {
+    let workers = thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk_size = paths.len().div_ceil(workers).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| Path::new(path).exists())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("stat worker panicked"))
+            .collect()
+    })
+}
+
+// Same MIME strings the editor stores from `File.type`
+fn mime_type(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "tif" | "tiff" => "image/tiff",
+        "heic" => "image/heic",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::fs;
+
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqliteExecutor, SqlitePool};
+use tauri::{AppHandle, Manager};
+
+use crate::csv;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::metrics;
+use crate::settings::{Settings, SettingsStore};
+
+const MAX_PAGE_SIZE: u32 = 500;
+
+// Entity types the viewer can look up: the table, the column naming the
+// entity and the status of a row that still exists
+const ENTITY_TABLES: &[(&str, &str, &str, &str)] = &[
+    ("document", "documents", "title", "'active'"),
+    (
+        "category",
+        "categories",
+        "name",
+        "CASE WHEN id IN (SELECT id FROM archived_categories) THEN 'archived' ELSE 'active' END",
+    ),
+    ("attachment", "attachments", "filename", "'active'"),
+    ("tag", "tags", "name", "'active'"),
+    ("smart_folder", "smart_folders", "name", "'active'"),
+];
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub occurred_at: i64,
+    pub operation: String,
+    pub entity_type: String,
+    pub entity_id: Option<i64>,
+    pub details: Option<String>,
+}
+
+/// Narrows `audit_log_detailed`; every field left out matches all entries.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    /// E.g. `create`, `update`, `delete` or `export`; any of them.
+    #[serde(default)]
+    pub operations: Vec<String>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i64>,
+    /// Unix seconds, inclusive.
+    pub since: Option<i64>,
+    /// Unix seconds, exclusive.
+    pub until: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEntity {
+    /// The current title or name, or for a deleted entity the last one the
+    /// log recorded; `None` when it never recorded one.
+    pub name: Option<String>,
+    /// `active`, `archived` (categories), `trashed` (documents in the
+    /// trash) or `deleted`.
+    pub status: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct AuditItem {
+    #[serde(flatten)]
+    pub entry: AuditEntry,
+    /// `None` for entries about no single entity, e.g. an archive import.
+    pub entity: Option<AuditEntity>,
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    /// Entries matching the filter, across all pages.
+    pub total: i64,
+    pub offset: u32,
+    pub entries: Vec<AuditItem>,
+}
+
+/// Records an operation the database triggers can't see, e.g. an export.
+/// Pass the transaction of the operation when there is one.
+pub(crate) async fn record<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    operation: &str,
+    entity_type: &str,
+    entity_id: Option<i64>,
+    details: &str,
+) -> CmdResult<()> {
+    sqlx::query(
+        "INSERT INTO audit_log (operation, entity_type, entity_id, details) VALUES (?, ?, ?, ?)",
+    )
+    .bind(operation)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(details)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Pages through the audit log oldest first, from `since` (unix seconds)
+/// onwards.
+#[tauri::command]
+pub async fn audit_log(
+    app: AppHandle,
+    since: Option<i64>,
+    limit: u32,
+) -> CmdResult<Vec<AuditEntry>> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("audit_log");
+    let entries: Vec<AuditEntry> = sqlx::query_as(
+        "SELECT * FROM audit_log WHERE occurred_at >= ? ORDER BY occurred_at ASC, id ASC LIMIT ?",
+    )
+    .bind(since.unwrap_or(0))
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, entries.len());
+
+    Ok(entries)
+}
+
+/// Pages through the audit log newest first for the history viewer, each
+/// entry with what became of its entity: its current title or name and
+/// whether it is archived, in the trash or gone for good, in which case
+/// the name is the one the log recorded last.
+#[tauri::command]
+pub async fn audit_log_detailed(
+    app: AppHandle,
+    filter: Option<AuditFilter>,
+    offset: Option<u32>,
+    limit: u32,
+) -> CmdResult<AuditPage> {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(AppError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let filter = filter.unwrap_or_default();
+    let offset = offset.unwrap_or(0);
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("audit_log_detailed");
+    let mut query = QueryBuilder::new("SELECT COUNT(*) FROM audit_log");
+    push_filter(&mut query, &filter);
+    let (total,): (i64,) = query.build_query_as().fetch_one(&pool).await?;
+
+    let mut query = QueryBuilder::new("SELECT * FROM audit_log");
+    push_filter(&mut query, &filter);
+    query.push(" ORDER BY occurred_at DESC, id DESC LIMIT ");
+    query.push_bind(limit);
+    query.push(" OFFSET ");
+    query.push_bind(offset);
+    let entries: Vec<AuditEntry> = query.build_query_as().fetch_all(&pool).await?;
+
+    let mut entities = resolve_entities(&pool, &entries).await?;
+    let entries: Vec<AuditItem> = entries
+        .into_iter()
+        .map(|entry| {
+            let entity = entry
+                .entity_id
+                .and_then(|id| entities.remove(&(entry.entity_type.clone(), id)));
+            AuditItem { entry, entity }
+        })
+        .collect();
+    timer.finish(&app, entries.len());
+
+    Ok(AuditPage {
+        total,
+        offset,
+        entries,
+    })
+}
+
+fn push_filter(query: &mut QueryBuilder<Sqlite>, filter: &AuditFilter) {
+    query.push(" WHERE 1");
+    if !filter.operations.is_empty() {
+        query.push(" AND operation IN (");
+        let mut operations = query.separated(", ");
+        for operation in &filter.operations {
+            operations.push_bind(operation.clone());
+        }
+        operations.push_unseparated(")");
+    }
+    if let Some(entity_type) = &filter.entity_type {
+        query.push(" AND entity_type = ");
+        query.push_bind(entity_type.clone());
+    }
+    if let Some(entity_id) = filter.entity_id {
+        query.push(" AND entity_id = ");
+        query.push_bind(entity_id);
+    }
+    if let Some(since) = filter.since {
+        query.push(" AND occurred_at >= ");
+        query.push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        query.push(" AND occurred_at < ");
+        query.push_bind(until);
+    }
+}
+
+// The entities of a page of entries, a few queries per entity type rather
+// than one per entry. Entities of other types are left out.
+async fn resolve_entities(
+    pool: &SqlitePool,
+    entries: &[AuditEntry],
+) -> CmdResult<HashMap<(String, i64), AuditEntity>> {
+    let mut resolved = HashMap::new();
+    for (entity_type, table, name, status) in ENTITY_TABLES {
+        let mut ids: Vec<i64> = entries
+            .iter()
+            .filter(|entry| entry.entity_type == *entity_type)
+            .filter_map(|entry| entry.entity_id)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.is_empty() {
+            continue;
+        }
+
+        let mut sources = vec![(
+            format!(
+                "SELECT id, {}, {} FROM {} WHERE id IN (",
+                name, status, table
+            ),
+            None,
+        )];
+        if *entity_type == "document" {
+            sources.push((
+                "SELECT id, title, 'trashed' FROM deleted_documents WHERE id IN (".to_string(),
+                None,
+            ));
+        }
+        // The names the triggers record; an export's details are a path
+        sources.push((
+            "SELECT entity_id, details, 'deleted' FROM audit_log
+             WHERE operation IN ('create', 'update', 'delete') AND entity_type = "
+                .to_string(),
+            Some(*entity_type),
+        ));
+
+        for (select, recorded_type) in sources {
+            let missing: Vec<i64> = ids
+                .iter()
+                .copied()
+                .filter(|id| !resolved.contains_key(&(entity_type.to_string(), *id)))
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(select);
+            if let Some(recorded_type) = recorded_type {
+                query.push_bind(recorded_type);
+                query.push(" AND entity_id IN (");
+            }
+            let mut separated = query.separated(", ");
+            for id in &missing {
+                separated.push_bind(*id);
+            }
+            separated.push_unseparated(")");
+            if recorded_type.is_some() {
+                // Newest last, so it wins
+                query.push(" ORDER BY id ASC");
+            }
+            let rows: Vec<(i64, Option<String>, String)> =
+                query.build_query_as().fetch_all(pool).await?;
+
+            for (id, name, status) in rows {
+                let status = match status.as_str() {
+                    "archived" => "archived",
+                    "trashed" => "trashed",
+                    "deleted" => "deleted",
+                    _ => "active",
+                };
+                resolved.insert((entity_type.to_string(), id), AuditEntity { name, status });
+            }
+        }
+
+        // Deleted before the log kept names, or never named in it
+        for id in ids {
+            resolved
+                .entry((entity_type.to_string(), id))
+                .or_insert(AuditEntity {
+                    name: None,
+                    status: "deleted",
+                });
+        }
+    }
+    Ok(resolved)
+}
+
+/// Writes the whole audit log to `dest_path` as CSV and returns the number
+/// of rows. Line breaks are written as configured for exports.
+#[tauri::command]
+pub async fn export_audit_log(app: AppHandle, dest_path: String) -> CmdResult<usize> {
+    let pool = db::pool(&app).await?;
+
+    write_audit_log(&pool, &app.state::<SettingsStore>().get(), &dest_path).await
+}
+
+async fn write_audit_log(
+    pool: &SqlitePool,
+    settings: &Settings,
+    dest_path: &str,
+) -> CmdResult<usize> {
+    let entries: Vec<AuditEntry> =
+        sqlx::query_as("SELECT * FROM audit_log ORDER BY occurred_at ASC, id ASC")
+            .fetch_all(pool)
+            .await?;
+
+    let mut csv = String::from("id,occurred_at,operation,entity_type,entity_id,details\n");
+    for entry in &entries {
+        let occurred_at = DateTime::from_timestamp(entry.occurred_at, 0)
+            .map(|datetime| datetime.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .unwrap_or_default();
+        let fields = [
+            entry.id.to_string(),
+            occurred_at,
+            entry.operation.clone(),
+            entry.entity_type.clone(),
+            entry.entity_id.map(|id| id.to_string()).unwrap_or_default(),
+            entry.details.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv::field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    fs::write(dest_path, settings.export_text(csv))?;
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::LineEnding;
+
+    #[test]
+    fn audit_csv_uses_the_export_line_ending() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            for details in ["plain", "two\nlines"] {
+                record(&pool, "export", "document", Some(1), details)
+                    .await
+                    .unwrap();
+            }
+
+            let dest = std::env::temp_dir().join(format!("ando-audit-{}.csv", std::process::id()));
+            let settings = Settings {
+                export_line_ending: LineEnding::Crlf,
+                ..Settings::default()
+            };
+            let rows = write_audit_log(&pool, &settings, &dest.to_string_lossy())
+                .await
+                .unwrap();
+            assert_eq!(rows, 2);
+            let csv = fs::read_to_string(&dest).unwrap();
+            assert_eq!(csv.matches("\r\n").count(), 4);
+            assert!(!csv.replace("\r\n", "").contains(['\r', '\n']));
+            fs::remove_file(&dest).unwrap();
+        });
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::commands::documents::{fold_case_and_accents, refresh_name_sort, refresh_title_sort};
+use crate::db;
+use crate::error::CmdResult;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 50;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutocompleteKind {
+    Tags,
+    Titles,
+    Categories,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Suggestion {
+    pub value: String,
+    /// Documents with the tag or in the category; for titles, documents
+    /// sharing the title.
+    pub uses: i64,
+}
+
+/// Existing tags, titles or category names starting with `prefix`, most
+/// used first and then alphabetically, for type-ahead. Case and accents
+/// don't matter, so "cafe" finds "Café".
+#[tauri::command]
+pub async fn autocomplete(
+    app: AppHandle,
+    prefix: String,
+    kind: AutocompleteKind,
+    limit: Option<u32>,
+) -> CmdResult<Vec<Suggestion>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let pool = db::pool(&app).await?;
+
+    // A range over the folded keys, so the lookup stays on their index;
+    // usage is only counted for the names in it
+    let from = fold_case_and_accents(&prefix);
+    let to = format!("{}{}", from, char::MAX);
+
+    let sql = match kind {
+        AutocompleteKind::Tags => {
+            refresh_name_sort(&pool).await?;
+            "SELECT t.name AS value,
+               (SELECT COUNT(*) FROM document_tags dt WHERE dt.tag_id = t.id) AS uses
+             FROM tags t
+             WHERE t.name_sort >= ? AND t.name_sort < ?
+             ORDER BY uses DESC, t.name_sort ASC, t.name ASC
+             LIMIT ?"
+        }
+        AutocompleteKind::Categories => {
+            refresh_name_sort(&pool).await?;
+            "SELECT c.name AS value,
+               (SELECT COUNT(*) FROM documents d WHERE d.category_id = c.id) AS uses
+             FROM categories c
+             WHERE c.name_sort >= ? AND c.name_sort < ?
+             ORDER BY uses DESC, c.name_sort ASC, c.name ASC
+             LIMIT ?"
+        }
+        AutocompleteKind::Titles => {
+            refresh_title_sort(&pool).await?;
+            "SELECT title AS value, COUNT(*) AS uses FROM documents
+             WHERE title_sort >= ? AND title_sort < ?
+             GROUP BY title
+             ORDER BY uses DESC, MIN(title_sort) ASC, title ASC
+             LIMIT ?"
+        }
+    };
+
+    let suggestions = sqlx::query_as(sql)
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all(&pool)
+        .await?;
+    Ok(suggestions)
+}
@@ -0,0 +1,1089 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool, Transaction};
+use tauri::{AppHandle, Manager, State};
+
+use crate::archive::delta::{self, DeltaContents, DeltaMetadata, Entity, Tombstone};
+use crate::archive::import::ArchiveReader;
+use crate::archive::zip::ZipReader;
+use crate::commands::documents::{normalize_name, trash_documents};
+use crate::commands::import::{
+    self, ConflictResolution, IdMapping, ImportReport, ImportSkip, Resolution,
+};
+use crate::commands::{archive, attachments, audit, search};
+use crate::db::{self, Attachment, Category, Document};
+use crate::error::{AppError, CmdResult};
+use crate::jobs;
+use crate::remote;
+use crate::secrets;
+use crate::settings::{RemoteTarget, SettingsStore};
+
+const BACKUP_EXTENSION: &str = ".andoarchive";
+const CREDENTIALS_SECRET: &str = "backup_remote";
+
+/// For S3 remotes, the access key id and the secret key.
+#[derive(Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RemoteBackup {
+    pub name: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UploadedBackup {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct LocalBackup {
+    pub filename: String,
+    /// Export date from the backup's metadata, or the file's modification
+    /// time when that cannot be read.
+    pub created_at: Option<String>,
+    pub size: u64,
+    /// `None` when the backup's metadata cannot be read.
+    pub document_count: Option<usize>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RestoreReport {
+    #[serde(flatten)]
+    pub import: ImportReport,
+    /// Backup of the archive as it was before a replacing restore.
+    pub safety_backup: Option<String>,
+}
+
+// The part of an archive's metadata.json a backup listing needs
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupMetadata {
+    export_date: String,
+    total_documents: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct DownloadedBackup {
+    pub name: String,
+    pub size: u64,
+    /// `false` when the remote had no checksum to compare against.
+    pub verified: bool,
+}
+
+/// Starts a job exporting the whole archive and uploading it to `remote`,
+/// which becomes the configured backup remote. The upload is read back and
+/// checked against the local checksum. Returns the job id.
+///
+/// Credentials given to any backup command are remembered in the OS
+/// keyring where there is one; without them the remembered ones are used.
+#[tauri::command]
+pub async fn upload_backup(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    remote: RemoteTarget,
+    credentials: Option<Credentials>,
+) -> CmdResult<u64> {
+    let pool = db::pool(&app).await?;
+    let credentials = resolve_credentials(&store, credentials).await?;
+    let client = connect(&remote, &credentials)?;
+
+    let mut settings = store.get();
+    settings.backup_remote = Some(remote);
+    store.replace(settings)?;
+
+    let temp_path = app
+        .path()
+        .app_cache_dir()?
+        .join("backup-upload.andoarchive");
+    let name = format!(
+        "ando-backup-{}{}",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        BACKUP_EXTENSION
+    );
+
+    let job_app = app.clone();
+    let job_id = jobs::spawn(&app, "upload_backup", move |job| async move {
+        if let Some(dir) = temp_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        archive::write_complete_archive(&job_app, &pool, temp_path.clone()).await?;
+
+        tauri::async_runtime::spawn_blocking(move || {
+            let result = upload(&client, &temp_path, &name, &job);
+            let _ = fs::remove_file(&temp_path);
+            result
+        })
+        .await?
+    });
+
+    Ok(job_id)
+}
+
+/// Backups in the configured remote, by name.
+#[tauri::command]
+pub async fn list_remote_backups(
+    store: State<'_, SettingsStore>,
+    credentials: Option<Credentials>,
+) -> CmdResult<Vec<RemoteBackup>> {
+    let credentials = resolve_credentials(&store, credentials).await?;
+    let client = configured_client(&store, &credentials)?;
+
+    let files = tauri::async_runtime::spawn_blocking(move || client.list())
+        .await?
+        .map_err(AppError::Io)?;
+
+    let mut backups: Vec<RemoteBackup> = files
+        .into_iter()
+        .filter(|(name, _)| name.ends_with(BACKUP_EXTENSION))
+        .map(|(name, size)| RemoteBackup { name, size })
+        .collect();
+    backups.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(backups)
+}
+
+/// Starts a job downloading a backup from the configured remote to
+/// `dest_path`, checking it against the checksum stored next to it.
+/// Returns the job id.
+#[tauri::command]
+pub async fn download_backup(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    name: String,
+    dest_path: String,
+    credentials: Option<Credentials>,
+) -> CmdResult<u64> {
+    if !name.ends_with(BACKUP_EXTENSION) || name.contains('/') {
+        return Err(AppError::Validation(format!("Not a backup name: {}", name)));
+    }
+    let credentials = resolve_credentials(&store, credentials).await?;
+    let client = configured_client(&store, &credentials)?;
+
+    let job_id = jobs::spawn(&app, "download_backup", move |job| async move {
+        tauri::async_runtime::spawn_blocking(move || {
+            let dest = PathBuf::from(&dest_path);
+            let result = download(&client, &name, &dest, &job);
+            if result.is_err() {
+                let _ = fs::remove_file(&dest);
+            }
+            result
+        })
+        .await?
+    });
+
+    Ok(job_id)
+}
+
+/// Writes a backup of the whole archive to the local backups folder.
+#[tauri::command]
+pub async fn create_backup(app: AppHandle) -> CmdResult<LocalBackup> {
+    let pool = db::pool(&app).await?;
+    let filename = write_local_backup(&app, &pool, "").await?;
+    let path = backups_dir(&app)?.join(&filename);
+    tauri::async_runtime::spawn_blocking(move || local_backup(&path, filename))
+        .await
+        .map_err(AppError::from)
+}
+
+/// Backups in the local backups folder, newest first. Document counts come
+/// from each backup's metadata, without reading the rest of the archive.
+#[tauri::command]
+pub async fn list_backups(app: AppHandle) -> CmdResult<Vec<LocalBackup>> {
+    let dir = backups_dir(&app)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(AppError::from(e)),
+        };
+
+        let mut backups: Vec<LocalBackup> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let filename = entry.file_name().to_string_lossy().into_owned();
+                filename
+                    .ends_with(BACKUP_EXTENSION)
+                    .then(|| local_backup(&entry.path(), filename))
+            })
+            .collect();
+        backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(backups)
+    })
+    .await?
+}
+
+/// Starts a job importing a local backup with `mode` for every conflict.
+/// Before a replacing restore the current archive is backed up first, so a
+/// mistaken restore can be undone. Returns the job id.
+#[tauri::command]
+pub async fn restore_backup(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    filename: String,
+    mode: Resolution,
+    confirmed: Option<bool>,
+) -> CmdResult<u64> {
+    if mode == Resolution::Replace {
+        store.get().require_confirmation(confirmed)?;
+    }
+    let pool = db::pool(&app).await?;
+
+    let path = backups_dir(&app)?.join(&filename);
+    if !filename.ends_with(BACKUP_EXTENSION) || filename.contains(['/', '\\']) || !path.is_file() {
+        return Err(AppError::NotFound(format!(
+            "Backup not found: {}",
+            filename
+        )));
+    }
+
+    let job_app = app.clone();
+    let job_id = jobs::spawn(&app, "restore_backup", move |job| async move {
+        let safety_backup = if mode == Resolution::Replace {
+            Some(write_local_backup(&job_app, &pool, "-before-restore").await?)
+        } else {
+            None
+        };
+
+        let resolution = ConflictResolution {
+            categories: mode,
+            documents: mode,
+        };
+        let path = path.to_string_lossy().into_owned();
+        let import =
+            import::import_archive_file(&job_app, &pool, &path, resolution, false, Some(&job))
+                .await?;
+
+        Ok(RestoreReport {
+            import,
+            safety_backup,
+        })
+    });
+
+    Ok(job_id)
+}
+
+#[derive(Serialize)]
+pub struct IncrementalExport {
+    pub since: i64,
+    /// Pass as `since` to the next incremental export.
+    pub marker: i64,
+    pub category_ids: Vec<i64>,
+    pub document_ids: Vec<i64>,
+    pub attachment_ids: Vec<i64>,
+    pub tombstones: Vec<Tombstone>,
+    pub file_size: u64,
+}
+
+/// Exports what changed after the marker `since` as a delta: the
+/// categories, documents and attachments created or edited, and tombstones
+/// for those deleted. `since` is the marker the previous incremental
+/// export returned, or 0 for everything.
+#[tauri::command]
+pub async fn export_incremental(
+    app: AppHandle,
+    since: i64,
+    dest_path: String,
+) -> CmdResult<IncrementalExport> {
+    let pool = db::pool(&app).await?;
+
+    // Read in one transaction, so the marker matches the records
+    let mut tx = pool.begin().await?;
+    let (marker,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(seq), 0) FROM change_log")
+        .fetch_one(&mut *tx)
+        .await?;
+    if !(0..=marker).contains(&since) {
+        return Err(AppError::Validation(format!(
+            "Marker {} is not one of this archive's, which is at {}",
+            since, marker
+        )));
+    }
+    let (source,): (Option<String>,) = sqlx::query_as("SELECT uid FROM archive_meta WHERE id = 1")
+        .fetch_one(&mut *tx)
+        .await?;
+    let source = source.ok_or_else(|| AppError::Internal("Archive has no uid".to_string()))?;
+
+    let changes: Vec<(String, i64, bool)> = sqlx::query_as(
+        "SELECT entity, entity_id, deleted FROM change_log
+         WHERE seq > ? AND seq <= ? ORDER BY seq ASC",
+    )
+    .bind(since)
+    .bind(marker)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut changed: HashMap<&'static str, Vec<i64>> = HashMap::new();
+    let mut tombstones = Vec::new();
+    for (entity, id, deleted) in changes {
+        let Some(entity) = Entity::parse(&entity) else {
+            continue;
+        };
+        if deleted {
+            tombstones.push(Tombstone { entity, id });
+        } else {
+            changed.entry(entity.as_str()).or_default().push(id);
+        }
+    }
+    let ids_of = |entity: Entity| changed.get(entity.as_str()).cloned().unwrap_or_default();
+
+    let categories: Vec<Category> = fetch_changed(
+        &mut tx,
+        "SELECT * FROM categories WHERE id IN (",
+        ") ORDER BY level ASC, id ASC",
+        &ids_of(Entity::Category),
+    )
+    .await?;
+    let documents: Vec<Document> = fetch_changed(
+        &mut tx,
+        "SELECT * FROM documents WHERE id IN (",
+        ") ORDER BY id ASC",
+        &ids_of(Entity::Document),
+    )
+    .await?;
+    let attachments: Vec<Attachment> = fetch_changed(
+        &mut tx,
+        "SELECT * FROM attachments WHERE id IN (",
+        ") ORDER BY document_id ASC, sort_order ASC, id ASC",
+        &ids_of(Entity::Attachment),
+    )
+    .await?;
+    tx.commit().await?;
+
+    let metadata = DeltaMetadata {
+        format: delta::FORMAT.to_string(),
+        version: delta::VERSION,
+        source,
+        since,
+        marker,
+        export_date: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        app_version: app.package_info().version.to_string(),
+    };
+    let category_ids = categories.iter().map(|category| category.id).collect();
+    let document_ids = documents.iter().map(|document| document.id).collect();
+
+    let dest = PathBuf::from(&dest_path);
+    let (file_size, attachment_ids, tombstones) = tauri::async_runtime::spawn_blocking(move || {
+        let (size, attachment_ids) = delta::write_delta(
+            &dest,
+            &metadata,
+            &categories,
+            &documents,
+            &attachments,
+            &tombstones,
+        )?;
+        Ok::<_, AppError>((size, attachment_ids, tombstones))
+    })
+    .await??;
+
+    audit::record(&pool, "export", "incremental", None, &dest_path).await?;
+
+    Ok(IncrementalExport {
+        since,
+        marker,
+        category_ids,
+        document_ids,
+        attachment_ids,
+        tombstones,
+        file_size,
+    })
+}
+
+#[derive(Serialize, Default)]
+pub struct IncrementalImportReport {
+    pub source: String,
+    /// How far this archive now is with the source's changes.
+    pub marker: i64,
+    pub categories_added: usize,
+    pub categories_updated: usize,
+    pub documents_added: usize,
+    pub documents_updated: usize,
+    pub attachments_added: usize,
+    pub deleted: usize,
+    pub skipped: Vec<ImportSkip>,
+    pub document_ids: Vec<IdMapping>,
+}
+
+/// Applies a delta written by `export_incremental` in another archive.
+/// Records are matched with the ones earlier deltas of that archive
+/// brought in, so the first delta has to start at marker 0 and the rest
+/// follow without gaps; overlapping ones are fine. Documents deleted in
+/// the source go to the trash here. Needs confirmation, as records are
+/// overwritten and deleted.
+#[tauri::command]
+pub async fn import_incremental(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    src_path: String,
+    confirmed: Option<bool>,
+) -> CmdResult<IncrementalImportReport> {
+    store.get().require_confirmation(confirmed)?;
+    let pool = db::pool(&app).await?;
+
+    let path = PathBuf::from(&src_path);
+    let (mut contents, zip) =
+        tauri::async_runtime::spawn_blocking(move || delta::read_delta(&path)).await??;
+    for category in &mut contents.categories {
+        category.name = normalize_name(&category.name);
+    }
+    for document in &mut contents.documents {
+        document.title = normalize_name(&document.title);
+    }
+    let metadata = &contents.metadata;
+
+    let (uid,): (Option<String>,) = sqlx::query_as("SELECT uid FROM archive_meta WHERE id = 1")
+        .fetch_one(&pool)
+        .await?;
+    if uid.as_deref() == Some(metadata.source.as_str()) {
+        return Err(AppError::Validation(
+            "This delta was exported from this archive".to_string(),
+        ));
+    }
+    let applied: Option<(i64,)> =
+        sqlx::query_as("SELECT marker FROM incremental_sources WHERE source = ?")
+            .bind(&metadata.source)
+            .fetch_optional(&pool)
+            .await?;
+    match applied {
+        None if metadata.since != 0 => {
+            return Err(AppError::Conflict(format!(
+                "This delta starts at marker {}; import the one from marker 0 first",
+                metadata.since
+            )))
+        }
+        Some((applied,)) if metadata.since > applied => {
+            return Err(AppError::Conflict(format!(
+                "This delta starts at marker {} but the archive is at {}; import the deltas in between first",
+                metadata.since, applied
+            )))
+        }
+        Some((applied,)) if metadata.marker <= applied => {
+            return Err(AppError::Conflict("This delta was already imported".to_string()))
+        }
+        _ => {}
+    }
+
+    // One batched reindex at the end beats updating the index per row
+    let deferred = search::defer_indexing(&pool).await?;
+    let mut files = DeltaFiles::default();
+    let result = apply_delta(&app, &pool, &contents, zip, &mut files).await;
+    if deferred {
+        search::resume_indexing(&pool).await?;
+    }
+
+    match result {
+        Ok(report) => {
+            for path in &files.removed {
+                let _ = fs::remove_file(path);
+            }
+            audit::record(&pool, "import", "incremental", None, &src_path).await?;
+            Ok(report)
+        }
+        Err(e) => {
+            for path in &files.written {
+                let _ = fs::remove_file(path);
+            }
+            Err(e)
+        }
+    }
+}
+
+// Attachment files a delta copied in, removed again if it fails, and the
+// ones it replaced or deleted, removed once it commits
+#[derive(Default)]
+struct DeltaFiles {
+    written: Vec<PathBuf>,
+    removed: Vec<String>,
+}
+
+async fn apply_delta(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    contents: &DeltaContents,
+    zip: ArchiveReader,
+    files: &mut DeltaFiles,
+) -> CmdResult<IncrementalImportReport> {
+    let source = contents.metadata.source.as_str();
+    let mut report = IncrementalImportReport {
+        source: source.to_string(),
+        marker: contents.metadata.marker,
+        ..Default::default()
+    };
+    let mut tx = pool.begin().await?;
+
+    for category in &contents.categories {
+        let parent_id = match category.parent_id {
+            Some(parent) => local_id(&mut tx, source, Entity::Category, parent).await?,
+            None => None,
+        };
+        let level: i64 = match parent_id {
+            Some(parent) => {
+                sqlx::query_as::<_, (i64,)>("SELECT level FROM categories WHERE id = ?")
+                    .bind(parent)
+                    .fetch_one(&mut *tx)
+                    .await?
+                    .0
+                    + 1
+            }
+            None => 0,
+        };
+
+        let existing = local_id(&mut tx, source, Entity::Category, category.id).await?;
+        let updated = match existing {
+            Some(id) => {
+                sqlx::query(
+                    "UPDATE categories SET name = ?, icon = ?, color = ?, parent_id = ?,
+                   description = ?, sort_order = ?, level = ?
+                 WHERE id = ?",
+                )
+                .bind(&category.name)
+                .bind(&category.icon)
+                .bind(&category.color)
+                .bind(parent_id)
+                .bind(&category.description)
+                .bind(category.sort_order)
+                .bind(level)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                    > 0
+            }
+            None => false,
+        };
+        if updated {
+            report.categories_updated += 1;
+            continue;
+        }
+        let id = sqlx::query(
+            "INSERT INTO categories (name, icon, color, parent_id, description, sort_order, level)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&category.name)
+        .bind(&category.icon)
+        .bind(&category.color)
+        .bind(parent_id)
+        .bind(&category.description)
+        .bind(category.sort_order)
+        .bind(level)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        map_id(&mut tx, source, Entity::Category, category.id, id).await?;
+        report.categories_added += 1;
+    }
+
+    for document in &contents.documents {
+        let category_id = match document.category_id {
+            Some(category) => local_id(&mut tx, source, Entity::Category, category).await?,
+            None => None,
+        };
+
+        let existing = local_id(&mut tx, source, Entity::Document, document.id).await?;
+        let updated = match existing {
+            Some(id) => {
+                sqlx::query(
+                    "UPDATE documents SET title = ?, description = ?, text_content = ?,
+                   category_id = ?, created_at = ?, updated_at = ?
+                 WHERE id = ?",
+                )
+                .bind(&document.title)
+                .bind(&document.description)
+                .bind(&document.text_content)
+                .bind(category_id)
+                .bind(&document.created_at)
+                .bind(&document.updated_at)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                    > 0
+            }
+            None => false,
+        };
+        let id = match existing {
+            Some(id) if updated => {
+                report.documents_updated += 1;
+                id
+            }
+            _ => {
+                let id = sqlx::query(
+                    "INSERT INTO documents
+                       (title, description, text_content, category_id, created_at, updated_at)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&document.title)
+                .bind(&document.description)
+                .bind(&document.text_content)
+                .bind(category_id)
+                .bind(&document.created_at)
+                .bind(&document.updated_at)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+                map_id(&mut tx, source, Entity::Document, document.id, id).await?;
+                report.documents_added += 1;
+                id
+            }
+        };
+        report.document_ids.push(IdMapping {
+            import_id: document.id,
+            target_id: Some(id),
+        });
+    }
+
+    let zip = Arc::new(Mutex::new(zip));
+    for attachment in &contents.attachments {
+        let Some(document_id) =
+            local_id(&mut tx, source, Entity::Document, attachment.document_id).await?
+        else {
+            report.skipped.push(ImportSkip {
+                kind: "attachment",
+                name: attachment.filename.clone(),
+                reason: "Its document is not in this archive".to_string(),
+            });
+            continue;
+        };
+        if let Some(id) = local_id(&mut tx, source, Entity::Attachment, attachment.id).await? {
+            files.removed.extend(delete_attachment(&mut tx, id).await?);
+        }
+
+        let dir = db::attachments_dir(app, document_id)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let dest = dir.join(format!("{}_{}", millis, attachment.filename));
+
+        let zip = Arc::clone(&zip);
+        let export_path = attachment.export_path.clone();
+        let target = dest.clone();
+        tauri::async_runtime::spawn_blocking(move || import::extract(&zip, &export_path, &target))
+            .await??;
+        files.written.push(dest.clone());
+
+        let phash = if attachment.filetype.starts_with("image/") {
+            attachments::image_hash(dest.clone()).await
+        } else {
+            None
+        };
+
+        let id = sqlx::query(
+            "INSERT INTO attachments (document_id, filename, filepath, filetype, filesize, sort_order, phash)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(document_id)
+        .bind(&attachment.filename)
+        .bind(dest.to_string_lossy().to_string())
+        .bind(&attachment.filetype)
+        .bind(attachment.filesize)
+        .bind(attachment.sort_order)
+        .bind(phash)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        map_id(&mut tx, source, Entity::Attachment, attachment.id, id).await?;
+        report.attachments_added += 1;
+    }
+
+    // Attachments before their documents, documents before their categories
+    let mut tombstones: Vec<&Tombstone> = contents.tombstones.iter().collect();
+    tombstones.sort_by_key(|tombstone| match tombstone.entity {
+        Entity::Attachment => 0,
+        Entity::Document => 1,
+        Entity::Category => 2,
+    });
+    for tombstone in tombstones {
+        let Some(id) = local_id(&mut tx, source, tombstone.entity, tombstone.id).await? else {
+            continue;
+        };
+        let deleted = match tombstone.entity {
+            Entity::Attachment => match delete_attachment(&mut tx, id).await? {
+                Some(path) => {
+                    files.removed.push(path);
+                    true
+                }
+                None => false,
+            },
+            Entity::Document => {
+                let exists = sqlx::query_as::<_, (i64,)>("SELECT id FROM documents WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .is_some();
+                if exists {
+                    trash_documents(&mut tx, &[id]).await?;
+                }
+                exists
+            }
+            Entity::Category => {
+                sqlx::query("DELETE FROM categories WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected()
+                    > 0
+            }
+        };
+        if deleted {
+            report.deleted += 1;
+        }
+        sqlx::query(
+            "DELETE FROM incremental_ids WHERE source = ? AND entity = ? AND source_id = ?",
+        )
+        .bind(source)
+        .bind(tombstone.entity.as_str())
+        .bind(tombstone.id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO incremental_sources (source, marker) VALUES (?, ?)
+         ON CONFLICT(source) DO UPDATE SET marker = excluded.marker,
+           updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(source)
+    .bind(contents.metadata.marker)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+async fn fetch_changed<T>(
+    tx: &mut Transaction<'_, Sqlite>,
+    select: &str,
+    order: &str,
+    ids: &[i64],
+) -> CmdResult<Vec<T>>
+where
+    T: for<'r> sqlx::FromRow<'r, SqliteRow> + Send + Unpin,
+{
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(select);
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    query.push(order);
+    query
+        .build_query_as()
+        .fetch_all(&mut **tx)
+        .await
+        .map_err(AppError::from)
+}
+
+// The record a delta's record became here, if an earlier delta brought it
+async fn local_id(
+    tx: &mut Transaction<'_, Sqlite>,
+    source: &str,
+    entity: Entity,
+    source_id: i64,
+) -> CmdResult<Option<i64>> {
+    sqlx::query_as::<_, (i64,)>(
+        "SELECT local_id FROM incremental_ids WHERE source = ? AND entity = ? AND source_id = ?",
+    )
+    .bind(source)
+    .bind(entity.as_str())
+    .bind(source_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map(|row| row.map(|(id,)| id))
+    .map_err(AppError::from)
+}
+
+async fn map_id(
+    tx: &mut Transaction<'_, Sqlite>,
+    source: &str,
+    entity: Entity,
+    source_id: i64,
+    local_id: i64,
+) -> CmdResult<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO incremental_ids (source, entity, source_id, local_id)
+         VALUES (?, ?, ?, ?)",
+    )
+    .bind(source)
+    .bind(entity.as_str())
+    .bind(source_id)
+    .bind(local_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// Deletes an attachment row and returns its file, to remove after commit
+async fn delete_attachment(tx: &mut Transaction<'_, Sqlite>, id: i64) -> CmdResult<Option<String>> {
+    let path: Option<(String,)> = sqlx::query_as("SELECT filepath FROM attachments WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM attachments WHERE id = ?")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(path.map(|(path,)| path))
+}
+
+fn backups_dir(app: &AppHandle) -> CmdResult<PathBuf> {
+    let app_dir = app.path().app_data_dir()?;
+    Ok(app_dir.join("ando-archive").join("backups"))
+}
+
+// Returns the name of the written backup
+async fn write_local_backup(
+    app: &AppHandle,
+    pool: &sqlx::SqlitePool,
+    suffix: &str,
+) -> CmdResult<String> {
+    let dir = backups_dir(app)?;
+    fs::create_dir_all(&dir)?;
+    let filename = format!(
+        "ando-backup-{}{}{}",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        suffix,
+        BACKUP_EXTENSION
+    );
+    archive::write_complete_archive(app, pool, dir.join(&filename)).await?;
+    Ok(filename)
+}
+
+fn local_backup(path: &Path, filename: String) -> LocalBackup {
+    let file_meta = fs::metadata(path).ok();
+    let metadata = read_backup_metadata(path).ok();
+    let created_at = metadata
+        .as_ref()
+        .map(|metadata| metadata.export_date.clone())
+        .or_else(|| {
+            let modified = file_meta.as_ref()?.modified().ok()?;
+            Some(DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Millis, true))
+        });
+
+    LocalBackup {
+        filename,
+        created_at,
+        size: file_meta.map_or(0, |meta| meta.len()),
+        document_count: metadata.map(|metadata| metadata.total_documents),
+    }
+}
+
+fn read_backup_metadata(path: &Path) -> CmdResult<BackupMetadata> {
+    let file = File::open(path)?;
+    let mut zip = ZipReader::new(BufReader::new(file))?;
+    let entry = zip
+        .find("metadata.json")
+        .ok_or_else(|| AppError::Validation("Backup has no metadata".to_string()))?;
+    let bytes = zip.read(&entry)?;
+    serde_json::from_slice(&bytes).map_err(AppError::from)
+}
+
+fn upload(
+    client: &remote::Client,
+    path: &Path,
+    name: &str,
+    job: &jobs::JobContext,
+) -> CmdResult<UploadedBackup> {
+    let size = fs::metadata(path)?.len();
+    let sha256 = {
+        let mut hasher = HashingWriter::new(io::sink());
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        hasher.finish()
+    };
+
+    let mut file = BufReader::new(File::open(path)?);
+    client
+        .put(name, &mut file, size, &mut |sent| {
+            job.progress(sent as usize, size as usize);
+            !job.is_cancelled()
+        })
+        .map_err(AppError::Io)?;
+
+    // Same layout as `sha256sum`, so the sidecar can be checked by hand
+    let checksum = format!("{}  {}\n", sha256, name);
+    client
+        .put(
+            &checksum_name(name),
+            &mut checksum.as_bytes(),
+            checksum.len() as u64,
+            &mut |_| true,
+        )
+        .map_err(AppError::Io)?;
+
+    let mut remote = HashingWriter::new(io::sink());
+    client
+        .get(name, &mut remote, &mut |_| !job.is_cancelled())
+        .map_err(AppError::Io)?;
+    if remote.finish() != sha256 {
+        return Err(AppError::Io(format!(
+            "Uploaded backup {} does not match the local copy",
+            name
+        )));
+    }
+
+    Ok(UploadedBackup {
+        name: name.to_string(),
+        size,
+        sha256,
+    })
+}
+
+fn download(
+    client: &remote::Client,
+    name: &str,
+    dest: &Path,
+    job: &jobs::JobContext,
+) -> CmdResult<DownloadedBackup> {
+    let size = client
+        .list()
+        .map_err(AppError::Io)?
+        .into_iter()
+        .find(|(file, _)| file == name)
+        .ok_or_else(|| AppError::NotFound(format!("Backup not found: {}", name)))?
+        .1;
+
+    let file = File::create(dest)?;
+    let mut writer = HashingWriter::new(BufWriter::new(file));
+    let total = size.unwrap_or(0) as usize;
+    let written = client
+        .get(name, &mut writer, &mut |received| {
+            job.progress(received as usize, total.max(received as usize));
+            !job.is_cancelled()
+        })
+        .map_err(AppError::Io)?;
+    writer.inner.flush()?;
+    let sha256 = writer.finish();
+
+    let expected = client
+        .get_text(&checksum_name(name))
+        .map_err(AppError::Io)?
+        .and_then(|sidecar| sidecar.split_whitespace().next().map(str::to_lowercase));
+    if let Some(expected) = &expected {
+        if *expected != sha256 {
+            return Err(AppError::Io(format!("Backup {} failed its checksum", name)));
+        }
+    }
+
+    Ok(DownloadedBackup {
+        name: name.to_string(),
+        size: written,
+        verified: expected.is_some(),
+    })
+}
+
+fn checksum_name(name: &str) -> String {
+    format!("{}.sha256", name)
+}
+
+// Remembers given credentials, or looks up the remembered ones. Failing to
+// remember them only means being asked again next time
+async fn resolve_credentials(
+    store: &SettingsStore,
+    credentials: Option<Credentials>,
+) -> CmdResult<Credentials> {
+    if let Some(credentials) = credentials {
+        let json = serde_json::to_string(&credentials)?;
+        let stored =
+            tauri::async_runtime::spawn_blocking(move || secrets::set(CREDENTIALS_SECRET, &json))
+                .await?;
+        match stored {
+            Ok(()) => {
+                let mut settings = store.get();
+                if settings.backup_secret.as_deref() != Some(CREDENTIALS_SECRET) {
+                    settings.backup_secret = Some(CREDENTIALS_SECRET.to_string());
+                    store.replace(settings)?;
+                }
+            }
+            Err(e) => log::warn!("Not remembering backup credentials: {}", e),
+        }
+        return Ok(credentials);
+    }
+
+    let missing = || AppError::Validation("Backup credentials required".to_string());
+    let key = store.get().backup_secret.ok_or_else(missing)?;
+    let json = tauri::async_runtime::spawn_blocking(move || secrets::get(&key))
+        .await?
+        .map_err(|e| AppError::Validation(format!("Backup credentials required: {}", e.message())))?
+        .ok_or_else(missing)?;
+    serde_json::from_str(&json).map_err(AppError::from)
+}
+
+fn configured_client(
+    store: &SettingsStore,
+    credentials: &Credentials,
+) -> CmdResult<remote::Client> {
+    let remote = store
+        .get()
+        .backup_remote
+        .ok_or_else(|| AppError::Validation("No backup remote configured".to_string()))?;
+    connect(&remote, credentials)
+}
+
+fn connect(remote: &RemoteTarget, credentials: &Credentials) -> CmdResult<remote::Client> {
+    let client = match remote {
+        RemoteTarget::Webdav { url } => {
+            remote::webdav::Client::new(url, &credentials.username, &credentials.password)
+                .map(remote::Client::Webdav)
+        }
+        RemoteTarget::S3 {
+            endpoint,
+            bucket,
+            region,
+        } => remote::s3::Client::new(
+            endpoint,
+            bucket,
+            region,
+            &credentials.username,
+            &credentials.password,
+        )
+        .map(remote::Client::S3),
+    };
+    client.map_err(AppError::Validation)
+}
+
+// Passes writes through while hashing them
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> String {
+        hex::encode(self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
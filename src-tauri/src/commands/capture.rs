@@ -0,0 +1,46 @@
+use std::fs;
+
+use chrono::Local;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::capture;
+use crate::commands::documents::DocumentEvent;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::watcher;
+
+/// Captures a screen region (or the full screen) into a new document of
+/// `category_id` and returns the document id.
+#[tauri::command]
+pub async fn capture_screenshot_to_document(app: AppHandle, category_id: i64) -> CmdResult<i64> {
+    let pool = db::pool(&app).await?;
+
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    let dir = app.path().app_cache_dir()?.join("captures");
+    fs::create_dir_all(&dir)?;
+    // The document is titled after the file
+    let path = dir.join(format!(
+        "Screenshot {}.png",
+        Local::now().format("%Y-%m-%d at %H.%M.%S")
+    ));
+
+    let target = path.clone();
+    tauri::async_runtime::spawn_blocking(move || capture::capture_screen(&target))
+        .await?
+        .map_err(AppError::Io)?;
+
+    let ingested = watcher::ingest(&app, &pool, &path, category_id, None).await;
+    let _ = fs::remove_file(&path);
+    let document_id = ingested?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id });
+
+    Ok(document_id)
+}
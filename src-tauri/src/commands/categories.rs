@@ -0,0 +1,783 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::commands::documents::{self, DocumentSort};
+use crate::csv;
+use crate::db::{self, Category};
+use crate::error::{AppError, CmdResult};
+use crate::metrics;
+use crate::rules::{self, CategoryRule, RuleApplied};
+use crate::settings::SettingsStore;
+
+#[derive(Clone, Serialize)]
+pub(crate) struct CategoryEvent {
+    pub category_id: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct CountedCategory {
+    #[sqlx(flatten)]
+    category: Category,
+    document_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: Category,
+    /// Documents directly in this category.
+    pub document_count: i64,
+    /// Documents in this category and all of its descendants.
+    pub total_count: i64,
+    pub children: Vec<CategoryNode>,
+}
+
+/// The whole category hierarchy with document counts, for the sidebar.
+/// Archived categories and everything under them are left out unless
+/// `include_archived`.
+#[tauri::command]
+pub async fn category_tree(
+    app: AppHandle,
+    include_archived: Option<bool>,
+) -> CmdResult<Vec<CategoryNode>> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("category_tree");
+    let rows: Vec<CountedCategory> = sqlx::query_as(&format!(
+        "SELECT c.*, COUNT(d.id) AS document_count
+         FROM categories c
+         LEFT JOIN documents d ON d.category_id = c.id
+         WHERE {}
+         GROUP BY c.id
+         ORDER BY c.sort_order ASC, c.name ASC",
+        archived_scope("c.id", include_archived)
+    ))
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, rows.len());
+
+    Ok(build_tree(rows))
+}
+
+/// The condition keeping rows of archived categories, and of their
+/// subcategories, out of a query unless `include_archived`. `column` holds
+/// the category id, e.g. `d.category_id`; uncategorized rows always pass.
+/// Every default listing goes through this, so archiving hides a document
+/// everywhere at once.
+pub(crate) fn archived_scope(column: &str, include_archived: Option<bool>) -> String {
+    if include_archived.unwrap_or(false) {
+        "1".to_string()
+    } else {
+        format!(
+            "({0} IS NULL OR {0} NOT IN (SELECT id FROM archived_categories))",
+            column
+        )
+    }
+}
+
+/// Archives a category: it, its subcategories and their documents drop out
+/// of the tree, lists, search and stats until `unarchive_category`.
+/// Nothing is moved or deleted.
+#[tauri::command]
+pub async fn archive_category(app: AppHandle, id: i64) -> CmdResult<()> {
+    set_archived(&app, id, true).await
+}
+
+/// Brings an archived category back with everything under it.
+#[tauri::command]
+pub async fn unarchive_category(app: AppHandle, id: i64) -> CmdResult<()> {
+    set_archived(&app, id, false).await
+}
+
+async fn set_archived(app: &AppHandle, id: i64, archived: bool) -> CmdResult<()> {
+    let pool = db::pool(app).await?;
+
+    let current: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT archived_at FROM categories WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?;
+    let (archived_at,) =
+        current.ok_or_else(|| AppError::NotFound("Category not found".to_string()))?;
+    if archived_at.is_some() == archived {
+        return Ok(());
+    }
+
+    sqlx::query(
+        "UPDATE categories SET archived_at = CASE WHEN ? THEN CURRENT_TIMESTAMP END WHERE id = ?",
+    )
+    .bind(archived)
+    .bind(id)
+    .execute(&pool)
+    .await?;
+
+    let event = if archived {
+        "category_archived"
+    } else {
+        "category_unarchived"
+    };
+    let _ = app.emit(event, CategoryEvent { category_id: id });
+
+    Ok(())
+}
+
+/// Every category as a flat list, most recently active first; categories
+/// that never had a document come last, by name.
+#[tauri::command]
+pub async fn list_categories(app: AppHandle) -> CmdResult<Vec<Category>> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("list_categories");
+    let categories: Vec<Category> = sqlx::query_as(
+        "SELECT * FROM categories
+         ORDER BY last_activity_at IS NULL, last_activity_at DESC, name ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, categories.len());
+
+    Ok(categories)
+}
+
+// Iterative so deep hierarchies can't overflow the stack
+fn build_tree(rows: Vec<CountedCategory>) -> Vec<CategoryNode> {
+    let ids: HashSet<i64> = rows.iter().map(|row| row.category.id).collect();
+    let mut children: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut roots = Vec::new();
+    for row in &rows {
+        match row.category.parent_id {
+            Some(parent) if ids.contains(&parent) => {
+                children.entry(parent).or_default().push(row.category.id)
+            }
+            _ => roots.push(row.category.id),
+        }
+    }
+
+    // Breadth-first order, parents before children. Categories caught in a
+    // parent cycle are never reached from a root and become roots themselves.
+    let mut order = Vec::with_capacity(rows.len());
+    let mut visited = HashSet::new();
+    let mut starts: Vec<i64> = roots.clone();
+    starts.extend(rows.iter().map(|row| row.category.id));
+    for start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+        if !roots.contains(&start) {
+            roots.push(start);
+        }
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            order.push(id);
+            for child in children.get(&id).into_iter().flatten() {
+                if visited.insert(*child) {
+                    queue.push_back(*child);
+                }
+            }
+        }
+    }
+
+    let mut nodes: HashMap<i64, CategoryNode> = rows
+        .into_iter()
+        .map(|row| {
+            let node = CategoryNode {
+                total_count: row.document_count,
+                document_count: row.document_count,
+                category: row.category,
+                children: Vec::new(),
+            };
+            (node.category.id, node)
+        })
+        .collect();
+
+    // Children first, so each node is complete when moved into its parent
+    let mut finished: HashMap<i64, CategoryNode> = HashMap::new();
+    for id in order.iter().rev() {
+        let mut node = nodes.remove(id).unwrap();
+        for child in children.get(id).into_iter().flatten() {
+            if let Some(child) = finished.remove(child) {
+                node.total_count += child.total_count;
+                node.children.push(child);
+            }
+        }
+        finished.insert(*id, node);
+    }
+
+    roots.iter().filter_map(|id| finished.remove(id)).collect()
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ManifestEntry {
+    pub id: i64,
+    pub title: String,
+    pub category_id: i64,
+    /// e.g. `Finance / Invoices / 2024`.
+    #[sqlx(skip)]
+    pub category_path: String,
+    /// Of the stored body, in UTF-8.
+    pub body_bytes: i64,
+    pub attachment_count: i64,
+    pub attachment_bytes: i64,
+    /// SHA-256 of the title, body and tags, blind to whitespace changes.
+    pub content_hash: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct ManifestTotals {
+    pub documents: usize,
+    pub attachments: i64,
+    pub body_bytes: i64,
+    pub attachment_bytes: i64,
+    /// Bodies and attachments together.
+    pub bytes: i64,
+}
+
+#[derive(Serialize)]
+pub struct CategoryManifest {
+    pub category_id: i64,
+    pub category_path: String,
+    pub recursive: bool,
+    pub generated_at: String,
+    /// By category path, then title.
+    pub documents: Vec<ManifestEntry>,
+    pub totals: ManifestTotals,
+}
+
+/// An inventory of every document filed in a category, and with
+/// `recursive` (the default) in its subcategories, for records audits:
+/// where each is filed, its size, attachments, content hash and times.
+/// Archived categories are listed like any other.
+#[tauri::command]
+pub async fn category_manifest(
+    app: AppHandle,
+    category_id: i64,
+    recursive: Option<bool>,
+) -> CmdResult<CategoryManifest> {
+    let pool = db::pool(&app).await?;
+    let recursive = recursive.unwrap_or(true);
+
+    let timer = metrics::Timer::start("category_manifest");
+    // Hashes of edited documents are filled in lazily
+    documents::refresh_content_hashes(&pool).await?;
+
+    let categories: Vec<(i64, String, Option<i64>)> =
+        sqlx::query_as("SELECT id, name, parent_id FROM categories")
+            .fetch_all(&pool)
+            .await?;
+    let paths = category_paths(&categories);
+    let category_path = paths
+        .get(&category_id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound("Category not found".to_string()))?;
+
+    let mut entries: Vec<ManifestEntry> = sqlx::query_as(
+        "WITH RECURSIVE scope(id) AS (
+           SELECT ?
+           UNION
+           SELECT c.id FROM categories c JOIN scope s ON c.parent_id = s.id WHERE ?
+         )
+         SELECT d.id, d.title, d.category_id,
+           LENGTH(CAST(COALESCE(d.text_content, '') AS BLOB)) AS body_bytes,
+           COALESCE(a.attachment_count, 0) AS attachment_count,
+           COALESCE(a.attachment_bytes, 0) AS attachment_bytes,
+           d.content_hash, d.created_at, d.updated_at
+         FROM documents d
+         JOIN scope s ON s.id = d.category_id
+         LEFT JOIN (
+           SELECT document_id, COUNT(*) AS attachment_count,
+             COALESCE(SUM(filesize), 0) AS attachment_bytes
+           FROM attachments GROUP BY document_id
+         ) a ON a.document_id = d.id",
+    )
+    .bind(category_id)
+    .bind(recursive)
+    .fetch_all(&pool)
+    .await?;
+    for entry in &mut entries {
+        entry.category_path = paths.get(&entry.category_id).cloned().unwrap_or_default();
+    }
+    entries.sort_by(|a, b| {
+        (&a.category_path, a.title.to_lowercase(), a.id).cmp(&(
+            &b.category_path,
+            b.title.to_lowercase(),
+            b.id,
+        ))
+    });
+    timer.finish(&app, entries.len());
+
+    let mut totals = ManifestTotals {
+        documents: entries.len(),
+        ..Default::default()
+    };
+    for entry in &entries {
+        totals.attachments += entry.attachment_count;
+        totals.body_bytes += entry.body_bytes;
+        totals.attachment_bytes += entry.attachment_bytes;
+    }
+    totals.bytes = totals.body_bytes + totals.attachment_bytes;
+
+    Ok(CategoryManifest {
+        category_id,
+        category_path,
+        recursive,
+        generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        documents: entries,
+        totals,
+    })
+}
+
+/// Writes `category_manifest` to `dest_path` as CSV, one row per
+/// document and a last `total` row, and returns the totals. Line breaks
+/// are written as configured for exports.
+#[tauri::command]
+pub async fn export_category_manifest(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    category_id: i64,
+    recursive: Option<bool>,
+    dest_path: String,
+) -> CmdResult<ManifestTotals> {
+    let manifest = category_manifest(app, category_id, recursive).await?;
+
+    let mut out = String::from(
+        "id,title,category,body_bytes,attachments,attachment_bytes,total_bytes,content_hash,created_at,updated_at\n",
+    );
+    let mut push = |fields: [String; 10]| {
+        let row: Vec<String> = fields.iter().map(|field| csv::field(field)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    };
+    for entry in &manifest.documents {
+        push([
+            entry.id.to_string(),
+            entry.title.clone(),
+            entry.category_path.clone(),
+            entry.body_bytes.to_string(),
+            entry.attachment_count.to_string(),
+            entry.attachment_bytes.to_string(),
+            (entry.body_bytes + entry.attachment_bytes).to_string(),
+            entry.content_hash.clone().unwrap_or_default(),
+            entry.created_at.clone(),
+            entry.updated_at.clone(),
+        ]);
+    }
+    let totals = manifest.totals;
+    push([
+        "total".to_string(),
+        format!("{} documents", totals.documents),
+        manifest.category_path,
+        totals.body_bytes.to_string(),
+        totals.attachments.to_string(),
+        totals.attachment_bytes.to_string(),
+        totals.bytes.to_string(),
+        String::new(),
+        String::new(),
+        manifest.generated_at,
+    ]);
+
+    fs::write(&dest_path, store.get().export_text(out))?;
+
+    Ok(totals)
+}
+
+// "Parent / Child" for every category; one caught in a parent cycle is
+// named from where the cycle closes
+fn category_paths(categories: &[(i64, String, Option<i64>)]) -> HashMap<i64, String> {
+    let by_id: HashMap<i64, (&str, Option<i64>)> = categories
+        .iter()
+        .map(|(id, name, parent)| (*id, (name.as_str(), *parent)))
+        .collect();
+    by_id
+        .keys()
+        .map(|&id| {
+            let mut names = Vec::new();
+            let mut seen = HashSet::new();
+            let mut next = Some(id);
+            while let Some(current) = next.filter(|current| seen.insert(*current)) {
+                let Some(&(name, parent)) = by_id.get(&current) else {
+                    break;
+                };
+                names.push(name);
+                next = parent;
+            }
+            names.reverse();
+            (id, names.join(" / "))
+        })
+        .collect()
+}
+
+/// Deletes a category together with its subcategories, their documents and
+/// the attachment files.
+#[tauri::command]
+pub async fn delete_category(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    id: i64,
+    confirmed: Option<bool>,
+) -> CmdResult<()> {
+    store.get().require_confirmation(confirmed)?;
+
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+
+    let documents: Vec<(i64,)> = sqlx::query_as(
+        "WITH RECURSIVE subtree(id) AS (
+           SELECT id FROM categories WHERE id = ?
+           UNION
+           SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id
+         )
+         SELECT d.id FROM documents d JOIN subtree s ON d.category_id = s.id",
+    )
+    .bind(id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Subcategories and documents cascade with the row
+    let result = sqlx::query("DELETE FROM categories WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    tx.commit().await?;
+
+    let document_ids: Vec<i64> = documents.into_iter().map(|(id,)| id).collect();
+    documents::remove_attachment_files(&app, &document_ids);
+    let _ = app.emit("category_deleted", CategoryEvent { category_id: id });
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct CategoryRuleEntry {
+    pub category_id: i64,
+    pub rule: CategoryRule,
+}
+
+/// Hand-sorts the documents of a category, for `DocumentSort::Manual`.
+/// `ordered_ids` must list each of its documents once.
+#[tauri::command]
+pub async fn reorder_documents_in_category(
+    app: AppHandle,
+    category_id: i64,
+    ordered_ids: Vec<i64>,
+) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let current: Vec<(i64,)> = sqlx::query_as("SELECT id FROM documents WHERE category_id = ?")
+        .bind(category_id)
+        .fetch_all(&pool)
+        .await?;
+
+    let current: HashSet<i64> = current.into_iter().map(|(id,)| id).collect();
+    let requested: HashSet<i64> = ordered_ids.iter().copied().collect();
+    if requested.len() != ordered_ids.len() || requested != current {
+        return Err(AppError::Validation(
+            "Document order must include every document of the category once".to_string(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    for (index, id) in ordered_ids.iter().enumerate() {
+        sqlx::query("UPDATE documents SET manual_order = ? WHERE id = ?")
+            .bind(index as i64 + 1)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    let _ = app.emit("documents_reordered", CategoryEvent { category_id });
+
+    Ok(())
+}
+
+/// How a category's listing opens, for `list_documents`.
+#[derive(Serialize)]
+pub struct CategoryView {
+    pub category_id: i64,
+    pub sort_by: DocumentSort,
+    pub descending: bool,
+    /// As the frontend saved them, e.g. tags to narrow the list to.
+    pub filters: Map<String, Value>,
+    /// `false` when the category has no view of its own and these are the
+    /// defaults.
+    pub saved: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredView {
+    sort_by: DocumentSort,
+    descending: bool,
+    filters: Map<String, Value>,
+}
+
+/// Remembers how to list a category, e.g. receipts newest first or a
+/// project in manual order; `descending` defaults to the sort's usual
+/// direction. Without a `sort_by`, forgets it so the defaults apply.
+#[tauri::command]
+pub async fn set_category_view(
+    app: AppHandle,
+    category_id: i64,
+    sort_by: Option<DocumentSort>,
+    descending: Option<bool>,
+    filters: Option<Map<String, Value>>,
+) -> CmdResult<CategoryView> {
+    let pool = db::pool(&app).await?;
+
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    match sort_by {
+        Some(sort_by) => {
+            let view = StoredView {
+                sort_by,
+                descending: descending.unwrap_or(sort_by.descending_by_default()),
+                filters: filters.unwrap_or_default(),
+            };
+            sqlx::query(
+                "INSERT INTO category_views (category_id, view) VALUES (?, ?)
+                 ON CONFLICT (category_id) DO UPDATE SET
+                   view = excluded.view, updated_at = CURRENT_TIMESTAMP",
+            )
+            .bind(category_id)
+            .bind(serde_json::to_string(&view)?)
+            .execute(&pool)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM category_views WHERE category_id = ?")
+                .bind(category_id)
+                .execute(&pool)
+                .await?;
+        }
+    }
+
+    load_view(&pool, category_id).await
+}
+
+/// The saved view of a category, or the default listing when it has none.
+#[tauri::command]
+pub async fn get_category_view(app: AppHandle, category_id: i64) -> CmdResult<CategoryView> {
+    let pool = db::pool(&app).await?;
+    load_view(&pool, category_id).await
+}
+
+async fn load_view(pool: &SqlitePool, category_id: i64) -> CmdResult<CategoryView> {
+    let row: Option<(String,)> =
+        sqlx::query_as("SELECT view FROM category_views WHERE category_id = ?")
+            .bind(category_id)
+            .fetch_optional(pool)
+            .await?;
+    let stored = row.and_then(|(view,)| match serde_json::from_str::<StoredView>(&view) {
+        Ok(view) => Some(view),
+        Err(e) => {
+            log::warn!("Ignoring invalid view of category {}: {}", category_id, e);
+            None
+        }
+    });
+
+    Ok(match stored {
+        Some(view) => CategoryView {
+            category_id,
+            sort_by: view.sort_by,
+            descending: view.descending,
+            filters: view.filters,
+            saved: true,
+        },
+        None => {
+            let sort_by = DocumentSort::default();
+            CategoryView {
+                category_id,
+                sort_by,
+                descending: sort_by.descending_by_default(),
+                filters: Map::new(),
+                saved: false,
+            }
+        }
+    })
+}
+
+/// Sets the lifecycle rule of a category, replacing the one it had, or
+/// removes it when `rule` is `None`, then applies all rules once and
+/// returns what they did. Trash rules need confirmation.
+#[tauri::command]
+pub async fn set_category_rule(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    category_id: i64,
+    rule: Option<CategoryRule>,
+    confirmed: Option<bool>,
+) -> CmdResult<Vec<RuleApplied>> {
+    let pool = db::pool(&app).await?;
+
+    let exists = |id: i64| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_as::<_, (i64,)>("SELECT id FROM categories WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&pool)
+                .await
+                .map(|row| row.is_some())
+                .map_err(AppError::from)
+        }
+    };
+    if !exists(category_id).await? {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    let Some(rule) = rule else {
+        sqlx::query("DELETE FROM category_rules WHERE category_id = ?")
+            .bind(category_id)
+            .execute(&pool)
+            .await?;
+        return Ok(Vec::new());
+    };
+    if rule.older_than_days() == 0 {
+        return Err(AppError::Validation(
+            "older_than_days must be at least 1".to_string(),
+        ));
+    }
+    match &rule {
+        CategoryRule::Archive {
+            target_category_id, ..
+        } => {
+            if *target_category_id == category_id {
+                return Err(AppError::Validation(
+                    "A category can't archive into itself".to_string(),
+                ));
+            }
+            if !exists(*target_category_id).await? {
+                return Err(AppError::NotFound("Archive category not found".to_string()));
+            }
+        }
+        CategoryRule::Trash { .. } => store.get().require_confirmation(confirmed)?,
+    }
+
+    sqlx::query(
+        "INSERT INTO category_rules (category_id, rule) VALUES (?, ?)
+         ON CONFLICT(category_id) DO UPDATE SET rule = excluded.rule,
+           updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(category_id)
+    .bind(serde_json::to_string(&rule)?)
+    .execute(&pool)
+    .await?;
+
+    rules::apply(&app, &pool).await
+}
+
+#[tauri::command]
+pub async fn list_category_rules(app: AppHandle) -> CmdResult<Vec<CategoryRuleEntry>> {
+    let pool = db::pool(&app).await?;
+
+    let rows: Vec<(i64, String)> =
+        sqlx::query_as("SELECT category_id, rule FROM category_rules ORDER BY category_id")
+            .fetch_all(&pool)
+            .await?;
+    rows.into_iter()
+        .map(|(category_id, rule)| {
+            Ok(CategoryRuleEntry {
+                category_id,
+                rule: serde_json::from_str(&rule)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn visible(
+        pool: &SqlitePool,
+        table: &str,
+        column: &str,
+        include: Option<bool>,
+    ) -> Vec<i64> {
+        let ids: Vec<(i64,)> = sqlx::query_as(&format!(
+            "SELECT id FROM {} WHERE {} ORDER BY id",
+            table,
+            archived_scope(column, include)
+        ))
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        ids.into_iter().map(|(id,)| id).collect()
+    }
+
+    #[test]
+    fn archived_scope_hides_the_whole_subtree() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            // 1 > 2 > 3 gets archived at 1; 4 stays
+            sqlx::query(
+                "INSERT INTO categories (id, name, parent_id) VALUES
+                   (1, 'Old', NULL), (2, 'Older', 1), (3, 'Oldest', 2), (4, 'Current', NULL)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query(
+                "INSERT INTO documents (id, title, category_id) VALUES
+                   (1, 'a', 1), (2, 'b', 3), (3, 'c', 4), (4, 'd', NULL)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            assert_eq!(
+                visible(&pool, "documents", "category_id", None).await,
+                [1, 2, 3, 4]
+            );
+
+            sqlx::query("UPDATE categories SET archived_at = CURRENT_TIMESTAMP WHERE id = 1")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(
+                visible(&pool, "documents", "category_id", None).await,
+                [3, 4]
+            );
+            assert_eq!(visible(&pool, "categories", "id", Some(false)).await, [4]);
+            assert_eq!(
+                visible(&pool, "documents", "category_id", Some(true)).await,
+                [1, 2, 3, 4]
+            );
+            assert_eq!(
+                visible(&pool, "categories", "id", Some(true)).await,
+                [1, 2, 3, 4]
+            );
+
+            // Archiving only the middle keeps its parent
+            sqlx::query(
+                "UPDATE categories SET archived_at = CASE WHEN id = 2 THEN CURRENT_TIMESTAMP END",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            assert_eq!(visible(&pool, "categories", "id", None).await, [1, 4]);
+            assert_eq!(
+                visible(&pool, "documents", "category_id", None).await,
+                [1, 3, 4]
+            );
+        });
+    }
+}
@@ -0,0 +1,61 @@
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands::documents::refresh_content_hashes;
+use crate::db;
+use crate::error::CmdResult;
+use crate::metrics;
+
+const DEFAULT_LIMIT: u32 = 1000;
+const MAX_LIMIT: u32 = 10_000;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Change {
+    pub seq: i64,
+    /// `document`, `category` or `attachment`.
+    pub entity: String,
+    pub entity_id: i64,
+    /// `create`, `update` or `delete`; `trash`, `restore` and `purge` (gone
+    /// from the trash for good) for documents and their attachments;
+    /// `archive` and `unarchive` for categories.
+    pub op: String,
+    /// A document's content hash after the change, as `get_document` has
+    /// it; `None` for other entities and for changes a later one replaced
+    /// before the hash was computed.
+    pub content_hash: Option<String>,
+    /// Unix seconds.
+    pub occurred_at: i64,
+    /// The import that created the entity, for `create` changes.
+    pub import_session: Option<i64>,
+}
+
+/// The changes after `seq`, oldest first, at most `limit` (1000 by
+/// default). A sync tool polls with the last `seq` it applied; 0 starts
+/// from the beginning, where every entity there was when the feed began
+/// has a `create`.
+#[tauri::command]
+pub async fn changes_since(app: AppHandle, seq: i64, limit: Option<u32>) -> CmdResult<Vec<Change>> {
+    let pool = db::pool(&app).await?;
+    refresh_content_hashes(&pool).await?;
+
+    let timer = metrics::Timer::start("changes_since");
+    let changes: Vec<Change> =
+        sqlx::query_as("SELECT * FROM change_feed WHERE seq > ? ORDER BY seq ASC LIMIT ?")
+            .bind(seq)
+            .bind(limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+            .fetch_all(&pool)
+            .await?;
+    timer.finish(&app, changes.len());
+
+    Ok(changes)
+}
+
+/// The `seq` of the latest change, 0 before the first.
+#[tauri::command]
+pub async fn current_change_seq(app: AppHandle) -> CmdResult<i64> {
+    let pool = db::pool(&app).await?;
+    let (seq,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(seq), 0) FROM change_feed")
+        .fetch_one(&pool)
+        .await?;
+    Ok(seq)
+}
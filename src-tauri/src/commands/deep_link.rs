@@ -0,0 +1,17 @@
+use tauri::AppHandle;
+
+use crate::db;
+use crate::deep_link::{self, ResolvedTarget};
+use crate::error::CmdResult;
+
+/// Where a target leads: an `andoarchive://` link to a document,
+/// attachment or version, a bare document id like `42`, or any other text
+/// as a search. QR codes, the quick switcher, reminders and links from
+/// other apps all route through this. Fails with `not_found` for items
+/// deleted since the link was made and `validation` for links it can't
+/// read.
+#[tauri::command]
+pub async fn resolve_target(app: AppHandle, target: String) -> CmdResult<ResolvedTarget> {
+    let pool = db::pool(&app).await?;
+    deep_link::resolve(&pool, &target).await
+}
@@ -0,0 +1,363 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{Sqlite, Transaction};
+use tauri::{AppHandle, State};
+
+use crate::commands::{audit, search};
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::settings::SettingsStore;
+
+const MAX_DOCUMENTS: u32 = 100_000;
+// Everything is dated from here rather than from today, so a seed gives
+// the same archive whenever it runs
+const BASE_DATE: i64 = 1_704_067_200; // 2024-01-01T00:00:00Z
+const SPAN_SECONDS: u64 = 3 * 365 * 24 * 60 * 60;
+// One document in this many gets an attachment
+const ATTACHMENT_EVERY: u64 = 8;
+
+const CATEGORIES: &[&str] = &[
+    "Projects",
+    "Finances",
+    "Home",
+    "Travel",
+    "Health",
+    "Receipts",
+    "Contracts",
+    "Recipes",
+    "Research",
+    "Letters",
+    "Manuals",
+    "Taxes",
+    "Insurance",
+    "Car",
+    "School",
+    "Garden",
+    "Photos",
+    "Ideas",
+    "Meetings",
+    "Reading",
+];
+const COLORS: &[&str] = &[
+    "#ef4444", "#f97316", "#eab308", "#22c55e", "#06b6d4", "#3b82f6", "#8b5cf6", "#ec4899",
+];
+const TAGS: &[&str] = &[
+    "urgent",
+    "todo",
+    "done",
+    "reference",
+    "2023",
+    "2024",
+    "2025",
+    "draft",
+    "signed",
+    "scan",
+    "work",
+    "personal",
+    "family",
+    "invoice",
+    "warranty",
+    "résumé",
+    "ideas",
+    "archive",
+    "follow-up",
+    "shared",
+    "private",
+    "review",
+    "paid",
+    "unpaid",
+    "important",
+    "old",
+    "travel",
+    "health",
+    "school",
+    "café",
+];
+const WORDS: &[&str] = &[
+    "annual",
+    "report",
+    "meeting",
+    "notes",
+    "invoice",
+    "receipt",
+    "contract",
+    "plan",
+    "summary",
+    "draft",
+    "letter",
+    "budget",
+    "schedule",
+    "project",
+    "review",
+    "list",
+    "garden",
+    "kitchen",
+    "travel",
+    "insurance",
+    "policy",
+    "claim",
+    "renewal",
+    "account",
+    "statement",
+    "quarter",
+    "design",
+    "proposal",
+    "manual",
+    "warranty",
+    "school",
+    "form",
+    "application",
+    "recipe",
+    "appointment",
+    "checklist",
+    "inventory",
+    "backup",
+    "photo",
+    "scan",
+    "tax",
+    "return",
+    "lease",
+    "payment",
+    "order",
+    "delivery",
+    "repair",
+    "estimate",
+    "minutes",
+    "agenda",
+    "research",
+    "article",
+    "chapter",
+    "outline",
+    "ideas",
+    "journal",
+    "naïve",
+    "café",
+    "Zürich",
+    "São Paulo",
+];
+
+#[derive(Default, Serialize)]
+pub struct SeedReport {
+    pub seed: u64,
+    pub categories: usize,
+    pub documents: usize,
+    pub tags: usize,
+    pub attachments: usize,
+}
+
+// splitmix64: small, fast and the same on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+
+    // Between `min` and `min + spread - 1` words
+    fn words(&mut self, min: u64, spread: u64) -> String {
+        let count = min + self.below(spread);
+        (0..count)
+            .map(|_| self.pick(WORDS))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Fills an empty archive with sample categories, documents, tags and
+/// attachments generated from `seed`, so demos and performance issues can
+/// be reproduced. The same seed and count give the same archive. Needs
+/// `developer_mode` and `confirmed`, and refuses to touch an archive that
+/// already has documents.
+#[tauri::command]
+pub async fn seed_demo_data(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    count: u32,
+    seed: u64,
+    confirmed: Option<bool>,
+) -> CmdResult<SeedReport> {
+    if !store.get().developer_mode {
+        return Err(AppError::Validation(
+            "seed_demo_data requires developer_mode".to_string(),
+        ));
+    }
+    if confirmed != Some(true) {
+        return Err(AppError::ConfirmationRequired(
+            "Seeding adds demo data to the archive; confirm to continue".to_string(),
+        ));
+    }
+    if count == 0 || count > MAX_DOCUMENTS {
+        return Err(AppError::Validation(format!(
+            "Count must be between 1 and {}",
+            MAX_DOCUMENTS
+        )));
+    }
+
+    let pool = db::pool(&app).await?;
+    let (existing,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents")
+        .fetch_one(&pool)
+        .await?;
+    if existing > 0 {
+        return Err(AppError::Conflict(
+            "Demo data can only be seeded into an empty archive".to_string(),
+        ));
+    }
+
+    let deferred = search::defer_indexing(&pool).await?;
+    let mut tx = pool.begin().await?;
+    let mut written = Vec::new();
+    let result = generate(&app, &mut tx, count, seed, &mut written).await;
+    let result = match result {
+        Ok(report) => {
+            let details = format!(
+                "seed {}: {} documents, {} categories",
+                seed, report.documents, report.categories
+            );
+            audit::record(&mut *tx, "seed_demo", "database", None, &details).await?;
+            tx.commit().await?;
+            Ok(report)
+        }
+        Err(e) => {
+            for path in &written {
+                let _ = fs::remove_file(path);
+            }
+            Err(e)
+        }
+    };
+    if deferred {
+        search::resume_indexing(&pool).await?;
+    }
+    result
+}
+
+async fn generate(
+    app: &AppHandle,
+    tx: &mut Transaction<'_, Sqlite>,
+    count: u32,
+    seed: u64,
+    written: &mut Vec<PathBuf>,
+) -> CmdResult<SeedReport> {
+    let mut rng = Rng(seed);
+    let mut report = SeedReport {
+        seed,
+        ..Default::default()
+    };
+
+    // A few top-level categories, the rest nested one level below them
+    let category_count = (count as usize / 50).clamp(3, CATEGORIES.len());
+    let top_level = (category_count / 3).max(1);
+    let mut categories: Vec<(i64, i64)> = Vec::new();
+    for (index, name) in CATEGORIES.iter().take(category_count).enumerate() {
+        let (parent_id, level) = if index < top_level {
+            (None, 0)
+        } else {
+            let (parent, _) = categories[rng.below(top_level as u64) as usize];
+            (Some(parent), 1)
+        };
+        let id = sqlx::query(
+            "INSERT INTO categories (name, icon, color, parent_id, level, sort_order)
+             VALUES (?, 'folder', ?, ?, ?, ?)",
+        )
+        .bind(name)
+        .bind(rng.pick(COLORS))
+        .bind(parent_id)
+        .bind(level)
+        .bind(index as i64)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid();
+        categories.push((id, level));
+    }
+    report.categories = categories.len();
+
+    // Tags can outlive the documents that used them
+    let mut tag_ids = Vec::new();
+    for name in TAGS {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(name)
+            .execute(&mut **tx)
+            .await?;
+        let (id,): (i64,) = sqlx::query_as("SELECT id FROM tags WHERE name = ?")
+            .bind(name)
+            .fetch_one(&mut **tx)
+            .await?;
+        tag_ids.push(id);
+    }
+    report.tags = tag_ids.len();
+
+    let base = DateTime::<Utc>::from_timestamp(BASE_DATE, 0).unwrap_or_default();
+    for number in 1..=u64::from(count) {
+        let mut title = rng.words(2, 4);
+        if let Some(first) = title.get(..1) {
+            title = first.to_uppercase() + &title[1..];
+        }
+        let paragraphs = 1 + rng.below(4);
+        let body: String = (0..paragraphs)
+            .map(|_| format!("<p>{}.</p>", rng.words(20, 40)))
+            .collect();
+        let created = base + Duration::seconds(rng.below(SPAN_SECONDS) as i64);
+        let updated = created + Duration::seconds(rng.below(30 * 24 * 60 * 60) as i64);
+        let (category_id, _) = categories[rng.below(categories.len() as u64) as usize];
+
+        let document_id = sqlx::query(
+            "INSERT INTO documents (title, description, text_content, category_id, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(format!("{} {}", title, number))
+        .bind(rng.words(6, 1))
+        .bind(&body)
+        .bind(category_id)
+        .bind(created.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(updated.format("%Y-%m-%d %H:%M:%S").to_string())
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid();
+
+        for _ in 0..rng.below(4) {
+            sqlx::query("INSERT OR IGNORE INTO document_tags (document_id, tag_id) VALUES (?, ?)")
+                .bind(document_id)
+                .bind(tag_ids[rng.below(tag_ids.len() as u64) as usize])
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        if rng.below(ATTACHMENT_EVERY) == 0 {
+            let filename = format!("notes-{}.txt", number);
+            let contents = rng.words(50, 200);
+            let dir = db::attachments_dir(app, document_id)?;
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(format!("demo_{}", filename));
+            fs::write(&path, &contents)?;
+            written.push(path.clone());
+            sqlx::query(
+                "INSERT INTO attachments (document_id, filename, filepath, filetype, filesize, sort_order)
+                 VALUES (?, ?, ?, 'text/plain', ?, 1)",
+            )
+            .bind(document_id)
+            .bind(&filename)
+            .bind(path.to_string_lossy().to_string())
+            .bind(contents.len() as i64)
+            .execute(&mut **tx)
+            .await?;
+            report.attachments += 1;
+        }
+        report.documents += 1;
+    }
+
+    Ok(report)
+}
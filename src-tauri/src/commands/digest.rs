@@ -0,0 +1,254 @@
+use std::fs;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::db;
+use crate::error::CmdResult;
+use crate::markdown;
+use crate::settings::SettingsStore;
+
+// Added documents listed by title; the rest are only counted
+const LISTED_DOCUMENTS: usize = 10;
+const TOP_TAGS: i64 = 5;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestPeriod {
+    Week,
+    Month,
+}
+
+impl DigestPeriod {
+    fn days(self) -> i64 {
+        match self {
+            DigestPeriod::Week => 7,
+            DigestPeriod::Month => 30,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            DigestPeriod::Week => "week",
+            DigestPeriod::Month => "month",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+#[derive(Serialize)]
+pub struct Digest {
+    /// Unix seconds; the digest covers `from` up to `to`.
+    pub from: i64,
+    pub to: i64,
+    pub content: String,
+    /// Where the digest was written, when a `dest_path` was given.
+    pub path: Option<String>,
+}
+
+/// A recap of the last 7 or 30 days from the audit log: documents added,
+/// categories worked in, the tags used most and how much attachment
+/// storage grew. Markdown unless `format` is `html`; with `dest_path` it is
+/// also written there. A quiet period gets a short note instead of empty
+/// sections.
+#[tauri::command]
+pub async fn generate_digest(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    period: DigestPeriod,
+    format: Option<DigestFormat>,
+    dest_path: Option<String>,
+) -> CmdResult<Digest> {
+    let pool = db::pool(&app).await?;
+    let to = Utc::now();
+    let from = to - Duration::days(period.days());
+    let since = from.timestamp();
+
+    // Documents created in the period that are still around, newest first
+    let added: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT d.id, d.title FROM audit_log l
+         JOIN documents d ON d.id = l.entity_id
+         WHERE l.entity_type = 'document' AND l.operation = 'create' AND l.occurred_at >= ?
+         GROUP BY d.id
+         ORDER BY MAX(l.occurred_at) DESC, d.id DESC",
+    )
+    .bind(since)
+    .fetch_all(&pool)
+    .await?;
+    let (edited, removed): (i64, i64) = sqlx::query_as(
+        "SELECT
+           COUNT(DISTINCT CASE WHEN operation = 'update' THEN entity_id END),
+           COUNT(DISTINCT CASE WHEN operation = 'delete' THEN entity_id END)
+         FROM audit_log
+         WHERE entity_type = 'document' AND occurred_at >= ?",
+    )
+    .bind(since)
+    .fetch_one(&pool)
+    .await?;
+
+    let categories: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT c.name, COUNT(DISTINCT d.id) AS touched FROM audit_log l
+         JOIN documents d ON d.id = l.entity_id
+         JOIN categories c ON c.id = d.category_id
+         WHERE l.entity_type = 'document' AND l.operation IN ('create', 'update')
+           AND l.occurred_at >= ?
+         GROUP BY c.id
+         ORDER BY touched DESC, c.name ASC",
+    )
+    .bind(since)
+    .fetch_all(&pool)
+    .await?;
+
+    let tags: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT t.name, COUNT(*) AS uses FROM document_tags dt
+         JOIN tags t ON t.id = dt.tag_id
+         WHERE dt.document_id IN (
+           SELECT entity_id FROM audit_log
+           WHERE entity_type = 'document' AND operation IN ('create', 'update')
+             AND occurred_at >= ?
+         )
+         GROUP BY t.id
+         ORDER BY uses DESC, t.name ASC
+         LIMIT ?",
+    )
+    .bind(since)
+    .bind(TOP_TAGS)
+    .fetch_all(&pool)
+    .await?;
+
+    let (new_files, new_bytes, total_bytes): (i64, i64, i64) = sqlx::query_as(
+        "SELECT
+           COUNT(*) FILTER (WHERE id IN (SELECT entity_id FROM audit_log
+             WHERE entity_type = 'attachment' AND operation = 'create' AND occurred_at >= ?1)),
+           COALESCE(SUM(filesize) FILTER (WHERE id IN (SELECT entity_id FROM audit_log
+             WHERE entity_type = 'attachment' AND operation = 'create' AND occurred_at >= ?1)), 0),
+           COALESCE(SUM(filesize), 0)
+         FROM attachments",
+    )
+    .bind(since)
+    .fetch_one(&pool)
+    .await?;
+
+    let to_date = |date: DateTime<Utc>| date.format("%Y-%m-%d").to_string();
+    let mut text = format!(
+        "# Your {} in the archive\n\n{} to {}\n\n",
+        period.label(),
+        to_date(from),
+        to_date(to)
+    );
+    if added.is_empty() && edited == 0 && removed == 0 && new_files == 0 {
+        text.push_str(&format!(
+            "Nothing happened this {}: no documents were added, edited or removed. \
+             The archive holds {} of attachments.\n",
+            period.label(),
+            format_bytes(total_bytes)
+        ));
+    } else {
+        text.push_str(&format!(
+            "## Documents\n\n{} added, {} edited, {} removed.\n\n",
+            added.len(),
+            edited,
+            removed
+        ));
+        for (_, title) in added.iter().take(LISTED_DOCUMENTS) {
+            text.push_str(&format!("- {}\n", escape(title)));
+        }
+        if added.len() > LISTED_DOCUMENTS {
+            text.push_str(&format!("- and {} more\n", added.len() - LISTED_DOCUMENTS));
+        }
+        if !added.is_empty() {
+            text.push('\n');
+        }
+
+        if !categories.is_empty() {
+            text.push_str("## Categories\n\n");
+            for (name, count) in &categories {
+                text.push_str(&format!(
+                    "- {}: {}\n",
+                    escape(name),
+                    plural(*count, "document")
+                ));
+            }
+            text.push('\n');
+        }
+
+        if !tags.is_empty() {
+            text.push_str("## Most used tags\n\n");
+            for (name, count) in &tags {
+                text.push_str(&format!(
+                    "- {}: {}\n",
+                    escape(name),
+                    plural(*count, "document")
+                ));
+            }
+            text.push('\n');
+        }
+
+        text.push_str(&format!(
+            "## Storage\n\n{} added in {}, {} in total.\n",
+            format_bytes(new_bytes),
+            plural(new_files, "attachment"),
+            format_bytes(total_bytes)
+        ));
+    }
+
+    let content = match format.unwrap_or_default() {
+        DigestFormat::Markdown => text,
+        DigestFormat::Html => markdown::to_html(&text, store.get().markdown),
+    };
+    if let Some(dest_path) = &dest_path {
+        fs::write(dest_path, store.get().export_text(content.clone()))?;
+    }
+
+    Ok(Digest {
+        from: since,
+        to: to.timestamp(),
+        content,
+        path: dest_path,
+    })
+}
+
+fn plural(count: i64, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Names are shown as written, not as Markdown
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '#' | '<' | '>' | '!' | '|' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
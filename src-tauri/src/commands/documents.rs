@@ -0,0 +1,3018 @@
+use std::fs;
+
+use base64::Engine;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use image::ImageFormat;
+use regex::{Captures, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::collation;
+use crate::commands::review::document_exists;
+use crate::commands::{attachments, categories, related, search, settings, toc};
+use crate::cursor;
+use crate::db::{self, Attachment, Document};
+use crate::deep_link;
+use crate::encoding;
+use crate::error::{AppError, CmdResult};
+use crate::html;
+use crate::language::{self, LanguageGuess};
+use crate::markdown;
+use crate::metrics;
+use crate::qr::QrCode;
+use crate::sanitize::{self, SanitizeOptions};
+use crate::settings::{Settings, SettingsStore};
+use crate::summary;
+
+#[derive(Clone, Serialize)]
+pub(crate) struct DocumentEvent {
+    pub document_id: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DocumentAttachmentMatch {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub document: Document,
+    pub matching_attachments: i64,
+}
+
+/// Overrides the stored timestamps (unix seconds) of a document, e.g. to
+/// keep the original dates of imported records. Fields left `None` keep
+/// their current value.
+#[tauri::command]
+pub async fn set_document_timestamps(
+    app: AppHandle,
+    id: i64,
+    created_at: Option<i64>,
+    updated_at: Option<i64>,
+) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let (current_created, current_updated): (i64, i64) = sqlx::query_as(
+        "SELECT CAST(strftime('%s', created_at) AS INTEGER), CAST(strftime('%s', updated_at) AS INTEGER)
+         FROM documents WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let created = created_at.unwrap_or(current_created);
+    let updated = updated_at.unwrap_or(current_updated);
+    if updated < created {
+        return Err(AppError::Validation(
+            "updated_at must not be earlier than created_at".to_string(),
+        ));
+    }
+
+    sqlx::query("UPDATE documents SET created_at = ?, updated_at = ? WHERE id = ?")
+        .bind(to_sql_datetime(created)?)
+        .bind(to_sql_datetime(updated)?)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentSort {
+    /// A to Z, ignoring case and accents.
+    Title,
+    /// Newest first.
+    Created,
+    /// Most recently edited first.
+    #[default]
+    Updated,
+    /// As set by `reorder_documents_in_category`; needs a category.
+    Manual,
+}
+
+impl DocumentSort {
+    // Sort keys before the id that break ties; `collated` titles sort by
+    // the collation locale alone
+    fn key_count(self, collated: bool) -> usize {
+        match self {
+            DocumentSort::Title if collated => 1,
+            DocumentSort::Title => 2,
+            DocumentSort::Created | DocumentSort::Updated | DocumentSort::Manual => 1,
+        }
+    }
+
+    /// Whether the sort runs high to low unless told otherwise.
+    pub(crate) fn descending_by_default(self) -> bool {
+        matches!(self, DocumentSort::Created | DocumentSort::Updated)
+    }
+}
+
+/// Which columns `list_documents` returns.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    /// Enough for a list row, without the body; open documents with
+    /// `get_document`.
+    Summary,
+    #[default]
+    Full,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DocumentSummary {
+    pub id: i64,
+    pub title: String,
+    pub category_id: Option<i64>,
+    pub updated_at: String,
+    pub word_count: i64,
+    /// The first words of the body as plain text; empty while locked.
+    pub snippet: String,
+    pub locked: bool,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum DocumentListing {
+    Summary(Vec<DocumentSummary>),
+    Full(Vec<Document>),
+}
+
+#[derive(Serialize)]
+pub struct DocumentPage {
+    pub documents: DocumentListing,
+    /// Passed back as `cursor` for the next page; `None` after the last.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct SummaryRow {
+    id: i64,
+    title: String,
+    category_id: Option<i64>,
+    updated_at: String,
+    word_count: Option<i64>,
+    locked: bool,
+    head: String,
+}
+
+// Where a page ended: the sort keys and id of its last row, and the
+// listing they belong to
+#[derive(Serialize, Deserialize)]
+struct ListPosition {
+    sort: DocumentSort,
+    category_id: Option<i64>,
+    #[serde(default)]
+    include_archived: bool,
+    #[serde(default)]
+    collated: bool,
+    // `None` in cursors from before the direction could be chosen
+    #[serde(default)]
+    descending: Option<bool>,
+    keys: Vec<String>,
+    id: i64,
+}
+
+// Enough of the body for a snippet, whatever markup comes first
+const SUMMARY_HEAD_CHARS: i64 = 2000;
+
+/// Documents of a category, or all of them, in the given order. With
+/// `limit`, one page at a time: pages continue after the last row of the
+/// previous one rather than at an offset, so documents added or removed
+/// meanwhile don't shift rows between pages. Documents of archived
+/// categories are left out unless `include_archived`. `descending`
+/// reverses a sort's usual direction when it differs from it. Locked
+/// documents come with `locked` set and without their body.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn list_documents(
+    app: AppHandle,
+    category_id: Option<i64>,
+    sort_by: Option<DocumentSort>,
+    descending: Option<bool>,
+    projection: Option<Projection>,
+    limit: Option<u32>,
+    cursor: Option<String>,
+    include_archived: Option<bool>,
+) -> CmdResult<DocumentPage> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("list_documents");
+    let page = document_page(
+        &pool,
+        category_id,
+        sort_by,
+        descending,
+        projection,
+        limit,
+        cursor,
+        include_archived,
+    )
+    .await?;
+    timer.finish(
+        &app,
+        match &page.documents {
+            DocumentListing::Summary(rows) => rows.len(),
+            DocumentListing::Full(rows) => rows.len(),
+        },
+    );
+
+    Ok(page)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn document_page(
+    pool: &SqlitePool,
+    category_id: Option<i64>,
+    sort_by: Option<DocumentSort>,
+    descending: Option<bool>,
+    projection: Option<Projection>,
+    limit: Option<u32>,
+    cursor: Option<String>,
+    include_archived: Option<bool>,
+) -> CmdResult<DocumentPage> {
+    let sort_by = sort_by.unwrap_or_default();
+    let descending = descending.unwrap_or(sort_by.descending_by_default());
+    let include_archived = include_archived.unwrap_or(false);
+    let projection = projection.unwrap_or_default();
+
+    // Titles sort by the collation locale once the connections have it
+    let collated = sort_by == DocumentSort::Title && collation::is_active(pool).await?;
+
+    let after = match cursor {
+        Some(cursor) => {
+            let position: ListPosition = cursor::decode(&cursor)
+                .ok_or_else(|| AppError::Validation("Invalid cursor".to_string()))?;
+            if position.sort != sort_by
+                || position.category_id != category_id
+                || position.include_archived != include_archived
+                || position.collated != collated
+                || position
+                    .descending
+                    .unwrap_or(sort_by.descending_by_default())
+                    != descending
+            {
+                return Err(AppError::Validation(
+                    "Cursor belongs to a different listing".to_string(),
+                ));
+            }
+            if position.keys.len() != sort_by.key_count(collated) {
+                return Err(AppError::Validation("Invalid cursor".to_string()));
+            }
+            Some(position)
+        }
+        None => None,
+    };
+
+    if sort_by == DocumentSort::Title && !collated {
+        refresh_title_sort(pool).await?;
+    }
+    if sort_by == DocumentSort::Manual && category_id.is_none() {
+        return Err(AppError::Validation(
+            "Manual order is only kept within a category".to_string(),
+        ));
+    }
+    match projection {
+        Projection::Summary => refresh_word_counts(pool).await?,
+        Projection::Full => refresh_content_hashes(pool).await?,
+    };
+    let keys: &[&str] = match sort_by {
+        DocumentSort::Title if collated => &["title COLLATE LOCALE", "id"],
+        DocumentSort::Title => &["COALESCE(title_sort, '')", "title", "id"],
+        DocumentSort::Created => &["created_at", "id"],
+        DocumentSort::Updated => &["updated_at", "id"],
+        DocumentSort::Manual => &["manual_order", "id"],
+    };
+    let (direction, past) = if descending {
+        ("DESC", "<")
+    } else {
+        ("ASC", ">")
+    };
+    let order = keys
+        .iter()
+        .map(|key| format!("{} {}", key, direction))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let after_condition = format!(
+        "({}) {} ({})",
+        keys.join(", "),
+        past,
+        vec!["?"; keys.len()].join(", ")
+    );
+    let columns = match projection {
+        // Only the head of the body leaves SQLite
+        Projection::Summary => format!(
+            "id, title, category_id, updated_at, word_count, locked,
+             CASE WHEN locked THEN '' ELSE substr(COALESCE(text_content, ''), 1, {}) END AS head",
+            SUMMARY_HEAD_CHARS
+        ),
+        Projection::Full => "*".to_string(),
+    };
+    let scope = categories::archived_scope("category_id", Some(include_archived));
+    let mut conditions = vec![scope.as_str()];
+    if category_id.is_some() {
+        conditions.push("category_id = ?");
+    }
+    if after.is_some() {
+        conditions.push(&after_condition);
+    }
+    let mut sql = format!(
+        "SELECT {} FROM documents WHERE {}",
+        columns,
+        conditions.join(" AND ")
+    );
+    sql.push_str(&format!(" ORDER BY {}", order));
+    // One row past the page tells whether another page follows
+    let fetch = limit.map(|limit| i64::from(limit.max(1)) + 1);
+    if fetch.is_some() {
+        sql.push_str(" LIMIT ?");
+    }
+    let keys = after
+        .as_ref()
+        .map(|after| after.keys.as_slice())
+        .unwrap_or(&[]);
+    let after_id = after.as_ref().map(|after| after.id);
+
+    // The page and the keys of its last row are read from one snapshot
+    let mut tx = pool.begin().await?;
+    let (listing, more, last_id) = match projection {
+        Projection::Summary => {
+            let mut query = sqlx::query_as::<_, SummaryRow>(&sql);
+            if let Some(category_id) = category_id {
+                query = query.bind(category_id);
+            }
+            for key in keys {
+                query = query.bind(key);
+            }
+            if let Some(after_id) = after_id {
+                query = query.bind(after_id);
+            }
+            if let Some(fetch) = fetch {
+                query = query.bind(fetch);
+            }
+            let mut rows = query.fetch_all(&mut *tx).await?;
+            let more = fetch.is_some_and(|fetch| rows.len() as i64 == fetch);
+            if more {
+                rows.pop();
+            }
+            let last_id = rows.last().map(|row| row.id);
+            let summaries = rows
+                .into_iter()
+                .map(|row| DocumentSummary {
+                    id: row.id,
+                    title: row.title,
+                    category_id: row.category_id,
+                    updated_at: row.updated_at,
+                    word_count: row.word_count.unwrap_or(0),
+                    snippet: search::snippet(&html::strip_tags(&row.head), &[]),
+                    locked: row.locked,
+                })
+                .collect();
+            (DocumentListing::Summary(summaries), more, last_id)
+        }
+        Projection::Full => {
+            let mut query = sqlx::query_as::<_, Document>(&sql);
+            if let Some(category_id) = category_id {
+                query = query.bind(category_id);
+            }
+            for key in keys {
+                query = query.bind(key);
+            }
+            if let Some(after_id) = after_id {
+                query = query.bind(after_id);
+            }
+            if let Some(fetch) = fetch {
+                query = query.bind(fetch);
+            }
+            let mut rows = query.fetch_all(&mut *tx).await?;
+            let more = fetch.is_some_and(|fetch| rows.len() as i64 == fetch);
+            if more {
+                rows.pop();
+            }
+            let last_id = rows.last().map(|row| row.id);
+            let documents = rows
+                .into_iter()
+                .map(Document::without_locked_body)
+                .collect();
+            (DocumentListing::Full(documents), more, last_id)
+        }
+    };
+    let next_cursor = match last_id {
+        Some(id) if more => Some(cursor::encode(&ListPosition {
+            sort: sort_by,
+            category_id,
+            include_archived,
+            collated,
+            descending: Some(descending),
+            keys: sort_keys(&mut tx, sort_by, collated, id).await?,
+            id,
+        })),
+        _ => None,
+    };
+    tx.commit().await?;
+
+    Ok(DocumentPage {
+        documents: listing,
+        next_cursor,
+    })
+}
+
+// The values `list_documents` orders by, besides the id
+async fn sort_keys(
+    tx: &mut Transaction<'_, Sqlite>,
+    sort_by: DocumentSort,
+    collated: bool,
+    id: i64,
+) -> CmdResult<Vec<String>> {
+    let keys = match sort_by {
+        DocumentSort::Title if collated => {
+            let (title,): (String,) = sqlx::query_as("SELECT title FROM documents WHERE id = ?")
+                .bind(id)
+                .fetch_one(&mut **tx)
+                .await?;
+            vec![title]
+        }
+        DocumentSort::Title => {
+            let (title_sort, title): (String, String) = sqlx::query_as(
+                "SELECT COALESCE(title_sort, ''), title FROM documents WHERE id = ?",
+            )
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await?;
+            vec![title_sort, title]
+        }
+        DocumentSort::Created => {
+            let (created_at,): (String,) =
+                sqlx::query_as("SELECT created_at FROM documents WHERE id = ?")
+                    .bind(id)
+                    .fetch_one(&mut **tx)
+                    .await?;
+            vec![created_at]
+        }
+        DocumentSort::Updated => {
+            let (updated_at,): (String,) =
+                sqlx::query_as("SELECT updated_at FROM documents WHERE id = ?")
+                    .bind(id)
+                    .fetch_one(&mut **tx)
+                    .await?;
+            vec![updated_at]
+        }
+        DocumentSort::Manual => {
+            let (manual_order,): (i64,) =
+                sqlx::query_as("SELECT COALESCE(manual_order, 0) FROM documents WHERE id = ?")
+                    .bind(id)
+                    .fetch_one(&mut **tx)
+                    .await?;
+            vec![manual_order.to_string()]
+        }
+    };
+    Ok(keys)
+}
+
+// Bodies `get_document` pages when asked, around a hundred printed pages
+const LARGE_BODY_CHARS: usize = 256 * 1024;
+const PREVIEW_CHARS: usize = 16 * 1024;
+// Per `get_document_body_range` call
+const MAX_RANGE_CHARS: usize = 1 << 20;
+
+#[derive(Serialize)]
+pub struct DocumentView {
+    #[serde(flatten)]
+    pub document: Document,
+    /// Characters in the whole body.
+    pub body_chars: usize,
+    /// Whether `text_content` is only the start of the body, to be read
+    /// in full through `get_document_body_range`.
+    pub body_truncated: bool,
+}
+
+/// One document with its full content, without marking it read. With
+/// `paged`, a body past `LARGE_BODY_CHARS` comes as a preview of its first
+/// `PREVIEW_CHARS` characters and `body_truncated`, so the editor can page
+/// through the rest; saving the preview back would cut the body short.
+#[tauri::command]
+pub async fn get_document(app: AppHandle, id: i64, paged: Option<bool>) -> CmdResult<DocumentView> {
+    let pool = db::pool(&app).await?;
+    refresh_content_hashes(&pool).await?;
+    let mut document: Document = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let body_chars = document
+        .text_content
+        .as_deref()
+        .map_or(0, |body| body.chars().count());
+    let body_truncated = paged.unwrap_or(false) && body_chars > LARGE_BODY_CHARS;
+    if body_truncated {
+        document.text_content = document
+            .text_content
+            .map(|body| char_range(&body, 0, PREVIEW_CHARS).to_string());
+    }
+    Ok(DocumentView {
+        document,
+        body_chars,
+        body_truncated,
+    })
+}
+
+#[derive(Serialize)]
+pub struct BodyRange {
+    pub document_id: i64,
+    /// Where `text` starts, in characters; past the end it is the end.
+    pub offset: usize,
+    pub text: String,
+    /// Characters in the whole body.
+    pub total_chars: usize,
+    /// Changes whenever the body might have, so pages read across an edit
+    /// can be told apart.
+    pub updated_at: String,
+}
+
+/// Up to `len` characters of the body from character `offset`, at most
+/// `MAX_RANGE_CHARS` at a time, for paging through bodies too large to
+/// load at once. Offsets count Unicode characters, so a range never ends
+/// inside one.
+#[tauri::command]
+pub async fn get_document_body_range(
+    app: AppHandle,
+    id: i64,
+    offset: usize,
+    len: usize,
+) -> CmdResult<BodyRange> {
+    let pool = db::pool(&app).await?;
+    let (body, updated_at): (Option<String>, String) =
+        sqlx::query_as("SELECT text_content, updated_at FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let body = body.unwrap_or_default();
+    let total_chars = body.chars().count();
+    let offset = offset.min(total_chars);
+    Ok(BodyRange {
+        document_id: id,
+        offset,
+        text: char_range(&body, offset, len.min(MAX_RANGE_CHARS)).to_string(),
+        total_chars,
+        updated_at,
+    })
+}
+
+// `len` characters from character `start`, as a slice on char boundaries
+fn char_range(text: &str, start: usize, len: usize) -> &str {
+    let byte = |chars: usize| {
+        text.char_indices()
+            .nth(chars)
+            .map_or(text.len(), |(index, _)| index)
+    };
+    let begin = byte(start);
+    let end = text[begin..]
+        .char_indices()
+        .nth(len)
+        .map_or(text.len(), |(index, _)| begin + index);
+    &text[begin..end]
+}
+
+/// Fills in the sort keys of documents created or renamed since the last
+/// refresh; a trigger clears the key whenever a title changes, wherever
+/// the change comes from.
+pub(crate) async fn refresh_title_sort(pool: &SqlitePool) -> CmdResult<usize> {
+    let stale: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, title FROM documents WHERE title_sort IS NULL")
+            .fetch_all(pool)
+            .await?;
+    if stale.is_empty() {
+        return Ok(0);
+    }
+
+    let mut tx = pool.begin().await?;
+    for (id, title) in &stale {
+        sqlx::query("UPDATE documents SET title_sort = ? WHERE id = ?")
+            .bind(fold_case_and_accents(title))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(stale.len())
+}
+
+/// Fills in the folded names of tags and categories created or renamed
+/// since the last refresh, the same way as `refresh_title_sort`.
+pub(crate) async fn refresh_name_sort(pool: &SqlitePool) -> CmdResult<usize> {
+    let mut refreshed = 0;
+    for table in ["tags", "categories"] {
+        let stale: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id, name FROM {} WHERE name_sort IS NULL",
+            table
+        ))
+        .fetch_all(pool)
+        .await?;
+        if stale.is_empty() {
+            continue;
+        }
+
+        let mut tx = pool.begin().await?;
+        for (id, name) in &stale {
+            sqlx::query(&format!("UPDATE {} SET name_sort = ? WHERE id = ?", table))
+                .bind(fold_case_and_accents(name))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        refreshed += stale.len();
+    }
+
+    Ok(refreshed)
+}
+
+/// Counts the words of documents whose body changed since the last
+/// refresh, like `refresh_title_sort` does for titles. Bodies are read a
+/// batch at a time so a large archive isn't loaded at once.
+pub(crate) async fn refresh_word_counts(pool: &SqlitePool) -> CmdResult<usize> {
+    let mut refreshed = 0;
+    loop {
+        let stale: Vec<(i64, Option<String>)> = sqlx::query_as(
+            "SELECT id, text_content FROM documents WHERE word_count IS NULL LIMIT 200",
+        )
+        .fetch_all(pool)
+        .await?;
+        if stale.is_empty() {
+            return Ok(refreshed);
+        }
+
+        let mut tx = pool.begin().await?;
+        for (id, body) in &stale {
+            let words = html::strip_tags(body.as_deref().unwrap_or_default())
+                .split_whitespace()
+                .count();
+            sqlx::query("UPDATE documents SET word_count = ? WHERE id = ?")
+                .bind(words as i64)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        refreshed += stale.len();
+    }
+}
+
+/// Hashes the content of documents that changed since the last refresh,
+/// like `refresh_word_counts` does for bodies.
+pub(crate) async fn refresh_content_hashes(pool: &SqlitePool) -> CmdResult<usize> {
+    let mut refreshed = 0;
+    loop {
+        let stale: Vec<(i64, String, Option<String>, String)> = sqlx::query_as(
+            "SELECT d.id, d.title, d.text_content,
+               (SELECT json_group_array(t.name) FROM document_tags dt
+                JOIN tags t ON t.id = dt.tag_id WHERE dt.document_id = d.id)
+             FROM documents d WHERE d.content_hash IS NULL LIMIT 200",
+        )
+        .fetch_all(pool)
+        .await?;
+        if stale.is_empty() {
+            return Ok(refreshed);
+        }
+
+        let mut tx = pool.begin().await?;
+        for (id, title, body, tags) in &stale {
+            let tags: Vec<String> = serde_json::from_str(tags)?;
+            let hash = content_hash(title, body.as_deref().unwrap_or_default(), &tags);
+            sqlx::query("UPDATE documents SET content_hash = ? WHERE id = ?")
+                .bind(&hash)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            // The change that led to this content, if nothing came after it
+            sqlx::query(
+                "UPDATE change_feed SET content_hash = ?
+                 WHERE seq = (SELECT MAX(seq) FROM change_feed
+                              WHERE entity = 'document' AND entity_id = ?)
+                   AND op IN ('create', 'update', 'restore') AND content_hash IS NULL",
+            )
+            .bind(&hash)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        refreshed += stale.len();
+    }
+}
+
+// SHA-256 over the title, body and tag set, each with whitespace runs
+// collapsed and in NFC, so reflowing text or retyping an accent leaves it
+// unchanged. Tags are compared without case, like the column does.
+fn content_hash(title: &str, body: &str, tags: &[String]) -> String {
+    let collapse = |text: &str| {
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .nfc()
+            .collect::<String>()
+    };
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|tag| collapse(tag).to_lowercase())
+        .collect();
+    tags.sort();
+    tags.dedup();
+
+    let mut hasher = Sha256::new();
+    hasher.update(collapse(title));
+    hasher.update([0]);
+    hasher.update(collapse(body));
+    for tag in &tags {
+        hasher.update([0]);
+        hasher.update(tag);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Backfills sort keys, word counts and content hashes at launch,
+/// including for rows that existed before the columns did, after bringing
+/// stored names into the form `normalize_name` gives them.
+pub fn backfill_listing_columns(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let pool = db::wait_for_pool(&app).await;
+        if let Err(e) = normalize_stored_names(&pool).await {
+            log::warn!("Failed to normalize names: {}", e);
+        }
+        if let Err(e) = refresh_title_sort(&pool).await {
+            log::warn!("Failed to fill in title sort keys: {}", e);
+        }
+        if let Err(e) = refresh_name_sort(&pool).await {
+            log::warn!("Failed to fill in name sort keys: {}", e);
+        }
+        if let Err(e) = refresh_word_counts(&pool).await {
+            log::warn!("Failed to fill in word counts: {}", e);
+        }
+        if let Err(e) = refresh_content_hashes(&pool).await {
+            log::warn!("Failed to fill in content hashes: {}", e);
+        }
+        if let Err(e) = related::refresh_related_index(&pool).await {
+            log::warn!("Failed to index related documents: {}", e);
+        }
+    });
+}
+
+/// Trimmed and composed (NFC), so a name typed as "é" equals the same name
+/// pasted as "e" plus a combining accent. SQLite compares code points, so
+/// names are normalized as they come in.
+pub(crate) fn normalize_name(name: &str) -> String {
+    name.trim().nfc().collect()
+}
+
+// Names written before normalization, or by the frontend. Tags that turn
+// out to be the same are merged into the older one.
+async fn normalize_stored_names(pool: &SqlitePool) -> CmdResult<()> {
+    // Pure ASCII is always normalized already
+    for (table, column) in [
+        ("categories", "name"),
+        ("documents", "title"),
+        ("tags", "name"),
+    ] {
+        let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id, {} FROM {} WHERE {} GLOB '*[^ -~]*' ORDER BY id ASC",
+            column, table, column
+        ))
+        .fetch_all(pool)
+        .await?;
+
+        for (id, name) in rows {
+            if is_nfc(&name) {
+                continue;
+            }
+            let normalized: String = name.nfc().collect();
+            let mut tx = pool.begin().await?;
+            let duplicate: Option<(i64,)> = if table == "tags" {
+                sqlx::query_as("SELECT id FROM tags WHERE name = ? AND id != ? ORDER BY id LIMIT 1")
+                    .bind(&normalized)
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            } else {
+                None
+            };
+            match duplicate {
+                Some((target,)) => {
+                    sqlx::query(
+                        "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+                         SELECT document_id, ? FROM document_tags WHERE tag_id = ?",
+                    )
+                    .bind(target)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                    sqlx::query("DELETE FROM tags WHERE id = ?")
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                None => {
+                    sqlx::query(&format!("UPDATE {} SET {} = ? WHERE id = ?", table, column))
+                        .bind(&normalized)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            tx.commit().await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lowercase with accents dropped, so "Ångström" sorts with "angstrom" and
+/// "apple" next to "Apple".
+pub(crate) fn fold_case_and_accents(text: &str) -> String {
+    text.trim()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CloneOptions {
+    pub attachments: bool,
+    pub tags: bool,
+    pub fields: bool,
+    /// Date the copy now rather than keep the original's dates.
+    pub reset_timestamps: bool,
+}
+
+impl Default for CloneOptions {
+    fn default() -> Self {
+        Self {
+            attachments: true,
+            tags: true,
+            fields: true,
+            reset_timestamps: true,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct CloneAttachment {
+    id: i64,
+    filename: String,
+    filepath: String,
+    filetype: String,
+    filesize: Option<i64>,
+    sort_order: i64,
+    created_at: String,
+}
+
+/// Copies a document, titled with a "(copy)" suffix, into the same
+/// category, e.g. to use it as a template. Attachments, tags and custom
+/// fields come along unless `options` leaves them out; attachment files
+/// are hard-linked where possible rather than duplicated. History and
+/// reading stats start over. Returns the new document's id.
+#[tauri::command]
+pub async fn clone_document(
+    app: AppHandle,
+    id: i64,
+    options: Option<CloneOptions>,
+) -> CmdResult<i64> {
+    let options = options.unwrap_or_default();
+    let pool = db::pool(&app).await?;
+
+    let source: Document = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+    let attachments: Vec<CloneAttachment> = if options.attachments {
+        sqlx::query_as(
+            "SELECT id, filename, filepath, filetype, filesize, sort_order, created_at
+             FROM attachments WHERE document_id = ? ORDER BY sort_order ASC, id ASC",
+        )
+        .bind(id)
+        .fetch_all(&pool)
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let mut tx = pool.begin().await?;
+    let (created_at, updated_at) = if options.reset_timestamps {
+        (None, None)
+    } else {
+        (Some(&source.created_at), Some(&source.updated_at))
+    };
+    let document_id = sqlx::query(
+        "INSERT INTO documents (title, description, text_content, category_id, lang, created_at,
+           updated_at)
+         SELECT ?, description, text_content, category_id, lang,
+           COALESCE(?, CURRENT_TIMESTAMP), COALESCE(?, CURRENT_TIMESTAMP)
+         FROM documents WHERE id = ?",
+    )
+    .bind(format!("{} (copy)", source.title))
+    .bind(created_at)
+    .bind(updated_at)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+
+    if options.tags {
+        sqlx::query(
+            "INSERT INTO document_tags (document_id, tag_id)
+             SELECT ?, tag_id FROM document_tags WHERE document_id = ?",
+        )
+        .bind(document_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    if options.fields {
+        sqlx::query(
+            "INSERT INTO document_fields (document_id, key, value)
+             SELECT ?, key, value FROM document_fields WHERE document_id = ?",
+        )
+        .bind(document_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Files made so far, removed again if anything fails
+    let mut linked = Vec::new();
+    let copied = clone_attachments(&app, &mut tx, document_id, &attachments, &mut linked).await;
+    let committed = match copied {
+        Ok(()) => tx.commit().await.map_err(AppError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = committed {
+        for path in &linked {
+            let _ = fs::remove_file(path);
+        }
+        if let Ok(dir) = db::attachments_dir(&app, document_id) {
+            let _ = fs::remove_dir(dir);
+        }
+        return Err(e);
+    }
+
+    // The copy links wherever the original does
+    related::refresh_related_index(&pool).await?;
+
+    let _ = app.emit("document_created", DocumentEvent { document_id });
+
+    Ok(document_id)
+}
+
+async fn clone_attachments(
+    app: &AppHandle,
+    tx: &mut Transaction<'_, Sqlite>,
+    document_id: i64,
+    attachments: &[CloneAttachment],
+    linked: &mut Vec<std::path::PathBuf>,
+) -> CmdResult<()> {
+    for attachment in attachments {
+        let source = std::path::Path::new(&attachment.filepath);
+        if !source.is_file() {
+            log::warn!(
+                "Not cloning attachment {}: its file is missing",
+                attachment.id
+            );
+            continue;
+        }
+        let dest = attachments::link_into_store(app, document_id, source, &attachment.filename)?;
+        linked.push(dest.clone());
+
+        let attachment_id = sqlx::query(
+            "INSERT INTO attachments
+               (document_id, filename, filepath, filetype, filesize, sort_order, created_at, phash,
+                ocr_text, ocr_lang, alt_text, alt_text_source)
+             SELECT ?, ?, ?, ?, ?, ?, ?, phash, ocr_text, ocr_lang, alt_text, alt_text_source
+             FROM attachments WHERE id = ?",
+        )
+        .bind(document_id)
+        .bind(&attachment.filename)
+        .bind(dest.to_string_lossy().to_string())
+        .bind(&attachment.filetype)
+        .bind(attachment.filesize)
+        .bind(attachment.sort_order)
+        .bind(&attachment.created_at)
+        .bind(attachment.id)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid();
+
+        sqlx::query(
+            "INSERT INTO attachment_annotations (attachment_id, layer)
+             SELECT ?, layer FROM attachment_annotations WHERE attachment_id = ?",
+        )
+        .bind(attachment_id)
+        .bind(attachment.id)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO attachment_provenance
+               (attachment_id, original_path, created_at, modified_at, accessed_at, size,
+                readonly, mode, imported_at)
+             SELECT ?, original_path, created_at, modified_at, accessed_at, size, readonly, mode,
+               imported_at
+             FROM attachment_provenance WHERE attachment_id = ?",
+        )
+        .bind(attachment_id)
+        .bind(attachment.id)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Permanently deletes a document with its tags, versions and attachment
+/// files.
+#[tauri::command]
+pub async fn delete_document(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    id: i64,
+    confirmed: Option<bool>,
+) -> CmdResult<()> {
+    store.get().require_confirmation(confirmed)?;
+
+    let pool = db::pool(&app).await?;
+
+    // Attachments, tags and versions cascade with the row
+    let result = sqlx::query("DELETE FROM documents WHERE id = ?")
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    remove_attachment_files(&app, &[id]);
+    let _ = app.emit("document_deleted", DocumentEvent { document_id: id });
+
+    Ok(())
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct DocumentsEvent {
+    pub document_ids: Vec<i64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct EmptyDocument {
+    pub id: i64,
+    pub title: String,
+    pub category_id: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct EmptyCandidate {
+    #[sqlx(flatten)]
+    document: EmptyDocument,
+    text_content: Option<String>,
+    versions: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EmptyCleanup {
+    pub dry_run: bool,
+    pub count: usize,
+    pub documents: Vec<EmptyDocument>,
+}
+
+/// Finds documents that were created and never filled in: no body text,
+/// description, attachments or tags, and no earlier version with text.
+/// Unless `dry_run`, they are moved to `deleted_documents`, where
+/// `restore_document` can bring them back.
+#[tauri::command]
+pub async fn cleanup_empty_documents(app: AppHandle, dry_run: bool) -> CmdResult<EmptyCleanup> {
+    let pool = db::pool(&app).await?;
+
+    let mut tx = pool.begin().await?;
+    let candidates: Vec<EmptyCandidate> = sqlx::query_as(
+        "SELECT d.id, d.title, d.category_id, d.created_at, d.text_content,
+               (SELECT GROUP_CONCAT(v.text_content, ' ') FROM document_versions v
+                WHERE v.document_id = d.id) AS versions
+             FROM documents d
+             WHERE TRIM(COALESCE(d.description, '')) = ''
+               AND NOT EXISTS (SELECT 1 FROM attachments a WHERE a.document_id = d.id)
+               AND NOT EXISTS (SELECT 1 FROM document_tags dt WHERE dt.document_id = d.id)
+             ORDER BY d.id",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // The editor saves empty bodies as markup like `<p></p>`
+    let is_blank = |html: &Option<String>| {
+        html.as_deref()
+            .map_or(true, |html| html::strip_tags(html).trim().is_empty())
+    };
+    let documents: Vec<EmptyDocument> = candidates
+        .into_iter()
+        .filter(|candidate| is_blank(&candidate.text_content) && is_blank(&candidate.versions))
+        .map(|candidate| candidate.document)
+        .collect();
+
+    let report = EmptyCleanup {
+        dry_run,
+        count: documents.len(),
+        documents,
+    };
+    if dry_run || report.documents.is_empty() {
+        return Ok(report);
+    }
+
+    let ids: Vec<i64> = report.documents.iter().map(|d| d.id).collect();
+    trash_documents(&mut tx, &ids).await?;
+    tx.commit().await?;
+
+    let _ = app.emit(
+        "documents_deleted",
+        DocumentsEvent {
+            document_ids: report.documents.iter().map(|d| d.id).collect(),
+        },
+    );
+
+    Ok(report)
+}
+
+/// Moves documents to `deleted_documents` with their tags and attachment
+/// rows; the attachment files stay where they are.
+pub(crate) async fn trash_documents(
+    tx: &mut Transaction<'_, Sqlite>,
+    ids: &[i64],
+) -> CmdResult<()> {
+    for id in ids {
+        sqlx::query(
+            "INSERT OR REPLACE INTO deleted_documents
+               (id, title, description, text_content, category_id, created_at, updated_at,
+                tags, attachments, link_references, fields, notes)
+             SELECT d.id, d.title, d.description, d.text_content, d.category_id,
+               d.created_at, d.updated_at,
+               (SELECT json_group_array(t.name) FROM document_tags dt
+                JOIN tags t ON t.id = dt.tag_id WHERE dt.document_id = d.id),
+               (SELECT json_group_array(json_object(
+                  'filename', a.filename, 'filepath', a.filepath, 'filetype', a.filetype,
+                  'filesize', a.filesize, 'created_at', a.created_at,
+                  'sort_order', a.sort_order, 'phash', a.phash,
+                  'ocr_text', a.ocr_text, 'ocr_lang', a.ocr_lang,
+                  'alt_text', a.alt_text, 'alt_text_source', a.alt_text_source,
+                  'replaced_at', a.replaced_at,
+                  'versions', json((SELECT json_group_array(json_object(
+                                 'filename', v.filename, 'filepath', v.filepath,
+                                 'filetype', v.filetype, 'filesize', v.filesize,
+                                 'phash', v.phash, 'ocr_text', v.ocr_text,
+                                 'ocr_lang', v.ocr_lang, 'alt_text', v.alt_text,
+                                 'alt_text_source', v.alt_text_source,
+                                 'created_at', v.created_at, 'replaced_at', v.replaced_at))
+                               FROM attachment_versions v WHERE v.attachment_id = a.id)),
+                  'annotations', (SELECT layer FROM attachment_annotations
+                                  WHERE attachment_id = a.id),
+                  'provenance', json((SELECT json_object(
+                                   'original_path', p.original_path,
+                                   'created_at', p.created_at, 'modified_at', p.modified_at,
+                                   'accessed_at', p.accessed_at, 'size', p.size,
+                                   'readonly', p.readonly, 'mode', p.mode,
+                                   'imported_at', p.imported_at)
+                                 FROM attachment_provenance p WHERE p.attachment_id = a.id))))
+                FROM attachments a WHERE a.document_id = d.id),
+               (SELECT json_group_array(json_object(
+                  'url', r.url, 'title', r.title, 'created_at', r.created_at))
+                FROM link_references r WHERE r.document_id = d.id),
+               (SELECT json_group_object(f.key, f.value)
+                FROM document_fields f WHERE f.document_id = d.id),
+               (SELECT json_group_array(json_object(
+                  'text', n.text, 'created_at', n.created_at, 'updated_at', n.updated_at))
+                FROM document_notes n WHERE n.document_id = d.id)
+             FROM documents d WHERE d.id = ?",
+        )
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query("DELETE FROM documents WHERE id = ?")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Brings back a trashed document under its old id, with its tags,
+/// attachments, link references, custom fields and notes. It goes
+/// uncategorized if its category is gone.
+#[tauri::command]
+pub async fn restore_document(app: AppHandle, id: i64) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let mut tx = pool.begin().await?;
+    if !restore_trashed(&mut tx, id).await? {
+        return Err(AppError::NotFound("Deleted document not found".to_string()));
+    }
+    tx.commit().await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(())
+}
+
+// Whether there was a trashed document with the id to restore
+async fn restore_trashed(tx: &mut Transaction<'_, Sqlite>, id: i64) -> CmdResult<bool> {
+    let restored = sqlx::query(
+        "INSERT INTO documents (id, title, description, text_content, category_id, created_at, updated_at)
+         SELECT id, title, description, text_content,
+           (SELECT c.id FROM categories c WHERE c.id = category_id),
+           created_at, updated_at
+         FROM deleted_documents WHERE id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if restored == 0 {
+        return Ok(false);
+    }
+    sqlx::query(
+        "INSERT OR IGNORE INTO tags (name)
+         SELECT j.value FROM deleted_documents dd, json_each(COALESCE(dd.tags, '[]')) j
+         WHERE dd.id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+         SELECT dd.id, t.id FROM deleted_documents dd, json_each(COALESCE(dd.tags, '[]')) j
+         JOIN tags t ON t.name = j.value
+         WHERE dd.id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO attachments
+           (document_id, filename, filepath, filetype, filesize, created_at, sort_order, phash,
+            ocr_text, ocr_lang, alt_text, alt_text_source, replaced_at)
+         SELECT dd.id, json_extract(j.value, '$.filename'), json_extract(j.value, '$.filepath'),
+           json_extract(j.value, '$.filetype'), json_extract(j.value, '$.filesize'),
+           json_extract(j.value, '$.created_at'), json_extract(j.value, '$.sort_order'),
+           json_extract(j.value, '$.phash'), json_extract(j.value, '$.ocr_text'),
+           json_extract(j.value, '$.ocr_lang'), json_extract(j.value, '$.alt_text'),
+           json_extract(j.value, '$.alt_text_source'), json_extract(j.value, '$.replaced_at')
+         FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j
+         WHERE dd.id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    // Matched up with the restored attachments by their path on disk
+    sqlx::query(
+        "INSERT INTO attachment_versions
+           (attachment_id, filename, filepath, filetype, filesize, phash, ocr_text, ocr_lang,
+            alt_text, alt_text_source, created_at, replaced_at)
+         SELECT a.id, json_extract(v.value, '$.filename'), json_extract(v.value, '$.filepath'),
+           json_extract(v.value, '$.filetype'), json_extract(v.value, '$.filesize'),
+           json_extract(v.value, '$.phash'), json_extract(v.value, '$.ocr_text'),
+           json_extract(v.value, '$.ocr_lang'), json_extract(v.value, '$.alt_text'),
+           json_extract(v.value, '$.alt_text_source'), json_extract(v.value, '$.created_at'),
+           json_extract(v.value, '$.replaced_at')
+         FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j
+         JOIN attachments a ON a.document_id = dd.id
+           AND a.filepath = json_extract(j.value, '$.filepath'),
+         json_each(COALESCE(json_extract(j.value, '$.versions'), '[]')) v
+         WHERE dd.id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO attachment_annotations (attachment_id, layer)
+         SELECT a.id, json_extract(j.value, '$.annotations')
+         FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j
+         JOIN attachments a ON a.document_id = dd.id
+           AND a.filepath = json_extract(j.value, '$.filepath')
+         WHERE dd.id = ? AND json_extract(j.value, '$.annotations') IS NOT NULL",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO attachment_provenance
+           (attachment_id, original_path, created_at, modified_at, accessed_at, size, readonly,
+            mode, imported_at)
+         SELECT a.id, json_extract(j.value, '$.provenance.original_path'),
+           json_extract(j.value, '$.provenance.created_at'),
+           json_extract(j.value, '$.provenance.modified_at'),
+           json_extract(j.value, '$.provenance.accessed_at'),
+           json_extract(j.value, '$.provenance.size'),
+           json_extract(j.value, '$.provenance.readonly'),
+           json_extract(j.value, '$.provenance.mode'),
+           json_extract(j.value, '$.provenance.imported_at')
+         FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j
+         JOIN attachments a ON a.document_id = dd.id
+           AND a.filepath = json_extract(j.value, '$.filepath')
+         WHERE dd.id = ? AND json_extract(j.value, '$.provenance.original_path') IS NOT NULL",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO link_references (document_id, url, title, created_at)
+         SELECT dd.id, json_extract(j.value, '$.url'), json_extract(j.value, '$.title'),
+           json_extract(j.value, '$.created_at')
+         FROM deleted_documents dd, json_each(COALESCE(dd.link_references, '[]')) j
+         WHERE dd.id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO document_fields (document_id, key, value)
+         SELECT dd.id, j.key, j.value
+         FROM deleted_documents dd, json_each(COALESCE(dd.fields, '{}')) j
+         WHERE dd.id = ?",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO document_notes (document_id, text, created_at, updated_at)
+         SELECT dd.id, json_extract(j.value, '$.text'), json_extract(j.value, '$.created_at'),
+           json_extract(j.value, '$.updated_at')
+         FROM deleted_documents dd, json_each(COALESCE(dd.notes, '[]')) j
+         WHERE dd.id = ?
+         ORDER BY j.key",
+    )
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query("DELETE FROM deleted_documents WHERE id = ?")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(true)
+}
+
+/// Selects trashed documents; every one that matches all the fields set.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TrashFilter {
+    /// The category the document was in when trashed.
+    pub category_id: Option<i64>,
+    /// A date or RFC 3339 time; documents trashed at or after it.
+    pub deleted_after: Option<String>,
+    /// A regex the title has to match, ignoring case.
+    pub title_pattern: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TrashBatch {
+    pub count: usize,
+    pub document_ids: Vec<i64>,
+}
+
+/// Restores every trashed document matching `filter` in one transaction,
+/// as `restore_document` would each, and emits `documents_restored`.
+#[tauri::command]
+pub async fn restore_trash(app: AppHandle, filter: TrashFilter) -> CmdResult<TrashBatch> {
+    let pool = db::pool(&app).await?;
+
+    let mut tx = pool.begin().await?;
+    let ids = matching_trash(&mut tx, &filter).await?;
+    for id in &ids {
+        restore_trashed(&mut tx, *id).await?;
+    }
+    tx.commit().await?;
+
+    if !ids.is_empty() {
+        let _ = app.emit(
+            "documents_restored",
+            DocumentsEvent {
+                document_ids: ids.clone(),
+            },
+        );
+    }
+
+    Ok(TrashBatch {
+        count: ids.len(),
+        document_ids: ids,
+    })
+}
+
+/// Permanently deletes the trashed documents matching `filter`, with
+/// their attachment files, and emits `trash_purged`. Needs confirmation.
+#[tauri::command]
+pub async fn purge_trash_matching(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    filter: TrashFilter,
+    confirmed: Option<bool>,
+) -> CmdResult<TrashBatch> {
+    store.get().require_confirmation(confirmed)?;
+    let pool = db::pool(&app).await?;
+
+    let mut tx = pool.begin().await?;
+    let ids = matching_trash(&mut tx, &filter).await?;
+    let mut files: Vec<String> = Vec::new();
+    for id in &ids {
+        let paths: Vec<(Option<String>,)> = sqlx::query_as(
+            "SELECT json_extract(j.value, '$.filepath')
+             FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j
+             WHERE dd.id = ?
+             UNION
+             SELECT json_extract(v.value, '$.filepath')
+             FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j,
+               json_each(COALESCE(json_extract(j.value, '$.versions'), '[]')) v
+             WHERE dd.id = ?",
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        files.extend(paths.into_iter().filter_map(|(path,)| path));
+        sqlx::query("DELETE FROM deleted_documents WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    // File by file, since a document created since may have reused the
+    // id and with it the folder
+    for file in &files {
+        let path = std::path::Path::new(file);
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove {}: {}", file, e);
+            }
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+
+    if !ids.is_empty() {
+        let _ = app.emit(
+            "trash_purged",
+            DocumentsEvent {
+                document_ids: ids.clone(),
+            },
+        );
+    }
+
+    Ok(TrashBatch {
+        count: ids.len(),
+        document_ids: ids,
+    })
+}
+
+/// Permanently deletes everything in the trash, as `purge_trash_matching`
+/// with an empty filter. Needs confirmation.
+#[tauri::command]
+pub async fn empty_trash(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    confirmed: Option<bool>,
+) -> CmdResult<TrashBatch> {
+    purge_trash_matching(app, store, TrashFilter::default(), confirmed).await
+}
+
+async fn matching_trash(
+    tx: &mut Transaction<'_, Sqlite>,
+    filter: &TrashFilter,
+) -> CmdResult<Vec<i64>> {
+    let title = filter
+        .title_pattern
+        .as_deref()
+        .map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .size_limit(MAX_PATTERN_SIZE)
+                .build()
+                .map_err(|e| AppError::Validation(format!("Invalid title pattern: {}", e)))
+        })
+        .transpose()?;
+    let deleted_after = filter
+        .deleted_after
+        .as_deref()
+        .map(|after| {
+            stored_timestamp(after).ok_or_else(|| {
+                AppError::Validation(format!("Invalid deleted_after date: {}", after))
+            })
+        })
+        .transpose()?;
+
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, title FROM deleted_documents
+         WHERE (?1 IS NULL OR category_id = ?1) AND (?2 IS NULL OR deleted_at >= ?2)
+         ORDER BY id ASC",
+    )
+    .bind(filter.category_id)
+    .bind(deleted_after)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .filter(|(_, name)| title.as_ref().map_or(true, |regex| regex.is_match(name)))
+        .map(|(id, _)| id)
+        .collect())
+}
+
+// A date or RFC 3339 time as a UTC timestamp in SQLite's format
+fn stored_timestamp(value: &str) -> Option<String> {
+    const STORED: &str = "%Y-%m-%d %H:%M:%S";
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc).format(STORED).to_string());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| format!("{} 00:00:00", date))
+}
+
+// Best effort once the rows are gone; a leftover file is only wasted space
+pub(crate) fn remove_attachment_files(app: &AppHandle, document_ids: &[i64]) {
+    for id in document_ids {
+        if let Ok(dir) = db::attachments_dir(app, *id) {
+            if dir.exists() {
+                if let Err(e) = fs::remove_dir_all(&dir) {
+                    log::warn!("Failed to remove {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AttachmentDump {
+    id: i64,
+    filename: String,
+    filetype: String,
+    filesize: Option<i64>,
+    sha256: Option<String>,
+    file_exists: bool,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct VersionDump {
+    id: i64,
+    title: String,
+    description: Option<String>,
+    text_content: Option<String>,
+    #[sqlx(skip)]
+    body_length: usize,
+    created_at: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct FieldDump {
+    key: String,
+    value: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct ReferenceDump {
+    id: i64,
+    url: String,
+    title: Option<String>,
+    created_at: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct NoteDump {
+    id: i64,
+    text: Option<String>,
+    #[sqlx(skip)]
+    text_length: usize,
+    created_at: String,
+    updated_at: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DocumentDump {
+    document: Document,
+    body_redacted: bool,
+    body_length: usize,
+    tags: Vec<String>,
+    attachments: Vec<AttachmentDump>,
+    /// Oldest first; the last one is the current content.
+    versions: Vec<VersionDump>,
+    fields: Vec<FieldDump>,
+    references: Vec<ReferenceDump>,
+    /// Documents this one links to, and those linking to it.
+    links_to: Vec<i64>,
+    linked_from: Vec<i64>,
+    notes: Vec<NoteDump>,
+}
+
+fn char_count(text: Option<&str>) -> usize {
+    text.map_or(0, |text| text.chars().count())
+}
+
+/// Dumps the full state of a document as JSON for support: its row, tags,
+/// attachments, versions, custom fields, references, links and notes.
+/// With `redact_body` the description, body, version texts and notes are
+/// left out, only their size is kept.
+#[tauri::command]
+pub async fn export_document_json(app: AppHandle, id: i64, redact_body: bool) -> CmdResult<String> {
+    let pool = db::pool(&app).await?;
+
+    let mut document: Document = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let tags: Vec<(String,)> = sqlx::query_as(
+        "SELECT t.name FROM tags t
+         JOIN document_tags dt ON dt.tag_id = t.id
+         WHERE dt.document_id = ?
+         ORDER BY t.name ASC",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let attachments: Vec<Attachment> = sqlx::query_as(
+        "SELECT * FROM attachments WHERE document_id = ? ORDER BY sort_order ASC, id ASC",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let attachments = tauri::async_runtime::spawn_blocking(move || {
+        attachments
+            .into_iter()
+            .map(|attachment| {
+                let sha256 = fs::read(&attachment.filepath)
+                    .ok()
+                    .map(|bytes| hex::encode(Sha256::digest(bytes)));
+                AttachmentDump {
+                    id: attachment.id,
+                    filename: attachment.filename,
+                    filetype: attachment.filetype,
+                    filesize: attachment.filesize,
+                    file_exists: sha256.is_some(),
+                    sha256,
+                }
+            })
+            .collect()
+    })
+    .await?;
+
+    let mut versions: Vec<VersionDump> = sqlx::query_as(
+        "SELECT id, title, description, text_content, created_at FROM document_versions
+         WHERE document_id = ? ORDER BY id ASC",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let fields: Vec<FieldDump> =
+        sqlx::query_as("SELECT key, value FROM document_fields WHERE document_id = ? ORDER BY key")
+            .bind(id)
+            .fetch_all(&pool)
+            .await?;
+
+    let references: Vec<ReferenceDump> = sqlx::query_as(
+        "SELECT id, url, title, created_at FROM link_references
+         WHERE document_id = ? ORDER BY id ASC",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let links: Vec<(i64, bool)> = sqlx::query_as(
+        "SELECT target_id, 1 FROM document_links WHERE source_id = ?1
+         UNION ALL SELECT source_id, 0 FROM document_links WHERE target_id = ?1
+         ORDER BY 1",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let mut notes: Vec<NoteDump> = sqlx::query_as(
+        "SELECT id, text, created_at, updated_at FROM document_notes
+         WHERE document_id = ? ORDER BY id ASC",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let body_length = char_count(document.text_content.as_deref());
+    for version in &mut versions {
+        version.body_length = char_count(version.text_content.as_deref());
+    }
+    for note in &mut notes {
+        note.text_length = char_count(note.text.as_deref());
+    }
+    if redact_body {
+        document.description = None;
+        document.text_content = None;
+        for version in &mut versions {
+            version.description = None;
+            version.text_content = None;
+        }
+        for note in &mut notes {
+            note.text = None;
+        }
+    }
+
+    let (links_to, linked_from): (Vec<_>, Vec<_>) =
+        links.into_iter().partition(|(_, outgoing)| *outgoing);
+    let dump = DocumentDump {
+        document,
+        body_redacted: redact_body,
+        body_length,
+        tags: tags.into_iter().map(|(name,)| name).collect(),
+        attachments,
+        versions,
+        fields,
+        references,
+        links_to: links_to.into_iter().map(|(id, _)| id).collect(),
+        linked_from: linked_from.into_iter().map(|(id, _)| id).collect(),
+        notes,
+    };
+
+    serde_json::to_string_pretty(&dump).map_err(AppError::from)
+}
+
+// Compiled size cap, so a pathological pattern fails instead of eating
+// memory; the regex crate itself always matches in linear time
+pub(crate) const MAX_PATTERN_SIZE: usize = 1 << 20;
+const MAX_PREVIEWS: usize = 3;
+const PREVIEW_CONTEXT: usize = 30;
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReplaceScope {
+    All,
+    /// A category and its subcategories.
+    Category {
+        id: i64,
+    },
+    Tag {
+        name: String,
+    },
+}
+
+#[derive(Serialize)]
+pub struct ReplaceMatch {
+    pub document_id: i64,
+    pub title: String,
+    pub occurrences: usize,
+    /// The first few replacements with some text around them, as they
+    /// would read afterwards.
+    pub previews: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkReplaceReport {
+    pub dry_run: bool,
+    pub documents_changed: usize,
+    pub occurrences: usize,
+    pub matches: Vec<ReplaceMatch>,
+}
+
+/// Regex find and replace over document bodies in `scope`. Only text is
+/// searched, never the markup, and `replacement` may refer to groups as
+/// `$1` or `$name`. Changes are applied in one transaction, each modified
+/// document getting a new version.
+#[tauri::command]
+pub async fn bulk_replace(
+    app: AppHandle,
+    pattern: String,
+    replacement: String,
+    scope: ReplaceScope,
+    dry_run: bool,
+) -> CmdResult<BulkReplaceReport> {
+    let regex = RegexBuilder::new(&pattern)
+        .size_limit(MAX_PATTERN_SIZE)
+        .build()
+        .map_err(|e| AppError::Validation(format!("Invalid pattern: {}", e)))?;
+    if regex.is_match("") {
+        return Err(AppError::Validation(
+            "Pattern must not match empty text".to_string(),
+        ));
+    }
+
+    let pool = db::pool(&app).await?;
+    let settings = app.state::<SettingsStore>().get();
+
+    let mut tx = pool.begin().await?;
+    let documents: Vec<(i64, String, Option<String>)> = match &scope {
+        ReplaceScope::All => {
+            sqlx::query_as("SELECT id, title, text_content FROM documents")
+                .fetch_all(&mut *tx)
+                .await
+        }
+        ReplaceScope::Category { id } => {
+            sqlx::query_as(
+                "WITH RECURSIVE subtree(id) AS (
+               SELECT id FROM categories WHERE id = ?
+               UNION
+               SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id
+             )
+             SELECT d.id, d.title, d.text_content FROM documents d
+             JOIN subtree s ON d.category_id = s.id",
+            )
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await
+        }
+        ReplaceScope::Tag { name } => {
+            sqlx::query_as(
+                "SELECT d.id, d.title, d.text_content FROM documents d
+             JOIN document_tags dt ON dt.document_id = d.id
+             JOIN tags t ON t.id = dt.tag_id
+             WHERE t.name = ? COLLATE NOCASE",
+            )
+            .bind(name)
+            .fetch_all(&mut *tx)
+            .await
+        }
+    }?;
+
+    let mut oversized = Vec::new();
+    let mut report = BulkReplaceReport {
+        dry_run,
+        documents_changed: 0,
+        occurrences: 0,
+        matches: Vec::new(),
+    };
+    for (id, title, body) in documents {
+        let Some(body) = body else {
+            continue;
+        };
+        if toc::is_generated(&mut *tx, id).await? {
+            continue;
+        }
+
+        let mut occurrences = 0;
+        let mut previews = Vec::new();
+        let replaced = html::map_text(&body, |text| {
+            for captures in regex.captures_iter(text) {
+                occurrences += 1;
+                if previews.len() < MAX_PREVIEWS {
+                    previews.push(preview(text, &captures, &replacement));
+                }
+            }
+            regex.replace_all(text, replacement.as_str()).into_owned()
+        });
+        if occurrences == 0 {
+            continue;
+        }
+
+        // A replacement that grows a body past a strict limit fails the batch
+        let size = check_body_size(&settings, &replaced)?;
+        if !dry_run && replaced != body {
+            oversized.extend(size.over_limit.then_some((id, size)));
+            sqlx::query(
+                "UPDATE documents SET text_content = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+            .bind(&replaced)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        report.documents_changed += 1;
+        report.occurrences += occurrences;
+        report.matches.push(ReplaceMatch {
+            document_id: id,
+            title,
+            occurrences,
+            previews,
+        });
+    }
+
+    if dry_run {
+        return Ok(report);
+    }
+    tx.commit().await?;
+
+    for found in &report.matches {
+        let _ = app.emit(
+            "document_updated",
+            DocumentEvent {
+                document_id: found.document_id,
+            },
+        );
+    }
+    for (document_id, size) in oversized {
+        warn_if_body_too_large(&app, document_id, size);
+    }
+
+    Ok(report)
+}
+
+fn preview(text: &str, captures: &Captures, replacement: &str) -> String {
+    let found = captures.get(0).expect("group 0 is the whole match");
+    let mut replaced = String::new();
+    captures.expand(replacement, &mut replaced);
+
+    let before: String = text[..found.start()]
+        .chars()
+        .rev()
+        .take(PREVIEW_CONTEXT)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let after: String = text[found.end()..].chars().take(PREVIEW_CONTEXT).collect();
+    format!("…{}{}{}…", before, replaced, after)
+}
+
+// Larger images are left out rather than bloating the clipboard
+pub(crate) const MAX_INLINE_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct ClipboardCopy {
+    /// `false` when the clipboard only took the plain text fallback.
+    pub rich: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct InlineImage {
+    #[sqlx(flatten)]
+    attachment: Attachment,
+    /// From `describe_image`; the filename stands in without one.
+    alt_text: Option<String>,
+}
+
+/// Copies a document to the clipboard as HTML, with its image attachments
+/// inlined under their alt text and the export stylesheet if one is set, and as plain text for
+/// apps that don't take HTML.
+#[tauri::command]
+pub async fn copy_document_as_html(app: AppHandle, id: i64) -> CmdResult<ClipboardCopy> {
+    let pool = db::pool(&app).await?;
+
+    let document: Document = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let attachments: Vec<InlineImage> = sqlx::query_as(
+        "SELECT * FROM attachments WHERE document_id = ? AND filetype LIKE 'image/%'
+         ORDER BY sort_order ASC, id ASC",
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await?;
+
+    let markdown_options = app.state::<SettingsStore>().get().markdown;
+    let stylesheet = settings::export_stylesheet(&app)?;
+    let (markup, plain) = tauri::async_runtime::spawn_blocking(move || {
+        // Editor bodies are HTML already and go in as they are
+        let body = document.text_content.unwrap_or_default();
+        let body = markdown::body_html(&body, markdown_options).into_owned();
+        let mut markup = stylesheet
+            .map(|css| format!("<style>\n{}\n</style>\n", css))
+            .unwrap_or_default();
+        markup.push_str(&format!(
+            "<h1>{}</h1>\n{}",
+            html::escape(&document.title),
+            body
+        ));
+        for InlineImage {
+            attachment,
+            alt_text,
+        } in attachments
+        {
+            let small_enough = fs::metadata(&attachment.filepath)
+                .is_ok_and(|meta| meta.len() <= MAX_INLINE_IMAGE_BYTES);
+            if !small_enough {
+                continue;
+            }
+            let Ok(bytes) = fs::read(&attachment.filepath) else {
+                continue;
+            };
+            markup.push_str(&format!(
+                "\n<p><img src=\"data:{};base64,{}\" alt=\"{}\"></p>",
+                attachment.filetype,
+                base64::engine::general_purpose::STANDARD.encode(bytes),
+                html::escape(alt_text.as_deref().unwrap_or(&attachment.filename)),
+            ));
+        }
+
+        let mut paragraphs = vec![document.title];
+        paragraphs.extend(html::to_paragraphs(&body));
+        (markup, paragraphs.join("\n\n"))
+    })
+    .await?;
+
+    let clipboard = app.clipboard();
+    if let Err(e) = clipboard.write_html(markup, Some(plain.clone())) {
+        log::warn!("Falling back to plain text copy: {}", e);
+        clipboard.write_text(plain)?;
+        return Ok(ClipboardCopy { rich: false });
+    }
+
+    Ok(ClipboardCopy { rich: true })
+}
+
+// Pixels per QR module, enough to print at a few centimetres
+const QR_SCALE: u32 = 8;
+
+/// Writes a PNG QR code of the document's `andoarchive://` link to
+/// `dest_path`, e.g. to stick on the paper original. Returns the path.
+#[tauri::command]
+pub async fn document_qr(app: AppHandle, id: i64, dest_path: String) -> CmdResult<String> {
+    let pool = db::pool(&app).await?;
+
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let code =
+        QrCode::encode(deep_link::document_link(id).as_bytes()).map_err(AppError::Validation)?;
+    code.to_image(QR_SCALE)
+        .save_with_format(&dest_path, ImageFormat::Png)?;
+
+    Ok(dest_path)
+}
+
+// Below this a stored guess would more likely be wrong than useful
+const MIN_LANGUAGE_CONFIDENCE: f64 = 0.5;
+
+#[tauri::command]
+pub fn detect_language(text: String) -> LanguageGuess {
+    language::detect(&text)
+}
+
+/// Detects and stores the language of a document's body when none is set
+/// yet. Returns the stored language, if any.
+#[tauri::command]
+pub async fn detect_document_language(app: AppHandle, id: i64) -> CmdResult<Option<String>> {
+    let pool = db::pool(&app).await?;
+
+    let (lang, body): (Option<String>, Option<String>) =
+        sqlx::query_as("SELECT lang, text_content FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+    if lang.is_some() {
+        return Ok(lang);
+    }
+
+    let guess = language::detect(&html::strip_tags(body.as_deref().unwrap_or_default()));
+    if guess.confidence < MIN_LANGUAGE_CONFIDENCE {
+        return Ok(None);
+    }
+
+    sqlx::query("UPDATE documents SET lang = ? WHERE id = ? AND lang IS NULL")
+        .bind(&guess.language)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(Some(guess.language))
+}
+
+/// Overrides the stored language of a document; `None` clears it so it is
+/// detected again.
+#[tauri::command]
+pub async fn set_document_language(app: AppHandle, id: i64, lang: Option<String>) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let lang = lang
+        .map(|lang| lang.trim().to_lowercase())
+        .filter(|lang| !lang.is_empty());
+    let result = sqlx::query("UPDATE documents SET lang = ? WHERE id = ?")
+        .bind(lang)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(())
+}
+
+/// Locks or unlocks a document. A locked document's body, and every
+/// stored version of it, stays out of lists, search results, snippets and
+/// the search indexes until it is unlocked; its title and description are
+/// still searchable.
+#[tauri::command]
+pub async fn set_document_locked(app: AppHandle, id: i64, locked: bool) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    if !document_exists(&app, id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+    let result = sqlx::query("UPDATE documents SET locked = ? WHERE id = ? AND locked != ?")
+        .bind(locked)
+        .bind(id)
+        .bind(locked)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() > 0 {
+        let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+    }
+
+    Ok(())
+}
+
+/// Number of locked documents, for the lock badge.
+#[tauri::command]
+pub async fn locked_document_count(app: AppHandle) -> CmdResult<i64> {
+    let pool = db::pool(&app).await?;
+
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents WHERE locked")
+        .fetch_one(&pool)
+        .await?;
+    Ok(count)
+}
+
+const DEFAULT_SUMMARY_SENTENCES: u32 = 3;
+const MAX_SUMMARY_SENTENCES: u32 = 20;
+// Share of the body length that has to change before a stored summary is
+// considered stale
+const SUMMARY_STALE_RATIO: f64 = 0.1;
+
+/// Extractive summary of the document's body for list previews, stored in
+/// `summary`. The stored one is returned until the body length changes
+/// significantly, or `force` is set; `max_sentences` only applies when a
+/// summary is computed. Short bodies are their own summary.
+#[tauri::command]
+pub async fn summarize_document(
+    app: AppHandle,
+    id: i64,
+    max_sentences: Option<u32>,
+    force: Option<bool>,
+) -> CmdResult<String> {
+    let pool = db::pool(&app).await?;
+
+    let (body, summary, source_length): (Option<String>, Option<String>, Option<i64>) =
+        sqlx::query_as(
+            "SELECT text_content, summary, summary_source_length FROM documents WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let body = body.unwrap_or_default();
+    let length = body.chars().count() as i64;
+    if let (Some(summary), Some(source_length), false) =
+        (summary, source_length, force.unwrap_or(false))
+    {
+        let changed = (length - source_length).abs() as f64;
+        if changed <= source_length as f64 * SUMMARY_STALE_RATIO {
+            return Ok(summary);
+        }
+    }
+
+    let max_sentences = max_sentences
+        .unwrap_or(DEFAULT_SUMMARY_SENTENCES)
+        .clamp(1, MAX_SUMMARY_SENTENCES) as usize;
+    let summary = tauri::async_runtime::spawn_blocking(move || {
+        summary::summarize(&html::to_paragraphs(&body), max_sentences)
+    })
+    .await?;
+
+    sqlx::query("UPDATE documents SET summary = ?, summary_source_length = ? WHERE id = ?")
+        .bind(&summary)
+        .bind(length)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(summary)
+}
+
+#[derive(Clone, Serialize)]
+pub struct BodySize {
+    pub chars: usize,
+    /// `max_body_chars`, if one is set.
+    pub limit: Option<usize>,
+    pub over_limit: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct BodySizeWarning {
+    document_id: i64,
+    #[serde(flatten)]
+    size: BodySize,
+}
+
+/// Whether a body can be saved as it is, for creating and updating
+/// documents to ask first. Past `max_body_chars` it fails with
+/// `BodyTooLarge` when `strict_body_limit` is on, and is flagged
+/// `over_limit` otherwise, so the UI can suggest splitting the document or
+/// attaching the content instead.
+#[tauri::command]
+pub fn check_document_body(store: State<'_, SettingsStore>, body: String) -> CmdResult<BodySize> {
+    check_body_size(&store.get(), &body)
+}
+
+pub(crate) fn check_body_size(settings: &Settings, body: &str) -> CmdResult<BodySize> {
+    let chars = body.chars().count();
+    let limit = settings.max_body_chars.map(|limit| limit as usize);
+    let over_limit = limit.is_some_and(|limit| chars > limit);
+    if let (true, Some(limit)) = (over_limit && settings.strict_body_limit, limit) {
+        return Err(AppError::BodyTooLarge {
+            limit,
+            actual: chars,
+        });
+    }
+    Ok(BodySize {
+        chars,
+        limit,
+        over_limit,
+    })
+}
+
+/// Emits `body_size_warning` for a body saved past the soft limit.
+pub(crate) fn warn_if_body_too_large(app: &AppHandle, document_id: i64, size: BodySize) {
+    if size.over_limit {
+        let _ = app.emit("body_size_warning", BodySizeWarning { document_id, size });
+    }
+}
+
+/// Markdown rendered to sanitized HTML exactly as exports render Markdown
+/// bodies, with the extensions enabled in settings, for live previews.
+#[tauri::command]
+pub async fn render_markdown(store: State<'_, SettingsStore>, text: String) -> CmdResult<String> {
+    let options = store.get().markdown;
+    tauri::async_runtime::spawn_blocking(move || markdown::to_html(&text, options))
+        .await
+        .map_err(AppError::from)
+}
+
+#[derive(Serialize)]
+pub struct SanitizeResult {
+    pub document_id: i64,
+    pub changed: bool,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// Cleans up a document body, typically pasted or imported HTML, keeping
+/// it as HTML or converting it to Markdown. The previous body stays
+/// available as a version.
+#[tauri::command]
+pub async fn sanitize_document(
+    app: AppHandle,
+    id: i64,
+    options: Option<SanitizeOptions>,
+) -> CmdResult<SanitizeResult> {
+    let pool = db::pool(&app).await?;
+    let options = options.unwrap_or_default();
+
+    let (body,): (Option<String>,) =
+        sqlx::query_as("SELECT text_content FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let mut results = sanitize_bodies(vec![(id, body.unwrap_or_default())], options).await?;
+    let sanitized = results.pop().expect("one body in, one result out");
+    if !sanitized.result.changed {
+        return Ok(sanitized.result);
+    }
+
+    sqlx::query(
+        "UPDATE documents SET text_content = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+    )
+    .bind(&sanitized.body)
+    .bind(id)
+    .execute(&pool)
+    .await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(sanitized.result)
+}
+
+#[derive(Serialize)]
+pub struct SanitizeReport {
+    pub documents_checked: usize,
+    pub documents_changed: usize,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// `sanitize_document` for every document in a category and its
+/// subcategories, in one transaction.
+#[tauri::command]
+pub async fn sanitize_category(
+    app: AppHandle,
+    category_id: i64,
+    options: Option<SanitizeOptions>,
+) -> CmdResult<SanitizeReport> {
+    let pool = db::pool(&app).await?;
+    let options = options.unwrap_or_default();
+
+    let mut tx = pool.begin().await?;
+    let documents: Vec<(i64, Option<String>)> = sqlx::query_as(
+        "WITH RECURSIVE subtree(id) AS (
+           SELECT id FROM categories WHERE id = ?
+           UNION
+           SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id
+         )
+         SELECT d.id, d.text_content FROM documents d
+         JOIN subtree s ON d.category_id = s.id",
+    )
+    .bind(category_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let documents = documents
+        .into_iter()
+        .map(|(id, body)| (id, body.unwrap_or_default()))
+        .collect();
+    let results = sanitize_bodies(documents, options).await?;
+
+    let mut report = SanitizeReport {
+        documents_checked: results.len(),
+        documents_changed: 0,
+        bytes_before: 0,
+        bytes_after: 0,
+    };
+    let mut changed = Vec::new();
+    for sanitized in results {
+        report.bytes_before += sanitized.result.bytes_before;
+        report.bytes_after += sanitized.result.bytes_after;
+        if !sanitized.result.changed {
+            continue;
+        }
+        sqlx::query(
+            "UPDATE documents SET text_content = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(&sanitized.body)
+        .bind(sanitized.result.document_id)
+        .execute(&mut *tx)
+        .await?;
+        report.documents_changed += 1;
+        changed.push(sanitized.result.document_id);
+    }
+    tx.commit().await?;
+
+    for document_id in changed {
+        let _ = app.emit("document_updated", DocumentEvent { document_id });
+    }
+
+    Ok(report)
+}
+
+struct Sanitized {
+    result: SanitizeResult,
+    body: String,
+}
+
+// Parsing is CPU-bound, so whole batches go to a blocking thread
+async fn sanitize_bodies(
+    documents: Vec<(i64, String)>,
+    options: SanitizeOptions,
+) -> CmdResult<Vec<Sanitized>> {
+    tauri::async_runtime::spawn_blocking(move || {
+        documents
+            .into_iter()
+            .map(|(document_id, body)| {
+                let cleaned = sanitize::sanitize(&body, options);
+                Sanitized {
+                    result: SanitizeResult {
+                        document_id,
+                        changed: cleaned != body,
+                        bytes_before: body.len(),
+                        bytes_after: cleaned.len(),
+                    },
+                    body: cleaned,
+                }
+            })
+            .collect()
+    })
+    .await
+    .map_err(AppError::from)
+}
+
+#[derive(Serialize)]
+pub struct EncodingPreview {
+    /// `title`, `description` or `body`.
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Serialize)]
+pub struct EncodingFix {
+    pub document_id: i64,
+    pub title: String,
+    /// The encoding the document's UTF-8 text had been misread as.
+    pub misread_as: &'static str,
+    /// Runs of garbled characters re-decoded.
+    pub segments: usize,
+    /// The first few fixes with some text around them.
+    pub previews: Vec<EncodingPreview>,
+}
+
+#[derive(Serialize)]
+pub struct EncodingReport {
+    pub dry_run: bool,
+    pub documents_changed: usize,
+    pub fixes: Vec<EncodingFix>,
+}
+
+/// Finds mojibake in a document, UTF-8 text that was decoded as
+/// Windows-1252 or Latin-1 on import, and re-decodes it. `None` when the
+/// text looks fine. With `dry_run` only the previews are returned;
+/// otherwise title, description and body are fixed, the previous text
+/// staying available as a version.
+#[tauri::command]
+pub async fn detect_and_fix_encoding(
+    app: AppHandle,
+    id: i64,
+    dry_run: bool,
+) -> CmdResult<Option<EncodingFix>> {
+    let report = fix_encodings(&app, vec![id], dry_run).await?;
+    if report.fixes.is_empty() && !document_exists(&app, id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+    Ok(report.fixes.into_iter().next())
+}
+
+/// `detect_and_fix_encoding` over many documents, e.g. the `document_ids`
+/// of an import report, applied in one transaction.
+#[tauri::command]
+pub async fn fix_encoding_batch(
+    app: AppHandle,
+    document_ids: Vec<i64>,
+    dry_run: bool,
+) -> CmdResult<EncodingReport> {
+    fix_encodings(&app, document_ids, dry_run).await
+}
+
+struct EncodingCandidate {
+    fix: EncodingFix,
+    title: String,
+    description: Option<String>,
+    body: Option<String>,
+}
+
+async fn fix_encodings(
+    app: &AppHandle,
+    document_ids: Vec<i64>,
+    dry_run: bool,
+) -> CmdResult<EncodingReport> {
+    let pool = db::pool(app).await?;
+
+    let mut tx = pool.begin().await?;
+    let mut documents = Vec::with_capacity(document_ids.len());
+    for id in document_ids {
+        let document: Option<Document> = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        documents.extend(document);
+    }
+
+    let candidates = tauri::async_runtime::spawn_blocking(move || {
+        documents
+            .into_iter()
+            .filter_map(encoding_candidate)
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    let mut report = EncodingReport {
+        dry_run,
+        documents_changed: candidates.len(),
+        fixes: Vec::with_capacity(candidates.len()),
+    };
+    for candidate in candidates {
+        if !dry_run {
+            sqlx::query(
+                "UPDATE documents SET title = ?, description = ?, text_content = ?,
+                 updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+            .bind(&candidate.title)
+            .bind(&candidate.description)
+            .bind(&candidate.body)
+            .bind(candidate.fix.document_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        report.fixes.push(candidate.fix);
+    }
+
+    if dry_run {
+        return Ok(report);
+    }
+    tx.commit().await?;
+
+    for fix in &report.fixes {
+        let _ = app.emit(
+            "document_updated",
+            DocumentEvent {
+                document_id: fix.document_id,
+            },
+        );
+    }
+
+    Ok(report)
+}
+
+// The repaired fields of a document with mojibake in any of them
+fn encoding_candidate(document: Document) -> Option<EncodingCandidate> {
+    let mut misread = None;
+    let mut segments = 0;
+    let mut previews = Vec::new();
+    let mut repair = |field: &'static str, text: &str| {
+        let repaired = encoding::repair(text, MAX_PREVIEWS - previews.len(), PREVIEW_CONTEXT)?;
+        if misread != Some(encoding::Misread::Windows1252) {
+            misread = Some(repaired.misread);
+        }
+        segments += repaired.segments;
+        previews.extend(
+            repaired
+                .previews
+                .into_iter()
+                .map(|(before, after)| EncodingPreview {
+                    field,
+                    before,
+                    after,
+                }),
+        );
+        Some(repaired.text)
+    };
+
+    let title = repair("title", &document.title);
+    let description = document
+        .description
+        .as_deref()
+        .and_then(|description| repair("description", description));
+    // Only the text between tags, so URLs and attributes stay as they are
+    let body = document.text_content.as_deref().and_then(|body| {
+        let mut changed = false;
+        let fixed = html::map_text(body, |text| match repair("body", text) {
+            Some(fixed) => {
+                changed = true;
+                fixed
+            }
+            None => text.to_string(),
+        });
+        changed.then_some(fixed)
+    });
+
+    let misread = misread?;
+    Some(EncodingCandidate {
+        fix: EncodingFix {
+            document_id: document.id,
+            title: title.clone().unwrap_or_else(|| document.title.clone()),
+            misread_as: misread.label(),
+            segments,
+            previews,
+        },
+        title: title.unwrap_or(document.title),
+        description: description.or(document.description),
+        body: body.or(document.text_content),
+    })
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeBucket {
+    Day,
+    /// Weeks start on Monday.
+    Week,
+    Month,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TimelineDocument {
+    pub id: i64,
+    pub title: String,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct TimelineBucket {
+    /// Local date the bucket starts on, `YYYY-MM-DD`.
+    pub start: String,
+    /// Relative to now, e.g. `Today`, `Last week` or `3 months ago`.
+    pub label: String,
+    pub count: usize,
+    /// Newest first.
+    pub documents: Vec<TimelineDocument>,
+}
+
+/// Documents grouped by the local day, week or month they were created in,
+/// newest bucket first. Local time uses the offset from settings.
+#[tauri::command]
+pub async fn documents_timeline(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    bucket: TimeBucket,
+) -> CmdResult<Vec<TimelineBucket>> {
+    let pool = db::pool(&app).await?;
+    let offset = store.get().utc_offset_minutes();
+    let modifier = format!("{:+} minutes", offset);
+
+    // Stored timestamps are UTC; shifting them first makes the bucket
+    // boundaries fall on local midnight
+    let start = match bucket {
+        TimeBucket::Day => "date(created_at, ?1)",
+        TimeBucket::Week => "date(created_at, ?1, 'weekday 0', '-6 days')",
+        TimeBucket::Month => "date(created_at, ?1, 'start of month')",
+    };
+    let rows: Vec<(String, i64, String, String)> = sqlx::query_as(&format!(
+        "SELECT {} AS bucket, id, title, created_at FROM documents
+         ORDER BY bucket DESC, created_at DESC, id DESC",
+        start
+    ))
+    .bind(&modifier)
+    .fetch_all(&pool)
+    .await?;
+
+    let today = (Utc::now() + Duration::minutes(offset.into())).date_naive();
+    let mut buckets: Vec<TimelineBucket> = Vec::new();
+    for (start, id, title, created_at) in rows {
+        let document = TimelineDocument {
+            id,
+            title,
+            created_at,
+        };
+        match buckets.last_mut() {
+            Some(last) if last.start == start => {
+                last.count += 1;
+                last.documents.push(document);
+            }
+            _ => buckets.push(TimelineBucket {
+                label: bucket_label(bucket, &start, today),
+                start,
+                count: 1,
+                documents: vec![document],
+            }),
+        }
+    }
+
+    Ok(buckets)
+}
+
+fn bucket_label(bucket: TimeBucket, start: &str, today: NaiveDate) -> String {
+    let Ok(start_date) = NaiveDate::parse_from_str(start, "%Y-%m-%d") else {
+        return start.to_string();
+    };
+    let (ago, units) = match bucket {
+        TimeBucket::Day => (
+            (today - start_date).num_days(),
+            ["Today", "Yesterday", "days"],
+        ),
+        TimeBucket::Week => {
+            let this_week = today - Duration::days(today.weekday().num_days_from_monday().into());
+            (
+                (this_week - start_date).num_days() / 7,
+                ["This week", "Last week", "weeks"],
+            )
+        }
+        TimeBucket::Month => {
+            let months = |date: NaiveDate| i64::from(date.year()) * 12 + i64::from(date.month0());
+            (
+                months(today) - months(start_date),
+                ["This month", "Last month", "months"],
+            )
+        }
+    };
+    match ago {
+        0 => units[0].to_string(),
+        1 => units[1].to_string(),
+        // Created "in the future" by a changed clock or offset
+        ago if ago < 0 => start.to_string(),
+        ago => format!("{} {} ago", ago, units[2]),
+    }
+}
+
+/// Documents with attachments of a content type, either a prefix ending in
+/// `/` (`image/`) or an exact type (`application/pdf`).
+#[tauri::command]
+pub async fn documents_with_attachment_type(
+    app: AppHandle,
+    mime_prefix: String,
+) -> CmdResult<Vec<DocumentAttachmentMatch>> {
+    let pool = db::pool(&app).await?;
+
+    // LIKE is case-insensitive for ASCII, which covers MIME types
+    let mime_prefix = mime_prefix.trim();
+    if mime_prefix.is_empty() {
+        return Err(AppError::Validation(
+            "Content type cannot be empty".to_string(),
+        ));
+    }
+
+    let pattern = if mime_prefix.ends_with('/') {
+        format!("{}%", escape_like(mime_prefix))
+    } else {
+        escape_like(mime_prefix)
+    };
+
+    let timer = metrics::Timer::start("documents_with_attachment_type");
+    let matches: Vec<DocumentAttachmentMatch> = sqlx::query_as(
+        "SELECT d.*, COUNT(a.id) AS matching_attachments
+         FROM documents d
+         JOIN attachments a ON a.document_id = d.id
+         WHERE a.filetype LIKE ? ESCAPE '\\'
+         GROUP BY d.id
+         ORDER BY d.updated_at DESC",
+    )
+    .bind(pattern)
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, matches.len());
+
+    Ok(matches)
+}
+
+pub(crate) fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// Same format SQLite uses for CURRENT_TIMESTAMP
+pub(crate) fn to_sql_datetime(timestamp: i64) -> CmdResult<String> {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+        .ok_or_else(|| AppError::Validation(format!("Invalid timestamp: {}", timestamp)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_name_composes_combining_marks() {
+        assert_eq!(normalize_name("  Cafe\u{301} "), "Caf\u{e9}");
+        // Canonical ordering puts the marks in the same order either way
+        assert_eq!(
+            normalize_name("q\u{323}\u{307}"),
+            normalize_name("q\u{307}\u{323}")
+        );
+        // Hangul jamo compose into the syllable
+        assert_eq!(normalize_name("\u{1100}\u{1161}"), "\u{ac00}");
+    }
+
+    #[test]
+    fn normalize_name_keeps_rtl_text_in_logical_order() {
+        // Alef followed by a combining madda is the precomposed alef madda
+        assert_eq!(normalize_name("\u{627}\u{653}\u{644}"), "\u{622}\u{644}");
+        assert_eq!(normalize_name(" שלום "), "שלום");
+    }
+
+    #[test]
+    fn normalize_name_leaves_emoji_sequences_alone() {
+        // Skin tone, ZWJ sequence and flag
+        for emoji in ["👍🏽", "👩‍💻", "🇯🇵"] {
+            assert_eq!(normalize_name(emoji), emoji);
+        }
+        assert_eq!(
+            normalize_name("\t📁 Re\u{301}sume\u{301}\n"),
+            "📁 R\u{e9}sum\u{e9}"
+        );
+    }
+
+    fn body_limit(limit: Option<u32>, strict: bool) -> Settings {
+        Settings {
+            max_body_chars: limit,
+            strict_body_limit: strict,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn body_limit_allows_exactly_the_limit() {
+        let settings = body_limit(Some(1000), true);
+        let size = check_body_size(&settings, &"a".repeat(1000)).unwrap();
+        assert_eq!(
+            (size.chars, size.limit, size.over_limit),
+            (1000, Some(1000), false)
+        );
+
+        match check_body_size(&settings, &"a".repeat(1001)) {
+            Err(AppError::BodyTooLarge { limit, actual }) => {
+                assert_eq!((limit, actual), (1000, 1001))
+            }
+            other => panic!(
+                "expected body_too_large, got {:?}",
+                other.map(|size| size.chars)
+            ),
+        }
+    }
+
+    #[test]
+    fn soft_body_limit_only_flags() {
+        let settings = body_limit(Some(1000), false);
+        assert!(
+            !check_body_size(&settings, &"a".repeat(1000))
+                .unwrap()
+                .over_limit
+        );
+        assert!(
+            check_body_size(&settings, &"a".repeat(1001))
+                .unwrap()
+                .over_limit
+        );
+
+        let unlimited = body_limit(None, true);
+        let size = check_body_size(&unlimited, &"a".repeat(5000)).unwrap();
+        assert_eq!((size.limit, size.over_limit), (None, false));
+    }
+
+    #[test]
+    fn body_limit_counts_characters_not_bytes() {
+        let settings = body_limit(Some(1000), true);
+        // 1000 characters, 4000 bytes
+        let emoji = "😀".repeat(1000);
+        assert_eq!(check_body_size(&settings, &emoji).unwrap().chars, 1000);
+        // 1001 characters, since the accent is a character of its own
+        let decomposed = format!("{}e\u{301}", "a".repeat(999));
+        assert!(check_body_size(&settings, &decomposed).is_err());
+    }
+
+    #[test]
+    fn normalize_stored_names_merges_tags_that_become_equal() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query(
+                "INSERT INTO documents (id, title) VALUES (1, 'Cafe\u{301} notes'), (2, 'Menu')",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO tags (id, name) VALUES (1, 'caf\u{e9}'), (2, 'cafe\u{301}')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "INSERT INTO document_tags (document_id, tag_id) VALUES (1, 1), (1, 2), (2, 2)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            normalize_stored_names(&pool).await.unwrap();
+
+            let tags: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM tags")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+            assert_eq!(tags, vec![(1, "caf\u{e9}".to_string())]);
+            let links: Vec<(i64, i64)> = sqlx::query_as(
+                "SELECT document_id, tag_id FROM document_tags ORDER BY document_id",
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            assert_eq!(links, vec![(1, 1), (2, 1)]);
+            let (title,): (String,) = sqlx::query_as("SELECT title FROM documents WHERE id = 1")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(title, "Caf\u{e9} notes");
+        });
+    }
+
+    #[test]
+    fn char_ranges_stay_on_char_boundaries() {
+        // One, two, three and four bytes per character
+        let body = "aé€😀b";
+        assert_eq!(char_range(body, 0, 2), "aé");
+        assert_eq!(char_range(body, 1, 3), "é€😀");
+        assert_eq!(char_range(body, 3, 1), "😀");
+        assert_eq!(char_range(body, 4, 10), "b");
+        assert_eq!(char_range(body, 2, 0), "");
+        assert_eq!(char_range(body, 5, 3), "");
+        assert_eq!(char_range(body, 99, 3), "");
+        assert_eq!(char_range("", 0, 3), "");
+    }
+
+    #[test]
+    fn char_range_pages_add_up_to_the_body() {
+        let body = "Grüße 😀 ".repeat(1000);
+        let total = body.chars().count();
+        let mut pages = String::new();
+        let mut offset = 0;
+        while offset < total {
+            let page = char_range(&body, offset, 7);
+            assert!(page.chars().count() <= 7);
+            pages.push_str(page);
+            offset += 7;
+        }
+        assert_eq!(pages, body);
+    }
+
+    #[test]
+    fn empty_filter_matches_the_whole_trash() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query(
+                "INSERT INTO deleted_documents (id, title, category_id, deleted_at)
+                 VALUES (3, 'Old', 1, '2020-01-01 00:00:00'), (7, 'New', NULL, CURRENT_TIMESTAMP)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            let mut tx = pool.begin().await.unwrap();
+            let all = matching_trash(&mut tx, &TrashFilter::default())
+                .await
+                .unwrap();
+            assert_eq!(all, vec![3, 7]);
+            let recent = TrashFilter {
+                deleted_after: Some("2021-01-01".to_string()),
+                ..TrashFilter::default()
+            };
+            assert_eq!(matching_trash(&mut tx, &recent).await.unwrap(), vec![7]);
+        });
+    }
+
+    #[test]
+    fn destructive_commands_wait_for_confirmation() {
+        let settings = Settings::default();
+        assert!(matches!(
+            settings.require_confirmation(None),
+            Err(AppError::ConfirmationRequired(_))
+        ));
+        assert!(settings.require_confirmation(Some(false)).is_err());
+        assert!(settings.require_confirmation(Some(true)).is_ok());
+
+        let unguarded = Settings {
+            confirm_destructive: false,
+            ..Settings::default()
+        };
+        assert!(unguarded.require_confirmation(None).is_ok());
+    }
+
+    #[test]
+    fn summary_pages_are_a_fraction_of_full_ones() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            // 300 to 4000 words per body, as in a typical archive
+            for i in 0..200 {
+                let words = 300 + (i * 37) % 3700;
+                let body: String = (0..words).map(|w| format!("word{} ", w % 97)).collect();
+                sqlx::query("INSERT INTO documents (title, text_content) VALUES (?, ?)")
+                    .bind(format!("Document {}", i))
+                    .bind(format!("<p>{}</p>", body))
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+
+            let page = |projection| {
+                let pool = pool.clone();
+                async move {
+                    document_page(&pool, None, None, None, Some(projection), None, None, None)
+                        .await
+                        .unwrap()
+                }
+            };
+            let full = page(Projection::Full).await;
+            let summary = page(Projection::Summary).await;
+            let (DocumentListing::Full(documents), DocumentListing::Summary(summaries)) =
+                (&full.documents, &summary.documents)
+            else {
+                panic!("listed with the wrong projection");
+            };
+            assert_eq!(
+                documents.iter().map(|d| d.id).collect::<Vec<_>>(),
+                summaries.iter().map(|s| s.id).collect::<Vec<_>>()
+            );
+            assert!(summaries.iter().all(|s| s.word_count >= 300));
+
+            let full_bytes = serde_json::to_vec(&full).unwrap().len();
+            let summary_bytes = serde_json::to_vec(&summary).unwrap().len();
+            assert!(
+                summary_bytes * 20 < full_bytes,
+                "{} bytes of summaries against {} in full",
+                summary_bytes,
+                full_bytes
+            );
+        });
+    }
+}
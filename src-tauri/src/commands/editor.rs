@@ -0,0 +1,54 @@
+use tauri::{AppHandle, State};
+
+use crate::commands::toc;
+use crate::db;
+use crate::editor::{self, ExternalEdits};
+use crate::error::{AppError, CmdResult};
+use crate::settings::SettingsStore;
+
+/// Opens a document body in an external editor and imports every save back
+/// into the document, each as a new version. `editor_cmd` becomes the
+/// preferred editor; without it the one in settings is used. Edits made in
+/// the app meanwhile win: the external copy is then kept and reported with
+/// `external_edit_conflict` instead of being imported. Returns the path of
+/// the copy being edited.
+#[tauri::command]
+pub async fn open_in_external_editor(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    edits: State<'_, ExternalEdits>,
+    id: i64,
+    editor_cmd: Option<String>,
+) -> CmdResult<String> {
+    let mut settings = store.get();
+    let editor_cmd = editor_cmd
+        .filter(|cmd| !cmd.trim().is_empty())
+        .or_else(|| settings.external_editor.clone())
+        .ok_or_else(|| AppError::Validation("No external editor configured".to_string()))?;
+    let command = editor::split_command(&editor_cmd);
+
+    let pool = db::pool(&app).await?;
+    if toc::is_generated(&pool, id).await? {
+        return Err(AppError::Validation(
+            "Generated documents can't be edited".to_string(),
+        ));
+    }
+    let (body,): (Option<String>,) =
+        sqlx::query_as("SELECT text_content FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    let path = edits
+        .open(&app, pool, id, body.unwrap_or_default(), &command)
+        .map_err(AppError::Io)?;
+
+    // Only remembered once it actually started
+    if settings.external_editor.as_deref() != Some(editor_cmd.as_str()) {
+        settings.external_editor = Some(editor_cmd);
+        store.replace(settings)?;
+    }
+
+    Ok(path.to_string_lossy().to_string())
+}
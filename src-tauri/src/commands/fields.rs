@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::{normalize_name, DocumentsEvent};
+use crate::db;
+use crate::error::{AppError, CmdResult};
+
+// Custom fields are stored as text; the type only decides what is accepted
+// and how it is written, so values of one type sort and compare alike.
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    Text,
+    /// Written without exponent or trailing zeros, e.g. `1250.5`.
+    Number,
+    /// `YYYY-MM-DD`.
+    Date,
+    /// `true` or `false`; yes/no and 1/0 are read as well.
+    Boolean,
+}
+
+impl FieldType {
+    fn normalize(self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        match self {
+            FieldType::Text => {
+                if value.is_empty() {
+                    Err("Field value cannot be empty".to_string())
+                } else {
+                    Ok(normalize_name(value))
+                }
+            }
+            FieldType::Number => value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .map(|number| number.to_string())
+                .ok_or_else(|| format!("{:?} is not a number", value)),
+            FieldType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .map_err(|_| format!("{:?} is not a date like 2024-03-31", value)),
+            FieldType::Boolean => match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok("true".to_string()),
+                "false" | "no" | "0" => Ok("false".to_string()),
+                _ => Err(format!("{:?} is not true or false", value)),
+            },
+        }
+    }
+}
+
+/// Sets the custom field `key` to `value` on every document in `ids`, all
+/// or none, e.g. the same fiscal year on a batch of invoices. The value is
+/// checked and written per `value_type`, text by default. Returns how many
+/// documents changed; those already holding the value don't count.
+#[tauri::command]
+pub async fn set_field_for_documents(
+    app: AppHandle,
+    ids: Vec<i64>,
+    key: String,
+    value: String,
+    value_type: Option<FieldType>,
+) -> CmdResult<usize> {
+    let key = field_key(&key)?;
+    let value = value_type
+        .unwrap_or_default()
+        .normalize(&value)
+        .map_err(AppError::Validation)?;
+    let ids = unique(ids);
+
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let mut changed = Vec::new();
+    for id in &ids {
+        let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM documents WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if exists.is_none() {
+            return Err(AppError::NotFound(format!("Document not found: {}", id)));
+        }
+        let written = sqlx::query(
+            "INSERT INTO document_fields (document_id, key, value) VALUES (?, ?, ?)
+             ON CONFLICT (document_id, key) DO UPDATE SET value = excluded.value
+             WHERE value != excluded.value",
+        )
+        .bind(id)
+        .bind(&key)
+        .bind(&value)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if written > 0 {
+            changed.push(*id);
+        }
+    }
+    tx.commit().await?;
+
+    Ok(announce(&app, changed))
+}
+
+/// Removes the custom field `key` from every document in `ids` that has
+/// it. Returns how many documents changed.
+#[tauri::command]
+pub async fn remove_field_for_documents(
+    app: AppHandle,
+    ids: Vec<i64>,
+    key: String,
+) -> CmdResult<usize> {
+    let key = field_key(&key)?;
+    let ids = unique(ids);
+
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let mut changed = Vec::new();
+    for id in &ids {
+        let removed = sqlx::query("DELETE FROM document_fields WHERE document_id = ? AND key = ?")
+            .bind(id)
+            .bind(&key)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            changed.push(*id);
+        }
+    }
+    tx.commit().await?;
+
+    Ok(announce(&app, changed))
+}
+
+fn field_key(key: &str) -> CmdResult<String> {
+    let key = normalize_name(key);
+    if key.is_empty() {
+        return Err(AppError::Validation(
+            "Field names cannot be empty".to_string(),
+        ));
+    }
+    Ok(key)
+}
+
+fn unique(ids: Vec<i64>) -> Vec<i64> {
+    let mut seen = HashSet::new();
+    ids.into_iter().filter(|id| seen.insert(*id)).collect()
+}
+
+// One event for the whole batch
+fn announce(app: &AppHandle, document_ids: Vec<i64>) -> usize {
+    let count = document_ids.len();
+    if count > 0 {
+        let _ = app.emit("documents_updated", DocumentsEvent { document_ids });
+    }
+    count
+}
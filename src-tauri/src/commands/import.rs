@@ -0,0 +1,1831 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqliteExecutor, SqlitePool, Transaction};
+use tauri::{AppHandle, Emitter, PhysicalPosition, State, Window};
+
+use crate::archive::import::{self, ArchiveContents, ArchiveReader};
+use crate::archive::vault;
+use crate::archive::zip::ZipReader;
+use crate::commands::attachments::AttachmentEvent;
+use crate::commands::categories::CategoryEvent;
+use crate::commands::documents::{normalize_name, trash_documents, DocumentsEvent};
+use crate::commands::{attachments, audit, search};
+use crate::csv;
+use crate::db;
+use crate::encoding;
+use crate::error::{AppError, CmdResult};
+use crate::jobs::JobContext;
+use crate::settings::SettingsStore;
+use crate::watcher;
+
+/// How to handle items that already exist, same as the frontend importer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    /// Keep the existing item; skipped documents bring no attachments.
+    Skip,
+    /// Update the existing item and add the imported attachments.
+    Merge,
+    /// Update the existing item and replace its attachments.
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ConflictResolution {
+    pub categories: Resolution,
+    pub documents: Resolution,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ImportConflict {
+    pub kind: &'static str,
+    pub import_id: i64,
+    pub existing_id: i64,
+    pub name: String,
+    pub reason: String,
+    pub resolution: Resolution,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ImportSkip {
+    pub kind: &'static str,
+    pub name: String,
+    pub reason: String,
+}
+
+/// Where an archive id ends up. `target_id` is `None` for records that a
+/// dry run would create.
+#[derive(Clone, Serialize)]
+pub struct IdMapping {
+    pub import_id: i64,
+    pub target_id: Option<i64>,
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub export_type: String,
+    pub categories_added: usize,
+    pub categories_updated: usize,
+    pub documents_added: usize,
+    pub documents_updated: usize,
+    pub attachments_added: usize,
+    pub conflicts: Vec<ImportConflict>,
+    pub skipped: Vec<ImportSkip>,
+    pub category_ids: Vec<IdMapping>,
+    pub document_ids: Vec<IdMapping>,
+    /// What `undo_import` takes to undo the import; `None` for dry runs.
+    pub session_id: Option<i64>,
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Create,
+    Reuse(i64),
+    Update(i64),
+}
+
+// What an import will do, decided up front from the archive JSON and
+// read-only queries, so a dry run and the real import agree
+// Attachment files an import copied in, removed again if it fails, and
+// the ones it replaced, removed once it commits
+#[derive(Default)]
+struct ImportFiles {
+    written: Vec<PathBuf>,
+    replaced: Vec<String>,
+}
+
+struct Plan {
+    categories: Vec<Action>,
+    documents: Vec<Option<Action>>,
+    attachments: Vec<bool>,
+    replace_attachments: bool,
+    report: ImportReport,
+}
+
+/// Imports a `.andoarchive`. With `dry_run` nothing is written; the
+/// returned report describes what the import would do.
+#[tauri::command]
+pub async fn import_archive(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    path: String,
+    resolution: ConflictResolution,
+    dry_run: bool,
+    confirmed: Option<bool>,
+) -> CmdResult<ImportReport> {
+    // Replacing overwrites existing records, previewing it is harmless
+    let replaces =
+        resolution.categories == Resolution::Replace || resolution.documents == Resolution::Replace;
+    if replaces && !dry_run {
+        store.get().require_confirmation(confirmed)?;
+    }
+
+    let pool = db::pool(&app).await?;
+    import_archive_file(&app, &pool, &path, resolution, dry_run, None).await
+}
+
+/// Plans and, unless `dry_run`, runs the import of the archive at `path`,
+/// reporting progress per document and attachment to `job` if given.
+pub(crate) async fn import_archive_file(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    path: &str,
+    resolution: ConflictResolution,
+    dry_run: bool,
+    job: Option<&JobContext>,
+) -> CmdResult<ImportReport> {
+    let archive_path = PathBuf::from(path);
+    let (mut contents, zip) =
+        tauri::async_runtime::spawn_blocking(move || import::read_archive(&archive_path)).await??;
+    for category in &mut contents.categories {
+        category.name = normalize_name(&category.name);
+    }
+    for document in &mut contents.documents {
+        document.title = normalize_name(&document.title);
+    }
+
+    let mut plan = plan_archive(pool, &contents, &zip, resolution).await?;
+    plan.report.dry_run = dry_run;
+    if dry_run {
+        return Ok(plan.report);
+    }
+
+    // One batched reindex at the end beats updating the index per row
+    let deferred = search::defer_indexing(pool).await?;
+    let result = execute_archive(app, pool, path, contents, zip, plan, job).await;
+    if deferred {
+        search::resume_indexing(pool).await?;
+    }
+    result
+}
+
+#[derive(Serialize)]
+pub struct VaultFailure {
+    pub import_id: i64,
+    pub title: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct VaultImportReport {
+    pub categories_added: usize,
+    pub documents_added: usize,
+    pub skipped: Vec<ImportSkip>,
+    /// Documents that could not be decrypted, e.g. because they were
+    /// damaged or altered.
+    pub failed: Vec<VaultFailure>,
+    pub document_ids: Vec<IdMapping>,
+    /// What `undo_import` takes to undo the import.
+    pub session_id: Option<i64>,
+}
+
+/// Decrypts documents from a vault written by `export_vault` and adds them
+/// as new documents; all of them, or just `document_ids` (ids in the
+/// vault). Categories are matched by name and created when missing, and
+/// documents whose title already exists in their category are skipped.
+/// A wrong password fails the whole import before anything is written;
+/// the recovery key works in its place for vaults exported after it was
+/// generated.
+#[tauri::command]
+pub async fn import_vault(
+    app: AppHandle,
+    src_path: String,
+    master_password: String,
+    document_ids: Option<Vec<i64>>,
+) -> CmdResult<VaultImportReport> {
+    let path = PathBuf::from(&src_path);
+    // Key derivation is deliberately slow
+    let (mut file, contents) = tauri::async_runtime::spawn_blocking(move || {
+        let json = fs::read_to_string(&path)?;
+        let file: vault::VaultFile = serde_json::from_str(&json)
+            .map_err(|e| AppError::Validation(format!("Not a vault: {}", e)))?;
+        // Vaults before version 3 were sealed with the legacy cipher
+        let cipher = match file.version {
+            0..=2 => vault::LEGACY_CIPHER,
+            _ => vault::CIPHER,
+        };
+        if file.format != vault::FORMAT || file.cipher != cipher {
+            return Err(AppError::Validation("Not a vault".to_string()));
+        }
+        if file.version > vault::VERSION {
+            return Err(AppError::IncompatibleVersion {
+                archive: file.version,
+                supported: vault::VERSION,
+            });
+        }
+
+        let master = match &file.master_key {
+            Some(sealed) => vault::unwrap_master(&master_password, &file.kdf, sealed).or_else(
+                |e| match &file.recovery {
+                    Some(recovery) => vault::recovery_key(&master_password)
+                        .ok()
+                        .and_then(|key| key.open_key(recovery, "recovery").ok())
+                        .ok_or(e),
+                    None => Err(e),
+                },
+            )?,
+            // Version 1 used the password key as the master key
+            None => vault::Key::derive(&master_password, &file.kdf)?,
+        };
+        vault::verify_check(&master, &file.check)?;
+
+        let wanted: Option<HashSet<i64>> = document_ids.map(|ids| ids.into_iter().collect());
+        let contents: Vec<(usize, CmdResult<vault::VaultContent>)> = file
+            .documents
+            .iter()
+            .enumerate()
+            .filter(|(_, document)| {
+                wanted
+                    .as_ref()
+                    .map_or(true, |ids| ids.contains(&document.id))
+            })
+            .map(|(index, document)| {
+                let content = master
+                    .open_key(&document.key, &format!("key:{}", document.id))
+                    .and_then(|key| {
+                        key.open(&document.content, &format!("content:{}", document.id))
+                    })
+                    .and_then(|json| serde_json::from_slice(&json).map_err(AppError::from));
+                (index, content)
+            })
+            .collect();
+        Ok((file, contents))
+    })
+    .await??;
+    for category in &mut file.categories {
+        category.name = normalize_name(&category.name);
+    }
+    for document in &mut file.documents {
+        document.title = normalize_name(&document.title);
+        for tag in &mut document.tags {
+            *tag = normalize_name(tag);
+        }
+    }
+
+    let pool = db::pool(&app).await?;
+    let mut report = VaultImportReport::default();
+
+    let existing: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM categories")
+        .fetch_all(&pool)
+        .await?;
+    let existing: HashMap<String, i64> = existing
+        .into_iter()
+        .map(|(id, name)| (name.to_lowercase(), id))
+        .collect();
+    let categories: HashMap<i64, &vault::VaultCategory> = file
+        .categories
+        .iter()
+        .map(|category| (category.id, category))
+        .collect();
+
+    let mut tx = pool.begin().await?;
+    let session = begin_session(&mut *tx, "vault", &src_path).await?;
+    report.session_id = Some(session);
+    let mut category_ids: HashMap<i64, i64> = HashMap::new();
+    for (index, content) in contents {
+        let document = &file.documents[index];
+        let content = match content {
+            Ok(content) => content,
+            Err(reason) => {
+                report.failed.push(VaultFailure {
+                    import_id: document.id,
+                    title: document.title.clone(),
+                    reason: reason.message().into_owned(),
+                });
+                continue;
+            }
+        };
+
+        let category_id = match document.category_id {
+            Some(id) => Some(
+                vault_category(
+                    &mut tx,
+                    id,
+                    &categories,
+                    &existing,
+                    &mut category_ids,
+                    &mut report,
+                    session,
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
+        let duplicate: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM documents WHERE category_id IS ? AND title = ? COLLATE NOCASE LIMIT 1",
+        )
+        .bind(category_id)
+        .bind(&document.title)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if duplicate.is_some() {
+            report.skipped.push(ImportSkip {
+                kind: "document",
+                name: document.title.clone(),
+                reason: "Document with same title already exists in category".to_string(),
+            });
+            continue;
+        }
+
+        let id = sqlx::query(
+            "INSERT INTO documents
+               (title, description, text_content, category_id, created_at, updated_at,
+                import_session)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&document.title)
+        .bind(&content.description)
+        .bind(&content.text_content)
+        .bind(category_id)
+        .bind(&document.created_at)
+        .bind(&document.updated_at)
+        .bind(session)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
+        for tag in &document.tags {
+            sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+                 SELECT ?, id FROM tags WHERE name = ?",
+            )
+            .bind(id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        report.documents_added += 1;
+        report.document_ids.push(IdMapping {
+            import_id: document.id,
+            target_id: Some(id),
+        });
+    }
+
+    audit::record(&mut *tx, "import", "vault", None, &src_path).await?;
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+// The target id of a vault category, reusing one with the same name or
+// creating it (and its missing parents)
+async fn vault_category(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: i64,
+    categories: &HashMap<i64, &vault::VaultCategory>,
+    existing: &HashMap<String, i64>,
+    category_ids: &mut HashMap<i64, i64>,
+    report: &mut VaultImportReport,
+    session: i64,
+) -> CmdResult<i64> {
+    // Walk up to the first category already resolved or found by name
+    let mut chain = Vec::new();
+    let mut parent_id = None;
+    let mut next = Some(id);
+    while let Some(id) = next {
+        if let Some(&target) = category_ids.get(&id) {
+            parent_id = Some(target);
+            break;
+        }
+        let category = categories
+            .get(&id)
+            .ok_or_else(|| AppError::Validation(format!("Vault is missing category {}", id)))?;
+        if let Some(&target) = existing.get(&category.name.to_lowercase()) {
+            category_ids.insert(id, target);
+            parent_id = Some(target);
+            break;
+        }
+        if chain.contains(&id) {
+            return Err(AppError::Validation(
+                "Vault categories form a cycle".to_string(),
+            ));
+        }
+        chain.push(id);
+        next = category.parent_id;
+    }
+
+    for id in chain.into_iter().rev() {
+        let category = categories[&id];
+        let level = match parent_id {
+            Some(parent) => {
+                let (level,): (i64,) = sqlx::query_as("SELECT level FROM categories WHERE id = ?")
+                    .bind(parent)
+                    .fetch_one(&mut **tx)
+                    .await?;
+                level + 1
+            }
+            None => 0,
+        };
+        let target = sqlx::query(
+            "INSERT INTO categories (name, icon, color, parent_id, level, import_session)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&category.name)
+        .bind(&category.icon)
+        .bind(&category.color)
+        .bind(parent_id)
+        .bind(level)
+        .bind(session)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid();
+        report.categories_added += 1;
+        category_ids.insert(id, target);
+        parent_id = Some(target);
+    }
+
+    Ok(category_ids[&id])
+}
+
+/// Imports every file at the top of `path` as a document of `category_id`,
+/// skipping files whose title already exists there. With `dry_run` nothing
+/// is written.
+#[tauri::command]
+pub async fn import_folder(
+    app: AppHandle,
+    path: String,
+    category_id: i64,
+    dry_run: bool,
+) -> CmdResult<ImportReport> {
+    let pool = db::pool(&app).await?;
+
+    let folder = PathBuf::from(&path);
+    if !folder.is_dir() {
+        return Err(AppError::Validation(format!("Not a folder: {}", path)));
+    }
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    let mut files: Vec<PathBuf> = watcher::scan(&folder).into_keys().collect();
+    files.sort();
+
+    let session = if dry_run {
+        None
+    } else {
+        Some(begin_session(&pool, "folder", &path).await?)
+    };
+    let deferred = !dry_run && search::defer_indexing(&pool).await?;
+    let result = ingest_folder(&app, &pool, files, category_id, session).await;
+    if deferred {
+        search::resume_indexing(&pool).await?;
+    }
+    result
+}
+
+// Without a session, a dry run
+async fn ingest_folder(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    files: Vec<PathBuf>,
+    category_id: i64,
+    session: Option<i64>,
+) -> CmdResult<ImportReport> {
+    let dry_run = session.is_none();
+    let mut report = ImportReport {
+        dry_run,
+        export_type: "folder".to_string(),
+        session_id: session,
+        ..Default::default()
+    };
+    for file in files {
+        let title = watcher::title_for(&file);
+        let existing: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM documents WHERE category_id = ? AND title = ? COLLATE NOCASE LIMIT 1",
+        )
+        .bind(category_id)
+        .bind(&title)
+        .fetch_optional(pool)
+        .await?;
+
+        if let Some((existing_id,)) = existing {
+            report.conflicts.push(ImportConflict {
+                kind: "document",
+                import_id: 0,
+                existing_id,
+                name: title.clone(),
+                reason: "Document with same title already exists in category".to_string(),
+                resolution: Resolution::Skip,
+            });
+            report.skipped.push(ImportSkip {
+                kind: "document",
+                name: title,
+                reason: "Already imported".to_string(),
+            });
+            continue;
+        }
+
+        if !dry_run {
+            watcher::ingest(app, pool, &file, category_id, session).await?;
+        }
+        report.documents_added += 1;
+        report.attachments_added += 1;
+    }
+
+    Ok(report)
+}
+
+#[derive(Clone, Serialize)]
+pub struct FilesDropped {
+    pub paths: Vec<String>,
+    /// Where in the window, in physical pixels, to tell what they were
+    /// dropped on.
+    pub position: PhysicalPosition<f64>,
+}
+
+/// Passes files dropped on the window to the frontend as `files_dropped`;
+/// it decides where they go and calls `import_dropped_files`.
+pub fn handle_drop(window: &Window, paths: &[PathBuf], position: PhysicalPosition<f64>) {
+    let paths = paths
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    let _ = window.emit("files_dropped", FilesDropped { paths, position });
+}
+
+/// Imports dropped files, walking into dropped folders. Dropped on a
+/// document, they are attached to it; otherwise each file becomes a
+/// document of `category_id` like with `import_folder`.
+#[tauri::command]
+pub async fn import_dropped_files(
+    app: AppHandle,
+    paths: Vec<String>,
+    category_id: i64,
+    document_id: Option<i64>,
+) -> CmdResult<ImportReport> {
+    let pool = db::pool(&app).await?;
+
+    let (table, missing) = match document_id {
+        Some(_) => ("documents", "Document not found"),
+        None => ("categories", "Category not found"),
+    };
+    let exists: Option<(i64,)> = sqlx::query_as(&format!("SELECT id FROM {} WHERE id = ?", table))
+        .bind(document_id.unwrap_or(category_id))
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound(missing.to_string()));
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            dropped_files(&path, &mut files);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    let session = begin_session(&pool, "drop", &paths.join("; ")).await?;
+    let Some(document_id) = document_id else {
+        let deferred = search::defer_indexing(&pool).await?;
+        let result = ingest_folder(&app, &pool, files, category_id, Some(session)).await;
+        if deferred {
+            search::resume_indexing(&pool).await?;
+        }
+        let mut report = result?;
+        report.export_type = "drop".to_string();
+        return Ok(report);
+    };
+
+    let mut report = ImportReport {
+        export_type: "drop".to_string(),
+        session_id: Some(session),
+        ..Default::default()
+    };
+    for file in &files {
+        let attachment = attachments::store_file(&app, &pool, document_id, file).await?;
+        sqlx::query("UPDATE attachments SET import_session = ? WHERE id = ?")
+            .bind(session)
+            .bind(attachment.id)
+            .execute(&pool)
+            .await?;
+        report.attachments_added += 1;
+    }
+    if report.attachments_added > 0 {
+        let _ = app.emit("attachment_added", AttachmentEvent { document_id });
+    }
+    Ok(report)
+}
+
+// Files in `folder` and its subfolders, in path order. Hidden folders are
+// skipped, and symlinked ones aren't followed.
+fn dropped_files(folder: &Path, files: &mut Vec<PathBuf>) {
+    let mut found: Vec<PathBuf> = watcher::scan(folder).into_keys().collect();
+    found.sort();
+    files.extend(found);
+
+    let Ok(entries) = fs::read_dir(folder) else {
+        return;
+    };
+    let mut folders: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    folders.sort();
+    for folder in folders {
+        dropped_files(&folder, files);
+    }
+}
+
+/// A CSV column, by header name or by position counted from 0.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum CsvColumn {
+    Index(usize),
+    Name(String),
+}
+
+/// Which columns fill which document fields. Columns given in `fields`
+/// become custom fields under their key; unmapped columns are ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CsvMapping {
+    pub title: CsvColumn,
+    pub body: Option<CsvColumn>,
+    /// Tags separated by `;` or `,` within the cell.
+    pub tags: Option<CsvColumn>,
+    /// `YYYY-MM-DD`, `YYYY-MM-DD HH:MM:SS` or RFC 3339; import time when
+    /// unmapped.
+    pub created_at: Option<CsvColumn>,
+    #[serde(default)]
+    pub fields: HashMap<String, CsvColumn>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CsvOptions {
+    /// `,` unless given, e.g. `;` or a tab.
+    pub delimiter: Option<char>,
+    /// Whether the first row holds the column names. Detected when unset:
+    /// it does when it contains every column named in the mapping, or,
+    /// with a mapping by position only, when none of its cells is empty
+    /// or a number.
+    pub has_header: Option<bool>,
+}
+
+// A row turned into what goes in the database
+struct CsvDocument {
+    title: String,
+    body: Option<String>,
+    tags: Vec<String>,
+    created_at: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+/// Imports a CSV file as documents of `category_id`, one per row, with
+/// columns assigned to document fields by `mapping`. Rows that don't parse
+/// or lack a title are reported in `skipped` with their line number while
+/// the other rows are imported. `document_ids` maps each imported row's
+/// line number to its document.
+#[tauri::command]
+pub async fn import_csv(
+    app: AppHandle,
+    path: String,
+    mapping: CsvMapping,
+    category_id: i64,
+    options: Option<CsvOptions>,
+) -> CmdResult<ImportReport> {
+    let pool = db::pool(&app).await?;
+    let options = options.unwrap_or_default();
+    let delimiter = options.delimiter.unwrap_or(',');
+    if delimiter == '"' || delimiter == '\r' || delimiter == '\n' {
+        return Err(AppError::Validation(format!(
+            "Invalid delimiter: {:?}",
+            delimiter
+        )));
+    }
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    let bytes = fs::read(&path)?;
+    let mut records = tauri::async_runtime::spawn_blocking(move || {
+        csv::parse(&encoding::decode_text(&bytes), delimiter)
+    })
+    .await?
+    .into_iter()
+    .peekable();
+
+    let first = match records.peek() {
+        Some(Ok(record)) => Some(record.fields.clone()),
+        _ => None,
+    };
+    let has_header = options.has_header.unwrap_or_else(|| {
+        first
+            .as_deref()
+            .is_some_and(|first| looks_like_header(first, &mapping))
+    });
+    let header = match (has_header, first) {
+        (true, Some(header)) => {
+            records.next();
+            header
+        }
+        _ => Vec::new(),
+    };
+
+    // Every named column has to be there, or no row could be read
+    let resolve = |column: &CsvColumn| match column {
+        CsvColumn::Index(index) => Ok(*index),
+        CsvColumn::Name(name) => header
+            .iter()
+            .position(|cell| cell.trim().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| AppError::Validation(format!("No column named {:?}", name))),
+    };
+    let title_column = resolve(&mapping.title)?;
+    let body_column = mapping.body.as_ref().map(resolve).transpose()?;
+    let tags_column = mapping.tags.as_ref().map(resolve).transpose()?;
+    let created_column = mapping.created_at.as_ref().map(resolve).transpose()?;
+    let mut field_columns = Vec::new();
+    for (key, column) in &mapping.fields {
+        let key = normalize_name(key);
+        if key.is_empty() {
+            return Err(AppError::Validation(
+                "Field names cannot be empty".to_string(),
+            ));
+        }
+        field_columns.push((key, resolve(column)?));
+    }
+    field_columns.sort();
+
+    let mut report = ImportReport {
+        export_type: "csv".to_string(),
+        ..Default::default()
+    };
+    let mut rows = Vec::new();
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                report.skipped.push(ImportSkip {
+                    kind: "row",
+                    name: format!("line {}", e.line),
+                    reason: e.message,
+                });
+                continue;
+            }
+        };
+        let cell = |column: usize| record.fields.get(column).map(|cell| cell.trim());
+
+        let title = normalize_name(cell(title_column).unwrap_or_default());
+        if title.is_empty() {
+            report.skipped.push(ImportSkip {
+                kind: "row",
+                name: format!("line {}", record.line),
+                reason: "No title".to_string(),
+            });
+            continue;
+        }
+        let created_at = match created_column
+            .and_then(cell)
+            .filter(|cell| !cell.is_empty())
+        {
+            Some(cell) => match csv_timestamp(cell) {
+                Some(timestamp) => Some(timestamp),
+                None => {
+                    report.skipped.push(ImportSkip {
+                        kind: "row",
+                        name: format!("line {}", record.line),
+                        reason: format!("Unrecognized date: {}", cell),
+                    });
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let tags: Vec<String> = tags_column
+            .and_then(cell)
+            .unwrap_or_default()
+            .split([';', ','])
+            .map(normalize_name)
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        rows.push((
+            record.line,
+            CsvDocument {
+                title,
+                body: body_column
+                    .and_then(cell)
+                    .filter(|body| !body.is_empty())
+                    .map(str::to_string),
+                tags,
+                created_at,
+                fields: field_columns
+                    .iter()
+                    .filter_map(|(key, column)| {
+                        cell(*column)
+                            .filter(|value| !value.is_empty())
+                            .map(|value| (key.clone(), value.to_string()))
+                    })
+                    .collect(),
+            },
+        ));
+    }
+
+    let deferred = search::defer_indexing(&pool).await?;
+    let result = write_csv_rows(&pool, &path, category_id, &rows, &mut report).await;
+    if deferred {
+        search::resume_indexing(&pool).await?;
+    }
+    result?;
+
+    audit::record(&pool, "import", "csv", Some(category_id), &path).await?;
+    Ok(report)
+}
+
+async fn write_csv_rows(
+    pool: &SqlitePool,
+    path: &str,
+    category_id: i64,
+    rows: &[(usize, CsvDocument)],
+    report: &mut ImportReport,
+) -> CmdResult<()> {
+    let mut tx = pool.begin().await?;
+    let session = begin_session(&mut *tx, "csv", path).await?;
+    report.session_id = Some(session);
+    for (line, row) in rows {
+        let document_id = sqlx::query(
+            "INSERT INTO documents
+               (title, text_content, category_id, created_at, updated_at, import_session)
+             VALUES (?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP), COALESCE(?, CURRENT_TIMESTAMP), ?)",
+        )
+        .bind(&row.title)
+        .bind(&row.body)
+        .bind(category_id)
+        .bind(&row.created_at)
+        .bind(&row.created_at)
+        .bind(session)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
+        for tag in &row.tags {
+            sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+                 SELECT ?, id FROM tags WHERE name = ?",
+            )
+            .bind(document_id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for (key, value) in &row.fields {
+            sqlx::query("INSERT INTO document_fields (document_id, key, value) VALUES (?, ?, ?)")
+                .bind(document_id)
+                .bind(key)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        report.documents_added += 1;
+        report.document_ids.push(IdMapping {
+            import_id: *line as i64,
+            target_id: Some(document_id),
+        });
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn looks_like_header(first: &[String], mapping: &CsvMapping) -> bool {
+    let named: Vec<&str> = [
+        Some(&mapping.title),
+        mapping.body.as_ref(),
+        mapping.tags.as_ref(),
+        mapping.created_at.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .chain(mapping.fields.values())
+    .filter_map(|column| match column {
+        CsvColumn::Name(name) => Some(name.as_str()),
+        CsvColumn::Index(_) => None,
+    })
+    .collect();
+    if !named.is_empty() {
+        return named.iter().all(|name| {
+            first
+                .iter()
+                .any(|cell| cell.trim().eq_ignore_ascii_case(name.trim()))
+        });
+    }
+    first
+        .iter()
+        .all(|cell| !cell.trim().is_empty() && cell.trim().parse::<f64>().is_err())
+}
+
+// A CSV date as the UTC timestamp documents store
+fn csv_timestamp(cell: &str) -> Option<String> {
+    const STORED: &str = "%Y-%m-%d %H:%M:%S";
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(cell) {
+        return Some(
+            datetime
+                .with_timezone(&chrono::Utc)
+                .format(STORED)
+                .to_string(),
+        );
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(cell, format) {
+            return Some(datetime.format(STORED).to_string());
+        }
+    }
+    chrono::NaiveDate::parse_from_str(cell, "%Y-%m-%d")
+        .ok()
+        .map(|date| format!("{} 00:00:00", date))
+}
+
+/// A category an `import_zip` filed documents into, with its subfolders.
+#[derive(Serialize)]
+pub struct ZipCategory {
+    pub id: i64,
+    pub name: String,
+    /// Whether the import created it, rather than reusing one with the
+    /// same name in the same place.
+    pub created: bool,
+    pub children: Vec<ZipCategory>,
+}
+
+#[derive(Serialize)]
+pub struct ZipImportReport {
+    #[serde(flatten)]
+    pub import: ImportReport,
+    /// Subcategories of the root category, mirroring the zip's folders.
+    pub categories: Vec<ZipCategory>,
+}
+
+// A file in the zip: its folders from the top down, and its name
+struct ZipFile {
+    folders: Vec<String>,
+    name: String,
+    index: usize,
+}
+
+/// Imports a zip of loose files, recreating its folders as nested
+/// categories under `root_category_id` and each file as a document with
+/// the file attached. Files at the top of the zip go into the root
+/// category itself. Folders that already exist as a category of the same
+/// name in the same place are reused, and files whose title already
+/// exists in their category are skipped.
+#[tauri::command]
+pub async fn import_zip(
+    app: AppHandle,
+    path: String,
+    root_category_id: i64,
+) -> CmdResult<ZipImportReport> {
+    let pool = db::pool(&app).await?;
+
+    let root: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+        .bind(root_category_id)
+        .fetch_optional(&pool)
+        .await?;
+    if root.is_none() {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let scratch = std::env::temp_dir().join(format!("ando-archive-zip-{}", millis));
+    let zip_path = PathBuf::from(&path);
+    let extract_to = scratch.clone();
+    let (files, skipped) =
+        tauri::async_runtime::spawn_blocking(move || unpack_zip(&zip_path, &extract_to)).await??;
+
+    let session = begin_session(&pool, "zip", &path).await?;
+    let deferred = search::defer_indexing(&pool).await?;
+    let result = ingest_zip(&app, &pool, &scratch, files, root_category_id, session).await;
+    if deferred {
+        search::resume_indexing(&pool).await?;
+    }
+    let _ = fs::remove_dir_all(&scratch);
+
+    let mut report = result?;
+    report.import.skipped.splice(0..0, skipped);
+    Ok(report)
+}
+
+// Extracts every regular file to `dest/<index>/<name>`, keeping the names
+// attachments get; entries that can't be placed safely are reported
+fn unpack_zip(path: &Path, dest: &Path) -> CmdResult<(Vec<ZipFile>, Vec<ImportSkip>)> {
+    let file = fs::File::open(path)?;
+    let mut zip = ZipReader::new(std::io::BufReader::new(file))?;
+
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    let entries = zip.entries().to_vec();
+    for entry in entries {
+        // Some tools write Windows separators
+        let parts: Vec<&str> = entry
+            .name
+            .split(['/', '\\'])
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        let Some((&name, folders)) = parts.split_last() else {
+            continue;
+        };
+        if entry.name.ends_with('/') {
+            continue;
+        }
+        // Finder metadata and other hidden files
+        if parts
+            .iter()
+            .any(|part| part.starts_with('.') || *part == "__MACOSX")
+        {
+            continue;
+        }
+        if parts.contains(&"..") || entry.name.starts_with('/') || name.contains(':') {
+            skipped.push(ImportSkip {
+                kind: "file",
+                name: entry.name.clone(),
+                reason: "Unsafe path in zip".to_string(),
+            });
+            continue;
+        }
+
+        let data = match zip.read(&entry) {
+            Ok(data) => data,
+            Err(e) => {
+                skipped.push(ImportSkip {
+                    kind: "file",
+                    name: entry.name.clone(),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let index = files.len();
+        let dir = dest.join(index.to_string());
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(name), data)?;
+
+        files.push(ZipFile {
+            folders: folders
+                .iter()
+                .map(|folder| normalize_name(folder))
+                .collect(),
+            name: name.to_string(),
+            index,
+        });
+    }
+
+    Ok((files, skipped))
+}
+
+async fn ingest_zip(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    scratch: &Path,
+    mut files: Vec<ZipFile>,
+    root_category_id: i64,
+    session: i64,
+) -> CmdResult<ZipImportReport> {
+    let mut report = ImportReport {
+        export_type: "zip".to_string(),
+        session_id: Some(session),
+        ..Default::default()
+    };
+    // Folders by path, so equal names under different parents stay apart
+    let mut folders: HashMap<Vec<String>, (i64, bool)> = HashMap::new();
+
+    files.sort_by(|a, b| (&a.folders, &a.name).cmp(&(&b.folders, &b.name)));
+    for file in files {
+        let mut category_id = root_category_id;
+        for depth in 1..=file.folders.len() {
+            let path = file.folders[..depth].to_vec();
+            if let Some(&(id, _)) = folders.get(&path) {
+                category_id = id;
+                continue;
+            }
+            let (id, created) =
+                zip_folder_category(pool, category_id, &path[depth - 1], session).await?;
+            if created {
+                report.categories_added += 1;
+            }
+            folders.insert(path, (id, created));
+            category_id = id;
+        }
+
+        let source = scratch.join(file.index.to_string()).join(&file.name);
+        let title = watcher::title_for(&source);
+        let existing: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM documents WHERE category_id = ? AND title = ? COLLATE NOCASE LIMIT 1",
+        )
+        .bind(category_id)
+        .bind(&title)
+        .fetch_optional(pool)
+        .await?;
+        if let Some((existing_id,)) = existing {
+            report.conflicts.push(ImportConflict {
+                kind: "document",
+                import_id: 0,
+                existing_id,
+                name: title.clone(),
+                reason: "Document with same title already exists in category".to_string(),
+                resolution: Resolution::Skip,
+            });
+            report.skipped.push(ImportSkip {
+                kind: "document",
+                name: title,
+                reason: "Already imported".to_string(),
+            });
+            continue;
+        }
+
+        let document_id = watcher::ingest(app, pool, &source, category_id, Some(session)).await?;
+        report.documents_added += 1;
+        report.attachments_added += 1;
+        report.document_ids.push(IdMapping {
+            import_id: file.index as i64,
+            target_id: Some(document_id),
+        });
+    }
+
+    let mut paths: Vec<(Vec<String>, i64, bool)> = folders
+        .into_iter()
+        .map(|(path, (id, created))| (path, id, created))
+        .collect();
+    paths.sort();
+    Ok(ZipImportReport {
+        categories: zip_category_tree(&paths, &[]),
+        import: report,
+    })
+}
+
+// The child of `parent_id` called `name`, created if there is none yet
+async fn zip_folder_category(
+    pool: &SqlitePool,
+    parent_id: i64,
+    name: &str,
+    session: i64,
+) -> CmdResult<(i64, bool)> {
+    let existing: Option<(i64,)> = sqlx::query_as(
+        "SELECT id FROM categories WHERE parent_id = ? AND name = ? COLLATE NOCASE LIMIT 1",
+    )
+    .bind(parent_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    if let Some((id,)) = existing {
+        return Ok((id, false));
+    }
+
+    let (level,): (i64,) = sqlx::query_as("SELECT level FROM categories WHERE id = ?")
+        .bind(parent_id)
+        .fetch_one(pool)
+        .await?;
+    let id = sqlx::query(
+        "INSERT INTO categories (name, parent_id, level, import_session) VALUES (?, ?, ?, ?)",
+    )
+    .bind(name)
+    .bind(parent_id)
+    .bind(level + 1)
+    .bind(session)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+    Ok((id, true))
+}
+
+fn zip_category_tree(paths: &[(Vec<String>, i64, bool)], parent: &[String]) -> Vec<ZipCategory> {
+    paths
+        .iter()
+        .filter(|(path, _, _)| path.len() == parent.len() + 1 && path.starts_with(parent))
+        .map(|(path, id, created)| ZipCategory {
+            id: *id,
+            name: path[parent.len()].clone(),
+            created: *created,
+            children: zip_category_tree(paths, path),
+        })
+        .collect()
+}
+
+async fn plan_archive(
+    pool: &SqlitePool,
+    contents: &ArchiveContents,
+    zip: &ArchiveReader,
+    resolution: ConflictResolution,
+) -> CmdResult<Plan> {
+    let mut report = ImportReport {
+        export_type: contents.metadata.export_type.clone(),
+        ..Default::default()
+    };
+
+    let existing: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM categories")
+        .fetch_all(pool)
+        .await?;
+    let existing: HashMap<String, i64> = existing
+        .into_iter()
+        .map(|(id, name)| (name.to_lowercase(), id))
+        .collect();
+
+    let mut categories = Vec::with_capacity(contents.categories.len());
+    let mut category_targets: HashMap<i64, Option<i64>> = HashMap::new();
+    for category in &contents.categories {
+        let action = match existing.get(&category.name.to_lowercase()) {
+            Some(&existing_id) => {
+                report.conflicts.push(ImportConflict {
+                    kind: "category",
+                    import_id: category.id,
+                    existing_id,
+                    name: category.name.clone(),
+                    reason: "Category with same name already exists".to_string(),
+                    resolution: resolution.categories,
+                });
+                if resolution.categories == Resolution::Skip {
+                    Action::Reuse(existing_id)
+                } else {
+                    report.categories_updated += 1;
+                    Action::Update(existing_id)
+                }
+            }
+            None => {
+                report.categories_added += 1;
+                Action::Create
+            }
+        };
+
+        let target_id = match action {
+            Action::Create => None,
+            Action::Reuse(id) | Action::Update(id) => Some(id),
+        };
+        category_targets.insert(category.id, target_id);
+        report.category_ids.push(IdMapping {
+            import_id: category.id,
+            target_id,
+        });
+        categories.push(action);
+    }
+
+    let mut documents = Vec::with_capacity(contents.documents.len());
+    let mut imported_documents = HashSet::new();
+    for document in &contents.documents {
+        let Some(&target_category) = document
+            .category_id
+            .and_then(|id| category_targets.get(&id))
+        else {
+            report.skipped.push(ImportSkip {
+                kind: "document",
+                name: document.title.clone(),
+                reason: "Category not included in the archive".to_string(),
+            });
+            documents.push(None);
+            continue;
+        };
+
+        // A category created by this import has no documents to clash with
+        let existing_id = match target_category {
+            Some(category_id) => sqlx::query_as::<_, (i64,)>(
+                "SELECT id FROM documents WHERE category_id = ? AND title = ? COLLATE NOCASE LIMIT 1",
+            )
+            .bind(category_id)
+            .bind(&document.title)
+            .fetch_optional(pool)
+            .await?
+            .map(|(id,)| id),
+            None => None,
+        };
+
+        let action = match existing_id {
+            Some(existing_id) => {
+                report.conflicts.push(ImportConflict {
+                    kind: "document",
+                    import_id: document.id,
+                    existing_id,
+                    name: document.title.clone(),
+                    reason: "Document with same title already exists in category".to_string(),
+                    resolution: resolution.documents,
+                });
+                if resolution.documents == Resolution::Skip {
+                    report.skipped.push(ImportSkip {
+                        kind: "document",
+                        name: document.title.clone(),
+                        reason: "Kept the existing document".to_string(),
+                    });
+                    documents.push(None);
+                    continue;
+                }
+                report.documents_updated += 1;
+                Action::Update(existing_id)
+            }
+            None => {
+                report.documents_added += 1;
+                Action::Create
+            }
+        };
+
+        imported_documents.insert(document.id);
+        report.document_ids.push(IdMapping {
+            import_id: document.id,
+            target_id: existing_id,
+        });
+        documents.push(Some(action));
+    }
+
+    let mut attachments = Vec::with_capacity(contents.attachments.len());
+    for attachment in &contents.attachments {
+        let reason = if !imported_documents.contains(&attachment.document_id) {
+            Some("Its document is not imported")
+        } else if zip.find(&attachment.export_path).is_none() {
+            Some("File missing from archive")
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => {
+                report.skipped.push(ImportSkip {
+                    kind: "attachment",
+                    name: attachment.filename.clone(),
+                    reason: reason.to_string(),
+                });
+                attachments.push(false);
+            }
+            None => {
+                report.attachments_added += 1;
+                attachments.push(true);
+            }
+        }
+    }
+
+    Ok(Plan {
+        categories,
+        documents,
+        attachments,
+        replace_attachments: resolution.documents == Resolution::Replace,
+        report,
+    })
+}
+
+async fn execute_archive(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    path: &str,
+    contents: ArchiveContents,
+    zip: ArchiveReader,
+    mut plan: Plan,
+    job: Option<&JobContext>,
+) -> CmdResult<ImportReport> {
+    let mut files = ImportFiles::default();
+
+    let mut tx = pool.begin().await?;
+    plan.report.session_id = Some(begin_session(&mut *tx, "archive", path).await?);
+    let outcome = write_plan(app, &mut tx, &contents, zip, plan, &mut files, job).await;
+
+    let report = match outcome {
+        Ok(report) => report,
+        Err(e) => {
+            // The transaction rolls back on drop; the copied files go too
+            for file in &files.written {
+                let _ = fs::remove_file(file);
+            }
+            return Err(e);
+        }
+    };
+
+    audit::record(&mut *tx, "import", "archive", None, path).await?;
+    tx.commit().await?;
+
+    for file in files.replaced {
+        let _ = fs::remove_file(file);
+    }
+
+    Ok(report)
+}
+
+async fn write_plan(
+    app: &AppHandle,
+    tx: &mut Transaction<'_, Sqlite>,
+    contents: &ArchiveContents,
+    zip: ArchiveReader,
+    mut plan: Plan,
+    files: &mut ImportFiles,
+    job: Option<&JobContext>,
+) -> CmdResult<ImportReport> {
+    let total = plan.documents.iter().flatten().count()
+        + plan.attachments.iter().filter(|&&import| import).count();
+    let mut processed = 0;
+    let mut advance = || {
+        processed += 1;
+        if let Some(job) = job {
+            job.progress(processed, total);
+        }
+    };
+
+    let session = plan.report.session_id;
+    let mut category_ids = HashMap::new();
+    for (category, action) in contents.categories.iter().zip(&plan.categories) {
+        let icon = category.icon.as_deref().unwrap_or("folder");
+        let color = category.color.as_deref().unwrap_or("#6B7280");
+
+        let id = match *action {
+            Action::Reuse(id) => id,
+            Action::Update(id) => {
+                sqlx::query("UPDATE categories SET name = ?, icon = ?, color = ? WHERE id = ?")
+                    .bind(&category.name)
+                    .bind(icon)
+                    .bind(color)
+                    .bind(id)
+                    .execute(&mut **tx)
+                    .await?;
+                id
+            }
+            Action::Create => {
+                // Archives list parents before their children
+                let parent_id = category
+                    .parent_id
+                    .and_then(|parent| category_ids.get(&parent).copied());
+                let level = match parent_id {
+                    Some(parent) => {
+                        let (level,): (i64,) =
+                            sqlx::query_as("SELECT level FROM categories WHERE id = ?")
+                                .bind(parent)
+                                .fetch_one(&mut **tx)
+                                .await?;
+                        level + 1
+                    }
+                    None => 0,
+                };
+
+                sqlx::query(
+                    "INSERT INTO categories
+                       (name, icon, color, parent_id, description, level, import_session)
+                     VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&category.name)
+                .bind(icon)
+                .bind(color)
+                .bind(parent_id)
+                .bind(&category.description)
+                .bind(level)
+                .bind(session)
+                .execute(&mut **tx)
+                .await?
+                .last_insert_rowid()
+            }
+        };
+        category_ids.insert(category.id, id);
+    }
+    for mapping in &mut plan.report.category_ids {
+        mapping.target_id = category_ids.get(&mapping.import_id).copied();
+    }
+
+    let mut document_ids = HashMap::new();
+    for (document, action) in contents.documents.iter().zip(&plan.documents) {
+        let Some(action) = action else {
+            continue;
+        };
+
+        let id = match *action {
+            Action::Reuse(id) => id,
+            Action::Create => {
+                let category_id = document
+                    .category_id
+                    .and_then(|id| category_ids.get(&id))
+                    .copied();
+                sqlx::query(
+                    "INSERT INTO documents
+                       (title, description, text_content, category_id, import_session)
+                     VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(&document.title)
+                .bind(&document.description)
+                .bind(&document.text_content)
+                .bind(category_id)
+                .bind(session)
+                .execute(&mut **tx)
+                .await?
+                .last_insert_rowid()
+            }
+            Action::Update(id) => {
+                sqlx::query(
+                    "UPDATE documents SET title = ?, description = ?, text_content = ?, updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?",
+                )
+                .bind(&document.title)
+                .bind(&document.description)
+                .bind(&document.text_content)
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+
+                if plan.replace_attachments {
+                    let old_files: Vec<(String,)> = sqlx::query_as(
+                        "SELECT filepath FROM attachments WHERE document_id = ?
+                         UNION
+                         SELECT v.filepath FROM attachment_versions v
+                         JOIN attachments a ON a.id = v.attachment_id
+                         WHERE a.document_id = ?",
+                    )
+                    .bind(id)
+                    .bind(id)
+                    .fetch_all(&mut **tx)
+                    .await?;
+                    sqlx::query("DELETE FROM attachments WHERE document_id = ?")
+                        .bind(id)
+                        .execute(&mut **tx)
+                        .await?;
+                    files
+                        .replaced
+                        .extend(old_files.into_iter().map(|(path,)| path));
+                }
+                id
+            }
+        };
+        document_ids.insert(document.id, id);
+        advance();
+    }
+    for mapping in &mut plan.report.document_ids {
+        mapping.target_id = document_ids.get(&mapping.import_id).copied();
+    }
+
+    let zip = Arc::new(Mutex::new(zip));
+    for (attachment, import) in contents.attachments.iter().zip(&plan.attachments) {
+        if !import {
+            continue;
+        }
+        let document_id = document_ids[&attachment.document_id];
+
+        let dir = db::attachments_dir(app, document_id)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let dest = dir.join(format!("{}_{}", millis, attachment.filename));
+
+        let zip = Arc::clone(&zip);
+        let export_path = attachment.export_path.clone();
+        let target = dest.clone();
+        tauri::async_runtime::spawn_blocking(move || extract(&zip, &export_path, &target))
+            .await??;
+        files.written.push(dest.clone());
+
+        let phash = if attachment.filetype.starts_with("image/") {
+            attachments::image_hash(dest.clone()).await
+        } else {
+            None
+        };
+
+        sqlx::query(
+            "INSERT INTO attachments
+               (document_id, filename, filepath, filetype, filesize, sort_order, phash,
+                import_session)
+             VALUES (?, ?, ?, ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM attachments WHERE document_id = ?), ?, ?)",
+        )
+        .bind(document_id)
+        .bind(&attachment.filename)
+        .bind(dest.to_string_lossy().to_string())
+        .bind(&attachment.filetype)
+        .bind(attachment.filesize)
+        .bind(document_id)
+        .bind(phash)
+        .bind(session)
+        .execute(&mut **tx)
+        .await?;
+        advance();
+    }
+
+    Ok(plan.report)
+}
+
+const DEFAULT_SESSION_LIMIT: u32 = 50;
+const MAX_SESSION_LIMIT: u32 = 500;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ImportSession {
+    pub id: i64,
+    /// `archive`, `vault`, `folder`, `drop`, `csv` or `zip`.
+    pub kind: String,
+    /// The file or folder imported, or the dropped paths.
+    pub source: String,
+    pub started_at: String,
+    pub undone_at: Option<String>,
+    /// What the import created that is still there.
+    pub documents: i64,
+    pub categories: i64,
+    pub attachments: i64,
+}
+
+#[derive(Serialize)]
+pub struct UndoneImport {
+    pub session_id: i64,
+    pub documents_trashed: usize,
+    pub categories_removed: usize,
+    /// Categories the import created that hold something else by now.
+    pub categories_kept: usize,
+    /// Attachments the import added to documents it didn't create.
+    pub attachments_removed: usize,
+}
+
+// Opened by every import that writes, so `undo_import` can find what it
+// created
+async fn begin_session<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    kind: &str,
+    source: &str,
+) -> CmdResult<i64> {
+    let id = sqlx::query("INSERT INTO import_sessions (kind, source) VALUES (?, ?)")
+        .bind(kind)
+        .bind(source)
+        .execute(executor)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+/// Recent imports, newest first, with what each created that is still
+/// there.
+#[tauri::command]
+pub async fn list_import_sessions(
+    app: AppHandle,
+    limit: Option<u32>,
+) -> CmdResult<Vec<ImportSession>> {
+    let pool = db::pool(&app).await?;
+
+    let sessions = sqlx::query_as(
+        "SELECT s.id, s.kind, s.source, s.started_at, s.undone_at,
+           (SELECT COUNT(*) FROM documents d WHERE d.import_session = s.id) AS documents,
+           (SELECT COUNT(*) FROM categories c WHERE c.import_session = s.id) AS categories,
+           (SELECT COUNT(*) FROM attachments a WHERE a.import_session = s.id) AS attachments
+         FROM import_sessions s
+         ORDER BY s.id DESC LIMIT ?",
+    )
+    .bind(
+        limit
+            .unwrap_or(DEFAULT_SESSION_LIMIT)
+            .min(MAX_SESSION_LIMIT),
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Undoes an import in one go: the documents it created move to the trash,
+/// where `restore_document` can still bring them back, attachments it
+/// added to other documents are detached, and the categories it created
+/// are removed unless something else is in them by now. Documents it
+/// updated keep the changes, and attachments a replacing import removed
+/// stay gone.
+#[tauri::command]
+pub async fn undo_import(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    session_id: i64,
+    confirmed: Option<bool>,
+) -> CmdResult<UndoneImport> {
+    store.get().require_confirmation(confirmed)?;
+    let pool = db::pool(&app).await?;
+
+    let mut tx = pool.begin().await?;
+    let session: Option<(String, Option<String>)> =
+        sqlx::query_as("SELECT source, undone_at FROM import_sessions WHERE id = ?")
+            .bind(session_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let source = match session {
+        None => return Err(AppError::NotFound("Import not found".to_string())),
+        Some((_, Some(_))) => {
+            return Err(AppError::Conflict(
+                "This import was undone already".to_string(),
+            ))
+        }
+        Some((source, None)) => source,
+    };
+
+    // Those of the import's own documents go to the trash with them
+    let added: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT a.id, a.document_id FROM attachments a
+         JOIN documents d ON d.id = a.document_id
+         WHERE a.import_session = ? AND d.import_session IS NOT ?",
+    )
+    .bind(session_id)
+    .bind(session_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut paths = Vec::new();
+    for (id, _) in &added {
+        paths.extend(attachments::stored_paths(&mut tx, *id).await?);
+        sqlx::query("DELETE FROM attachments WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let paths = attachments::unreferenced(&mut tx, paths).await?;
+
+    let documents: Vec<(i64,)> =
+        sqlx::query_as("SELECT id FROM documents WHERE import_session = ? ORDER BY id")
+            .bind(session_id)
+            .fetch_all(&mut *tx)
+            .await?;
+    let document_ids: Vec<i64> = documents.into_iter().map(|(id,)| id).collect();
+    trash_documents(&mut tx, &document_ids).await?;
+
+    // Deepest first, so a parent is empty once its children are gone
+    let categories: Vec<(i64,)> = sqlx::query_as(
+        "SELECT id FROM categories WHERE import_session = ? ORDER BY level DESC, id DESC",
+    )
+    .bind(session_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut removed_categories = Vec::new();
+    for (id,) in &categories {
+        let removed = sqlx::query(
+            "DELETE FROM categories WHERE id = ?
+               AND NOT EXISTS (SELECT 1 FROM documents WHERE category_id = ?)
+               AND NOT EXISTS (SELECT 1 FROM categories WHERE parent_id = ?)",
+        )
+        .bind(id)
+        .bind(id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if removed > 0 {
+            removed_categories.push(*id);
+        }
+    }
+
+    sqlx::query("UPDATE import_sessions SET undone_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(&mut *tx, "undo_import", "import", Some(session_id), &source).await?;
+    tx.commit().await?;
+
+    for path in paths {
+        if let Err(e) = fs::remove_file(&path) {
+            log::warn!("Failed to remove {}: {}", path, e);
+        }
+    }
+
+    let attached_to: HashSet<i64> = added.iter().map(|(_, document_id)| *document_id).collect();
+    for document_id in attached_to {
+        let _ = app.emit("attachment_removed", AttachmentEvent { document_id });
+    }
+    if !document_ids.is_empty() {
+        let _ = app.emit(
+            "documents_deleted",
+            DocumentsEvent {
+                document_ids: document_ids.clone(),
+            },
+        );
+    }
+    for category_id in &removed_categories {
+        let _ = app.emit(
+            "category_deleted",
+            CategoryEvent {
+                category_id: *category_id,
+            },
+        );
+    }
+
+    Ok(UndoneImport {
+        session_id,
+        documents_trashed: document_ids.len(),
+        categories_removed: removed_categories.len(),
+        categories_kept: categories.len() - removed_categories.len(),
+        attachments_removed: added.len(),
+    })
+}
+
+pub(crate) fn extract(zip: &Mutex<ArchiveReader>, name: &str, dest: &Path) -> CmdResult<()> {
+    let mut zip = zip.lock().unwrap();
+    let entry = zip
+        .find(name)
+        .ok_or_else(|| AppError::Validation(format!("Archive is missing {}", name)))?;
+    let bytes = zip.read(&entry)?;
+
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(dest, bytes).map_err(AppError::from)
+}
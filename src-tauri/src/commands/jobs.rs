@@ -0,0 +1,50 @@
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use crate::error::CmdResult;
+use crate::jobs::Jobs;
+use crate::power::{self, Power, PowerMode};
+
+#[derive(Serialize)]
+pub struct PowerState {
+    pub mode: PowerMode,
+    pub on_battery: bool,
+    pub percent: Option<u8>,
+    /// Whether background jobs are held back right now.
+    pub paused: bool,
+}
+
+/// Asks a running background job to stop; returns false if it already ended.
+#[tauri::command]
+pub fn cancel_job(jobs: State<'_, Jobs>, job_id: u64) -> bool {
+    jobs.cancel(job_id)
+}
+
+/// Switches how background jobs react to the power supply for the rest of
+/// the session: `auto` pauses them on battery as configured in settings,
+/// `performance` never does and `saver` always does. Takes effect at once.
+#[tauri::command]
+pub async fn set_power_mode(
+    app: AppHandle,
+    power: State<'_, Power>,
+    mode: PowerMode,
+) -> CmdResult<PowerState> {
+    power.set_mode(mode);
+    Ok(power_state(&app, &power).await)
+}
+
+/// The power mode, power supply and whether background jobs are paused.
+#[tauri::command]
+pub async fn get_power_state(app: AppHandle, power: State<'_, Power>) -> CmdResult<PowerState> {
+    Ok(power_state(&app, &power).await)
+}
+
+async fn power_state(app: &AppHandle, power: &Power) -> PowerState {
+    let status = power::update(app).await;
+    PowerState {
+        mode: power.mode(),
+        on_battery: status.on_battery,
+        percent: status.percent,
+        paused: power.paused(),
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::{normalize_name, DocumentSort};
+use crate::db;
+use crate::error::{AppError, CmdResult};
+
+// Columns every document has; custom fields are `field:<key>`
+const BUILTIN_COLUMNS: &[&str] = &[
+    "title",
+    "category",
+    "tags",
+    "created",
+    "updated",
+    "word_count",
+    "snippet",
+    "attachments",
+];
+const FIELD_PREFIX: &str = "field:";
+const MIN_WIDTH: u32 = 24;
+const MAX_WIDTH: u32 = 4000;
+
+#[derive(Serialize)]
+pub struct ListLayout {
+    pub name: String,
+    pub columns: Vec<String>,
+    /// In pixels, one per column.
+    pub widths: Vec<u32>,
+    pub sort: DocumentSort,
+    pub updated_at: String,
+}
+
+// What the `layout` column holds; the name and time have columns of their own
+#[derive(Serialize, Deserialize)]
+struct StoredLayout {
+    columns: Vec<String>,
+    widths: Vec<u32>,
+    sort: DocumentSort,
+}
+
+#[derive(Serialize)]
+pub struct AppliedLayout {
+    pub layout: ListLayout,
+    /// Field columns left out because no document has the field anymore.
+    pub dropped_columns: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct LayoutApplied {
+    name: String,
+}
+
+/// Saves the columns, widths and sort of the document list under `name`,
+/// replacing a layout of the same name. Columns are the built-in ones or
+/// `field:<key>` for a custom field some document has.
+#[tauri::command]
+pub async fn save_list_layout(
+    app: AppHandle,
+    name: String,
+    columns: Vec<String>,
+    widths: Vec<u32>,
+    sort: DocumentSort,
+) -> CmdResult<ListLayout> {
+    let name = normalize_name(&name);
+    if name.is_empty() {
+        return Err(AppError::Validation("Layout name is required".to_string()));
+    }
+    if columns.is_empty() {
+        return Err(AppError::Validation(
+            "A layout needs at least one column".to_string(),
+        ));
+    }
+    if widths.len() != columns.len() {
+        return Err(AppError::Validation(
+            "Every column needs exactly one width".to_string(),
+        ));
+    }
+    if let Some(width) = widths
+        .iter()
+        .find(|width| !(MIN_WIDTH..=MAX_WIDTH).contains(*width))
+    {
+        return Err(AppError::Validation(format!(
+            "Column width {} is not between {} and {}",
+            width, MIN_WIDTH, MAX_WIDTH
+        )));
+    }
+    let mut seen = HashSet::new();
+    if let Some(column) = columns.iter().find(|column| !seen.insert(column.as_str())) {
+        return Err(AppError::Validation(format!(
+            "Column {} is listed twice",
+            column
+        )));
+    }
+
+    let pool = db::pool(&app).await?;
+    let fields = field_keys(&pool).await?;
+    if let Some(column) = columns
+        .iter()
+        .find(|column| !column_exists(column, &fields))
+    {
+        return Err(AppError::Validation(format!("Unknown column: {}", column)));
+    }
+
+    let layout = StoredLayout {
+        columns,
+        widths,
+        sort,
+    };
+    sqlx::query(
+        "INSERT INTO list_layouts (name, layout) VALUES (?, ?)
+         ON CONFLICT (name) DO UPDATE SET
+           name = excluded.name, layout = excluded.layout, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(&name)
+    .bind(serde_json::to_string(&layout)?)
+    .execute(&pool)
+    .await?;
+
+    load_layout(&pool, &name)
+        .await?
+        .ok_or_else(|| AppError::Internal("Saved layout is gone".to_string()))
+}
+
+/// Every saved layout, by name.
+#[tauri::command]
+pub async fn list_layouts(app: AppHandle) -> CmdResult<Vec<ListLayout>> {
+    let pool = db::pool(&app).await?;
+
+    let rows: Vec<(String, String, String)> =
+        sqlx::query_as("SELECT name, layout, updated_at FROM list_layouts ORDER BY name ASC")
+            .fetch_all(&pool)
+            .await?;
+    Ok(rows.into_iter().filter_map(parse_layout).collect())
+}
+
+/// Switches the document list to a saved layout, announced with
+/// `layout_applied`. Columns of fields no document has anymore are left
+/// out of what is returned; the saved layout keeps them.
+#[tauri::command]
+pub async fn apply_layout(app: AppHandle, name: String) -> CmdResult<AppliedLayout> {
+    let pool = db::pool(&app).await?;
+
+    let mut layout = load_layout(&pool, &normalize_name(&name))
+        .await?
+        .ok_or_else(|| AppError::NotFound("Layout not found".to_string()))?;
+    let fields = field_keys(&pool).await?;
+    let mut dropped_columns = Vec::new();
+    let (columns, widths) = layout
+        .columns
+        .drain(..)
+        .zip(layout.widths.drain(..))
+        .filter(|(column, _)| {
+            let exists = column_exists(column, &fields);
+            if !exists {
+                dropped_columns.push(column.clone());
+            }
+            exists
+        })
+        .unzip();
+    layout.columns = columns;
+    layout.widths = widths;
+
+    let _ = app.emit(
+        "layout_applied",
+        LayoutApplied {
+            name: layout.name.clone(),
+        },
+    );
+
+    Ok(AppliedLayout {
+        layout,
+        dropped_columns,
+    })
+}
+
+#[tauri::command]
+pub async fn delete_layout(app: AppHandle, name: String) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let deleted = sqlx::query("DELETE FROM list_layouts WHERE name = ?")
+        .bind(normalize_name(&name))
+        .execute(&pool)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(AppError::NotFound("Layout not found".to_string()));
+    }
+    Ok(())
+}
+
+fn column_exists(column: &str, fields: &HashSet<String>) -> bool {
+    match column.strip_prefix(FIELD_PREFIX) {
+        Some(key) => fields.contains(key),
+        None => BUILTIN_COLUMNS.contains(&column),
+    }
+}
+
+async fn field_keys(pool: &SqlitePool) -> CmdResult<HashSet<String>> {
+    let keys: Vec<(String,)> = sqlx::query_as("SELECT DISTINCT key FROM document_fields")
+        .fetch_all(pool)
+        .await?;
+    Ok(keys.into_iter().map(|(key,)| key).collect())
+}
+
+async fn load_layout(pool: &SqlitePool, name: &str) -> CmdResult<Option<ListLayout>> {
+    let row: Option<(String, String, String)> =
+        sqlx::query_as("SELECT name, layout, updated_at FROM list_layouts WHERE name = ?")
+            .bind(name)
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.and_then(parse_layout))
+}
+
+fn parse_layout((name, layout, updated_at): (String, String, String)) -> Option<ListLayout> {
+    match serde_json::from_str::<StoredLayout>(&layout) {
+        Ok(layout) if layout.widths.len() == layout.columns.len() => Some(ListLayout {
+            name,
+            columns: layout.columns,
+            widths: layout.widths,
+            sort: layout.sort,
+            updated_at,
+        }),
+        Ok(_) => {
+            log::warn!("Skipping layout {}: columns and widths differ", name);
+            None
+        }
+        Err(e) => {
+            log::warn!("Skipping invalid layout {}: {}", name, e);
+            None
+        }
+    }
+}
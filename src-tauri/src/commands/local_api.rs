@@ -0,0 +1,27 @@
+use tauri::AppHandle;
+
+use crate::error::{AppError, CmdResult};
+use crate::local_api;
+
+// The keyring tools can block on an unlock prompt, so they run off the
+// main thread
+
+/// The bearer token for the local API, created on first use, for pasting
+/// into a script or widget.
+#[tauri::command]
+pub async fn get_local_api_token() -> CmdResult<String> {
+    tauri::async_runtime::spawn_blocking(local_api::token)
+        .await?
+        .map_err(AppError::Internal)
+}
+
+/// Replaces the local API token, locking out anything that still has the
+/// old one, and restarts the endpoint with it when running.
+#[tauri::command]
+pub async fn regenerate_local_api_token(app: AppHandle) -> CmdResult<String> {
+    let token = tauri::async_runtime::spawn_blocking(local_api::regenerate_token)
+        .await?
+        .map_err(AppError::Internal)?;
+    tauri::async_runtime::spawn_blocking(move || local_api::restart(&app)).await?;
+    Ok(token)
+}
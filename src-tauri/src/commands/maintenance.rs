@@ -0,0 +1,1311 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Column, Executor, Row, SqlitePool, Statement, TypeInfo, ValueRef};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::collation::{self, EffectiveCollation};
+use crate::commands::{attachments, audit, search};
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::jobs;
+use crate::metrics::{MetricsReport, QueryMetrics};
+use crate::migrations;
+use crate::settings::{Settings, SettingsStore};
+
+/// Timings recorded while `debug_metrics` is on: the most recent calls and
+/// per-command totals, slow calls included. `clear` starts over.
+#[tauri::command]
+pub fn query_metrics(
+    store: State<'_, SettingsStore>,
+    metrics: State<'_, QueryMetrics>,
+    clear: Option<bool>,
+) -> MetricsReport {
+    let report = metrics.report(store.get().debug_metrics);
+    if clear.unwrap_or(false) {
+        metrics.clear();
+    }
+    report
+}
+
+#[derive(Clone, Serialize)]
+pub struct OptimizeReport {
+    pub recompressed: usize,
+    pub bytes_saved: u64,
+}
+
+/// Starts a background job re-encoding PNG/JPEG attachments (JPEG at
+/// `quality`), keeping the new file only when it is smaller. Returns the
+/// job id.
+#[tauri::command]
+pub async fn optimize_attachments(app: AppHandle, quality: u8) -> CmdResult<u64> {
+    if !(1..=100).contains(&quality) {
+        return Err(AppError::Validation(
+            "Quality must be between 1 and 100".to_string(),
+        ));
+    }
+
+    let pool = db::pool(&app).await?;
+
+    let job_id = jobs::spawn(&app, "optimize_attachments", move |job| async move {
+        let images: Vec<(i64, String, String)> = sqlx::query_as(
+            "SELECT id, filepath, filetype FROM attachments
+             WHERE filetype IN ('image/png', 'image/jpeg')
+             ORDER BY id ASC",
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        let total = images.len();
+        let mut report = OptimizeReport {
+            recompressed: 0,
+            bytes_saved: 0,
+        };
+
+        for (index, (id, filepath, filetype)) in images.into_iter().enumerate() {
+            if job.is_cancelled() {
+                break;
+            }
+            job.progress(index, total);
+
+            let path = PathBuf::from(&filepath);
+            let recompressed =
+                tauri::async_runtime::spawn_blocking(move || recompress(&path, &filetype, quality))
+                    .await?;
+
+            let (original_size, bytes) = match recompressed {
+                Ok(Some(result)) => result,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("Skipping attachment {}: {}", id, e);
+                    continue;
+                }
+            };
+
+            // Swap the file in only once the new size is recorded
+            let temp_path = format!("{}.optimizing", filepath);
+            fs::write(&temp_path, &bytes)?;
+
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE attachments SET filesize = ? WHERE id = ?")
+                .bind(bytes.len() as i64)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+
+            if let Err(e) = fs::rename(&temp_path, &filepath) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e.into());
+            }
+            tx.commit().await?;
+
+            report.recompressed += 1;
+            report.bytes_saved += original_size - bytes.len() as u64;
+        }
+
+        job.progress(total, total);
+        Ok(report)
+    });
+
+    Ok(job_id)
+}
+
+// Returns the original size and the re-encoded bytes, or `None` when
+// re-encoding would not shrink the file
+fn recompress(path: &Path, mime: &str, quality: u8) -> CmdResult<Option<(u64, Vec<u8>)>> {
+    let original = fs::read(path)?;
+    let image = image::load_from_memory(&original)?;
+
+    let mut output = Vec::new();
+    match mime {
+        "image/png" => image.write_with_encoder(PngEncoder::new_with_quality(
+            &mut output,
+            CompressionType::Best,
+            FilterType::Adaptive,
+        )),
+        "image/jpeg" => {
+            image.write_with_encoder(JpegEncoder::new_with_quality(&mut output, quality))
+        }
+        _ => return Ok(None),
+    }?;
+
+    if output.len() < original.len() {
+        Ok(Some((original.len() as u64, output)))
+    } else {
+        Ok(None)
+    }
+}
+
+#[derive(Serialize)]
+pub struct PruneReport {
+    pub versions_removed: u64,
+    pub bytes_freed: u64,
+}
+
+/// Drops old document versions, keeping the `keep_last` most recent per
+/// document and, with `older_than_days`, anything newer than the cutoff.
+/// A `None` document id prunes the whole archive. The current version is
+/// never removed.
+#[tauri::command]
+pub async fn prune_versions(
+    app: AppHandle,
+    document_id: Option<i64>,
+    keep_last: u32,
+    older_than_days: Option<u32>,
+) -> CmdResult<PruneReport> {
+    let pool = db::pool(&app).await?;
+
+    let cutoff = older_than_days
+        .map(|days| Utc::now().timestamp() - days as i64 * 86_400)
+        .unwrap_or(i64::MAX);
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "CREATE TEMP TABLE prunable_versions AS
+         SELECT id, COALESCE(LENGTH(CAST(title AS BLOB)), 0)
+                  + COALESCE(LENGTH(CAST(description AS BLOB)), 0)
+                  + COALESCE(LENGTH(CAST(text_content AS BLOB)), 0) AS bytes
+         FROM (
+           SELECT *, ROW_NUMBER() OVER (PARTITION BY document_id ORDER BY id DESC) AS position
+           FROM document_versions
+           WHERE ?1 IS NULL OR document_id = ?1
+         )
+         WHERE position > ?2 AND created_at < ?3",
+    )
+    .bind(document_id)
+    .bind(keep_last.max(1))
+    .bind(cutoff)
+    .execute(&mut *tx)
+    .await?;
+
+    let (bytes_freed,): (i64,) =
+        sqlx::query_as("SELECT COALESCE(SUM(bytes), 0) FROM prunable_versions")
+            .fetch_one(&mut *tx)
+            .await?;
+
+    let removed =
+        sqlx::query("DELETE FROM document_versions WHERE id IN (SELECT id FROM prunable_versions)")
+            .execute(&mut *tx)
+            .await?;
+
+    sqlx::query("DROP TABLE prunable_versions")
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(PruneReport {
+        versions_removed: removed.rows_affected(),
+        bytes_freed: bytes_freed as u64,
+    })
+}
+
+/// Turns the idle maintenance pass (WAL checkpoint, search index optimize,
+/// thumbnail prefetch) on or off and sets how long the app has to be idle
+/// before it runs.
+#[tauri::command]
+pub fn set_idle_maintenance(
+    store: State<'_, SettingsStore>,
+    enabled: bool,
+    idle_minutes: u32,
+) -> CmdResult<Settings> {
+    let mut settings = store.get();
+    settings.idle_maintenance = enabled;
+    settings.idle_minutes = idle_minutes;
+    store.replace(settings.clone())?;
+    Ok(settings)
+}
+
+#[derive(Serialize)]
+pub struct DbTuning {
+    /// As set; `None` leaves SQLite's default.
+    pub cache_mb: Option<u32>,
+    pub mmap_mb: Option<u32>,
+    /// What a connection actually uses, read back from SQLite. The mmap
+    /// size can come out lower than set where SQLite caps it.
+    pub effective_cache_bytes: i64,
+    pub effective_mmap_bytes: i64,
+    pub database_bytes: u64,
+    /// Starting points for this archive's size: a quarter of the file as
+    /// cache within 8 to 256 MiB, and the whole file mapped with room to
+    /// grow, up to 1 GiB. A 100k-document archive of about 800 MB gets
+    /// 200 MiB of cache and 864 MiB of mmap.
+    pub suggested_cache_mb: u32,
+    pub suggested_mmap_mb: u32,
+}
+
+/// The page cache and mmap settings and what the connections use.
+#[tauri::command]
+pub async fn get_db_tuning(app: AppHandle, store: State<'_, SettingsStore>) -> CmdResult<DbTuning> {
+    let pool = db::pool(&app).await?;
+    let settings = store.get();
+    db_tuning(&app, &pool, settings.db_cache_mb, settings.db_mmap_mb).await
+}
+
+/// Sets the page cache and mmap sizes in MiB, `None` for SQLite's default,
+/// and reconnects so they take effect. Larger values trade memory for
+/// speed on large archives; `get_db_tuning` suggests some.
+#[tauri::command]
+pub async fn set_db_tuning(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    cache_mb: Option<u32>,
+    mmap_mb: Option<u32>,
+) -> CmdResult<DbTuning> {
+    let mut settings = store.get();
+    settings.db_cache_mb = cache_mb;
+    settings.db_mmap_mb = mmap_mb;
+    settings.validate()?;
+
+    let pool = db::reconnect(&app, &settings).await?;
+    store.replace(settings)?;
+    db_tuning(&app, &pool, cache_mb, mmap_mb).await
+}
+
+async fn db_tuning(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    cache_mb: Option<u32>,
+    mmap_mb: Option<u32>,
+) -> CmdResult<DbTuning> {
+    let mut conn = pool.acquire().await?;
+    let (cache_size,): (i64,) = sqlx::query_as("PRAGMA cache_size")
+        .fetch_one(&mut *conn)
+        .await?;
+    let (page_size,): (i64,) = sqlx::query_as("PRAGMA page_size")
+        .fetch_one(&mut *conn)
+        .await?;
+    // No row when mmap is compiled out
+    let mmap_size: Option<(i64,)> = sqlx::query_as("PRAGMA mmap_size")
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    let database_bytes = fs::metadata(db::database_path(app)?)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let database_mb = (database_bytes >> 20) as u32;
+
+    Ok(DbTuning {
+        cache_mb,
+        mmap_mb,
+        // Negative sizes are in KiB, positive ones in pages
+        effective_cache_bytes: if cache_size < 0 {
+            -cache_size * 1024
+        } else {
+            cache_size * page_size
+        },
+        effective_mmap_bytes: mmap_size.map_or(0, |(size,)| size),
+        database_bytes,
+        suggested_cache_mb: (database_mb / 4).clamp(8, 256),
+        suggested_mmap_mb: (database_mb + 64).min(1024),
+    })
+}
+
+/// The collation locale and whether titles sort by it yet.
+#[tauri::command]
+pub async fn get_collation(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+) -> CmdResult<EffectiveCollation> {
+    let pool = db::pool(&app).await?;
+    Ok(collation::effective(&pool, store.get().collation_locale).await?)
+}
+
+/// Sets the locale whose alphabet orders titles, e.g. `sv-SE` for å, ä
+/// and ö after z, and reconnects so it takes effect; `None` goes back to
+/// folding case and accents away.
+#[tauri::command]
+pub async fn set_collation_locale(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    locale: Option<String>,
+) -> CmdResult<EffectiveCollation> {
+    let mut settings = store.get();
+    settings.collation_locale = locale
+        .map(|locale| locale.trim().to_string())
+        .filter(|locale| !locale.is_empty());
+    settings.validate()?;
+
+    let pool = db::reconnect(&app, &settings).await?;
+    let locale = settings.collation_locale.clone();
+    store.replace(settings)?;
+    Ok(collation::effective(&pool, locale).await?)
+}
+
+// On top of SQLite's own busy timeout, so a checkpoint never hangs the
+// caller
+const CHECKPOINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckpointMode {
+    /// Copies what it can without waiting on readers or writers.
+    Passive,
+    /// Waits for writers, then copies every frame.
+    Full,
+    /// Like `Full`, then truncates the WAL file to zero bytes.
+    Truncate,
+}
+
+#[derive(Serialize)]
+pub struct CheckpointReport {
+    pub wal_frames: i64,
+    pub frames_checkpointed: i64,
+    pub database_size: u64,
+    pub wal_size: u64,
+}
+
+/// Copies the write-ahead log back into the database, e.g. to shrink a
+/// large WAL after heavy imports. Readers can keep going meanwhile; when
+/// other connections hold the database too long it fails as busy.
+#[tauri::command]
+pub async fn checkpoint_database(
+    app: AppHandle,
+    mode: CheckpointMode,
+) -> CmdResult<CheckpointReport> {
+    let pool = db::pool(&app).await?;
+    let path = db::database_path(&app)?;
+
+    let sql = match mode {
+        CheckpointMode::Passive => "PRAGMA wal_checkpoint(PASSIVE)",
+        CheckpointMode::Full => "PRAGMA wal_checkpoint(FULL)",
+        CheckpointMode::Truncate => "PRAGMA wal_checkpoint(TRUNCATE)",
+    };
+    let busy_error = || {
+        AppError::DbLocked("Database is busy, try again once other work has finished".to_string())
+    };
+    let (busy, wal_frames, frames_checkpointed): (i64, i64, i64) =
+        tokio::time::timeout(CHECKPOINT_TIMEOUT, sqlx::query_as(sql).fetch_one(&pool))
+            .await
+            .map_err(|_| busy_error())??;
+    if busy != 0 {
+        return Err(busy_error());
+    }
+
+    let size_of = |path: &Path| fs::metadata(path).map_or(0, |meta| meta.len());
+    let mut wal_path = path.clone().into_os_string();
+    wal_path.push("-wal");
+
+    Ok(CheckpointReport {
+        wal_frames,
+        frames_checkpointed,
+        database_size: size_of(&path),
+        wal_size: size_of(Path::new(&wal_path)),
+    })
+}
+
+// How many offending ids or paths a check lists before just counting
+const LISTED_PROBLEMS: usize = 10;
+
+#[derive(Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub details: String,
+}
+
+#[derive(Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+/// Read-only health check of the archive: schema version, search index
+/// size, attachment records and files, and the category tree. Every check
+/// runs even when an earlier one fails or errors, and reports what it
+/// found.
+#[tauri::command]
+pub async fn self_test(app: AppHandle) -> CmdResult<SelfTestReport> {
+    let pool = db::pool(&app).await?;
+
+    let checks = vec![
+        ("schema_version", check_schema_version(&pool).await),
+        ("search_index", check_search_index(&pool).await),
+        ("attachment_records", check_attachment_records(&pool).await),
+        (
+            "attachment_files",
+            check_attachment_files(&app, &pool).await,
+        ),
+        ("category_tree", check_category_tree(&pool).await),
+    ];
+    let checks: Vec<CheckResult> = checks
+        .into_iter()
+        .map(|(name, outcome)| match outcome {
+            Ok((passed, details)) => CheckResult {
+                name,
+                passed,
+                details,
+            },
+            Err(e) => CheckResult {
+                name,
+                passed: false,
+                details: format!("Check could not run: {}", e),
+            },
+        })
+        .collect();
+
+    Ok(SelfTestReport {
+        passed: checks.iter().all(|check| check.passed),
+        checks,
+    })
+}
+
+async fn check_schema_version(pool: &SqlitePool) -> CmdResult<(bool, String)> {
+    let (applied,): (Option<i64>,) =
+        sqlx::query_as("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1")
+            .fetch_one(pool)
+            .await?;
+    let applied = applied.unwrap_or(0);
+    let expected = i64::from(migrations::schema_version());
+    Ok((
+        applied == expected,
+        format!(
+            "schema version {}, this build expects {}",
+            applied, expected
+        ),
+    ))
+}
+
+async fn check_search_index(pool: &SqlitePool) -> CmdResult<(bool, String)> {
+    if search::indexing_deferred(pool).await? {
+        return Ok((
+            true,
+            "indexing is deferred by a bulk operation; counts will differ until it resumes"
+                .to_string(),
+        ));
+    }
+    // The docsize shadow table has one row per indexed document
+    let (documents, indexed): (i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM documents), (SELECT COUNT(*) FROM documents_fts_docsize)",
+    )
+    .fetch_one(pool)
+    .await?;
+    let (versions, versions_indexed): (i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM document_versions),
+                (SELECT COUNT(*) FROM document_versions_fts_docsize)",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((
+        documents == indexed && versions == versions_indexed,
+        format!(
+            "{} of {} documents and {} of {} versions indexed",
+            indexed, documents, versions_indexed, versions
+        ),
+    ))
+}
+
+// Every record belongs to a document and has a file of its own, since
+// detaching one deletes its file
+async fn check_attachment_records(pool: &SqlitePool) -> CmdResult<(bool, String)> {
+    let orphaned: Vec<(i64,)> = sqlx::query_as(
+        "SELECT a.id FROM attachments a
+         WHERE NOT EXISTS (SELECT 1 FROM documents d WHERE d.id = a.document_id)
+         ORDER BY a.id",
+    )
+    .fetch_all(pool)
+    .await?;
+    let shared: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT filepath, COUNT(*) FROM attachments GROUP BY filepath HAVING COUNT(*) > 1
+         ORDER BY filepath",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut problems = Vec::new();
+    if !orphaned.is_empty() {
+        let ids: Vec<i64> = orphaned.iter().map(|(id,)| *id).collect();
+        problems.push(format!(
+            "{} without a document ({})",
+            ids.len(),
+            listed(&ids)
+        ));
+    }
+    if !shared.is_empty() {
+        let paths: Vec<String> = shared
+            .iter()
+            .map(|(path, count)| format!("{} x{}", path, count))
+            .collect();
+        problems.push(format!(
+            "{} files shared by several records ({})",
+            paths.len(),
+            listed(&paths)
+        ));
+    }
+    Ok(report_problems(
+        problems,
+        "every attachment record is consistent",
+    ))
+}
+
+// Records whose file is gone, and files under the attachments folder that
+// no record or trashed document refers to
+async fn check_attachment_files(app: &AppHandle, pool: &SqlitePool) -> CmdResult<(bool, String)> {
+    let records: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, filepath FROM attachments ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+    let trashed: Vec<(String,)> = sqlx::query_as(
+        "SELECT json_extract(j.value, '$.filepath')
+         FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j",
+    )
+    .fetch_all(pool)
+    .await?;
+    let root = db::attachments_root(app)?;
+
+    let (missing, unreferenced) = tauri::async_runtime::spawn_blocking(move || {
+        let paths: Vec<String> = records.iter().map(|(_, path)| path.clone()).collect();
+        let missing: Vec<i64> = records
+            .iter()
+            .zip(attachments::files_exist(&paths))
+            .filter(|(_, exists)| !exists)
+            .map(|((id, _), _)| *id)
+            .collect();
+
+        let known: HashSet<PathBuf> = paths
+            .into_iter()
+            .chain(trashed.into_iter().map(|(path,)| path))
+            .map(PathBuf::from)
+            .collect();
+        let mut unreferenced = Vec::new();
+        for folder in fs::read_dir(&root).into_iter().flatten().flatten() {
+            for file in fs::read_dir(folder.path()).into_iter().flatten().flatten() {
+                let path = file.path();
+                if path.is_file() && !known.contains(&path) {
+                    unreferenced.push(path.to_string_lossy().to_string());
+                }
+            }
+        }
+        unreferenced.sort();
+        (missing, unreferenced)
+    })
+    .await?;
+
+    let mut problems = Vec::new();
+    if !missing.is_empty() {
+        problems.push(format!(
+            "{} records with a missing file ({})",
+            missing.len(),
+            listed(&missing)
+        ));
+    }
+    if !unreferenced.is_empty() {
+        problems.push(format!(
+            "{} files no attachment refers to ({})",
+            unreferenced.len(),
+            listed(&unreferenced)
+        ));
+    }
+    Ok(report_problems(
+        problems,
+        "every attachment file is in place",
+    ))
+}
+
+async fn check_category_tree(pool: &SqlitePool) -> CmdResult<(bool, String)> {
+    let rows: Vec<(i64, Option<i64>)> =
+        sqlx::query_as("SELECT id, parent_id FROM categories ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+    let parents: HashMap<i64, Option<i64>> = rows.iter().copied().collect();
+
+    let mut dangling = Vec::new();
+    let mut in_cycle = Vec::new();
+    for (id, parent) in &rows {
+        if parent.is_some_and(|parent| !parents.contains_key(&parent)) {
+            dangling.push(*id);
+            continue;
+        }
+        // Walking up from a category in a cycle comes back to it
+        let mut seen = HashSet::from([*id]);
+        let mut current = *parent;
+        while let Some(next) = current {
+            if next == *id {
+                in_cycle.push(*id);
+                break;
+            }
+            if !seen.insert(next) {
+                break;
+            }
+            current = parents.get(&next).copied().flatten();
+        }
+    }
+
+    let mut problems = Vec::new();
+    if !in_cycle.is_empty() {
+        problems.push(format!(
+            "{} categories in a parent cycle ({})",
+            in_cycle.len(),
+            listed(&in_cycle)
+        ));
+    }
+    if !dangling.is_empty() {
+        problems.push(format!(
+            "{} categories with a missing parent ({})",
+            dangling.len(),
+            listed(&dangling)
+        ));
+    }
+    Ok(report_problems(
+        problems,
+        &format!("{} categories, no cycles", rows.len()),
+    ))
+}
+
+fn report_problems(problems: Vec<String>, healthy: &str) -> (bool, String) {
+    if problems.is_empty() {
+        (true, healthy.to_string())
+    } else {
+        (false, problems.join("; "))
+    }
+}
+
+fn listed<T: ToString>(items: &[T]) -> String {
+    let mut list: Vec<String> = items
+        .iter()
+        .take(LISTED_PROBLEMS)
+        .map(T::to_string)
+        .collect();
+    if items.len() > LISTED_PROBLEMS {
+        list.push(format!("{} more", items.len() - LISTED_PROBLEMS));
+    }
+    list.join(", ")
+}
+
+// Candidates of each kind shown when the archive is over its size limit
+const SUGGESTIONS_PER_KIND: i64 = 10;
+// Versions a suggestion leaves per document
+const SUGGESTED_KEEP_VERSIONS: i64 = 5;
+
+// Whether the last check found the archive over its limit, so the warning
+// is sent once per crossing rather than on every write
+static OVER_LIMIT: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+pub struct SizeBreakdown {
+    /// The database file with its write-ahead log, versions and trashed
+    /// text included.
+    pub database_bytes: u64,
+    pub attachment_bytes: u64,
+    /// Files of trashed documents, kept until the trash is emptied.
+    pub trash_attachment_bytes: u64,
+    /// Part of `database_bytes`: every stored version of every document.
+    pub version_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PruneSuggestion {
+    Attachment {
+        attachment_id: i64,
+        document_id: i64,
+        filename: String,
+        bytes: u64,
+    },
+    /// A trashed document, oldest first among equals.
+    Trash {
+        document_id: i64,
+        title: String,
+        deleted_at: Option<String>,
+        bytes: u64,
+    },
+    /// Versions of a document past the most recent few.
+    Versions {
+        document_id: i64,
+        title: String,
+        versions: i64,
+        bytes: u64,
+    },
+}
+
+impl PruneSuggestion {
+    fn bytes(&self) -> u64 {
+        match self {
+            PruneSuggestion::Attachment { bytes, .. }
+            | PruneSuggestion::Trash { bytes, .. }
+            | PruneSuggestion::Versions { bytes, .. } => *bytes,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ArchiveSize {
+    pub total_bytes: u64,
+    /// `max_archive_mb` in bytes; `None` when no limit is set.
+    pub limit_bytes: Option<u64>,
+    pub over_limit: bool,
+    pub breakdown: SizeBreakdown,
+    /// What could go to get back under the limit, most bytes first. Only
+    /// filled in when over it; nothing is deleted.
+    pub suggestions: Vec<PruneSuggestion>,
+}
+
+#[derive(Clone, Serialize)]
+struct ArchiveSizeWarning {
+    total_bytes: u64,
+    limit_bytes: u64,
+}
+
+/// How much space the archive takes against the `max_archive_mb` soft
+/// limit, broken down by kind. Over the limit, suggests the largest
+/// attachments, trashed documents and old versions to prune.
+#[tauri::command]
+pub async fn check_archive_size(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+) -> CmdResult<ArchiveSize> {
+    let pool = db::pool(&app).await?;
+    let breakdown = size_breakdown(&app, &pool).await?;
+    let total_bytes = total_size(&breakdown);
+    let limit_bytes = store.get().max_archive_mb.map(|mb| mb as u64 * 1024 * 1024);
+    let over_limit = limit_bytes.is_some_and(|limit| total_bytes > limit);
+    OVER_LIMIT.store(over_limit, Ordering::Relaxed);
+
+    let mut suggestions = Vec::new();
+    if over_limit {
+        let attachments: Vec<(i64, i64, String, i64)> = sqlx::query_as(
+            "SELECT id, document_id, filename, COALESCE(filesize, 0) AS bytes FROM attachments
+             ORDER BY bytes DESC, id ASC
+             LIMIT ?",
+        )
+        .bind(SUGGESTIONS_PER_KIND)
+        .fetch_all(&pool)
+        .await?;
+        suggestions.extend(attachments.into_iter().map(
+            |(attachment_id, document_id, filename, bytes)| PruneSuggestion::Attachment {
+                attachment_id,
+                document_id,
+                filename,
+                bytes: bytes as u64,
+            },
+        ));
+
+        let trash: Vec<(i64, String, Option<String>, i64)> = sqlx::query_as(
+            "SELECT dd.id, dd.title, dd.deleted_at,
+               COALESCE(LENGTH(CAST(dd.text_content AS BLOB)), 0)
+                 + COALESCE((SELECT SUM(json_extract(j.value, '$.filesize'))
+                             FROM json_each(COALESCE(dd.attachments, '[]')) j), 0) AS bytes
+             FROM deleted_documents dd
+             ORDER BY bytes DESC, dd.deleted_at ASC
+             LIMIT ?",
+        )
+        .bind(SUGGESTIONS_PER_KIND)
+        .fetch_all(&pool)
+        .await?;
+        suggestions.extend(
+            trash
+                .into_iter()
+                .map(
+                    |(document_id, title, deleted_at, bytes)| PruneSuggestion::Trash {
+                        document_id,
+                        title,
+                        deleted_at,
+                        bytes: bytes as u64,
+                    },
+                ),
+        );
+
+        let versions: Vec<(i64, String, i64, i64)> = sqlx::query_as(
+            "SELECT d.id, d.title, COUNT(*),
+               SUM(COALESCE(LENGTH(CAST(v.title AS BLOB)), 0)
+                   + COALESCE(LENGTH(CAST(v.description AS BLOB)), 0)
+                   + COALESCE(LENGTH(CAST(v.text_content AS BLOB)), 0)) AS bytes
+             FROM (
+               SELECT *, ROW_NUMBER() OVER (PARTITION BY document_id ORDER BY id DESC) AS position
+               FROM document_versions
+             ) v
+             JOIN documents d ON d.id = v.document_id
+             WHERE v.position > ?
+             GROUP BY d.id
+             ORDER BY bytes DESC, d.id ASC
+             LIMIT ?",
+        )
+        .bind(SUGGESTED_KEEP_VERSIONS)
+        .bind(SUGGESTIONS_PER_KIND)
+        .fetch_all(&pool)
+        .await?;
+        suggestions.extend(
+            versions
+                .into_iter()
+                .map(
+                    |(document_id, title, versions, bytes)| PruneSuggestion::Versions {
+                        document_id,
+                        title,
+                        versions,
+                        bytes: bytes as u64,
+                    },
+                ),
+        );
+
+        suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.bytes()));
+    }
+
+    Ok(ArchiveSize {
+        total_bytes,
+        limit_bytes,
+        over_limit,
+        breakdown,
+        suggestions,
+    })
+}
+
+/// Checks the archive against `max_archive_mb` in the background after a
+/// write that grew it, emitting `archive_size_warning` when it has just
+/// gone over.
+pub(crate) fn warn_if_over_limit(app: &AppHandle) {
+    let Some(limit_mb) = app.state::<SettingsStore>().get().max_archive_mb else {
+        return;
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let Ok(pool) = db::pool(&app).await else {
+            return;
+        };
+        let breakdown = match size_breakdown(&app, &pool).await {
+            Ok(breakdown) => breakdown,
+            Err(e) => {
+                log::warn!("Failed to check the archive size: {}", e.message());
+                return;
+            }
+        };
+        let total_bytes = total_size(&breakdown);
+        let limit_bytes = limit_mb as u64 * 1024 * 1024;
+        let over = total_bytes > limit_bytes;
+        if over && !OVER_LIMIT.swap(true, Ordering::Relaxed) {
+            let _ = app.emit(
+                "archive_size_warning",
+                ArchiveSizeWarning {
+                    total_bytes,
+                    limit_bytes,
+                },
+            );
+        } else if !over {
+            OVER_LIMIT.store(false, Ordering::Relaxed);
+        }
+    });
+}
+
+fn total_size(breakdown: &SizeBreakdown) -> u64 {
+    breakdown.database_bytes + breakdown.attachment_bytes + breakdown.trash_attachment_bytes
+}
+
+// Attachment sizes come from their records rather than a walk of the
+// folder, so the check stays cheap enough to run after every write
+async fn size_breakdown(app: &AppHandle, pool: &SqlitePool) -> CmdResult<SizeBreakdown> {
+    let database = db::database_path(app)?;
+    let mut wal = database.clone().into_os_string();
+    wal.push("-wal");
+    let database_bytes = [database, PathBuf::from(wal)]
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+
+    let (attachment_bytes,): (i64,) =
+        sqlx::query_as("SELECT COALESCE(SUM(filesize), 0) FROM attachments")
+            .fetch_one(pool)
+            .await?;
+    let (trash_attachment_bytes,): (i64,) = sqlx::query_as(
+        "SELECT COALESCE(SUM(json_extract(j.value, '$.filesize')), 0)
+         FROM deleted_documents dd, json_each(COALESCE(dd.attachments, '[]')) j",
+    )
+    .fetch_one(pool)
+    .await?;
+    let (version_bytes,): (i64,) = sqlx::query_as(
+        "SELECT COALESCE(SUM(COALESCE(LENGTH(CAST(title AS BLOB)), 0)
+           + COALESCE(LENGTH(CAST(description AS BLOB)), 0)
+           + COALESCE(LENGTH(CAST(text_content AS BLOB)), 0)), 0)
+         FROM document_versions",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(SizeBreakdown {
+        database_bytes,
+        attachment_bytes: attachment_bytes as u64,
+        trash_attachment_bytes: trash_attachment_bytes as u64,
+        version_bytes: version_bytes as u64,
+    })
+}
+
+#[derive(Serialize)]
+pub struct MissingLegacyFile {
+    pub attachment_id: i64,
+    pub filepath: String,
+}
+
+#[derive(Serialize)]
+pub struct LegacyMigration {
+    /// Files copied into the attachments folder, their records updated.
+    pub migrated: usize,
+    /// Relative paths that already pointed inside it, now made absolute.
+    pub rewritten: usize,
+    /// Already in the attachments folder; nothing to do.
+    pub current: usize,
+    pub missing: Vec<MissingLegacyFile>,
+}
+
+/// Brings attachments from early archives into the attachments folder.
+/// Those recorded by a path outside it, usually where the file was picked
+/// from, are copied in and their records pointed at the copy; relative
+/// paths are resolved against the folder. The originals stay where they
+/// are. Safe to run again: attachments already inside are left alone, and
+/// files that are missing are reported and kept as they are.
+#[tauri::command]
+pub async fn migrate_legacy_attachments(app: AppHandle) -> CmdResult<LegacyMigration> {
+    let pool = db::pool(&app).await?;
+    let root = db::attachments_root(&app)?;
+    let report = migrate_attachments(&pool, &root).await?;
+    if report.migrated > 0 {
+        warn_if_over_limit(&app);
+    }
+    Ok(report)
+}
+
+// `migrate_legacy_attachments` for the attachments folder `root`
+async fn migrate_attachments(pool: &SqlitePool, root: &Path) -> CmdResult<LegacyMigration> {
+    let rows: Vec<(i64, i64, String, String)> = sqlx::query_as(
+        "SELECT id, document_id, filename, filepath FROM attachments ORDER BY id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut report = LegacyMigration {
+        migrated: 0,
+        rewritten: 0,
+        current: 0,
+        missing: Vec::new(),
+    };
+    for (id, document_id, filename, filepath) in rows {
+        let recorded = Path::new(&filepath);
+        if recorded.is_absolute() && recorded.starts_with(root) {
+            report.current += 1;
+            continue;
+        }
+        let source = if recorded.is_absolute() {
+            recorded.to_path_buf()
+        } else {
+            root.join(recorded)
+        };
+        let Some(metadata) = fs::metadata(&source)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+        else {
+            report.missing.push(MissingLegacyFile {
+                attachment_id: id,
+                filepath,
+            });
+            continue;
+        };
+
+        if source.starts_with(root) {
+            sqlx::query("UPDATE attachments SET filepath = ? WHERE id = ?")
+                .bind(source.to_string_lossy().to_string())
+                .bind(id)
+                .execute(pool)
+                .await?;
+            report.rewritten += 1;
+            continue;
+        }
+
+        let dir = root.join(document_id.to_string());
+        let dest = attachments::copy_into_store(&dir, &source, &filename, &metadata)?;
+        let updated = sqlx::query("UPDATE attachments SET filepath = ?, filesize = ? WHERE id = ?")
+            .bind(dest.to_string_lossy().to_string())
+            .bind(metadata.len() as i64)
+            .bind(id)
+            .execute(pool)
+            .await;
+        if let Err(e) = updated {
+            let _ = fs::remove_file(&dest);
+            return Err(e.into());
+        }
+        attachments::record_provenance(pool, id, &source, &metadata).await;
+        report.migrated += 1;
+    }
+
+    if report.migrated + report.rewritten > 0 {
+        let details = format!(
+            "{} copied, {} rewritten, {} missing",
+            report.migrated,
+            report.rewritten,
+            report.missing.len()
+        );
+        audit::record(pool, "migrate", "attachments", None, &details).await?;
+    }
+
+    Ok(report)
+}
+
+// Keywords that make a statement need `force`, wherever they appear
+const DESTRUCTIVE_KEYWORDS: &[&str] = &["DROP", "DELETE", "UPDATE", "ALTER", "DETACH", "ATTACH"];
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MaintenanceSqlResult {
+    /// What a query returned. Blobs are base64.
+    Rows {
+        columns: Vec<String>,
+        rows: Vec<Vec<serde_json::Value>>,
+    },
+    Affected {
+        rows_affected: u64,
+    },
+}
+
+/// Runs one SQL statement against the archive database, for support and
+/// troubleshooting. Only available with `developer_mode` on; statements
+/// that drop, delete, rewrite or attach anything also need `force`. Every
+/// call ends up in the audit log, refused ones included.
+#[tauri::command]
+pub async fn run_maintenance_sql(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    sql: String,
+    force: Option<bool>,
+) -> CmdResult<MaintenanceSqlResult> {
+    let pool = db::pool(&app).await?;
+    let force = force.unwrap_or(false);
+
+    let result = if !store.get().developer_mode {
+        Err(AppError::Validation(
+            "run_maintenance_sql requires developer_mode".to_string(),
+        ))
+    } else {
+        match single_statement(&sql) {
+            Ok(statement) if !force && is_destructive(statement) => {
+                Err(AppError::ConfirmationRequired(
+                    "Statement may destroy data; pass force to run it anyway".to_string(),
+                ))
+            }
+            Ok(statement) => execute_statement(&pool, statement).await,
+            Err(e) => Err(e),
+        }
+    };
+
+    let details = serde_json::json!({
+        "sql": sql,
+        "force": force,
+        "result": match &result {
+            Ok(MaintenanceSqlResult::Rows { rows, .. }) => format!("{} rows", rows.len()),
+            Ok(MaintenanceSqlResult::Affected { rows_affected }) => {
+                format!("{} rows affected", rows_affected)
+            }
+            Err(e) => format!("error: {}", e),
+        },
+    });
+    audit::record(
+        &pool,
+        "maintenance_sql",
+        "database",
+        None,
+        &details.to_string(),
+    )
+    .await?;
+
+    result
+}
+
+async fn execute_statement(pool: &SqlitePool, sql: &str) -> CmdResult<MaintenanceSqlResult> {
+    // Preparing tells queries apart from statements that return nothing
+    let statement = pool.prepare(sql).await?;
+    if statement.columns().is_empty() {
+        let done = sqlx::query(sql).execute(pool).await?;
+        return Ok(MaintenanceSqlResult::Affected {
+            rows_affected: done.rows_affected(),
+        });
+    }
+
+    let columns = statement
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect();
+    let rows = sqlx::query(sql)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| (0..row.len()).map(|i| json_value(row, i)).collect())
+        .collect::<CmdResult<_>>()?;
+    Ok(MaintenanceSqlResult::Rows { columns, rows })
+}
+
+fn json_value(row: &SqliteRow, index: usize) -> CmdResult<serde_json::Value> {
+    let raw = row.try_get_raw(index)?;
+    if raw.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+    // The storage class of this value, not the declared column type
+    let value = match raw.type_info().name() {
+        "INTEGER" => row.try_get::<i64, _>(index).map(serde_json::Value::from),
+        "REAL" => row.try_get::<f64, _>(index).map(serde_json::Value::from),
+        "BLOB" => row
+            .try_get::<Vec<u8>, _>(index)
+            .map(|bytes| BASE64.encode(bytes).into()),
+        _ => row.try_get::<String, _>(index).map(serde_json::Value::from),
+    };
+    value.map_err(AppError::from)
+}
+
+// The statement without its trailing semicolon; fails on anything after it
+fn single_statement(sql: &str) -> CmdResult<&str> {
+    let code = code_outside_literals(sql);
+    let statement = match code.find(';') {
+        Some(end) if code[end + 1..].trim().is_empty() => &sql[..end],
+        Some(_) => {
+            return Err(AppError::Validation(
+                "Only one statement can be run at a time".to_string(),
+            ))
+        }
+        None => sql,
+    };
+    if code.trim().trim_end_matches(';').trim().is_empty() {
+        return Err(AppError::Validation("No SQL statement given".to_string()));
+    }
+    Ok(statement.trim())
+}
+
+fn is_destructive(sql: &str) -> bool {
+    let code = code_outside_literals(sql).to_uppercase();
+    let mut words = code.split(|c: char| !c.is_ascii_alphanumeric() && c != '_');
+    let first = code.split_whitespace().next().unwrap_or_default();
+    // `REPLACE INTO`, but not the replace() function; PRAGMA assignments
+    // such as writable_schema
+    first == "REPLACE"
+        || code.contains("OR REPLACE")
+        || (first == "PRAGMA" && code.contains('='))
+        || words.any(|word| DESTRUCTIVE_KEYWORDS.contains(&word))
+}
+
+// `sql` with string literals, quoted identifiers and comments blanked out,
+// keeping byte offsets, so keywords and semicolons inside them are ignored
+fn code_outside_literals(sql: &str) -> String {
+    let mut code = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        let close = match c {
+            '\'' | '"' | '`' => Some(c),
+            '[' => Some(']'),
+            _ => None,
+        };
+        if let Some(close) = close {
+            code.push(' ');
+            for c in chars.by_ref() {
+                code.extend(std::iter::repeat(' ').take(c.len_utf8()));
+                if c == close {
+                    break;
+                }
+            }
+        } else if c == '-' && chars.peek() == Some(&'-') {
+            code.push(' ');
+            for c in chars.by_ref() {
+                code.extend(std::iter::repeat(' ').take(c.len_utf8()));
+                if c == '\n' {
+                    break;
+                }
+            }
+        } else if c == '/' && chars.peek() == Some(&'*') {
+            code.push(' ');
+            let mut previous = ' ';
+            for c in chars.by_ref() {
+                code.extend(std::iter::repeat(' ').take(c.len_utf8()));
+                if previous == '*' && c == '/' {
+                    break;
+                }
+                previous = c;
+            }
+        } else {
+            code.push(c);
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_attachment(pool: &SqlitePool, id: i64, filepath: &Path) {
+        sqlx::query(
+            "INSERT INTO attachments (id, document_id, filename, filepath, filetype, filesize)
+             VALUES (?, 1, ?, ?, 'text/plain', 0)",
+        )
+        .bind(id)
+        .bind(filepath.file_name().unwrap().to_string_lossy().to_string())
+        .bind(filepath.to_string_lossy().to_string())
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn migrates_a_legacy_database_once() {
+        let base = std::env::temp_dir().join(format!("ando-legacy-{}", std::process::id()));
+        let root = base.join("attachments");
+        let picked = base.join("picked");
+        fs::create_dir_all(root.join("1")).unwrap();
+        fs::create_dir_all(&picked).unwrap();
+        fs::write(picked.join("outside.txt"), "outside").unwrap();
+        fs::write(root.join("1").join("relative.txt"), "relative").unwrap();
+        fs::write(root.join("1").join("inside.txt"), "inside").unwrap();
+
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query("INSERT INTO documents (id, title) VALUES (1, 'Legacy')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            insert_attachment(&pool, 1, &picked.join("outside.txt")).await;
+            insert_attachment(&pool, 2, Path::new("1/relative.txt")).await;
+            insert_attachment(&pool, 3, &root.join("1").join("inside.txt")).await;
+            insert_attachment(&pool, 4, &picked.join("gone.txt")).await;
+
+            let report = migrate_attachments(&pool, &root).await.unwrap();
+            assert_eq!(
+                (report.migrated, report.rewritten, report.current),
+                (1, 1, 1)
+            );
+            assert_eq!(report.missing.len(), 1);
+            assert_eq!(report.missing[0].attachment_id, 4);
+
+            let paths: Vec<(i64, String)> =
+                sqlx::query_as("SELECT id, filepath FROM attachments ORDER BY id")
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+            let copied = PathBuf::from(&paths[0].1);
+            assert!(copied.starts_with(root.join("1")));
+            assert_eq!(fs::read_to_string(&copied).unwrap(), "outside");
+            assert!(picked.join("outside.txt").exists(), "original removed");
+            assert_eq!(
+                PathBuf::from(&paths[1].1),
+                root.join("1").join("relative.txt")
+            );
+            assert_eq!(paths[3].1, picked.join("gone.txt").to_string_lossy());
+
+            let (original,): (String,) = sqlx::query_as(
+                "SELECT original_path FROM attachment_provenance WHERE attachment_id = 1",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            assert_eq!(original, picked.join("outside.txt").to_string_lossy());
+
+            let again = migrate_attachments(&pool, &root).await.unwrap();
+            assert_eq!((again.migrated, again.rewritten, again.current), (0, 0, 3));
+            assert_eq!(again.missing.len(), 1);
+        });
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,38 @@
+pub mod api;
+pub mod archive;
+pub mod archive_meta;
+pub mod attachments;
+pub mod audit;
+pub mod autocomplete;
+pub mod backup;
+pub mod capture;
+pub mod categories;
+pub mod changes;
+pub mod deep_link;
+pub mod demo;
+pub mod digest;
+pub mod documents;
+pub mod editor;
+pub mod fields;
+pub mod import;
+pub mod jobs;
+pub mod layouts;
+pub mod local_api;
+pub mod maintenance;
+pub mod notes;
+pub mod references;
+pub mod related;
+pub mod reminders;
+pub mod review;
+pub mod seal;
+pub mod search;
+pub mod secrets;
+pub mod sensitive;
+pub mod settings;
+pub mod switcher;
+pub mod tabs;
+pub mod tags;
+pub mod taxonomy;
+pub mod thumbnails;
+pub mod toc;
+pub mod watcher;
@@ -0,0 +1,114 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::review::document_exists;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+
+// Notes comment on a document without touching its body, so they stay out
+// of the body, its versions and exports; `search_documents` only looks at
+// them with `include_notes`.
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DocumentNote {
+    pub id: i64,
+    pub document_id: i64,
+    pub text: String,
+    pub created_at: String,
+    /// `None` until the note is edited.
+    pub updated_at: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct NotesEvent {
+    document_id: i64,
+}
+
+/// Adds a note to the document, e.g. "received the original by mail".
+#[tauri::command]
+pub async fn add_note(app: AppHandle, document_id: i64, text: String) -> CmdResult<DocumentNote> {
+    let text = note_text(&text)?;
+    if !document_exists(&app, document_id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let pool = db::pool(&app).await?;
+    let note = sqlx::query_as(
+        "INSERT INTO document_notes (document_id, text) VALUES (?, ?)
+         RETURNING id, document_id, text, created_at, updated_at",
+    )
+    .bind(document_id)
+    .bind(&text)
+    .fetch_one(&pool)
+    .await?;
+
+    let _ = app.emit("notes_changed", NotesEvent { document_id });
+
+    Ok(note)
+}
+
+/// Notes of a document, oldest first.
+#[tauri::command]
+pub async fn list_notes(app: AppHandle, document_id: i64) -> CmdResult<Vec<DocumentNote>> {
+    let pool = db::pool(&app).await?;
+
+    sqlx::query_as(
+        "SELECT id, document_id, text, created_at, updated_at FROM document_notes
+         WHERE document_id = ? ORDER BY id ASC",
+    )
+    .bind(document_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn update_note(app: AppHandle, id: i64, text: String) -> CmdResult<DocumentNote> {
+    let text = note_text(&text)?;
+    let pool = db::pool(&app).await?;
+
+    let note: DocumentNote = sqlx::query_as(
+        "UPDATE document_notes SET text = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?
+         RETURNING id, document_id, text, created_at, updated_at",
+    )
+    .bind(&text)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Note not found".to_string()))?;
+
+    let _ = app.emit(
+        "notes_changed",
+        NotesEvent {
+            document_id: note.document_id,
+        },
+    );
+
+    Ok(note)
+}
+
+#[tauri::command]
+pub async fn delete_note(app: AppHandle, id: i64) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let removed: Option<(i64,)> =
+        sqlx::query_as("DELETE FROM document_notes WHERE id = ? RETURNING document_id")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?;
+    let Some((document_id,)) = removed else {
+        return Err(AppError::NotFound("Note not found".to_string()));
+    };
+
+    let _ = app.emit("notes_changed", NotesEvent { document_id });
+
+    Ok(())
+}
+
+fn note_text(text: &str) -> CmdResult<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(AppError::Validation("Note cannot be empty".to_string()));
+    }
+    Ok(text.to_string())
+}
@@ -0,0 +1,193 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::{normalize_name, DocumentEvent};
+use crate::commands::review::document_exists;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+
+const TITLE_TIMEOUT: Duration = Duration::from_secs(5);
+// The title is in the head, well before this
+const TITLE_MAX_BYTES: u64 = 256 * 1024;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct LinkReference {
+    pub id: i64,
+    pub document_id: i64,
+    pub url: String,
+    pub title: Option<String>,
+    pub created_at: String,
+}
+
+struct Url<'a> {
+    scheme: &'a str,
+    host: &'a str,
+    port: u16,
+    // Path and query, starting with `/`
+    target: &'a str,
+}
+
+/// Links a web page to the document without downloading it. Without a
+/// `title`, the page's own is looked up, which only works for `http://`
+/// URLs since this build has no TLS; otherwise the link is stored
+/// untitled.
+#[tauri::command]
+pub async fn add_link_reference(
+    app: AppHandle,
+    document_id: i64,
+    url: String,
+    title: Option<String>,
+) -> CmdResult<LinkReference> {
+    let url = url.trim().to_string();
+    parse_url(&url).ok_or_else(|| AppError::Validation(format!("Not a web address: {}", url)))?;
+    if !document_exists(&app, document_id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let title = match title
+        .map(|title| normalize_name(&title))
+        .filter(|title| !title.is_empty())
+    {
+        Some(title) => Some(title),
+        None => {
+            let page = url.clone();
+            tauri::async_runtime::spawn_blocking(move || fetch_title(&page))
+                .await
+                .ok()
+                .flatten()
+        }
+    };
+
+    let pool = db::pool(&app).await?;
+    let reference = sqlx::query_as(
+        "INSERT INTO link_references (document_id, url, title) VALUES (?, ?, ?)
+         RETURNING id, document_id, url, title, created_at",
+    )
+    .bind(document_id)
+    .bind(&url)
+    .bind(&title)
+    .fetch_one(&pool)
+    .await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id });
+
+    Ok(reference)
+}
+
+/// Links of a document, oldest first.
+#[tauri::command]
+pub async fn list_references(app: AppHandle, document_id: i64) -> CmdResult<Vec<LinkReference>> {
+    let pool = db::pool(&app).await?;
+
+    sqlx::query_as(
+        "SELECT id, document_id, url, title, created_at FROM link_references
+         WHERE document_id = ? ORDER BY id ASC",
+    )
+    .bind(document_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn remove_reference(app: AppHandle, id: i64) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let removed: Option<(i64,)> =
+        sqlx::query_as("DELETE FROM link_references WHERE id = ? RETURNING document_id")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?;
+    let Some((document_id,)) = removed else {
+        return Err(AppError::NotFound("Reference not found".to_string()));
+    };
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id });
+
+    Ok(())
+}
+
+fn parse_url(url: &str) -> Option<Url<'_>> {
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme_port = match scheme.to_ascii_lowercase().as_str() {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, target) = rest.split_at(end);
+    // Credentials in the URL are kept but never sent
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let (host, port) = match authority.strip_prefix('[') {
+        // IPv6 literal
+        Some(literal) => {
+            let (host, port) = literal.split_once(']')?;
+            (host, port.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => scheme_port,
+    };
+    let target = target.split('#').next().unwrap_or_default();
+    Some(Url {
+        scheme,
+        host,
+        port,
+        target: if target.is_empty() { "/" } else { target },
+    })
+}
+
+// The <title> of an HTML page, if it comes back in time. Redirects aren't
+// followed.
+fn fetch_title(url: &str) -> Option<String> {
+    let url = parse_url(url)?;
+    if !url.scheme.eq_ignore_ascii_case("http") {
+        return None;
+    }
+    let address = (url.host, url.port).to_socket_addrs().ok()?.next()?;
+    let mut stream = TcpStream::connect_timeout(&address, TITLE_TIMEOUT).ok()?;
+    stream.set_read_timeout(Some(TITLE_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TITLE_TIMEOUT)).ok()?;
+    let target = if url.target.starts_with('/') {
+        url.target.to_string()
+    } else {
+        format!("/{}", url.target)
+    };
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: ando-archive\r\nAccept: text/html\r\n\r\n",
+        target, url.host
+    )
+    .ok()?;
+
+    let mut response = Vec::new();
+    // A timeout mid-page still leaves what arrived so far
+    let _ = stream.take(TITLE_MAX_BYTES).read_to_end(&mut response);
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    let status = head.split_whitespace().nth(1)?;
+    if status != "200" {
+        return None;
+    }
+
+    let title = dom_query::Document::from(body).select("title").text();
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(normalize_name(&title)).filter(|title| !title.is_empty())
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, State};
+
+use crate::commands::review::document_exists;
+use crate::db;
+use crate::deep_link;
+use crate::error::{AppError, CmdResult};
+use crate::html;
+use crate::settings::SettingsStore;
+
+// Terms kept per document; the rarer words past these add little
+const TERMS_PER_DOCUMENT: usize = 50;
+const MIN_TERM_CHARS: usize = 3;
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct RelatedDocument {
+    pub id: i64,
+    pub title: String,
+    pub category_id: Option<i64>,
+    #[sqlx(skip)]
+    pub score: f64,
+    /// The signals that matched, e.g. `tags` or `links`.
+    #[sqlx(skip)]
+    pub reasons: Vec<&'static str>,
+}
+
+#[derive(Default)]
+struct Signals {
+    tags: f64,
+    category: bool,
+    linked: bool,
+    terms: f64,
+}
+
+/// Documents most like the given one, best first: a weighted score of
+/// shared tags, a shared category, links between the two and overlap of
+/// their words, with the weights from settings. Trashed documents are
+/// never among them.
+#[tauri::command]
+pub async fn related_documents(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    id: i64,
+    limit: Option<u32>,
+) -> CmdResult<Vec<RelatedDocument>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+    if !document_exists(&app, id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+    let pool = db::pool(&app).await?;
+    refresh_related_index(&pool).await?;
+    let weights = store.get().related;
+
+    let mut signals: HashMap<i64, Signals> = HashMap::new();
+
+    if weights.tags > 0.0 {
+        // Jaccard similarity of the tag sets
+        let rows: Vec<(i64, i64, i64)> = sqlx::query_as(
+            "SELECT dt.document_id, COUNT(*),
+               (SELECT COUNT(*) FROM document_tags o WHERE o.document_id = dt.document_id)
+             FROM document_tags dt
+             WHERE dt.tag_id IN (SELECT tag_id FROM document_tags WHERE document_id = ?)
+               AND dt.document_id != ?
+             GROUP BY dt.document_id",
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_all(&pool)
+        .await?;
+        let (own,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM document_tags WHERE document_id = ?")
+                .bind(id)
+                .fetch_one(&pool)
+                .await?;
+        for (other, shared, total) in rows {
+            let union = own + total - shared;
+            signals.entry(other).or_default().tags = shared as f64 / union.max(1) as f64;
+        }
+    }
+
+    if weights.category > 0.0 {
+        let rows: Vec<(i64,)> = sqlx::query_as(
+            "SELECT d.id FROM documents d
+             JOIN documents own ON own.id = ? AND own.category_id = d.category_id
+             WHERE d.id != own.id",
+        )
+        .bind(id)
+        .fetch_all(&pool)
+        .await?;
+        for (other,) in rows {
+            signals.entry(other).or_default().category = true;
+        }
+    }
+
+    if weights.links > 0.0 {
+        let rows: Vec<(i64,)> = sqlx::query_as(
+            "SELECT target_id FROM document_links WHERE source_id = ?
+             UNION SELECT source_id FROM document_links WHERE target_id = ?",
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_all(&pool)
+        .await?;
+        for (other,) in rows {
+            if other != id {
+                signals.entry(other).or_default().linked = true;
+            }
+        }
+    }
+
+    if weights.terms > 0.0 {
+        for (other, similarity) in term_similarities(&pool, id).await? {
+            signals.entry(other).or_default().terms = similarity;
+        }
+    }
+
+    let mut scored: Vec<(i64, f64, Vec<&'static str>)> = signals
+        .into_iter()
+        .map(|(other, signals)| {
+            let mut score = 0.0;
+            let mut reasons = Vec::new();
+            for (reason, weight, value) in [
+                ("tags", weights.tags, signals.tags),
+                ("category", weights.category, f64::from(signals.category)),
+                ("links", weights.links, f64::from(signals.linked)),
+                ("terms", weights.terms, signals.terms),
+            ] {
+                if weight > 0.0 && value > 0.0 {
+                    score += weight * value;
+                    reasons.push(reason);
+                }
+            }
+            (other, score, reasons)
+        })
+        .filter(|(_, score, _)| *score > 0.0)
+        .collect();
+    // Ties go to the newer document
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+    scored.truncate(limit);
+
+    let mut related = Vec::with_capacity(scored.len());
+    for (other, score, reasons) in scored {
+        let document: Option<RelatedDocument> =
+            sqlx::query_as("SELECT id, title, category_id FROM documents WHERE id = ?")
+                .bind(other)
+                .fetch_optional(&pool)
+                .await?;
+        if let Some(mut document) = document {
+            document.score = score;
+            document.reasons = reasons;
+            related.push(document);
+        }
+    }
+
+    Ok(related)
+}
+
+// Cosine similarity of the term vectors, with each shared term weighted by
+// how rare it is in the archive, so common words count for little
+async fn term_similarities(pool: &SqlitePool, id: i64) -> CmdResult<HashMap<i64, f64>> {
+    let own: HashMap<String, f64> = sqlx::query_as::<_, (String, f64)>(
+        "SELECT term, weight FROM document_terms WHERE document_id = ?",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    if own.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let rows: Vec<(String, i64, f64)> = sqlx::query_as(
+        "SELECT term, document_id, weight FROM document_terms
+         WHERE term IN (SELECT term FROM document_terms WHERE document_id = ?)
+           AND document_id != ?",
+    )
+    .bind(id)
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    let (documents,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents")
+        .fetch_one(pool)
+        .await?;
+
+    let mut frequency: HashMap<&str, usize> = HashMap::new();
+    for (term, _, _) in &rows {
+        *frequency.entry(term.as_str()).or_default() += 1;
+    }
+    // Scaled so a term only this pair shares counts fully
+    let rarest = ((documents + 1) as f64 / 2.0).ln().max(f64::EPSILON);
+
+    let mut similarities: HashMap<i64, f64> = HashMap::new();
+    for (term, other, weight) in &rows {
+        let in_documents = frequency[term.as_str()] + 1;
+        let rarity = ((documents + 1) as f64 / in_documents as f64).ln() / rarest;
+        *similarities.entry(*other).or_default() += own[term] * weight * rarity.clamp(0.0, 1.0);
+    }
+    Ok(similarities)
+}
+
+/// Extracts the term vectors and document links of documents that changed
+/// since the last refresh, a batch at a time like `refresh_word_counts`.
+pub(crate) async fn refresh_related_index(pool: &SqlitePool) -> CmdResult<usize> {
+    let mut refreshed = 0;
+    loop {
+        let stale: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+            "SELECT id, title, text_content FROM documents WHERE terms_indexed IS NULL LIMIT 200",
+        )
+        .fetch_all(pool)
+        .await?;
+        if stale.is_empty() {
+            return Ok(refreshed);
+        }
+
+        let mut tx = pool.begin().await?;
+        for (id, title, body) in &stale {
+            let body = body.as_deref().unwrap_or_default();
+            sqlx::query("DELETE FROM document_terms WHERE document_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM document_links WHERE source_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+
+            for (term, weight) in term_vector(&format!("{}\n{}", title, html::strip_tags(body))) {
+                sqlx::query(
+                    "INSERT INTO document_terms (document_id, term, weight) VALUES (?, ?, ?)",
+                )
+                .bind(id)
+                .bind(term)
+                .bind(weight)
+                .execute(&mut *tx)
+                .await?;
+            }
+            for target in linked_documents(body) {
+                if target != *id {
+                    sqlx::query(
+                        "INSERT OR IGNORE INTO document_links (source_id, target_id) VALUES (?, ?)",
+                    )
+                    .bind(id)
+                    .bind(target)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+
+            sqlx::query("UPDATE documents SET terms_indexed = 1 WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        refreshed += stale.len();
+    }
+}
+
+// The most frequent words, lowercased, with weights scaled to a unit
+// vector. Numbers and short words are left out.
+fn term_vector(text: &str) -> Vec<(String, f64)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() < MIN_TERM_CHARS || !word.chars().any(char::is_alphabetic) {
+            continue;
+        }
+        *counts.entry(word.to_lowercase()).or_default() += 1;
+    }
+
+    let mut terms: Vec<(String, usize)> = counts.into_iter().collect();
+    terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    terms.truncate(TERMS_PER_DOCUMENT);
+    let norm = terms
+        .iter()
+        .map(|(_, count)| (*count as f64).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    terms
+        .into_iter()
+        .map(|(term, count)| (term, count as f64 / norm))
+        .collect()
+}
+
+// Ids of the `andoarchive://document/…` links in a body
+fn linked_documents(body: &str) -> HashSet<i64> {
+    let prefix = format!("{}://document/", deep_link::SCHEME);
+    body.match_indices(&prefix)
+        .filter_map(|(start, _)| {
+            let rest = &body[start..];
+            let end = rest[prefix.len()..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |end| prefix.len() + end);
+            deep_link::parse(&rest[..end])
+        })
+        .collect()
+}
@@ -0,0 +1,50 @@
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::DocumentEvent;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::reminders::Reminder;
+
+/// Schedules a reminder for a document at `timestamp` (unix seconds),
+/// replacing any previous one.
+#[tauri::command]
+pub async fn set_reminder(app: AppHandle, id: i64, timestamp: i64) -> CmdResult<()> {
+    update_reminder(&app, id, Some(timestamp)).await
+}
+
+#[tauri::command]
+pub async fn clear_reminder(app: AppHandle, id: i64) -> CmdResult<()> {
+    update_reminder(&app, id, None).await
+}
+
+/// Reminders that have not fired yet, soonest first.
+#[tauri::command]
+pub async fn list_upcoming_reminders(app: AppHandle) -> CmdResult<Vec<Reminder>> {
+    let pool = db::pool(&app).await?;
+
+    sqlx::query_as(
+        "SELECT id AS document_id, title, remind_at FROM documents
+         WHERE remind_at IS NOT NULL AND reminder_fired = 0
+         ORDER BY remind_at ASC",
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(AppError::from)
+}
+
+async fn update_reminder(app: &AppHandle, id: i64, remind_at: Option<i64>) -> CmdResult<()> {
+    let pool = db::pool(app).await?;
+
+    let result = sqlx::query("UPDATE documents SET remind_at = ?, reminder_fired = 0 WHERE id = ?")
+        .bind(remind_at)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::commands::documents::DocumentEvent;
+use crate::db::{self, Document};
+use crate::error::{AppError, CmdResult};
+use crate::metrics;
+use crate::settings::SettingsStore;
+use crate::switcher::QuickSwitcher;
+
+// A document left open overnight shouldn't outweigh a week of reading
+const MAX_VIEW_MS: u64 = 2 * 60 * 60 * 1000;
+const DEFAULT_VIEWED_LIMIT: u32 = 20;
+const MAX_VIEWED_LIMIT: u32 = 200;
+const DEFAULT_STALE_LIMIT: u32 = 100;
+const MAX_STALE_LIMIT: u32 = 1000;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ViewStats {
+    pub id: i64,
+    pub title: String,
+    /// Summed over every view, in milliseconds.
+    pub total_view_time: i64,
+    pub view_count: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct StaleDocument {
+    pub id: i64,
+    pub title: String,
+    pub category_id: Option<i64>,
+    pub category_name: Option<String>,
+    pub created_at: String,
+    /// Unix seconds; `None` if it was never opened.
+    pub opened_at: Option<i64>,
+    pub open_count: i64,
+    /// Days since it was last opened, or since it was created if never.
+    pub stale_days: i64,
+    pub age_days: i64,
+    /// The body plus every attachment file, in bytes.
+    pub size_bytes: i64,
+    pub attachment_count: i64,
+}
+
+/// Flags a document for review, or clears the flag once it has been
+/// checked. Documents created from files start out flagged.
+#[tauri::command]
+pub async fn set_needs_review(app: AppHandle, id: i64, flag: bool) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let result = sqlx::query("UPDATE documents SET needs_review = ? WHERE id = ?")
+        .bind(flag)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+
+    Ok(())
+}
+
+/// Documents flagged for review, oldest first so the backlog is worked
+/// through in order.
+#[tauri::command]
+pub async fn list_needs_review(app: AppHandle) -> CmdResult<Vec<Document>> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("list_needs_review");
+    let documents: Vec<Document> = sqlx::query_as(
+        "SELECT * FROM documents WHERE needs_review = 1 ORDER BY created_at ASC, id ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, documents.len());
+
+    Ok(documents
+        .into_iter()
+        .map(Document::without_locked_body)
+        .collect())
+}
+
+/// Number of flagged documents, for the sidebar badge.
+#[tauri::command]
+pub async fn needs_review_count(app: AppHandle) -> CmdResult<i64> {
+    let pool = db::pool(&app).await?;
+
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents WHERE needs_review = 1")
+        .fetch_one(&pool)
+        .await?;
+    Ok(count)
+}
+
+/// Loads a document for viewing and marks it read. New and imported
+/// documents start out unread. Opens are counted for `quick_switch`.
+#[tauri::command]
+pub async fn open_document(app: AppHandle, id: i64) -> CmdResult<Document> {
+    let pool = db::pool(&app).await?;
+
+    let document: Document = sqlx::query_as("SELECT * FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    set_read(&app, id, true).await?;
+
+    // For the quick switcher's ranking
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query("UPDATE documents SET opened_at = ?, open_count = open_count + 1 WHERE id = ?")
+        .bind(now)
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    app.state::<QuickSwitcher>().opened(id, now);
+
+    Ok(document)
+}
+
+/// Adds a view that lasted `duration_ms` to the document's totals for
+/// `most_viewed` and `least_viewed`, when `track_view_time` is on. Called
+/// as the document is closed.
+#[tauri::command]
+pub async fn close_document(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    id: i64,
+    duration_ms: u64,
+) -> CmdResult<()> {
+    if !store.get().track_view_time {
+        return Ok(());
+    }
+    let pool = db::pool(&app).await?;
+
+    let duration_ms = duration_ms.min(MAX_VIEW_MS) as i64;
+    let result = sqlx::query(
+        "UPDATE documents SET total_view_time = total_view_time + ?, view_count = view_count + 1
+         WHERE id = ?",
+    )
+    .bind(duration_ms)
+    .bind(id)
+    .execute(&pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn mark_read(app: AppHandle, id: i64) -> CmdResult<()> {
+    if !document_exists(&app, id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+    set_read(&app, id, true).await
+}
+
+#[tauri::command]
+pub async fn mark_unread(app: AppHandle, id: i64) -> CmdResult<()> {
+    if !document_exists(&app, id).await? {
+        return Err(AppError::NotFound("Document not found".to_string()));
+    }
+    set_read(&app, id, false).await
+}
+
+// Only emits when the state actually changed, so reopening a read document
+// doesn't refresh every list
+async fn set_read(app: &AppHandle, id: i64, read: bool) -> CmdResult<()> {
+    let pool = db::pool(app).await?;
+
+    let result = sqlx::query("UPDATE documents SET is_read = ? WHERE id = ? AND is_read != ?")
+        .bind(read)
+        .bind(id)
+        .bind(read)
+        .execute(&pool)
+        .await?;
+    if result.rows_affected() > 0 {
+        let _ = app.emit("document_updated", DocumentEvent { document_id: id });
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn document_exists(app: &AppHandle, id: i64) -> CmdResult<bool> {
+    let pool = db::pool(app).await?;
+
+    let found: Option<(i64,)> = sqlx::query_as("SELECT id FROM documents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+    Ok(found.is_some())
+}
+
+/// Unread documents, newest first like an inbox.
+#[tauri::command]
+pub async fn list_unread(app: AppHandle) -> CmdResult<Vec<Document>> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("list_unread");
+    let documents: Vec<Document> = sqlx::query_as(
+        "SELECT * FROM documents WHERE is_read = 0 ORDER BY created_at DESC, id DESC",
+    )
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, documents.len());
+
+    Ok(documents
+        .into_iter()
+        .map(Document::without_locked_body)
+        .collect())
+}
+
+/// Number of unread documents, for the inbox badge.
+#[tauri::command]
+pub async fn unread_count(app: AppHandle) -> CmdResult<i64> {
+    let pool = db::pool(&app).await?;
+
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents WHERE is_read = 0")
+        .fetch_one(&pool)
+        .await?;
+    Ok(count)
+}
+
+/// Documents viewed longest in total, for finding the notes referred to
+/// most.
+#[tauri::command]
+pub async fn most_viewed(app: AppHandle, limit: Option<u32>) -> CmdResult<Vec<ViewStats>> {
+    viewed(
+        &app,
+        "ORDER BY total_view_time DESC, view_count DESC, id ASC",
+        limit,
+    )
+    .await
+}
+
+/// Documents viewed least, never viewed first and then the oldest, as
+/// candidates for archiving.
+#[tauri::command]
+pub async fn least_viewed(app: AppHandle, limit: Option<u32>) -> CmdResult<Vec<ViewStats>> {
+    viewed(
+        &app,
+        "ORDER BY total_view_time ASC, view_count ASC, updated_at ASC, id ASC",
+        limit,
+    )
+    .await
+}
+
+async fn viewed(app: &AppHandle, order: &str, limit: Option<u32>) -> CmdResult<Vec<ViewStats>> {
+    let pool = db::pool(app).await?;
+    let limit = limit
+        .unwrap_or(DEFAULT_VIEWED_LIMIT)
+        .clamp(1, MAX_VIEWED_LIMIT);
+
+    let stats = sqlx::query_as(&format!(
+        "SELECT id, title, total_view_time, view_count FROM documents {} LIMIT ?",
+        order
+    ))
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    Ok(stats)
+}
+
+/// Documents at least `min_age_days` old that haven't been opened in
+/// `not_opened_days`, stalest first, as candidates for archiving or
+/// deleting. Documents never opened count from when they were created.
+/// Documents open as tabs are left out.
+#[tauri::command]
+pub async fn find_stale_documents(
+    app: AppHandle,
+    not_opened_days: u32,
+    min_age_days: u32,
+    limit: Option<u32>,
+) -> CmdResult<Vec<StaleDocument>> {
+    let pool = db::pool(&app).await?;
+    let limit = limit
+        .unwrap_or(DEFAULT_STALE_LIMIT)
+        .clamp(1, MAX_STALE_LIMIT);
+
+    let timer = metrics::Timer::start("find_stale_documents");
+    let stale: Vec<StaleDocument> = sqlx::query_as(
+        "SELECT * FROM (
+           SELECT d.id, d.title, d.category_id, c.name AS category_name, d.created_at,
+                  d.opened_at, d.open_count,
+                  CAST(julianday('now') - julianday(d.created_at) AS INTEGER) AS age_days,
+                  CAST(COALESCE(
+                    (CAST(strftime('%s', 'now') AS INTEGER) - d.opened_at) / 86400,
+                    julianday('now') - julianday(d.created_at)
+                  ) AS INTEGER) AS stale_days,
+                  length(CAST(COALESCE(d.text_content, '') AS BLOB))
+                    + COALESCE(a.bytes, 0) AS size_bytes,
+                  COALESCE(a.count, 0) AS attachment_count
+           FROM documents d
+           LEFT JOIN categories c ON c.id = d.category_id
+           LEFT JOIN (
+             SELECT document_id, SUM(COALESCE(filesize, 0)) AS bytes, COUNT(*) AS count
+             FROM attachments GROUP BY document_id
+           ) a ON a.document_id = d.id
+           WHERE d.id NOT IN (SELECT document_id FROM open_tabs)
+         )
+         WHERE age_days >= ?1 AND stale_days >= ?2
+         ORDER BY stale_days DESC, open_count ASC, size_bytes DESC, id ASC
+         LIMIT ?3",
+    )
+    .bind(min_age_days)
+    .bind(not_opened_days)
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, stale.len());
+
+    Ok(stale)
+}
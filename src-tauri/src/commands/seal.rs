@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::fs;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tauri::AppHandle;
+
+use crate::commands::audit;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::jobs::{self, JobContext};
+use crate::secrets;
+
+// A seal is a manifest of every document's content hash, a hash over the
+// whole manifest and an HMAC of that hash under a key kept in the OS
+// keyring, so the table can't be edited to match tampered documents
+// without the key. A document's hash covers its title, description, body,
+// category, creation date, tags and each attachment's name and bytes.
+
+const KEY_SECRET: &str = "archive_seal_key";
+// Documents read per query, so the whole archive is never in memory
+const BATCH_SIZE: i64 = 200;
+// Stands in for the hash of an attachment whose file is gone
+const MISSING_FILE: &str = "missing";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SealedDocument {
+    pub document_id: i64,
+    pub title: String,
+    pub hash: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SealSummary {
+    pub seal_id: i64,
+    pub sealed_at: String,
+    pub documents: usize,
+    pub root_hash: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SealDivergence {
+    pub document_id: i64,
+    /// The current title, or the sealed one for a document since deleted.
+    pub title: String,
+    /// `None` for a document since deleted.
+    pub updated_at: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SealReport {
+    pub seal_id: i64,
+    pub sealed_at: String,
+    /// Whether the stored manifest still hashes to its signed root hash
+    /// and the signature matches the keyring's key. When it doesn't, the
+    /// seal itself was edited and the lists below can't be trusted.
+    pub signature_valid: bool,
+    /// False when the keyring no longer has the key the seal was signed
+    /// with, e.g. on another machine; the signature can't be checked.
+    pub key_available: bool,
+    /// Documents whose content no longer matches their sealed hash.
+    pub changed: Vec<SealDivergence>,
+    /// Sealed documents that no longer exist.
+    pub removed: Vec<SealDivergence>,
+    /// Documents added since sealing, which the seal says nothing about.
+    pub added: Vec<SealDivergence>,
+    /// Whether the signature holds and nothing sealed changed or went.
+    pub intact: bool,
+}
+
+/// Starts a job hashing every document and its attachments into a new
+/// seal, signed with the archive's seal key from the OS keyring, which is
+/// generated on first use. Earlier seals are kept. Returns the job id.
+#[tauri::command]
+pub async fn seal_archive(app: AppHandle) -> CmdResult<u64> {
+    let key = tauri::async_runtime::spawn_blocking(|| seal_key(true))
+        .await??
+        .ok_or_else(|| AppError::Internal("The seal key was not stored".to_string()))?;
+    let pool = db::pool(&app).await?;
+
+    let job_id = jobs::spawn(&app, "seal_archive", move |job| async move {
+        let Some(documents) = manifest(&pool, &job).await? else {
+            return Err(AppError::Conflict(
+                "Sealing was cancelled; no seal was stored".to_string(),
+            ));
+        };
+        let root_hash = root_hash(&documents);
+        let manifest_json = serde_json::to_string(&documents)?;
+
+        // The time is signed too, so a seal can't be passed off as older
+        let sealed_at = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let mut tx = pool.begin().await?;
+        let (seal_id,): (i64,) = sqlx::query_as(
+            "INSERT INTO archive_seals (sealed_at, manifest, root_hash, signature)
+             VALUES (?, ?, ?, ?) RETURNING id",
+        )
+        .bind(&sealed_at)
+        .bind(&manifest_json)
+        .bind(&root_hash)
+        .bind(sign(&key, &root_hash, &sealed_at))
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record(&mut *tx, "seal", "archive", Some(seal_id), &root_hash).await?;
+        tx.commit().await?;
+
+        Ok(SealSummary {
+            seal_id,
+            sealed_at,
+            documents: documents.len(),
+            root_hash,
+        })
+    });
+
+    Ok(job_id)
+}
+
+/// Starts a job checking the archive against its latest seal: whether the
+/// seal's signature still holds, and which documents changed or went
+/// since, each with when it was last modified. Fails with `not_found`
+/// when the archive was never sealed. Returns the job id.
+#[tauri::command]
+pub async fn verify_seal(app: AppHandle) -> CmdResult<u64> {
+    let pool = db::pool(&app).await?;
+    let (seal_id, sealed_at, manifest_json, stored_root, signature): (
+        i64,
+        String,
+        String,
+        String,
+        String,
+    ) = sqlx::query_as(
+        "SELECT id, sealed_at, manifest, root_hash, signature FROM archive_seals
+         ORDER BY id DESC LIMIT 1",
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::NotFound(
+            "The archive has not been sealed yet; seal it first to check it later".to_string(),
+        )
+    })?;
+    let sealed: Vec<SealedDocument> = serde_json::from_str(&manifest_json)
+        .map_err(|e| AppError::Validation(format!("The seal's manifest is corrupt: {}", e)))?;
+    let key = tauri::async_runtime::spawn_blocking(|| seal_key(false)).await??;
+
+    let job_id = jobs::spawn(&app, "verify_seal", move |job| async move {
+        let Some(current) = manifest(&pool, &job).await? else {
+            return Err(AppError::Conflict(
+                "Checking the seal was cancelled".to_string(),
+            ));
+        };
+
+        let signature_valid = root_hash(&sealed) == stored_root
+            && key
+                .as_ref()
+                .is_some_and(|key| verify(key, &stored_root, &sealed_at, &signature));
+        let updated_at: HashMap<i64, String> =
+            sqlx::query_as::<_, (i64, String)>("SELECT id, updated_at FROM documents")
+                .fetch_all(&pool)
+                .await?
+                .into_iter()
+                .collect();
+        let divergence = |document: &SealedDocument| SealDivergence {
+            document_id: document.document_id,
+            title: document.title.clone(),
+            updated_at: updated_at.get(&document.document_id).cloned(),
+        };
+
+        let now: HashMap<i64, &SealedDocument> = current
+            .iter()
+            .map(|document| (document.document_id, document))
+            .collect();
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for document in &sealed {
+            match now.get(&document.document_id) {
+                Some(current) if current.hash != document.hash => changed.push(divergence(current)),
+                Some(_) => {}
+                None => removed.push(divergence(document)),
+            }
+        }
+        let was: HashMap<i64, &SealedDocument> = sealed
+            .iter()
+            .map(|document| (document.document_id, document))
+            .collect();
+        let added: Vec<SealDivergence> = current
+            .iter()
+            .filter(|document| !was.contains_key(&document.document_id))
+            .map(divergence)
+            .collect();
+
+        Ok(SealReport {
+            seal_id,
+            sealed_at,
+            signature_valid,
+            key_available: key.is_some(),
+            intact: signature_valid && changed.is_empty() && removed.is_empty(),
+            changed,
+            removed,
+            added,
+        })
+    });
+
+    Ok(job_id)
+}
+
+// The key from the keyring, created when missing and `create` is set
+fn seal_key(create: bool) -> CmdResult<Option<Vec<u8>>> {
+    if let Some(key) = secrets::get(KEY_SECRET)?.filter(|key| !key.is_empty()) {
+        let key = hex::decode(key)
+            .map_err(|e| AppError::Validation(format!("The seal key is corrupt: {}", e)))?;
+        return Ok(Some(key));
+    }
+    if !create {
+        return Ok(None);
+    }
+    let mut key = [0u8; 32];
+    getrandom::getrandom(&mut key).map_err(|e| AppError::Internal(e.to_string()))?;
+    secrets::set(KEY_SECRET, &hex::encode(key))?;
+    Ok(Some(key.to_vec()))
+}
+
+fn mac(key: &[u8], root_hash: &str, sealed_at: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes any key size");
+    mac.update(root_hash.as_bytes());
+    mac.update(&[0]);
+    mac.update(sealed_at.as_bytes());
+    mac
+}
+
+fn sign(key: &[u8], root_hash: &str, sealed_at: &str) -> String {
+    hex::encode(mac(key, root_hash, sealed_at).finalize().into_bytes())
+}
+
+fn verify(key: &[u8], root_hash: &str, sealed_at: &str, signature: &str) -> bool {
+    hex::decode(signature).is_ok_and(|signature| {
+        mac(key, root_hash, sealed_at)
+            .verify_slice(&signature)
+            .is_ok()
+    })
+}
+
+// Over every document id and hash in id order
+fn root_hash(documents: &[SealedDocument]) -> String {
+    let mut documents: Vec<&SealedDocument> = documents.iter().collect();
+    documents.sort_by_key(|document| document.document_id);
+    let mut hasher = Sha256::new();
+    for document in documents {
+        hasher.update(format!("{}:{}\n", document.document_id, document.hash));
+    }
+    hex::encode(hasher.finalize())
+}
+
+// Every document's hash in id order, `None` once the job is cancelled
+async fn manifest(pool: &SqlitePool, job: &JobContext) -> CmdResult<Option<Vec<SealedDocument>>> {
+    let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents")
+        .fetch_one(pool)
+        .await?;
+    let total = total as usize;
+
+    let mut documents = Vec::new();
+    let mut after = 0;
+    loop {
+        if job.is_cancelled() {
+            return Ok(None);
+        }
+        let batch: Vec<DocumentRow> = sqlx::query_as(
+            "SELECT d.id, d.title, d.description, d.text_content, d.category_id, d.created_at,
+               (SELECT json_group_array(t.name) FROM document_tags dt
+                JOIN tags t ON t.id = dt.tag_id WHERE dt.document_id = d.id) AS tags,
+               (SELECT json_group_array(json_array(a.id, a.filename, a.filepath))
+                FROM attachments a WHERE a.document_id = d.id) AS attachments
+             FROM documents d WHERE d.id > ? ORDER BY d.id ASC LIMIT ?",
+        )
+        .bind(after)
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after = last.id;
+
+        let hashed = tauri::async_runtime::spawn_blocking(move || {
+            batch
+                .into_iter()
+                .map(|row| row.seal())
+                .collect::<CmdResult<Vec<SealedDocument>>>()
+        })
+        .await??;
+        documents.extend(hashed);
+        // Documents added meanwhile can take it past the count
+        job.progress(documents.len(), total.max(documents.len()));
+    }
+    Ok(Some(documents))
+}
+
+#[derive(sqlx::FromRow)]
+struct DocumentRow {
+    id: i64,
+    title: String,
+    description: Option<String>,
+    text_content: Option<String>,
+    category_id: Option<i64>,
+    created_at: String,
+    tags: String,
+    attachments: String,
+}
+
+impl DocumentRow {
+    // Exact bytes, unlike `content_hash`, which forgives whitespace: any
+    // change at all should show
+    fn seal(self) -> CmdResult<SealedDocument> {
+        let mut tags: Vec<String> = serde_json::from_str(&self.tags)?;
+        tags.sort();
+        let mut attachments: Vec<(i64, String, String)> = serde_json::from_str(&self.attachments)?;
+        attachments.sort_by_key(|(id, _, _)| *id);
+        let attachments: Vec<(i64, String, String)> = attachments
+            .into_iter()
+            .map(|(id, filename, filepath)| {
+                let file_hash = match fs::read(&filepath) {
+                    Ok(bytes) => hex::encode(Sha256::digest(bytes)),
+                    Err(e) => {
+                        log::warn!("Sealing {} as missing: {}", filepath, e);
+                        MISSING_FILE.to_string()
+                    }
+                };
+                (id, filename, file_hash)
+            })
+            .collect();
+
+        let content = serde_json::to_vec(&(
+            &self.title,
+            &self.description,
+            &self.text_content,
+            self.category_id,
+            &self.created_at,
+            &tags,
+            &attachments,
+        ))?;
+        Ok(SealedDocument {
+            document_id: self.id,
+            title: self.title,
+            hash: hex::encode(Sha256::digest(content)),
+        })
+    }
+}
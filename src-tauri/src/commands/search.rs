@@ -0,0 +1,1003 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::{Sqlite, SqlitePool};
+use tauri::{AppHandle, Manager, State};
+
+use crate::collation;
+use crate::commands::categories;
+use crate::db::{self, Document};
+use crate::error::{AppError, CmdResult};
+use crate::html;
+use crate::metrics;
+use crate::migrations;
+use crate::settings::{SearchOptions, SettingsStore};
+
+const SNIPPET_WORDS: usize = 16;
+
+// Full-text tables, what each one indexes and the columns it takes. The
+// document and version indexes read views that leave locked content out.
+const INDEXES: &[(&str, &str, &str)] = &[
+    ("documents_fts", "documents_fts_content", DOCUMENT_COLUMNS),
+    (
+        "document_versions_fts",
+        "document_versions_fts_content",
+        DOCUMENT_COLUMNS,
+    ),
+    ("attachments_fts", "attachments", "filename, alt_text"),
+    ("document_notes_fts", "document_notes", "text"),
+];
+const DOCUMENT_COLUMNS: &str = "title, description, text_content";
+
+// The triggers keeping those in sync, put back from the migrations that
+// create them
+const SYNC_TRIGGERS: &[&str] = &[
+    "documents_fts_ai",
+    "documents_fts_ad",
+    "documents_fts_au",
+    "document_versions_fts_ai",
+    "document_versions_fts_ad",
+    "document_versions_fts_lock",
+    "attachments_fts_ai",
+    "attachments_fts_ad",
+    "attachments_fts_au",
+    "document_notes_fts_ai",
+    "document_notes_fts_ad",
+    "document_notes_fts_au",
+];
+
+#[derive(Serialize)]
+pub struct IndexingCatchUp {
+    /// Rows added to the indexes after indexing was deferred.
+    pub rows_indexed: u64,
+    /// Whether rows were also edited or deleted meanwhile, so the indexes
+    /// had to be rebuilt in full.
+    pub rebuilt: bool,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct SearchIndexReport {
+    pub tokenizer: String,
+    pub documents_indexed: i64,
+    pub duration_ms: u64,
+}
+
+/// Stores new tokenizer options and reindexes with them. Options SQLite
+/// rejects are not stored.
+#[tauri::command]
+pub async fn set_search_options(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    options: SearchOptions,
+) -> CmdResult<SearchIndexReport> {
+    let pool = db::pool(&app).await?;
+
+    let tokenizer = options.tokenizer()?;
+    check_tokenizer(&pool, &tokenizer).await?;
+
+    let mut settings = store.get();
+    let changed = settings.search.tokenizer()? != tokenizer;
+    settings.search = options;
+    store.replace(settings)?;
+
+    if changed {
+        rebuild(&pool, &tokenizer).await
+    } else {
+        // Stopwords only apply to queries, so the index is still current
+        Ok(SearchIndexReport {
+            tokenizer,
+            documents_indexed: document_count(&pool).await?,
+            duration_ms: 0,
+        })
+    }
+}
+
+/// Recreates the full-text indexes of documents, their versions,
+/// attachments and notes with the configured tokenizer.
+#[tauri::command]
+pub async fn rebuild_search_index(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+) -> CmdResult<SearchIndexReport> {
+    let pool = db::pool(&app).await?;
+
+    let tokenizer = store.get().search.tokenizer()?;
+    check_tokenizer(&pool, &tokenizer).await?;
+    rebuild(&pool, &tokenizer).await
+}
+
+/// Drops the triggers that keep the search indexes in sync, to speed up a
+/// bulk operation, or puts them back and indexes whatever changed in the
+/// meantime. Returns the catch-up done when re-enabling.
+#[tauri::command]
+pub async fn defer_search_indexing(
+    app: AppHandle,
+    enabled: bool,
+) -> CmdResult<Option<IndexingCatchUp>> {
+    let pool = db::pool(&app).await?;
+
+    if enabled {
+        defer_indexing(&pool).await?;
+        Ok(None)
+    } else {
+        resume_indexing(&pool).await.map(Some)
+    }
+}
+
+/// Defers indexing unless it already is, returning whether this call did,
+/// so nested bulk operations leave resuming to the outermost one.
+pub(crate) async fn defer_indexing(pool: &SqlitePool) -> CmdResult<bool> {
+    if indexing_deferred(pool).await? {
+        return Ok(false);
+    }
+    let mut tx = pool.begin().await?;
+    for name in SYNC_TRIGGERS {
+        sqlx::query(&format!("DROP TRIGGER IF EXISTS {}", name))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Restores the sync triggers and indexes the rows added since, in one
+/// batch per index. Edits and deletes cannot be replayed, so when the
+/// integrity check finds any the index is rebuilt instead.
+pub(crate) async fn resume_indexing(pool: &SqlitePool) -> CmdResult<IndexingCatchUp> {
+    let started = Instant::now();
+    let mut rows_indexed = 0;
+    let mut rebuilt = false;
+
+    let mut tx = pool.begin().await?;
+    for name in SYNC_TRIGGERS {
+        let sql = migrations::trigger_sql(name)
+            .ok_or_else(|| AppError::Internal(format!("No migration creates {}", name)))?;
+        sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    for (index, content, columns) in INDEXES {
+        // The docsize shadow table lists the rows the index has seen
+        rows_indexed += sqlx::query(&format!(
+            "INSERT INTO {0}(rowid, {2})
+             SELECT id, {2} FROM {1}
+             WHERE id NOT IN (SELECT id FROM {0}_docsize)",
+            index, content, columns
+        ))
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let consistent = sqlx::query(&format!(
+            "INSERT INTO {0}({0}, rank) VALUES ('integrity-check', 1)",
+            index
+        ))
+        .execute(&mut *tx)
+        .await
+        .is_ok();
+        if !consistent {
+            sqlx::query(&format!("INSERT INTO {0}({0}) VALUES ('rebuild')", index))
+                .execute(&mut *tx)
+                .await?;
+            rebuilt = true;
+        }
+    }
+    tx.commit().await?;
+
+    Ok(IndexingCatchUp {
+        rows_indexed,
+        rebuilt,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Resumes indexing left deferred by a bulk operation that never finished,
+/// e.g. when the app quit in the middle of an import, and puts back the
+/// configured tokenizer if a migration recreated the indexes.
+pub fn resume_interrupted_indexing(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let pool = db::wait_for_pool(&app).await;
+        match indexing_deferred(&pool).await {
+            Ok(false) => {}
+            Ok(true) => {
+                if let Err(e) = resume_indexing(&pool).await {
+                    log::warn!("Failed to resume search indexing: {}", e);
+                }
+            }
+            Err(e) => log::warn!("Failed to check search indexing: {}", e),
+        }
+
+        if let Err(e) = reapply_tokenizer(&app, &pool).await {
+            log::warn!("Failed to check the search tokenizer: {}", e);
+        }
+    });
+}
+
+// Rebuilds the indexes once a migration recreated them with the default
+// tokenizer in place of the configured one
+async fn reapply_tokenizer(app: &AppHandle, pool: &SqlitePool) -> CmdResult<()> {
+    let tokenizer = app.state::<SettingsStore>().get().search.tokenizer()?;
+    if !index_tokenizer_current(pool, &tokenizer).await? {
+        rebuild(pool, &tokenizer).await?;
+    }
+    Ok(())
+}
+
+// Whether the document index was built with `tokenizer`; migrations create
+// it with the default one, spelled as they spell it
+async fn index_tokenizer_current(pool: &SqlitePool, tokenizer: &str) -> CmdResult<bool> {
+    let (sql,): (String,) =
+        sqlx::query_as("SELECT sql FROM sqlite_master WHERE name = 'documents_fts'")
+            .fetch_one(pool)
+            .await?;
+    Ok(sql.contains(&format!("tokenize = \"{}\"", tokenizer))
+        || (SearchOptions::default().tokenizer()? == tokenizer
+            && sql.contains("tokenize = 'unicode61'")))
+}
+
+pub(crate) async fn indexing_deferred(pool: &SqlitePool) -> CmdResult<bool> {
+    let triggers: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'trigger'")
+            .fetch_all(pool)
+            .await?;
+    Ok(SYNC_TRIGGERS
+        .iter()
+        .any(|name| !triggers.iter().any(|(trigger,)| trigger == name)))
+}
+
+/// Full-text search over title, description and body, and the names and
+/// alt text of attachments, best matches first. Configured stopwords are
+/// left out of the query, and documents of archived categories out of the
+/// results unless `include_archived`. Notes are only searched with
+/// `include_notes`. Locked documents match by everything but their body,
+/// and come without it.
+#[tauri::command]
+pub async fn search_documents(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    query: String,
+    limit: Option<u32>,
+    include_archived: Option<bool>,
+    include_notes: Option<bool>,
+) -> CmdResult<Vec<Document>> {
+    let pool = db::pool(&app).await?;
+
+    let terms = query_terms(&store, &query);
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Equally good matches in title order, by the collation locale if set
+    let title_order = if collation::is_active(&pool).await? {
+        "d.title COLLATE LOCALE"
+    } else {
+        "d.title"
+    };
+    let timer = metrics::Timer::start("search_documents");
+    let documents: Vec<Document> = sqlx::query_as(&ranked_search(
+        include_archived,
+        include_notes.unwrap_or(false),
+        title_order,
+    ))
+    .bind(match_expression(&terms))
+    .bind(limit.unwrap_or(100))
+    .fetch_all(&pool)
+    .await?;
+    timer.finish(&app, documents.len());
+
+    Ok(documents
+        .into_iter()
+        .map(Document::without_locked_body)
+        .collect())
+}
+
+// Best matches first over documents, attachments and, with
+// `include_notes`, notes; binds the match expression and the limit
+fn ranked_search(include_archived: Option<bool>, include_notes: bool, title_order: &str) -> String {
+    format!(
+        "SELECT d.* FROM (
+           SELECT rowid AS document_id, rank FROM documents_fts WHERE documents_fts MATCH ?1
+           UNION ALL
+           SELECT a.document_id, f.rank FROM attachments_fts f
+           JOIN attachments a ON a.id = f.rowid
+           WHERE attachments_fts MATCH ?1
+           {}
+         ) m
+         JOIN documents d ON d.id = m.document_id
+         WHERE {}
+         GROUP BY d.id
+         ORDER BY MIN(m.rank), {}, d.id
+         LIMIT ?2",
+        if include_notes {
+            "UNION ALL
+             SELECT n.document_id, f.rank FROM document_notes_fts f
+             JOIN document_notes n ON n.id = f.rowid
+             WHERE document_notes_fts MATCH ?1"
+        } else {
+            ""
+        },
+        categories::archived_scope("d.category_id", include_archived),
+        title_order
+    )
+}
+
+// Ranking a query that matches most of the archive can take a while; past
+// this the search box gets unranked matches instead
+const INCREMENTAL_TIME_LIMIT: Duration = Duration::from_millis(300);
+// SQLite instructions between checks for cancellation
+const PROGRESS_OPS: i32 = 1000;
+
+/// The latest request of each search box, so an older one still running
+/// can be called off once a newer one arrives.
+#[derive(Default)]
+pub struct SearchSessions {
+    latest: Mutex<HashMap<String, (u64, Arc<AtomicBool>)>>,
+}
+
+impl SearchSessions {
+    // The token the request runs under, or `None` when a newer request
+    // already came in. A page that reloads counts from the start again, so
+    // an id of 0 or 1 starts the session over.
+    fn start(&self, session: &str, request_id: u64) -> Option<Arc<AtomicBool>> {
+        let mut latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((latest_id, cancelled)) = latest.get(session) {
+            if *latest_id > request_id && request_id > 1 {
+                return None;
+            }
+            cancelled.store(true, Ordering::Relaxed);
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        latest.insert(session.to_string(), (request_id, cancelled.clone()));
+        Some(cancelled)
+    }
+}
+
+// A pooled connection with a progress handler set. `release` removes the
+// handler before the connection goes back to the pool the frontend
+// shares; dropped without it, e.g. on an early return, the connection is
+// closed instead, so no other query runs under the handler.
+struct ProgressGuard {
+    conn: PoolConnection<Sqlite>,
+    released: bool,
+}
+
+impl ProgressGuard {
+    async fn set(
+        mut conn: PoolConnection<Sqlite>,
+        handler: impl FnMut() -> bool + Send + 'static,
+    ) -> CmdResult<Self> {
+        conn.lock_handle()
+            .await?
+            .set_progress_handler(PROGRESS_OPS, handler);
+        Ok(Self {
+            conn,
+            released: false,
+        })
+    }
+
+    async fn replace(&mut self, handler: impl FnMut() -> bool + Send + 'static) -> CmdResult<()> {
+        self.conn
+            .lock_handle()
+            .await?
+            .set_progress_handler(PROGRESS_OPS, handler);
+        Ok(())
+    }
+
+    async fn release(mut self) -> CmdResult<()> {
+        self.conn.lock_handle().await?.remove_progress_handler();
+        self.released = true;
+        Ok(())
+    }
+}
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        if !self.released {
+            self.conn.close_on_drop();
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct IncrementalResults {
+    pub request_id: u64,
+    pub documents: Vec<Document>,
+    /// A newer request of the same session came in, so these are empty and
+    /// should be dropped.
+    pub cancelled: bool,
+    /// Ranking took too long, so these are the first matches found rather
+    /// than the best.
+    pub truncated: bool,
+}
+
+/// Search as the user types: like `search_documents`, the last word
+/// matching as a prefix. Each call carries an increasing `request_id`; a
+/// call still running when a newer one of the same `session` arrives is
+/// cancelled and comes back empty with `cancelled`, so results never
+/// arrive out of order. Counting again from 0 or 1 starts a new session.
+#[tauri::command]
+pub async fn search_documents_incremental(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    query: String,
+    request_id: u64,
+    session: Option<String>,
+    limit: Option<u32>,
+    include_archived: Option<bool>,
+) -> CmdResult<IncrementalResults> {
+    let mut results = IncrementalResults {
+        request_id,
+        documents: Vec::new(),
+        cancelled: false,
+        truncated: false,
+    };
+    let session = session.unwrap_or_default();
+    let Some(cancelled) = app.state::<SearchSessions>().start(&session, request_id) else {
+        results.cancelled = true;
+        return Ok(results);
+    };
+    let terms = query_terms(&store, &query);
+    if terms.is_empty() {
+        return Ok(results);
+    }
+
+    let pool = db::pool(&app).await?;
+    let title_order = if collation::is_active(&pool).await? {
+        "d.title COLLATE LOCALE"
+    } else {
+        "d.title"
+    };
+    let expression = format!("{}*", match_expression(&terms));
+    let limit = limit.unwrap_or(20);
+
+    let timer = metrics::Timer::start("search_documents_incremental");
+    let deadline = Instant::now() + INCREMENTAL_TIME_LIMIT;
+    let token = cancelled.clone();
+    let mut guard = ProgressGuard::set(pool.acquire().await?, move || {
+        !token.load(Ordering::Relaxed) && Instant::now() < deadline
+    })
+    .await?;
+    let ranked = sqlx::query_as(&ranked_search(include_archived, false, title_order))
+        .bind(&expression)
+        .bind(limit)
+        .fetch_all(&mut *guard.conn)
+        .await;
+
+    let outcome = match ranked {
+        Ok(documents) => Ok(documents),
+        // Interrupted by the progress handler
+        Err(_) if cancelled.load(Ordering::Relaxed) => Ok(Vec::new()),
+        Err(_) if Instant::now() >= deadline => {
+            results.truncated = true;
+            let token = cancelled.clone();
+            guard
+                .replace(move || !token.load(Ordering::Relaxed))
+                .await?;
+            // Without ranking, FTS stops at the first matches it finds
+            sqlx::query_as(&format!(
+                "SELECT d.* FROM documents_fts f JOIN documents d ON d.id = f.rowid
+                 WHERE documents_fts MATCH ? AND {}
+                 LIMIT ?",
+                categories::archived_scope("d.category_id", include_archived)
+            ))
+            .bind(&expression)
+            .bind(limit)
+            .fetch_all(&mut *guard.conn)
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    guard.release().await?;
+
+    if cancelled.load(Ordering::Relaxed) {
+        results.cancelled = true;
+        results.truncated = false;
+        return Ok(results);
+    }
+    results.documents = outcome?
+        .into_iter()
+        .map(Document::without_locked_body)
+        .collect();
+    timer.finish(&app, results.documents.len());
+    Ok(results)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct VersionHit {
+    pub document_id: i64,
+    pub title: String,
+    pub version_id: i64,
+    /// 1 for the first version of the document.
+    pub version: i64,
+    pub created_at: i64,
+    /// Whether this is the document's current content.
+    pub is_current: bool,
+    #[sqlx(default)]
+    pub snippet: String,
+    #[sqlx(rename = "text_content")]
+    #[serde(skip)]
+    body: Option<String>,
+}
+
+/// Full-text search over every stored version of every document, to find
+/// text that has since been edited out. Kept apart from `search_documents`
+/// since it is slower and turns up older duplicates. Versions of locked
+/// documents are not indexed.
+#[tauri::command]
+pub async fn search_versions(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    query: String,
+    limit: Option<u32>,
+) -> CmdResult<Vec<VersionHit>> {
+    let pool = db::pool(&app).await?;
+
+    let terms = query_terms(&store, &query);
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let timer = metrics::Timer::start("search_versions");
+    let mut hits: Vec<VersionHit> = sqlx::query_as(
+        "SELECT v.document_id, d.title, v.id AS version_id, v.version, v.created_at,
+                v.id = v.latest AS is_current, v.text_content
+         FROM document_versions_fts f
+         JOIN (
+           SELECT *,
+                  ROW_NUMBER() OVER (PARTITION BY document_id ORDER BY id) AS version,
+                  MAX(id) OVER (PARTITION BY document_id) AS latest
+           FROM document_versions
+         ) v ON v.id = f.rowid
+         JOIN documents d ON d.id = v.document_id
+         WHERE document_versions_fts MATCH ?
+         ORDER BY f.rank
+         LIMIT ?",
+    )
+    .bind(match_expression(&terms))
+    .bind(limit.unwrap_or(50))
+    .fetch_all(&pool)
+    .await?;
+
+    for hit in &mut hits {
+        let text = html::strip_tags(hit.body.as_deref().unwrap_or_default());
+        hit.snippet = snippet(&text, &terms);
+        hit.body = None;
+    }
+    timer.finish(&app, hits.len());
+
+    Ok(hits)
+}
+
+// Query words minus the configured stopwords
+fn query_terms(store: &SettingsStore, query: &str) -> Vec<String> {
+    let stopwords: Vec<String> = store
+        .get()
+        .search
+        .stopwords
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect();
+    query
+        .split_whitespace()
+        .map(|term| term.replace('"', ""))
+        .filter(|term| !term.is_empty() && !stopwords.contains(&term.to_lowercase()))
+        .collect()
+}
+
+// Every term quoted, so FTS5 operators in the input are taken literally
+fn match_expression(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|term| format!("\"{}\"", term))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Text around the first term found in the body, or its start when the
+// match was elsewhere
+pub(crate) fn snippet(text: &str, terms: &[String]) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let position = words
+        .iter()
+        .position(|word| {
+            let word = word.to_lowercase();
+            terms.iter().any(|term| word.contains(&term.to_lowercase()))
+        })
+        .unwrap_or(0);
+
+    let start = position.saturating_sub(SNIPPET_WORDS / 2);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    let mut snippet = words[start..end].join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "… ");
+    }
+    if end < words.len() {
+        snippet.push_str(" …");
+    }
+    snippet
+}
+
+// Lets SQLite parse the tokenizer on a throwaway table first, so a bad
+// config never touches the real index
+async fn check_tokenizer(pool: &SqlitePool, tokenizer: &str) -> CmdResult<()> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query(&format!(
+        "CREATE VIRTUAL TABLE temp.fts_tokenizer_check USING fts5(x, tokenize = \"{}\")",
+        tokenizer
+    ))
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| AppError::Validation(format!("Invalid tokenizer \"{}\": {}", tokenizer, e)))?;
+    sqlx::query("DROP TABLE temp.fts_tokenizer_check")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+// The sync triggers refer to the tables by name, so they keep working once
+// they are recreated
+async fn rebuild(pool: &SqlitePool, tokenizer: &str) -> CmdResult<SearchIndexReport> {
+    let started = Instant::now();
+
+    let mut tx = pool.begin().await?;
+    for (index, content, columns) in INDEXES {
+        sqlx::query(&format!("DROP TABLE IF EXISTS {}", index))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(
+            "CREATE VIRTUAL TABLE {} USING fts5(
+               {},
+               content = '{}', content_rowid = 'id',
+               tokenize = \"{}\"
+             )",
+            index, columns, content, tokenizer
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!("INSERT INTO {0}({0}) VALUES ('rebuild')", index))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(SearchIndexReport {
+        tokenizer: tokenizer.to_string(),
+        documents_indexed: document_count(pool).await?,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+async fn document_count(pool: &SqlitePool) -> CmdResult<i64> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents")
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enough VM instructions to reach the progress handler
+    const LONG_QUERY: &str =
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 100000)
+         SELECT COUNT(*) FROM c";
+
+    #[test]
+    fn sessions_drop_stale_requests() {
+        let sessions = SearchSessions::default();
+        let first = sessions.start("page", 5).unwrap();
+        assert!(sessions.start("page", 3).is_none());
+        assert!(!first.load(Ordering::Relaxed));
+
+        let second = sessions.start("page", 6).unwrap();
+        assert!(first.load(Ordering::Relaxed));
+        assert!(!second.load(Ordering::Relaxed));
+        // Other search boxes count on their own
+        assert!(sessions.start("sidebar", 1).is_some());
+        assert!(!second.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn sessions_start_over_after_a_reload() {
+        let sessions = SearchSessions::default();
+        let before = sessions.start("page", 40).unwrap();
+        for request_id in [0, 1] {
+            assert!(sessions.start("page", request_id).is_some());
+        }
+        assert!(before.load(Ordering::Relaxed));
+        assert!(sessions.start("page", 2).is_some());
+        assert!(sessions.start("page", 1).is_some());
+    }
+
+    #[test]
+    fn progress_handler_never_outlives_the_search() {
+        tauri::async_runtime::block_on(async {
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+
+            let mut guard = ProgressGuard::set(pool.acquire().await.unwrap(), || false)
+                .await
+                .unwrap();
+            let interrupted = sqlx::query(LONG_QUERY).fetch_one(&mut *guard.conn).await;
+            assert!(interrupted.is_err());
+            guard.release().await.unwrap();
+            assert!(sqlx::query(LONG_QUERY).fetch_one(&pool).await.is_ok());
+
+            // Dropped without release, e.g. by an early return
+            let guard = ProgressGuard::set(pool.acquire().await.unwrap(), || false)
+                .await
+                .unwrap();
+            drop(guard);
+            assert!(sqlx::query(LONG_QUERY).fetch_one(&pool).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn every_sync_trigger_comes_from_a_migration() {
+        for name in SYNC_TRIGGERS {
+            let sql = migrations::trigger_sql(name).unwrap();
+            assert!(sql.trim_start().starts_with("CREATE TRIGGER"), "{}", name);
+            assert!(sql.ends_with("END"), "{}", name);
+        }
+        assert!(migrations::trigger_sql("documents_fts").is_none());
+    }
+
+    #[test]
+    fn resuming_puts_the_migration_triggers_back() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            let triggers = || async {
+                let rows: Vec<(String, String)> = sqlx::query_as(
+                    "SELECT name, sql FROM sqlite_master WHERE type = 'trigger' ORDER BY name",
+                )
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+                rows
+            };
+            let migrated = triggers().await;
+
+            assert!(defer_indexing(&pool).await.unwrap());
+            assert!(indexing_deferred(&pool).await.unwrap());
+            sqlx::query(
+                "INSERT INTO documents (title, text_content) VALUES ('Deferred', 'walrus')",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            let catch_up = resume_indexing(&pool).await.unwrap();
+            assert!(!indexing_deferred(&pool).await.unwrap());
+            assert_eq!(triggers().await, migrated);
+            assert!(catch_up.rows_indexed >= 1);
+            let (found,): (i64,) = sqlx::query_as(
+                "SELECT COUNT(*) FROM documents_fts WHERE documents_fts MATCH 'walrus'",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            assert_eq!(found, 1);
+        });
+    }
+
+    // Rows of a full-text index matching `query`
+    async fn matches(pool: &SqlitePool, index: &str, query: &str) -> i64 {
+        let (found,): (i64,) = sqlx::query_as(&format!(
+            "SELECT COUNT(*) FROM {0} WHERE {0} MATCH ?",
+            index
+        ))
+        .bind(query)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        found
+    }
+
+    #[test]
+    fn locked_bodies_stay_out_of_the_index() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query("INSERT INTO documents (title, text_content) VALUES ('Diary', 'walrus')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "documents_fts", "walrus").await, 1);
+
+            sqlx::query("UPDATE documents SET locked = 1")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "documents_fts", "walrus").await, 0);
+            assert_eq!(matches(&pool, "documents_fts", "diary").await, 1);
+
+            let tokenizer = SearchOptions::default().tokenizer().unwrap();
+            rebuild(&pool, &tokenizer).await.unwrap();
+            assert_eq!(matches(&pool, "documents_fts", "walrus").await, 0);
+            assert_eq!(matches(&pool, "documents_fts", "diary").await, 1);
+
+            sqlx::query("UPDATE documents SET locked = 0")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "documents_fts", "walrus").await, 1);
+        });
+    }
+
+    #[test]
+    fn versions_of_locked_documents_leave_the_index() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query("INSERT INTO documents (title, text_content) VALUES ('Diary', 'tern')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            let version = "INSERT INTO document_versions (document_id, title, text_content)
+                           SELECT id, title, ? FROM documents";
+            sqlx::query(version)
+                .bind("walrus")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "document_versions_fts", "walrus").await, 1);
+
+            sqlx::query("UPDATE documents SET locked = 1")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "document_versions_fts", "walrus").await, 0);
+            // Nor do versions saved while locked go in
+            sqlx::query(version)
+                .bind("narwhal")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "document_versions_fts", "narwhal").await, 0);
+
+            let tokenizer = SearchOptions::default().tokenizer().unwrap();
+            rebuild(&pool, &tokenizer).await.unwrap();
+            assert_eq!(matches(&pool, "document_versions_fts", "walrus").await, 0);
+
+            sqlx::query("UPDATE documents SET locked = 0")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(matches(&pool, "document_versions_fts", "walrus").await, 1);
+            assert_eq!(matches(&pool, "document_versions_fts", "narwhal").await, 1);
+
+            // Deleting a locked document takes versions the index never had
+            sqlx::query("UPDATE documents SET locked = 1")
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("DELETE FROM documents")
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                "INSERT INTO document_versions_fts(document_versions_fts, rank)
+                 VALUES ('integrity-check', 1)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn locked_documents_come_without_a_body() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query(
+                "INSERT INTO documents (title, text_content, locked)
+                 VALUES ('Open', 'visible', 0), ('Closed', 'secret', 1)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            let documents: Vec<Document> = sqlx::query_as("SELECT * FROM documents ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+            let documents: Vec<Document> = documents
+                .into_iter()
+                .map(Document::without_locked_body)
+                .collect();
+            assert!(!documents[0].locked);
+            assert_eq!(documents[0].text_content.as_deref(), Some("visible"));
+            assert!(documents[1].locked);
+            assert_eq!(documents[1].text_content, None);
+        });
+    }
+
+    // What a bulk import does, one committed row at a time
+    async fn import_documents(pool: &SqlitePool, numbers: std::ops::Range<usize>) {
+        for i in numbers {
+            sqlx::query("INSERT INTO documents (title, text_content) VALUES (?, ?)")
+                .bind(format!("Report {}", i))
+                .bind(format!("walrus {} tern{}", i % 7, i % 3))
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    // Rowids each index returns for a few queries, and its integrity check
+    async fn index_state(pool: &SqlitePool) -> Vec<Vec<i64>> {
+        let mut state = Vec::new();
+        for (index, _, _) in INDEXES {
+            sqlx::query(&format!(
+                "INSERT INTO {0}({0}, rank) VALUES ('integrity-check', 1)",
+                index
+            ))
+            .execute(pool)
+            .await
+            .unwrap();
+            for query in ["walrus", "tern1", "report", "3"] {
+                let rows: Vec<(i64,)> = sqlx::query_as(&format!(
+                    "SELECT rowid FROM {0} WHERE {0} MATCH ? ORDER BY rowid",
+                    index
+                ))
+                .bind(query)
+                .fetch_all(pool)
+                .await
+                .unwrap();
+                state.push(rows.into_iter().map(|(id,)| id).collect());
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn deferred_imports_are_indexed_like_live_ones() {
+        tauri::async_runtime::block_on(async {
+            let live = db::test_pool().await;
+            import_documents(&live, 0..300).await;
+
+            let deferred = db::test_pool().await;
+            assert!(defer_indexing(&deferred).await.unwrap());
+            import_documents(&deferred, 0..300).await;
+            let catch_up = resume_indexing(&deferred).await.unwrap();
+            // Each document and its first version
+            assert_eq!(catch_up.rows_indexed, 600);
+            assert!(!catch_up.rebuilt);
+
+            assert_eq!(index_state(&deferred).await, index_state(&live).await);
+        });
+    }
+
+    #[test]
+    fn edits_while_deferred_rebuild_the_index() {
+        tauri::async_runtime::block_on(async {
+            let edit = |pool: SqlitePool| async move {
+                for sql in [
+                    "UPDATE documents SET text_content = 'narwhal' WHERE id = 5",
+                    "DELETE FROM documents WHERE id = 8",
+                    "UPDATE documents SET locked = 1 WHERE id = 13",
+                ] {
+                    sqlx::query(sql).execute(&pool).await.unwrap();
+                }
+            };
+            let live = db::test_pool().await;
+            import_documents(&live, 0..50).await;
+            edit(live.clone()).await;
+
+            let deferred = db::test_pool().await;
+            import_documents(&deferred, 0..20).await;
+            assert!(defer_indexing(&deferred).await.unwrap());
+            import_documents(&deferred, 20..50).await;
+            edit(deferred.clone()).await;
+            assert!(resume_indexing(&deferred).await.unwrap().rebuilt);
+
+            assert_eq!(index_state(&deferred).await, index_state(&live).await);
+            assert_eq!(matches(&deferred, "documents_fts", "narwhal").await, 1);
+        });
+    }
+}
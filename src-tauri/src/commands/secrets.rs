@@ -0,0 +1,20 @@
+use crate::error::CmdResult;
+use crate::secrets;
+
+// The keyring can block on an unlock prompt, so it is used off the main
+// thread
+
+#[tauri::command]
+pub async fn set_secret(key: String, value: String) -> CmdResult<()> {
+    tauri::async_runtime::spawn_blocking(move || secrets::set(&key, &value)).await?
+}
+
+#[tauri::command]
+pub async fn get_secret(key: String) -> CmdResult<Option<String>> {
+    tauri::async_runtime::spawn_blocking(move || secrets::get(&key)).await?
+}
+
+#[tauri::command]
+pub async fn delete_secret(key: String) -> CmdResult<()> {
+    tauri::async_runtime::spawn_blocking(move || secrets::delete(&key)).await?
+}
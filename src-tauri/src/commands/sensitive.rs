@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::{DocumentEvent, MAX_PATTERN_SIZE};
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::html;
+use crate::jobs;
+
+const SENSITIVE_TAG: &str = "sensitive";
+// Documents read per query, so the whole archive is never in memory
+const BATCH_SIZE: i64 = 200;
+// Per document; every match still counts
+const MAX_LOCATIONS: usize = 20;
+// Characters of context each side of a match
+const CONTEXT_CHARS: usize = 20;
+
+// Kept narrow so a hit is more likely a real secret than a stray number
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    // Checked with the Luhn sum as well
+    ("credit_card", r"\b(?:\d[ -]?){12,18}\d\b"),
+    (
+        "us_ssn",
+        r"\b(?:00[1-9]|0[1-9]\d|[1-578]\d\d|6[0-57-9]\d|66[0-57-9])-(?:0[1-9]|[1-9]\d)-(?:000[1-9]|00[1-9]\d|0[1-9]\d\d|[1-9]\d{3})\b",
+    ),
+    (
+        "iban",
+        r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b",
+    ),
+    ("aws_access_key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    ("github_token", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+    ("slack_token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}\b"),
+    (
+        "private_key",
+        r"-----BEGIN (?:RSA |EC |DSA |OPENSSH |ENCRYPTED )?PRIVATE KEY-----",
+    ),
+    (
+        "api_key",
+        r#"(?i)\b(?:api[_-]?key|secret|access[_-]?token|password)\s*[:=]\s*["']?[A-Za-z0-9_\-+/=]{12,}"#,
+    ),
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SensitivePattern {
+    pub name: String,
+    pub pattern: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SensitiveMatch {
+    pub pattern: String,
+    /// `title` or `body`.
+    pub field: &'static str,
+    /// Character offset in the field's text, markup left out.
+    pub offset: usize,
+    /// The text around the match, with all but the last four characters
+    /// of the match masked.
+    pub excerpt: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SensitiveDocument {
+    pub document_id: i64,
+    pub title: String,
+    pub match_count: usize,
+    /// The first few matches.
+    pub matches: Vec<SensitiveMatch>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SensitiveReport {
+    pub documents_scanned: usize,
+    pub documents: Vec<SensitiveDocument>,
+    /// Matches per pattern name, patterns without any included.
+    pub counts: BTreeMap<String, usize>,
+    /// Documents newly tagged `sensitive`.
+    pub tagged: usize,
+}
+
+/// The patterns `scan_sensitive` uses unless told otherwise.
+#[tauri::command]
+pub fn builtin_sensitive_patterns() -> Vec<SensitivePattern> {
+    BUILTIN_PATTERNS
+        .iter()
+        .map(|(name, pattern)| SensitivePattern {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+        })
+        .collect()
+}
+
+/// Starts a job looking through every title and body for credit card
+/// numbers, SSNs, keys and the like, and for `patterns` of the user's,
+/// returning where they turn up and how often each pattern matched.
+/// Nothing is changed except, with `tag`, tagging the documents found
+/// `sensitive`; use `export_redacted` to leave the matches out of an
+/// export. The job can be cancelled and reports what it found up to then.
+/// Returns the job id.
+#[tauri::command]
+pub async fn scan_sensitive(
+    app: AppHandle,
+    patterns: Option<Vec<SensitivePattern>>,
+    include_builtin: Option<bool>,
+    tag: Option<bool>,
+) -> CmdResult<u64> {
+    let mut compiled = Vec::new();
+    if include_builtin.unwrap_or(true) {
+        for (name, pattern) in BUILTIN_PATTERNS {
+            compiled.push((name.to_string(), compile(name, pattern)?));
+        }
+    }
+    for pattern in patterns.unwrap_or_default() {
+        let name = pattern.name.trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::Validation(
+                "Every pattern needs a name".to_string(),
+            ));
+        }
+        if compiled.iter().any(|(existing, _)| *existing == name) {
+            return Err(AppError::Validation(format!(
+                "Pattern {} is given twice",
+                name
+            )));
+        }
+        let regex = compile(&name, &pattern.pattern)?;
+        compiled.push((name, regex));
+    }
+    if compiled.is_empty() {
+        return Err(AppError::Validation("No patterns to scan for".to_string()));
+    }
+
+    let pool = db::pool(&app).await?;
+    let tag = tag.unwrap_or(false);
+    let events = app.clone();
+    let job_id = jobs::spawn(&app, "scan_sensitive", move |job| async move {
+        let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents")
+            .fetch_one(&pool)
+            .await?;
+        let total = total as usize;
+        let mut report = SensitiveReport {
+            documents_scanned: 0,
+            documents: Vec::new(),
+            counts: compiled.iter().map(|(name, _)| (name.clone(), 0)).collect(),
+            tagged: 0,
+        };
+
+        let mut after = 0;
+        loop {
+            if job.is_cancelled() {
+                break;
+            }
+            let batch: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+                "SELECT id, title, text_content FROM documents WHERE id > ? ORDER BY id ASC LIMIT ?",
+            )
+            .bind(after)
+            .bind(BATCH_SIZE)
+            .fetch_all(&pool)
+            .await?;
+            let Some(&(last, _, _)) = batch.last() else {
+                break;
+            };
+            after = last;
+            let scanned = batch.len();
+
+            let patterns = compiled.clone();
+            let (found, counts) = tauri::async_runtime::spawn_blocking(move || {
+                let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+                let found: Vec<SensitiveDocument> = batch
+                    .into_iter()
+                    .filter_map(|(id, title, body)| {
+                        scan_document(id, title, body.as_deref(), &patterns, &mut counts)
+                    })
+                    .collect();
+                (found, counts)
+            })
+            .await?;
+
+            report.documents_scanned += scanned;
+            for (name, count) in counts {
+                *report.counts.entry(name).or_default() += count;
+            }
+            report.documents.extend(found);
+            // Documents added meanwhile can take it past the count
+            job.progress(
+                report.documents_scanned,
+                total.max(report.documents_scanned),
+            );
+        }
+
+        if tag && !report.documents.is_empty() {
+            let mut tx = pool.begin().await?;
+            sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+                .bind(SENSITIVE_TAG)
+                .execute(&mut *tx)
+                .await?;
+            let mut tagged = Vec::new();
+            for document in &report.documents {
+                let inserted = sqlx::query(
+                    "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+                     SELECT ?, id FROM tags WHERE name = ?",
+                )
+                .bind(document.document_id)
+                .bind(SENSITIVE_TAG)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+                if inserted > 0 {
+                    tagged.push(document.document_id);
+                }
+            }
+            tx.commit().await?;
+
+            report.tagged = tagged.len();
+            for document_id in tagged {
+                let _ = events.emit("document_updated", DocumentEvent { document_id });
+            }
+        }
+
+        Ok(report)
+    });
+
+    Ok(job_id)
+}
+
+fn compile(name: &str, pattern: &str) -> CmdResult<Regex> {
+    let regex = RegexBuilder::new(pattern)
+        .size_limit(MAX_PATTERN_SIZE)
+        .build()
+        .map_err(|e| AppError::Validation(format!("Invalid pattern {}: {}", name, e)))?;
+    if regex.is_match("") {
+        return Err(AppError::Validation(format!(
+            "Pattern {} must not match empty text",
+            name
+        )));
+    }
+    Ok(regex)
+}
+
+fn scan_document(
+    document_id: i64,
+    title: String,
+    body: Option<&str>,
+    patterns: &[(String, Regex)],
+    counts: &mut BTreeMap<String, usize>,
+) -> Option<SensitiveDocument> {
+    let body = html::strip_tags(body.unwrap_or_default());
+    let mut match_count = 0;
+    let mut matches = Vec::new();
+    for (field, text) in [("title", title.as_str()), ("body", body.as_str())] {
+        for (name, regex) in patterns {
+            for found in regex.find_iter(text) {
+                if name == "credit_card" && !luhn_valid(found.as_str()) {
+                    continue;
+                }
+                match_count += 1;
+                *counts.entry(name.clone()).or_default() += 1;
+                if matches.len() < MAX_LOCATIONS {
+                    matches.push(SensitiveMatch {
+                        pattern: name.clone(),
+                        field,
+                        offset: text[..found.start()].chars().count(),
+                        excerpt: excerpt(text, found.start(), found.end()),
+                    });
+                }
+            }
+        }
+    }
+    (match_count > 0).then_some(SensitiveDocument {
+        document_id,
+        title,
+        match_count,
+        matches,
+    })
+}
+
+// The match masked but for its last four characters, with some context,
+// so the report itself doesn't spread what it found
+fn excerpt(text: &str, start: usize, end: usize) -> String {
+    let before: String = {
+        let mut chars: Vec<char> = text[..start].chars().rev().take(CONTEXT_CHARS).collect();
+        chars.reverse();
+        chars.into_iter().collect()
+    };
+    let after: String = text[end..].chars().take(CONTEXT_CHARS).collect();
+    let found: Vec<char> = text[start..end].chars().collect();
+    let shown = found.len().saturating_sub(4);
+    let masked: String = found
+        .iter()
+        .enumerate()
+        .map(|(index, c)| {
+            if index < shown && !c.is_whitespace() {
+                '•'
+            } else {
+                *c
+            }
+        })
+        .collect();
+    let excerpt = format!("{}{}{}", before, masked, after);
+    excerpt.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, &digit)| {
+            if index % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    (13..=19).contains(&digits.len()) && sum % 10 == 0
+}
@@ -0,0 +1,298 @@
+use std::fs;
+
+use regex::{Captures, Regex};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager, State};
+
+use crate::accelerator::{self, Accelerator};
+use crate::error::{AppError, CmdResult};
+use crate::local_api;
+use crate::menu;
+use crate::settings::{Settings, SettingsStore};
+
+// Bumped when the preferences file layout changes
+const PREFERENCES_VERSION: u64 = 1;
+
+// Machine-specific settings that don't travel with exported preferences
+const LOCAL_KEYS: &[&str] = &["watched_folders", "enable_local_api", "local_api_port"];
+
+const EXPORT_STYLESHEET: &str = "export.css";
+// Room for a themed stylesheet with a font or two inlined as data URLs
+const MAX_STYLESHEET_BYTES: usize = 512 * 1024;
+
+#[derive(Serialize)]
+pub struct SettingsUpdate {
+    pub settings: Settings,
+    /// The thumbnail size changed, so cached thumbnails should be rebuilt
+    /// with `rebuild_thumbnails`.
+    pub thumbnails_outdated: bool,
+    /// The tokenizer options changed, so the search index should be rebuilt
+    /// with `rebuild_search_index`.
+    pub search_index_outdated: bool,
+}
+
+#[tauri::command]
+pub fn get_settings(store: State<'_, SettingsStore>) -> Settings {
+    store.get()
+}
+
+/// Applies the given keys on top of the current settings.
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    changes: Value,
+) -> CmdResult<SettingsUpdate> {
+    let Value::Object(changes) = changes else {
+        return Err(AppError::Validation(
+            "Settings changes must be an object".to_string(),
+        ));
+    };
+
+    let previous = store.get();
+    let mut merged = settings_object(&previous)?;
+    merged.extend(changes);
+
+    apply(&app, &store, previous, merged)
+}
+
+#[derive(Serialize)]
+pub struct AcceleratorCheck {
+    pub valid: bool,
+    /// Why the accelerator is invalid, or why it may not work here though
+    /// it is valid, e.g. because the system takes it for itself.
+    pub warning: Option<String>,
+}
+
+/// Checks a keybinding before it is saved: whether the menu would accept
+/// it, and whether it collides with a well-known system shortcut on this
+/// platform.
+#[tauri::command]
+pub fn validate_accelerator(accelerator: String) -> AcceleratorCheck {
+    match Accelerator::parse(&accelerator) {
+        Ok(parsed) => AcceleratorCheck {
+            valid: true,
+            warning: accelerator::warning(&parsed),
+        },
+        Err(e) => AcceleratorCheck {
+            valid: false,
+            warning: Some(e),
+        },
+    }
+}
+
+/// Writes the portable part of the settings, keybindings included, to a
+/// JSON file that `import_preferences` can restore on another machine.
+#[tauri::command]
+pub fn export_preferences(store: State<'_, SettingsStore>, dest_path: String) -> CmdResult<()> {
+    let mut settings = settings_object(&store.get())?;
+    for key in LOCAL_KEYS {
+        settings.remove(*key);
+    }
+
+    let preferences = serde_json::json!({
+        "version": PREFERENCES_VERSION,
+        "settings": settings,
+    });
+    let json = serde_json::to_string_pretty(&preferences)?;
+    fs::write(&dest_path, json).map_err(AppError::from)
+}
+
+/// Restores preferences exported with `export_preferences`. Without
+/// `overwrite`, only settings and keybindings not set here yet are taken.
+#[tauri::command]
+pub fn import_preferences(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    src_path: String,
+    overwrite: bool,
+) -> CmdResult<SettingsUpdate> {
+    let json = fs::read_to_string(&src_path)?;
+    let preferences: Value = serde_json::from_str(&json)
+        .map_err(|e| AppError::Validation(format!("Invalid preferences file: {}", e)))?;
+
+    match preferences.get("version").and_then(Value::as_u64) {
+        Some(PREFERENCES_VERSION) => {}
+        Some(version) => {
+            return Err(AppError::Validation(format!(
+                "Unsupported preferences version {}",
+                version
+            )))
+        }
+        None => {
+            return Err(AppError::Validation(
+                "Invalid preferences file: missing version".to_string(),
+            ))
+        }
+    }
+    let Some(Value::Object(imported)) = preferences.get("settings") else {
+        return Err(AppError::Validation(
+            "Invalid preferences file: missing settings".to_string(),
+        ));
+    };
+
+    let previous = store.get();
+    let stored_keys = store.stored_keys();
+    let mut merged = settings_object(&previous)?;
+
+    for (key, value) in imported {
+        if LOCAL_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+
+        // Keybindings merge per menu item rather than as a whole
+        if key == "keybindings" {
+            if let (Value::Object(incoming), Some(Value::Object(current))) =
+                (value, merged.get_mut("keybindings"))
+            {
+                for (id, accelerator) in incoming {
+                    if overwrite || !current.contains_key(id) {
+                        current.insert(id.clone(), accelerator.clone());
+                    }
+                }
+                continue;
+            }
+        }
+
+        if overwrite || !stored_keys.contains(key) {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+
+    apply(&app, &store, previous, merged)
+}
+
+fn settings_object(settings: &Settings) -> CmdResult<Map<String, Value>> {
+    match serde_json::to_value(settings)? {
+        Value::Object(fields) => Ok(fields),
+        _ => Err(AppError::Validation(
+            "Settings must serialize to an object".to_string(),
+        )),
+    }
+}
+
+// Validates and stores the merged settings, rebuilding the menu when the
+// keybindings changed
+fn apply(
+    app: &AppHandle,
+    store: &SettingsStore,
+    previous: Settings,
+    merged: Map<String, Value>,
+) -> CmdResult<SettingsUpdate> {
+    let settings: Settings = serde_json::from_value(Value::Object(merged))?;
+    settings.validate()?;
+
+    if settings.keybindings != previous.keybindings {
+        // Building the menu also checks the accelerators parse
+        if let Err(e) = menu::apply(app, &settings.keybindings) {
+            let _ = menu::apply(app, &previous.keybindings);
+            return Err(AppError::Validation(format!("Invalid keybinding: {}", e)));
+        }
+    }
+
+    if let Err(e) = store.replace(settings.clone()) {
+        let _ = menu::apply(app, &previous.keybindings);
+        return Err(e);
+    }
+    if settings.enable_local_api != previous.enable_local_api
+        || settings.local_api_port != previous.local_api_port
+    {
+        local_api::sync(app);
+    }
+
+    Ok(SettingsUpdate {
+        thumbnails_outdated: settings.thumbnail_size != previous.thumbnail_size,
+        search_index_outdated: settings.search.tokenizer().ok() != previous.search.tokenizer().ok(),
+        settings,
+    })
+}
+
+/// The stylesheet rich HTML exports use instead of their default look, or
+/// `None` when none is set.
+#[tauri::command]
+pub fn get_export_stylesheet(app: AppHandle) -> CmdResult<Option<String>> {
+    export_stylesheet(&app)
+}
+
+/// Stores `css` as the export stylesheet and returns what was kept:
+/// `@import` rules and `url()` references pointing at remote resources are
+/// removed so opening an export never reaches out to the network. Data
+/// URLs and relative references stay.
+#[tauri::command]
+pub fn set_export_stylesheet(app: AppHandle, css: String) -> CmdResult<String> {
+    if css.len() > MAX_STYLESHEET_BYTES {
+        return Err(AppError::Validation(format!(
+            "Stylesheets are limited to {} KB",
+            MAX_STYLESHEET_BYTES / 1024
+        )));
+    }
+    let css = strip_remote_resources(&css);
+
+    let path = app.path().app_config_dir()?.join(EXPORT_STYLESHEET);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, &css)?;
+    Ok(css)
+}
+
+/// Goes back to the default export styles.
+#[tauri::command]
+pub fn reset_export_stylesheet(app: AppHandle) -> CmdResult<()> {
+    let path = app.path().app_config_dir()?.join(EXPORT_STYLESHEET);
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn export_stylesheet(app: &AppHandle) -> CmdResult<Option<String>> {
+    let path = app.path().app_config_dir()?.join(EXPORT_STYLESHEET);
+    match fs::read_to_string(path) {
+        Ok(css) => Ok(Some(css).filter(|css| !css.trim().is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn strip_remote_resources(css: &str) -> String {
+    let imports = Regex::new(r#"(?i)@import\s+(?:url\(\s*)?["']?([^"')\s;]*)[^;]*;?"#)
+        .expect("the import pattern is valid");
+    let urls =
+        Regex::new(r#"(?i)url\(\s*["']?([^"')\s]*)["']?\s*\)"#).expect("the url pattern is valid");
+
+    let css = imports.replace_all(css, |captures: &Captures| {
+        if is_remote(&captures[1]) {
+            String::new()
+        } else {
+            captures[0].to_string()
+        }
+    });
+    let css = urls.replace_all(&css, |captures: &Captures| {
+        if is_remote(&captures[1]) {
+            "none".to_string()
+        } else {
+            captures[0].to_string()
+        }
+    });
+    // Keeps the stylesheet from closing the <style> element it goes into
+    css.replace("</", "<\\/")
+}
+
+// Anything with a scheme other than data:, and protocol-relative URLs
+fn is_remote(url: &str) -> bool {
+    if url.starts_with("//") || url.starts_with("\\\\") {
+        return true;
+    }
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            !scheme.eq_ignore_ascii_case("data")
+                && !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,65 @@
+use tauri::{AppHandle, State};
+
+use crate::db;
+use crate::error::CmdResult;
+use crate::metrics;
+use crate::switcher::{Entry, QuickSwitchResult, QuickSwitcher};
+
+const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 100;
+
+#[derive(sqlx::FromRow)]
+struct TitleRow {
+    id: i64,
+    title: String,
+    category: Option<String>,
+    opened_at: Option<i64>,
+    open_count: i64,
+}
+
+/// Documents whose title fuzzily matches `query`, best first, for a
+/// jump-to-document palette. Characters only have to appear in order, so
+/// "qrep" finds "Quarterly report"; documents opened often and recently
+/// rank higher. An empty query lists the recently opened ones.
+#[tauri::command]
+pub async fn quick_switch(
+    app: AppHandle,
+    switcher: State<'_, QuickSwitcher>,
+    query: String,
+    limit: Option<u32>,
+) -> CmdResult<Vec<QuickSwitchResult>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("quick_switch");
+    let (generation,): (i64,) =
+        sqlx::query_as("SELECT generation FROM quick_switch_state WHERE id = 1")
+            .fetch_one(&pool)
+            .await?;
+    if !switcher.is_current(generation) {
+        let rows: Vec<TitleRow> = sqlx::query_as(
+            "SELECT d.id, d.title, c.name AS category, d.opened_at, d.open_count FROM documents d
+             LEFT JOIN categories c ON c.id = d.category_id",
+        )
+        .fetch_all(&pool)
+        .await?;
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                Entry::new(
+                    row.id,
+                    row.title,
+                    row.category,
+                    row.opened_at,
+                    row.open_count,
+                )
+            })
+            .collect();
+        switcher.load(generation, entries);
+    }
+
+    let results = switcher.search(&query, limit, chrono::Utc::now().timestamp());
+    timer.finish(&app, results.len());
+
+    Ok(results)
+}
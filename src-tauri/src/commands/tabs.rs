@@ -0,0 +1,85 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::db;
+use crate::error::CmdResult;
+use crate::settings::SettingsStore;
+
+#[derive(Clone, Serialize)]
+struct TabsRestored {
+    document_ids: Vec<i64>,
+}
+
+/// Remembers the documents open as tabs, oldest first, so they reopen on
+/// the next launch. Duplicates are dropped and only the newest `max_tabs`
+/// are kept; returns the ids stored.
+#[tauri::command]
+pub async fn save_open_tabs(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    ids: Vec<i64>,
+) -> CmdResult<Vec<i64>> {
+    let pool = db::pool(&app).await?;
+    let max_tabs = store.get().max_tabs as usize;
+
+    let mut seen = HashSet::new();
+    let mut ids: Vec<i64> = ids.into_iter().filter(|id| seen.insert(*id)).collect();
+    if ids.len() > max_tabs {
+        ids.drain(..ids.len() - max_tabs);
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM open_tabs")
+        .execute(&mut *tx)
+        .await?;
+    for (position, id) in ids.iter().enumerate() {
+        // Ids of documents that don't exist are left out
+        sqlx::query(
+            "INSERT INTO open_tabs (position, document_id)
+             SELECT ?, id FROM documents WHERE id = ?",
+        )
+        .bind(position as i64)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    load_tabs(&pool).await
+}
+
+#[tauri::command]
+pub async fn get_open_tabs(app: AppHandle) -> CmdResult<Vec<i64>> {
+    let pool = db::pool(&app).await?;
+    load_tabs(&pool).await
+}
+
+/// Sends the tabs of the last session with `tabs_restored` once the
+/// database is loaded, so the frontend can rebuild the tab strip.
+pub fn restore_open_tabs(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let pool = db::wait_for_pool(&app).await;
+        match load_tabs(&pool).await {
+            Ok(document_ids) if document_ids.is_empty() => {}
+            Ok(document_ids) => {
+                let _ = app.emit("tabs_restored", TabsRestored { document_ids });
+            }
+            Err(e) => log::warn!("Failed to restore open tabs: {}", e),
+        }
+    });
+}
+
+// Joined with documents so deleted ones never come back
+async fn load_tabs(pool: &SqlitePool) -> CmdResult<Vec<i64>> {
+    let ids: Vec<(i64,)> = sqlx::query_as(
+        "SELECT t.document_id FROM open_tabs t
+         JOIN documents d ON d.id = t.document_id
+         ORDER BY t.position ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
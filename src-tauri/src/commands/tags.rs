@@ -0,0 +1,582 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::{fold_case_and_accents, normalize_name, DocumentEvent};
+use crate::commands::{audit, categories};
+use crate::db::{self, Document};
+use crate::error::{AppError, CmdResult};
+use crate::metrics;
+
+// Between the parts of a tag path, e.g. `project/acme`
+const SEPARATOR: char = '/';
+const DEFAULT_MAX_EDGES: u32 = 200;
+// Edits per character of the longer name, e.g. one in "invoice(s)"
+const DEFAULT_MERGE_THRESHOLD: f64 = 0.2;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TagNode {
+    pub id: i64,
+    pub name: String,
+    pub usage_count: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TagEdge {
+    pub source: i64,
+    pub target: i64,
+    pub weight: i64,
+}
+
+#[derive(Serialize)]
+pub struct TagGraph {
+    pub nodes: Vec<TagNode>,
+    pub edges: Vec<TagEdge>,
+}
+
+#[derive(Serialize)]
+pub struct TagTreeNode {
+    /// `None` for a parent no tag has been created for, e.g. `project` of a
+    /// `project/acme` tagged before tags had paths.
+    pub id: Option<i64>,
+    /// The last part of the path.
+    pub name: String,
+    pub path: String,
+    /// Documents with this very tag.
+    pub usage_count: i64,
+    /// Documents with this tag or any under it, each once.
+    pub document_count: usize,
+    pub children: Vec<TagTreeNode>,
+}
+
+#[derive(Serialize)]
+pub struct TagMergeSuggestion {
+    /// The most used tag of the group, to merge the others into.
+    pub target_id: i64,
+    /// Every tag of the group, most used first.
+    pub tags: Vec<TagNode>,
+}
+
+/// Tags the document, creating the tag on first use. Names with `/` are
+/// paths, e.g. `project/acme`, and create their parent tags as well; the
+/// document only gets the tag named.
+#[tauri::command]
+pub async fn add_tag(app: AppHandle, document_id: i64, name: String) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let name = tag_path(&name)?;
+
+    let mut tx = pool.begin().await?;
+
+    for (end, _) in name.match_indices(SEPARATOR) {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(&name[..end])
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+        .bind(&name)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+         SELECT ?, id FROM tags WHERE name = ?",
+    )
+    .bind(document_id)
+    .bind(name)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id });
+
+    Ok(())
+}
+
+/// Untags the document. Names no tag could have, such as an empty one, are
+/// looked up as they are, so tags named before paths existed can still be
+/// removed.
+#[tauri::command]
+pub async fn remove_tag(app: AppHandle, document_id: i64, name: String) -> CmdResult<()> {
+    let pool = db::pool(&app).await?;
+
+    let name = tag_path(&name).unwrap_or_else(|_| normalize_name(&name));
+    sqlx::query(
+        "DELETE FROM document_tags
+         WHERE document_id = ? AND tag_id = (SELECT id FROM tags WHERE name = ?)",
+    )
+    .bind(document_id)
+    .bind(name)
+    .execute(&pool)
+    .await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id });
+
+    Ok(())
+}
+
+/// Every tag as a tree of `/` paths, children by name.
+#[tauri::command]
+pub async fn list_tags(app: AppHandle) -> CmdResult<Vec<TagTreeNode>> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("list_tags");
+    let tags: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM tags")
+        .fetch_all(&pool)
+        .await?;
+    let taggings: Vec<(i64, i64)> = sqlx::query_as("SELECT tag_id, document_id FROM document_tags")
+        .fetch_all(&pool)
+        .await?;
+
+    let tag_count = tags.len();
+    let tree = build_tree(tags, taggings);
+    timer.finish(&app, tag_count);
+    Ok(tree)
+}
+
+/// Documents tagged `prefix` or any tag under it, e.g. `project` for
+/// `project/acme` and `project/globex`, most recently edited first.
+/// Documents of archived categories are left out unless
+/// `include_archived`.
+#[tauri::command]
+pub async fn documents_by_tag_prefix(
+    app: AppHandle,
+    prefix: String,
+    include_archived: Option<bool>,
+) -> CmdResult<Vec<Document>> {
+    let prefix = tag_path(&prefix)?;
+    let pool = db::pool(&app).await?;
+
+    tagged_under(&pool, &prefix, include_archived).await
+}
+
+async fn tagged_under(
+    pool: &SqlitePool,
+    prefix: &str,
+    include_archived: Option<bool>,
+) -> CmdResult<Vec<Document>> {
+    // One comparison, in the collation of the unique index, for the tag
+    // itself and those under it
+    sqlx::query_as(&format!(
+        "SELECT d.* FROM documents d
+         WHERE d.id IN (
+           SELECT dt.document_id FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+           WHERE substr(t.name || '/', 1, length(?1) + 1) = (?1 || '/') COLLATE NOCASE
+         ) AND {}
+         ORDER BY d.updated_at DESC, d.id DESC",
+        categories::archived_scope("d.category_id", include_archived)
+    ))
+    .bind(prefix)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Tags with their usage counts plus the strongest co-occurrence pairs,
+/// capped at `max_edges`.
+#[tauri::command]
+pub async fn tag_graph(app: AppHandle, max_edges: Option<u32>) -> CmdResult<TagGraph> {
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("tag_graph");
+    let nodes: Vec<TagNode> = sqlx::query_as(
+        "SELECT t.id, t.name, COUNT(dt.document_id) AS usage_count
+         FROM tags t
+         LEFT JOIN document_tags dt ON dt.tag_id = t.id
+         GROUP BY t.id
+         ORDER BY usage_count DESC, t.name ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let edges: Vec<TagEdge> = sqlx::query_as(
+        "SELECT a.tag_id AS source, b.tag_id AS target, COUNT(*) AS weight
+         FROM document_tags a
+         JOIN document_tags b ON b.document_id = a.document_id AND b.tag_id > a.tag_id
+         GROUP BY a.tag_id, b.tag_id
+         ORDER BY weight DESC, source ASC, target ASC
+         LIMIT ?",
+    )
+    .bind(max_edges.unwrap_or(DEFAULT_MAX_EDGES))
+    .fetch_all(&pool)
+    .await?;
+
+    timer.finish(&app, nodes.len() + edges.len());
+
+    Ok(TagGraph { nodes, edges })
+}
+
+/// Groups of tags whose names look like spellings of the same tag, ignoring
+/// case and accents: two tags are similar when their edit distance, divided
+/// by the length of the longer name, is at most `threshold`, and groups
+/// chain similar pairs together. Largest groups by usage first. Nothing is
+/// merged; accepted groups go to `merge_tags`.
+#[tauri::command]
+pub async fn suggest_tag_merges(
+    app: AppHandle,
+    threshold: Option<f64>,
+) -> CmdResult<Vec<TagMergeSuggestion>> {
+    let threshold = threshold.unwrap_or(DEFAULT_MERGE_THRESHOLD);
+    if !(0.0..1.0).contains(&threshold) {
+        return Err(AppError::Validation(
+            "threshold must be at least 0 and below 1".to_string(),
+        ));
+    }
+    let pool = db::pool(&app).await?;
+
+    let timer = metrics::Timer::start("suggest_tag_merges");
+    let tags: Vec<TagNode> = sqlx::query_as(
+        "SELECT t.id, t.name, COUNT(dt.document_id) AS usage_count
+         FROM tags t
+         LEFT JOIN document_tags dt ON dt.tag_id = t.id
+         GROUP BY t.id
+         ORDER BY usage_count DESC, t.name ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let suggestions = tauri::async_runtime::spawn_blocking(move || {
+        let folded: Vec<Vec<char>> = tags
+            .iter()
+            .map(|tag| fold_case_and_accents(&tag.name).chars().collect())
+            .collect();
+
+        // Union-find over the similar pairs
+        let mut parent: Vec<usize> = (0..tags.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for a in 0..tags.len() {
+            for b in a + 1..tags.len() {
+                let longest = folded[a].len().max(folded[b].len()).max(1) as f64;
+                let length_gap = folded[a].len().abs_diff(folded[b].len()) as f64;
+                if length_gap / longest > threshold {
+                    continue;
+                }
+                if edit_distance(&folded[a], &folded[b]) as f64 / longest <= threshold {
+                    let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                    // Tags are ordered by usage, so the lower index stays
+                    // the root and becomes the target
+                    parent[ra.max(rb)] = ra.min(rb);
+                }
+            }
+        }
+
+        let mut groups: BTreeMap<usize, Vec<TagNode>> = BTreeMap::new();
+        for (i, tag) in tags.into_iter().enumerate() {
+            let group = root(&mut parent, i);
+            groups.entry(group).or_default().push(tag);
+        }
+        let mut suggestions: Vec<TagMergeSuggestion> = groups
+            .into_values()
+            .filter(|tags| tags.len() > 1)
+            .map(|tags| TagMergeSuggestion {
+                target_id: tags[0].id,
+                tags,
+            })
+            .collect();
+        suggestions.sort_by_key(|s| {
+            std::cmp::Reverse(s.tags.iter().map(|tag| tag.usage_count).sum::<i64>())
+        });
+        suggestions
+    })
+    .await?;
+    timer.finish(&app, suggestions.len());
+
+    Ok(suggestions)
+}
+
+/// Moves every document tagged with one of `source_ids` to `target_id` and
+/// deletes the source tags. Returns the number of documents retagged.
+#[tauri::command]
+pub async fn merge_tags(app: AppHandle, target_id: i64, source_ids: Vec<i64>) -> CmdResult<usize> {
+    let source_ids: Vec<i64> = source_ids
+        .into_iter()
+        .filter(|id| *id != target_id)
+        .collect();
+    if source_ids.is_empty() {
+        return Ok(0);
+    }
+    let pool = db::pool(&app).await?;
+
+    let target: Option<(String,)> = sqlx::query_as("SELECT name FROM tags WHERE id = ?")
+        .bind(target_id)
+        .fetch_optional(&pool)
+        .await?;
+    let Some((target_name,)) = target else {
+        return Err(AppError::NotFound("Tag not found".to_string()));
+    };
+
+    let where_source = |query: &mut QueryBuilder<Sqlite>| {
+        let mut ids = query.separated(", ");
+        for id in &source_ids {
+            ids.push_bind(*id);
+        }
+        ids.push_unseparated(")");
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let mut query =
+        QueryBuilder::new("SELECT DISTINCT document_id FROM document_tags WHERE tag_id IN (");
+    where_source(&mut query);
+    let documents: Vec<(i64,)> = query.build_query_as().fetch_all(&mut *tx).await?;
+
+    let mut query = QueryBuilder::new(
+        "INSERT OR IGNORE INTO document_tags (document_id, tag_id) SELECT document_id, ",
+    );
+    query.push_bind(target_id);
+    query.push(" FROM document_tags WHERE tag_id IN (");
+    where_source(&mut query);
+    query.build().execute(&mut *tx).await?;
+
+    // Their document_tags rows go with them
+    let mut query = QueryBuilder::new("DELETE FROM tags WHERE id IN (");
+    where_source(&mut query);
+    let merged = query.build().execute(&mut *tx).await?.rows_affected();
+
+    let details = format!("{} tags into {}", merged, target_name);
+    audit::record(&mut *tx, "merge", "tag", Some(target_id), &details).await?;
+    tx.commit().await?;
+
+    for (document_id,) in &documents {
+        let _ = app.emit(
+            "document_updated",
+            DocumentEvent {
+                document_id: *document_id,
+            },
+        );
+    }
+
+    Ok(documents.len())
+}
+
+/// A tag name with each `/` part normalized like any name; `\\` counts as
+/// a separator too, and spaces around one are dropped. Empty parts, as in
+/// `project//acme`, are refused.
+pub(crate) fn tag_path(name: &str) -> CmdResult<String> {
+    let segments: Vec<String> = name.split(['/', '\\']).map(normalize_name).collect();
+    if segments.iter().all(|segment| segment.is_empty()) {
+        return Err(AppError::Validation("Tag name cannot be empty".to_string()));
+    }
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(AppError::Validation(format!(
+            "Tag name {:?} has an empty part",
+            name.trim()
+        )));
+    }
+    Ok(segments.join("/"))
+}
+
+// The tags as a tree, counting `taggings` of (tag, document)
+fn build_tree(tags: Vec<(i64, String)>, taggings: Vec<(i64, i64)>) -> Vec<TagTreeNode> {
+    // Paths folded like the unique index folds names, ASCII case only, so
+    // `Project/acme` and `project/globex` share their parent
+    let mut nodes: BTreeMap<String, PathNode> = BTreeMap::new();
+    let mut paths: HashMap<i64, String> = HashMap::new();
+    for (id, name) in tags {
+        let mut path = String::new();
+        for segment in name.split(SEPARATOR) {
+            if !path.is_empty() {
+                path.push(SEPARATOR);
+            }
+            path.push_str(&segment.to_ascii_lowercase());
+            nodes.entry(path.clone()).or_insert_with(|| PathNode {
+                name: segment.to_string(),
+                ..Default::default()
+            });
+        }
+        let node = nodes.get_mut(&path).expect("inserted above");
+        node.id = Some(id);
+        node.name = name
+            .rsplit(SEPARATOR)
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        node.path = Some(name);
+        paths.insert(id, path);
+    }
+    for (tag_id, document_id) in taggings {
+        let Some(path) = paths.get(&tag_id) else {
+            continue;
+        };
+        if let Some(node) = nodes.get_mut(path) {
+            node.usage_count += 1;
+        }
+        // The tag and every tag above it
+        let mut ancestor = path.as_str();
+        loop {
+            if let Some(node) = nodes.get_mut(ancestor) {
+                node.documents.insert(document_id);
+            }
+            match ancestor.rfind(SEPARATOR) {
+                Some(end) => ancestor = &ancestor[..end],
+                None => break,
+            }
+        }
+    }
+
+    tag_tree(&mut nodes, None)
+}
+
+#[derive(Default)]
+struct PathNode {
+    id: Option<i64>,
+    name: String,
+    path: Option<String>,
+    usage_count: i64,
+    documents: HashSet<i64>,
+}
+
+// The children of `parent`, or the top level, taken out of `nodes`.
+// `parent` is the folded key and the path as shown.
+fn tag_tree(
+    nodes: &mut BTreeMap<String, PathNode>,
+    parent: Option<(&str, &str)>,
+) -> Vec<TagTreeNode> {
+    let child_paths: Vec<String> = nodes
+        .keys()
+        .filter(|path| match parent {
+            Some((parent, _)) => path
+                .strip_prefix(parent)
+                .and_then(|rest| rest.strip_prefix(SEPARATOR))
+                .is_some_and(|rest| !rest.contains(SEPARATOR)),
+            None => !path.contains(SEPARATOR),
+        })
+        .cloned()
+        .collect();
+    let mut children = Vec::new();
+    for path in child_paths {
+        let node = nodes.remove(&path).expect("listed above");
+        // Parents without a tag of their own are shown as their children
+        // spell them
+        let shown = node.path.unwrap_or_else(|| match parent {
+            Some((_, shown)) => format!("{}{}{}", shown, SEPARATOR, node.name),
+            None => node.name.clone(),
+        });
+        children.push(TagTreeNode {
+            id: node.id,
+            children: tag_tree(nodes, Some((&path, &shown))),
+            path: shown,
+            name: node.name,
+            usage_count: node.usage_count,
+            document_count: node.documents.len(),
+        });
+    }
+    children
+}
+
+// Levenshtein distance, one row at a time
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_paths_are_normalized_part_by_part() {
+        assert_eq!(tag_path("project/acme").unwrap(), "project/acme");
+        assert_eq!(tag_path(" project \\ acme ").unwrap(), "project/acme");
+        assert_eq!(tag_path("project").unwrap(), "project");
+        for empty_part in ["project//acme", "/project", "project/", "project/ /acme"] {
+            assert!(
+                matches!(tag_path(empty_part), Err(AppError::Validation(_))),
+                "{}",
+                empty_part
+            );
+        }
+        assert!(matches!(tag_path(" / "), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn tag_tree_counts_documents_once_per_subtree() {
+        let tags = vec![
+            (1, "Project/acme".to_string()),
+            (2, "project/globex".to_string()),
+            (3, "inbox".to_string()),
+        ];
+        // Document 10 is under both children of project
+        let taggings = vec![(1, 10), (2, 10), (2, 11), (3, 12)];
+        let tree = build_tree(tags, taggings);
+
+        assert_eq!(tree.len(), 2);
+        let (inbox, project) = (&tree[0], &tree[1]);
+        assert_eq!((inbox.id, inbox.usage_count), (Some(3), 1));
+
+        // No tag row of its own: shown as the first child spelled it
+        assert_eq!(project.id, None);
+        assert_eq!(project.name, "Project");
+        assert_eq!(project.path, "Project");
+        assert_eq!(project.usage_count, 0);
+        assert_eq!(project.document_count, 2);
+
+        let children: Vec<(&str, &str, i64)> = project
+            .children
+            .iter()
+            .map(|child| (child.name.as_str(), child.path.as_str(), child.usage_count))
+            .collect();
+        assert_eq!(
+            children,
+            [("acme", "Project/acme", 1), ("globex", "project/globex", 2)]
+        );
+    }
+
+    #[test]
+    fn tag_tree_names_a_parent_without_a_tag_from_its_child() {
+        let tree = build_tree(vec![(1, "Café/Privé/notes".to_string())], vec![(1, 5)]);
+        let cafe = &tree[0];
+        let prive = &cafe.children[0];
+        assert_eq!((cafe.id, cafe.path.as_str()), (None, "Café"));
+        assert_eq!((prive.id, prive.path.as_str()), (None, "Café/Privé"));
+        assert_eq!(prive.children[0].path, "Café/Privé/notes");
+        assert_eq!(prive.document_count, 1);
+    }
+
+    #[test]
+    fn tag_prefix_matches_the_tag_and_those_under_it() {
+        tauri::async_runtime::block_on(async {
+            let pool = db::test_pool().await;
+            sqlx::query(
+                "INSERT INTO documents (id, title) VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd');
+                 INSERT INTO tags (id, name)
+                 VALUES (1, 'Project'), (2, 'project/acme'), (3, 'PROJECT/Globex'),
+                        (4, 'projects');
+                 INSERT INTO document_tags (document_id, tag_id)
+                 VALUES (1, 1), (2, 2), (3, 3), (4, 4);",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+
+            let ids = |documents: Vec<Document>| {
+                let mut ids: Vec<i64> = documents.iter().map(|d| d.id).collect();
+                ids.sort();
+                ids
+            };
+            let under = tagged_under(&pool, "project", None).await.unwrap();
+            assert_eq!(ids(under), [1, 2, 3]);
+            let under = tagged_under(&pool, "Project/ACME", None).await.unwrap();
+            assert_eq!(ids(under), [2]);
+        });
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::commands::documents::normalize_name;
+use crate::db::{self, Category};
+use crate::error::{AppError, CmdResult};
+use crate::rules::CategoryRule;
+use crate::settings::SettingsStore;
+
+const TAXONOMY_VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Taxonomy {
+    version: u64,
+    /// Parents always come before their children.
+    categories: Vec<TaxonomyCategory>,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TaxonomyCategory {
+    /// Only meaningful within the file, for `parent_id` and rule targets.
+    id: i64,
+    name: String,
+    icon: String,
+    color: String,
+    parent_id: Option<i64>,
+    description: Option<String>,
+    sort_order: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rule: Option<CategoryRule>,
+}
+
+#[derive(Serialize)]
+pub struct TaxonomyExport {
+    pub categories: usize,
+    pub tags: usize,
+}
+
+#[derive(Serialize)]
+pub struct TaxonomyImport {
+    pub categories_created: usize,
+    /// Categories that already existed under the same parent and were kept.
+    pub categories_merged: usize,
+    pub tags_created: usize,
+    pub tags_merged: usize,
+    /// Lifecycle rules set on the new categories.
+    pub rules: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct TaxonomyImported {
+    categories_created: usize,
+    tags_created: usize,
+}
+
+/// Writes the category hierarchy, with icons, colors and lifecycle rules,
+/// and every tag name to a JSON file that `import_taxonomy` can recreate
+/// in another archive. No documents are included.
+#[tauri::command]
+pub async fn export_taxonomy(app: AppHandle, dest_path: String) -> CmdResult<TaxonomyExport> {
+    let pool = db::pool(&app).await?;
+
+    let categories: Vec<Category> =
+        sqlx::query_as("SELECT * FROM categories ORDER BY sort_order ASC, name ASC, id ASC")
+            .fetch_all(&pool)
+            .await?;
+    let mut rules: HashMap<i64, CategoryRule> = HashMap::new();
+    let rows: Vec<(i64, String)> = sqlx::query_as("SELECT category_id, rule FROM category_rules")
+        .fetch_all(&pool)
+        .await?;
+    for (category_id, rule) in rows {
+        match serde_json::from_str(&rule) {
+            Ok(rule) => {
+                rules.insert(category_id, rule);
+            }
+            Err(e) => log::warn!("Skipping invalid rule of category {}: {}", category_id, e),
+        }
+    }
+    let tags: Vec<(String,)> = sqlx::query_as("SELECT name FROM tags ORDER BY name ASC")
+        .fetch_all(&pool)
+        .await?;
+
+    let taxonomy = Taxonomy {
+        version: TAXONOMY_VERSION,
+        categories: parents_first(categories)
+            .into_iter()
+            .map(|category| TaxonomyCategory {
+                rule: rules.remove(&category.id),
+                id: category.id,
+                name: category.name,
+                icon: category.icon,
+                color: category.color,
+                parent_id: category.parent_id,
+                description: category.description,
+                sort_order: category.sort_order,
+            })
+            .collect(),
+        tags: tags.into_iter().map(|(name,)| name).collect(),
+    };
+    fs::write(&dest_path, serde_json::to_string_pretty(&taxonomy)?)?;
+
+    Ok(TaxonomyExport {
+        categories: taxonomy.categories.len(),
+        tags: taxonomy.tags.len(),
+    })
+}
+
+// Breadth-first from the roots. Categories whose parent is missing or in a
+// cycle are treated as roots, so every category is listed once.
+fn parents_first(categories: Vec<Category>) -> Vec<Category> {
+    let ids: HashSet<i64> = categories.iter().map(|category| category.id).collect();
+    let mut children: HashMap<i64, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (index, category) in categories.iter().enumerate() {
+        match category.parent_id {
+            Some(parent) if ids.contains(&parent) && parent != category.id => {
+                children.entry(parent).or_default().push(index)
+            }
+            _ => roots.push(index),
+        }
+    }
+
+    let mut order = Vec::with_capacity(categories.len());
+    let mut visited = vec![false; categories.len()];
+    let starts: Vec<usize> = roots.into_iter().chain(0..categories.len()).collect();
+    for start in starts {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(index) = queue.pop_front() {
+            order.push(index);
+            for &child in children.get(&categories[index].id).into_iter().flatten() {
+                if !visited[child] {
+                    visited[child] = true;
+                    queue.push_back(child);
+                }
+            }
+        }
+    }
+
+    let mut slots: Vec<Option<Category>> = categories.into_iter().map(Some).collect();
+    let mut sorted: Vec<Category> = order
+        .into_iter()
+        .filter_map(|index| slots[index].take())
+        .collect();
+    // A category reached before its parent had a cycle; it becomes a root
+    let mut seen = HashSet::new();
+    for category in &mut sorted {
+        if category
+            .parent_id
+            .is_some_and(|parent| !seen.contains(&parent))
+        {
+            category.parent_id = None;
+        }
+        seen.insert(category.id);
+    }
+    sorted
+}
+
+/// Recreates a taxonomy exported with `export_taxonomy`. With `merge`, a
+/// category that already exists under the same parent is kept and its
+/// subcategories are added to it, and existing tags are kept. Without it,
+/// any name that already exists is a conflict and nothing is imported.
+/// Importing trash rules needs confirmation.
+#[tauri::command]
+pub async fn import_taxonomy(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    path: String,
+    merge: bool,
+    confirmed: Option<bool>,
+) -> CmdResult<TaxonomyImport> {
+    let json = fs::read_to_string(&path)?;
+    let taxonomy: Taxonomy = serde_json::from_str(&json)
+        .map_err(|e| AppError::Validation(format!("Invalid taxonomy file: {}", e)))?;
+    if taxonomy.version != TAXONOMY_VERSION {
+        return Err(AppError::Validation(format!(
+            "Unsupported taxonomy version {}",
+            taxonomy.version
+        )));
+    }
+    if taxonomy
+        .categories
+        .iter()
+        .any(|category| matches!(category.rule, Some(CategoryRule::Trash { .. })))
+    {
+        store.get().require_confirmation(confirmed)?;
+    }
+
+    let pool = db::pool(&app).await?;
+    let mut tx = pool.begin().await?;
+    let mut report = TaxonomyImport {
+        categories_created: 0,
+        categories_merged: 0,
+        tags_created: 0,
+        tags_merged: 0,
+        rules: 0,
+    };
+
+    // File ids to ids here, and which of them were created by this import
+    let mut category_ids: HashMap<i64, i64> = HashMap::new();
+    let mut created: HashSet<i64> = HashSet::new();
+    for category in &taxonomy.categories {
+        let name = normalize_name(&category.name);
+        if name.is_empty() {
+            return Err(AppError::Validation(
+                "Invalid taxonomy file: a category has no name".to_string(),
+            ));
+        }
+        let parent_id = category
+            .parent_id
+            .and_then(|parent| category_ids.get(&parent).copied());
+
+        let existing: Option<(i64,)> =
+            sqlx::query_as("SELECT id FROM categories WHERE name = ? AND parent_id IS ?")
+                .bind(&name)
+                .bind(parent_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        if let Some((id,)) = existing {
+            // Listed twice in the file is not a collision with this archive
+            if !merge && !created.contains(&id) {
+                return Err(AppError::Conflict(format!(
+                    "A category named {} already exists",
+                    name
+                )));
+            }
+            category_ids.insert(category.id, id);
+            report.categories_merged += 1;
+            continue;
+        }
+
+        let level = match parent_id {
+            Some(parent) => {
+                let (level,): (i64,) = sqlx::query_as("SELECT level FROM categories WHERE id = ?")
+                    .bind(parent)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                level + 1
+            }
+            None => 0,
+        };
+        let id = sqlx::query(
+            "INSERT INTO categories (name, icon, color, parent_id, description, sort_order, level)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&name)
+        .bind(&category.icon)
+        .bind(&category.color)
+        .bind(parent_id)
+        .bind(&category.description)
+        .bind(category.sort_order)
+        .bind(level)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        category_ids.insert(category.id, id);
+        created.insert(id);
+        report.categories_created += 1;
+    }
+
+    // Rules go on new categories only, once every target has an id here
+    for category in &taxonomy.categories {
+        let Some(mut rule) = category.rule.clone() else {
+            continue;
+        };
+        let id = category_ids[&category.id];
+        if !created.contains(&id) {
+            continue;
+        }
+        if let CategoryRule::Archive {
+            target_category_id, ..
+        } = &mut rule
+        {
+            match category_ids.get(target_category_id) {
+                Some(target) if *target != id => *target_category_id = *target,
+                _ => {
+                    log::warn!(
+                        "Skipping rule of category {}: its archive category is not in the file",
+                        category.name
+                    );
+                    continue;
+                }
+            }
+        }
+        sqlx::query("INSERT INTO category_rules (category_id, rule) VALUES (?, ?)")
+            .bind(id)
+            .bind(serde_json::to_string(&rule)?)
+            .execute(&mut *tx)
+            .await?;
+        report.rules += 1;
+    }
+
+    let mut seen_tags = HashSet::new();
+    for tag in &taxonomy.tags {
+        let name = normalize_name(tag);
+        // Tag names are compared regardless of case
+        if name.is_empty() || !seen_tags.insert(name.to_lowercase()) {
+            continue;
+        }
+        let inserted = sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(&name)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if inserted > 0 {
+            report.tags_created += 1;
+        } else if merge {
+            report.tags_merged += 1;
+        } else {
+            return Err(AppError::Conflict(format!(
+                "A tag named {} already exists",
+                name
+            )));
+        }
+    }
+
+    tx.commit().await?;
+
+    let _ = app.emit(
+        "taxonomy_imported",
+        TaxonomyImported {
+            categories_created: report.categories_created,
+            tags_created: report.tags_created,
+        },
+    );
+
+    Ok(report)
+}
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, Manager};
+
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::jobs;
+use crate::settings::SettingsStore;
+use crate::thumbnails::{self, ThumbnailCache, ThumbnailCacheStats};
+
+/// Path of the attachment's thumbnail at the configured size, generating
+/// it on first request.
+#[tauri::command]
+pub async fn get_thumbnail(app: AppHandle, attachment_id: i64) -> CmdResult<String> {
+    let pool = db::pool(&app).await?;
+
+    let (filepath,): (String,) =
+        sqlx::query_as("SELECT filepath FROM attachments WHERE id = ? AND filetype LIKE 'image/%'")
+            .bind(attachment_id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Image attachment not found".to_string()))?;
+
+    let settings = app.state::<SettingsStore>().get();
+    let size = settings.thumbnail_size;
+    let root = thumbnails::cache_root(&app)?;
+    let dest = thumbnails::thumbnail_path(&root, size, attachment_id);
+
+    let target = dest.clone();
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let cache = handle.state::<ThumbnailCache>();
+        if target.exists() {
+            cache.hit(&target);
+            return Ok(());
+        }
+        cache.miss();
+        thumbnails::generate(&PathBuf::from(filepath), &target, size)?;
+        cache.added(&root, &target, settings.thumbnail_cache_mb);
+        Ok::<_, AppError>(())
+    })
+    .await??;
+
+    Ok(dest.to_string_lossy().to_string())
+}
+
+/// Starts a job that drops thumbnails of other sizes and regenerates the
+/// cache at the configured size. With `background` it pauses while power
+/// is low. Returns the job id.
+#[tauri::command]
+pub async fn rebuild_thumbnails(app: AppHandle, background: Option<bool>) -> CmdResult<u64> {
+    let pool = db::pool(&app).await?;
+    let root = thumbnails::cache_root(&app)?;
+    let settings = app.state::<SettingsStore>().get();
+    let (size, limit_mb) = (settings.thumbnail_size, settings.thumbnail_cache_mb);
+    let handle = app.clone();
+
+    let background = background.unwrap_or(false);
+    let job_id = jobs::spawn_with(
+        &app,
+        "rebuild_thumbnails",
+        background,
+        move |job| async move {
+            let evict_root = root.clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                thumbnails::evict_other_sizes(&evict_root, size)
+            })
+            .await??;
+            handle.state::<ThumbnailCache>().recount();
+
+            let images: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT id, filepath FROM attachments WHERE filetype LIKE 'image/%' ORDER BY id ASC",
+        )
+        .fetch_all(&pool)
+        .await?;
+
+            let total = images.len();
+            let mut generated = 0;
+
+            for (index, (id, filepath)) in images.into_iter().enumerate() {
+                job.wait_if_paused().await;
+                if job.is_cancelled() {
+                    break;
+                }
+                job.progress(index, total);
+
+                let dest = thumbnails::thumbnail_path(&root, size, id);
+                if dest.exists() {
+                    continue;
+                }
+
+                let (cache_root, handle) = (root.clone(), handle.clone());
+                let result = tauri::async_runtime::spawn_blocking(move || {
+                    thumbnails::generate(&PathBuf::from(filepath), &dest, size)?;
+                    handle
+                        .state::<ThumbnailCache>()
+                        .added(&cache_root, &dest, limit_mb);
+                    Ok::<_, AppError>(())
+                })
+                .await?;
+
+                match result {
+                    Ok(()) => generated += 1,
+                    Err(e) => {
+                        log::warn!("Could not generate thumbnail for attachment {}: {}", id, e)
+                    }
+                }
+            }
+
+            job.progress(total, total);
+            Ok(generated)
+        },
+    );
+
+    Ok(job_id)
+}
+
+/// How big the thumbnail cache is against `thumbnail_cache_mb`, and how
+/// often it was hit and evicted from since launch.
+#[tauri::command]
+pub async fn thumbnail_cache_stats(app: AppHandle) -> CmdResult<ThumbnailCacheStats> {
+    let root = thumbnails::cache_root(&app)?;
+    let limit_mb = app.state::<SettingsStore>().get().thumbnail_cache_mb;
+
+    let handle = app.clone();
+    let stats = tauri::async_runtime::spawn_blocking(move || {
+        handle.state::<ThumbnailCache>().stats(&root, limit_mb)
+    })
+    .await?;
+    Ok(stats)
+}
@@ -0,0 +1,234 @@
+use serde::Serialize;
+use sqlx::{SqliteExecutor, SqlitePool};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::{refresh_title_sort, DocumentEvent};
+use crate::commands::related;
+use crate::db;
+use crate::deep_link;
+use crate::error::{AppError, CmdResult};
+use crate::html;
+
+const INDEX_KIND: &str = "index";
+
+#[derive(Serialize)]
+pub struct IndexDocument {
+    pub document_id: i64,
+    /// Whether the index was written by this call rather than kept.
+    pub generated: bool,
+    /// Documents listed in it.
+    pub documents: usize,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct GeneratedDocument {
+    pub kind: String,
+    /// The category it was generated for; `None` for the whole archive.
+    pub category_id: Option<i64>,
+    pub generated_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct Entry {
+    id: i64,
+    title: String,
+    updated_at: String,
+    /// Slash-separated names from the indexed category down.
+    category: Option<String>,
+}
+
+/// Creates a document listing every document in a category and its
+/// subcategories, or in the whole archive, as links grouped by category
+/// with counts and last-updated dates. It lives in that category and is
+/// marked as generated, so the app keeps it read-only. An existing index
+/// is returned as is unless `force`, which writes it again.
+#[tauri::command]
+pub async fn generate_index_document(
+    app: AppHandle,
+    category_id: Option<i64>,
+    force: Option<bool>,
+) -> CmdResult<IndexDocument> {
+    let pool = db::pool(&app).await?;
+
+    let scope_name = match category_id {
+        Some(id) => {
+            let name: Option<(String,)> =
+                sqlx::query_as("SELECT name FROM categories WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(&pool)
+                    .await?;
+            Some(
+                name.ok_or_else(|| AppError::NotFound("Category not found".to_string()))?
+                    .0,
+            )
+        }
+        None => None,
+    };
+    let existing: Option<(i64,)> = sqlx::query_as(
+        "SELECT document_id FROM generated_documents WHERE kind = ? AND category_id IS ?",
+    )
+    .bind(INDEX_KIND)
+    .bind(category_id)
+    .fetch_optional(&pool)
+    .await?;
+
+    let entries = index_entries(&pool, category_id).await?;
+    if let (Some((document_id,)), false) = (existing, force.unwrap_or(false)) {
+        return Ok(IndexDocument {
+            document_id,
+            generated: false,
+            documents: entries.len(),
+        });
+    }
+
+    let title = match &scope_name {
+        Some(name) => format!("Index of {}", name),
+        None => "Index".to_string(),
+    };
+    let body = render_index(&title, &entries);
+
+    let mut tx = pool.begin().await?;
+    let document_id = match existing {
+        Some((document_id,)) => {
+            sqlx::query(
+                "UPDATE documents SET title = ?, text_content = ?, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?",
+            )
+            .bind(&title)
+            .bind(&body)
+            .bind(document_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "UPDATE generated_documents SET generated_at = CURRENT_TIMESTAMP
+                 WHERE document_id = ?",
+            )
+            .bind(document_id)
+            .execute(&mut *tx)
+            .await?;
+            document_id
+        }
+        None => {
+            let document_id = sqlx::query(
+                "INSERT INTO documents (title, description, text_content, category_id)
+                 VALUES (?, '', ?, ?)",
+            )
+            .bind(&title)
+            .bind(&body)
+            .bind(category_id)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+            sqlx::query(
+                "INSERT INTO generated_documents (document_id, kind, category_id) VALUES (?, ?, ?)",
+            )
+            .bind(document_id)
+            .bind(INDEX_KIND)
+            .bind(category_id)
+            .execute(&mut *tx)
+            .await?;
+            document_id
+        }
+    };
+    tx.commit().await?;
+
+    // Records the links so backlinks and related documents see them
+    related::refresh_related_index(&pool).await?;
+
+    let _ = app.emit("document_updated", DocumentEvent { document_id });
+
+    Ok(IndexDocument {
+        document_id,
+        generated: true,
+        documents: entries.len(),
+    })
+}
+
+/// What generated a document, for showing it read-only; `None` for the
+/// documents users write.
+#[tauri::command]
+pub async fn get_generated_document(
+    app: AppHandle,
+    id: i64,
+) -> CmdResult<Option<GeneratedDocument>> {
+    let pool = db::pool(&app).await?;
+
+    let generated = sqlx::query_as(
+        "SELECT kind, category_id, generated_at FROM generated_documents WHERE document_id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await?;
+    Ok(generated)
+}
+
+/// Whether the app generated the document, so edits to it are refused.
+pub(crate) async fn is_generated<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    id: i64,
+) -> CmdResult<bool> {
+    let found: Option<(i64,)> =
+        sqlx::query_as("SELECT document_id FROM generated_documents WHERE document_id = ?")
+            .bind(id)
+            .fetch_optional(executor)
+            .await?;
+    Ok(found.is_some())
+}
+
+// Grouped by category path, then alphabetically, leaving generated
+// documents out so indexes don't list each other
+async fn index_entries(pool: &SqlitePool, category_id: Option<i64>) -> CmdResult<Vec<Entry>> {
+    refresh_title_sort(pool).await?;
+
+    let entries = sqlx::query_as(
+        "WITH RECURSIVE tree(id, path) AS (
+           SELECT id, name FROM categories
+           WHERE (?1 IS NULL AND parent_id IS NULL) OR id = ?1
+           UNION ALL
+           SELECT c.id, t.path || '/' || c.name FROM categories c JOIN tree t ON c.parent_id = t.id
+         )
+         SELECT d.id, d.title, d.updated_at, t.path AS category FROM documents d
+         LEFT JOIN tree t ON t.id = d.category_id
+         WHERE (t.id IS NOT NULL OR (?1 IS NULL AND d.category_id IS NULL))
+           AND d.id NOT IN (SELECT document_id FROM generated_documents)
+         ORDER BY t.path IS NULL, t.path ASC, d.title_sort ASC, d.id ASC",
+    )
+    .bind(category_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(entries)
+}
+
+fn render_index(title: &str, entries: &[Entry]) -> String {
+    let mut body = format!(
+        "<h1>{}</h1>\n<p>{} documents</p>\n",
+        html::escape(title),
+        entries.len()
+    );
+    let mut start = 0;
+    while start < entries.len() {
+        let category = entries[start].category.as_deref();
+        let end = entries[start..]
+            .iter()
+            .position(|entry| entry.category.as_deref() != category)
+            .map_or(entries.len(), |offset| start + offset);
+        body.push_str(&format!(
+            "<h2>{} ({})</h2>\n<ul>\n",
+            html::escape(category.unwrap_or("Uncategorized")),
+            end - start
+        ));
+        for entry in &entries[start..end] {
+            // Stored as "YYYY-MM-DD HH:MM:SS"; the day is enough here
+            let updated = entry.updated_at.get(..10).unwrap_or(&entry.updated_at);
+            body.push_str(&format!(
+                "<li><a href=\"{}\">{}</a> — updated {}</li>\n",
+                deep_link::document_link(entry.id),
+                html::escape(&entry.title),
+                html::escape(updated)
+            ));
+        }
+        body.push_str("</ul>\n");
+        start = end;
+    }
+    body
+}
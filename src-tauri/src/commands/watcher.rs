@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, State};
+
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::settings::{SettingsStore, WatchedFolder};
+use crate::watcher::FolderWatchers;
+
+/// Starts importing new files dropped into `path` as documents of
+/// `category_id`. The folder keeps being watched after a restart.
+#[tauri::command]
+pub async fn watch_folder(
+    app: AppHandle,
+    store: State<'_, SettingsStore>,
+    watchers: State<'_, FolderWatchers>,
+    path: String,
+    category_id: i64,
+) -> CmdResult<()> {
+    let folder = PathBuf::from(&path);
+    if !folder.is_dir() {
+        return Err(AppError::Validation(format!("Not a folder: {}", path)));
+    }
+
+    let pool = db::pool(&app).await?;
+    let exists: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(&pool)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("Category not found".to_string()));
+    }
+
+    let mut settings = store.get();
+    settings
+        .watched_folders
+        .retain(|watched| watched.path != path);
+    settings
+        .watched_folders
+        .push(WatchedFolder { path, category_id });
+    store.replace(settings)?;
+
+    watchers.start(&app, folder, category_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_watching(
+    store: State<'_, SettingsStore>,
+    watchers: State<'_, FolderWatchers>,
+    path: String,
+) -> CmdResult<bool> {
+    let mut settings = store.get();
+    settings
+        .watched_folders
+        .retain(|watched| watched.path != path);
+    store.replace(settings)?;
+
+    Ok(watchers.stop(&PathBuf::from(path)))
+}
+
+#[tauri::command]
+pub fn list_watched_folders(store: State<'_, SettingsStore>) -> Vec<WatchedFolder> {
+    store.get().watched_folders
+}
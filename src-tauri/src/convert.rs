@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageFormat};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::ColorType;
+
+use crate::pdf::{Image, PdfDocument, A4};
+
+const JPEG_QUALITY: u8 = 90;
+
+/// Target formats an attachment of this content type can be converted to.
+pub fn targets_for(mime: &str) -> &'static [&'static str] {
+    match mime {
+        "image/png" => &["jpeg", "pdf"],
+        "image/jpeg" => &["png", "pdf"],
+        "image/tiff" => &["png", "jpeg", "pdf"],
+        _ => &[],
+    }
+}
+
+/// File extension and content type of a target format.
+pub fn target_type(target: &str) -> Option<(&'static str, &'static str)> {
+    match target {
+        "png" => Some(("png", "image/png")),
+        "jpeg" => Some(("jpg", "image/jpeg")),
+        "pdf" => Some(("pdf", "application/pdf")),
+        _ => None,
+    }
+}
+
+/// Writes `source` converted to `target` at `dest`. Every page of a
+/// multi-page TIFF ends up in a PDF; the image formats only take the first.
+pub fn convert(source: &Path, mime: &str, target: &str, dest: &Path) -> Result<(), String> {
+    let pages = image_pages(source, mime)?;
+    let first = pages
+        .first()
+        .ok_or_else(|| "The file has no images".to_string())?;
+
+    match target {
+        "png" => first
+            .save_with_format(dest, ImageFormat::Png)
+            .map_err(|e| e.to_string()),
+        "jpeg" => {
+            // JPEG has no alpha channel
+            let file = File::create(dest).map_err(|e| e.to_string())?;
+            DynamicImage::ImageRgb8(first.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(file, JPEG_QUALITY))
+                .map_err(|e| e.to_string())
+        }
+        "pdf" => std::fs::write(dest, image_pdf(&pages).to_bytes()).map_err(|e| e.to_string()),
+        _ => Err(format!("Unknown target format: {}", target)),
+    }
+}
+
+/// The pages of an image file: every page of a TIFF, the one image of
+/// anything else.
+pub fn image_pages(source: &Path, mime: &str) -> Result<Vec<DynamicImage>, String> {
+    if mime == "image/tiff" {
+        tiff_pages(source)
+    } else {
+        Ok(vec![image::open(source).map_err(|e| e.to_string())?])
+    }
+}
+
+// One image per page, scaled to fit an A4 page turned to match the first
+// image
+fn image_pdf(pages: &[DynamicImage]) -> PdfDocument {
+    let (short, long) = A4;
+    let landscape = pages[0].width() > pages[0].height();
+    let (page_width, page_height) = if landscape {
+        (long, short)
+    } else {
+        (short, long)
+    };
+
+    let mut pdf = PdfDocument::new((page_width, page_height));
+    for image in pages {
+        let scale = (page_width / image.width() as f32).min(page_height / image.height() as f32);
+        let (width, height) = (image.width() as f32 * scale, image.height() as f32 * scale);
+
+        let page = pdf.add_page();
+        let id = pdf.add_image(Image::from_dynamic(image));
+        pdf.draw_image(
+            page,
+            id,
+            (page_width - width) / 2.0,
+            (page_height - height) / 2.0,
+            width,
+            height,
+        );
+    }
+    pdf
+}
+
+// The image crate only reads the first page of a TIFF
+fn tiff_pages(path: &Path) -> Result<Vec<DynamicImage>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    let mut pages = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+        let color = decoder.colortype().map_err(|e| e.to_string())?;
+        let data = decoder.read_image().map_err(|e| e.to_string())?;
+
+        let page = match (color, data) {
+            (ColorType::Gray(1), DecodingResult::U8(bits)) => bilevel(width, height, &bits),
+            (ColorType::Gray(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+            }
+            (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+            }
+            (ColorType::RGB(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+            }
+            (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+            }
+            (ColorType::Gray(16), DecodingResult::U16(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+            }
+            (ColorType::RGB(16), DecodingResult::U16(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+            }
+            (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+                ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+            }
+            (color, _) => {
+                return Err(format!(
+                    "Unsupported TIFF colour type {:?} on page {}",
+                    color,
+                    pages.len() + 1
+                ))
+            }
+        };
+        pages.push(page.ok_or_else(|| format!("Corrupt TIFF page {}", pages.len() + 1))?);
+
+        if !decoder.more_images() {
+            return Ok(pages);
+        }
+        decoder.next_image().map_err(|e| e.to_string())?;
+    }
+}
+
+// Rows of packed bits, most significant first and padded to whole bytes,
+// with set bits white
+fn bilevel(width: u32, height: u32, bits: &[u8]) -> Option<DynamicImage> {
+    let row_bytes = width.div_ceil(8) as usize;
+    if bits.len() < row_bytes * height as usize {
+        return None;
+    }
+    let image = GrayImage::from_fn(width, height, |x, y| {
+        let byte = bits[y as usize * row_bytes + x as usize / 8];
+        let set = byte & (0x80 >> (x % 8)) != 0;
+        image::Luma([if set { 255 } else { 0 }])
+    });
+    Some(DynamicImage::ImageLuma8(image))
+}
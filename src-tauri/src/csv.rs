@@ -0,0 +1,120 @@
+// Delimited text as RFC 4180 describes it: fields separated by a delimiter,
+// records by CRLF or LF, and fields that contain either (or quotes)
+// wrapped in double quotes with inner quotes doubled.
+
+pub struct Record {
+    /// Line the record starts on, counted from 1.
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The records of `text`, in order. A malformed record comes back as an
+/// error and parsing picks up again at the next line. Blank lines are
+/// skipped.
+pub fn parse(text: &str, delimiter: char) -> Vec<Result<Record, ParseError>> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut error = None;
+        // Whether the current field is quoted, and its quotes are closed
+        let mut quoted = false;
+        let mut closed = false;
+
+        loop {
+            let Some(c) = chars.next() else {
+                if quoted && !closed {
+                    error = Some("Quoted field is never closed".to_string());
+                }
+                break;
+            };
+            match c {
+                '"' if quoted && !closed => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        closed = true;
+                    }
+                }
+                '"' if field.is_empty() && !quoted => quoted = true,
+                '\r' | '\n' if !quoted || closed => {
+                    if c == '\r' && chars.peek() == Some(&'\n') {
+                        chars.next();
+                    }
+                    line += 1;
+                    break;
+                }
+                c if c == delimiter && (!quoted || closed) => {
+                    fields.push(std::mem::take(&mut field));
+                    quoted = false;
+                    closed = false;
+                }
+                '"' => {
+                    error = Some(format!(
+                        "Unexpected quote in field {}; quote the whole field and double inner quotes",
+                        fields.len() + 1
+                    ));
+                }
+                c if closed => {
+                    error = Some(format!(
+                        "Text after the closing quote of field {}",
+                        fields.len() + 1
+                    ));
+                    field.push(c);
+                }
+                c => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            if error.is_some() {
+                // Skip the rest of the broken line
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+                break;
+            }
+        }
+
+        if let Some(message) = error {
+            records.push(Err(ParseError {
+                line: start,
+                message,
+            }));
+            continue;
+        }
+        fields.push(field);
+        if fields.len() == 1 && fields[0].is_empty() && !quoted {
+            continue;
+        }
+        records.push(Ok(Record {
+            line: start,
+            fields,
+        }));
+    }
+    records
+}
+
+/// `value` as one field of a comma-separated record, quoted if need be.
+pub fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
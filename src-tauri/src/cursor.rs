@@ -0,0 +1,48 @@
+// Opaque paging cursors: the position as JSON, with an HMAC so a cursor
+// can't be edited into a position the listing never returned. The key
+// lives only as long as the process, so cursors don't survive a restart.
+
+use std::sync::OnceLock;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+
+fn mac() -> HmacSha256 {
+    let key = KEY.get_or_init(|| {
+        let mut key = [0; 32];
+        getrandom::getrandom(&mut key).expect("the system random source is available");
+        key
+    });
+    HmacSha256::new_from_slice(key).expect("HMAC takes any key size")
+}
+
+pub fn encode<T: Serialize>(position: &T) -> String {
+    let payload = serde_json::to_vec(position).expect("positions serialize to JSON");
+    let mut mac = mac();
+    mac.update(&payload);
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(&payload),
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    )
+}
+
+/// The position in `cursor`, or `None` when it wasn't made by `encode` in
+/// this process.
+pub fn decode<T: DeserializeOwned>(cursor: &str) -> Option<T> {
+    let (payload, tag) = cursor.split_once('.')?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+    let mut mac = mac();
+    mac.update(&payload);
+    mac.verify_slice(&tag).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
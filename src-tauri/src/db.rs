@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_sql::{DbInstances, DbPool};
+
+use crate::collation::{self, Collator};
+use crate::error::{AppError, CmdResult};
+use crate::settings::{Settings, SettingsStore};
+
+// Same connection string the frontend passes to `Database.load`
+pub const DB_URL: &str = "sqlite:ando-archive.db";
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Category {
+    pub id: i64,
+    pub name: String,
+    pub icon: String,
+    pub color: String,
+    pub parent_id: Option<i64>,
+    pub description: Option<String>,
+    pub level: i64,
+    pub sort_order: i64,
+    pub created_at: String,
+    /// When a document in the category was last created, edited or moved
+    /// in or out.
+    pub last_activity_at: Option<String>,
+    /// Set while the category is archived, which hides it, its
+    /// subcategories and their documents.
+    pub archived_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Document {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub text_content: Option<String>,
+    pub category_id: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Changes only when the title, body or tags do, ignoring whitespace.
+    /// `None` until computed, and for queries that don't select it.
+    #[sqlx(default)]
+    pub content_hash: Option<String>,
+    /// Set while the document is locked; lists and search results then
+    /// leave out its body and what is derived from it.
+    #[sqlx(default)]
+    pub locked: bool,
+}
+
+impl Document {
+    /// Drops the body of a locked document, and the hash that could
+    /// confirm a guess at it, before it goes into a list or search result.
+    pub fn without_locked_body(mut self) -> Self {
+        if self.locked {
+            self.text_content = None;
+            self.content_hash = None;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Attachment {
+    pub id: i64,
+    pub document_id: i64,
+    pub filename: String,
+    pub filepath: String,
+    pub filetype: String,
+    pub filesize: Option<i64>,
+    pub sort_order: i64,
+    pub created_at: String,
+}
+
+/// Returns the pool opened by `tauri-plugin-sql`, so commands share the
+/// connection (and migrations) the frontend already uses.
+pub async fn pool(app: &AppHandle) -> CmdResult<SqlitePool> {
+    let instances = app.state::<DbInstances>();
+    let instances = instances.0.read().await;
+
+    instances
+        .get(DB_URL)
+        .map(|db| match db {
+            DbPool::Sqlite(pool) => pool.clone(),
+        })
+        .ok_or_else(|| AppError::Internal("Database not initialized".to_string()))
+}
+
+/// Waits until the frontend has loaded the database, for work started at
+/// launch.
+pub async fn wait_for_pool(app: &AppHandle) -> SqlitePool {
+    loop {
+        if let Ok(pool) = pool(app).await {
+            return pool;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Replaces the pool `tauri-plugin-sql` holds with one whose connections
+/// use the settings' page cache and mmap sizes and collation locale. Code
+/// holding the old pool keeps working on it; new calls to `pool` get the
+/// new one.
+pub async fn reconnect(app: &AppHandle, settings: &Settings) -> CmdResult<SqlitePool> {
+    let path = database_path(app)?;
+    // The plugin opens it from a URL, with the defaults that come with it
+    let mut options = SqliteConnectOptions::from_str(&format!("sqlite:{}", path.display()))?;
+    if let Some(cache_mb) = settings.db_cache_mb {
+        // Negative sizes are in KiB rather than pages
+        options = options.pragma("cache_size", format!("-{}", u64::from(cache_mb) * 1024));
+    }
+    if let Some(mmap_mb) = settings.db_mmap_mb {
+        options = options.pragma("mmap_size", (u64::from(mmap_mb) << 20).to_string());
+    }
+    if let Some(locale) = &settings.collation_locale {
+        let collator = Collator::new(locale);
+        options = options.collation(collation::NAME, move |a, b| collator.compare(a, b));
+    }
+    let pool = SqlitePool::connect_with(options).await?;
+
+    let instances = app.state::<DbInstances>();
+    instances
+        .0
+        .write()
+        .await
+        .insert(DB_URL.to_string(), DbPool::Sqlite(pool.clone()));
+    Ok(pool)
+}
+
+/// Reconnects once the frontend has loaded the database when the settings
+/// tune it or set a collation locale, since the plugin opens it with
+/// SQLite's defaults.
+pub fn tune_on_launch(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let settings = app.state::<SettingsStore>().get();
+        if settings.db_cache_mb.is_none()
+            && settings.db_mmap_mb.is_none()
+            && settings.collation_locale.is_none()
+        {
+            return;
+        }
+        wait_for_pool(&app).await;
+        if let Err(e) = reconnect(&app, &settings).await {
+            log::warn!("Failed to apply database settings: {}", e);
+        }
+    });
+}
+
+/// The database file; `tauri-plugin-sql` resolves its URL against the app
+/// config dir.
+pub fn database_path(app: &AppHandle) -> CmdResult<PathBuf> {
+    let config_dir = app.path().app_config_dir()?;
+    Ok(config_dir.join(DB_URL.trim_start_matches("sqlite:")))
+}
+
+/// Directory holding the attachment files of a document.
+pub fn attachments_dir(app: &AppHandle, document_id: i64) -> CmdResult<PathBuf> {
+    Ok(attachments_root(app)?.join(document_id.to_string()))
+}
+
+/// The folder holding every document's attachment folder.
+pub fn attachments_root(app: &AppHandle) -> CmdResult<PathBuf> {
+    let app_dir = app.path().app_data_dir()?;
+    Ok(app_dir.join("ando-archive").join("attachments"))
+}
+
+/// An in-memory database with every migration applied, for tests.
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    // Each connection to `:memory:` is its own database, so keep just one
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    for migration in crate::migrations::migrations() {
+        sqlx::raw_sql(migration.sql).execute(&pool).await.unwrap();
+    }
+    pool
+}
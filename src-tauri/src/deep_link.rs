@@ -0,0 +1,325 @@
+use std::process::Command;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::documents::DocumentEvent;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+use crate::local_api;
+
+pub const SCHEME: &str = "andoarchive";
+
+/// Stable link to a document, e.g. `andoarchive://document/42`.
+pub fn document_link(id: i64) -> String {
+    format!("{}://document/{}", SCHEME, id)
+}
+
+/// Document id of a link made by `document_link`.
+pub fn parse(link: &str) -> Option<i64> {
+    let rest = link.strip_prefix(SCHEME)?.strip_prefix("://")?;
+    let id = rest.strip_prefix("document/")?.trim_end_matches('/');
+    id.parse().ok()
+}
+
+/// Something a link, QR code, reminder or the search box can point at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// `andoarchive://document/42`, or just `42` or `#42`.
+    Document(i64),
+    /// `andoarchive://attachment/7`.
+    Attachment(i64),
+    /// `andoarchive://version/12`, by the id `search_versions` returns.
+    Version(i64),
+    /// `andoarchive://search?q=...`, or any other text.
+    Search(String),
+}
+
+/// What `target` points at; `None` for `andoarchive://` links of a kind
+/// this version doesn't know, and for blank text.
+pub fn parse_target(target: &str) -> Option<Target> {
+    let target = target.trim();
+    let Some(rest) = target
+        .strip_prefix(SCHEME)
+        .and_then(|rest| rest.strip_prefix("://"))
+    else {
+        if target.is_empty() {
+            return None;
+        }
+        let id = target.strip_prefix('#').unwrap_or(target);
+        return Some(match id.parse() {
+            Ok(id) => Target::Document(id),
+            Err(_) => Target::Search(target.to_string()),
+        });
+    };
+
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let path = path.trim_end_matches('/');
+    if path == "search" {
+        let text = query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == "q").then(|| local_api::percent_decode(value))?
+        })?;
+        return (!text.trim().is_empty()).then(|| Target::Search(text.trim().to_string()));
+    }
+    let (kind, id) = path.split_once('/')?;
+    let id = id.parse().ok()?;
+    match kind {
+        "document" => Some(Target::Document(id)),
+        "attachment" => Some(Target::Attachment(id)),
+        "version" => Some(Target::Version(id)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    Document,
+    Attachment,
+    Version,
+    Search,
+}
+
+/// Where the frontend should go, with the ids it needs to get there.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedTarget {
+    pub kind: TargetKind,
+    /// Of the document, attachment or version; `None` for a search.
+    pub id: Option<i64>,
+    pub extra: TargetExtra,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TargetExtra {
+    /// The document an attachment or version belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// 1 for a document's first version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+}
+
+/// Parses `target` and checks that what it points at still exists.
+pub async fn resolve(pool: &SqlitePool, target: &str) -> CmdResult<ResolvedTarget> {
+    let parsed = parse_target(target)
+        .ok_or_else(|| AppError::Validation(format!("Unknown link: {}", target.trim())))?;
+
+    let resolved = match parsed {
+        Target::Document(id) => {
+            let (title,): (String,) = sqlx::query_as("SELECT title FROM documents WHERE id = ?")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("Document {} no longer exists", id)))?;
+            ResolvedTarget {
+                kind: TargetKind::Document,
+                id: Some(id),
+                extra: TargetExtra {
+                    document_id: Some(id),
+                    title: Some(title),
+                    ..Default::default()
+                },
+            }
+        }
+        Target::Attachment(id) => {
+            let (document_id, filename): (i64, String) =
+                sqlx::query_as("SELECT document_id, filename FROM attachments WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(pool)
+                    .await?
+                    .ok_or_else(|| {
+                        AppError::NotFound(format!("Attachment {} no longer exists", id))
+                    })?;
+            ResolvedTarget {
+                kind: TargetKind::Attachment,
+                id: Some(id),
+                extra: TargetExtra {
+                    document_id: Some(document_id),
+                    title: Some(filename),
+                    ..Default::default()
+                },
+            }
+        }
+        Target::Version(id) => {
+            let (document_id, title, version): (i64, String, i64) = sqlx::query_as(
+                "SELECT v.document_id, d.title,
+                        (SELECT COUNT(*) FROM document_versions p
+                         WHERE p.document_id = v.document_id AND p.id <= v.id)
+                 FROM document_versions v JOIN documents d ON d.id = v.document_id
+                 WHERE v.id = ?",
+            )
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Version {} no longer exists", id)))?;
+            ResolvedTarget {
+                kind: TargetKind::Version,
+                id: Some(id),
+                extra: TargetExtra {
+                    document_id: Some(document_id),
+                    title: Some(title),
+                    version: Some(version),
+                    ..Default::default()
+                },
+            }
+        }
+        Target::Search(query) => ResolvedTarget {
+            kind: TargetKind::Search,
+            id: None,
+            extra: TargetExtra {
+                query: Some(query),
+                ..Default::default()
+            },
+        },
+    };
+    Ok(resolved)
+}
+
+/// Registers the app as the handler of `andoarchive://` links for the
+/// current user, pointing at this executable. macOS takes the scheme from
+/// `Info.plist` instead. Skipped in debug builds so a dev binary never
+/// takes over the links.
+pub fn register() {
+    if cfg!(debug_assertions) {
+        return;
+    }
+    if let Err(e) = register_handler() {
+        log::warn!("Failed to register {}:// links: {}", SCHEME, e);
+    }
+}
+
+fn register_handler() -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+
+    if cfg!(target_os = "linux") {
+        let Some(home) = std::env::var_os("HOME") else {
+            return Ok(());
+        };
+        let applications = std::path::Path::new(&home).join(".local/share/applications");
+        std::fs::create_dir_all(&applications).map_err(|e| e.to_string())?;
+        let desktop_file = format!("{}-handler.desktop", SCHEME);
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName=Ando Archive\nExec=\"{}\" %u\nNoDisplay=true\nMimeType=x-scheme-handler/{};\n",
+            exe.display(),
+            SCHEME
+        );
+        std::fs::write(applications.join(&desktop_file), entry).map_err(|e| e.to_string())?;
+        let mime_type = format!("x-scheme-handler/{}", SCHEME);
+        run("xdg-mime", &["default", &desktop_file, &mime_type])
+    } else if cfg!(target_os = "windows") {
+        let key = format!("HKCU\\Software\\Classes\\{}", SCHEME);
+        let command = format!("\"{}\" \"%1\"", exe.display());
+        run("reg", &["add", &key, "/ve", "/d", "URL:Ando Archive", "/f"])?;
+        run("reg", &["add", &key, "/v", "URL Protocol", "/d", "", "/f"])?;
+        run(
+            "reg",
+            &[
+                "add",
+                &format!("{}\\shell\\open\\command", key),
+                "/ve",
+                "/d",
+                &command,
+                "/f",
+            ],
+        )
+    } else {
+        Ok(())
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status))
+    }
+}
+
+/// Opens a link passed on the command line, which is how Linux and
+/// Windows hand links to the app.
+pub fn open_from_args(app: &AppHandle) {
+    if let Some(link) = link_in_args(std::env::args().skip(1)) {
+        open(app, &link);
+    }
+}
+
+/// Handles a second launch, which the single-instance plugin stops and
+/// passes here with its arguments: its link opens in this instance, and
+/// without one the window is just brought up.
+pub fn open_from_second_instance(app: &AppHandle, args: Vec<String>) {
+    match link_in_args(args.into_iter().skip(1)) {
+        Some(link) => open(app, &link),
+        None => {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
+        }
+    }
+}
+
+fn link_in_args(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let prefix = format!("{}://", SCHEME);
+    args.into_iter().find(|arg| arg.starts_with(&prefix))
+}
+
+/// Brings the window up and, once the database is loaded, tells the
+/// frontend where to go: `open_document` for documents, `open_target` with
+/// the resolved target for anything else. Unknown links and links to
+/// deleted items are logged and dropped.
+pub fn open(app: &AppHandle, link: &str) {
+    let app = app.clone();
+    let link = link.to_string();
+    tauri::async_runtime::spawn(async move {
+        let pool = db::wait_for_pool(&app).await;
+        let target = match resolve(&pool, &link).await {
+            Ok(target) => target,
+            Err(e) => {
+                log::warn!("Ignoring link {}: {}", link, e);
+                return;
+            }
+        };
+
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.set_focus();
+        }
+        match (target.kind, target.id) {
+            (TargetKind::Document, Some(document_id)) => {
+                let _ = app.emit("open_document", DocumentEvent { document_id });
+            }
+            _ => {
+                let _ = app.emit("open_target", target);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn links_are_found_among_other_arguments() {
+        assert_eq!(
+            link_in_args(args(&["--flag", "andoarchive://document/42"])).as_deref(),
+            Some("andoarchive://document/42")
+        );
+        assert_eq!(link_in_args(args(&["--flag", "notes.txt"])), None);
+        assert_eq!(link_in_args(args(&["https://example.com"])), None);
+    }
+}
@@ -0,0 +1,277 @@
+use std::path::Path;
+use std::process::Command;
+
+use image::{ImageDecoder, ImageFormat, ImageReader};
+
+use crate::editor;
+use crate::settings::Settings;
+
+// Short descriptions of images for alt text and search. Where they come
+// from is up to a `Describer`: by default what the file says about itself
+// and any text recognized in it, or a command such as a local captioning
+// model when `image_describer` is set.
+
+// Long enough for a sentence or two; alt text is read aloud in full
+const MAX_DESCRIPTION_CHARS: usize = 300;
+// Of the recognized text, what goes into a metadata description
+const MAX_TEXT_CHARS: usize = 120;
+
+/// What is already known about an image before it is described.
+pub struct ImageFacts<'a> {
+    pub filename: &'a str,
+    pub ocr_text: Option<&'a str>,
+}
+
+pub trait Describer: Send + Sync {
+    /// Stored alongside each description, so it is clear what wrote it.
+    fn source(&self) -> &'static str;
+    fn describe(&self, path: &Path, facts: &ImageFacts) -> Result<String, String>;
+}
+
+/// The describer the settings choose.
+pub fn describer(settings: &Settings) -> Box<dyn Describer> {
+    match &settings.image_describer {
+        Some(command) => Box::new(CommandDescriber {
+            command: editor::split_command(command),
+        }),
+        None => Box::new(MetadataDescriber),
+    }
+}
+
+/// Format, size, EXIF date and camera, and the start of the OCR text.
+pub struct MetadataDescriber;
+
+impl Describer for MetadataDescriber {
+    fn source(&self) -> &'static str {
+        "metadata"
+    }
+
+    fn describe(&self, path: &Path, facts: &ImageFacts) -> Result<String, String> {
+        let mut description = String::new();
+        // Formats the decoder lacks still get their text described
+        if let Ok(image) = read_image(path) {
+            description.push_str(&image);
+        }
+        let text = facts
+            .ocr_text
+            .map(str::trim)
+            .filter(|text| !text.is_empty());
+        if let Some(text) = text {
+            if !description.is_empty() {
+                description.push_str(". ");
+            }
+            description.push_str(&format!("Text: \"{}\"", excerpt(text, MAX_TEXT_CHARS)));
+        }
+        if description.is_empty() {
+            return Err(format!("Nothing is known about {}", facts.filename));
+        }
+        Ok(description)
+    }
+}
+
+/// Runs a command with the image appended and takes what it prints.
+pub struct CommandDescriber {
+    command: Vec<String>,
+}
+
+impl Describer for CommandDescriber {
+    fn source(&self) -> &'static str {
+        "command"
+    }
+
+    fn describe(&self, path: &Path, _facts: &ImageFacts) -> Result<String, String> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| "No image describer configured".to_string())?;
+        let output = Command::new(program)
+            .args(args)
+            .arg(path)
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => format!("Image describer not found: {}", program),
+                _ => format!("Failed to start {}: {}", program, e),
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{} failed: {}", program, stderr.trim()));
+        }
+        let description = String::from_utf8_lossy(&output.stdout);
+        let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+        if description.is_empty() {
+            return Err(format!("{} printed no description", program));
+        }
+        Ok(excerpt(&description, MAX_DESCRIPTION_CHARS))
+    }
+}
+
+// e.g. "JPEG image, 4032×3024, taken 2023-07-14 with Apple iPhone 12"
+fn read_image(path: &Path) -> Result<String, String> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?;
+    let format = match reader.format() {
+        Some(ImageFormat::Jpeg) => "JPEG",
+        Some(ImageFormat::Png) => "PNG",
+        Some(ImageFormat::Tiff) => "TIFF",
+        _ => return Err("Unsupported image format".to_string()),
+    };
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions();
+    let mut description = format!("{} image, {}×{}", format, width, height);
+
+    let exif = decoder
+        .exif_metadata()
+        .ok()
+        .flatten()
+        .map(|chunk| Exif::parse(&chunk))
+        .unwrap_or_default();
+    if let Some(date) = exif.taken {
+        description.push_str(&format!(", taken {}", date));
+    }
+    let camera = match (exif.make, exif.model) {
+        // Models often repeat the make, e.g. "Canon" and "Canon EOS R6"
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    if let Some(camera) = camera {
+        description.push_str(&format!(" with {}", camera));
+    }
+    Ok(description)
+}
+
+fn excerpt(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text,
+    }
+}
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TYPE_ASCII: u16 = 2;
+
+#[derive(Default)]
+struct Exif {
+    make: Option<String>,
+    model: Option<String>,
+    /// The day the picture was taken, as YYYY-MM-DD.
+    taken: Option<String>,
+}
+
+impl Exif {
+    // A TIFF-structured chunk: byte order, then IFD0, which may point to
+    // the Exif IFD holding the original date. Anything malformed is left
+    // out rather than failing the description.
+    fn parse(chunk: &[u8]) -> Self {
+        let little = match chunk.get(..4) {
+            Some([0x49, 0x49, 42, 0]) => true,
+            Some([0x4D, 0x4D, 0, 42]) => false,
+            _ => return Self::default(),
+        };
+        let tiff = Tiff { chunk, little };
+
+        let mut exif = Self::default();
+        let mut date_time = None;
+        let mut exif_ifd = None;
+        for entry in tiff
+            .read_u32(4)
+            .map(|offset| tiff.entries(offset))
+            .unwrap_or_default()
+        {
+            match entry.tag {
+                TAG_MAKE => exif.make = tiff.ascii(&entry),
+                TAG_MODEL => exif.model = tiff.ascii(&entry),
+                TAG_DATE_TIME => date_time = tiff.ascii(&entry),
+                TAG_EXIF_IFD => exif_ifd = Some(entry.value),
+                _ => {}
+            }
+        }
+        let original = exif_ifd.and_then(|offset| {
+            tiff.entries(offset)
+                .iter()
+                .find(|entry| entry.tag == TAG_DATE_TIME_ORIGINAL)
+                .and_then(|entry| tiff.ascii(entry))
+        });
+        // Stored as "YYYY:MM:DD HH:MM:SS"
+        exif.taken = original
+            .or(date_time)
+            .and_then(|date| date.get(..10).map(|day| day.replace(':', "-")))
+            .filter(|day| day.chars().all(|c| c.is_ascii_digit() || c == '-'));
+        exif
+    }
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// The value itself when it fits in four bytes, else its offset.
+    value: u32,
+    /// Where `value` sits in the chunk.
+    position: usize,
+}
+
+struct Tiff<'a> {
+    chunk: &'a [u8],
+    little: bool,
+}
+
+impl Tiff<'_> {
+    fn read_u16(&self, at: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.chunk.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.chunk.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn entries(&self, offset: u32) -> Vec<Entry> {
+        let offset = offset as usize;
+        let count = self.read_u16(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|index| {
+                let at = offset + 2 + index * 12;
+                Some(Entry {
+                    tag: self.read_u16(at)?,
+                    kind: self.read_u16(at + 2)?,
+                    count: self.read_u32(at + 4)?,
+                    value: self.read_u32(at + 8)?,
+                    position: at + 8,
+                })
+            })
+            .collect()
+    }
+
+    fn ascii(&self, entry: &Entry) -> Option<String> {
+        if entry.kind != TYPE_ASCII {
+            return None;
+        }
+        let start = if entry.count <= 4 {
+            entry.position
+        } else {
+            entry.value as usize
+        };
+        let bytes = self
+            .chunk
+            .get(start..start.checked_add(entry.count as usize)?)?;
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
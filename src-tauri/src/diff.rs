@@ -0,0 +1,369 @@
+// Line diffs in unified format, as `diff -u` and git print them. Myers'
+// algorithm finds a shortest edit script in O((N + M) D) time. The linear
+// space variant is used: it searches from both ends for the middle of the
+// script and recurses on either side, so memory stays O(N + M) however far
+// apart two versions are. Past MAX_COST edits in one search the remaining
+// lines are replaced as a block rather than diffed further.
+
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// The hunks turning `old` into `new`, each starting with its `@@` line;
+/// empty when they are the same.
+pub fn unified(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edit_script(&old, &new);
+
+    // Line numbers in `old` and `new` before each edit
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut a, mut b) = (0, 0);
+    for edit in &edits {
+        positions.push((a, b));
+        match edit {
+            Edit::Keep => {
+                a += 1;
+                b += 1;
+            }
+            Edit::Delete => a += 1,
+            Edit::Insert => b += 1,
+        }
+    }
+
+    let mut out = String::new();
+    let mut index = 0;
+    while index < edits.len() {
+        if edits[index] == Edit::Keep {
+            index += 1;
+            continue;
+        }
+        // A hunk runs until more than twice the context of unchanged lines
+        let start = index.saturating_sub(CONTEXT);
+        let mut end = index;
+        let mut kept = 0;
+        while end < edits.len() && kept <= 2 * CONTEXT {
+            if edits[end] == Edit::Keep {
+                kept += 1;
+            } else {
+                kept = 0;
+            }
+            end += 1;
+        }
+        let end = end - kept.saturating_sub(CONTEXT);
+
+        let (old_start, new_start) = positions[start];
+        let old_len = edits[start..end]
+            .iter()
+            .filter(|edit| **edit != Edit::Insert)
+            .count();
+        let new_len = edits[start..end]
+            .iter()
+            .filter(|edit| **edit != Edit::Delete)
+            .count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_len),
+            range(new_start, new_len)
+        ));
+        for (edit, &(a, b)) in edits[start..end].iter().zip(&positions[start..end]) {
+            let (prefix, line) = match edit {
+                Edit::Keep => (' ', old[a]),
+                Edit::Delete => ('-', old[a]),
+                Edit::Insert => ('+', new[b]),
+            };
+            out.push(prefix);
+            out.push_str(line);
+            out.push('\n');
+        }
+        index = end;
+    }
+    out
+}
+
+/// Lines added and removed between `old` and `new`.
+pub fn stat(old: &str, new: &str) -> (usize, usize) {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edit_script(&old, &new);
+    let added = edits.iter().filter(|edit| **edit == Edit::Insert).count();
+    let removed = edits.iter().filter(|edit| **edit == Edit::Delete).count();
+    (added, removed)
+}
+
+// `start,len` with lines counted from 1; an empty range names the line
+// before it, as diff does
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len() + new.len());
+    let size = old.len() + new.len() + 2;
+    let mut forward = Frontier::new(size);
+    let mut backward = Frontier::new(size);
+    conquer(old, new, &mut forward, &mut backward, &mut edits);
+    edits
+}
+
+// Edits in one middle-snake search before giving up on a minimal script
+const MAX_COST: usize = 4096;
+
+// Furthest x reached on each diagonal k = x - y
+struct Frontier {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Frontier {
+    fn new(size: usize) -> Self {
+        Frontier {
+            offset: size as isize,
+            x: vec![0; 2 * size + 1],
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.x[(k + self.offset) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.x[(k + self.offset) as usize] = x;
+    }
+}
+
+fn conquer(
+    old: &[&str],
+    new: &[&str],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+    edits: &mut Vec<Edit>,
+) {
+    // Common ends are kept as they are, which leaves less to search
+    let prefix = common_prefix(old, new);
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = common_suffix(old, new);
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    edits.extend(std::iter::repeat(Edit::Keep).take(prefix));
+
+    if old.is_empty() || new.is_empty() {
+        edits.extend(std::iter::repeat(Edit::Delete).take(old.len()));
+        edits.extend(std::iter::repeat(Edit::Insert).take(new.len()));
+    } else if let Some((x, y)) = middle_snake(old, new, forward, backward) {
+        conquer(&old[..x], &new[..y], forward, backward, edits);
+        conquer(&old[x..], &new[y..], forward, backward, edits);
+    } else {
+        edits.extend(std::iter::repeat(Edit::Delete).take(old.len()));
+        edits.extend(std::iter::repeat(Edit::Insert).take(new.len()));
+    }
+
+    edits.extend(std::iter::repeat(Edit::Keep).take(suffix));
+}
+
+// A point on a shortest edit script about halfway along it, found by
+// searching forward from the start and backward from the end until the
+// two frontiers meet; `None` past MAX_COST
+fn middle_snake(
+    old: &[&str],
+    new: &[&str],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+) -> Option<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    // Backward diagonal k is forward diagonal delta - k
+    let delta = n as isize - m as isize;
+    let odd = delta % 2 != 0;
+    forward.set(1, 0);
+    backward.set(1, 0);
+
+    let max = ((n + m).div_ceil(2) + 1).min(MAX_COST) as isize;
+    for d in 0..max {
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1)) {
+                forward.get(k + 1)
+            } else {
+                forward.get(k - 1) + 1
+            };
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+            if x < n && y < m {
+                x += common_prefix(&old[x..], &new[y..]);
+            }
+            forward.set(k, x);
+            if odd && (k - delta).abs() < d && x + backward.get(delta - k) >= n {
+                return Some(start);
+            }
+            k += 2;
+        }
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                backward.get(k + 1)
+            } else {
+                backward.get(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let run = common_suffix(&old[..n - x], &new[..m - y]);
+                x += run;
+                y += run;
+            }
+            backward.set(k, x);
+            if !odd && (delta - k).abs() <= d && x + forward.get(delta - k) >= n {
+                return Some((n - x, m - y));
+            }
+            k += 2;
+        }
+    }
+    None
+}
+
+fn common_prefix(old: &[&str], new: &[&str]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix(old: &[&str], new: &[&str]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Applies `edits` to `old`, checking that kept lines match
+    fn apply<'a>(edits: &[Edit], old: &[&'a str], new: &[&'a str]) -> Vec<&'a str> {
+        let (mut a, mut b) = (0, 0);
+        let mut out = Vec::new();
+        for edit in edits {
+            match edit {
+                Edit::Keep => {
+                    assert_eq!(old[a], new[b]);
+                    out.push(old[a]);
+                    a += 1;
+                    b += 1;
+                }
+                Edit::Delete => a += 1,
+                Edit::Insert => {
+                    out.push(new[b]);
+                    b += 1;
+                }
+            }
+        }
+        assert_eq!(a, old.len());
+        out
+    }
+
+    // Length of the longest common subsequence, the slow way
+    fn lcs(old: &[&str], new: &[&str]) -> usize {
+        let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for (i, a) in old.iter().enumerate() {
+            for (j, b) in new.iter().enumerate() {
+                table[i + 1][j + 1] = if a == b {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[old.len()][new.len()]
+    }
+
+    #[test]
+    fn insert() {
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nb\nnew\nc\n"),
+            "@@ -1,3 +1,4 @@\n a\n b\n+new\n c\n"
+        );
+        assert_eq!(stat("a\nb\nc\n", "a\nb\nnew\nc\n"), (1, 0));
+    }
+
+    #[test]
+    fn delete() {
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nc\n"),
+            "@@ -1,3 +1,2 @@\n a\n-b\n c\n"
+        );
+        assert_eq!(stat("a\nb\nc\n", "a\nc\n"), (0, 1));
+    }
+
+    #[test]
+    fn replace() {
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nB\nc\n"),
+            "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+        assert_eq!(stat("a\nb\nc\n", "a\nB\nc\n"), (1, 1));
+    }
+
+    #[test]
+    fn empty_inputs() {
+        assert_eq!(unified("", ""), "");
+        assert_eq!(unified("same\n", "same\n"), "");
+        assert_eq!(unified("", "a\nb\n"), "@@ -0,0 +1,2 @@\n+a\n+b\n");
+        assert_eq!(unified("a\n", ""), "@@ -1 +0,0 @@\n-a\n");
+        assert_eq!(stat("", ""), (0, 0));
+    }
+
+    #[test]
+    fn far_apart_changes_get_separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new = old.replace("2\n", "two\n").replace("19\n", "nineteen\n");
+        let hunks = unified(&old, &new);
+        assert_eq!(hunks.matches("@@ -").count(), 2);
+        assert!(hunks.starts_with("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n"));
+    }
+
+    #[test]
+    fn scripts_are_shortest() {
+        // Small alphabets make many equally long scripts to choose from
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        let alphabet = ["a", "b", "c", "d"];
+        for _ in 0..500 {
+            let letters = 2 + next() % 3;
+            let old: Vec<&str> = (0..next() % 40)
+                .map(|_| alphabet[next() % letters])
+                .collect();
+            let new: Vec<&str> = (0..next() % 40)
+                .map(|_| alphabet[next() % letters])
+                .collect();
+            let edits = edit_script(&old, &new);
+            assert_eq!(apply(&edits, &old, &new), new);
+            let kept = edits.iter().filter(|edit| **edit == Edit::Keep).count();
+            assert_eq!(kept, lcs(&old, &new), "{:?} -> {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn past_the_cost_limit_lines_are_replaced_as_a_block() {
+        let old: Vec<String> = (0..MAX_COST * 2).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..MAX_COST * 2).map(|i| format!("new {}", i)).collect();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+        let edits = edit_script(&old, &new);
+        assert_eq!(apply(&edits, &old, &new), new);
+        assert_eq!(
+            edits.iter().filter(|edit| **edit == Edit::Delete).count(),
+            old.len()
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::documents::{self, BodySize, DocumentEvent};
+use crate::error::AppError;
+use crate::settings::{Settings, SettingsStore};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// An editor exiting this quickly without saving handed the file to an
+// instance that was already running (`code`, `subl` and the like)
+const HANDOFF_EXIT: Duration = Duration::from_secs(3);
+// How long a handed-off file keeps being watched after its last save
+const HANDOFF_IDLE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Serialize)]
+struct EditConflict {
+    document_id: i64,
+    /// The edited copy, left in place so the edits aren't lost.
+    path: String,
+}
+
+#[derive(Clone, Serialize)]
+struct EditRejected {
+    document_id: i64,
+    path: String,
+    error: AppError,
+}
+
+/// Documents open in an external editor, keyed by id, with their copy.
+#[derive(Default)]
+pub struct ExternalEdits {
+    open: Arc<Mutex<HashMap<i64, PathBuf>>>,
+}
+
+impl ExternalEdits {
+    /// Writes `body` to a temporary file, opens it with `command` and
+    /// copies every save back into the document until the editor closes.
+    /// A document already open gets the editor launched on its existing
+    /// copy.
+    pub fn open(
+        &self,
+        app: &AppHandle,
+        pool: SqlitePool,
+        document_id: i64,
+        body: String,
+        command: &[String],
+    ) -> Result<PathBuf, String> {
+        if let Some(path) = self.open.lock().unwrap().get(&document_id) {
+            launch(command, path)?;
+            return Ok(path.clone());
+        }
+
+        let dir = std::env::temp_dir().join("ando-archive");
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("document-{}.html", document_id));
+        fs::write(&path, &body).map_err(|e| e.to_string())?;
+
+        let child = match launch(command, &path) {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+        self.open.lock().unwrap().insert(document_id, path.clone());
+
+        let app = app.clone();
+        let open = Arc::clone(&self.open);
+        let copy = path.clone();
+        tauri::async_runtime::spawn(async move {
+            let keep_copy = watch(&app, &pool, document_id, &copy, body, child).await;
+            open.lock().unwrap().remove(&document_id);
+            if !keep_copy {
+                let _ = fs::remove_file(&copy);
+            }
+        });
+
+        Ok(path)
+    }
+}
+
+/// Splits an editor command into program and arguments on whitespace,
+/// keeping double-quoted parts together, e.g.
+/// `"C:\Program Files\Editor\editor.exe" --wait`.
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for c in command.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+fn launch(command: &[String], path: &Path) -> Result<Child, String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| "No external editor configured".to_string())?;
+    Command::new(program)
+        .args(args)
+        .arg(path)
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("Editor not found: {}", program),
+            _ => format!("Failed to start {}: {}", program, e),
+        })
+}
+
+// Polls the copy until the editor is done with it, returning whether the
+// copy has to be kept because of a conflict
+async fn watch(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    document_id: i64,
+    path: &Path,
+    mut synced: String,
+    mut child: Child,
+) -> bool {
+    let started = Instant::now();
+    let mut modified = modified_at(path);
+    let mut last_save = None;
+    let mut handed_off = false;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        // Checked before reading the copy, so a save right before closing
+        // is still picked up
+        let exited = !handed_off && !matches!(child.try_wait(), Ok(None));
+
+        let current = modified_at(path);
+        if current != modified {
+            modified = current;
+            last_save = Some(Instant::now());
+            let settings = app.state::<SettingsStore>().get();
+            match sync(pool, &settings, document_id, path, &synced).await {
+                Ok(Some((body, size))) => {
+                    synced = body;
+                    let _ = app.emit("document_updated", DocumentEvent { document_id });
+                    documents::warn_if_body_too_large(app, document_id, size);
+                }
+                Ok(None) => {}
+                Err(Sync::Conflict) => {
+                    let _ = app.emit(
+                        "external_edit_conflict",
+                        EditConflict {
+                            document_id,
+                            path: path.to_string_lossy().to_string(),
+                        },
+                    );
+                    return true;
+                }
+                // Like a conflict, the copy is kept for the user to shorten
+                Err(Sync::TooLarge(error)) => {
+                    let _ = app.emit(
+                        "external_edit_rejected",
+                        EditRejected {
+                            document_id,
+                            path: path.to_string_lossy().to_string(),
+                            error,
+                        },
+                    );
+                    return true;
+                }
+                Err(Sync::Failed(e)) => {
+                    log::warn!("Failed to import edits of document {}: {}", document_id, e)
+                }
+            }
+        }
+
+        if exited {
+            if last_save.is_some() || started.elapsed() >= HANDOFF_EXIT {
+                return false;
+            }
+            handed_off = true;
+        }
+        if handed_off && last_save.unwrap_or(started).elapsed() >= HANDOFF_IDLE {
+            return false;
+        }
+    }
+}
+
+enum Sync {
+    /// The document changed in the app since the last sync.
+    Conflict,
+    /// The body is over a strict `max_body_chars`.
+    TooLarge(AppError),
+    Failed(String),
+}
+
+// Copies the file into the document unless it was changed in the app in the
+// meantime; the update creates a version like any other edit
+async fn sync(
+    pool: &SqlitePool,
+    settings: &Settings,
+    document_id: i64,
+    path: &Path,
+    synced: &str,
+) -> Result<Option<(String, BodySize)>, Sync> {
+    let body = fs::read_to_string(path).map_err(|e| Sync::Failed(e.to_string()))?;
+    if body == synced {
+        return Ok(None);
+    }
+    let size = documents::check_body_size(settings, &body).map_err(Sync::TooLarge)?;
+
+    let updated = sqlx::query(
+        "UPDATE documents SET text_content = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND COALESCE(text_content, '') = ?",
+    )
+    .bind(&body)
+    .bind(document_id)
+    .bind(synced)
+    .execute(pool)
+    .await
+    .map_err(|e| Sync::Failed(e.to_string()))?
+    .rows_affected();
+    if updated == 0 {
+        return Err(Sync::Conflict);
+    }
+
+    Ok(Some((body, size)))
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+// Mojibake from UTF-8 text decoded as Windows-1252 or Latin-1, the usual
+// way imports go wrong ("cafÃ©" for "café"). Every byte of a UTF-8
+// multi-byte sequence is non-ASCII, so each misread character turns into
+// a run of non-ASCII characters; mapping such a run back to its bytes and
+// decoding them as UTF-8 only succeeds when it really was mojibake. Text
+// in other encodings can't be told apart without the original bytes and is
+// left alone.
+
+// Windows-1252 characters for bytes 0x80 to 0x9F; the five unassigned ones
+// decode to the C1 control of the same value
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+// Text encoded more than once needs a pass per round
+const MAX_PASSES: usize = 3;
+
+/// The encoding UTF-8 text was misread as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misread {
+    Latin1,
+    Windows1252,
+}
+
+impl Misread {
+    pub fn label(self) -> &'static str {
+        match self {
+            Misread::Latin1 => "iso-8859-1",
+            Misread::Windows1252 => "windows-1252",
+        }
+    }
+}
+
+pub struct Repair {
+    pub text: String,
+    pub misread: Misread,
+    /// Runs of characters that were re-decoded.
+    pub segments: usize,
+    /// `(before, after)` of the first few runs, with some text around them.
+    pub previews: Vec<(String, String)>,
+}
+
+/// `text` with its mojibake re-decoded, or `None` when there is none.
+pub fn repair(text: &str, max_previews: usize, context: usize) -> Option<Repair> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut fixed = String::with_capacity(text.len());
+    let mut misread = Misread::Latin1;
+    let mut segments = 0;
+    let mut previews = Vec::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_ascii() {
+            fixed.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let end = (i..chars.len())
+            .find(|&j| chars[j].is_ascii())
+            .unwrap_or(chars.len());
+        let run: String = chars[i..end].iter().collect();
+
+        match redecode(&run) {
+            Some((repaired, used)) => {
+                if used == Misread::Windows1252 {
+                    misread = used;
+                }
+                segments += 1;
+                if previews.len() < max_previews {
+                    let before: String = chars[i.saturating_sub(context)..i].iter().collect();
+                    let after: String = chars[end..(end + context).min(chars.len())]
+                        .iter()
+                        .collect();
+                    previews.push((
+                        format!("…{}{}{}…", before, run, after),
+                        format!("…{}{}{}…", before, repaired, after),
+                    ));
+                }
+                fixed.push_str(&repaired);
+            }
+            None => fixed.push_str(&run),
+        }
+        i = end;
+    }
+
+    (segments > 0).then_some(Repair {
+        text: fixed,
+        misread,
+        segments,
+        previews,
+    })
+}
+
+// Re-decodes a run for as long as it keeps decoding, for text that went
+// through the wrong decoder more than once
+fn redecode(run: &str) -> Option<(String, Misread)> {
+    let mut current = run.to_string();
+    let mut misread = Misread::Latin1;
+    let mut passes = 0;
+    while passes < MAX_PASSES {
+        let Some((bytes, used)) = encode_single_byte(&current) else {
+            break;
+        };
+        let Ok(decoded) = String::from_utf8(bytes) else {
+            break;
+        };
+        if decoded == current {
+            break;
+        }
+        if used == Misread::Windows1252 {
+            misread = used;
+        }
+        current = decoded;
+        passes += 1;
+    }
+    (passes > 0).then_some((current, misread))
+}
+
+// The bytes a single-byte decoder turned into `text`, if it could have
+fn encode_single_byte(text: &str) -> Option<(Vec<u8>, Misread)> {
+    let mut misread = Misread::Latin1;
+    let bytes = text
+        .chars()
+        .map(|c| {
+            if let Some(index) = WINDOWS_1252_HIGH.iter().position(|x| *x == c) {
+                if c as u32 > 0xFF {
+                    misread = Misread::Windows1252;
+                }
+                Some(0x80 + index as u8)
+            } else {
+                u8::try_from(c as u32).ok()
+            }
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    Some((bytes, misread))
+}
+
+/// Bytes as text: UTF-8 when they are valid UTF-8, minus a byte order
+/// mark, otherwise Windows-1252, what spreadsheet apps write on Windows.
+pub fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes
+            .iter()
+            .map(|&byte| match byte {
+                0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+                _ => char::from(byte),
+            })
+            .collect(),
+    }
+}
+
+/// The line break exported text is written with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// What Notepad and older Windows tools expect.
+    Crlf,
+}
+
+/// Text with every line break, `\r\n`, `\n` or a lone `\r`, written as
+/// `ending`, and without a byte order mark, however the parts it was put
+/// together from were stored.
+pub fn normalize_line_endings(text: &str, ending: LineEnding) -> String {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    let newline = match ending {
+        LineEnding::Lf => "\n",
+        LineEnding::Crlf => "\r\n",
+    };
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                normalized.push_str(newline);
+            }
+            '\n' => normalized.push_str(newline),
+            _ => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_every_line_break() {
+        let mixed = "one\r\ntwo\nthree\rfour";
+        assert_eq!(
+            normalize_line_endings(mixed, LineEnding::Lf),
+            "one\ntwo\nthree\nfour"
+        );
+        assert_eq!(
+            normalize_line_endings(mixed, LineEnding::Crlf),
+            "one\r\ntwo\r\nthree\r\nfour"
+        );
+    }
+
+    #[test]
+    fn blank_lines_survive() {
+        // \r\r\n is a lone \r and then \r\n, two breaks
+        assert_eq!(normalize_line_endings("a\r\r\nb", LineEnding::Lf), "a\n\nb");
+        assert_eq!(
+            normalize_line_endings("a\n\r\nb\n", LineEnding::Crlf),
+            "a\r\n\r\nb\r\n"
+        );
+        assert_eq!(normalize_line_endings("\r\n", LineEnding::Lf), "\n");
+        assert_eq!(normalize_line_endings("", LineEnding::Crlf), "");
+    }
+
+    #[test]
+    fn converting_twice_changes_nothing() {
+        let once = normalize_line_endings("a\rb\nc\r\n", LineEnding::Crlf);
+        assert_eq!(normalize_line_endings(&once, LineEnding::Crlf), once);
+        assert_eq!(normalize_line_endings(&once, LineEnding::Lf), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn drops_only_a_leading_bom() {
+        assert_eq!(
+            normalize_line_endings("\u{FEFF}caf\u{e9}\r\n\u{FEFF}", LineEnding::Lf),
+            "caf\u{e9}\n\u{FEFF}"
+        );
+    }
+
+    #[test]
+    fn line_endings_serialize_lowercase() {
+        assert_eq!(
+            serde_json::to_string(&LineEnding::Crlf).unwrap(),
+            "\"crlf\""
+        );
+        let ending: LineEnding = serde_json::from_str("\"lf\"").unwrap();
+        assert_eq!(ending, LineEnding::Lf);
+    }
+}
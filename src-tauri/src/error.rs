@@ -0,0 +1,199 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// The error every command returns, serialized as `{ code, message }` so
+/// the frontend can tell kinds of failure apart without matching text.
+#[derive(Debug, Clone)]
+pub enum AppError {
+    NotFound(String),
+    /// The input can't be used as given.
+    Validation(String),
+    /// The change clashes with data that is already there.
+    Conflict(String),
+    /// A destructive operation was called without `confirmed`.
+    ConfirmationRequired(String),
+    Io(String),
+    /// The database is busy with another writer; retrying can succeed.
+    DbLocked(String),
+    /// The database or a file can't be written.
+    ReadOnly(String),
+    BadPassword(String),
+    /// The file was written by a newer version of the app, in a format or
+    /// schema this one doesn't know.
+    IncompatibleVersion {
+        archive: u32,
+        supported: u32,
+    },
+    /// A document body is longer than `max_body_chars` and
+    /// `strict_body_limit` is on; both counts are in characters.
+    BodyTooLarge {
+        limit: usize,
+        actual: usize,
+    },
+    /// Anything else, e.g. a failure inside a helper module.
+    Internal(String),
+}
+
+pub type CmdResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Validation(_) => "validation",
+            AppError::Conflict(_) => "conflict",
+            AppError::ConfirmationRequired(_) => "confirmation_required",
+            AppError::Io(_) => "io",
+            AppError::DbLocked(_) => "db_locked",
+            AppError::ReadOnly(_) => "read_only",
+            AppError::BadPassword(_) => "bad_password",
+            AppError::IncompatibleVersion { .. } => "incompatible_version",
+            AppError::BodyTooLarge { .. } => "body_too_large",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> Cow<'_, str> {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Validation(message)
+            | AppError::Conflict(message)
+            | AppError::ConfirmationRequired(message)
+            | AppError::Io(message)
+            | AppError::DbLocked(message)
+            | AppError::ReadOnly(message)
+            | AppError::BadPassword(message)
+            | AppError::Internal(message) => Cow::Borrowed(message),
+            AppError::IncompatibleVersion { archive, supported } => Cow::Owned(format!(
+                "Made by a newer version of the app (version {}, this one reads up to {}); update the app to open it",
+                archive, supported
+            )),
+            AppError::BodyTooLarge { limit, actual } => Cow::Owned(format!(
+                "The body is {} characters, over the limit of {}; split it into several documents or attach the content as a file",
+                actual, limit
+            )),
+        }
+    }
+
+    fn from_io(error: &io::Error, message: String) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => AppError::NotFound(message),
+            io::ErrorKind::PermissionDenied => AppError::ReadOnly(message),
+            _ => AppError::Io(message),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let versions = match self {
+            AppError::IncompatibleVersion { archive, supported } => Some((archive, supported)),
+            _ => None,
+        };
+        let body_size = match self {
+            AppError::BodyTooLarge { limit, actual } => Some((limit, actual)),
+            _ => None,
+        };
+        let fields = if versions.is_some() || body_size.is_some() {
+            4
+        } else {
+            2
+        };
+        let mut error = serializer.serialize_struct("AppError", fields)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.message())?;
+        // So the frontend can say which version to update to
+        if let Some((archive, supported)) = versions {
+            error.serialize_field("archive", archive)?;
+            error.serialize_field("supported", supported)?;
+        }
+        // And how much to cut
+        if let Some((limit, actual)) = body_size {
+            error.serialize_field("limit", limit)?;
+            error.serialize_field("actual", actual)?;
+        }
+        error.end()
+    }
+}
+
+// For jobs and background tasks, which report plain messages
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.message().into_owned()
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        let message = error.to_string();
+        match &error {
+            sqlx::Error::RowNotFound => AppError::NotFound(message),
+            sqlx::Error::PoolTimedOut => AppError::DbLocked(message),
+            sqlx::Error::Database(database) => {
+                // Extended result codes keep the primary one in the low byte
+                let code = database
+                    .code()
+                    .and_then(|code| code.parse::<i32>().ok())
+                    .map(|code| code & 0xff);
+                match code {
+                    // SQLITE_BUSY, SQLITE_LOCKED
+                    Some(5) | Some(6) => AppError::DbLocked(message),
+                    // SQLITE_READONLY
+                    Some(8) => AppError::ReadOnly(message),
+                    // SQLITE_CONSTRAINT
+                    Some(19) => AppError::Conflict(message),
+                    _ => AppError::Internal(message),
+                }
+            }
+            sqlx::Error::Io(e) => AppError::from_io(e, message),
+            _ => AppError::Internal(message),
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        let message = error.to_string();
+        AppError::from_io(&error, message)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        AppError::Validation(error.to_string())
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(error: tauri::Error) -> Self {
+        AppError::Internal(error.to_string())
+    }
+}
+
+impl From<image::ImageError> for AppError {
+    fn from(error: image::ImageError) -> Self {
+        let message = error.to_string();
+        match &error {
+            image::ImageError::IoError(e) => AppError::from_io(e, message),
+            _ => AppError::Validation(message),
+        }
+    }
+}
+
+impl From<tauri_plugin_clipboard_manager::Error> for AppError {
+    fn from(error: tauri_plugin_clipboard_manager::Error) -> Self {
+        AppError::Internal(error.to_string())
+    }
+}
@@ -0,0 +1,141 @@
+// Document bodies are stored as the editor's HTML
+
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Plain text paragraphs, one per block element.
+pub fn to_paragraphs(html: &str) -> Vec<String> {
+    const BLOCKS: &[&str] = &[
+        "p",
+        "div",
+        "br",
+        "li",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "blockquote",
+        "pre",
+        "tr",
+    ];
+
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        current.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        if BLOCKS.contains(&name.as_str()) {
+            push_paragraph(&mut paragraphs, &current);
+            current.clear();
+            if name == "li" && !tag.starts_with('/') {
+                current.push_str("• ");
+            }
+        }
+    }
+    current.push_str(rest);
+    push_paragraph(&mut paragraphs, &current);
+
+    paragraphs
+}
+
+fn push_paragraph(paragraphs: &mut Vec<String>, raw: &str) {
+    let text = decode_entities(raw)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !text.is_empty() && text != "•" {
+        paragraphs.push(text);
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Rewrites the text between tags with `f`, leaving the markup untouched.
+pub fn map_text(html: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while !rest.is_empty() {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        output.push_str(&f(&rest[..text_end]));
+        rest = &rest[text_end..];
+
+        let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+        output.push_str(&rest[..tag_end]);
+        rest = &rest[tag_end..];
+    }
+    output
+}
+
+/// Rewrites the quoted attribute values within tags with `f`, leaving the
+/// text and the rest of the markup untouched.
+pub fn map_attribute_values(html: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(tag_start) = rest.find('<') {
+        let tag_end = rest[tag_start..]
+            .find('>')
+            .map_or(rest.len(), |end| tag_start + end + 1);
+        output.push_str(&rest[..tag_start]);
+
+        let mut tag = &rest[tag_start..tag_end];
+        while let Some(open) = tag.find(['"', '\'']) {
+            let quote = &tag[open..open + 1];
+            output.push_str(&tag[..=open]);
+            tag = &tag[open + 1..];
+            let Some(close) = tag.find(quote) else {
+                break;
+            };
+            output.push_str(&f(&tag[..close]));
+            output.push_str(quote);
+            tag = &tag[close + 1..];
+        }
+        output.push_str(tag);
+        rest = &rest[tag_end..];
+    }
+    output.push_str(rest);
+    output
+}
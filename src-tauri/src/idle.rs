@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri::ipc::Invoke;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db;
+use crate::error::AppError;
+use crate::power::Power;
+use crate::settings::SettingsStore;
+use crate::thumbnails::{self, ThumbnailCache};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Last user activity, fed by window focus and command invocations.
+pub struct Activity {
+    last: Mutex<Instant>,
+    // Bumped on every activity so a running pass can tell it was interrupted
+    generation: AtomicU64,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            last: Mutex::new(Instant::now()),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Activity {
+    pub fn touch(&self) {
+        *self.last.lock().unwrap() = Instant::now();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last.lock().unwrap().elapsed()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// Wraps the invoke handler so every command call counts as activity.
+pub fn track<R: Runtime>(
+    handler: impl Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        invoke.message.webview_ref().state::<Activity>().touch();
+        handler(invoke)
+    }
+}
+
+/// Runs light maintenance once per idle period when enabled in settings,
+/// unless background jobs are paused to save power. A pass stops between
+/// steps as soon as there is new activity, and picks up again the next
+/// time the app goes idle.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut completed_generation = None;
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+
+            let settings = app.state::<SettingsStore>().get();
+            let activity = app.state::<Activity>();
+            let generation = activity.generation();
+            let idle_threshold = Duration::from_secs(settings.idle_minutes as u64 * 60);
+            if !settings.idle_maintenance
+                || app.state::<Power>().paused()
+                || activity.idle_for() < idle_threshold
+                || completed_generation == Some(generation)
+            {
+                continue;
+            }
+
+            let Ok(pool) = db::pool(&app).await else {
+                continue;
+            };
+            match run(&app, &pool, generation).await {
+                Ok(true) => completed_generation = Some(generation),
+                Ok(false) => log::info!("Idle maintenance paused by user activity"),
+                Err(e) => log::warn!("Idle maintenance failed: {}", e),
+            }
+        }
+    });
+}
+
+// Returns whether the pass finished without being interrupted
+async fn run(app: &AppHandle, pool: &sqlx::SqlitePool, generation: u64) -> Result<bool, String> {
+    let interrupted = || app.state::<Activity>().generation() != generation;
+
+    // Passive checkpoints never wait on readers or writers
+    sqlx::query("PRAGMA wal_checkpoint(PASSIVE)")
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if interrupted() {
+        return Ok(false);
+    }
+
+    sqlx::query("INSERT INTO documents_fts(documents_fts) VALUES ('optimize')")
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if interrupted() {
+        return Ok(false);
+    }
+
+    let root = thumbnails::cache_root(app)?;
+    let limit_mb = app.state::<SettingsStore>().get().thumbnail_cache_mb;
+    let finished = prefetch_thumbnails(app, pool, &root, interrupted).await;
+
+    // Once per pass rather than per thumbnail, in case thumbnails opened
+    // meanwhile took the room
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        handle.state::<ThumbnailCache>().enforce(&root, limit_mb);
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    finished
+}
+
+// Thumbnails for images that have none, newest first, until the cache's
+// prefetch budget is spent. Returns whether it got through them.
+async fn prefetch_thumbnails(
+    app: &AppHandle,
+    pool: &sqlx::SqlitePool,
+    root: &Path,
+    interrupted: impl Fn() -> bool,
+) -> Result<bool, String> {
+    let settings = app.state::<SettingsStore>().get();
+    let (size, limit_mb) = (settings.thumbnail_size, settings.thumbnail_cache_mb);
+    let mut budget = app
+        .state::<ThumbnailCache>()
+        .prefetch_budget(root, limit_mb);
+    let images: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, filepath FROM attachments WHERE filetype LIKE 'image/%' ORDER BY id DESC",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // One image at a time, so activity never waits longer than a single
+    // thumbnail
+    for (id, filepath) in images {
+        if interrupted() {
+            return Ok(false);
+        }
+        if budget == Some(0) {
+            log::info!("Thumbnail cache is close to its limit, skipping the rest of the prefetch");
+            break;
+        }
+
+        let dest = thumbnails::thumbnail_path(root, size, id);
+        if dest.exists() {
+            continue;
+        }
+        let (cache_root, handle) = (root.to_path_buf(), app.clone());
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            thumbnails::generate(&PathBuf::from(filepath), &dest, size)?;
+            Ok::<_, AppError>(
+                handle
+                    .state::<ThumbnailCache>()
+                    .prefetched(&cache_root, &dest),
+            )
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        match result {
+            Ok(added) => budget = budget.map(|left| left.saturating_sub(added)),
+            Err(e) => log::warn!("Could not prefetch thumbnail for attachment {}: {}", id, e),
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::{AppError, CmdResult};
+use crate::power::Power;
+
+// How often a paused background job checks whether it may go on
+const PAUSE_POLL: Duration = Duration::from_secs(5);
+// The rate is measured over this much recent progress
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+// Early rates swing with the first few items, so no estimate before this
+const WARM_UP: Duration = Duration::from_secs(5);
+const WARM_UP_ITEMS: usize = 3;
+
+/// Background jobs currently running, keyed by id, with their cancel flag.
+#[derive(Default)]
+pub struct Jobs {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl Jobs {
+    fn start(&self) -> (u64, Arc<AtomicBool>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.running
+            .lock()
+            .unwrap()
+            .insert(id, Arc::clone(&cancelled));
+        (id, cancelled)
+    }
+
+    fn finish(&self, id: u64) {
+        self.running.lock().unwrap().remove(&id);
+    }
+
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.running.lock().unwrap().get(&id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct JobProgress {
+    job_id: u64,
+    kind: &'static str,
+    processed: usize,
+    total: usize,
+    /// Over the last half minute; `None` while warming up.
+    items_per_second: Option<f64>,
+    /// Seconds left at that rate; `None` while warming up.
+    eta_seconds: Option<u64>,
+}
+
+// Recent progress reports, oldest first, for the processing rate
+#[derive(Default)]
+struct Rate {
+    started: Option<Instant>,
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl Rate {
+    fn record(&mut self, now: Instant, processed: usize) {
+        // Going backwards means the job started over on something else
+        if self
+            .samples
+            .back()
+            .is_some_and(|&(_, last)| processed < last)
+        {
+            self.reset();
+        }
+        self.started.get_or_insert(now);
+        self.samples.push_back((now, processed));
+        // Keeps one sample older than the window, so it is always spanned
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) > RATE_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.started = None;
+        self.samples.clear();
+    }
+
+    fn per_second(&self, now: Instant) -> Option<f64> {
+        let started = self.started?;
+        let (&(first_at, first), &(_, last)) = (self.samples.front()?, self.samples.back()?);
+        if now.duration_since(started) < WARM_UP || last - first < WARM_UP_ITEMS {
+            return None;
+        }
+        // Measured up to now, so a stall shows as a slowing rate
+        let seconds = now.duration_since(first_at).as_secs_f64();
+        (seconds > 0.0).then(|| (last - first) as f64 / seconds)
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct JobFinished<T> {
+    job_id: u64,
+    kind: &'static str,
+    cancelled: bool,
+    result: Option<T>,
+    error: Option<AppError>,
+}
+
+/// Handle a running job uses to report progress and check for cancellation.
+#[derive(Clone)]
+pub struct JobContext {
+    app: AppHandle,
+    id: u64,
+    kind: &'static str,
+    cancelled: Arc<AtomicBool>,
+    background: bool,
+    rate: Arc<Mutex<Rate>>,
+}
+
+impl JobContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// For background jobs, waits while they are paused to save power, or
+    /// until the job is cancelled. Jobs the user started never wait.
+    pub async fn wait_if_paused(&self) {
+        if !self.background {
+            return;
+        }
+        let mut waited = false;
+        while self.app.state::<Power>().paused() && !self.is_cancelled() {
+            tokio::time::sleep(PAUSE_POLL).await;
+            waited = true;
+        }
+        // Time spent paused says nothing about the rate
+        if waited {
+            self.rate.lock().unwrap().reset();
+        }
+    }
+
+    /// Reports progress, with the rate and time left once there has been
+    /// enough of it to tell.
+    pub fn progress(&self, processed: usize, total: usize) {
+        let now = Instant::now();
+        let items_per_second = {
+            let mut rate = self.rate.lock().unwrap();
+            rate.record(now, processed);
+            rate.per_second(now)
+        };
+        let eta_seconds = items_per_second
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| (total.saturating_sub(processed) as f64 / rate).ceil() as u64);
+        let _ = self.app.emit(
+            "job_progress",
+            JobProgress {
+                job_id: self.id,
+                kind: self.kind,
+                processed,
+                total,
+                items_per_second,
+                eta_seconds,
+            },
+        );
+    }
+}
+
+/// Runs `job` in the background and returns its id right away. Progress is
+/// reported through `job_progress` events and the outcome through a
+/// `job_finished` event, with the error as commands return it.
+pub fn spawn<T, F, Fut>(app: &AppHandle, kind: &'static str, job: F) -> u64
+where
+    T: Serialize + Clone + Send + 'static,
+    F: FnOnce(JobContext) -> Fut + Send + 'static,
+    Fut: Future<Output = CmdResult<T>> + Send + 'static,
+{
+    spawn_with(app, kind, false, job)
+}
+
+/// Like `spawn`, but with `background` the job is non-urgent work that
+/// holds back at `wait_if_paused` while power is low.
+pub fn spawn_with<T, F, Fut>(app: &AppHandle, kind: &'static str, background: bool, job: F) -> u64
+where
+    T: Serialize + Clone + Send + 'static,
+    F: FnOnce(JobContext) -> Fut + Send + 'static,
+    Fut: Future<Output = CmdResult<T>> + Send + 'static,
+{
+    let (id, cancelled) = app.state::<Jobs>().start();
+    let context = JobContext {
+        app: app.clone(),
+        id,
+        kind,
+        cancelled: Arc::clone(&cancelled),
+        background,
+        rate: Arc::default(),
+    };
+    let app = app.clone();
+
+    tauri::async_runtime::spawn(async move {
+        let outcome = job(context).await;
+        app.state::<Jobs>().finish(id);
+
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        let _ = app.emit(
+            "job_finished",
+            JobFinished {
+                job_id: id,
+                kind,
+                cancelled: cancelled.load(Ordering::Relaxed),
+                result,
+                error,
+            },
+        );
+    });
+
+    id
+}
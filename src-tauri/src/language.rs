@@ -0,0 +1,200 @@
+use serde::Serialize;
+
+// Shorter texts are reported with proportionally lower confidence
+const CONFIDENT_WORD_COUNT: f64 = 20.0;
+const CONFIDENT_CHAR_COUNT: f64 = 20.0;
+
+// Common function words, enough to tell apart the Latin-script languages
+// users are likely to write in
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "in", "that", "it", "for", "was", "on", "with", "as",
+            "this", "are", "be", "have", "not", "you", "at",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "a", "de", "que", "e", "do", "da", "em", "um", "uma", "para", "com", "não", "os",
+            "as", "no", "na", "se", "por", "mais", "foi", "são", "está",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "de", "que", "y", "en", "los", "las", "un", "una", "por", "con", "para",
+            "es", "se", "del", "al", "lo", "como", "pero", "está",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "de", "des", "et", "un", "une", "est", "que", "en", "du", "pour",
+            "dans", "qui", "pas", "sur", "avec", "ce", "je", "il",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "den", "mit", "sich",
+            "von", "auf", "für", "ich", "es", "dem", "auch", "wird",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "e", "la", "un", "una", "per", "non", "sono", "del", "della", "con",
+            "gli", "le", "si", "anche", "è", "questo", "alla",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "dat", "niet", "op", "te", "zijn", "voor",
+            "met", "ik", "maar", "ook", "er", "aan", "wordt", "bij",
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageGuess {
+    /// ISO 639-1 code, or `und` when nothing could be recognized.
+    pub language: String,
+    /// Between 0 and 1; low for short or mixed-language text.
+    pub confidence: f64,
+}
+
+impl LanguageGuess {
+    fn undetermined() -> Self {
+        Self {
+            language: "und".to_string(),
+            confidence: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+const SCRIPT_COUNT: usize = Script::Han as usize + 1;
+
+fn script_of(c: char) -> Option<Script> {
+    match c as u32 {
+        0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F => Some(Script::Latin),
+        0x370..=0x3FF => Some(Script::Greek),
+        0x400..=0x4FF => Some(Script::Cyrillic),
+        0x590..=0x5FF => Some(Script::Hebrew),
+        0x600..=0x6FF => Some(Script::Arabic),
+        0x900..=0x97F => Some(Script::Devanagari),
+        0xE00..=0xE7F => Some(Script::Thai),
+        0x1100..=0x11FF | 0xAC00..=0xD7AF => Some(Script::Hangul),
+        0x3040..=0x30FF => Some(Script::Kana),
+        0x4E00..=0x9FFF => Some(Script::Han),
+        _ => None,
+    }
+}
+
+/// Guesses the language of `text` from its script and, for Latin-script
+/// text, from the share of common function words of each language.
+pub fn detect(text: &str) -> LanguageGuess {
+    let mut counts = [0usize; SCRIPT_COUNT];
+    for c in text.chars() {
+        if let Some(script) = script_of(c) {
+            counts[script as usize] += 1;
+        }
+    }
+    let letters: usize = counts.iter().sum();
+    if letters == 0 {
+        return LanguageGuess::undetermined();
+    }
+
+    let count = |script: Script| counts[script as usize];
+
+    // Japanese mixes kana with kanji, so both count towards it
+    let japanese = count(Script::Kana)
+        + if count(Script::Kana) > 0 {
+            count(Script::Han)
+        } else {
+            0
+        };
+    let candidates = [
+        ("ja", japanese),
+        ("zh", if japanese > 0 { 0 } else { count(Script::Han) }),
+        ("ko", count(Script::Hangul)),
+        ("ru", count(Script::Cyrillic)),
+        ("el", count(Script::Greek)),
+        ("ar", count(Script::Arabic)),
+        ("he", count(Script::Hebrew)),
+        ("hi", count(Script::Devanagari)),
+        ("th", count(Script::Thai)),
+    ];
+    let (language, script_letters) = candidates
+        .into_iter()
+        .max_by_key(|(_, letters)| *letters)
+        .unwrap();
+
+    if script_letters * 2 > letters {
+        let share = script_letters as f64 / letters as f64;
+        let length = (script_letters as f64 / CONFIDENT_CHAR_COUNT).min(1.0);
+        return LanguageGuess {
+            language: language.to_string(),
+            confidence: round(share * length),
+        };
+    }
+
+    detect_latin(text)
+}
+
+fn detect_latin(text: &str) -> LanguageGuess {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    if words.is_empty() {
+        return LanguageGuess::undetermined();
+    }
+
+    let mut scores: Vec<(&str, usize)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|word| stopwords.contains(&word.as_str()))
+                .count();
+            (*language, hits)
+        })
+        .collect();
+    scores.sort_by_key(|(_, hits)| std::cmp::Reverse(*hits));
+
+    let (language, best) = scores[0];
+    let runner_up = scores[1].1;
+    if best == 0 {
+        return LanguageGuess::undetermined();
+    }
+
+    // Shared words (`de`, `a`, `la`) make close scores ambiguous
+    let margin = (best - runner_up) as f64 / best as f64;
+    let length = (words.len() as f64 / CONFIDENT_WORD_COUNT).min(1.0);
+    LanguageGuess {
+        language: language.to_string(),
+        confidence: round(margin * length),
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
@@ -1,14 +1,295 @@
+mod accelerator;
+mod annotate;
+mod archive;
+mod capture;
+mod collation;
+mod commands;
+mod convert;
+mod csv;
+mod cursor;
+mod db;
+mod deep_link;
+mod describe;
+mod diff;
+mod editor;
+mod encoding;
+mod error;
+mod html;
+mod idle;
+mod jobs;
+mod language;
+mod local_api;
+mod markdown;
 mod menu;
+mod metrics;
+mod migrations;
+mod ocr;
+mod pdf;
+mod phash;
+mod power;
+mod qr;
+mod reminders;
+mod remote;
+mod rules;
+mod sanitize;
+mod secrets;
+mod settings;
+mod smart_folders;
+mod summary;
+mod switcher;
+mod thumbnails;
+mod watcher;
 
-use tauri::{Manager, WindowEvent};
+use tauri::{DragDropEvent, Manager, WindowEvent};
+
+// Every command the frontend can invoke, listed once. This builds the
+// invoke handler and the list `api_version` reports, so the two can't
+// drift apart. Commands are stable unless marked otherwise.
+macro_rules! command_registry {
+    ($($module:ident::$name:ident $([$stability:ident])?,)*) => {
+        fn invoke_handler() -> impl Fn(tauri::ipc::Invoke) -> bool + Send + Sync + 'static {
+            idle::track(tauri::generate_handler![$(commands::$module::$name),*])
+        }
+
+        pub(crate) const COMMANDS: &[commands::api::CommandInfo] = &[$(
+            commands::api::CommandInfo {
+                name: stringify!($name),
+                stability: command_registry!(@stability $($stability)?),
+            },
+        )*];
+    };
+    (@stability) => {
+        commands::api::Stability::Stable
+    };
+    (@stability $stability:ident) => {
+        commands::api::Stability::$stability
+    };
+}
+
+command_registry! {
+    api::api_version,
+    archive::export_category,
+    archive::verify_archive,
+    archive::export_plaintext,
+    archive::export_document,
+    archive::get_last_export,
+    archive::export_history_patches,
+    archive::export_combined_pdf,
+    archive::export_attachments_by_type,
+    archive::export,
+    archive::export_smart_folder,
+    archive::export_redacted,
+    archive::export_search_index,
+    archive::export_vault,
+    archive::generate_recovery_key,
+    archive::unlock_with_recovery_key,
+    archive::export_mobile_bundle,
+    archive::verify_mobile_bundle,
+    archive::contact_sheet,
+    import::import_vault,
+    import::import_zip,
+    archive_meta::get_archive_meta,
+    archive_meta::set_archive_meta,
+    archive_meta::archive_stats,
+    attachments::attach_file,
+    attachments::detach_file,
+    attachments::reorder_attachments,
+    attachments::list_attachments_paged,
+    attachments::attachment_provenance,
+    attachments::convert_attachment,
+    attachments::replace_attachment,
+    attachments::list_attachment_versions,
+    attachments::restore_attachment_version,
+    attachments::documents_with_missing_attachments,
+    attachments::find_similar_images,
+    attachments::ocr_all_pending,
+    attachments::describe_image,
+    attachments::save_annotations [Experimental],
+    attachments::get_annotations [Experimental],
+    attachments::export_annotated [Experimental],
+    audit::audit_log,
+    audit::audit_log_detailed,
+    audit::export_audit_log,
+    autocomplete::autocomplete [Experimental],
+    backup::upload_backup,
+    backup::list_remote_backups,
+    backup::download_backup,
+    backup::create_backup,
+    backup::list_backups,
+    backup::restore_backup,
+    backup::export_incremental,
+    backup::import_incremental,
+    capture::capture_screenshot_to_document,
+    categories::category_tree,
+    categories::list_categories,
+    categories::reorder_documents_in_category,
+    categories::set_category_view,
+    categories::get_category_view,
+    categories::set_category_rule,
+    categories::list_category_rules,
+    categories::delete_category,
+    categories::archive_category,
+    categories::unarchive_category,
+    categories::category_manifest,
+    categories::export_category_manifest,
+    changes::changes_since,
+    changes::current_change_seq,
+    deep_link::resolve_target,
+    demo::seed_demo_data,
+    digest::generate_digest,
+    documents::clone_document,
+    documents::delete_document,
+    documents::set_document_timestamps,
+    documents::documents_with_attachment_type,
+    documents::export_document_json,
+    documents::copy_document_as_html,
+    documents::document_qr,
+    documents::bulk_replace,
+    documents::detect_language,
+    documents::detect_document_language,
+    documents::set_document_language,
+    documents::set_document_locked,
+    documents::locked_document_count,
+    documents::summarize_document,
+    documents::render_markdown,
+    documents::check_document_body,
+    documents::detect_and_fix_encoding,
+    documents::fix_encoding_batch,
+    documents::sanitize_document,
+    documents::sanitize_category,
+    documents::list_documents,
+    documents::get_document,
+    documents::get_document_body_range,
+    documents::documents_timeline,
+    documents::cleanup_empty_documents,
+    documents::restore_document,
+    documents::restore_trash,
+    documents::purge_trash_matching,
+    documents::empty_trash,
+    editor::open_in_external_editor,
+    fields::set_field_for_documents,
+    fields::remove_field_for_documents,
+    import::import_archive,
+    import::import_folder,
+    import::import_dropped_files,
+    import::import_csv,
+    import::list_import_sessions,
+    import::undo_import,
+    jobs::cancel_job,
+    jobs::set_power_mode [Experimental],
+    jobs::get_power_state [Experimental],
+    layouts::save_list_layout,
+    layouts::list_layouts,
+    layouts::apply_layout,
+    layouts::delete_layout,
+    local_api::get_local_api_token [Experimental],
+    local_api::regenerate_local_api_token [Experimental],
+    maintenance::optimize_attachments,
+    maintenance::prune_versions,
+    maintenance::set_idle_maintenance,
+    maintenance::checkpoint_database,
+    maintenance::get_db_tuning,
+    maintenance::set_db_tuning,
+    maintenance::get_collation,
+    maintenance::set_collation_locale,
+    maintenance::query_metrics [Experimental],
+    maintenance::run_maintenance_sql [Experimental],
+    maintenance::self_test,
+    maintenance::check_archive_size,
+    maintenance::migrate_legacy_attachments,
+    notes::add_note,
+    notes::list_notes,
+    notes::update_note,
+    notes::delete_note,
+    references::add_link_reference,
+    references::list_references,
+    references::remove_reference,
+    related::related_documents,
+    reminders::set_reminder,
+    reminders::clear_reminder,
+    reminders::list_upcoming_reminders,
+    review::set_needs_review,
+    review::list_needs_review,
+    review::needs_review_count,
+    review::open_document,
+    review::close_document,
+    review::mark_read,
+    review::mark_unread,
+    review::list_unread,
+    review::unread_count,
+    review::most_viewed,
+    review::least_viewed,
+    review::find_stale_documents,
+    tabs::save_open_tabs,
+    tabs::get_open_tabs,
+    seal::seal_archive,
+    seal::verify_seal,
+    search::set_search_options,
+    search::rebuild_search_index,
+    search::search_documents,
+    search::search_documents_incremental,
+    search::search_versions,
+    search::defer_search_indexing,
+    secrets::set_secret,
+    secrets::get_secret,
+    secrets::delete_secret,
+    sensitive::builtin_sensitive_patterns,
+    sensitive::scan_sensitive,
+    settings::get_settings,
+    settings::update_settings,
+    settings::validate_accelerator,
+    settings::export_preferences,
+    settings::import_preferences,
+    settings::get_export_stylesheet,
+    settings::set_export_stylesheet,
+    settings::reset_export_stylesheet,
+    switcher::quick_switch [Experimental],
+    tags::add_tag,
+    tags::remove_tag,
+    tags::list_tags,
+    tags::documents_by_tag_prefix,
+    tags::tag_graph,
+    tags::suggest_tag_merges,
+    tags::merge_tags,
+    taxonomy::export_taxonomy,
+    taxonomy::import_taxonomy,
+    thumbnails::get_thumbnail,
+    thumbnails::rebuild_thumbnails,
+    thumbnails::thumbnail_cache_stats,
+    toc::generate_index_document,
+    toc::get_generated_document,
+    watcher::watch_folder,
+    watcher::stop_watching,
+    watcher::list_watched_folders,
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        // First, so a second launch hands over its arguments and exits
+        // before anything else starts
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            deep_link::open_from_second_instance(app, args);
+        }))
         .plugin(tauri_plugin_clipboard_manager::init())
-        .plugin(tauri_plugin_sql::Builder::new().build())
+        .plugin(
+            tauri_plugin_sql::Builder::new()
+                .add_migrations(db::DB_URL, migrations::migrations())
+                .build(),
+        )
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
+        .manage(editor::ExternalEdits::default())
+        .manage(idle::Activity::default())
+        .manage(jobs::Jobs::default())
+        .manage(local_api::LocalApi::default())
+        .manage(metrics::QueryMetrics::default())
+        .manage(power::Power::default())
+        .manage(commands::search::SearchSessions::default())
+        .manage(switcher::QuickSwitcher::default())
+        .manage(thumbnails::ThumbnailCache::default())
+        .manage(watcher::FolderWatchers::default())
         .setup(|app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -18,8 +299,30 @@ pub fn run() {
                 )?;
             }
 
+            let settings_path = app.path().app_config_dir()?.join("settings.json");
+            let settings = settings::SettingsStore::load(settings_path);
+            let current = settings.get();
+            let watchers = app.state::<watcher::FolderWatchers>();
+            for folder in current.watched_folders {
+                watchers.start(app.handle(), folder.path.into(), folder.category_id);
+            }
+            app.manage(settings);
+
+            db::tune_on_launch(app.handle().clone());
+            reminders::start(app.handle().clone());
+            rules::start(app.handle().clone());
+            idle::start(app.handle().clone());
+            power::start(app.handle().clone());
+            local_api::sync(app.handle());
+            commands::archive_meta::restore_window_title(app.handle().clone());
+            commands::search::resume_interrupted_indexing(app.handle().clone());
+            commands::tabs::restore_open_tabs(app.handle().clone());
+            commands::documents::backfill_listing_columns(app.handle().clone());
+            deep_link::register();
+            deep_link::open_from_args(app.handle());
+
             // Create and set the menu
-            let menu = menu::create_app_menu(app.handle())?;
+            let menu = menu::create_app_menu(app.handle(), &current.keybindings)?;
             app.set_menu(menu)?;
 
             // Get the main window and set minimum size
@@ -32,14 +335,30 @@ pub fn run() {
         .on_menu_event(|app, event| {
             menu::handle_menu_event(app, event.id().as_ref());
         })
-        .on_window_event(|_window, event| {
-            match event {
-                WindowEvent::CloseRequested { .. } => {
-                    // Handle window close if needed
-                }
-                _ => {}
+        .on_window_event(|window, event| match event {
+            WindowEvent::CloseRequested { .. } => {
+                // Handle window close if needed
+            }
+            WindowEvent::Focused(true) => window.state::<idle::Activity>().touch(),
+            WindowEvent::DragDrop(DragDropEvent::Drop { paths, position }) => {
+                commands::import::handle_drop(window, paths, *position)
             }
+            _ => {}
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(invoke_handler())
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                local_api::stop(app);
+            }
+            // macOS delivers links to the running app instead of starting it
+            // with them as arguments
+            #[cfg(target_os = "macos")]
+            if let tauri::RunEvent::Opened { urls } = event {
+                for url in urls {
+                    deep_link::open(app, url.as_str());
+                }
+            }
+        });
 }
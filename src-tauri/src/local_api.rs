@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::commands::{api, archive_meta, maintenance, search};
+use crate::error::{AppError, CmdResult};
+use crate::secrets;
+use crate::settings::SettingsStore;
+
+// A read-only HTTP/1.1 endpoint on 127.0.0.1 for scripts and widgets. It
+// answers a handful of GET routes by calling the same code the commands
+// use, and only with the bearer token kept in the keyring.
+
+const TOKEN_KEY: &str = "local_api_token";
+// How often the accept loop looks for a shutdown request
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+// Requests are answered by this many threads; connections past what they
+// and the queue can hold are turned away with 503
+const WORKERS: usize = 4;
+const QUEUED_CONNECTIONS: usize = 16;
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+const MAX_SEARCH_LIMIT: u32 = 100;
+
+struct Server {
+    port: u16,
+    shutdown: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// The running endpoint, if `enable_local_api` is on.
+#[derive(Default)]
+pub struct LocalApi {
+    server: Mutex<Option<Server>>,
+}
+
+/// Starts or stops the endpoint to match the settings, restarting it when
+/// the port changed.
+pub fn sync(app: &AppHandle) {
+    let settings = app.state::<SettingsStore>().get();
+    let local_api = app.state::<LocalApi>();
+    let mut server = local_api.server.lock().unwrap_or_else(|e| e.into_inner());
+    let wanted = settings.enable_local_api.then_some(settings.local_api_port);
+    if server.as_ref().map(|server| server.port) == wanted {
+        return;
+    }
+
+    if let Some(running) = server.take() {
+        shut_down(running);
+    }
+    if let Some(port) = wanted {
+        *server = Some(start(app.clone(), port));
+    }
+}
+
+/// Stops the endpoint, e.g. on exit, waiting for the accept loop to end.
+pub fn stop(app: &AppHandle) {
+    let server = app
+        .state::<LocalApi>()
+        .server
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    if let Some(server) = server {
+        shut_down(server);
+    }
+}
+
+/// Restarts a running endpoint so it picks up a new token.
+pub fn restart(app: &AppHandle) {
+    stop(app);
+    sync(app);
+}
+
+fn shut_down(server: Server) {
+    server.shutdown.store(true, Ordering::Relaxed);
+    let _ = server.thread.join();
+    log::info!("Local API on port {} stopped", server.port);
+}
+
+fn start(app: AppHandle, port: u16) -> Server {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let stopping = shutdown.clone();
+    let thread = thread::spawn(move || {
+        // The keyring can prompt to unlock, so this waits here rather than
+        // holding up whoever started the server
+        let token = match token() {
+            Ok(token) => token,
+            Err(e) => {
+                log::warn!("Local API not started: {}", e);
+                return;
+            }
+        };
+        let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::warn!("Local API could not listen on port {}: {}", port, e);
+                return;
+            }
+        };
+        if let Err(e) = listener.set_nonblocking(true) {
+            log::warn!("Local API not started: {}", e);
+            return;
+        }
+        log::info!("Local API listening on 127.0.0.1:{}", port);
+
+        let token = Arc::new(token);
+        let (queue, connections) = mpsc::sync_channel(QUEUED_CONNECTIONS);
+        let connections = Arc::new(Mutex::new(connections));
+        let workers: Vec<JoinHandle<()>> = (0..WORKERS)
+            .map(|_| {
+                let app = app.clone();
+                let token = token.clone();
+                let connections = connections.clone();
+                thread::spawn(move || work(&app, &connections, &token))
+            })
+            .collect();
+        while !stopping.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => match queue.try_send(stream) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(mut stream)) => {
+                        let _ = stream.set_nonblocking(false);
+                        let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                        write_response(&mut stream, 503, &json!({ "error": "busy" }));
+                    }
+                    Err(TrySendError::Disconnected(_)) => break,
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(POLL_INTERVAL)
+                }
+                Err(e) => {
+                    log::warn!("Local API accept failed: {}", e);
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+        // Workers finish what is queued, then see the queue close
+        drop(queue);
+        for worker in workers {
+            let _ = worker.join();
+        }
+    });
+    Server {
+        port,
+        shutdown,
+        thread,
+    }
+}
+
+/// The bearer token requests need, generated and stored in the keyring
+/// the first time.
+pub fn token() -> Result<String, String> {
+    match secrets::get(TOKEN_KEY)? {
+        Some(token) if !token.is_empty() => Ok(token),
+        _ => regenerate_token(),
+    }
+}
+
+/// Replaces the token, so anything holding the old one is locked out.
+pub fn regenerate_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    let token = hex::encode(bytes);
+    secrets::set(TOKEN_KEY, &token)?;
+    Ok(token)
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    authorization: Option<String>,
+}
+
+// One worker: answers queued connections until the queue closes
+fn work(app: &AppHandle, connections: &Mutex<Receiver<TcpStream>>, token: &str) {
+    loop {
+        let next = connections.lock().unwrap_or_else(|e| e.into_inner()).recv();
+        match next {
+            Ok(stream) => handle(app, stream, token),
+            Err(_) => return,
+        }
+    }
+}
+
+fn handle(app: &AppHandle, mut stream: TcpStream, token: &str) {
+    // Accepted sockets inherit non-blocking mode on some platforms
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+
+    let (status, body) = match read_request(&stream) {
+        Ok(request) => respond(app, request, token),
+        Err(e) => (400, json!({ "error": e })),
+    };
+    write_response(&mut stream, status, &body);
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, body: &Value) {
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        reason,
+        body.len()
+    );
+    if status == 401 {
+        response.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream.take(MAX_HEADER_BYTES as u64));
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("Malformed request line".to_string());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).map_err(|e| e.to_string())? == 0 {
+            return Err("Request headers are too long or cut off".to_string());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter_map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Some((percent_decode(key)?, percent_decode(value)?))
+            })
+            .collect(),
+        authorization,
+    })
+}
+
+fn respond(app: &AppHandle, request: Request, token: &str) -> (u16, Value) {
+    let presented = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(presented.trim().as_bytes(), token.as_bytes()) {
+        return (401, json!({ "error": "Missing or invalid bearer token" }));
+    }
+    if request.method != "GET" {
+        return (405, json!({ "error": "Only GET is supported" }));
+    }
+
+    let result = tauri::async_runtime::block_on(async {
+        match request.path.as_str() {
+            "/health" => health(app).map(Some),
+            "/stats" => stats(app, &request.query).await.map(Some),
+            "/search" => search(app, &request.query).await.map(Some),
+            _ => Ok(None),
+        }
+    });
+    match result {
+        Ok(Some(body)) => (200, body),
+        Ok(None) => (404, json!({ "error": "Unknown route" })),
+        Err(AppError::Validation(message)) => (400, json!({ "error": message })),
+        Err(e) => {
+            log::warn!("Local API request failed: {}", e.message());
+            (500, json!({ "error": e.message() }))
+        }
+    }
+}
+
+fn health(app: &AppHandle) -> CmdResult<Value> {
+    Ok(json!({
+        "status": "ok",
+        "app_version": app.package_info().version.to_string(),
+        "api_version": api::API_VERSION,
+    }))
+}
+
+async fn stats(app: &AppHandle, query: &HashMap<String, String>) -> CmdResult<Value> {
+    let include_archived = match query.get("include_archived").map(String::as_str) {
+        None | Some("false") => false,
+        Some("true") => true,
+        Some(_) => {
+            return Err(AppError::Validation(
+                "include_archived must be true or false".to_string(),
+            ))
+        }
+    };
+    let stats = archive_meta::archive_stats(app.clone(), Some(include_archived)).await?;
+    let size = maintenance::check_archive_size(app.clone(), app.state()).await?;
+
+    Ok(json!({
+        "counts": {
+            "documents": stats.documents,
+            "categories": stats.categories,
+            "tags": stats.tags,
+            "attachments": stats.attachments,
+        },
+        "unread": stats.unread,
+        "needs_review": stats.needs_review,
+        "total_bytes": size.total_bytes,
+        "limit_bytes": size.limit_bytes,
+    }))
+}
+
+async fn search(app: &AppHandle, query: &HashMap<String, String>) -> CmdResult<Value> {
+    let text = query
+        .get("q")
+        .filter(|text| !text.trim().is_empty())
+        .ok_or_else(|| AppError::Validation("Missing query parameter q".to_string()))?;
+    let limit = match query.get("limit") {
+        Some(limit) => limit
+            .parse::<u32>()
+            .map_err(|_| AppError::Validation("Invalid limit".to_string()))?,
+        None => DEFAULT_SEARCH_LIMIT,
+    }
+    .clamp(1, MAX_SEARCH_LIMIT);
+
+    let documents = search::search_documents(
+        app.clone(),
+        app.state(),
+        text.clone(),
+        Some(limit),
+        None,
+        None,
+    )
+    .await?;
+    // Bodies stay in the app; scripts get enough to show and link a hit
+    let hits: Vec<Value> = documents
+        .into_iter()
+        .map(|document| {
+            json!({
+                "id": document.id,
+                "title": document.title,
+                "description": document.description,
+                "category_id": document.category_id,
+                "updated_at": document.updated_at,
+            })
+        })
+        .collect();
+    Ok(json!({ "documents": hits }))
+}
+
+// `+` is a space in query strings; `None` for invalid escapes or UTF-8
+pub(crate) fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = text.get(index + 1..index + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                index += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// So response timing doesn't give the token away byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
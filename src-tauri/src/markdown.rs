@@ -0,0 +1,1040 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::html;
+use crate::sanitize::{self, SanitizeOptions};
+
+// CommonMark's block and inline structure, minus raw HTML, which is shown
+// as text. Output always goes through the sanitizer, so previews, copies
+// and PDF exports render the same markup.
+
+/// Extensions on top of CommonMark, shared by previews and exports.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownOptions {
+    /// Pipe tables with a `| --- |` delimiter row under the header.
+    pub tables: bool,
+    /// `[^label]` references to `[^label]: text` definitions, numbered in
+    /// order of first use and listed at the end.
+    pub footnotes: bool,
+    /// `- [ ]` and `- [x]` list items, shown as ☐ and ☑.
+    pub task_lists: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            task_lists: true,
+        }
+    }
+}
+
+/// Renders Markdown to sanitized HTML.
+pub fn to_html(text: &str, options: MarkdownOptions) -> String {
+    let lines: Vec<String> = text.lines().map(expand_indent).collect();
+    let mut renderer = Renderer {
+        options,
+        links: HashMap::new(),
+        footnotes: HashMap::new(),
+        footnote_order: Vec::new(),
+    };
+    let lines = renderer.collect_definitions(lines);
+    let mut output = renderer.blocks(&lines, false);
+
+    if !renderer.footnote_order.is_empty() {
+        output.push_str("<hr>\n<ol>\n");
+        // Footnotes can reference further footnotes, which get appended
+        let mut index = 0;
+        while index < renderer.footnote_order.len() {
+            let label = renderer.footnote_order[index].clone();
+            let lines = renderer.footnotes.get(&label).cloned().unwrap_or_default();
+            let content = renderer.blocks(&lines, true);
+            output.push_str(&format!("<li>{}</li>\n", content.trim_end()));
+            index += 1;
+        }
+        output.push_str("</ol>\n");
+    }
+
+    sanitize::sanitize(&output, SanitizeOptions::default())
+}
+
+/// A document body as HTML. Bodies from the editor are HTML already and
+/// come back as they are; bodies that don't start with a tag, e.g. ones
+/// converted to Markdown by `sanitize_document`, are rendered.
+pub fn body_html(body: &str, options: MarkdownOptions) -> Cow<'_, str> {
+    let start = body.trim_start();
+    if start.is_empty() || start.starts_with('<') {
+        Cow::Borrowed(body)
+    } else {
+        Cow::Owned(to_html(body, options))
+    }
+}
+
+struct Renderer {
+    options: MarkdownOptions,
+    /// Reference link definitions by lowercase label: destination, title.
+    links: HashMap<String, (String, Option<String>)>,
+    /// Footnote definitions by lowercase label.
+    footnotes: HashMap<String, Vec<String>>,
+    /// Labels in the order they were first referenced.
+    footnote_order: Vec<String>,
+}
+
+struct ListMarker {
+    ordered: bool,
+    /// The bullet, or the `.`/`)` after the number.
+    delimiter: char,
+    start: u64,
+    /// Where the item's content starts, in columns.
+    content_offset: usize,
+}
+
+impl Renderer {
+    // Takes link reference and footnote definitions out of the top-level
+    // lines, leaving everything else where it was
+    fn collect_definitions(&mut self, lines: Vec<String>) -> Vec<String> {
+        let mut kept = Vec::with_capacity(lines.len());
+        let mut fence: Option<(char, usize)> = None;
+        let mut iter = lines.into_iter().peekable();
+        while let Some(line) = iter.next() {
+            let trimmed = line.trim_start();
+            if let Some((c, len)) = fence {
+                if closes_fence(trimmed, c, len) {
+                    fence = None;
+                }
+                kept.push(line);
+                continue;
+            }
+            if indent_of(&line) < 4 {
+                if let Some(open) = fence_start(trimmed) {
+                    fence = Some(open);
+                    kept.push(line);
+                    continue;
+                }
+            }
+            if indent_of(&line) >= 4 || !trimmed.starts_with('[') {
+                kept.push(line);
+                continue;
+            }
+
+            if self.options.footnotes {
+                if let Some((label, first)) = footnote_definition(trimmed) {
+                    let mut content = vec![first.to_string()];
+                    // Indented lines, blank lines between them included,
+                    // continue the definition
+                    while let Some(next) = iter.peek() {
+                        if next.trim().is_empty() {
+                            content.push(String::new());
+                        } else if indent_of(next) >= 4 {
+                            content.push(next[4..].to_string());
+                        } else {
+                            break;
+                        }
+                        iter.next();
+                    }
+                    while content.last().is_some_and(|line| line.is_empty()) {
+                        content.pop();
+                    }
+                    self.footnotes.entry(label).or_insert(content);
+                    continue;
+                }
+            }
+            if let Some((label, destination, title)) = link_definition(trimmed) {
+                self.links.entry(label).or_insert((destination, title));
+                continue;
+            }
+            kept.push(line);
+        }
+        kept
+    }
+
+    // Renders block structure; `tight` leaves paragraphs without `<p>`, as
+    // in lists without blank lines between items
+    fn blocks(&mut self, lines: &[String], tight: bool) -> String {
+        let mut output = String::new();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = &lines[i];
+            if line.trim().is_empty() {
+                i += 1;
+                continue;
+            }
+            let trimmed = line.trim_start();
+
+            if indent_of(line) >= 4 {
+                let mut end = i;
+                while end < lines.len()
+                    && (lines[end].trim().is_empty() || indent_of(&lines[end]) >= 4)
+                {
+                    end += 1;
+                }
+                while lines[end - 1].trim().is_empty() {
+                    end -= 1;
+                }
+                let code: Vec<&str> = lines[i..end]
+                    .iter()
+                    .map(|line| line.get(4..).unwrap_or_default())
+                    .collect();
+                output.push_str(&format!(
+                    "<pre><code>{}\n</code></pre>\n",
+                    html::escape(&code.join("\n"))
+                ));
+                i = end;
+                continue;
+            }
+
+            if let Some((c, len)) = fence_start(trimmed) {
+                let indent = indent_of(line);
+                let mut end = i + 1;
+                while end < lines.len() && !closes_fence(lines[end].trim_start(), c, len) {
+                    end += 1;
+                }
+                let code: Vec<&str> = lines[i + 1..end]
+                    .iter()
+                    .map(|line| &line[indent_of(line).min(indent)..])
+                    .collect();
+                let mut code = html::escape(&code.join("\n"));
+                if end > i + 1 {
+                    code.push('\n');
+                }
+                output.push_str(&format!("<pre><code>{}</code></pre>\n", code));
+                i = (end + 1).min(lines.len());
+                continue;
+            }
+
+            if let Some((level, text)) = atx_heading(trimmed) {
+                output.push_str(&format!("<h{0}>{1}</h{0}>\n", level, self.inline(text)));
+                i += 1;
+                continue;
+            }
+
+            if is_rule(trimmed) {
+                output.push_str("<hr>\n");
+                i += 1;
+                continue;
+            }
+
+            if trimmed.starts_with('>') {
+                let mut quoted = Vec::new();
+                while i < lines.len() && !lines[i].trim().is_empty() {
+                    let trimmed = lines[i].trim_start();
+                    if let Some(rest) = trimmed.strip_prefix('>') {
+                        quoted.push(rest.strip_prefix(' ').unwrap_or(rest).to_string());
+                    } else if starts_block(&lines[i]) {
+                        break;
+                    } else {
+                        // A lazy continuation of the quoted paragraph
+                        quoted.push(lines[i].clone());
+                    }
+                    i += 1;
+                }
+                output.push_str(&format!(
+                    "<blockquote>\n{}</blockquote>\n",
+                    self.blocks(&quoted, false)
+                ));
+                continue;
+            }
+
+            if list_marker(line).is_some() {
+                i = self.list(lines, i, &mut output);
+                continue;
+            }
+
+            if self.options.tables && i + 1 < lines.len() && trimmed.contains('|') {
+                if let Some(next) = self.table(lines, i, &mut output) {
+                    i = next;
+                    continue;
+                }
+            }
+
+            // A paragraph, up to a blank line or the start of another block
+            let mut end = i + 1;
+            let mut heading = None;
+            while end < lines.len() && !lines[end].trim().is_empty() {
+                if let Some(level) = setext_level(&lines[end]) {
+                    heading = Some(level);
+                    break;
+                }
+                if starts_block(&lines[end]) {
+                    break;
+                }
+                end += 1;
+            }
+            let text: Vec<&str> = lines[i..end].iter().map(|line| line.trim_start()).collect();
+            let text = self.inline(text.join("\n").trim_end());
+            match heading {
+                Some(level) => {
+                    output.push_str(&format!("<h{0}>{1}</h{0}>\n", level, text));
+                    end += 1;
+                }
+                None if tight => {
+                    output.push_str(&text);
+                    output.push('\n');
+                }
+                None => output.push_str(&format!("<p>{}</p>\n", text)),
+            }
+            i = end;
+        }
+        output
+    }
+
+    // Renders the list starting at `start`, returning the line after it
+    fn list(&mut self, lines: &[String], start: usize, output: &mut String) -> usize {
+        let first = list_marker(&lines[start]).expect("called on a list item");
+        let mut items: Vec<Vec<String>> = Vec::new();
+        let mut tight = true;
+        let mut i = start;
+
+        while i < lines.len() {
+            let Some(marker) = list_marker(&lines[i]) else {
+                break;
+            };
+            if marker.ordered != first.ordered
+                || marker.delimiter != first.delimiter
+                || indent_of(&lines[i]) >= first.content_offset
+            {
+                break;
+            }
+            let offset = marker.content_offset;
+            let mut item = vec![lines[i].get(offset..).unwrap_or_default().to_string()];
+            i += 1;
+
+            let mut after_blank = false;
+            while i < lines.len() {
+                let line = &lines[i];
+                if line.trim().is_empty() {
+                    item.push(String::new());
+                    after_blank = true;
+                } else if indent_of(line) >= offset {
+                    if after_blank {
+                        tight = false;
+                    }
+                    item.push(line[offset..].to_string());
+                    after_blank = false;
+                } else if after_blank || starts_block(line) || list_marker(line).is_some() {
+                    break;
+                } else {
+                    // A lazy continuation of the item's paragraph
+                    item.push(line.trim_start().to_string());
+                }
+                i += 1;
+            }
+
+            let mut trailing_blank = false;
+            while item.last().is_some_and(|line| line.is_empty()) {
+                item.pop();
+                trailing_blank = true;
+            }
+            let continues = lines
+                .get(i)
+                .and_then(|line| list_marker(line))
+                .is_some_and(|next| {
+                    next.ordered == first.ordered && next.delimiter == first.delimiter
+                });
+            if trailing_blank && continues {
+                tight = false;
+            }
+            items.push(item);
+        }
+
+        match (first.ordered, first.start) {
+            (true, 1) => output.push_str("<ol>\n"),
+            (true, start) => output.push_str(&format!("<ol start=\"{}\">\n", start)),
+            (false, _) => output.push_str("<ul>\n"),
+        }
+        for mut item in items {
+            if self.options.task_lists {
+                if let Some(first_line) = item.first_mut() {
+                    if let Some(checkbox) = task_checkbox(first_line) {
+                        *first_line = format!("{} {}", checkbox, first_line[3..].trim_start());
+                    }
+                }
+            }
+            let content = self.blocks(&item, tight);
+            output.push_str(&format!("<li>{}</li>\n", content.trim_end()));
+        }
+        output.push_str(if first.ordered { "</ol>\n" } else { "</ul>\n" });
+
+        i
+    }
+
+    // Renders a pipe table if one starts at `start`, returning the line
+    // after it
+    fn table(&mut self, lines: &[String], start: usize, output: &mut String) -> Option<usize> {
+        let header = table_cells(&lines[start]);
+        let alignments = table_alignments(&lines[start + 1])?;
+        if header.len() != alignments.len() {
+            return None;
+        }
+
+        let cell = |renderer: &mut Self, tag: &str, text: &str, alignment: Option<&str>| {
+            let align = alignment.map_or(String::new(), |align| format!(" align=\"{}\"", align));
+            format!("<{0}{1}>{2}</{0}>", tag, align, renderer.inline(text))
+        };
+
+        output.push_str("<table>\n<thead>\n<tr>");
+        for (text, alignment) in header.iter().zip(&alignments) {
+            output.push_str(&cell(self, "th", text, *alignment));
+        }
+        output.push_str("</tr>\n</thead>\n");
+
+        let mut i = start + 2;
+        let mut body = String::new();
+        while i < lines.len() && !lines[i].trim().is_empty() && !starts_block(&lines[i]) {
+            let cells = table_cells(&lines[i]);
+            body.push_str("<tr>");
+            for (index, alignment) in alignments.iter().enumerate() {
+                let text = cells.get(index).map_or("", String::as_str);
+                body.push_str(&cell(self, "td", text, *alignment));
+            }
+            body.push_str("</tr>\n");
+            i += 1;
+        }
+        if !body.is_empty() {
+            output.push_str(&format!("<tbody>\n{}</tbody>\n", body));
+        }
+        output.push_str("</table>\n");
+
+        Some(i)
+    }
+
+    fn inline(&mut self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        self.inline_chars(&chars)
+    }
+
+    fn inline_chars(&mut self, chars: &[char]) -> String {
+        let mut output = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                    push_escaped(&mut output, chars[i + 1]);
+                    i += 2;
+                }
+                '\\' if chars.get(i + 1) == Some(&'\n') => {
+                    output.push_str("<br>\n");
+                    i += 2;
+                }
+                '`' => {
+                    let run = run_length(chars, i, '`');
+                    match find_backtick_run(chars, i + run, run) {
+                        Some(end) => {
+                            let code: String = chars[i + run..end].iter().collect();
+                            let code = code.replace('\n', " ");
+                            // One space on both sides lets code start or end
+                            // with a backtick
+                            let code =
+                                match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                                    Some(inner) if !inner.trim().is_empty() => inner,
+                                    _ => &code,
+                                };
+                            output.push_str(&format!("<code>{}</code>", html::escape(code)));
+                            i = end + run;
+                        }
+                        None => {
+                            output.extend(std::iter::repeat('`').take(run));
+                            i += run;
+                        }
+                    }
+                }
+                '!' if chars.get(i + 1) == Some(&'[') => match self.link_at(chars, i + 1) {
+                    Some(link) => {
+                        let alt: String = chars[link.text.0..link.text.1].iter().collect();
+                        output.push_str(&format!(
+                            "<img src=\"{}\" alt=\"{}\"{}>",
+                            html::escape(&link.destination),
+                            html::escape(&alt),
+                            title_attribute(&link.title)
+                        ));
+                        i = link.next;
+                    }
+                    None => {
+                        output.push('!');
+                        i += 1;
+                    }
+                },
+                '[' => {
+                    if let Some((number, next)) = self.footnote_reference(chars, i) {
+                        output.push_str(&format!("<sup>{}</sup>", number));
+                        i = next;
+                    } else if let Some(link) = self.link_at(chars, i) {
+                        let text = self.inline_chars(&chars[link.text.0..link.text.1]);
+                        output.push_str(&format!(
+                            "<a href=\"{}\"{}>{}</a>",
+                            html::escape(&link.destination),
+                            title_attribute(&link.title),
+                            text
+                        ));
+                        i = link.next;
+                    } else {
+                        output.push('[');
+                        i += 1;
+                    }
+                }
+                '<' => match autolink(chars, i) {
+                    Some((href, text, next)) => {
+                        output.push_str(&format!(
+                            "<a href=\"{}\">{}</a>",
+                            html::escape(&href),
+                            html::escape(&text)
+                        ));
+                        i = next;
+                    }
+                    None => {
+                        output.push_str("&lt;");
+                        i += 1;
+                    }
+                },
+                '*' | '_' | '~' => match emphasis_close(chars, i) {
+                    Some((run, close)) => {
+                        let inner = self.inline_chars(&chars[i + run..close]);
+                        let html = match (c, run) {
+                            ('~', _) => format!("<del>{}</del>", inner),
+                            (_, 1) => format!("<em>{}</em>", inner),
+                            (_, 2) => format!("<strong>{}</strong>", inner),
+                            _ => format!("<em><strong>{}</strong></em>", inner),
+                        };
+                        output.push_str(&html);
+                        i = close + run;
+                    }
+                    None => {
+                        let run = run_length(chars, i, c);
+                        output.extend(std::iter::repeat(c).take(run));
+                        i += run;
+                    }
+                },
+                '\n' => {
+                    // Two trailing spaces make a hard break
+                    let hard = output.ends_with("  ");
+                    output.truncate(output.trim_end_matches(' ').len());
+                    output.push_str(if hard { "<br>\n" } else { "\n" });
+                    i += 1;
+                }
+                c => {
+                    push_escaped(&mut output, c);
+                    i += 1;
+                }
+            }
+        }
+        output
+    }
+
+    // The number of a `[^label]` reference to a defined footnote
+    fn footnote_reference(&mut self, chars: &[char], open: usize) -> Option<(usize, usize)> {
+        if !self.options.footnotes || chars.get(open + 1) != Some(&'^') {
+            return None;
+        }
+        let close =
+            (open + 2..chars.len()).find(|&j| matches!(chars[j], ']' | '[' | ' ' | '\n'))?;
+        if chars[close] != ']' || close == open + 2 {
+            return None;
+        }
+        let label: String = chars[open + 2..close]
+            .iter()
+            .collect::<String>()
+            .to_lowercase();
+        if !self.footnotes.contains_key(&label) {
+            return None;
+        }
+        let number = match self.footnote_order.iter().position(|l| *l == label) {
+            Some(index) => index + 1,
+            None => {
+                self.footnote_order.push(label);
+                self.footnote_order.len()
+            }
+        };
+        Some((number, close + 1))
+    }
+
+    // An inline `[text](destination "title")` or reference link starting
+    // at the `[` at `open`
+    fn link_at(&self, chars: &[char], open: usize) -> Option<Link> {
+        let close = matching_bracket(chars, open)?;
+        let text = (open + 1, close);
+
+        if chars.get(close + 1) == Some(&'(') {
+            let mut j = skip_spaces(chars, close + 2);
+            let destination = if chars.get(j) == Some(&'<') {
+                let end = (j + 1..chars.len()).find(|&k| matches!(chars[k], '>' | '\n'))?;
+                if chars[end] != '>' {
+                    return None;
+                }
+                let destination = chars[j + 1..end].iter().collect();
+                j = end + 1;
+                destination
+            } else {
+                let begin = j;
+                let mut depth = 0;
+                while j < chars.len() && !chars[j].is_whitespace() {
+                    match chars[j] {
+                        '\\' => j += 1,
+                        '(' => depth += 1,
+                        ')' if depth == 0 => break,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                unescape(&chars[begin..j.min(chars.len())])
+            };
+
+            j = skip_spaces(chars, j);
+            let title = match chars.get(j) {
+                Some(&quote @ ('"' | '\'' | '(')) => {
+                    let closing = if quote == '(' { ')' } else { quote };
+                    let end = (j + 1..chars.len())
+                        .find(|&k| chars[k] == closing && chars[k - 1] != '\\')?;
+                    let title = unescape(&chars[j + 1..end]);
+                    j = skip_spaces(chars, end + 1);
+                    Some(title)
+                }
+                _ => None,
+            };
+            if chars.get(j) != Some(&')') {
+                return None;
+            }
+            return Some(Link {
+                text,
+                destination,
+                title,
+                next: j + 1,
+            });
+        }
+
+        // `[text][label]`, `[label][]` or `[label]`
+        let (label, next) = if chars.get(close + 1) == Some(&'[') {
+            let end = (close + 2..chars.len()).find(|&k| chars[k] == ']')?;
+            if end == close + 2 {
+                (&chars[text.0..text.1], end + 1)
+            } else {
+                (&chars[close + 2..end], end + 1)
+            }
+        } else {
+            (&chars[text.0..text.1], close + 1)
+        };
+        let (destination, title) = self.links.get(&normalize_label(label))?.clone();
+        Some(Link {
+            text,
+            destination,
+            title,
+            next,
+        })
+    }
+}
+
+struct Link {
+    /// Range of the link text.
+    text: (usize, usize),
+    destination: String,
+    title: Option<String>,
+    /// Index right after the link.
+    next: usize,
+}
+
+fn push_escaped(output: &mut String, c: char) {
+    match c {
+        '&' => output.push_str("&amp;"),
+        '<' => output.push_str("&lt;"),
+        '>' => output.push_str("&gt;"),
+        '"' => output.push_str("&quot;"),
+        c => output.push(c),
+    }
+}
+
+fn title_attribute(title: &Option<String>) -> String {
+    title.as_ref().map_or(String::new(), |title| {
+        format!(" title=\"{}\"", html::escape(title))
+    })
+}
+
+// Leading tabs count as four columns
+fn expand_indent(line: &str) -> String {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let mut columns = 0;
+    for c in line[..line.len() - rest.len()].chars() {
+        columns = if c == '\t' {
+            columns + 4 - columns % 4
+        } else {
+            columns + 1
+        };
+    }
+    format!("{}{}", " ".repeat(columns), rest)
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn run_length(chars: &[char], start: usize, c: char) -> usize {
+    chars[start..].iter().take_while(|&&x| x == c).count()
+}
+
+fn skip_spaces(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| *c == ' ' || *c == '\n') {
+        i += 1;
+    }
+    i
+}
+
+fn unescape(chars: &[char]) -> String {
+    let mut text = String::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' && chars.get(i + 1).is_some_and(char::is_ascii_punctuation) {
+            i += 1;
+        }
+        text.push(chars[i]);
+        i += 1;
+    }
+    text
+}
+
+// The start of a closing run of exactly `run` backticks
+fn find_backtick_run(chars: &[char], from: usize, run: usize) -> Option<usize> {
+    let mut j = from;
+    while j < chars.len() {
+        if chars[j] == '`' {
+            let length = run_length(chars, j, '`');
+            if length == run {
+                return Some(j);
+            }
+            j += length;
+        } else {
+            j += 1;
+        }
+    }
+    None
+}
+
+// The `]` closing the bracket at `open`, skipping escapes and code spans
+fn matching_bracket(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut j = open;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 1,
+            '`' => {
+                let run = run_length(chars, j, '`');
+                if let Some(end) = find_backtick_run(chars, j + run, run) {
+                    j = end + run;
+                    continue;
+                }
+                j += run - 1;
+            }
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(j);
+                }
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+// Run length and start of the closing run of emphasis opened at `open`.
+// Openers must be followed by a non-space, closers preceded by one, and
+// `_` never opens or closes inside a word
+fn emphasis_close(chars: &[char], open: usize) -> Option<(usize, usize)> {
+    let c = chars[open];
+    let run = run_length(chars, open, c);
+    if run > 3 || (c == '~' && run != 2) {
+        return None;
+    }
+    let after = *chars.get(open + run)?;
+    if after.is_whitespace() || (c == '_' && open > 0 && chars[open - 1].is_alphanumeric()) {
+        return None;
+    }
+
+    let mut j = open + run;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 2,
+            '`' => {
+                let length = run_length(chars, j, '`');
+                j = find_backtick_run(chars, j + length, length)
+                    .map_or(j + length, |end| end + length);
+            }
+            x if x == c => {
+                let length = run_length(chars, j, c);
+                let can_close = !chars[j - 1].is_whitespace()
+                    && (c != '_' || !chars.get(j + length).is_some_and(|n| n.is_alphanumeric()));
+                if can_close && length >= run {
+                    return Some((run, j));
+                }
+                // A nested opener: skip to past its closer
+                match emphasis_close(chars, j) {
+                    Some((nested, close)) => j = close + nested,
+                    None => j += length,
+                }
+            }
+            _ => j += 1,
+        }
+    }
+    None
+}
+
+// `<https://…>` or `<name@example.com>`
+fn autolink(chars: &[char], open: usize) -> Option<(String, String, usize)> {
+    let close = (open + 1..chars.len()).find(|&j| matches!(chars[j], '>' | '<' | ' ' | '\n'))?;
+    if chars[close] != '>' {
+        return None;
+    }
+    let inner: String = chars[open + 1..close].iter().collect();
+    let scheme = inner.split_once(':').map(|(scheme, _)| scheme);
+    let is_url = scheme.is_some_and(|scheme| {
+        scheme.len() >= 2
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    });
+    if is_url {
+        return Some((inner.clone(), inner, close + 1));
+    }
+    let is_email = inner
+        .split_once('@')
+        .is_some_and(|(name, domain)| !name.is_empty() && domain.contains('.'));
+    if is_email {
+        return Some((format!("mailto:{}", inner), inner, close + 1));
+    }
+    None
+}
+
+// Heading level and text of `# Heading #`
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let text = rest.trim();
+    // A closing run of `#` only counts after a space
+    let without_closing = text.trim_end_matches('#');
+    let text = if without_closing.is_empty() || without_closing.ends_with(' ') {
+        without_closing.trim_end()
+    } else {
+        text
+    };
+    Some((level, text))
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && matches!(marks[0], '-' | '*' | '_') && marks.iter().all(|c| *c == marks[0])
+}
+
+// `===` or `---` under a paragraph line
+fn setext_level(line: &str) -> Option<usize> {
+    if indent_of(line) >= 4 {
+        return None;
+    }
+    let underline = line.trim();
+    if !underline.is_empty() && underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if !underline.is_empty() && underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// Fence character and length of a code fence opening
+fn fence_start(line: &str) -> Option<(char, usize)> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.chars().take_while(|x| *x == c).count();
+    if length < 3 || (c == '`' && line[length..].contains('`')) {
+        return None;
+    }
+    Some((c, length))
+}
+
+fn closes_fence(line: &str, c: char, length: usize) -> bool {
+    let run = line.chars().take_while(|x| *x == c).count();
+    run >= length && line[run..].trim().is_empty()
+}
+
+fn list_marker(line: &str) -> Option<ListMarker> {
+    let indent = indent_of(line);
+    if indent >= 4 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let (ordered, delimiter, start, marker_len) = match rest.chars().next()? {
+        c @ ('-' | '*' | '+') => (false, c, 0, 1),
+        '0'..='9' => {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            let delimiter = rest[digits..].chars().next()?;
+            if digits > 9 || !matches!(delimiter, '.' | ')') {
+                return None;
+            }
+            (true, delimiter, rest[..digits].parse().ok()?, digits + 1)
+        }
+        _ => return None,
+    };
+
+    let after = &rest[marker_len..];
+    if after.trim().is_empty() {
+        return Some(ListMarker {
+            ordered,
+            delimiter,
+            start,
+            content_offset: indent + marker_len + 1,
+        });
+    }
+    let spaces = after.len() - after.trim_start_matches(' ').len();
+    if spaces == 0 {
+        return None;
+    }
+    // More than four spaces start indented code inside the item
+    let spaces = if spaces > 4 { 1 } else { spaces };
+    Some(ListMarker {
+        ordered,
+        delimiter,
+        start,
+        content_offset: indent + marker_len + spaces,
+    })
+}
+
+// Whether `line` starts a block that ends a paragraph before it
+fn starts_block(line: &str) -> bool {
+    if indent_of(line) >= 4 {
+        return false;
+    }
+    let trimmed = line.trim_start();
+    atx_heading(trimmed).is_some()
+        || fence_start(trimmed).is_some()
+        || is_rule(trimmed)
+        || trimmed.starts_with('>')
+        || list_marker(line).is_some_and(|marker| {
+            // Only lists starting at 1 interrupt, so a number at the start
+            // of a wrapped line stays text
+            !line[marker.content_offset.min(line.len())..]
+                .trim()
+                .is_empty()
+                && (!marker.ordered || marker.start == 1)
+        })
+}
+
+fn task_checkbox(line: &str) -> Option<char> {
+    let checkbox = match line.get(..3)? {
+        "[ ]" => '☐',
+        "[x]" | "[X]" => '☑',
+        _ => return None,
+    };
+    line[3..].starts_with(' ').then_some(checkbox)
+}
+
+fn table_cells(line: &str) -> Vec<String> {
+    let row = line.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = match row.strip_suffix('|') {
+        Some(inner) if !inner.ends_with('\\') => inner,
+        _ => row,
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+// Alignment of each column from a `| :-- | :-: | --: |` row
+fn table_alignments(line: &str) -> Option<Vec<Option<&'static str>>> {
+    if !line.contains('-') {
+        return None;
+    }
+    table_cells(line)
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Some("center"),
+                (true, false) => Some("left"),
+                (false, true) => Some("right"),
+                (false, false) => None,
+            })
+        })
+        .collect()
+}
+
+// Label and first line of `[^label]: text`
+fn footnote_definition(line: &str) -> Option<(String, &str)> {
+    let rest = line.strip_prefix("[^")?;
+    let (label, rest) = rest.split_once("]:")?;
+    if label.is_empty() || label.contains([' ', '[', ']']) {
+        return None;
+    }
+    Some((label.to_lowercase(), rest.trim_start()))
+}
+
+// Label, destination and title of `[label]: destination "title"`
+fn link_definition(line: &str) -> Option<(String, String, Option<String>)> {
+    let rest = line.strip_prefix('[')?;
+    let (label, rest) = rest.split_once("]:")?;
+    if label.trim().is_empty() || label.starts_with('^') || label.contains(['[', ']']) {
+        return None;
+    }
+    let rest = rest.trim();
+    let (destination, title) = match rest.split_once(char::is_whitespace) {
+        Some((destination, title)) => (destination, Some(title.trim())),
+        None => (rest, None),
+    };
+    if destination.is_empty() {
+        return None;
+    }
+    let destination = destination
+        .strip_prefix('<')
+        .and_then(|d| d.strip_suffix('>'))
+        .unwrap_or(destination);
+    let title = match title {
+        None => None,
+        Some(title) => {
+            let quoted = ["\"\"", "''", "()"].iter().find_map(|pair| {
+                let mut pair = pair.chars();
+                let (open, close) = (pair.next()?, pair.next()?);
+                title.strip_prefix(open)?.strip_suffix(close)
+            });
+            // Anything else after the destination isn't a definition
+            Some(quoted?.to_string())
+        }
+    };
+    let label: Vec<char> = label.chars().collect();
+    Some((normalize_label(&label), destination.to_string(), title))
+}
+
+// Labels match case-insensitively with whitespace runs collapsed
+fn normalize_label(label: &[char]) -> String {
+    label
+        .iter()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
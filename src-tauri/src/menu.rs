@@ -1,19 +1,43 @@
+use std::collections::BTreeMap;
+
 use tauri::{menu::*, AppHandle, Emitter, Wry};
 
-pub fn create_app_menu(app: &AppHandle<Wry>) -> Result<Menu<Wry>, Box<dyn std::error::Error>> {
+// Menu items whose shortcut can be rebound through the `keybindings` setting
+pub const BINDABLE_ITEMS: &[&str] = &[
+    "new_document",
+    "search",
+    "new_category",
+    "manage_categories",
+    "export_archive",
+    "import_archive",
+    "undo",
+    "redo",
+    "cut",
+    "copy",
+    "paste",
+    "toggle_sidebar",
+    "reload",
+];
+
+pub fn create_app_menu(
+    app: &AppHandle<Wry>,
+    keybindings: &BTreeMap<String, String>,
+) -> Result<Menu<Wry>, Box<dyn std::error::Error>> {
+    let key = |id: &str, default: &str| keybindings.get(id).cloned().unwrap_or(default.to_string());
+
     // DOCUMENTS MENU
     let documents_menu = SubmenuBuilder::new(app, "Documents")
         .item(
             &MenuItemBuilder::new("New Document")
                 .id("new_document")
-                .accelerator("CmdOrCtrl+N")
+                .accelerator(key("new_document", "CmdOrCtrl+N"))
                 .build(app)?,
         )
         .separator()
         .item(
             &MenuItemBuilder::new("Search Documents")
                 .id("search")
-                .accelerator("CmdOrCtrl+F")
+                .accelerator(key("search", "CmdOrCtrl+F"))
                 .build(app)?,
         )
         .build()?;
@@ -23,13 +47,13 @@ pub fn create_app_menu(app: &AppHandle<Wry>) -> Result<Menu<Wry>, Box<dyn std::e
         .item(
             &MenuItemBuilder::new("New Category")
                 .id("new_category")
-                .accelerator("CmdOrCtrl+Shift+N")
+                .accelerator(key("new_category", "CmdOrCtrl+Shift+N"))
                 .build(app)?,
         )
         .item(
             &MenuItemBuilder::new("Manage Categories")
                 .id("manage_categories")
-                .accelerator("CmdOrCtrl+Shift+M")
+                .accelerator(key("manage_categories", "CmdOrCtrl+Shift+M"))
                 .build(app)?,
         )
         .build()?;
@@ -39,13 +63,18 @@ pub fn create_app_menu(app: &AppHandle<Wry>) -> Result<Menu<Wry>, Box<dyn std::e
         .item(
             &MenuItemBuilder::new("Export Archive")
                 .id("export_archive")
-                .accelerator("CmdOrCtrl+E")
+                .accelerator(key("export_archive", "CmdOrCtrl+E"))
                 .build(app)?,
         )
         .item(
             &MenuItemBuilder::new("Import Archive")
                 .id("import_archive")
-                .accelerator("CmdOrCtrl+I")
+                .accelerator(key("import_archive", "CmdOrCtrl+I"))
+                .build(app)?,
+        )
+        .item(
+            &MenuItemBuilder::new("Restore from Backup")
+                .id("restore_backup")
                 .build(app)?,
         )
         .separator()
@@ -58,32 +87,32 @@ pub fn create_app_menu(app: &AppHandle<Wry>) -> Result<Menu<Wry>, Box<dyn std::e
         .item(
             &MenuItemBuilder::new("Undo")
                 .id("undo")
-                .accelerator("CmdOrCtrl+Z")
+                .accelerator(key("undo", "CmdOrCtrl+Z"))
                 .build(app)?,
         )
         .item(
             &MenuItemBuilder::new("Redo")
                 .id("redo")
-                .accelerator("CmdOrCtrl+Shift+Z")
+                .accelerator(key("redo", "CmdOrCtrl+Shift+Z"))
                 .build(app)?,
         )
         .separator()
         .item(
             &MenuItemBuilder::new("Cut")
                 .id("cut")
-                .accelerator("CmdOrCtrl+X")
+                .accelerator(key("cut", "CmdOrCtrl+X"))
                 .build(app)?,
         )
         .item(
             &MenuItemBuilder::new("Copy")
                 .id("copy")
-                .accelerator("CmdOrCtrl+C")
+                .accelerator(key("copy", "CmdOrCtrl+C"))
                 .build(app)?,
         )
         .item(
             &MenuItemBuilder::new("Paste")
                 .id("paste")
-                .accelerator("CmdOrCtrl+V")
+                .accelerator(key("paste", "CmdOrCtrl+V"))
                 .build(app)?,
         )
         .build()?;
@@ -92,14 +121,14 @@ pub fn create_app_menu(app: &AppHandle<Wry>) -> Result<Menu<Wry>, Box<dyn std::e
         .item(
             &MenuItemBuilder::new("Toggle Sidebar")
                 .id("toggle_sidebar")
-                .accelerator("CmdOrCtrl+B")
+                .accelerator(key("toggle_sidebar", "CmdOrCtrl+B"))
                 .build(app)?,
         )
         .separator()
         .item(
             &MenuItemBuilder::new("Reload")
                 .id("reload")
-                .accelerator("CmdOrCtrl+R")
+                .accelerator(key("reload", "CmdOrCtrl+R"))
                 .build(app)?,
         )
         .build()?;
@@ -124,6 +153,13 @@ pub fn create_app_menu(app: &AppHandle<Wry>) -> Result<Menu<Wry>, Box<dyn std::e
     Ok(menu)
 }
 
+/// Rebuilds the menu, e.g. after the keybindings changed.
+pub fn apply(app: &AppHandle<Wry>, keybindings: &BTreeMap<String, String>) -> Result<(), String> {
+    let menu = create_app_menu(app, keybindings).map_err(|e| e.to_string())?;
+    app.set_menu(menu).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 pub fn handle_menu_event(app: &AppHandle<Wry>, event: &str) {
     match event {
         // Documents
@@ -149,6 +185,9 @@ pub fn handle_menu_event(app: &AppHandle<Wry>, event: &str) {
         "import_archive" => {
             app.emit("menu_import_archive", ()).unwrap();
         }
+        "restore_backup" => {
+            app.emit("menu_restore_backup", ()).unwrap();
+        }
         "settings" => {
             app.emit("menu_settings", ()).unwrap();
         }
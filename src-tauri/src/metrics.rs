@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::settings::SettingsStore;
+
+// Timings kept for `query_metrics`, oldest dropped first
+const CAPACITY: usize = 500;
+const SLOW_THRESHOLD: Duration = Duration::from_millis(200);
+
+/// Recent command timings, recorded while the `debug_metrics` setting is
+/// on. Kept in memory only.
+#[derive(Default)]
+pub struct QueryMetrics {
+    recorded: Mutex<Recorded>,
+}
+
+#[derive(Default)]
+struct Recorded {
+    recent: VecDeque<QueryTiming>,
+    // Since launch, not just what is still in `recent`
+    totals: BTreeMap<&'static str, CommandTotals>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct QueryTiming {
+    pub command: &'static str,
+    /// Unix milliseconds.
+    pub finished_at: i64,
+    pub duration_ms: f64,
+    /// Rows the command returned, to put the timing in context.
+    pub rows: usize,
+    pub slow: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct CommandTotals {
+    pub command: &'static str,
+    pub calls: u64,
+    pub slow_calls: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+    pub total_rows: u64,
+}
+
+#[derive(Serialize)]
+pub struct MetricsReport {
+    pub enabled: bool,
+    pub slow_threshold_ms: u64,
+    /// Newest first.
+    pub recent: Vec<QueryTiming>,
+    /// Slowest in total first.
+    pub commands: Vec<CommandTotals>,
+}
+
+impl QueryMetrics {
+    fn record(&self, timing: QueryTiming) {
+        let mut recorded = self.recorded.lock().unwrap();
+        let totals = recorded
+            .totals
+            .entry(timing.command)
+            .or_insert(CommandTotals {
+                command: timing.command,
+                calls: 0,
+                slow_calls: 0,
+                total_ms: 0.0,
+                max_ms: 0.0,
+                total_rows: 0,
+            });
+        totals.calls += 1;
+        totals.slow_calls += u64::from(timing.slow);
+        totals.total_ms += timing.duration_ms;
+        totals.max_ms = totals.max_ms.max(timing.duration_ms);
+        totals.total_rows += timing.rows as u64;
+
+        if recorded.recent.len() == CAPACITY {
+            recorded.recent.pop_front();
+        }
+        recorded.recent.push_back(timing);
+    }
+
+    pub fn report(&self, enabled: bool) -> MetricsReport {
+        let recorded = self.recorded.lock().unwrap();
+        let mut commands: Vec<CommandTotals> = recorded.totals.values().cloned().collect();
+        commands.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms));
+        MetricsReport {
+            enabled,
+            slow_threshold_ms: SLOW_THRESHOLD.as_millis() as u64,
+            recent: recorded.recent.iter().rev().cloned().collect(),
+            commands,
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.recorded.lock().unwrap() = Recorded::default();
+    }
+}
+
+/// Times one command call; started before its queries, finished with the
+/// number of rows it returns.
+pub struct Timer {
+    command: &'static str,
+    started: Instant,
+}
+
+impl Timer {
+    pub fn start(command: &'static str) -> Self {
+        Self {
+            command,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn finish(self, app: &AppHandle, rows: usize) {
+        if !app.state::<SettingsStore>().get().debug_metrics {
+            return;
+        }
+        let elapsed = self.started.elapsed();
+        app.state::<QueryMetrics>().record(QueryTiming {
+            command: self.command,
+            finished_at: Utc::now().timestamp_millis(),
+            duration_ms: elapsed.as_secs_f64() * 1000.0,
+            rows,
+            slow: elapsed >= SLOW_THRESHOLD,
+        });
+    }
+}
@@ -0,0 +1,1252 @@
+use tauri_plugin_sql::{Migration, MigrationKind};
+
+/// The version a database is at once every migration has run.
+pub fn schema_version() -> u32 {
+    migrations()
+        .iter()
+        .map(|migration| migration.version as u32)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The `CREATE TRIGGER` statement for `name` in the latest migration that
+/// creates it, for code that drops a trigger to put it back exactly as the
+/// migrations left it.
+pub fn trigger_sql(name: &str) -> Option<&'static str> {
+    let create = format!("CREATE TRIGGER IF NOT EXISTS {}", name);
+    migrations().iter().rev().find_map(|migration| {
+        let sql = migration.sql;
+        let start = sql.match_indices(&create).find_map(|(start, _)| {
+            // Not a longer name starting the same
+            let rest = &sql[start + create.len()..];
+            rest.starts_with(char::is_whitespace).then_some(start)
+        })?;
+        // Up to the line closing the trigger body
+        let statement = &sql[start..];
+        let mut end = 0;
+        for line in statement.split_inclusive('\n') {
+            end += line.len();
+            if line.trim() == "END;" {
+                return Some(statement[..end].trim_end().trim_end_matches(';'));
+            }
+        }
+        None
+    })
+}
+
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        // Mirrors the tables the frontend creates, so later migrations
+        // can alter them even on a fresh database
+        Migration {
+            version: 1,
+            description: "create_initial_tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS categories (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  name TEXT NOT NULL,
+                  icon TEXT DEFAULT 'folder',
+                  color TEXT DEFAULT '#6B7280',
+                  parent_id INTEGER DEFAULT NULL,
+                  description TEXT DEFAULT NULL,
+                  level INTEGER DEFAULT 0,
+                  sort_order INTEGER DEFAULT 0,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (parent_id) REFERENCES categories (id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS documents (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  title TEXT NOT NULL,
+                  description TEXT,
+                  text_content TEXT,
+                  category_id INTEGER,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (category_id) REFERENCES categories (id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS attachments (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  document_id INTEGER NOT NULL,
+                  filename TEXT NOT NULL,
+                  filepath TEXT NOT NULL,
+                  filetype TEXT NOT NULL,
+                  filesize INTEGER,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 2,
+            description: "add_attachment_sort_order",
+            sql: "ALTER TABLE attachments ADD COLUMN sort_order INTEGER DEFAULT 0;",
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 3,
+            description: "create_tags",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS tags (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS document_tags (
+                  document_id INTEGER NOT NULL,
+                  tag_id INTEGER NOT NULL,
+                  PRIMARY KEY (document_id, tag_id),
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE,
+                  FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_document_tags_tag ON document_tags (tag_id);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 4,
+            description: "index_attachments_by_document",
+            sql: "CREATE INDEX IF NOT EXISTS idx_attachments_document ON attachments (document_id, filetype);",
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 5,
+            description: "add_document_reminders",
+            sql: r#"
+                ALTER TABLE documents ADD COLUMN remind_at INTEGER;
+                ALTER TABLE documents ADD COLUMN reminder_fired INTEGER NOT NULL DEFAULT 0;
+                CREATE INDEX IF NOT EXISTS idx_documents_remind_at ON documents (remind_at) WHERE remind_at IS NOT NULL;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 6,
+            description: "create_audit_log",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS audit_log (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  occurred_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+                  operation TEXT NOT NULL,
+                  entity_type TEXT NOT NULL,
+                  entity_id INTEGER,
+                  details TEXT
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_audit_log_occurred ON audit_log (occurred_at);
+
+                -- Triggers run inside the statement's transaction, so the log follows
+                -- every write, whether it comes from the frontend or a command
+                CREATE TRIGGER IF NOT EXISTS audit_documents_insert AFTER INSERT ON documents
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('create', 'document', NEW.id, NEW.title);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_documents_update
+                AFTER UPDATE OF title, description, text_content, category_id ON documents
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('update', 'document', NEW.id, NEW.title);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_documents_delete AFTER DELETE ON documents
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('delete', 'document', OLD.id, OLD.title);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_categories_insert AFTER INSERT ON categories
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('create', 'category', NEW.id, NEW.name);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_categories_update
+                AFTER UPDATE OF name, icon, color, parent_id, description ON categories
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('update', 'category', NEW.id, NEW.name);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_categories_delete AFTER DELETE ON categories
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('delete', 'category', OLD.id, OLD.name);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_attachments_insert AFTER INSERT ON attachments
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('create', 'attachment', NEW.id, NEW.filename);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS audit_attachments_delete AFTER DELETE ON attachments
+                BEGIN
+                  INSERT INTO audit_log (operation, entity_type, entity_id, details)
+                  VALUES ('delete', 'attachment', OLD.id, OLD.filename);
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 7,
+            description: "create_document_versions",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS document_versions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  document_id INTEGER NOT NULL,
+                  title TEXT NOT NULL,
+                  description TEXT,
+                  text_content TEXT,
+                  created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_document_versions_document ON document_versions (document_id, id);
+
+                -- The newest version of a document is always its current content
+                INSERT INTO document_versions (document_id, title, description, text_content)
+                SELECT id, title, description, text_content FROM documents;
+
+                CREATE TRIGGER IF NOT EXISTS version_documents_insert AFTER INSERT ON documents
+                BEGIN
+                  INSERT INTO document_versions (document_id, title, description, text_content)
+                  VALUES (NEW.id, NEW.title, NEW.description, NEW.text_content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS version_documents_update
+                AFTER UPDATE OF title, description, text_content ON documents
+                BEGIN
+                  INSERT INTO document_versions (document_id, title, description, text_content)
+                  VALUES (NEW.id, NEW.title, NEW.description, NEW.text_content);
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 8,
+            description: "add_document_lang",
+            sql: "ALTER TABLE documents ADD COLUMN lang TEXT;",
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 9,
+            description: "add_attachment_phash",
+            sql: "ALTER TABLE attachments ADD COLUMN phash TEXT;",
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 10,
+            description: "create_archive_meta",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS archive_meta (
+                  id INTEGER PRIMARY KEY CHECK (id = 1),
+                  name TEXT NOT NULL,
+                  description TEXT,
+                  icon TEXT NOT NULL DEFAULT 'archive'
+                );
+
+                INSERT OR IGNORE INTO archive_meta (id, name) VALUES (1, 'Ando Archive');
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 11,
+            description: "create_smart_folders",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS smart_folders (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  name TEXT NOT NULL,
+                  filter TEXT NOT NULL DEFAULT '{}',
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 12,
+            description: "create_documents_fts",
+            sql: r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(
+                  title,
+                  description,
+                  text_content,
+                  content = 'documents',
+                  content_rowid = 'id',
+                  tokenize = 'unicode61'
+                );
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_ai AFTER INSERT ON documents BEGIN
+                  INSERT INTO documents_fts(rowid, title, description, text_content)
+                  VALUES (new.id, new.title, new.description, new.text_content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_ad AFTER DELETE ON documents BEGIN
+                  INSERT INTO documents_fts(documents_fts, rowid, title, description, text_content)
+                  VALUES ('delete', old.id, old.title, old.description, old.text_content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_au AFTER UPDATE OF title, description, text_content ON documents BEGIN
+                  INSERT INTO documents_fts(documents_fts, rowid, title, description, text_content)
+                  VALUES ('delete', old.id, old.title, old.description, old.text_content);
+                  INSERT INTO documents_fts(rowid, title, description, text_content)
+                  VALUES (new.id, new.title, new.description, new.text_content);
+                END;
+
+                INSERT INTO documents_fts(documents_fts) VALUES ('rebuild');
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 13,
+            description: "create_document_versions_fts",
+            sql: r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS document_versions_fts USING fts5(
+                  title,
+                  description,
+                  text_content,
+                  content = 'document_versions',
+                  content_rowid = 'id',
+                  tokenize = 'unicode61'
+                );
+
+                -- Versions are only ever added or pruned, never edited
+                CREATE TRIGGER IF NOT EXISTS document_versions_fts_ai AFTER INSERT ON document_versions BEGIN
+                  INSERT INTO document_versions_fts(rowid, title, description, text_content)
+                  VALUES (new.id, new.title, new.description, new.text_content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS document_versions_fts_ad AFTER DELETE ON document_versions BEGIN
+                  INSERT INTO document_versions_fts(document_versions_fts, rowid, title, description, text_content)
+                  VALUES ('delete', old.id, old.title, old.description, old.text_content);
+                END;
+
+                INSERT INTO document_versions_fts(document_versions_fts) VALUES ('rebuild');
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 14,
+            description: "add_document_summary",
+            sql: r#"
+                ALTER TABLE documents ADD COLUMN summary TEXT;
+                -- Body length the summary was computed from, to tell when it is stale
+                ALTER TABLE documents ADD COLUMN summary_source_length INTEGER;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 15,
+            description: "add_document_needs_review",
+            sql: r#"
+                ALTER TABLE documents ADD COLUMN needs_review INTEGER NOT NULL DEFAULT 0;
+                CREATE INDEX IF NOT EXISTS idx_documents_needs_review ON documents (needs_review);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 16,
+            description: "create_deleted_documents",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS deleted_documents (
+                  id INTEGER PRIMARY KEY,
+                  title TEXT NOT NULL,
+                  description TEXT,
+                  text_content TEXT,
+                  category_id INTEGER,
+                  created_at DATETIME,
+                  updated_at DATETIME,
+                  deleted_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 17,
+            description: "add_document_is_read",
+            sql: r#"
+                ALTER TABLE documents ADD COLUMN is_read INTEGER NOT NULL DEFAULT 0;
+                -- Everything already in the archive has been seen
+                UPDATE documents SET is_read = 1;
+                CREATE INDEX IF NOT EXISTS idx_documents_is_read ON documents (is_read);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 18,
+            description: "create_open_tabs",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS open_tabs (
+                  position INTEGER PRIMARY KEY,
+                  document_id INTEGER NOT NULL,
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 19,
+            description: "add_document_title_sort",
+            sql: r#"
+                -- Filled in by the app, which can fold case and accents; NULL means stale
+                ALTER TABLE documents ADD COLUMN title_sort TEXT;
+                CREATE INDEX IF NOT EXISTS idx_documents_title_sort ON documents (title_sort);
+                CREATE TRIGGER IF NOT EXISTS documents_title_sort_au
+                AFTER UPDATE OF title ON documents
+                BEGIN
+                  UPDATE documents SET title_sort = NULL WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 20,
+            description: "add_document_word_count",
+            sql: r#"
+                -- Filled in by the app like title_sort; NULL means stale
+                ALTER TABLE documents ADD COLUMN word_count INTEGER;
+                CREATE TRIGGER IF NOT EXISTS documents_word_count_au
+                AFTER UPDATE OF text_content ON documents
+                BEGIN
+                  UPDATE documents SET word_count = NULL WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 21,
+            description: "add_vault_keys",
+            sql: r#"
+                -- The archive's vault master key, only ever stored wrapped: by a key
+                -- derived from the master password and, once generated, by a recovery key
+                CREATE TABLE IF NOT EXISTS vault_keys (
+                  id INTEGER PRIMARY KEY CHECK (id = 1),
+                  kdf TEXT NOT NULL,
+                  master_key TEXT NOT NULL,
+                  recovery TEXT,
+                  recovery_created_at DATETIME
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 22,
+            description: "add_category_last_activity",
+            sql: r#"
+                ALTER TABLE categories ADD COLUMN last_activity_at DATETIME;
+                UPDATE categories SET last_activity_at =
+                  (SELECT MAX(d.updated_at) FROM documents d WHERE d.category_id = categories.id);
+                CREATE INDEX IF NOT EXISTS idx_categories_last_activity ON categories (last_activity_at);
+
+                -- In the same transaction as the document change; a move touches both
+                -- the old and the new category
+                CREATE TRIGGER IF NOT EXISTS categories_activity_ai
+                AFTER INSERT ON documents
+                WHEN NEW.category_id IS NOT NULL
+                BEGIN
+                  UPDATE categories SET last_activity_at = CURRENT_TIMESTAMP WHERE id = NEW.category_id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS categories_activity_au
+                AFTER UPDATE OF title, description, text_content, category_id ON documents
+                WHEN NEW.title IS NOT OLD.title
+                  OR NEW.description IS NOT OLD.description
+                  OR NEW.text_content IS NOT OLD.text_content
+                  OR NEW.category_id IS NOT OLD.category_id
+                BEGIN
+                  UPDATE categories SET last_activity_at = CURRENT_TIMESTAMP
+                  WHERE id IN (NEW.category_id, OLD.category_id);
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 23,
+            description: "create_category_rules",
+            sql: r#"
+                -- Trashed documents keep their tags and attachment rows, as JSON, so a
+                -- restore brings them back too
+                ALTER TABLE deleted_documents ADD COLUMN tags TEXT;
+                ALTER TABLE deleted_documents ADD COLUMN attachments TEXT;
+
+                -- One lifecycle rule per category, as JSON
+                CREATE TABLE IF NOT EXISTS category_rules (
+                  category_id INTEGER PRIMARY KEY,
+                  rule TEXT NOT NULL,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (category_id) REFERENCES categories (id) ON DELETE CASCADE
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 24,
+            description: "create_change_log",
+            sql: r#"
+                -- Every change to a document, category or attachment moves its row to
+                -- the end, so seq serves as the marker of incremental exports. Deletions
+                -- stay as tombstones.
+                CREATE TABLE IF NOT EXISTS change_log (
+                  seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                  entity TEXT NOT NULL,
+                  entity_id INTEGER NOT NULL,
+                  deleted INTEGER NOT NULL DEFAULT 0,
+                  UNIQUE (entity, entity_id)
+                );
+
+                INSERT OR IGNORE INTO change_log (entity, entity_id)
+                SELECT 'category', id FROM categories ORDER BY level, id;
+                INSERT OR IGNORE INTO change_log (entity, entity_id)
+                SELECT 'document', id FROM documents ORDER BY id;
+                INSERT OR IGNORE INTO change_log (entity, entity_id)
+                SELECT 'attachment', id FROM attachments ORDER BY id;
+
+                CREATE TRIGGER IF NOT EXISTS change_documents_ai AFTER INSERT ON documents
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id) VALUES ('document', NEW.id);
+                END;
+                CREATE TRIGGER IF NOT EXISTS change_documents_au
+                AFTER UPDATE OF title, description, text_content, category_id ON documents
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id) VALUES ('document', NEW.id);
+                END;
+                CREATE TRIGGER IF NOT EXISTS change_documents_ad AFTER DELETE ON documents
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id, deleted) VALUES ('document', OLD.id, 1);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS change_categories_ai AFTER INSERT ON categories
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id) VALUES ('category', NEW.id);
+                END;
+                CREATE TRIGGER IF NOT EXISTS change_categories_au
+                AFTER UPDATE OF name, icon, color, parent_id, description, sort_order ON categories
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id) VALUES ('category', NEW.id);
+                END;
+                CREATE TRIGGER IF NOT EXISTS change_categories_ad AFTER DELETE ON categories
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id, deleted) VALUES ('category', OLD.id, 1);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS change_attachments_ai AFTER INSERT ON attachments
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id) VALUES ('attachment', NEW.id);
+                END;
+                CREATE TRIGGER IF NOT EXISTS change_attachments_au
+                AFTER UPDATE OF document_id, filename, filepath, filetype, sort_order ON attachments
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id) VALUES ('attachment', NEW.id);
+                END;
+                CREATE TRIGGER IF NOT EXISTS change_attachments_ad AFTER DELETE ON attachments
+                BEGIN
+                  INSERT OR REPLACE INTO change_log (entity, entity_id, deleted) VALUES ('attachment', OLD.id, 1);
+                END;
+
+                -- Tells the deltas of one archive apart from another's
+                ALTER TABLE archive_meta ADD COLUMN uid TEXT;
+                UPDATE archive_meta SET uid = lower(hex(randomblob(16))) WHERE uid IS NULL;
+
+                -- Per archive whose deltas were imported: the marker reached, and where
+                -- its records ended up here
+                CREATE TABLE IF NOT EXISTS incremental_sources (
+                  source TEXT PRIMARY KEY,
+                  marker INTEGER NOT NULL,
+                  updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+                CREATE TABLE IF NOT EXISTS incremental_ids (
+                  source TEXT NOT NULL,
+                  entity TEXT NOT NULL,
+                  source_id INTEGER NOT NULL,
+                  local_id INTEGER NOT NULL,
+                  PRIMARY KEY (source, entity, source_id)
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 25,
+            description: "create_link_references",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS link_references (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  document_id INTEGER NOT NULL,
+                  url TEXT NOT NULL,
+                  title TEXT,
+                  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_link_references_document ON link_references (document_id);
+
+                ALTER TABLE deleted_documents ADD COLUMN link_references TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 26,
+            description: "add_document_content_hash",
+            sql: r#"
+                -- Filled in by the app like word_count, which can hash; NULL means stale
+                ALTER TABLE documents ADD COLUMN content_hash TEXT;
+                CREATE INDEX IF NOT EXISTS idx_documents_content_hash ON documents (content_hash);
+                CREATE TRIGGER IF NOT EXISTS documents_content_hash_au
+                AFTER UPDATE OF title, text_content ON documents
+                BEGIN
+                  UPDATE documents SET content_hash = NULL WHERE id = NEW.id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS documents_content_hash_tag_ai
+                AFTER INSERT ON document_tags
+                BEGIN
+                  UPDATE documents SET content_hash = NULL WHERE id = NEW.document_id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS documents_content_hash_tag_ad
+                AFTER DELETE ON document_tags
+                BEGIN
+                  UPDATE documents SET content_hash = NULL WHERE id = OLD.document_id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS documents_content_hash_tag_au
+                AFTER UPDATE OF name ON tags
+                BEGIN
+                  UPDATE documents SET content_hash = NULL
+                  WHERE id IN (SELECT document_id FROM document_tags WHERE tag_id = NEW.id);
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 27,
+            description: "add_document_manual_order",
+            sql: r#"
+                -- Hand-sorted position within the category; documents created or moved
+                -- in go last
+                ALTER TABLE documents ADD COLUMN manual_order INTEGER;
+                UPDATE documents SET manual_order = ordered.position
+                FROM (
+                  SELECT id, ROW_NUMBER() OVER (PARTITION BY category_id ORDER BY created_at, id) AS position
+                  FROM documents
+                ) AS ordered
+                WHERE documents.id = ordered.id;
+                CREATE INDEX IF NOT EXISTS idx_documents_manual_order ON documents (category_id, manual_order);
+                CREATE TRIGGER IF NOT EXISTS documents_manual_order_ai
+                AFTER INSERT ON documents
+                WHEN NEW.manual_order IS NULL
+                BEGIN
+                  UPDATE documents SET manual_order = (
+                    SELECT COALESCE(MAX(manual_order), 0) + 1 FROM documents
+                    WHERE category_id IS NEW.category_id AND id != NEW.id
+                  ) WHERE id = NEW.id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS documents_manual_order_au
+                AFTER UPDATE OF category_id ON documents
+                WHEN NEW.category_id IS NOT OLD.category_id
+                BEGIN
+                  UPDATE documents SET manual_order = (
+                    SELECT COALESCE(MAX(manual_order), 0) + 1 FROM documents
+                    WHERE category_id IS NEW.category_id AND id != NEW.id
+                  ) WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 28,
+            description: "add_attachment_ocr_text",
+            sql: r#"
+                -- NULL until recognized; an image without any text gets ''
+                ALTER TABLE attachments ADD COLUMN ocr_text TEXT;
+                ALTER TABLE attachments ADD COLUMN ocr_lang TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 29,
+            description: "create_related_index",
+            sql: r#"
+                -- Term vectors and document links for related_documents, extracted by the
+                -- app; terms_indexed is NULL while a document's entries are stale
+                CREATE TABLE IF NOT EXISTS document_terms (
+                  document_id INTEGER NOT NULL,
+                  term TEXT NOT NULL,
+                  weight REAL NOT NULL,
+                  PRIMARY KEY (document_id, term),
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                ) WITHOUT ROWID;
+                CREATE INDEX IF NOT EXISTS idx_document_terms_term ON document_terms (term);
+
+                CREATE TABLE IF NOT EXISTS document_links (
+                  source_id INTEGER NOT NULL,
+                  target_id INTEGER NOT NULL,
+                  PRIMARY KEY (source_id, target_id),
+                  FOREIGN KEY (source_id) REFERENCES documents (id) ON DELETE CASCADE
+                ) WITHOUT ROWID;
+                CREATE INDEX IF NOT EXISTS idx_document_links_target ON document_links (target_id);
+
+                ALTER TABLE documents ADD COLUMN terms_indexed INTEGER;
+                CREATE TRIGGER IF NOT EXISTS documents_terms_indexed_au
+                AFTER UPDATE OF title, text_content ON documents
+                BEGIN
+                  UPDATE documents SET terms_indexed = NULL WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 30,
+            description: "add_document_locked",
+            sql: r#"
+                -- Locked documents keep their title and description searchable; the body, and
+                -- every stored version, stays out of lists, search results and the indexes
+                ALTER TABLE documents ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
+
+                -- What the indexes read, so rebuilding them leaves locked content out
+                CREATE VIEW IF NOT EXISTS documents_fts_content AS
+                SELECT id, title, description, CASE WHEN locked THEN NULL ELSE text_content END AS text_content
+                FROM documents;
+
+                CREATE VIEW IF NOT EXISTS document_versions_fts_content AS
+                SELECT id, title, description, text_content
+                FROM document_versions
+                WHERE document_id NOT IN (SELECT id FROM documents WHERE locked);
+
+                DROP TRIGGER IF EXISTS documents_fts_ai;
+                DROP TRIGGER IF EXISTS documents_fts_ad;
+                DROP TRIGGER IF EXISTS documents_fts_au;
+                DROP TABLE IF EXISTS documents_fts;
+                DROP TRIGGER IF EXISTS document_versions_fts_ai;
+                DROP TRIGGER IF EXISTS document_versions_fts_ad;
+                DROP TABLE IF EXISTS document_versions_fts;
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(
+                  title,
+                  description,
+                  text_content,
+                  content = 'documents_fts_content',
+                  content_rowid = 'id',
+                  tokenize = 'unicode61'
+                );
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS document_versions_fts USING fts5(
+                  title,
+                  description,
+                  text_content,
+                  content = 'document_versions_fts_content',
+                  content_rowid = 'id',
+                  tokenize = 'unicode61'
+                );
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_ai AFTER INSERT ON documents BEGIN
+                  INSERT INTO documents_fts(rowid, title, description, text_content)
+                  VALUES (new.id, new.title, new.description, CASE WHEN new.locked THEN NULL ELSE new.text_content END);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_ad AFTER DELETE ON documents BEGIN
+                  INSERT INTO documents_fts(documents_fts, rowid, title, description, text_content)
+                  VALUES ('delete', old.id, old.title, old.description, CASE WHEN old.locked THEN NULL ELSE old.text_content END);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_au AFTER UPDATE OF title, description, text_content, locked ON documents BEGIN
+                  INSERT INTO documents_fts(documents_fts, rowid, title, description, text_content)
+                  VALUES ('delete', old.id, old.title, old.description, CASE WHEN old.locked THEN NULL ELSE old.text_content END);
+                  INSERT INTO documents_fts(rowid, title, description, text_content)
+                  VALUES (new.id, new.title, new.description, CASE WHEN new.locked THEN NULL ELSE new.text_content END);
+                END;
+
+                -- Versions of a locked document are never indexed. The index's docsize table
+                -- lists the rows it holds, which still tells them apart once the document
+                -- itself is gone.
+                CREATE TRIGGER IF NOT EXISTS document_versions_fts_ai AFTER INSERT ON document_versions
+                WHEN NOT EXISTS (SELECT 1 FROM documents WHERE id = new.document_id AND locked) BEGIN
+                  INSERT INTO document_versions_fts(rowid, title, description, text_content)
+                  VALUES (new.id, new.title, new.description, new.text_content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS document_versions_fts_ad AFTER DELETE ON document_versions
+                WHEN EXISTS (SELECT 1 FROM document_versions_fts_docsize WHERE id = old.id) BEGIN
+                  INSERT INTO document_versions_fts(document_versions_fts, rowid, title, description, text_content)
+                  VALUES ('delete', old.id, old.title, old.description, old.text_content);
+                END;
+
+                -- Locking takes a document's versions out of the index, unlocking puts them back
+                CREATE TRIGGER IF NOT EXISTS document_versions_fts_lock AFTER UPDATE OF locked ON documents
+                WHEN new.locked != old.locked BEGIN
+                  INSERT INTO document_versions_fts(document_versions_fts, rowid, title, description, text_content)
+                  SELECT 'delete', id, title, description, text_content FROM document_versions
+                  WHERE document_id = new.id AND new.locked
+                    AND id IN (SELECT id FROM document_versions_fts_docsize);
+                  INSERT INTO document_versions_fts(rowid, title, description, text_content)
+                  SELECT id, title, description, text_content FROM document_versions
+                  WHERE document_id = new.id AND NOT new.locked
+                    AND id NOT IN (SELECT id FROM document_versions_fts_docsize);
+                END;
+
+                INSERT INTO documents_fts(documents_fts) VALUES ('rebuild');
+                INSERT INTO document_versions_fts(document_versions_fts) VALUES ('rebuild');
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 31,
+            description: "create_document_fields",
+            sql: r#"
+                -- Custom fields: named values beside the body, e.g. the amount of an expense
+                CREATE TABLE IF NOT EXISTS document_fields (
+                  document_id INTEGER NOT NULL,
+                  key TEXT NOT NULL,
+                  value TEXT NOT NULL,
+                  PRIMARY KEY (document_id, key),
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                ) WITHOUT ROWID;
+                CREATE INDEX IF NOT EXISTS idx_document_fields_key ON document_fields (key, value);
+
+                ALTER TABLE deleted_documents ADD COLUMN fields TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 32,
+            description: "create_attachment_annotations",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS attachment_annotations (
+                    attachment_id INTEGER PRIMARY KEY REFERENCES attachments(id) ON DELETE CASCADE,
+                    layer TEXT NOT NULL,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 33,
+            description: "add_name_sort",
+            sql: r#"
+                -- Folded names for prefix lookups, filled in by the app like title_sort
+                ALTER TABLE tags ADD COLUMN name_sort TEXT;
+                CREATE INDEX IF NOT EXISTS idx_tags_name_sort ON tags (name_sort);
+                CREATE TRIGGER IF NOT EXISTS tags_name_sort_au
+                AFTER UPDATE OF name ON tags
+                BEGIN
+                  UPDATE tags SET name_sort = NULL WHERE id = NEW.id;
+                END;
+
+                ALTER TABLE categories ADD COLUMN name_sort TEXT;
+                CREATE INDEX IF NOT EXISTS idx_categories_name_sort ON categories (name_sort);
+                CREATE TRIGGER IF NOT EXISTS categories_name_sort_au
+                AFTER UPDATE OF name ON categories
+                BEGIN
+                  UPDATE categories SET name_sort = NULL WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 34,
+            description: "create_quick_switch_state",
+            sql: r#"
+                -- When and how often a document was opened, for the quick switcher
+                ALTER TABLE documents ADD COLUMN opened_at INTEGER;
+                ALTER TABLE documents ADD COLUMN open_count INTEGER NOT NULL DEFAULT 0;
+
+                -- Bumped by every change to what the quick switcher lists, so it knows
+                -- when to reload the titles it keeps in memory
+                CREATE TABLE IF NOT EXISTS quick_switch_state (
+                  id INTEGER PRIMARY KEY CHECK (id = 1),
+                  generation INTEGER NOT NULL DEFAULT 0
+                );
+                INSERT OR IGNORE INTO quick_switch_state (id) VALUES (1);
+
+                CREATE TRIGGER IF NOT EXISTS quick_switch_documents_ai AFTER INSERT ON documents
+                BEGIN
+                  UPDATE quick_switch_state SET generation = generation + 1;
+                END;
+                CREATE TRIGGER IF NOT EXISTS quick_switch_documents_ad AFTER DELETE ON documents
+                BEGIN
+                  UPDATE quick_switch_state SET generation = generation + 1;
+                END;
+                CREATE TRIGGER IF NOT EXISTS quick_switch_documents_au
+                AFTER UPDATE OF title, category_id ON documents
+                BEGIN
+                  UPDATE quick_switch_state SET generation = generation + 1;
+                END;
+                CREATE TRIGGER IF NOT EXISTS quick_switch_categories_au AFTER UPDATE OF name ON categories
+                BEGIN
+                  UPDATE quick_switch_state SET generation = generation + 1;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 35,
+            description: "add_view_time",
+            sql: r#"
+                ALTER TABLE documents ADD COLUMN total_view_time INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE documents ADD COLUMN view_count INTEGER NOT NULL DEFAULT 0;
+                CREATE INDEX IF NOT EXISTS idx_documents_total_view_time ON documents(total_view_time);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 36,
+            description: "create_generated_documents",
+            sql: r#"
+                -- Documents the app writes itself, like the index of a category; one of
+                -- each kind per category, category_id NULL for the whole archive
+                CREATE TABLE IF NOT EXISTS generated_documents (
+                  document_id INTEGER PRIMARY KEY,
+                  kind TEXT NOT NULL,
+                  category_id INTEGER,
+                  generated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+                );
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_generated_documents_scope
+                  ON generated_documents (kind, IFNULL(category_id, 0));
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 37,
+            description: "create_attachment_provenance",
+            sql: r#"
+                -- Filesystem metadata of the file an attachment was imported from; times
+                -- are RFC 3339 in UTC, NULL where the platform doesn't record them
+                CREATE TABLE IF NOT EXISTS attachment_provenance (
+                  attachment_id INTEGER PRIMARY KEY,
+                  original_path TEXT NOT NULL,
+                  created_at TEXT,
+                  modified_at TEXT,
+                  accessed_at TEXT,
+                  size INTEGER NOT NULL,
+                  readonly INTEGER NOT NULL DEFAULT 0,
+                  mode INTEGER,
+                  imported_at TEXT NOT NULL,
+                  FOREIGN KEY (attachment_id) REFERENCES attachments (id) ON DELETE CASCADE
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 38,
+            description: "add_attachment_alt_text",
+            sql: r#"
+                -- NULL until described; alt_text_source names what wrote it
+                ALTER TABLE attachments ADD COLUMN alt_text TEXT;
+                ALTER TABLE attachments ADD COLUMN alt_text_source TEXT;
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS attachments_fts USING fts5(
+                  filename, alt_text,
+                  content = 'attachments', content_rowid = 'id',
+                  tokenize = 'unicode61'
+                );
+                CREATE TRIGGER IF NOT EXISTS attachments_fts_ai AFTER INSERT ON attachments BEGIN
+                  INSERT INTO attachments_fts(rowid, filename, alt_text) VALUES (new.id, new.filename, new.alt_text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS attachments_fts_ad AFTER DELETE ON attachments BEGIN
+                  INSERT INTO attachments_fts(attachments_fts, rowid, filename, alt_text)
+                  VALUES ('delete', old.id, old.filename, old.alt_text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS attachments_fts_au AFTER UPDATE OF filename, alt_text ON attachments BEGIN
+                  INSERT INTO attachments_fts(attachments_fts, rowid, filename, alt_text)
+                  VALUES ('delete', old.id, old.filename, old.alt_text);
+                  INSERT INTO attachments_fts(rowid, filename, alt_text) VALUES (new.id, new.filename, new.alt_text);
+                END;
+                INSERT INTO attachments_fts(attachments_fts) VALUES ('rebuild');
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 39,
+            description: "add_last_export",
+            sql: r#"
+                -- Where export_document last wrote the document, and as what
+                ALTER TABLE documents ADD COLUMN last_export_format TEXT;
+                ALTER TABLE documents ADD COLUMN last_export_path TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 40,
+            description: "create_list_layouts",
+            sql: r#"
+                -- Saved document list views: columns, their widths and the sort, as JSON
+                CREATE TABLE IF NOT EXISTS list_layouts (
+                  name TEXT PRIMARY KEY COLLATE NOCASE,
+                  layout TEXT NOT NULL,
+                  updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 41,
+            description: "add_category_archive",
+            sql: r#"
+                ALTER TABLE categories ADD COLUMN archived_at TEXT;
+
+                -- An archived category hides its whole subtree
+                CREATE VIEW IF NOT EXISTS archived_categories AS
+                WITH RECURSIVE archived(id) AS (
+                  SELECT id FROM categories WHERE archived_at IS NOT NULL
+                  UNION
+                  SELECT c.id FROM categories c JOIN archived a ON c.parent_id = a.id
+                )
+                SELECT id FROM archived;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 42,
+            description: "create_attachment_versions",
+            sql: r#"
+                -- Files an attachment held before it was replaced, kept in the document's
+                -- attachment folder like the current one
+                CREATE TABLE IF NOT EXISTS attachment_versions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  attachment_id INTEGER NOT NULL,
+                  filename TEXT NOT NULL,
+                  filepath TEXT NOT NULL,
+                  filetype TEXT NOT NULL,
+                  filesize INTEGER,
+                  phash TEXT,
+                  ocr_text TEXT,
+                  ocr_lang TEXT,
+                  alt_text TEXT,
+                  alt_text_source TEXT,
+                  -- When the version was current from, and when it stopped being
+                  created_at TEXT NOT NULL,
+                  replaced_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                  FOREIGN KEY (attachment_id) REFERENCES attachments (id) ON DELETE CASCADE
+                );
+                CREATE INDEX IF NOT EXISTS idx_attachment_versions_attachment ON attachment_versions (attachment_id);
+
+                -- When the current file took the attachment's place; NULL until it is replaced
+                ALTER TABLE attachments ADD COLUMN replaced_at TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 43,
+            description: "create_import_sessions",
+            sql: r#"
+                -- One row per import, so everything it created can be undone together
+                CREATE TABLE IF NOT EXISTS import_sessions (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  -- archive, vault, folder, drop, csv or zip
+                  kind TEXT NOT NULL,
+                  source TEXT NOT NULL,
+                  started_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                  undone_at TEXT
+                );
+
+                ALTER TABLE documents ADD COLUMN import_session INTEGER REFERENCES import_sessions (id) ON DELETE SET NULL;
+                ALTER TABLE categories ADD COLUMN import_session INTEGER REFERENCES import_sessions (id) ON DELETE SET NULL;
+                ALTER TABLE attachments ADD COLUMN import_session INTEGER REFERENCES import_sessions (id) ON DELETE SET NULL;
+                CREATE INDEX IF NOT EXISTS idx_documents_import_session ON documents (import_session);
+                CREATE INDEX IF NOT EXISTS idx_categories_import_session ON categories (import_session);
+                CREATE INDEX IF NOT EXISTS idx_attachments_import_session ON attachments (import_session);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 44,
+            description: "create_document_notes",
+            sql: r#"
+                -- Commentary on a document, kept apart from its archived body
+                CREATE TABLE IF NOT EXISTS document_notes (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    document_id INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                    text TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME
+                );
+                CREATE INDEX IF NOT EXISTS idx_document_notes_document ON document_notes (document_id);
+                ALTER TABLE deleted_documents ADD COLUMN notes TEXT;
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS document_notes_fts USING fts5(
+                  text,
+                  content = 'document_notes', content_rowid = 'id',
+                  tokenize = 'unicode61'
+                );
+                CREATE TRIGGER IF NOT EXISTS document_notes_fts_ai AFTER INSERT ON document_notes BEGIN
+                  INSERT INTO document_notes_fts(rowid, text) VALUES (new.id, new.text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS document_notes_fts_ad AFTER DELETE ON document_notes BEGIN
+                  INSERT INTO document_notes_fts(document_notes_fts, rowid, text) VALUES ('delete', old.id, old.text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS document_notes_fts_au AFTER UPDATE OF text ON document_notes BEGIN
+                  INSERT INTO document_notes_fts(document_notes_fts, rowid, text) VALUES ('delete', old.id, old.text);
+                  INSERT INTO document_notes_fts(rowid, text) VALUES (new.id, new.text);
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 45,
+            description: "create_category_views",
+            sql: r#"
+                -- How each category's listing opens, as JSON
+                CREATE TABLE IF NOT EXISTS category_views (
+                    category_id INTEGER PRIMARY KEY REFERENCES categories(id) ON DELETE CASCADE,
+                    view TEXT NOT NULL,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 46,
+            description: "index_audit_log_entities",
+            sql: r#"
+                -- For the history viewer: one entity's entries, entries of one
+                -- operation, and the names the log recorded for deleted entities
+                CREATE INDEX IF NOT EXISTS idx_audit_log_entity ON audit_log (entity_type, entity_id, id);
+                CREATE INDEX IF NOT EXISTS idx_audit_log_operation ON audit_log (operation, occurred_at);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 47,
+            description: "create_archive_seals",
+            sql: r#"
+                -- Signed manifests of every document's content hash, newest last
+                CREATE TABLE IF NOT EXISTS archive_seals (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    sealed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    manifest TEXT NOT NULL,
+                    root_hash TEXT NOT NULL,
+                    signature TEXT NOT NULL
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 48,
+            description: "create_change_feed",
+            sql: r#"
+                -- Append-only: one row per change, written by the triggers in the
+                -- change's own transaction, for sync tools to replay in seq order.
+                -- Trashing and restoring documents (and their attachments) and
+                -- archiving categories are ops of their own, not deletes and creates.
+                -- content_hash is filled in for the latest change of a document once
+                -- its hash is computed; import_session is the import that created it.
+                CREATE TABLE IF NOT EXISTS change_feed (
+                  seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                  entity TEXT NOT NULL,
+                  entity_id INTEGER NOT NULL,
+                  op TEXT NOT NULL,
+                  content_hash TEXT,
+                  occurred_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+                  import_session INTEGER
+                );
+                CREATE INDEX IF NOT EXISTS idx_change_feed_entity ON change_feed (entity, entity_id, seq);
+
+                INSERT INTO change_feed (entity, entity_id, op, content_hash, import_session)
+                SELECT 'category', id, 'create', NULL, import_session FROM categories ORDER BY level, id;
+                INSERT INTO change_feed (entity, entity_id, op, content_hash, import_session)
+                SELECT 'document', id, 'create', content_hash, import_session FROM documents ORDER BY id;
+                INSERT INTO change_feed (entity, entity_id, op, content_hash, import_session)
+                SELECT 'attachment', id, 'create', NULL, import_session FROM attachments ORDER BY id;
+
+                -- A document or attachment inserted while its document is in the trash
+                -- is being restored
+                CREATE TRIGGER IF NOT EXISTS feed_documents_ai AFTER INSERT ON documents
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op, import_session)
+                  SELECT 'document', NEW.id,
+                    CASE WHEN EXISTS (SELECT 1 FROM deleted_documents WHERE id = NEW.id)
+                      THEN 'restore' ELSE 'create' END,
+                    NEW.import_session;
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_documents_au
+                AFTER UPDATE OF title, description, text_content, category_id ON documents
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('document', NEW.id, 'update');
+                END;
+                -- Deleted once it is in the trash is trashed
+                CREATE TRIGGER IF NOT EXISTS feed_documents_ad AFTER DELETE ON documents
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op)
+                  SELECT 'document', OLD.id,
+                    CASE WHEN EXISTS (SELECT 1 FROM deleted_documents WHERE id = OLD.id)
+                      THEN 'trash' ELSE 'delete' END;
+                END;
+                -- Gone from the trash without coming back
+                CREATE TRIGGER IF NOT EXISTS feed_deleted_documents_ad AFTER DELETE ON deleted_documents
+                WHEN NOT EXISTS (SELECT 1 FROM documents WHERE id = OLD.id)
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('document', OLD.id, 'purge');
+                END;
+
+                -- Tags are part of a document's content; those trashed or restored
+                -- with it are not changes of their own
+                CREATE TRIGGER IF NOT EXISTS feed_document_tags_ai AFTER INSERT ON document_tags
+                WHEN NOT EXISTS (SELECT 1 FROM deleted_documents WHERE id = NEW.document_id)
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('document', NEW.document_id, 'update');
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_document_tags_ad AFTER DELETE ON document_tags
+                WHEN EXISTS (SELECT 1 FROM documents WHERE id = OLD.document_id)
+                  AND NOT EXISTS (SELECT 1 FROM deleted_documents WHERE id = OLD.document_id)
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('document', OLD.document_id, 'update');
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS feed_categories_ai AFTER INSERT ON categories
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op, import_session)
+                  VALUES ('category', NEW.id, 'create', NEW.import_session);
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_categories_au
+                AFTER UPDATE OF name, icon, color, parent_id, description, sort_order ON categories
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('category', NEW.id, 'update');
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_categories_archive
+                AFTER UPDATE OF archived_at ON categories
+                WHEN (OLD.archived_at IS NULL) != (NEW.archived_at IS NULL)
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op)
+                  VALUES ('category', NEW.id,
+                    CASE WHEN NEW.archived_at IS NULL THEN 'unarchive' ELSE 'archive' END);
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_categories_ad AFTER DELETE ON categories
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('category', OLD.id, 'delete');
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS feed_attachments_ai AFTER INSERT ON attachments
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op, import_session)
+                  SELECT 'attachment', NEW.id,
+                    CASE WHEN EXISTS (SELECT 1 FROM deleted_documents WHERE id = NEW.document_id)
+                      THEN 'restore' ELSE 'create' END,
+                    NEW.import_session;
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_attachments_au
+                AFTER UPDATE OF document_id, filename, filepath, filetype, sort_order ON attachments
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op) VALUES ('attachment', NEW.id, 'update');
+                END;
+                -- Imports tag their attachments with the session after inserting them
+                CREATE TRIGGER IF NOT EXISTS feed_attachments_session
+                AFTER UPDATE OF import_session ON attachments
+                WHEN NEW.import_session IS NOT NULL
+                BEGIN
+                  UPDATE change_feed SET import_session = NEW.import_session
+                  WHERE seq = (SELECT MAX(seq) FROM change_feed
+                               WHERE entity = 'attachment' AND entity_id = NEW.id)
+                    AND op = 'create' AND import_session IS NULL;
+                END;
+                CREATE TRIGGER IF NOT EXISTS feed_attachments_ad AFTER DELETE ON attachments
+                BEGIN
+                  INSERT INTO change_feed (entity, entity_id, op)
+                  SELECT 'attachment', OLD.id,
+                    CASE WHEN EXISTS (SELECT 1 FROM deleted_documents WHERE id = OLD.document_id)
+                      THEN 'trash' ELSE 'delete' END;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+    ]
+}
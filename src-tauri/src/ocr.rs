@@ -0,0 +1,104 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Text recognition goes through the tesseract command line tool, and PDF
+// pages are rasterized with poppler's pdftoppm first; neither is bundled
+
+const UNAVAILABLE: &str = "OCR needs tesseract installed";
+// Resolution PDF pages are rendered at, what tesseract is tuned for
+const PDF_DPI: u32 = 300;
+
+// Tells apart the scratch folders of PDFs recognized at the same time
+static NEXT_SCRATCH: AtomicU64 = AtomicU64::new(0);
+
+/// Recognizes the text of an image or PDF. `lang` takes tesseract's
+/// language codes, e.g. `eng` or `deu+eng`.
+pub fn recognize(path: &Path, filetype: &str, lang: &str) -> Result<String, String> {
+    if filetype != "application/pdf" {
+        return tesseract(path, lang);
+    }
+
+    let dir = scratch_dir("ocr")?;
+    let result = recognize_pdf(path, &dir, lang);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// A fresh folder in the temp dir; the caller removes it when done.
+pub fn scratch_dir(purpose: &str) -> Result<PathBuf, String> {
+    let dir = std::env::temp_dir().join(format!(
+        "ando-archive-{}-{}-{}",
+        purpose,
+        std::process::id(),
+        NEXT_SCRATCH.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+fn recognize_pdf(path: &Path, dir: &Path, lang: &str) -> Result<String, String> {
+    let mut text = Vec::new();
+    for page in render_pdf(path, dir, PDF_DPI)? {
+        let page_text = tesseract(&page, lang)?;
+        if !page_text.is_empty() {
+            text.push(page_text);
+        }
+    }
+    Ok(text.join("\n\n"))
+}
+
+/// Renders every page of a PDF to a PNG in `dir` at `dpi`, returning the
+/// files in page order.
+pub fn render_pdf(path: &Path, dir: &Path, dpi: u32) -> Result<Vec<PathBuf>, String> {
+    let output = match Command::new("pdftoppm")
+        .args(["-r", &dpi.to_string(), "-png"])
+        .arg(path)
+        .arg(dir.join("page"))
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err("Rendering PDFs needs pdftoppm installed".to_string())
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Could not render PDF: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // pdftoppm numbers the pages with zero padding, so they sort by name
+    let mut pages: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    pages.sort();
+    Ok(pages)
+}
+
+fn tesseract(path: &Path, lang: &str) -> Result<String, String> {
+    // Callers run several at once, so each keeps to one thread
+    let output = match Command::new("tesseract")
+        .arg(path)
+        .arg("stdout")
+        .args(["-l", lang])
+        .env("OMP_THREAD_LIMIT", "1")
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Err(UNAVAILABLE.to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "tesseract failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
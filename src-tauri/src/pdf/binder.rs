@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+
+use image::imageops::FilterType;
+use serde::Deserialize;
+
+use crate::error::{AppError, CmdResult};
+use crate::html;
+
+use super::{text_width, truncate, wrap, Font, Image, PdfDocument, A4, LETTER};
+
+const MARGIN: f32 = 56.0;
+const FOOTER_Y: f32 = 28.0;
+const BODY_SIZE: f32 = 11.0;
+const BODY_LEADING: f32 = 15.0;
+const TITLE_SIZE: f32 = 20.0;
+const TOC_SIZE: f32 = 11.0;
+const TOC_LEADING: f32 = 18.0;
+// Larger images are downscaled before embedding to keep the file small
+const MAX_IMAGE_PIXELS: u32 = 1400;
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BinderOptions {
+    pub page_size: PageSize,
+    pub include_images: bool,
+}
+
+impl Default for BinderOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            include_images: true,
+        }
+    }
+}
+
+pub struct BinderEntry {
+    pub title: String,
+    pub subtitle: String,
+    /// Editor HTML.
+    pub body: String,
+    pub images: Vec<PathBuf>,
+}
+
+/// Lays out a cover page, a table of contents and one section per entry,
+/// with a bookmark per entry and page numbers throughout. `progress` is
+/// called after each entry and stops the export by returning `false`.
+pub fn render(
+    title: &str,
+    export_date: &str,
+    entries: &[BinderEntry],
+    options: &BinderOptions,
+    mut progress: impl FnMut(usize) -> bool,
+) -> CmdResult<PdfDocument> {
+    let mut doc = PdfDocument::new(match options.page_size {
+        PageSize::A4 => A4,
+        PageSize::Letter => LETTER,
+    });
+    let (width, height) = doc.size();
+    let content_width = width - MARGIN * 2.0;
+
+    let cover = doc.add_page();
+    let title_lines = wrap(title, Font::Bold, 28.0, content_width);
+    let mut y = height * 0.6;
+    for line in &title_lines {
+        doc.text(cover, MARGIN, y, Font::Bold, 28.0, line);
+        y -= 34.0;
+    }
+    doc.text(cover, MARGIN, y - 10.0, Font::Regular, 12.0, export_date);
+    let summary = format!("{} documents", entries.len());
+    doc.text(cover, MARGIN, y - 28.0, Font::Regular, 12.0, &summary);
+
+    // Entries are one line each, so the contents length is known upfront
+    let toc_top = height - MARGIN - TITLE_SIZE - 20.0;
+    let toc_per_page = (((toc_top - MARGIN) / TOC_LEADING) as usize).max(1);
+    let toc_pages: Vec<usize> = (0..entries.len().div_ceil(toc_per_page).max(1))
+        .map(|_| doc.add_page())
+        .collect();
+
+    let mut start_pages = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let mut flow = Flow::new(&mut doc);
+        start_pages.push(flow.page);
+        flow.doc.add_bookmark(&entry.title, flow.page);
+
+        flow.paragraph(&entry.title, Font::Bold, TITLE_SIZE, TITLE_SIZE + 4.0);
+        if !entry.subtitle.is_empty() {
+            flow.paragraph(&entry.subtitle, Font::Regular, 9.0, 13.0);
+        }
+        flow.space(10.0);
+
+        for paragraph in html::to_paragraphs(&entry.body) {
+            flow.paragraph(&paragraph, Font::Regular, BODY_SIZE, BODY_LEADING);
+            flow.space(6.0);
+        }
+
+        if options.include_images {
+            for path in &entry.images {
+                match load_image(path) {
+                    Ok(image) => flow.image(image),
+                    Err(e) => log::warn!("Skipping image {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        if !progress(index + 1) {
+            return Err(AppError::Conflict("Export cancelled".to_string()));
+        }
+    }
+
+    doc.text(
+        toc_pages[0],
+        MARGIN,
+        height - MARGIN - TITLE_SIZE,
+        Font::Bold,
+        TITLE_SIZE,
+        "Contents",
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        let page = toc_pages[index / toc_per_page];
+        let y = toc_top - (index % toc_per_page) as f32 * TOC_LEADING;
+        let number = (start_pages[index] + 1).to_string();
+        let number_width = text_width(&number, Font::Regular, TOC_SIZE);
+
+        let label = truncate(
+            &entry.title,
+            Font::Regular,
+            TOC_SIZE,
+            content_width - number_width - 20.0,
+        );
+        doc.text(page, MARGIN, y, Font::Regular, TOC_SIZE, &label);
+        doc.text(
+            page,
+            width - MARGIN - number_width,
+            y,
+            Font::Regular,
+            TOC_SIZE,
+            &number,
+        );
+    }
+
+    let total = doc.page_count();
+    for page in 0..total {
+        let label = format!("{} / {}", page + 1, total);
+        let x = (width - text_width(&label, Font::Regular, 9.0)) / 2.0;
+        doc.text(page, x, FOOTER_Y, Font::Regular, 9.0, &label);
+    }
+
+    Ok(doc)
+}
+
+// Tracks the write position while filling pages top to bottom
+struct Flow<'a> {
+    doc: &'a mut PdfDocument,
+    page: usize,
+    y: f32,
+}
+
+impl<'a> Flow<'a> {
+    fn new(doc: &'a mut PdfDocument) -> Self {
+        let page = doc.add_page();
+        let y = doc.size().1 - MARGIN;
+        Self { doc, page, y }
+    }
+
+    fn content_width(&self) -> f32 {
+        self.doc.size().0 - MARGIN * 2.0
+    }
+
+    fn ensure_room(&mut self, needed: f32) {
+        if self.y - needed < MARGIN {
+            self.page = self.doc.add_page();
+            self.y = self.doc.size().1 - MARGIN;
+        }
+    }
+
+    fn space(&mut self, amount: f32) {
+        self.y -= amount;
+    }
+
+    fn paragraph(&mut self, text: &str, font: Font, size: f32, leading: f32) {
+        for line in wrap(text, font, size, self.content_width()) {
+            self.ensure_room(leading);
+            self.y -= leading;
+            self.doc.text(self.page, MARGIN, self.y, font, size, &line);
+        }
+    }
+
+    fn image(&mut self, image: Image) {
+        let max_height = self.doc.size().1 * 0.6;
+        let scale = (self.content_width() / image.width as f32)
+            .min(max_height / image.height as f32)
+            .min(1.0);
+        let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+
+        self.ensure_room(height + 10.0);
+        self.y -= height + 10.0;
+        let id = self.doc.add_image(image);
+        self.doc
+            .draw_image(self.page, id, MARGIN, self.y, width, height);
+    }
+}
+
+fn load_image(path: &PathBuf) -> Result<Image, String> {
+    let mut image = image::open(path).map_err(|e| e.to_string())?;
+    if image.width() > MAX_IMAGE_PIXELS || image.height() > MAX_IMAGE_PIXELS {
+        image = image.resize(MAX_IMAGE_PIXELS, MAX_IMAGE_PIXELS, FilterType::Triangle);
+    }
+
+    Ok(Image::from_dynamic(&image))
+}
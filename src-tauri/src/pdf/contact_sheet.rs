@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use super::{text_width, truncate, Font, Image, PdfDocument, A4};
+use crate::error::{AppError, CmdResult};
+
+const MARGIN: f32 = 36.0;
+const FOOTER_Y: f32 = 20.0;
+const HEADER_SIZE: f32 = 12.0;
+const CAPTION_SIZE: f32 = 8.0;
+const CAPTION_HEIGHT: f32 = 14.0;
+const GAP: f32 = 10.0;
+
+/// What goes under each thumbnail.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Caption {
+    #[default]
+    Filename,
+    /// The title of the document the image is attached to.
+    Title,
+}
+
+pub struct SheetEntry {
+    pub caption: String,
+    /// A thumbnail, small enough to embed as is.
+    pub image: PathBuf,
+}
+
+/// Lays `entries` out in a grid of `cols` columns, each thumbnail scaled
+/// to fit a `thumb_size` point square (or the column, if narrower) with
+/// its caption underneath. Pages are added as the grid fills them.
+/// Entries whose image can't be read are skipped; returns the document
+/// and the number of images placed.
+pub fn render(
+    title: &str,
+    entries: &[SheetEntry],
+    cols: u32,
+    thumb_size: f32,
+) -> CmdResult<(PdfDocument, usize)> {
+    let mut doc = PdfDocument::new(A4);
+    let (width, height) = doc.size();
+    let cols = cols.max(1) as usize;
+    let cell_width = (width - MARGIN * 2.0) / cols as f32;
+    let box_size = thumb_size.min(cell_width - GAP);
+    if box_size < 16.0 {
+        return Err(AppError::Validation(
+            "Too many columns for the page width".to_string(),
+        ));
+    }
+    let cell_height = box_size + CAPTION_HEIGHT + GAP;
+    let top = height - MARGIN - HEADER_SIZE - GAP;
+    let rows = ((top - MARGIN) / cell_height) as usize;
+    if rows == 0 {
+        return Err(AppError::Validation(
+            "Thumbnails too large for the page".to_string(),
+        ));
+    }
+
+    let mut placed = 0;
+    let mut page = None;
+    for entry in entries {
+        let image = match image::open(&entry.image) {
+            Ok(image) => Image::from_dynamic(&image),
+            Err(e) => {
+                log::warn!("Skipping image {}: {}", entry.image.display(), e);
+                continue;
+            }
+        };
+
+        let slot = placed % (rows * cols);
+        if slot == 0 {
+            let new_page = doc.add_page();
+            doc.text(
+                new_page,
+                MARGIN,
+                height - MARGIN - HEADER_SIZE,
+                Font::Bold,
+                HEADER_SIZE,
+                title,
+            );
+            page = Some(new_page);
+        }
+        let page = page.expect("a page is added for the first slot");
+
+        let cell_x = MARGIN + (slot % cols) as f32 * cell_width;
+        let cell_top = top - (slot / cols) as f32 * cell_height;
+
+        // Centred in the square above the caption, never scaled up
+        let scale = (box_size / image.width as f32)
+            .min(box_size / image.height as f32)
+            .min(1.0);
+        let (image_width, image_height) = (image.width as f32 * scale, image.height as f32 * scale);
+        let x = cell_x + (cell_width - image_width) / 2.0;
+        let y = cell_top - box_size + (box_size - image_height) / 2.0;
+        let id = doc.add_image(image);
+        doc.draw_image(page, id, x, y, image_width, image_height);
+
+        let caption = truncate(
+            &entry.caption,
+            Font::Regular,
+            CAPTION_SIZE,
+            cell_width - GAP,
+        );
+        let caption_x =
+            cell_x + (cell_width - text_width(&caption, Font::Regular, CAPTION_SIZE)) / 2.0;
+        doc.text(
+            page,
+            caption_x,
+            cell_top - box_size - CAPTION_HEIGHT + 4.0,
+            Font::Regular,
+            CAPTION_SIZE,
+            &caption,
+        );
+
+        placed += 1;
+    }
+
+    let total = doc.page_count();
+    for page in 0..total {
+        let label = format!("{} / {}", page + 1, total);
+        let x = (width - text_width(&label, Font::Regular, 9.0)) / 2.0;
+        doc.text(page, x, FOOTER_Y, Font::Regular, 9.0, &label);
+    }
+
+    Ok((doc, placed))
+}
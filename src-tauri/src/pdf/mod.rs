@@ -0,0 +1,422 @@
+// Minimal PDF writer: standard Type 1 fonts with WinAnsi encoding, RGB
+// images and a flat outline. Enough for text-heavy exports without
+// pulling in a PDF library.
+pub mod binder;
+pub mod contact_sheet;
+
+use std::fmt::Write as _;
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+pub const A4: (f32, f32) = (595.0, 842.0);
+pub const LETTER: (f32, f32) = (612.0, 792.0);
+
+// Object numbers fixed by `to_bytes`, everything else follows them
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const FONT_REGULAR: usize = 3;
+const FONT_BOLD: usize = 4;
+const OUTLINES: usize = 5;
+const FIRST_FREE: usize = 6;
+
+#[derive(Clone, Copy)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+/// 8-bit RGB pixels, row by row.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    /// Flattens transparency onto white, the page background.
+    pub fn from_dynamic(image: &image::DynamicImage) -> Self {
+        let rgba = image.to_rgba8();
+        let mut rgb = Vec::with_capacity(rgba.len() / 4 * 3);
+        for pixel in rgba.pixels() {
+            let [r, g, b, a] = pixel.0;
+            for channel in [r, g, b] {
+                rgb.push(((channel as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8);
+            }
+        }
+
+        Self {
+            width: rgba.width(),
+            height: rgba.height(),
+            rgb,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Page {
+    content: String,
+    images: Vec<usize>,
+}
+
+pub struct PdfDocument {
+    width: f32,
+    height: f32,
+    pages: Vec<Page>,
+    images: Vec<Image>,
+    bookmarks: Vec<(String, usize)>,
+}
+
+impl PdfDocument {
+    pub fn new((width, height): (f32, f32)) -> Self {
+        Self {
+            width,
+            height,
+            pages: Vec::new(),
+            images: Vec::new(),
+            bookmarks: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn add_page(&mut self) -> usize {
+        self.pages.push(Page::default());
+        self.pages.len() - 1
+    }
+
+    /// Draws a single line of text with its baseline at `y`.
+    pub fn text(&mut self, page: usize, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        let content = &mut self.pages[page].content;
+        let _ = writeln!(
+            content,
+            "BT /{} {} Tf {:.2} {:.2} Td {} Tj ET",
+            font.resource(),
+            size,
+            x,
+            y,
+            literal_string(text)
+        );
+    }
+
+    pub fn add_image(&mut self, image: Image) -> usize {
+        self.images.push(image);
+        self.images.len() - 1
+    }
+
+    /// Draws an image with its lower left corner at `x`, `y`.
+    pub fn draw_image(
+        &mut self,
+        page: usize,
+        image: usize,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) {
+        let page = &mut self.pages[page];
+        let _ = writeln!(
+            page.content,
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q",
+            width, height, x, y, image
+        );
+        if !page.images.contains(&image) {
+            page.images.push(image);
+        }
+    }
+
+    pub fn add_bookmark(&mut self, title: &str, page: usize) {
+        self.bookmarks.push((title.to_string(), page));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let first_page = FIRST_FREE + self.images.len();
+        let first_bookmark = first_page + self.pages.len() * 2;
+        let page_object = |index: usize| first_page + index * 2;
+
+        let mut objects: Vec<(usize, Vec<u8>)> = Vec::new();
+
+        objects.push((
+            CATALOG,
+            format!(
+                "<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines >>",
+                PAGES, OUTLINES
+            )
+            .into_bytes(),
+        ));
+
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|index| format!("{} 0 R", page_object(index)))
+            .collect();
+        objects.push((
+            PAGES,
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.pages.len()
+            )
+            .into_bytes(),
+        ));
+
+        for (id, base_font) in [(FONT_REGULAR, "Helvetica"), (FONT_BOLD, "Helvetica-Bold")] {
+            objects.push((
+                id,
+                format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    base_font
+                )
+                .into_bytes(),
+            ));
+        }
+
+        let mut outlines = String::from("<< /Type /Outlines");
+        if !self.bookmarks.is_empty() {
+            let _ = write!(
+                outlines,
+                " /First {} 0 R /Last {} 0 R /Count {}",
+                first_bookmark,
+                first_bookmark + self.bookmarks.len() - 1,
+                self.bookmarks.len()
+            );
+        }
+        outlines.push_str(" >>");
+        objects.push((OUTLINES, outlines.into_bytes()));
+
+        for (index, image) in self.images.iter().enumerate() {
+            let dict = format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8",
+                image.width, image.height
+            );
+            objects.push((FIRST_FREE + index, stream(&dict, &image.rgb)));
+        }
+
+        for (index, page) in self.pages.iter().enumerate() {
+            let images: String = page
+                .images
+                .iter()
+                .map(|image| format!(" /Im{} {} 0 R", image, FIRST_FREE + image))
+                .collect();
+            objects.push((
+                page_object(index),
+                format!(
+                    "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 {} 0 R /F2 {} 0 R >> /XObject <<{} >> >> /Contents {} 0 R >>",
+                    PAGES,
+                    self.width,
+                    self.height,
+                    FONT_REGULAR,
+                    FONT_BOLD,
+                    images,
+                    page_object(index) + 1
+                )
+                .into_bytes(),
+            ));
+            objects.push((page_object(index) + 1, stream("", page.content.as_bytes())));
+        }
+
+        for (index, (title, page)) in self.bookmarks.iter().enumerate() {
+            let mut item = format!(
+                "<< /Title {} /Parent {} 0 R /Dest [{} 0 R /Fit]",
+                text_string(title),
+                OUTLINES,
+                page_object(*page)
+            );
+            if index > 0 {
+                let _ = write!(item, " /Prev {} 0 R", first_bookmark + index - 1);
+            }
+            if index + 1 < self.bookmarks.len() {
+                let _ = write!(item, " /Next {} 0 R", first_bookmark + index + 1);
+            }
+            item.push_str(" >>");
+            objects.push((first_bookmark + index, item.into_bytes()));
+        }
+
+        objects.sort_by_key(|(id, _)| *id);
+
+        let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (id, body) in &objects {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref = out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            CATALOG,
+            xref
+        );
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+}
+
+fn stream(dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec can't fail
+    let _ = encoder.write_all(data);
+    let compressed = encoder.finish().unwrap_or_default();
+
+    let mut out = format!(
+        "<< {} /Filter /FlateDecode /Length {} >>\nstream\n",
+        dict,
+        compressed.len()
+    )
+    .into_bytes();
+    out.extend_from_slice(&compressed);
+    out.extend_from_slice(b"\nendstream");
+    out
+}
+
+// Characters outside WinAnsi have no glyph in the standard fonts
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{A0}'..='\u{FF}' => c as u32 as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+fn literal_string(text: &str) -> String {
+    let mut out = String::from("(");
+    for byte in text.chars().map(win_ansi) {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            0x20..=0x7E => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\{:03o}", byte);
+            }
+        }
+    }
+    out.push(')');
+    out
+}
+
+// Outline titles are shown by the viewer, so they can use full Unicode
+fn text_string(text: &str) -> String {
+    let mut out = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        let _ = write!(out, "{:04X}", unit);
+    }
+    out.push('>');
+    out
+}
+
+// Glyph widths of Helvetica and Helvetica-Bold for ' '..='~', in 1/1000 em
+const REGULAR_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+pub fn text_width(text: &str, font: Font, size: f32) -> f32 {
+    let widths = match font {
+        Font::Regular => &REGULAR_WIDTHS,
+        Font::Bold => &BOLD_WIDTHS,
+    };
+    let units: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => widths[c as usize - 32] as u32,
+            // Close enough for accented letters and punctuation
+            _ => 556,
+        })
+        .sum();
+    units as f32 * size / 1000.0
+}
+
+/// Cuts `text` to fit in `max_width`, marking the cut with an ellipsis.
+pub fn truncate(text: &str, font: Font, size: f32, max_width: f32) -> String {
+    if text_width(text, font, size) <= max_width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    for c in text.chars() {
+        truncated.push(c);
+        if text_width(&truncated, font, size) > max_width {
+            truncated.pop();
+            break;
+        }
+    }
+    format!("{}…", truncated.trim_end())
+}
+
+/// Breaks `text` into lines no wider than `max_width`, splitting words
+/// that don't fit on a line of their own.
+pub fn wrap(text: &str, font: Font, size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if text_width(&candidate, font, size) <= max_width {
+            line = candidate;
+            continue;
+        }
+
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            if text_width(&line, font, size) > max_width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::take(&mut line));
+                line.push(c);
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,64 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use image::imageops::FilterType;
+
+const SAMPLE_SIZE: usize = 32;
+const HASH_SIZE: usize = 8;
+
+/// 64-bit perceptual hash from the low frequencies of the image's DCT.
+/// Resized or re-encoded copies of an image hash within a few bits of each
+/// other.
+pub fn compute(path: &Path) -> Result<u64, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?;
+    let gray = image
+        .resize_exact(SAMPLE_SIZE as u32, SAMPLE_SIZE as u32, FilterType::Triangle)
+        .to_luma8();
+
+    let pixels: Vec<f64> = gray.pixels().map(|pixel| pixel.0[0] as f64).collect();
+
+    // Only the top-left block of the 2D DCT is needed
+    let cosines: Vec<f64> = (0..HASH_SIZE)
+        .flat_map(|u| {
+            (0..SAMPLE_SIZE)
+                .map(move |x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * SAMPLE_SIZE) as f64).cos())
+        })
+        .collect();
+    let mut coefficients = [0f64; HASH_SIZE * HASH_SIZE];
+    for v in 0..HASH_SIZE {
+        for u in 0..HASH_SIZE {
+            let mut sum = 0.0;
+            for y in 0..SAMPLE_SIZE {
+                let row = cosines[v * SAMPLE_SIZE + y];
+                for x in 0..SAMPLE_SIZE {
+                    sum += pixels[y * SAMPLE_SIZE + x] * cosines[u * SAMPLE_SIZE + x] * row;
+                }
+            }
+            coefficients[v * HASH_SIZE + u] = sum;
+        }
+    }
+
+    // The DC term only reflects overall brightness, so it is left out of
+    // the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    Ok(coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| **value > median)
+        .fold(0u64, |hash, (bit, _)| hash | 1 << bit))
+}
+
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+pub fn to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn from_hex(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex, 16).ok()
+}
@@ -0,0 +1,204 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::settings::SettingsStore;
+
+// There is no portable power API, so each platform is asked its own way:
+// sysfs on Linux, pmset on macOS and the battery WMI class on Windows.
+// Anything that can't be queried counts as plugged in.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How background jobs treat the power supply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerMode {
+    /// Pause background jobs on battery below the threshold in settings,
+    /// when `pause_jobs_on_battery` is on.
+    #[default]
+    Auto,
+    /// Never pause them.
+    Performance,
+    /// Always pause them, plugged in or not.
+    Saver,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PowerStatus {
+    pub on_battery: bool,
+    /// Charge left, when the platform reports it.
+    pub percent: Option<u8>,
+}
+
+/// The power mode picked for this session and whether background jobs are
+/// currently held back.
+#[derive(Default)]
+pub struct Power {
+    mode: Mutex<PowerMode>,
+    paused: AtomicBool,
+}
+
+impl Power {
+    pub fn mode(&self) -> PowerMode {
+        *self.mode.lock().unwrap()
+    }
+
+    pub fn set_mode(&self, mode: PowerMode) {
+        *self.mode.lock().unwrap() = mode;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct PowerChanged {
+    on_battery: bool,
+    percent: Option<u8>,
+}
+
+/// Checks the power supply every minute, pausing or resuming background
+/// jobs as it changes.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            update(&app).await;
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+/// Decides whether background jobs should pause from the mode, settings
+/// and power supply. Emits `jobs_paused` or `jobs_resumed` when that
+/// changes. Returns the power supply's state.
+pub async fn update(app: &AppHandle) -> PowerStatus {
+    let status = tauri::async_runtime::spawn_blocking(status)
+        .await
+        .unwrap_or(PLUGGED_IN);
+
+    let settings = app.state::<SettingsStore>().get();
+    let power = app.state::<Power>();
+    let pause = match power.mode() {
+        PowerMode::Performance => false,
+        PowerMode::Saver => true,
+        PowerMode::Auto => {
+            settings.pause_jobs_on_battery
+                && status.on_battery
+                && status
+                    .percent
+                    .map_or(true, |percent| percent < settings.battery_threshold)
+        }
+    };
+
+    if power.paused.swap(pause, Ordering::Relaxed) != pause {
+        let event = if pause { "jobs_paused" } else { "jobs_resumed" };
+        log::info!(
+            "Background jobs {}",
+            if pause { "paused" } else { "resumed" }
+        );
+        let _ = app.emit(
+            event,
+            PowerChanged {
+                on_battery: status.on_battery,
+                percent: status.percent,
+            },
+        );
+    }
+    status
+}
+
+const PLUGGED_IN: PowerStatus = PowerStatus {
+    on_battery: false,
+    percent: None,
+};
+
+/// Asks the system whether it runs on battery, and how full that is.
+pub fn status() -> PowerStatus {
+    if cfg!(target_os = "linux") {
+        linux_status()
+    } else if cfg!(target_os = "macos") {
+        macos_status()
+    } else if cfg!(target_os = "windows") {
+        windows_status()
+    } else {
+        None
+    }
+    .unwrap_or(PLUGGED_IN)
+}
+
+// A mains supply that is online means plugged in, whatever the batteries say
+fn linux_status() -> Option<PowerStatus> {
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|text| text.trim().to_string())
+    };
+    let mut on_mains = false;
+    let mut percent = None;
+    let mut has_battery = false;
+    for entry in std::fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let path = entry.path();
+        match read(path.join("type")).as_deref() {
+            Some("Mains") | Some("USB") => {
+                on_mains |= read(path.join("online")).as_deref() == Some("1");
+            }
+            Some("Battery") => {
+                has_battery = true;
+                if let Some(capacity) = read(path.join("capacity")).and_then(|c| c.parse().ok()) {
+                    percent = Some(percent.map_or(capacity, |lowest: u8| lowest.min(capacity)));
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(PowerStatus {
+        on_battery: has_battery && !on_mains,
+        percent,
+    })
+}
+
+// `pmset -g batt` prints e.g. "Now drawing from 'Battery Power'" and a line
+// with "87%; discharging"
+fn macos_status() -> Option<PowerStatus> {
+    let output = Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let percent = text
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .find_map(|word| word.strip_suffix('%')?.parse().ok());
+    Some(PowerStatus {
+        on_battery: text.contains("'Battery Power'"),
+        percent,
+    })
+}
+
+// BatteryStatus 1 is discharging, 4 and 5 low and critical; the class is
+// empty on machines without a battery
+fn windows_status() -> Option<PowerStatus> {
+    let mut command = Command::new("powershell");
+    command.args([
+        "-NoProfile",
+        "-NonInteractive",
+        "-Command",
+        "Get-CimInstance Win32_Battery | ForEach-Object { \"$($_.BatteryStatus) $($_.EstimatedChargeRemaining)\" }",
+    ]);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // CREATE_NO_WINDOW, or a console flashes up every minute
+        command.creation_flags(0x0800_0000);
+    }
+    let output = command.output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut fields = text.lines().next()?.split_whitespace();
+    let battery_status: u32 = fields.next()?.parse().ok()?;
+    Some(PowerStatus {
+        on_battery: matches!(battery_status, 1 | 4 | 5),
+        percent: fields.next().and_then(|percent| percent.parse().ok()),
+    })
+}
@@ -0,0 +1,431 @@
+// QR code encoder for short links: byte mode, error correction level M,
+// versions 1 to 10 (up to 213 bytes). Follows ISO/IEC 18004.
+
+use image::{GrayImage, Luma};
+
+const MAX_VERSION: usize = 10;
+// Error correction codewords per block and number of blocks at level M,
+// indexed by version
+const ECC_PER_BLOCK: [usize; MAX_VERSION + 1] = [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26];
+const BLOCKS: [usize; MAX_VERSION + 1] = [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5];
+// Level M in the format information
+const FORMAT_ECC_BITS: u32 = 0;
+// Light border, in modules, that scanners need around the code
+const QUIET_ZONE: usize = 4;
+
+/// Square grid of modules, `true` being dark.
+pub struct QrCode {
+    size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+impl QrCode {
+    pub fn encode(data: &[u8]) -> Result<Self, String> {
+        let version = (1..=MAX_VERSION)
+            .find(|&version| data_bits(data.len(), version) <= data_codewords(version) * 8)
+            .ok_or_else(|| format!("Too much data for a QR code: {} bytes", data.len()))?;
+
+        let codewords = interleave(version, &data_codewords_for(data, version));
+
+        let mut qr = Self {
+            size: version * 4 + 17,
+            modules: Vec::new(),
+            is_function: Vec::new(),
+        };
+        qr.modules = vec![false; qr.size * qr.size];
+        qr.is_function = vec![false; qr.size * qr.size];
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&codewords);
+
+        // Keep the mask that scores the lowest penalty
+        let mut best = (u32::MAX, 0);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(mask);
+            let penalty = qr.penalty();
+            if penalty < best.0 {
+                best = (penalty, mask);
+            }
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.1);
+        qr.draw_format_bits(best.1);
+
+        Ok(qr)
+    }
+
+    fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// Renders the code with `scale` pixels per module and a quiet zone.
+    pub fn to_image(&self, scale: u32) -> GrayImage {
+        let modules = (self.size + QUIET_ZONE * 2) as u32;
+        GrayImage::from_fn(modules * scale, modules * scale, |x, y| {
+            let (x, y) = ((x / scale) as usize, (y / scale) as usize);
+            let inside = (QUIET_ZONE..QUIET_ZONE + self.size).contains(&x)
+                && (QUIET_ZONE..QUIET_ZONE + self.size).contains(&y);
+            let dark = inside && self.is_dark(x - QUIET_ZONE, y - QUIET_ZONE);
+            Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                    if (0..size as i32).contains(&xx) && (0..size as i32).contains(&yy) {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(xx as usize, yy as usize, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+
+        let positions = alignment_positions(version);
+        let count = positions.len();
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // Corners taken by the finder patterns
+                if [(0, 0), (0, count - 1), (count - 1, 0)].contains(&(i, j)) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        let dark = dx.abs().max(dy.abs()) != 1;
+                        self.set_function((x as i32 + dx) as usize, (y as i32 + dy) as usize, dark);
+                    }
+                }
+            }
+        }
+
+        // Reserve the format areas; the real bits go in once the mask is known
+        self.draw_format_bits(0);
+
+        if version >= 7 {
+            let mut remainder = version as u32;
+            for _ in 0..12 {
+                remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+            }
+            let bits = (version as u32) << 12 | remainder;
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 != 0;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let data = FORMAT_ECC_BITS << 3 | mask;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    // Zigzags up and down two-module columns from the right
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut index = 0;
+        let mut right = size as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = right as usize - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward {
+                        size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.is_function[y * size + x] && index < codewords.len() * 8 {
+                        self.modules[y * size + x] =
+                            (codewords[index >> 3] >> (7 - (index & 7))) & 1 != 0;
+                        index += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    // Applying a mask twice undoes it
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if invert && !self.is_function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    fn penalty(&self) -> u32 {
+        let size = self.size;
+        let mut penalty = 0;
+
+        // Runs of five or more, and finder-like patterns, in rows and columns
+        for transpose in [false, true] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size)
+                    .map(|b| {
+                        if transpose {
+                            self.is_dark(a, b)
+                        } else {
+                            self.is_dark(b, a)
+                        }
+                    })
+                    .collect();
+
+                let mut run = 1;
+                for b in 1..size {
+                    if line[b] == line[b - 1] {
+                        run += 1;
+                        if run == 5 {
+                            penalty += 3;
+                        } else if run > 5 {
+                            penalty += 1;
+                        }
+                    } else {
+                        run = 1;
+                    }
+                }
+
+                const FINDER: [bool; 7] = [true, false, true, true, true, false, true];
+                for b in 0..size.saturating_sub(6) {
+                    if line[b..b + 7] != FINDER {
+                        continue;
+                    }
+                    let light = |from: i32, to: i32| {
+                        (from..to).all(|i| i < 0 || i as usize >= size || !line[i as usize])
+                    };
+                    if light(b as i32 - 4, b as i32) || light(b as i32 + 7, b as i32 + 11) {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.is_dark(x, y);
+                if dark == self.is_dark(x + 1, y)
+                    && dark == self.is_dark(x, y + 1)
+                    && dark == self.is_dark(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().filter(|&&dark| dark).count();
+        let total = size * size;
+        // Each 5% away from an even balance costs 10
+        let deviation = (dark * 20).abs_diff(total * 10);
+        penalty += (deviation.div_ceil(total).saturating_sub(1) * 10) as u32;
+
+        penalty
+    }
+}
+
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_PER_BLOCK[version] * BLOCKS[version]
+}
+
+fn count_bits(version: usize) -> usize {
+    if version < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+fn data_bits(len: usize, version: usize) -> usize {
+    if len >= 1 << count_bits(version) {
+        return usize::MAX;
+    }
+    4 + count_bits(version) + len * 8
+}
+
+// Mode indicator, length and data, then the terminator and padding
+fn data_codewords_for(data: &[u8], version: usize) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    let mut push = |value: usize, count: usize| {
+        for i in (0..count).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len(), count_bits(version));
+    for &byte in data {
+        push(byte as usize, 8);
+    }
+
+    let capacity = data_codewords(version) * 8;
+    let terminator = (capacity - bits.len()).min(4);
+    bits.extend(std::iter::repeat(false).take(terminator));
+    bits.extend(std::iter::repeat(false).take((8 - bits.len() % 8) % 8));
+
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | bit as u8))
+        .collect();
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if codewords.len() >= data_codewords(version) {
+            break;
+        }
+        codewords.push(pad);
+    }
+    codewords
+}
+
+// Splits the data into blocks, adds each block's error correction and
+// interleaves the result
+fn interleave(version: usize, data: &[u8]) -> Vec<u8> {
+    let blocks = BLOCKS[version];
+    let ecc_len = ECC_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw_codewords % blocks;
+    let short_len = raw_codewords / blocks;
+    let divisor = reed_solomon_divisor(ecc_len);
+
+    let mut split = Vec::with_capacity(blocks);
+    let mut offset = 0;
+    for i in 0..blocks {
+        let data_len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let mut block = data[offset..offset + data_len].to_vec();
+        offset += data_len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        // Short blocks get a placeholder so every block has the same length
+        if i < short_blocks {
+            block.push(0);
+        }
+        block.extend(ecc);
+        split.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..split[0].len() {
+        for (j, block) in split.iter().enumerate() {
+            if i != short_len - ecc_len || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (value, &coefficient) in result.iter_mut().zip(divisor) {
+            *value ^= gf_multiply(coefficient, factor);
+        }
+    }
+    result
+}
+
+// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2;
+    let size = version * 4 + 17;
+
+    let mut positions = vec![6];
+    for i in (0..count - 1).rev() {
+        positions.push(size - 7 - i * step);
+    }
+    positions
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::db;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Serialize, sqlx::FromRow)]
+pub struct Reminder {
+    pub document_id: i64,
+    pub title: String,
+    pub remind_at: i64,
+}
+
+/// Checks for due reminders every 30 seconds, starting right away so the
+/// ones that elapsed while the app was closed fire on startup.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            // The pool only exists once the frontend has loaded the database
+            if let Ok(pool) = db::pool(&app).await {
+                if let Err(e) = fire_due(&app, &pool).await {
+                    log::warn!("Failed to check reminders: {}", e);
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+async fn fire_due(app: &AppHandle, pool: &sqlx::SqlitePool) -> Result<(), String> {
+    let due: Vec<Reminder> = sqlx::query_as(
+        "SELECT id AS document_id, title, remind_at FROM documents
+         WHERE remind_at <= ? AND reminder_fired = 0
+         ORDER BY remind_at ASC",
+    )
+    .bind(Utc::now().timestamp())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for reminder in due {
+        sqlx::query("UPDATE documents SET reminder_fired = 1 WHERE id = ?")
+            .bind(reminder.document_id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // The event lets an open window jump to the document; the native
+        // notification reaches the user when the window is in the background
+        let shown = app
+            .notification()
+            .builder()
+            .title("Reminder")
+            .body(&reminder.title)
+            .show();
+        if let Err(e) = shown {
+            log::warn!("Failed to show reminder notification: {}", e);
+        }
+        let _ = app.emit("reminder_due", reminder);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,297 @@
+pub mod s3;
+pub mod webdav;
+
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use url::Url;
+
+// Backup remotes over HTTP(S), through ureq with rustls. Errors are plain
+// messages; the backup commands wrap them into `AppError::Io`.
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+const CHUNK_SIZE: usize = 64 * 1024;
+const CANCELLED: &str = "Transfer cancelled";
+// Listings and checksum files are small; anything past this is cut off
+const MAX_TEXT: u64 = 16 * 1024 * 1024;
+
+/// A WebDAV collection or an S3 bucket, behind the same four operations.
+pub enum Client {
+    Webdav(webdav::Client),
+    S3(s3::Client),
+}
+
+impl Client {
+    /// Uploads `len` bytes from `body` as `name`, calling `progress` with
+    /// the bytes sent so far. `progress` returning `false` aborts.
+    pub fn put(
+        &self,
+        name: &str,
+        body: &mut dyn Read,
+        len: u64,
+        progress: &mut dyn FnMut(u64) -> bool,
+    ) -> Result<(), String> {
+        match self {
+            Client::Webdav(client) => client.put(name, body, len, progress),
+            Client::S3(client) => client.put(name, body, len, progress),
+        }
+    }
+
+    /// Downloads `name` into `sink`, returning the number of bytes written.
+    pub fn get(
+        &self,
+        name: &str,
+        sink: &mut dyn Write,
+        progress: &mut dyn FnMut(u64) -> bool,
+    ) -> Result<u64, String> {
+        match self {
+            Client::Webdav(client) => client.get(name, sink, progress),
+            Client::S3(client) => client.get(name, sink, progress),
+        }
+    }
+
+    /// `None` when the remote has no file of that name.
+    pub fn get_text(&self, name: &str) -> Result<Option<String>, String> {
+        match self {
+            Client::Webdav(client) => client.get_text(name),
+            Client::S3(client) => client.get_text(name),
+        }
+    }
+
+    /// Files directly in the collection or bucket, with their size when
+    /// the server reports one.
+    pub fn list(&self) -> Result<Vec<(String, Option<u64>)>, String> {
+        match self {
+            Client::Webdav(client) => client.list(),
+            Client::S3(client) => client.list(),
+        }
+    }
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(TIMEOUT)
+        .timeout_read(TIMEOUT)
+        .timeout_write(TIMEOUT)
+        .build()
+}
+
+// An http(s) URL, refusing to carry credentials in the clear: they go over
+// https, or over http only to a server on this machine, e.g. a TLS tunnel
+fn parse_url(url: &str, with_credentials: bool) -> Result<Url, String> {
+    let invalid = || format!("Invalid remote URL: {}", url);
+    let parsed = Url::parse(url).map_err(|_| invalid())?;
+    let host = parsed.host_str().filter(|host| !host.is_empty());
+    let Some(host) = host else {
+        return Err(invalid());
+    };
+    match parsed.scheme() {
+        "https" => {}
+        "http" if !with_credentials || is_loopback(host) => {}
+        "http" => {
+            return Err(format!(
+                "Refusing to send credentials to {} over unencrypted http://; \
+                 use https://, or reach it through a TLS tunnel on localhost",
+                host
+            ))
+        }
+        _ => return Err(invalid()),
+    }
+    Ok(parsed)
+}
+
+fn is_loopback(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+// The response to a request, or a message saying why there is none
+fn response(
+    result: Result<ureq::Response, ureq::Error>,
+    action: &str,
+) -> Result<ureq::Response, String> {
+    match result {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(status, _)) => Err(status_message(action, status)),
+        Err(ureq::Error::Transport(e)) => Err(format!("Remote {} failed: {}", action, e)),
+    }
+}
+
+fn status_message(action: &str, status: u16) -> String {
+    match status {
+        401 | 403 => format!("Remote refused the {}: check the credentials", action),
+        404 => format!("Remote {} failed: not found", action),
+        status => format!("Remote {} failed with HTTP {}", action, status),
+    }
+}
+
+// Counts what is read through it, for upload progress
+struct ProgressReader<'a> {
+    inner: &'a mut dyn Read,
+    total: u64,
+    progress: &'a mut dyn FnMut(u64) -> bool,
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.total += read as u64;
+        if read > 0 && !(self.progress)(self.total) {
+            return Err(io::Error::other(CANCELLED));
+        }
+        Ok(read)
+    }
+}
+
+fn copy(
+    source: &mut dyn Read,
+    sink: &mut dyn Write,
+    mut total: u64,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<u64, String> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = source.read(&mut buffer).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Ok(total);
+        }
+        sink.write_all(&buffer[..read]).map_err(|e| e.to_string())?;
+        total += read as u64;
+        if !progress(total) {
+            return Err(CANCELLED.to_string());
+        }
+    }
+}
+
+// A response body as text, up to MAX_TEXT bytes
+fn read_text(response: ureq::Response) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_TEXT)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Text of the first `<name>` element in `xml`, whatever its namespace
+// prefix, with the five predefined entities decoded
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("{}>", name))? + name.len() + 1;
+    let end = start + xml[start..].find('<')?;
+    Some(
+        xml[start..end]
+            .trim()
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+// Everything but RFC 3986's unreserved characters, as S3 signing wants it
+fn percent_encode(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_only_go_over_https_or_to_loopback_hosts() {
+        for url in [
+            "https://nas.local/dav",
+            "https://192.168.1.10:8443/dav",
+            "http://localhost/dav/",
+            "http://LOCALHOST:8080/dav",
+            "http://127.0.0.1:8080/dav",
+            "http://127.4.5.6/dav",
+            "http://[::1]:8080/dav",
+        ] {
+            assert!(parse_url(url, true).is_ok(), "{}", url);
+        }
+        for url in [
+            "http://nas.local/dav",
+            "http://192.168.1.10:8080/dav",
+            "http://localhost.example.com/dav",
+        ] {
+            assert!(parse_url(url, true).is_err(), "{}", url);
+            assert!(parse_url(url, false).is_ok(), "{}", url);
+        }
+        for url in ["ftp://nas.local/dav", "nas.local/dav", "file:///dav", ""] {
+            assert!(parse_url(url, false).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn names_round_trip_through_percent_encoding() {
+        let name = "ando backup 2024 é%.andoarchive";
+        assert_eq!(percent_decode(&percent_encode(name)), name);
+        assert_eq!(percent_encode("a b/c"), "a%20b%2Fc");
+    }
+
+    #[test]
+    fn element_text_ignores_prefixes_and_decodes_entities() {
+        let xml = "<d:response><d:href>/dav/a&amp;b</d:href></d:response>";
+        assert_eq!(element_text(xml, "href").as_deref(), Some("/dav/a&b"));
+        assert_eq!(element_text("<Key> x </Key>", "Key").as_deref(), Some("x"));
+        assert_eq!(element_text("<Key>x</Key>", "Size"), None);
+    }
+
+    #[test]
+    fn progress_reader_counts_and_cancels() {
+        let data = vec![7u8; 10];
+        let mut seen = Vec::new();
+        let mut source = data.as_slice();
+        let mut reader = ProgressReader {
+            inner: &mut source,
+            total: 0,
+            progress: &mut |total| {
+                seen.push(total);
+                true
+            },
+        };
+        let mut buffer = [0; 4];
+        while reader.read(&mut buffer).unwrap() > 0 {}
+        assert_eq!(seen, [4, 8, 10]);
+
+        let mut source = data.as_slice();
+        let mut reader = ProgressReader {
+            inner: &mut source,
+            total: 0,
+            progress: &mut |total| total < 8,
+        };
+        assert!(reader.read(&mut buffer).is_ok());
+        assert!(reader.read(&mut buffer).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{Read, Write};
+
+use base64::Engine;
+use url::Url;
+
+use super::{
+    agent, copy, element_text, parse_url, percent_decode, percent_encode, read_text, response,
+    ProgressReader,
+};
+
+/// WebDAV client, enough to put, get and list files in one collection.
+/// Credentials are sent over `https://`, or over `http://` only to a server
+/// on this machine: anywhere else they would cross the network in the
+/// clear.
+pub struct Client {
+    agent: ureq::Agent,
+    // Collection URL, its path always ending in `/`
+    collection: Url,
+    authorization: Option<String>,
+}
+
+impl Client {
+    pub fn new(url: &str, username: &str, password: &str) -> Result<Self, String> {
+        let mut collection = parse_url(url, !username.is_empty())?;
+        if !collection.path().ends_with('/') {
+            let path = format!("{}/", collection.path());
+            collection.set_path(&path);
+        }
+        let authorization = (!username.is_empty()).then(|| {
+            let token = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", username, password));
+            format!("Basic {}", token)
+        });
+
+        Ok(Self {
+            agent: agent(),
+            collection,
+            authorization,
+        })
+    }
+
+    pub fn put(
+        &self,
+        name: &str,
+        body: &mut dyn Read,
+        len: u64,
+        progress: &mut dyn FnMut(u64) -> bool,
+    ) -> Result<(), String> {
+        let body = ProgressReader {
+            inner: &mut body.take(len),
+            total: 0,
+            progress,
+        };
+        let result = self
+            .request("PUT", name)
+            .set("Content-Length", &len.to_string())
+            .send(body);
+        response(result, "upload")?;
+        Ok(())
+    }
+
+    pub fn get(
+        &self,
+        name: &str,
+        sink: &mut dyn Write,
+        progress: &mut dyn FnMut(u64) -> bool,
+    ) -> Result<u64, String> {
+        let response = response(self.request("GET", name).call(), "download")?;
+        copy(&mut response.into_reader(), sink, 0, progress)
+    }
+
+    pub fn get_text(&self, name: &str) -> Result<Option<String>, String> {
+        match self.request("GET", name).call() {
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            result => {
+                let response = response(result, "download")?;
+                read_text(response).map(Some)
+            }
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<(String, Option<u64>)>, String> {
+        let request = r#"<?xml version="1.0" encoding="utf-8"?><propfind xmlns="DAV:"><prop><getcontentlength/></prop></propfind>"#;
+        let result = self
+            .request("PROPFIND", "")
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml")
+            .send_string(request);
+        let xml = read_text(response(result, "list")?)?;
+        Ok(parse_listing(&xml))
+    }
+
+    fn request(&self, method: &str, name: &str) -> ureq::Request {
+        let url = format!("{}{}", self.collection, percent_encode(name));
+        let request = self.agent.request(method, &url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+}
+
+// Files in a PROPFIND multistatus, skipping the collection itself and any
+// subcollections
+fn parse_listing(xml: &str) -> Vec<(String, Option<u64>)> {
+    let mut files = Vec::new();
+    for block in xml
+        .split("response>")
+        .filter(|block| block.contains("href>"))
+    {
+        let Some(href) = element_text(block, "href") else {
+            continue;
+        };
+        let href = percent_decode(&href);
+        if href.ends_with('/') {
+            continue;
+        }
+        let name = href.rsplit('/').next().unwrap_or_default().to_string();
+        let size = element_text(block, "getcontentlength").and_then(|size| size.parse().ok());
+        files.push((name, size));
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorization_is_only_set_with_a_username() {
+        let client = Client::new("https://nas.local/dav", "me", "secret").unwrap();
+        assert_eq!(client.authorization.as_deref(), Some("Basic bWU6c2VjcmV0"));
+        assert_eq!(client.collection.as_str(), "https://nas.local/dav/");
+        let client = Client::new("http://nas.local", "", "").unwrap();
+        assert!(client.authorization.is_none());
+        assert_eq!(client.collection.as_str(), "http://nas.local/");
+        assert!(Client::new("http://nas.local/dav", "me", "secret").is_err());
+    }
+
+    #[test]
+    fn listing_keeps_files_only() {
+        let xml = r#"<?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:">
+              <d:response><d:href>/dav/</d:href><d:propstat><d:prop/></d:propstat></d:response>
+              <d:response><d:href>/dav/old/</d:href></d:response>
+              <d:response>
+                <d:href>/dav/ando%20backup.andoarchive</d:href>
+                <d:propstat><d:prop><d:getcontentlength>1234</d:getcontentlength></d:prop></d:propstat>
+              </d:response>
+              <d:response><d:href>/dav/notes.txt</d:href></d:response>
+            </d:multistatus>"#;
+        assert_eq!(
+            parse_listing(xml),
+            [
+                ("ando backup.andoarchive".to_string(), Some(1234)),
+                ("notes.txt".to_string(), None),
+            ]
+        );
+    }
+}
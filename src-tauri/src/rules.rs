@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::documents::trash_documents;
+use crate::db;
+use crate::error::{AppError, CmdResult};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Documents one rule handles per pass, so a large backlog is worked off
+// in steps instead of holding the database at startup
+const MAX_DOCUMENTS_PER_PASS: i64 = 200;
+// Pause before the next step of a backlog
+const BACKLOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What happens to documents directly in a category once they are older
+/// than `older_than_days`, counted from their creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CategoryRule {
+    /// Moved to another category.
+    Archive {
+        older_than_days: u32,
+        target_category_id: i64,
+    },
+    /// Moved to the trash, where `restore_document` can bring them back.
+    Trash { older_than_days: u32 },
+}
+
+impl CategoryRule {
+    pub fn older_than_days(&self) -> u32 {
+        match self {
+            CategoryRule::Archive {
+                older_than_days, ..
+            }
+            | CategoryRule::Trash { older_than_days } => *older_than_days,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct RuleApplied {
+    pub category_id: i64,
+    pub rule: CategoryRule,
+    pub document_ids: Vec<i64>,
+    /// More documents matched than one pass handles; they follow shortly.
+    pub pending: bool,
+}
+
+/// Applies the category rules at startup and then every hour, emitting
+/// `rules_applied` with whatever they did.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let pool = db::wait_for_pool(&app).await;
+        loop {
+            let pending = match apply(&app, &pool).await {
+                Ok(applied) => applied.iter().any(|applied| applied.pending),
+                Err(e) => {
+                    log::warn!("Failed to apply category rules: {}", e);
+                    false
+                }
+            };
+            let interval = if pending {
+                BACKLOG_INTERVAL
+            } else {
+                CHECK_INTERVAL
+            };
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// One pass over every rule. Documents a rule handled no longer match it,
+/// so passes can be repeated safely.
+pub async fn apply(app: &AppHandle, pool: &SqlitePool) -> CmdResult<Vec<RuleApplied>> {
+    let rules: Vec<(i64, String)> =
+        sqlx::query_as("SELECT category_id, rule FROM category_rules ORDER BY category_id")
+            .fetch_all(pool)
+            .await?;
+
+    let mut applied = Vec::new();
+    for (category_id, rule) in rules {
+        let rule: CategoryRule = match serde_json::from_str(&rule) {
+            Ok(rule) => rule,
+            Err(e) => {
+                log::warn!("Skipping invalid rule of category {}: {}", category_id, e);
+                continue;
+            }
+        };
+        match apply_rule(pool, category_id, &rule).await {
+            Ok(Some(result)) => applied.push(result),
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to apply rule of category {}: {}", category_id, e),
+        }
+    }
+
+    if !applied.is_empty() {
+        let _ = app.emit("rules_applied", &applied);
+    }
+    Ok(applied)
+}
+
+async fn apply_rule(
+    pool: &SqlitePool,
+    category_id: i64,
+    rule: &CategoryRule,
+) -> CmdResult<Option<RuleApplied>> {
+    let mut tx = pool.begin().await?;
+
+    let mut ids: Vec<i64> = sqlx::query_as::<_, (i64,)>(
+        "SELECT id FROM documents
+         WHERE category_id = ? AND created_at < datetime('now', ?)
+         ORDER BY created_at ASC, id ASC
+         LIMIT ?",
+    )
+    .bind(category_id)
+    .bind(format!("-{} days", rule.older_than_days()))
+    .bind(MAX_DOCUMENTS_PER_PASS + 1)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|(id,)| id)
+    .collect();
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    let pending = ids.len() as i64 > MAX_DOCUMENTS_PER_PASS;
+    ids.truncate(MAX_DOCUMENTS_PER_PASS as usize);
+
+    match rule {
+        CategoryRule::Archive {
+            target_category_id, ..
+        } => {
+            let target: Option<(i64,)> = sqlx::query_as("SELECT id FROM categories WHERE id = ?")
+                .bind(target_category_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if target.is_none() {
+                return Err(AppError::NotFound(
+                    "Archive category no longer exists".to_string(),
+                ));
+            }
+            for id in &ids {
+                sqlx::query("UPDATE documents SET category_id = ? WHERE id = ?")
+                    .bind(target_category_id)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        CategoryRule::Trash { .. } => trash_documents(&mut tx, &ids).await?,
+    }
+    tx.commit().await?;
+
+    Ok(Some(RuleApplied {
+        category_id,
+        rule: rule.clone(),
+        document_ids: ids,
+        pending,
+    }))
+}
@@ -0,0 +1,172 @@
+use dom_query::Document;
+use serde::Deserialize;
+
+// Elements dropped with everything inside them
+const REMOVED_ELEMENTS: &str =
+    "script, style, noscript, iframe, frame, object, embed, link, meta, base, template, form";
+// Attributes that survive; everything else (event handlers, inline styles,
+// classes, data and tracking attributes) goes
+const KEPT_ATTRIBUTES: &[&str] = &[
+    "href", "src", "alt", "title", "colspan", "rowspan", "start", "align",
+];
+// Query parameters of links that only serve tracking
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SanitizeTarget {
+    /// Cleaned-up HTML the editor keeps working with.
+    #[default]
+    Html,
+    /// Markdown text.
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct SanitizeOptions {
+    pub target: SanitizeTarget,
+}
+
+/// Cleans a document body: drops scripts, styles, embeds, tracking pixels
+/// and every attribute not needed to render it, strips tracking parameters
+/// from links and collapses runs of whitespace.
+pub fn sanitize(html: &str, options: SanitizeOptions) -> String {
+    let document = Document::fragment(html);
+    // Fragments parse into an `html` element holding the content
+    let root = document.html_root();
+
+    document.select(REMOVED_ELEMENTS).remove();
+    // 1x1 images are tracking pixels, not content
+    document
+        .select(r#"img[width="1"], img[height="1"]"#)
+        .remove();
+    // Presentational wrappers go, their content stays
+    root.strip_elements(&["font", "span", "center"]);
+
+    for node in root.descendants().iter().filter(|node| node.is_element()) {
+        let unsafe_link = ["href", "src"].iter().any(|name| {
+            node.attr(name).is_some_and(|value| {
+                let value = value.trim_start().to_ascii_lowercase();
+                value.starts_with("javascript:") || value.starts_with("vbscript:")
+            })
+        });
+        if unsafe_link {
+            node.remove_attrs(&["href", "src"]);
+        }
+        if let Some(href) = node.attr("href") {
+            node.set_attr("href", &strip_tracking(&href));
+        }
+
+        let dropped: Vec<String> = node
+            .attrs()
+            .iter()
+            .map(|attr| attr.name.local.to_string())
+            .filter(|name| !KEPT_ATTRIBUTES.contains(&name.as_str()))
+            .collect();
+        let dropped: Vec<&str> = dropped.iter().map(String::as_str).collect();
+        node.remove_attrs(&dropped);
+    }
+
+    match options.target {
+        SanitizeTarget::Html => collapse_whitespace(&root.inner_html()),
+        SanitizeTarget::Markdown => collapse_blank_lines(&document.md(None)),
+    }
+}
+
+// Drops `utm_*` and the other tracking parameters from a URL's query
+fn strip_tracking(url: &str) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let Some((base, query)) = url.split_once('?') else {
+        return match fragment {
+            Some(fragment) => format!("{}#{}", url, fragment),
+            None => url.to_string(),
+        };
+    };
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let name = param
+                .split('=')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            !name.is_empty()
+                && !name.starts_with("utm_")
+                && !TRACKING_PARAMS.contains(&name.as_str())
+        })
+        .collect();
+
+    let mut cleaned = base.to_string();
+    if !kept.is_empty() {
+        cleaned.push('?');
+        cleaned.push_str(&kept.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        cleaned.push('#');
+        cleaned.push_str(fragment);
+    }
+    cleaned
+}
+
+// Turns every whitespace run in text into one space, except inside `<pre>`
+fn collapse_whitespace(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut pre_depth = 0usize;
+    let mut rest = html;
+    while !rest.is_empty() {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        let text = &rest[..text_end];
+        if pre_depth > 0 {
+            output.push_str(text);
+        } else {
+            let mut last_space = output.ends_with(|c: char| c.is_whitespace());
+            for c in text.chars() {
+                if c.is_whitespace() {
+                    if !last_space {
+                        output.push(' ');
+                    }
+                    last_space = true;
+                } else {
+                    output.push(c);
+                    last_space = false;
+                }
+            }
+        }
+        rest = &rest[text_end..];
+
+        let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+        let tag = rest[..tag_end].to_ascii_lowercase();
+        if tag.starts_with("<pre") {
+            pre_depth += 1;
+        } else if tag.starts_with("</pre") {
+            pre_depth = pre_depth.saturating_sub(1);
+        }
+        output.push_str(&rest[..tag_end]);
+        rest = &rest[tag_end..];
+    }
+    output.trim().to_string()
+}
+
+// At most one blank line between Markdown blocks, no trailing spaces
+fn collapse_blank_lines(markdown: &str) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    let mut blank_lines = 0;
+    for line in markdown.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !output.is_empty() {
+            output.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        output.push_str(line);
+        blank_lines = 0;
+    }
+    output
+}
@@ -0,0 +1,84 @@
+use crate::error::{AppError, CmdResult};
+
+// Secrets go to the OS keyring through the keyring crate: the Keychain on
+// macOS, the Credential Manager on Windows and the Secret Service (GNOME
+// Keyring, KWallet) on Linux
+
+const SERVICE: &str = "ando-archive";
+
+/// Returned when the platform has no usable keyring, so callers can fall
+/// back to asking for the secret every time.
+pub const UNAVAILABLE: &str = "No OS keyring available on this system";
+
+pub fn set(key: &str, value: &str) -> CmdResult<()> {
+    entry(key)?.set_password(value).map_err(keyring_error)
+}
+
+/// `None` when nothing is stored under `key`.
+pub fn get(key: &str) -> CmdResult<Option<String>> {
+    match entry(key)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(keyring_error(e)),
+    }
+}
+
+/// Deleting a secret that isn't there is not an error.
+pub fn delete(key: &str) -> CmdResult<()> {
+    match entry(key)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(keyring_error(e)),
+    }
+}
+
+fn entry(key: &str) -> CmdResult<keyring::Entry> {
+    check_key(key)?;
+    keyring::Entry::new(SERVICE, key).map_err(keyring_error)
+}
+
+fn keyring_error(e: keyring::Error) -> AppError {
+    match e {
+        // No keyring daemon, a locked keyring the user declined to unlock,
+        // or a platform without a backend
+        keyring::Error::NoStorageAccess(e) | keyring::Error::PlatformFailure(e) => {
+            AppError::Internal(format!("{}: {}", UNAVAILABLE, e))
+        }
+        keyring::Error::TooLong(..) | keyring::Error::Invalid(..) => {
+            AppError::Validation(e.to_string())
+        }
+        e => AppError::Internal(format!("Keyring error: {}", e)),
+    }
+}
+
+fn check_key(key: &str) -> CmdResult<()> {
+    if key.trim().is_empty() {
+        return Err(AppError::Validation(
+            "Secret key cannot be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_keys_are_rejected_before_the_keyring() {
+        for key in ["", "  "] {
+            assert!(matches!(get(key), Err(AppError::Validation(_))));
+            assert!(matches!(set(key, "value"), Err(AppError::Validation(_))));
+            assert!(matches!(delete(key), Err(AppError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn missing_keyrings_say_so() {
+        let e = keyring_error(keyring::Error::NoStorageAccess("locked".into()));
+        assert!(matches!(e, AppError::Internal(message) if message.starts_with(UNAVAILABLE)));
+        let e = keyring_error(keyring::Error::PlatformFailure("no daemon".into()));
+        assert!(matches!(e, AppError::Internal(message) if message.starts_with(UNAVAILABLE)));
+        let e = keyring_error(keyring::Error::TooLong("password".to_string(), 2560));
+        assert!(matches!(e, AppError::Validation(_)));
+    }
+}
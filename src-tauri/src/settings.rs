@@ -0,0 +1,423 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::collation;
+use crate::commands::archive::ExportTarget;
+use crate::editor;
+use crate::encoding::{self, LineEnding};
+use crate::error::{AppError, CmdResult};
+use crate::markdown::MarkdownOptions;
+use crate::menu;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub thumbnail_size: u32,
+    /// Least recently used thumbnails are evicted past this size; unbounded
+    /// when unset.
+    pub thumbnail_cache_mb: Option<u32>,
+    pub watched_folders: Vec<WatchedFolder>,
+    /// Menu item id to accelerator, overriding the built-in shortcut.
+    pub keybindings: BTreeMap<String, String>,
+    /// Destructive commands fail unless called with `confirmed: true`.
+    pub confirm_destructive: bool,
+    /// Run light maintenance after `idle_minutes` without user activity.
+    pub idle_maintenance: bool,
+    pub idle_minutes: u32,
+    pub search: SearchOptions,
+    /// Extensions `render_markdown` and the exports render Markdown with.
+    pub markdown: MarkdownOptions,
+    /// Where `upload_backup` last sent a backup. Credentials are never
+    /// stored here.
+    pub backup_remote: Option<RemoteTarget>,
+    /// Keyring entry holding the backup remote's credentials, if they were
+    /// remembered.
+    pub backup_secret: Option<String>,
+    /// Record command timings for `query_metrics`.
+    pub debug_metrics: bool,
+    /// Command `open_in_external_editor` runs with the file appended, e.g.
+    /// `code --wait`.
+    pub external_editor: Option<String>,
+    /// Offset from UTC used to bucket dates by local day, in minutes. The
+    /// system's current offset when unset.
+    pub utc_offset_minutes: Option<i32>,
+    /// Open tabs remembered across restarts; the oldest go first.
+    pub max_tabs: u32,
+    /// Unlocks `run_maintenance_sql`.
+    pub developer_mode: bool,
+    /// SQLite page cache per connection, in MiB. SQLite's default, about
+    /// 2 MiB, when unset.
+    pub db_cache_mb: Option<u32>,
+    /// How much of the database file SQLite may memory-map, in MiB.
+    /// SQLite's default, usually none, when unset.
+    pub db_mmap_mb: Option<u32>,
+    /// BCP 47 tag, e.g. `sv-SE`, whose alphabet orders titles; case and
+    /// accents are folded away when unset. Applied at launch or through
+    /// `set_collation_locale`.
+    pub collation_locale: Option<String>,
+    /// How much each signal counts in `related_documents`.
+    pub related: RelatedWeights,
+    /// Hold background jobs back while on battery below
+    /// `battery_threshold` percent; jobs the user started still run.
+    pub pause_jobs_on_battery: bool,
+    pub battery_threshold: u8,
+    /// Soft limit on the archive's size; writes past it only warn.
+    pub max_archive_mb: Option<u32>,
+    /// Record how long documents stay open, for `most_viewed` and
+    /// `least_viewed`. Nothing leaves this machine either way.
+    pub track_view_time: bool,
+    /// Serve read-only stats and search on 127.0.0.1 at `local_api_port`,
+    /// to requests with the token from `get_local_api_token`.
+    pub enable_local_api: bool,
+    pub local_api_port: u16,
+    /// Command `describe_image` runs with the image appended, whose output
+    /// becomes the description. Built from EXIF data and OCR text when
+    /// unset.
+    pub image_describer: Option<String>,
+    /// Bodies longer than this, in characters, slow lists and search down;
+    /// saving one warns, or with `strict_body_limit` fails. `None` for no
+    /// limit.
+    pub max_body_chars: Option<u32>,
+    pub strict_body_limit: bool,
+    /// Line break of exported Markdown, text, HTML, CSV and patch files.
+    pub export_line_ending: LineEnding,
+    /// Write every line break of exported text as `export_line_ending`;
+    /// off, text keeps the breaks it was stored with.
+    pub normalize_line_endings: bool,
+    /// Where `export` last wrote to, offered again next time.
+    pub last_export_target: Option<ExportTarget>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RemoteTarget {
+    /// A WebDAV collection, e.g. `https://nas.local/remote.php/dav/backups`.
+    /// Over plain `http://`, one needing a login has to be on this machine,
+    /// e.g. a TLS tunnel like `http://localhost:8080/dav/backups`.
+    Webdav { url: String },
+    /// An S3-compatible bucket, addressed path-style under `endpoint`, e.g.
+    /// `https://s3.eu-central-1.amazonaws.com`. An empty region signs for
+    /// `us-east-1`, which most other stores accept.
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+    },
+}
+
+/// Full-text tokenizer options, applied by `rebuild_search_index`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// `unicode61` diacritics mode: 0 keeps them, 1 and 2 fold accented
+    /// letters onto their base letter (2 also handles combining marks).
+    pub remove_diacritics: u8,
+    /// Extra characters treated as part of a word, e.g. `-_`.
+    pub tokenchars: String,
+    /// Words dropped from search queries.
+    pub stopwords: Vec<String>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            remove_diacritics: 1,
+            tokenchars: String::new(),
+            stopwords: Vec::new(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// The FTS5 `tokenize` option these settings describe.
+    pub fn tokenizer(&self) -> CmdResult<String> {
+        if self.remove_diacritics > 2 {
+            return Err(AppError::Validation(
+                "remove_diacritics must be 0, 1 or 2".to_string(),
+            ));
+        }
+        if let Some(c) = self
+            .tokenchars
+            .chars()
+            .find(|c| c.is_whitespace() || c.is_control() || *c == '\'' || *c == '"')
+        {
+            return Err(AppError::Validation(format!(
+                "Invalid character in tokenchars: {:?}",
+                c
+            )));
+        }
+
+        let mut tokenizer = format!("unicode61 remove_diacritics {}", self.remove_diacritics);
+        if !self.tokenchars.is_empty() {
+            tokenizer.push_str(&format!(" tokenchars '{}'", self.tokenchars));
+        }
+        Ok(tokenizer)
+    }
+}
+
+/// Weights of the signals `related_documents` scores by; 0 turns one off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelatedWeights {
+    /// Share of tags in common.
+    pub tags: f64,
+    /// Being in the same category.
+    pub category: f64,
+    /// A link from either document to the other.
+    pub links: f64,
+    /// Overlap of the words in title and body.
+    pub terms: f64,
+}
+
+impl Default for RelatedWeights {
+    fn default() -> Self {
+        Self {
+            tags: 1.0,
+            category: 0.5,
+            links: 2.0,
+            terms: 1.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedFolder {
+    pub path: String,
+    pub category_id: i64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            thumbnail_size: 256,
+            thumbnail_cache_mb: Some(512),
+            watched_folders: Vec::new(),
+            keybindings: BTreeMap::new(),
+            confirm_destructive: true,
+            idle_maintenance: false,
+            idle_minutes: 10,
+            search: SearchOptions::default(),
+            markdown: MarkdownOptions::default(),
+            backup_remote: None,
+            backup_secret: None,
+            debug_metrics: false,
+            external_editor: None,
+            utc_offset_minutes: None,
+            max_tabs: 20,
+            developer_mode: false,
+            db_cache_mb: None,
+            db_mmap_mb: None,
+            collation_locale: None,
+            related: RelatedWeights::default(),
+            pause_jobs_on_battery: true,
+            battery_threshold: 50,
+            max_archive_mb: None,
+            track_view_time: true,
+            enable_local_api: false,
+            local_api_port: 47_615,
+            image_describer: None,
+            // Around a thousand printed pages
+            max_body_chars: Some(2_000_000),
+            strict_body_limit: false,
+            export_line_ending: LineEnding::Lf,
+            normalize_line_endings: true,
+            last_export_target: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> CmdResult<()> {
+        if !(32..=2048).contains(&self.thumbnail_size) {
+            return Err(AppError::Validation(
+                "thumbnail_size must be between 32 and 2048".to_string(),
+            ));
+        }
+        if self.thumbnail_cache_mb == Some(0) {
+            return Err(AppError::Validation(
+                "thumbnail_cache_mb must be at least 1".to_string(),
+            ));
+        }
+        if !(1..=1440).contains(&self.idle_minutes) {
+            return Err(AppError::Validation(
+                "idle_minutes must be between 1 and 1440".to_string(),
+            ));
+        }
+        self.search.tokenizer()?;
+        if !(1..=100).contains(&self.max_tabs) {
+            return Err(AppError::Validation(
+                "max_tabs must be between 1 and 100".to_string(),
+            ));
+        }
+        if let Some(offset) = self.utc_offset_minutes {
+            if !(-14 * 60..=14 * 60).contains(&offset) {
+                return Err(AppError::Validation(
+                    "utc_offset_minutes must be between -840 and 840".to_string(),
+                ));
+            }
+        }
+        if let Some(cache_mb) = self.db_cache_mb {
+            if !(1..=4096).contains(&cache_mb) {
+                return Err(AppError::Validation(
+                    "db_cache_mb must be between 1 and 4096".to_string(),
+                ));
+            }
+        }
+        if self.local_api_port < 1024 {
+            return Err(AppError::Validation(
+                "local_api_port must be between 1024 and 65535".to_string(),
+            ));
+        }
+        if self.max_body_chars.is_some_and(|chars| chars < 1000) {
+            return Err(AppError::Validation(
+                "max_body_chars must be at least 1000".to_string(),
+            ));
+        }
+        if self.max_archive_mb == Some(0) {
+            return Err(AppError::Validation(
+                "max_archive_mb must be at least 1".to_string(),
+            ));
+        }
+        if !(1..=100).contains(&self.battery_threshold) {
+            return Err(AppError::Validation(
+                "battery_threshold must be between 1 and 100".to_string(),
+            ));
+        }
+        if self.db_mmap_mb.is_some_and(|mmap_mb| mmap_mb > 16384) {
+            return Err(AppError::Validation(
+                "db_mmap_mb must be at most 16384".to_string(),
+            ));
+        }
+        if let Some(locale) = &self.collation_locale {
+            if !collation::is_valid_locale(locale) {
+                return Err(AppError::Validation(format!(
+                    "{:?} is not a locale like sv-SE",
+                    locale
+                )));
+            }
+        }
+        let weights = &self.related;
+        if [weights.tags, weights.category, weights.links, weights.terms]
+            .iter()
+            .any(|weight| !(0.0..=100.0).contains(weight))
+        {
+            return Err(AppError::Validation(
+                "related weights must be between 0 and 100".to_string(),
+            ));
+        }
+        if let Some(editor) = &self.external_editor {
+            if editor::split_command(editor).is_empty() {
+                return Err(AppError::Validation(
+                    "external_editor must not be empty".to_string(),
+                ));
+            }
+        }
+        if let Some(describer) = &self.image_describer {
+            if editor::split_command(describer).is_empty() {
+                return Err(AppError::Validation(
+                    "image_describer must not be empty".to_string(),
+                ));
+            }
+        }
+        for (id, accelerator) in &self.keybindings {
+            if !menu::BINDABLE_ITEMS.contains(&id.as_str()) {
+                return Err(AppError::Validation(format!(
+                    "Unknown menu item in keybindings: {}",
+                    id
+                )));
+            }
+            if accelerator.trim().is_empty() {
+                return Err(AppError::Validation(format!("Empty keybinding for {}", id)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Offset from UTC of the user's local time, in minutes.
+    pub fn utc_offset_minutes(&self) -> i32 {
+        self.utc_offset_minutes
+            .unwrap_or_else(|| Local::now().offset().local_minus_utc() / 60)
+    }
+
+    /// Gate for destructive commands, so every frontend has to ask the user
+    /// before calling them.
+    pub fn require_confirmation(&self, confirmed: Option<bool>) -> CmdResult<()> {
+        if self.confirm_destructive && confirmed != Some(true) {
+            return Err(AppError::ConfirmationRequired(
+                "confirmation required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Exported text with its line breaks as configured.
+    pub fn export_text(&self, text: String) -> String {
+        if self.normalize_line_endings {
+            encoding::normalize_line_endings(&text, self.export_line_ending)
+        } else {
+            text
+        }
+    }
+}
+
+/// Settings persisted as `settings.json` in the app config dir.
+pub struct SettingsStore {
+    path: PathBuf,
+    current: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Reads the settings file, falling back to defaults when it is missing
+    /// or unreadable.
+    pub fn load(path: PathBuf) -> Self {
+        let settings = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                log::warn!("Ignoring invalid settings file: {}", e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        };
+
+        Self {
+            path,
+            current: Mutex::new(settings),
+        }
+    }
+
+    /// Keys present in the settings file, as opposed to ones left at their
+    /// default.
+    pub fn stored_keys(&self) -> HashSet<String> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|json| {
+                serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&json).ok()
+            })
+            .map(|fields| fields.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self) -> Settings {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Fails with `validation` for settings out of range, before anything
+    /// is written.
+    pub fn replace(&self, settings: Settings) -> CmdResult<()> {
+        settings.validate()?;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&settings)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        fs::write(&self.path, json)?;
+
+        *self.current.lock().unwrap() = settings;
+        Ok(())
+    }
+}
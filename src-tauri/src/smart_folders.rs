@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+use crate::commands::documents::{escape_like, to_sql_datetime};
+use crate::error::{AppError, CmdResult};
+
+/// Saved search of a smart folder, stored as JSON in `smart_folders.filter`.
+/// Every field that is set has to match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartFilter {
+    /// Matched against title, description and body like the search box.
+    pub text: Option<String>,
+    pub category_id: Option<i64>,
+    /// Documents must carry all of these tags.
+    pub tags: Vec<String>,
+    /// Creation time bounds in unix seconds, both inclusive.
+    pub created_after: Option<i64>,
+    pub created_before: Option<i64>,
+    /// Attachment content type, a prefix ending in `/` or an exact type.
+    pub attachment_type: Option<String>,
+    pub needs_review: Option<bool>,
+    pub is_read: Option<bool>,
+}
+
+/// Name of the smart folder and the ids of the documents its saved search
+/// matches, most recently updated first.
+pub async fn matching_documents(pool: &SqlitePool, id: i64) -> CmdResult<(String, Vec<i64>)> {
+    let (name, filter): (String, String) =
+        sqlx::query_as("SELECT name, filter FROM smart_folders WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Smart folder not found".to_string()))?;
+    let filter: SmartFilter = serde_json::from_str(&filter)
+        .map_err(|e| AppError::Validation(format!("Invalid smart folder filter: {}", e)))?;
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT d.id FROM documents d WHERE 1");
+
+    if let Some(text) = filter
+        .text
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    {
+        let pattern = format!("%{}%", escape_like(text));
+        query.push(" AND (d.title LIKE ");
+        query.push_bind(pattern.clone());
+        query.push(" ESCAPE '\\' OR d.description LIKE ");
+        query.push_bind(pattern.clone());
+        // Locked bodies match no more here than in search
+        query.push(" ESCAPE '\\' OR (NOT d.locked AND d.text_content LIKE ");
+        query.push_bind(pattern);
+        query.push(" ESCAPE '\\'))");
+    }
+    if let Some(category_id) = filter.category_id {
+        query.push(" AND d.category_id = ");
+        query.push_bind(category_id);
+    }
+    for tag in &filter.tags {
+        query.push(
+            " AND EXISTS (SELECT 1 FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+              WHERE dt.document_id = d.id AND t.name = ",
+        );
+        query.push_bind(tag.clone());
+        query.push(" COLLATE NOCASE)");
+    }
+    if let Some(after) = filter.created_after {
+        query.push(" AND d.created_at >= ");
+        query.push_bind(to_sql_datetime(after)?);
+    }
+    if let Some(before) = filter.created_before {
+        query.push(" AND d.created_at <= ");
+        query.push_bind(to_sql_datetime(before)?);
+    }
+    if let Some(mime) = filter
+        .attachment_type
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+    {
+        let pattern = if mime.ends_with('/') {
+            format!("{}%", escape_like(mime))
+        } else {
+            escape_like(mime)
+        };
+        query.push(" AND EXISTS (SELECT 1 FROM attachments a WHERE a.document_id = d.id AND a.filetype LIKE ");
+        query.push_bind(pattern);
+        query.push(" ESCAPE '\\')");
+    }
+    if let Some(needs_review) = filter.needs_review {
+        query.push(" AND d.needs_review = ");
+        query.push_bind(needs_review);
+    }
+    if let Some(is_read) = filter.is_read {
+        query.push(" AND d.is_read = ");
+        query.push_bind(is_read);
+    }
+    query.push(" ORDER BY d.updated_at DESC");
+
+    let ids: Vec<(i64,)> = query.build_query_as().fetch_all(pool).await?;
+
+    Ok((name, ids.into_iter().map(|(id,)| id).collect()))
+}
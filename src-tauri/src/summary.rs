@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+// TextRank damping factor and iteration limits, as in the original paper
+const DAMPING: f64 = 0.85;
+const MAX_ITERATIONS: usize = 50;
+const CONVERGENCE: f64 = 1e-4;
+
+/// Extractive summary of plain-text paragraphs: the `max_sentences`
+/// sentences most similar to the rest of the text, in their original
+/// order. Texts with no more sentences than that come back whole.
+pub fn summarize(paragraphs: &[String], max_sentences: usize) -> String {
+    let sentences: Vec<&str> = paragraphs
+        .iter()
+        .flat_map(|paragraph| split_sentences(paragraph))
+        .collect();
+    if sentences.len() <= max_sentences {
+        return paragraphs.join("\n\n");
+    }
+
+    let words: Vec<HashSet<String>> = sentences.iter().map(|s| sentence_words(s)).collect();
+    let count = sentences.len();
+
+    let mut weights = vec![vec![0.0; count]; count];
+    for i in 0..count {
+        for j in i + 1..count {
+            let weight = similarity(&words[i], &words[j]);
+            weights[i][j] = weight;
+            weights[j][i] = weight;
+        }
+    }
+    let totals: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+
+    let mut scores = vec![1.0; count];
+    for _ in 0..MAX_ITERATIONS {
+        let next: Vec<f64> = (0..count)
+            .map(|i| {
+                let incoming: f64 = (0..count)
+                    .filter(|&j| totals[j] > 0.0)
+                    .map(|j| weights[j][i] / totals[j] * scores[j])
+                    .sum();
+                (1.0 - DAMPING) + DAMPING * incoming
+            })
+            .collect();
+        let change = next
+            .iter()
+            .zip(&scores)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        scores = next;
+        if change < CONVERGENCE {
+            break;
+        }
+    }
+
+    // Ties go to the earlier sentence
+    let mut ranked: Vec<usize> = (0..count).collect();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+    let mut chosen = ranked[..max_sentences].to_vec();
+    chosen.sort_unstable();
+
+    chosen
+        .iter()
+        .map(|&i| sentences[i])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Splits after `.`, `!` or `?` followed by whitespace or the end of the
+// text, and after their full-width forms anywhere
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let ends = matches!(c, '。' | '！' | '？')
+            || (matches!(c, '.' | '!' | '?')
+                && chars.peek().map_or(true, |(_, next)| next.is_whitespace()));
+        if ends {
+            let end = index + c.len_utf8();
+            let sentence = text[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+fn sentence_words(sentence: &str) -> HashSet<String> {
+    sentence
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// Shared words, normalised by sentence length so long sentences do not win
+// by size alone
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let shared = a.intersection(b).count();
+    let norm = (a.len() as f64).ln() + (b.len() as f64).ln();
+    if shared == 0 || norm <= 0.0 {
+        return 0.0;
+    }
+    shared as f64 / norm
+}
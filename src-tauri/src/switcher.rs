@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::commands::documents::fold_case_and_accents;
+
+// Fuzzy matching for the quick switcher: the query's characters must appear
+// in the title in order, and an alignment scores higher the more of them
+// are consecutive or start a word. Titles are kept in memory, folded, so a
+// lookup never waits on the database beyond checking for changes.
+
+const MATCH: f64 = 16.0;
+const CONSECUTIVE: f64 = 12.0;
+const WORD_START: f64 = 10.0;
+const TITLE_START: f64 = 6.0;
+const GAP: f64 = 1.0;
+// Opens count less the longer ago they were, by a week's half-life
+const RECENCY_HALF_LIFE_DAYS: f64 = 7.0;
+
+#[derive(Serialize)]
+pub struct QuickSwitchResult {
+    pub id: i64,
+    pub title: String,
+    /// Name of the document's category.
+    pub category: Option<String>,
+    pub score: f64,
+}
+
+pub struct Entry {
+    pub id: i64,
+    pub title: String,
+    pub category: Option<String>,
+    folded: Vec<char>,
+    opened_at: Option<i64>,
+    open_count: i64,
+}
+
+impl Entry {
+    pub fn new(
+        id: i64,
+        title: String,
+        category: Option<String>,
+        opened_at: Option<i64>,
+        open_count: i64,
+    ) -> Self {
+        Self {
+            folded: fold_case_and_accents(&title).chars().collect(),
+            id,
+            title,
+            category,
+            opened_at,
+            open_count,
+        }
+    }
+
+    // Up to 1 for a document opened often and lately
+    fn recency(&self, now: i64) -> f64 {
+        let Some(opened_at) = self.opened_at else {
+            return 0.0;
+        };
+        let days = (now - opened_at).max(0) as f64 / 86_400.0;
+        let fresh = 0.5_f64.powf(days / RECENCY_HALF_LIFE_DAYS);
+        let frequent = 1.0 - 1.0 / (1.0 + self.open_count as f64 / 5.0);
+        (fresh + frequent) / 2.0
+    }
+}
+
+struct Index {
+    generation: i64,
+    entries: Vec<Entry>,
+    positions: HashMap<i64, usize>,
+}
+
+/// The titles the quick switcher searches, loaded from the database and
+/// reloaded whenever its change counter moves on.
+#[derive(Default)]
+pub struct QuickSwitcher {
+    index: Mutex<Option<Index>>,
+}
+
+impl QuickSwitcher {
+    /// Whether the titles loaded are those of `generation`.
+    pub fn is_current(&self, generation: i64) -> bool {
+        self.index
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|index| index.generation == generation)
+    }
+
+    pub fn load(&self, generation: i64, entries: Vec<Entry>) {
+        let positions = entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.id, position))
+            .collect();
+        *self.index.lock().unwrap() = Some(Index {
+            generation,
+            entries,
+            positions,
+        });
+    }
+
+    /// Counts an open right away, without waiting for a reload.
+    pub fn opened(&self, id: i64, at: i64) {
+        if let Some(index) = self.index.lock().unwrap().as_mut() {
+            if let Some(&position) = index.positions.get(&id) {
+                let entry = &mut index.entries[position];
+                entry.opened_at = Some(at);
+                entry.open_count += 1;
+            }
+        }
+    }
+
+    /// The best `limit` matches for `query`, with their scores. Documents
+    /// opened often and recently rank higher; an empty query lists just
+    /// those, most recent first.
+    pub fn search(&self, query: &str, limit: usize, now: i64) -> Vec<QuickSwitchResult> {
+        let query: Vec<char> = fold_case_and_accents(query)
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let index = self.index.lock().unwrap();
+        let Some(index) = index.as_ref() else {
+            return Vec::new();
+        };
+
+        let mut scored: Vec<(&Entry, f64)> = index
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let recency = entry.recency(now);
+                if query.is_empty() {
+                    return (recency > 0.0).then_some((entry, recency));
+                }
+                let score = score(&query, &entry.folded)?;
+                Some((entry, score * (1.0 + recency)))
+            })
+            .collect();
+        // Ties go to the shorter, then the newer title
+        scored.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then(a.0.folded.len().cmp(&b.0.folded.len()))
+                .then(b.0.id.cmp(&a.0.id))
+        });
+        scored.truncate(limit);
+
+        scored
+            .into_iter()
+            .map(|(entry, score)| QuickSwitchResult {
+                id: entry.id,
+                title: entry.title.clone(),
+                category: entry.category.clone(),
+                score,
+            })
+            .collect()
+    }
+}
+
+/// How well `query` matches `text` as a subsequence, normalized so a query
+/// matching a whole word at the start scores about 1; `None` when some of
+/// its characters are missing. Both are folded already.
+pub fn score(query: &[char], text: &[char]) -> Option<f64> {
+    if query.is_empty() || query.len() > text.len() {
+        return None;
+    }
+    // Cheap rejection before the full alignment
+    let mut rest = text.iter();
+    if !query.iter().all(|c| rest.any(|t| t == c)) {
+        return None;
+    }
+
+    let bonus: Vec<f64> = (0..text.len())
+        .map(|j| {
+            if j == 0 {
+                TITLE_START + WORD_START
+            } else if !text[j - 1].is_alphanumeric() && text[j].is_alphanumeric() {
+                WORD_START
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    // best[j]: the best score with the current query character at text[j]
+    let none = f64::NEG_INFINITY;
+    let mut best: Vec<f64> = (0..text.len())
+        .map(|j| {
+            if text[j] == query[0] {
+                MATCH + bonus[j] - GAP * j as f64 * 0.1
+            } else {
+                none
+            }
+        })
+        .collect();
+    for &c in &query[1..] {
+        let mut next = vec![none; text.len()];
+        // Best earlier alignment, less the gap up to here
+        let mut carried = none;
+        for j in 1..text.len() {
+            if j >= 2 {
+                carried = (carried - GAP).max(best[j - 2] - GAP);
+            }
+            if text[j] != c {
+                continue;
+            }
+            let from_previous = best[j - 1] + CONSECUTIVE;
+            let start = from_previous.max(carried);
+            if start > none {
+                next[j] = start + MATCH + bonus[j];
+            }
+        }
+        best = next;
+    }
+
+    let total = best.into_iter().fold(none, f64::max);
+    if total == none {
+        return None;
+    }
+    let ideal = MATCH * query.len() as f64
+        + CONSECUTIVE * (query.len() - 1) as f64
+        + TITLE_START
+        + WORD_START;
+    Some((total / ideal).max(0.0))
+}
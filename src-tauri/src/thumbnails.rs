@@ -0,0 +1,370 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use image::codecs::jpeg::JpegEncoder;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::error::CmdResult;
+
+// Thumbnails live in the cache dir, one folder per size:
+// thumbnails/<size>/<attachment id>.png
+pub fn cache_root(app: &AppHandle) -> CmdResult<PathBuf> {
+    let cache_dir = app.path().app_cache_dir()?;
+    Ok(cache_dir.join("thumbnails"))
+}
+
+pub fn thumbnail_path(root: &Path, size: u32, attachment_id: i64) -> PathBuf {
+    root.join(size.to_string())
+        .join(format!("{}.png", attachment_id))
+}
+
+/// Scales the image down to fit in `size`x`size` and writes it as PNG.
+pub fn generate(source: &Path, dest: &Path, size: u32) -> CmdResult<()> {
+    let image = image::open(source)?;
+
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    image
+        .thumbnail(size, size)
+        .save_with_format(dest, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+/// Scales the image down to fit in `size`x`size` and returns it as JPEG,
+/// which keeps photos far smaller than PNG where a little blur is fine.
+pub fn jpeg(source: &Path, size: u32, quality: u8) -> CmdResult<Vec<u8>> {
+    let image = image::open(source)?;
+
+    let mut bytes = Vec::new();
+    // JPEG has no alpha channel
+    let thumbnail = image.thumbnail(size, size).into_rgb8();
+    JpegEncoder::new_with_quality(&mut bytes, quality).encode_image(&thumbnail)?;
+    Ok(bytes)
+}
+
+/// Removes the attachment's cached thumbnails of every size, e.g. once its
+/// file is replaced.
+pub fn evict(root: &Path, attachment_id: i64) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path().join(format!("{}.png", attachment_id));
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Removes cached thumbnails of every size other than `keep_size`.
+pub fn evict_other_sizes(root: &Path, keep_size: u32) -> CmdResult<usize> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(0),
+    };
+
+    let mut evicted = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && entry.file_name() != keep_size.to_string().as_str() {
+            fs::remove_dir_all(&path)?;
+            evicted += 1;
+        }
+    }
+
+    Ok(evicted)
+}
+
+// A prefetch fills at most half the room left in the cache
+const PREFETCH_SHARE: u64 = 2;
+
+/// Keeps the thumbnail cache within `thumbnail_cache_mb`, evicting the
+/// least recently used thumbnails first. A thumbnail's modification time
+/// is its last use, so the order survives restarts; the counts are since
+/// launch. Only files under the cache root are ever removed.
+#[derive(Default)]
+pub struct ThumbnailCache {
+    // Bytes on disk, counted on first use; removals elsewhere leave it
+    // high until the next trim counts again
+    bytes: Mutex<Option<u64>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThumbnailCacheStats {
+    pub bytes: u64,
+    pub files: usize,
+    /// `None` when the cache is unbounded.
+    pub limit_bytes: Option<u64>,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits per request, `None` before the first.
+    pub hit_rate: Option<f64>,
+    pub evictions: u64,
+}
+
+struct CachedFile {
+    path: PathBuf,
+    len: u64,
+    used_at: SystemTime,
+}
+
+impl ThumbnailCache {
+    /// Counts a request served from the cache and marks the thumbnail used.
+    pub fn hit(&self, path: &Path) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        let touched = File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = touched {
+            log::warn!("Failed to mark {} used: {}", path.display(), e);
+        }
+    }
+
+    /// Counts a request the cache had to generate a thumbnail for.
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accounts for the thumbnail just written to `path` and trims the
+    /// cache if it is now over `limit_mb`, keeping `path` itself.
+    pub fn added(&self, root: &Path, path: &Path, limit_mb: Option<u32>) {
+        let mut bytes = self.bytes.lock().unwrap_or_else(|e| e.into_inner());
+        let (total, _) = count_added(&mut bytes, root, path);
+        if let Some(limit) = limit_bytes(limit_mb) {
+            if total > limit {
+                *bytes = Some(self.trim(root, limit, Some(path)));
+            }
+        }
+    }
+
+    /// Bytes of thumbnails a prefetch may still add: a share of the room
+    /// left below the level a trim evicts down to, so prefetched
+    /// thumbnails never push out ones that were viewed. `None` when the
+    /// cache is unbounded.
+    pub fn prefetch_budget(&self, root: &Path, limit_mb: Option<u32>) -> Option<u64> {
+        let limit = limit_bytes(limit_mb)?;
+        let mut bytes = self.bytes.lock().unwrap_or_else(|e| e.into_inner());
+        let total = *bytes.get_or_insert_with(|| scan(root).iter().map(|file| file.len).sum());
+        Some(trim_target(limit).saturating_sub(total) / PREFETCH_SHARE)
+    }
+
+    /// Accounts for a prefetched thumbnail at `path` without trimming,
+    /// and returns its size. A prefetch trims once when it is done.
+    pub fn prefetched(&self, root: &Path, path: &Path) -> u64 {
+        let mut bytes = self.bytes.lock().unwrap_or_else(|e| e.into_inner());
+        count_added(&mut bytes, root, path).1
+    }
+
+    /// Trims the cache if it is over `limit_mb`.
+    pub fn enforce(&self, root: &Path, limit_mb: Option<u32>) {
+        let Some(limit) = limit_bytes(limit_mb) else {
+            return;
+        };
+        let mut bytes = self.bytes.lock().unwrap_or_else(|e| e.into_inner());
+        let total = *bytes.get_or_insert_with(|| scan(root).iter().map(|file| file.len).sum());
+        if total > limit {
+            *bytes = Some(self.trim(root, limit, None));
+        }
+    }
+
+    /// Forgets the counted size, e.g. after thumbnails were removed
+    /// wholesale.
+    pub fn recount(&self) {
+        *self.bytes.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    pub fn stats(&self, root: &Path, limit_mb: Option<u32>) -> ThumbnailCacheStats {
+        let files = scan(root);
+        let bytes = files.iter().map(|file| file.len).sum();
+        *self.bytes.lock().unwrap_or_else(|e| e.into_inner()) = Some(bytes);
+
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        ThumbnailCacheStats {
+            bytes,
+            files: files.len(),
+            limit_bytes: limit_bytes(limit_mb),
+            hits,
+            misses,
+            hit_rate: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    // Evicts down to `trim_target`, so the next few thumbnails don't each
+    // trigger a trim. Returns the bytes left.
+    fn trim(&self, root: &Path, limit: u64, keep: Option<&Path>) -> u64 {
+        let mut files = scan(root);
+        let mut total: u64 = files.iter().map(|file| file.len).sum();
+        let target = trim_target(limit);
+        files.sort_by_key(|file| file.used_at);
+        for file in files {
+            if total <= target {
+                break;
+            }
+            if Some(file.path.as_path()) == keep {
+                continue;
+            }
+            match fs::remove_file(&file.path) {
+                Ok(()) => {
+                    total -= file.len;
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => log::warn!("Failed to evict {}: {}", file.path.display(), e),
+            }
+        }
+        total
+    }
+}
+
+fn limit_bytes(limit_mb: Option<u32>) -> Option<u64> {
+    limit_mb.map(|mb| u64::from(mb) << 20)
+}
+
+// Nine tenths of the limit
+fn trim_target(limit: u64) -> u64 {
+    limit / 10 * 9
+}
+
+// Adds the file at `path` to the counted size, counting the whole cache
+// when it hasn't been yet. Returns the new total and the file's size.
+fn count_added(bytes: &mut Option<u64>, root: &Path, path: &Path) -> (u64, u64) {
+    let len = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let total = match *bytes {
+        Some(total) => total + len,
+        None => scan(root).iter().map(|file| file.len).sum(),
+    };
+    *bytes = Some(total);
+    (total, len)
+}
+
+// Every thumbnail of every size, nothing else the cache dir might hold
+fn scan(root: &Path) -> Vec<CachedFile> {
+    let Ok(sizes) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    for size in sizes.flatten() {
+        let Ok(entries) = fs::read_dir(size.path()) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "png")
+            {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                files.push(CachedFile {
+                    path,
+                    len: metadata.len(),
+                    used_at: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const KB: u64 = 1 << 10;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("ando-thumbnails-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        root
+    }
+
+    // A 100 KB stand-in thumbnail, last used `age` seconds ago
+    fn thumbnail(root: &Path, id: i64, age: u64) -> PathBuf {
+        let path = thumbnail_path(root, 128, id);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![0; 100 * KB as usize]).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age))
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn prefetch_gets_half_the_room_below_the_trim_level() {
+        let root = cache_dir("budget");
+        let cache = ThumbnailCache::default();
+        assert_eq!(cache.prefetch_budget(&root, None), None);
+        assert_eq!(
+            cache.prefetch_budget(&root, Some(1)),
+            Some(trim_target(1 << 20) / 2)
+        );
+
+        for id in 0..4 {
+            thumbnail(&root, id, 0);
+        }
+        cache.recount();
+        assert_eq!(
+            cache.prefetch_budget(&root, Some(1)),
+            Some((trim_target(1 << 20) - 400 * KB) / 2)
+        );
+
+        // Near the limit there is nothing left to prefetch into
+        for id in 4..10 {
+            let path = thumbnail(&root, id, 0);
+            assert_eq!(cache.prefetched(&root, &path), 100 * KB);
+        }
+        assert_eq!(cache.prefetch_budget(&root, Some(1)), Some(0));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn prefetching_trims_once_least_recently_used_first() {
+        let root = cache_dir("enforce");
+        let cache = ThumbnailCache::default();
+        for id in 0..10 {
+            thumbnail(&root, id, 100 - id as u64);
+        }
+        cache.recount();
+        for id in 10..12 {
+            let path = thumbnail(&root, id, 0);
+            cache.prefetched(&root, &path);
+        }
+        assert_eq!(scan(&root).len(), 12);
+        assert_eq!(cache.stats(&root, Some(1)).evictions, 0);
+
+        cache.enforce(&root, Some(1));
+        let stats = cache.stats(&root, Some(1));
+        assert!(stats.bytes <= trim_target(1 << 20));
+        assert_eq!(stats.evictions, 3);
+        for id in 0..3 {
+            assert!(!thumbnail_path(&root, 128, id).exists());
+        }
+        assert!(thumbnail_path(&root, 128, 3).exists());
+
+        // Within the limit it leaves the cache alone
+        cache.enforce(&root, Some(1));
+        assert_eq!(cache.stats(&root, Some(1)).evictions, 3);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
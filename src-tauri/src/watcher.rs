@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tauri::{AppHandle, Emitter};
+
+use crate::commands::attachments;
+use crate::commands::documents::normalize_name;
+use crate::db;
+use crate::error::CmdResult;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// A file is imported once it stayed the same for this many polls in a row,
+// so scanners still writing it aren't picked up half-complete
+const STABLE_POLLS: u32 = 2;
+
+#[derive(Clone, Serialize)]
+struct FolderIngested {
+    folder: String,
+    path: String,
+    document_id: i64,
+}
+
+/// Import folders being polled, keyed by path, with their stop flag.
+#[derive(Default)]
+pub struct FolderWatchers {
+    running: Mutex<HashMap<PathBuf, Arc<AtomicBool>>>,
+}
+
+impl FolderWatchers {
+    pub fn stop(&self, path: &Path) -> bool {
+        match self.running.lock().unwrap().remove(path) {
+            Some(stopped) => {
+                stopped.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Polls `folder` and imports every file that appears in it as a new
+    /// document in `category_id`. Files already there are left alone.
+    pub fn start(&self, app: &AppHandle, folder: PathBuf, category_id: i64) {
+        let stopped = Arc::new(AtomicBool::new(false));
+        if let Some(previous) = self
+            .running
+            .lock()
+            .unwrap()
+            .insert(folder.clone(), Arc::clone(&stopped))
+        {
+            previous.store(true, Ordering::Relaxed);
+        }
+
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut known: HashMap<PathBuf, FileState> = scan(&folder)
+                .into_iter()
+                .map(|(path, snapshot)| (path, FileState::done(snapshot)))
+                .collect();
+
+            while !stopped.load(Ordering::Relaxed) {
+                tokio::time::sleep(POLL_INTERVAL).await;
+
+                let current = scan(&folder);
+                known.retain(|path, _| current.contains_key(path));
+
+                let mut ready = Vec::new();
+                for (path, snapshot) in current {
+                    let state = known
+                        .entry(path.clone())
+                        .or_insert(FileState::new(snapshot));
+                    if state.update(snapshot) {
+                        ready.push(path);
+                    }
+                }
+                if ready.is_empty() {
+                    continue;
+                }
+
+                // The pool only exists once the frontend has loaded the database
+                let Ok(pool) = db::pool(&app).await else {
+                    continue;
+                };
+                for path in ready {
+                    match ingest(&app, &pool, &path, category_id, None).await {
+                        Ok(document_id) => {
+                            if let Some(state) = known.get_mut(&path) {
+                                state.imported = true;
+                            }
+                            let _ = app.emit(
+                                "folder_ingested",
+                                FolderIngested {
+                                    folder: folder.to_string_lossy().to_string(),
+                                    path: path.to_string_lossy().to_string(),
+                                    document_id,
+                                },
+                            );
+                        }
+                        Err(e) => log::warn!("Failed to import {}: {}", path.display(), e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+type Snapshot = (u64, Option<SystemTime>);
+
+struct FileState {
+    snapshot: Snapshot,
+    stable_polls: u32,
+    imported: bool,
+}
+
+impl FileState {
+    fn new(snapshot: Snapshot) -> Self {
+        Self {
+            snapshot,
+            stable_polls: 0,
+            imported: false,
+        }
+    }
+
+    fn done(snapshot: Snapshot) -> Self {
+        Self {
+            imported: true,
+            ..Self::new(snapshot)
+        }
+    }
+
+    // Returns whether the file settled and should be imported now
+    fn update(&mut self, snapshot: Snapshot) -> bool {
+        if self.imported {
+            return false;
+        }
+        if snapshot == self.snapshot {
+            self.stable_polls += 1;
+        } else {
+            self.snapshot = snapshot;
+            self.stable_polls = 0;
+        }
+        self.stable_polls >= STABLE_POLLS
+    }
+}
+
+// Top-level regular files, skipping hidden and partial-download files
+pub(crate) fn scan(folder: &Path) -> HashMap<PathBuf, Snapshot> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return HashMap::new();
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            !name.starts_with('.')
+                && ![".part", ".crdownload", ".tmp"]
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix))
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata
+                .is_file()
+                .then(|| (entry.path(), (metadata.len(), metadata.modified().ok())))
+        })
+        .collect()
+}
+
+pub(crate) fn title_for(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| normalize_name(&stem.to_string_lossy()))
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// Creates a document titled after the file, with the file attached. It is
+/// flagged for review, having been filled in without the user. Imports
+/// pass their `import_session`.
+pub(crate) async fn ingest(
+    app: &AppHandle,
+    pool: &SqlitePool,
+    path: &Path,
+    category_id: i64,
+    import_session: Option<i64>,
+) -> CmdResult<i64> {
+    let title = title_for(path);
+
+    let document_id = sqlx::query(
+        "INSERT INTO documents (title, description, text_content, category_id, needs_review,
+           import_session)
+         VALUES (?, '', '', ?, 1, ?)",
+    )
+    .bind(&title)
+    .bind(category_id)
+    .bind(import_session)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    let attachment = match attachments::store_file(app, pool, document_id, path).await {
+        Ok(attachment) => attachment,
+        Err(e) => {
+            // Don't leave an empty document behind
+            let _ = sqlx::query("DELETE FROM documents WHERE id = ?")
+                .bind(document_id)
+                .execute(pool)
+                .await;
+            return Err(e);
+        }
+    };
+    if let Some(session) = import_session {
+        sqlx::query("UPDATE attachments SET import_session = ? WHERE id = ?")
+            .bind(session)
+            .bind(attachment.id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(document_id)
+}
@@ -562,7 +562,7 @@ class DatabaseManager {
   async getAttachments(documentId: number): Promise<Attachment[]> {
     if (!this.db) throw new Error("Database not initialized");
     return await this.db.select(
-      "SELECT * FROM attachments WHERE document_id = ?",
+      "SELECT * FROM attachments WHERE document_id = ? ORDER BY sort_order ASC, id ASC",
       [documentId]
     );
   }